@@ -798,7 +798,8 @@ pub unsafe extern "C" fn AMreceiveSyncMessage(
     let sync_message = to_sync_message!(sync_message);
     to_result(
         doc.sync()
-            .receive_sync_message(sync_state.as_mut(), sync_message.as_ref().clone()),
+            .receive_sync_message(sync_state.as_mut(), sync_message.as_ref().clone())
+            .map(|_| ()),
     )
 }
 
@@ -417,8 +417,11 @@ impl TryFrom<&Value> for f64 {
         use am::Value::*;
 
         if let Value(Scalar(scalar)) = value {
-            if let F64(float) = scalar.as_ref() {
-                return Ok(*float);
+            match scalar.as_ref() {
+                F64(float) => return Ok(*float),
+                // The C API has no 32 bit float type, widen it
+                F32(float) => return Ok(f64::from(*float)),
+                _ => {}
             }
         }
         Err(InvalidValueType {
@@ -1350,7 +1353,7 @@ impl From<&am::Value<'static>> for AMvalType {
                 Boolean(_) => Self::Bool,
                 Bytes(_) => Self::Bytes,
                 Counter(_) => Self::Counter,
-                F64(_) => Self::F64,
+                F64(_) | F32(_) => Self::F64,
                 Int(_) => Self::Int,
                 Null => Self::Null,
                 Str(_) => Self::Str,
@@ -226,6 +226,7 @@ pub enum OrdScalarValue {
     Int(i64),
     Uint(u64),
     F64(decorum::Total<f64>),
+    F32(decorum::Total<f32>),
     Counter(i64),
     Timestamp(i64),
     Boolean(bool),
@@ -241,6 +242,7 @@ impl From<automerge::ScalarValue> for OrdScalarValue {
             automerge::ScalarValue::Int(v) => OrdScalarValue::Int(v),
             automerge::ScalarValue::Uint(v) => OrdScalarValue::Uint(v),
             automerge::ScalarValue::F64(v) => OrdScalarValue::F64(decorum::Total::from(v)),
+            automerge::ScalarValue::F32(v) => OrdScalarValue::F32(decorum::Total::from(v)),
             automerge::ScalarValue::Counter(c) => OrdScalarValue::Counter(c.into()),
             automerge::ScalarValue::Timestamp(v) => OrdScalarValue::Timestamp(v),
             automerge::ScalarValue::Boolean(v) => OrdScalarValue::Boolean(v),
@@ -260,6 +262,7 @@ impl From<&OrdScalarValue> for automerge::ScalarValue {
             OrdScalarValue::Int(v) => automerge::ScalarValue::Int(*v),
             OrdScalarValue::Uint(v) => automerge::ScalarValue::Uint(*v),
             OrdScalarValue::F64(v) => automerge::ScalarValue::F64(v.into_inner()),
+            OrdScalarValue::F32(v) => automerge::ScalarValue::F32(v.into_inner()),
             OrdScalarValue::Counter(v) => automerge::ScalarValue::counter(*v),
             OrdScalarValue::Timestamp(v) => automerge::ScalarValue::Timestamp(*v),
             OrdScalarValue::Boolean(v) => automerge::ScalarValue::Boolean(*v),
@@ -283,6 +286,7 @@ impl serde::Serialize for OrdScalarValue {
             OrdScalarValue::Int(v) => serializer.serialize_i64(*v),
             OrdScalarValue::Uint(v) => serializer.serialize_u64(*v),
             OrdScalarValue::F64(v) => serializer.serialize_f64(v.into_inner()),
+            OrdScalarValue::F32(v) => serializer.serialize_f32(v.into_inner()),
             OrdScalarValue::Counter(v) => {
                 serializer.serialize_str(format!("Counter({})", v).as_str())
             }
@@ -458,6 +462,12 @@ impl From<f64> for RealizedObject {
     }
 }
 
+impl From<f32> for RealizedObject {
+    fn from(f: f32) -> Self {
+        RealizedObject::Value(OrdScalarValue::F32(f.into()))
+    }
+}
+
 impl From<Vec<u64>> for RealizedObject {
     fn from(vals: Vec<u64>) -> Self {
         RealizedObject::Sequence(
@@ -19,6 +19,7 @@ export type FullValue =
   ["int", number] |
   ["uint", number] |
   ["f64", number] |
+  ["f32", number] |
   ["boolean", boolean] |
   ["timestamp", Date] |
   ["counter", number] |
@@ -36,6 +37,7 @@ export type FullValueWithId =
   ["int", number, ObjID ] |
   ["uint", number, ObjID ] |
   ["f64", number, ObjID ] |
+  ["f32", number, ObjID ] |
   ["boolean", boolean, ObjID ] |
   ["timestamp", Date, ObjID ] |
   ["counter", number, ObjID ] |
@@ -59,6 +61,7 @@ export type Datatype =
   "int" |
   "uint" |
   "f64" |
+  "f32" |
   "null" |
   "timestamp" |
   "counter" |
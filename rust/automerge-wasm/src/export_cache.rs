@@ -255,6 +255,7 @@ impl<'a> ExportCache<'a> {
             am::ScalarValue::Int(v) => (Datatype::Int, (*v as f64).into()),
             am::ScalarValue::Uint(v) => (Datatype::Uint, (*v as f64).into()),
             am::ScalarValue::F64(v) => (Datatype::F64, (*v).into()),
+            am::ScalarValue::F32(v) => (Datatype::F32, (*v).into()),
             am::ScalarValue::Counter(v) => (Datatype::Counter, (f64::from(v)).into()),
             am::ScalarValue::Timestamp(v) => (
                 Datatype::Timestamp,
@@ -326,17 +326,17 @@ impl TryFrom<JS> for am::sync::State {
                 None
             }
         };
-        Ok(am::sync::State {
-            shared_heads,
-            last_sent_heads,
-            their_heads,
-            their_need,
-            their_have,
-            sent_hashes,
-            in_flight,
-            have_responded,
-            their_capabilities,
-        })
+        let mut state = am::sync::State::new();
+        state.shared_heads = shared_heads;
+        state.last_sent_heads = last_sent_heads;
+        state.their_heads = their_heads;
+        state.their_need = their_need;
+        state.their_have = their_have;
+        state.sent_hashes = sent_hashes;
+        state.in_flight = in_flight;
+        state.have_responded = have_responded;
+        state.their_capabilities = their_capabilities;
+        Ok(state)
     }
 }
 
@@ -1295,6 +1295,7 @@ impl Automerge {
             Some(Datatype::Uint) => value.as_f64().map(|v| am::ScalarValue::Uint(v as u64)),
             Some(Datatype::Str) => value.as_string().map(|v| am::ScalarValue::Str(v.into())),
             Some(Datatype::F64) => value.as_f64().map(am::ScalarValue::F64),
+            Some(Datatype::F32) => value.as_f64().map(|v| am::ScalarValue::F32(v as f32)),
             Some(Datatype::Bytes) => Some(am::ScalarValue::Bytes(
                 value.clone().dyn_into::<Uint8Array>().unwrap().to_vec(),
             )),
@@ -1381,6 +1382,7 @@ pub(crate) fn alloc_scalar(value: &am::ScalarValue) -> (Datatype, JsValue) {
         am::ScalarValue::Int(v) => (Datatype::Int, (*v as f64).into()),
         am::ScalarValue::Uint(v) => (Datatype::Uint, (*v as f64).into()),
         am::ScalarValue::F64(v) => (Datatype::F64, (*v).into()),
+        am::ScalarValue::F32(v) => (Datatype::F32, (*v).into()),
         am::ScalarValue::Counter(v) => (Datatype::Counter, (f64::from(v)).into()),
         am::ScalarValue::Timestamp(v) => (
             Datatype::Timestamp,
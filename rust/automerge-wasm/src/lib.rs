@@ -1036,7 +1036,9 @@ impl Automerge {
     #[wasm_bindgen(js_name = emptyChange)]
     pub fn empty_change(&mut self, message: Option<String>, time: Option<f64>) -> JsValue {
         let time = time.map(|f| f as i64);
-        let options = CommitOptions { message, time };
+        let mut options = CommitOptions::default();
+        options.message = message;
+        options.time = time;
         let hash = self.doc.empty_change(options);
         JsValue::from_str(&hex::encode(hash))
     }
@@ -12,6 +12,7 @@ pub(crate) enum Datatype {
     Int,
     Uint,
     F64,
+    F32,
     Counter,
     Timestamp,
     Boolean,
@@ -60,6 +61,7 @@ impl From<&ScalarValue> for Datatype {
             ScalarValue::Int(_) => Self::Int,
             ScalarValue::Uint(_) => Self::Uint,
             ScalarValue::F64(_) => Self::F64,
+            ScalarValue::F32(_) => Self::F32,
             ScalarValue::Counter(_) => Self::Counter,
             ScalarValue::Timestamp(_) => Self::Timestamp,
             ScalarValue::Boolean(_) => Self::Boolean,
@@ -102,6 +104,7 @@ impl From<Datatype> for String {
             Datatype::Int => "int".into(),
             Datatype::Uint => "uint".into(),
             Datatype::F64 => "f64".into(),
+            Datatype::F32 => "f32".into(),
             Datatype::Counter => "counter".into(),
             Datatype::Timestamp => "timestamp".into(),
             Datatype::Boolean => "boolean".into(),
@@ -126,6 +129,7 @@ impl TryFrom<JsValue> for Datatype {
             "int" => Ok(Datatype::Int),
             "uint" => Ok(Datatype::Uint),
             "f64" => Ok(Datatype::F64),
+            "f32" => Ok(Datatype::F32),
             "counter" => Ok(Datatype::Counter),
             "timestamp" => Ok(Datatype::Timestamp),
             "boolean" => Ok(Datatype::Boolean),
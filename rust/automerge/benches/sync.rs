@@ -40,7 +40,7 @@ fn sync(doc1: &mut DocWithSync, doc2: &mut DocWithSync) {
         if let Some(message2) = doc2.doc.generate_sync_message(&mut doc2.peer_state) {
             doc1.doc
                 .receive_sync_message(&mut doc1.peer_state, message2)
-                .unwrap()
+                .unwrap();
         }
     }
 }
@@ -1,22 +1,27 @@
-use std::ops::RangeBounds;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::ops::{Range, RangeBounds};
 
 use crate::automerge::SaveOptions;
 use crate::automerge::{current_state, diff};
 use crate::exid::ExId;
 use crate::iter::Spans;
-use crate::iter::{Keys, ListRange, MapRange, Values};
-use crate::marks::{ExpandMark, Mark, MarkSet};
-use crate::patches::{PatchLog, TextRepresentation};
+use crate::iter::{
+    AllMarks, Entries, Keys, KeysWithConflicts, ListRange, ListRangeRev, MapRange, Objects, Values,
+    ValuesRef,
+};
+use crate::marks::{ExpandMark, Mark, MarkSet, RichText};
+use crate::patches::{PatchLog, TextEdit, TextRepresentation};
 use crate::sync::SyncDoc;
 use crate::transaction::{CommitOptions, Transactable};
 use crate::types::Clock;
-use crate::{hydrate, OnPartialLoad};
+use crate::{hydrate, ObjStats, ObjView, OnPartialLoad};
 use crate::{sync, ObjType, Parents, Patch, ReadDoc, ScalarValue};
 use crate::{
     transaction::TransactionInner, ActorId, Automerge, AutomergeError, Change, ChangeHash, Cursor,
     Prop, Value,
 };
-use crate::{LoadOptions, VerificationMode};
+use crate::{LoadOptions, TextEncoding, VerificationMode};
 
 /// An automerge document that automatically manages transactions.
 ///
@@ -60,6 +65,7 @@ pub struct AutoCommit {
     patch_log: PatchLog,
     diff_cursor: Vec<ChangeHash>,
     diff_cache: Option<(OpRange, Vec<Patch>)>,
+    text_cache: HashMap<ExId, (u64, String)>,
     save_cursor: Vec<ChangeHash>,
     isolation: Option<Vec<ChangeHash>>,
 }
@@ -75,6 +81,7 @@ impl Default for AutoCommit {
             patch_log: PatchLog::inactive(TextRepresentation::default()),
             diff_cursor: Vec::new(),
             diff_cache: None,
+            text_cache: HashMap::new(),
             save_cursor: Vec::new(),
             isolation: None,
         }
@@ -94,6 +101,7 @@ impl AutoCommit {
             patch_log: PatchLog::inactive(TextRepresentation::default()),
             diff_cursor: Vec::new(),
             diff_cache: None,
+            text_cache: HashMap::new(),
             save_cursor: Vec::new(),
             isolation: None,
         })
@@ -107,6 +115,7 @@ impl AutoCommit {
             patch_log: PatchLog::inactive(TextRepresentation::default()),
             diff_cursor: Vec::new(),
             diff_cache: None,
+            text_cache: HashMap::new(),
             save_cursor: Vec::new(),
             isolation: None,
         })
@@ -137,6 +146,7 @@ impl AutoCommit {
             patch_log: PatchLog::inactive(TextRepresentation::default()),
             diff_cursor: Vec::new(),
             diff_cache: None,
+            text_cache: HashMap::new(),
             save_cursor: Vec::new(),
             isolation: None,
         })
@@ -175,6 +185,12 @@ impl AutoCommit {
         self.doc.make_patches(patch_log)
     }
 
+    /// Generate the patches recorded in `patch_log` grouped by the top level object they are in,
+    /// see [`Automerge::make_patches_grouped()`]
+    pub fn make_patches_grouped(&self, patch_log: &mut PatchLog) -> HashMap<ExId, Vec<Patch>> {
+        self.doc.make_patches_grouped(patch_log)
+    }
+
     /// Generates a diff from `before` to `after`
     ///
     /// By default the diff requires a sequental scan of all the ops in the doc.
@@ -272,6 +288,7 @@ impl AutoCommit {
             patch_log: PatchLog::inactive(self.patch_log.text_rep()),
             diff_cursor: vec![],
             diff_cache: None,
+            text_cache: HashMap::new(),
             save_cursor: vec![],
             isolation: None,
         }
@@ -285,6 +302,7 @@ impl AutoCommit {
             patch_log: PatchLog::inactive(self.patch_log.text_rep()),
             diff_cursor: vec![],
             diff_cache: None,
+            text_cache: HashMap::new(),
             save_cursor: vec![],
             isolation: None,
         })
@@ -297,6 +315,7 @@ impl AutoCommit {
         &self.doc
     }
 
+    /// See [`Automerge::with_actor()`]
     pub fn with_actor(mut self, actor: ActorId) -> Self {
         self.ensure_transaction_closed();
         self.doc.set_actor(actor);
@@ -309,10 +328,49 @@ impl AutoCommit {
         self
     }
 
+    /// See [`Automerge::with_op_tree_node_size()`]
+    pub fn with_op_tree_node_size(mut self, size: usize) -> Self {
+        self.ensure_transaction_closed();
+        self.doc.set_op_tree_node_size(size);
+        self
+    }
+
+    /// See [`Automerge::with_lazy_objects()`]
+    pub fn with_lazy_objects(mut self, max_resident: usize) -> Self {
+        self.ensure_transaction_closed();
+        self.doc.set_lazy_objects(Some(max_resident));
+        self
+    }
+
+    /// See [`Automerge::set_lazy_objects()`]
+    pub fn set_lazy_objects(&mut self, max_resident: Option<usize>) -> &mut Self {
+        self.ensure_transaction_closed();
+        self.doc.set_lazy_objects(max_resident);
+        self
+    }
+
+    /// See [`Automerge::spill_cold_objects()`]
+    pub fn spill_cold_objects(&mut self) -> usize {
+        self.ensure_transaction_closed();
+        self.doc.spill_cold_objects()
+    }
+
+    /// See [`Automerge::compact()`], this commits any pending changes first
+    pub fn compact(&mut self) {
+        self.ensure_transaction_closed();
+        self.doc.compact()
+    }
+
     pub fn get_actor(&self) -> &ActorId {
         self.doc.get_actor()
     }
 
+    /// See [`Automerge::trim_changes()`], this commits any pending changes first
+    pub fn trim_changes(&mut self, keep_from: &[ChangeHash]) -> Result<(), AutomergeError> {
+        self.ensure_transaction_closed();
+        self.doc.trim_changes(keep_from)
+    }
+
     pub fn isolate(&mut self, heads: &[ChangeHash]) {
         self.ensure_transaction_closed();
         self.patch_to(heads);
@@ -361,6 +419,32 @@ impl AutoCommit {
         }
     }
 
+    /// Load an incremental save a group of changes at a time, see
+    /// [`Automerge::load_incremental_chunked()`]
+    pub fn load_incremental_chunked<F: FnMut(&[ChangeHash])>(
+        &mut self,
+        data: &[u8],
+        chunk_size: usize,
+        on_group: F,
+    ) -> Result<Vec<ChangeHash>, AutomergeError> {
+        self.ensure_transaction_closed();
+        if self.isolation.is_some() {
+            self.doc.load_incremental_chunked_log_patches(
+                data,
+                chunk_size,
+                on_group,
+                &mut PatchLog::null(),
+            )
+        } else {
+            self.doc.load_incremental_chunked_log_patches(
+                data,
+                chunk_size,
+                on_group,
+                &mut self.patch_log,
+            )
+        }
+    }
+
     pub fn apply_changes(
         &mut self,
         changes: impl IntoIterator<Item = Change>,
@@ -433,6 +517,9 @@ impl AutoCommit {
     }
 
     /// Save everything which is not a (transitive) dependency of `heads`
+    ///
+    /// This is the stateless counterpart of [`Self::save_incremental()`], see
+    /// [`Automerge::save_after()`].
     pub fn save_after(&mut self, heads: &[ChangeHash]) -> Vec<u8> {
         self.ensure_transaction_closed();
         self.doc.save_after(heads)
@@ -454,11 +541,42 @@ impl AutoCommit {
         self.doc.get_changes(have_deps)
     }
 
+    /// Get a change by its hash, committing any pending transaction first.
+    ///
+    /// Like [`ReadDoc::get_change_by_hash`] this borrows the change from the document's
+    /// history rather than copying it. If you only have a shared reference use the
+    /// [`ReadDoc`] method, which won't see changes in the open transaction.
     pub fn get_change_by_hash(&mut self, hash: &ChangeHash) -> Option<&Change> {
         self.ensure_transaction_closed();
         self.doc.get_change_by_hash(hash)
     }
 
+    /// See [`Automerge::freeze_object()`]
+    pub fn freeze_object<O: AsRef<ExId>>(&mut self, obj: O) -> Result<(), AutomergeError> {
+        self.doc.freeze_object(obj)
+    }
+
+    /// See [`Automerge::unfreeze_object()`]
+    pub fn unfreeze_object<O: AsRef<ExId>>(&mut self, obj: O) -> bool {
+        self.doc.unfreeze_object(obj)
+    }
+
+    /// See [`Automerge::is_frozen()`]
+    pub fn is_frozen<O: AsRef<ExId>>(&self, obj: O) -> bool {
+        self.doc.is_frozen(obj)
+    }
+
+    /// Whether the change `ancestor` is one of `of` or a transitive dependency of one of them,
+    /// see [`Automerge::is_ancestor()`]
+    pub fn is_ancestor(
+        &mut self,
+        ancestor: &ChangeHash,
+        of: &[ChangeHash],
+    ) -> Result<bool, AutomergeError> {
+        self.ensure_transaction_closed();
+        self.doc.is_ancestor(ancestor, of)
+    }
+
     /// Get changes in `other` that are not in `self`
     pub fn get_changes_added<'a>(&mut self, other: &'a mut Self) -> Vec<&'a Change> {
         self.ensure_transaction_closed();
@@ -545,8 +663,9 @@ impl AutoCommit {
     pub fn commit_with(&mut self, options: CommitOptions) -> Option<ChangeHash> {
         // ensure that even no changes triggers a change
         self.ensure_transaction_open();
-        let (patch_log, tx) = self.transaction.take().unwrap();
+        let (patch_log, mut tx) = self.transaction.take().unwrap();
         self.patch_log.merge(patch_log);
+        tx.set_extra(options.extra);
         let hash = tx.commit(&mut self.doc, options.message, options.time);
         if self.isolation.is_some() && hash.is_some() {
             self.isolation = hash.map(|h| vec![h])
@@ -562,6 +681,50 @@ impl AutoCommit {
             .unwrap_or(0)
     }
 
+    /// Like [`ReadDoc::text()`] but avoids allocating a new string when the object hasn't
+    /// changed since the last call for it
+    ///
+    /// The text of up to 16 objects is cached, and the cached text of an object is dropped by any
+    /// change to that object, including uncommitted operations. The text is borrowed from the cache if it was there and owned otherwise. While
+    /// the document is isolated (see [`Self::isolate()`]) nothing is cached.
+    ///
+    /// ```
+    /// # use std::borrow::Cow;
+    /// # use automerge::{transaction::Transactable, AutoCommit, ObjType, ROOT};
+    /// let mut doc = AutoCommit::new();
+    /// let text = doc.put_object(ROOT, "text", ObjType::Text).unwrap();
+    /// doc.splice_text(&text, 0, 0, "hello").unwrap();
+    /// assert!(matches!(doc.text_cow(&text).unwrap(), Cow::Owned(s) if s == "hello"));
+    /// assert!(matches!(doc.text_cow(&text).unwrap(), Cow::Borrowed("hello")));
+    /// doc.splice_text(&text, 5, 0, " world").unwrap();
+    /// assert!(matches!(doc.text_cow(&text).unwrap(), Cow::Owned(s) if s == "hello world"));
+    /// ```
+    pub fn text_cow<O: AsRef<ExId>>(&mut self, obj: O) -> Result<Cow<'_, str>, AutomergeError> {
+        let obj = obj.as_ref();
+        let meta = self.doc.exid_to_obj(obj)?;
+        // only sequences are cached, so loading into an empty document, which replaces its op
+        // set, can't leave an entry which looks fresh
+        if self.isolation.is_some() || !meta.typ.is_sequence() {
+            return self.text(obj).map(Cow::Owned);
+        }
+        let edited = self.doc.ops().last_edit(&meta.id);
+        if matches!(self.text_cache.get(obj), Some((e, _)) if Some(*e) == edited) {
+            return Ok(Cow::Borrowed(self.text_cache[obj].1.as_str()));
+        }
+        let text = self.text(obj)?;
+        if let Some(edited) = edited {
+            if !self.text_cache.contains_key(obj) && self.text_cache.len() >= TEXT_CACHE_SIZE {
+                // drop the entry of the object which was changed least recently
+                let oldest = self.text_cache.iter().min_by_key(|(_, (e, _))| *e);
+                if let Some(oldest) = oldest.map(|(obj, _)| obj.clone()) {
+                    self.text_cache.remove(&oldest);
+                }
+            }
+            self.text_cache.insert(obj.clone(), (edited, text.clone()));
+        }
+        Ok(Cow::Owned(text))
+    }
+
     /// Generate an empty change
     ///
     /// The main reason to do this is if you wish to create a "merge commit" which has all the
@@ -574,7 +737,7 @@ impl AutoCommit {
     pub fn empty_change(&mut self, options: CommitOptions) -> ChangeHash {
         self.ensure_transaction_closed();
         let args = self.doc.transaction_args(None);
-        TransactionInner::empty(&mut self.doc, args, options.message, options.time)
+        TransactionInner::empty(&mut self.doc, args, options)
     }
 
     /// An implementation of [`crate::sync::SyncDoc`] for this autocommit
@@ -628,6 +791,18 @@ impl AutoCommit {
 }
 
 impl ReadDoc for AutoCommit {
+    fn view<O: AsRef<ExId>>(&self, obj: O) -> Result<ObjView<'_>, AutomergeError> {
+        self.doc.view_for(obj.as_ref(), self.get_scope(None))
+    }
+
+    fn view_at<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        heads: &[ChangeHash],
+    ) -> Result<ObjView<'_>, AutomergeError> {
+        self.doc.view_for(obj.as_ref(), self.get_scope(Some(heads)))
+    }
+
     fn parents<O: AsRef<ExId>>(&self, obj: O) -> Result<Parents<'_>, AutomergeError> {
         self.doc.parents_for(obj.as_ref(), self.get_scope(None))
     }
@@ -649,6 +824,26 @@ impl ReadDoc for AutoCommit {
         self.doc.keys_for(obj.as_ref(), self.get_scope(Some(heads)))
     }
 
+    fn keys_with_conflicts<O: AsRef<ExId>>(&self, obj: O) -> KeysWithConflicts<'_> {
+        self.doc
+            .keys_with_conflicts_for(obj.as_ref(), self.get_scope(None))
+    }
+
+    fn map_keys_in_insertion_order<O: AsRef<ExId>>(&self, obj: O) -> Vec<String> {
+        self.doc
+            .map_keys_in_insertion_order_for(obj.as_ref(), self.get_scope(None))
+    }
+
+    fn map_values_sorted_by<O: AsRef<ExId>, K: Ord, F: Fn(&Value<'_>) -> K>(
+        &self,
+        obj: O,
+        key_fn: F,
+        heads: Option<&[ChangeHash]>,
+    ) -> Result<Vec<(Prop, Value<'_>)>, AutomergeError> {
+        self.doc
+            .map_values_sorted_by_for(obj.as_ref(), key_fn, self.get_scope(heads))
+    }
+
     fn map_range<'a, O: AsRef<ExId>, R: RangeBounds<String> + 'a>(
         &'a self,
         obj: O,
@@ -687,6 +882,53 @@ impl ReadDoc for AutoCommit {
             .list_range_for(obj.as_ref(), range, self.get_scope(Some(heads)))
     }
 
+    fn find_indices<O: AsRef<ExId>, F: Fn(&Value<'_>) -> bool>(
+        &self,
+        obj: O,
+        pred: F,
+        heads: Option<&[ChangeHash]>,
+    ) -> Result<Vec<usize>, AutomergeError> {
+        self.doc
+            .find_indices_for(obj.as_ref(), pred, self.get_scope(heads))
+    }
+
+    fn list_endpoints<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        heads: Option<&[ChangeHash]>,
+    ) -> Result<Option<(crate::ListEndpoint<'_>, crate::ListEndpoint<'_>)>, AutomergeError> {
+        self.doc
+            .list_endpoints_for(obj.as_ref(), self.get_scope(heads))
+    }
+
+    fn list_range_rev<O: AsRef<ExId>, R: RangeBounds<usize>>(
+        &self,
+        obj: O,
+        range: R,
+    ) -> ListRangeRev<'_> {
+        self.doc
+            .list_range_rev_for(obj.as_ref(), range, self.get_scope(None))
+    }
+
+    fn list_range_rev_at<O: AsRef<ExId>, R: RangeBounds<usize>>(
+        &self,
+        obj: O,
+        range: R,
+        heads: &[ChangeHash],
+    ) -> ListRangeRev<'_> {
+        self.doc
+            .list_range_rev_for(obj.as_ref(), range, self.get_scope(Some(heads)))
+    }
+
+    fn read_bytes<O: AsRef<ExId>, R: RangeBounds<usize>>(
+        &self,
+        obj: O,
+        range: R,
+    ) -> Result<Vec<u8>, AutomergeError> {
+        self.doc
+            .read_bytes_for(obj.as_ref(), range, self.get_scope(None))
+    }
+
     fn values<O: AsRef<ExId>>(&self, obj: O) -> Values<'_> {
         self.doc.values_for(obj.as_ref(), self.get_scope(None))
     }
@@ -696,11 +938,41 @@ impl ReadDoc for AutoCommit {
             .values_for(obj.as_ref(), self.get_scope(Some(heads)))
     }
 
+    fn entries<O: AsRef<ExId>>(&self, obj: O) -> Entries<'_> {
+        self.doc.entries_for(obj.as_ref(), self.get_scope(None))
+    }
+
+    fn entries_at<O: AsRef<ExId>>(&self, obj: O, heads: &[ChangeHash]) -> Entries<'_> {
+        self.doc
+            .entries_for(obj.as_ref(), self.get_scope(Some(heads)))
+    }
+
+    fn values_ref<O: AsRef<ExId>>(&self, obj: O) -> ValuesRef<'_> {
+        self.doc.values_ref_for(obj.as_ref(), self.get_scope(None))
+    }
+
+    fn objects(&self) -> Objects<'_> {
+        Objects::new(&self.doc, self.get_scope(None))
+    }
+
     fn length<O: AsRef<ExId>>(&self, obj: O) -> usize {
         self.doc.length_for(obj.as_ref(), self.get_scope(None))
     }
 
+    fn length_utf16<O: AsRef<ExId>>(&self, obj: O) -> usize {
+        self.doc
+            .length_utf16_for(obj.as_ref(), self.get_scope(None))
+    }
+
+    fn length_grapheme<O: AsRef<ExId>>(&self, obj: O) -> usize {
+        self.doc
+            .length_grapheme_for(obj.as_ref(), self.get_scope(None))
+    }
+
     fn length_at<O: AsRef<ExId>>(&self, obj: O, heads: &[ChangeHash]) -> usize {
+        if self.transaction.is_none() {
+            return self.doc.length_at(obj, heads);
+        }
         self.doc
             .length_for(obj.as_ref(), self.get_scope(Some(heads)))
     }
@@ -709,6 +981,15 @@ impl ReadDoc for AutoCommit {
         self.doc.object_type(obj)
     }
 
+    fn object_type_at<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        heads: &[ChangeHash],
+    ) -> Result<Option<ObjType>, AutomergeError> {
+        self.doc
+            .object_type_for(obj.as_ref(), self.get_scope(Some(heads)))
+    }
+
     fn marks<O: AsRef<ExId>>(&self, obj: O) -> Result<Vec<Mark<'_>>, AutomergeError> {
         self.doc.marks_for(obj.as_ref(), self.get_scope(None))
     }
@@ -722,6 +1003,24 @@ impl ReadDoc for AutoCommit {
             .marks_for(obj.as_ref(), self.get_scope(Some(heads)))
     }
 
+    fn all_marks(&self) -> AllMarks<'_> {
+        AllMarks::new(&self.doc, self.get_scope(None))
+    }
+
+    fn all_marks_at(&self, heads: &[ChangeHash]) -> AllMarks<'_> {
+        AllMarks::new(&self.doc, self.get_scope(Some(heads)))
+    }
+
+    fn marks_in_range<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        range: Range<usize>,
+        heads: Option<&[ChangeHash]>,
+    ) -> Result<Vec<Mark<'_>>, AutomergeError> {
+        self.doc
+            .marks_in_range_for(obj.as_ref(), range, self.get_scope(heads))
+    }
+
     fn get_marks<O: AsRef<ExId>>(
         &self,
         obj: O,
@@ -732,6 +1031,16 @@ impl ReadDoc for AutoCommit {
             .get_marks_for(obj.as_ref(), index, self.get_scope(heads))
     }
 
+    fn text_at_cursor<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        cursor: &Cursor,
+        heads: Option<&[ChangeHash]>,
+    ) -> Result<MarkSet, AutomergeError> {
+        self.doc
+            .text_at_cursor_for(obj.as_ref(), cursor, self.get_scope(heads))
+    }
+
     fn text<O: AsRef<ExId>>(&self, obj: O) -> Result<String, AutomergeError> {
         self.doc.text_for(obj.as_ref(), self.get_scope(None))
     }
@@ -744,6 +1053,15 @@ impl ReadDoc for AutoCommit {
         self.doc.text_for(obj.as_ref(), self.get_scope(Some(heads)))
     }
 
+    fn text_diff<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        before: &[ChangeHash],
+        after: &[ChangeHash],
+    ) -> Result<Vec<TextEdit>, AutomergeError> {
+        self.doc.text_diff_for(obj.as_ref(), before, after)
+    }
+
     fn spans<O: AsRef<ExId>>(&self, obj: O) -> Result<Spans<'_>, AutomergeError> {
         self.doc.spans_for(obj.as_ref(), self.get_scope(None))
     }
@@ -757,6 +1075,40 @@ impl ReadDoc for AutoCommit {
             .spans_for(obj.as_ref(), self.get_scope(Some(heads)))
     }
 
+    fn spans_range<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        range: Range<usize>,
+        heads: Option<&[ChangeHash]>,
+    ) -> Result<Spans<'_>, AutomergeError> {
+        self.doc
+            .spans_range_for(obj.as_ref(), range, self.get_scope(heads))
+    }
+
+    fn export_rich_text<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        range: Range<usize>,
+        heads: Option<&[ChangeHash]>,
+    ) -> Result<RichText, AutomergeError> {
+        self.doc
+            .export_rich_text_for(obj.as_ref(), range, self.get_scope(heads))
+    }
+
+    fn import_exid(&self, s: &str) -> Result<ExId, AutomergeError> {
+        self.doc.import_exid(s)
+    }
+
+    fn char_to_utf16<O: AsRef<ExId>>(&self, obj: O, index: usize) -> Result<usize, AutomergeError> {
+        self.doc
+            .char_to_utf16_for(obj.as_ref(), index, self.get_scope(None))
+    }
+
+    fn utf16_to_char<O: AsRef<ExId>>(&self, obj: O, index: usize) -> Result<usize, AutomergeError> {
+        self.doc
+            .utf16_to_char_for(obj.as_ref(), index, self.get_scope(None))
+    }
+
     fn get_cursor<O: AsRef<ExId>>(
         &self,
         obj: O,
@@ -777,6 +1129,78 @@ impl ReadDoc for AutoCommit {
             .get_cursor_position_for(obj.as_ref(), address, self.get_scope(at))
     }
 
+    fn get_cursor_positions<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        cursors: &[Cursor],
+        at: Option<&[ChangeHash]>,
+    ) -> Result<Vec<usize>, AutomergeError> {
+        self.doc
+            .get_cursor_positions_for(obj.as_ref(), cursors, self.get_scope(at))
+    }
+
+    fn get_cursor_position_and_value<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        address: &Cursor,
+        at: Option<&[ChangeHash]>,
+    ) -> Result<(usize, Option<Value<'_>>), AutomergeError> {
+        self.doc
+            .get_cursor_position_and_value_for(obj.as_ref(), address, self.get_scope(at))
+    }
+
+    fn index_of<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        elem: &ExId,
+        at: Option<&[ChangeHash]>,
+    ) -> Result<Option<usize>, AutomergeError> {
+        self.doc
+            .index_of_for(obj.as_ref(), elem, self.get_scope(at))
+    }
+
+    fn element_token<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        index: usize,
+    ) -> Result<String, AutomergeError> {
+        self.doc
+            .element_token_for(obj.as_ref(), index, self.get_scope(None))
+    }
+
+    fn index_of_token<O: AsRef<ExId>>(&self, obj: O, token: &str) -> Result<usize, AutomergeError> {
+        self.doc
+            .index_of_token_for(obj.as_ref(), token, self.get_scope(None))
+    }
+
+    fn visible_count_before<O: AsRef<ExId>>(&self, obj: O, op_pos: usize) -> usize {
+        self.doc
+            .visible_count_before_for(obj.as_ref(), op_pos, self.get_scope(None))
+    }
+
+    fn value_provenance<O: AsRef<ExId>, P: Into<Prop>>(
+        &self,
+        obj: O,
+        prop: P,
+    ) -> Result<Option<ChangeHash>, AutomergeError> {
+        self.doc
+            .value_provenance_for(obj.as_ref(), prop.into(), self.get_scope(None))
+    }
+
+    fn map_contains_key<O: AsRef<ExId>>(&self, obj: O, key: &str) -> Result<bool, AutomergeError> {
+        self.doc
+            .map_contains_key_for(obj.as_ref(), key, self.get_scope(None))
+    }
+
+    fn list_contains_index<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        index: usize,
+    ) -> Result<bool, AutomergeError> {
+        self.doc
+            .list_contains_index_for(obj.as_ref(), index, self.get_scope(None))
+    }
+
     fn hydrate<O: AsRef<ExId>>(
         &self,
         obj: O,
@@ -823,6 +1247,59 @@ impl ReadDoc for AutoCommit {
             .get_all_for(obj.as_ref(), prop.into(), self.get_scope(Some(heads)))
     }
 
+    fn get_conflicts<O: AsRef<ExId>, P: Into<Prop>>(
+        &self,
+        obj: O,
+        prop: P,
+    ) -> Result<Vec<(ActorId, Value<'_>, ExId)>, AutomergeError> {
+        self.doc
+            .get_conflicts_for(obj.as_ref(), prop.into(), self.get_scope(None))
+    }
+
+    fn get_timestamp<O: AsRef<ExId>, P: Into<Prop>>(
+        &self,
+        obj: O,
+        prop: P,
+    ) -> Result<Option<i64>, AutomergeError> {
+        self.doc
+            .get_timestamp_for(obj.as_ref(), prop.into(), self.get_scope(None))
+    }
+
+    fn get_at_element_creation<O: AsRef<ExId>, P: Into<Prop>, L: AsRef<ExId>>(
+        &self,
+        obj: O,
+        prop: P,
+        list: L,
+        cursor: &Cursor,
+    ) -> Result<Option<(Value<'_>, ExId)>, AutomergeError> {
+        self.doc.get_at_element_creation_for(
+            obj.as_ref(),
+            prop.into(),
+            list.as_ref(),
+            cursor,
+            self.get_scope(None),
+        )
+    }
+
+    fn get_by_timestamp<O: AsRef<ExId>, P: Into<Prop>>(
+        &self,
+        obj: O,
+        prop: P,
+    ) -> Result<Option<(Value<'_>, ExId)>, AutomergeError> {
+        self.doc
+            .get_by_timestamp_for(obj.as_ref(), prop.into(), self.get_scope(None))
+    }
+
+    fn get_many_at<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        props: &[Prop],
+        heads: &[ChangeHash],
+    ) -> Result<Vec<(Prop, Option<(Value<'_>, ExId)>)>, AutomergeError> {
+        self.doc
+            .get_many_for(obj.as_ref(), props, self.get_scope(Some(heads)))
+    }
+
     fn get_missing_deps(&self, heads: &[ChangeHash]) -> Vec<ChangeHash> {
         self.doc.get_missing_deps(heads)
     }
@@ -834,6 +1311,20 @@ impl ReadDoc for AutoCommit {
     fn stats(&self) -> crate::read::Stats {
         self.doc.stats()
     }
+
+    fn object_stats<O: AsRef<ExId>>(&self, obj: O) -> Result<ObjStats, AutomergeError> {
+        self.doc
+            .object_stats_for(obj.as_ref(), self.get_scope(None))
+    }
+
+    fn tombstone_count<O: AsRef<ExId>>(&self, obj: O) -> Result<usize, AutomergeError> {
+        self.doc
+            .tombstone_count_for(obj.as_ref(), self.get_scope(None))
+    }
+
+    fn total_tombstones(&self) -> usize {
+        self.doc.total_tombstones_for(self.get_scope(None))
+    }
 }
 
 impl Transactable for AutoCommit {
@@ -855,6 +1346,17 @@ impl Transactable for AutoCommit {
         tx.put(&mut self.doc, patch_log, obj.as_ref(), prop, value)
     }
 
+    fn put_if_absent<O: AsRef<ExId>, P: Into<Prop>, V: Into<ScalarValue>>(
+        &mut self,
+        obj: O,
+        prop: P,
+        value: V,
+    ) -> Result<bool, AutomergeError> {
+        self.ensure_transaction_open();
+        let (patch_log, tx) = self.transaction.as_mut().unwrap();
+        tx.put_if_absent(&mut self.doc, patch_log, obj.as_ref(), prop, value)
+    }
+
     fn put_object<O: AsRef<ExId>, P: Into<Prop>>(
         &mut self,
         obj: O,
@@ -923,6 +1425,17 @@ impl Transactable for AutoCommit {
         tx.splice(&mut self.doc, patch_log, obj.as_ref(), pos, del, vals)
     }
 
+    fn splice_objects<O: AsRef<ExId>>(
+        &mut self,
+        obj: O,
+        index: usize,
+        objs: Vec<ObjType>,
+    ) -> Result<Vec<ExId>, AutomergeError> {
+        self.ensure_transaction_open();
+        let (patch_log, tx) = self.transaction.as_mut().unwrap();
+        tx.splice_objects(&mut self.doc, patch_log, obj.as_ref(), index, objs)
+    }
+
     fn splice_text<O: AsRef<ExId>>(
         &mut self,
         obj: O,
@@ -936,6 +1449,73 @@ impl Transactable for AutoCommit {
         Ok(())
     }
 
+    fn splice_text_with_ids<O: AsRef<ExId>>(
+        &mut self,
+        obj: O,
+        pos: usize,
+        text: &str,
+    ) -> Result<Vec<ExId>, AutomergeError> {
+        self.ensure_transaction_open();
+        let (patch_log, tx) = self.transaction.as_mut().unwrap();
+        tx.splice_text_with_ids(&mut self.doc, patch_log, obj.as_ref(), pos, text)
+    }
+
+    fn splice_text_with_id_range<O: AsRef<ExId>>(
+        &mut self,
+        obj: O,
+        pos: usize,
+        text: &str,
+    ) -> Result<Option<(ExId, ExId)>, AutomergeError> {
+        self.ensure_transaction_open();
+        let (patch_log, tx) = self.transaction.as_mut().unwrap();
+        tx.splice_text_with_id_range(&mut self.doc, patch_log, obj.as_ref(), pos, text)
+    }
+
+    fn splice_text_utf16<O: AsRef<ExId>>(
+        &mut self,
+        obj: O,
+        pos: usize,
+        del: isize,
+        text: &str,
+    ) -> Result<(), AutomergeError> {
+        self.ensure_transaction_open();
+        let (patch_log, tx) = self.transaction.as_mut().unwrap();
+        tx.splice_text_utf16(&mut self.doc, patch_log, obj.as_ref(), pos, del, text)
+    }
+
+    fn splice_text_with_encoding<O: AsRef<ExId>>(
+        &mut self,
+        obj: O,
+        pos: usize,
+        del: isize,
+        text: &str,
+        encoding: TextEncoding,
+    ) -> Result<(), AutomergeError> {
+        self.ensure_transaction_open();
+        let (patch_log, tx) = self.transaction.as_mut().unwrap();
+        tx.splice_text_with_encoding(
+            &mut self.doc,
+            patch_log,
+            obj.as_ref(),
+            pos,
+            del,
+            text,
+            encoding.into(),
+        )
+    }
+
+    fn splice_text_with_marks<O: AsRef<ExId>>(
+        &mut self,
+        obj: O,
+        pos: usize,
+        text: &str,
+        marks: &[(Range<usize>, &str, ScalarValue)],
+    ) -> Result<(), AutomergeError> {
+        self.ensure_transaction_open();
+        let (patch_log, tx) = self.transaction.as_mut().unwrap();
+        tx.splice_text_with_marks(&mut self.doc, patch_log, obj.as_ref(), pos, text, marks)
+    }
+
     fn mark<O: AsRef<ExId>>(
         &mut self,
         obj: O,
@@ -947,6 +1527,17 @@ impl Transactable for AutoCommit {
         tx.mark(&mut self.doc, patch_log, obj.as_ref(), mark, expand)
     }
 
+    fn mark_and_return_range<O: AsRef<ExId>>(
+        &mut self,
+        obj: O,
+        mark: Mark<'_>,
+        expand: ExpandMark,
+    ) -> Result<Range<usize>, AutomergeError> {
+        self.ensure_transaction_open();
+        let (patch_log, tx) = self.transaction.as_mut().unwrap();
+        tx.mark_and_return_range(&mut self.doc, patch_log, obj.as_ref(), mark, expand)
+    }
+
     fn unmark<O: AsRef<ExId>>(
         &mut self,
         obj: O,
@@ -1046,7 +1637,7 @@ impl<'a> SyncDoc for SyncWrapper<'a> {
         &mut self,
         sync_state: &mut sync::State,
         message: sync::Message,
-    ) -> Result<(), AutomergeError> {
+    ) -> Result<Vec<ChangeHash>, AutomergeError> {
         self.inner.ensure_transaction_closed();
         if self.inner.isolation.is_some() {
             self.inner.doc.receive_sync_message_log_patches(
@@ -1070,13 +1661,16 @@ impl<'a> SyncDoc for SyncWrapper<'a> {
         sync_state: &mut sync::State,
         message: sync::Message,
         patch_log: &mut PatchLog,
-    ) -> Result<(), AutomergeError> {
+    ) -> Result<Vec<ChangeHash>, AutomergeError> {
         self.inner
             .doc
             .receive_sync_message_log_patches(sync_state, message, patch_log)
     }
 }
 
+/// The most objects [`AutoCommit::text_cow()`] keeps the text of
+const TEXT_CACHE_SIZE: usize = 16;
+
 #[derive(Debug, Clone, PartialEq)]
 struct OpRange {
     before_len: usize,
@@ -1,22 +1,27 @@
 use std::cmp::Ordering;
-use std::collections::{BTreeSet, HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::fmt::Debug;
 use std::num::NonZeroU64;
-use std::ops::RangeBounds;
+use std::ops::{ControlFlow, Range, RangeBounds};
 
 use itertools::Itertools;
+use unicode_segmentation::UnicodeSegmentation;
 
 use crate::change_graph::ChangeGraph;
 use crate::columnar::Key as EncodedKey;
 use crate::exid::ExId;
-use crate::iter::{Keys, ListRange, MapRange, Spans, Values};
-use crate::marks::{Mark, MarkAccumulator, MarkSet, MarkStateMachine};
+use crate::iter::{
+    AllMarks, Entries, Keys, KeysWithConflicts, ListRange, ListRangeRev, MapRange, Objects, Span,
+    Spans, Values, ValuesRef,
+};
+use crate::marks::{Mark, MarkAccumulator, MarkSet, MarkStateMachine, RichText};
 use crate::op_set::{OpSet, OpSetData};
 use crate::parents::Parents;
-use crate::patches::{Patch, PatchLog, TextRepresentation};
+use crate::patches::{Patch, PatchAction, PatchLog, TextEdit, TextRepresentation};
 use crate::query;
-use crate::read::ReadDocInternal;
+use crate::read::{ObjStats, ReadDocInternal};
 use crate::storage::{self, load, CompressConfig, VerificationMode};
+use crate::text_value::TextValue;
 use crate::transaction::{
     self, CommitOptions, Failure, Success, Transactable, Transaction, TransactionArgs,
 };
@@ -25,10 +30,21 @@ use crate::types::{
     ObjMeta, OpBuilder, OpId, OpIds, OpType, Value,
 };
 use crate::{hydrate, ScalarValue};
-use crate::{AutomergeError, Change, Cursor, ObjType, Prop, ReadDoc};
+use crate::{AutomergeError, Change, Cursor, ObjType, ObjView, Prop, ReadDoc};
 
 pub(crate) mod current_state;
 pub(crate) mod diff;
+mod integrity;
+mod raw_ops;
+mod shallow;
+mod structural_diff;
+mod trim;
+
+pub use integrity::IntegrityReport;
+pub use raw_ops::{RawAction, RawKey, RawOp};
+pub use shallow::ShallowDoc;
+pub use structural_diff::StructuralDiff;
+use trim::{HistoryEntry, Snapshot};
 
 #[cfg(test)]
 mod tests;
@@ -91,6 +107,7 @@ impl<'a> LoadOptions<'a> {
     }
 
     /// A [`PatchLog`] to log the changes required to materialize the current state of the
+    /// document
     ///
     /// The default is to not log patches
     pub fn patch_log(self, patch_log: &'a mut PatchLog) -> Self {
@@ -125,6 +142,65 @@ impl<'a> LoadOptions<'a> {
     }
 }
 
+/// Bounds on the size of a document loaded with [`Automerge::load_with_limits()`]
+///
+/// The default places no bounds on anything.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// The maximum number of operations stored in the document
+    pub max_ops: usize,
+    /// The maximum number of objects created in the document, including deleted objects
+    pub max_objects: usize,
+    /// The maximum number of changes in the document
+    pub max_change_count: usize,
+    /// The maximum total length of the chunks in the document once decompressed, in bytes
+    pub max_decompressed_size: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_ops: usize::MAX,
+            max_objects: usize::MAX,
+            max_change_count: usize::MAX,
+            max_decompressed_size: usize::MAX,
+        }
+    }
+}
+
+/// The sequence numbers of the changes from one actor which a document has, see
+/// [`Automerge::missing_deps_report()`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SeqReport {
+    /// Every change with a sequence number up to and including this one has been applied
+    pub applied: u64,
+    /// The sequence numbers of changes which have been received but are waiting for their
+    /// dependencies, sorted
+    pub queued: Vec<u64>,
+    /// The runs of sequence numbers after `applied` which are neither applied nor queued but are
+    /// lower than the highest queued sequence number, sorted
+    ///
+    /// Each gap is reported as a range rather than seq by seq, as a single queued change with a
+    /// huge sequence number would otherwise report billions of missing changes.
+    pub missing: Vec<Range<u64>>,
+}
+
+/// Size information about one change in a document, see [`Automerge::change_metadata()`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChangeStats {
+    pub hash: ChangeHash,
+    pub actor: ActorId,
+    pub seq: u64,
+    /// The number of operations in the change
+    pub op_count: usize,
+    /// The length of the uncompressed encoding of the change
+    pub size: usize,
+    /// The length of the change as returned by [`Change::bytes()`], which is compressed if the
+    /// change is large enough for that to help
+    pub compressed_size: usize,
+    pub message: Option<String>,
+}
+
 impl std::default::Default for LoadOptions<'static> {
     fn default() -> Self {
         Self {
@@ -169,7 +245,7 @@ pub struct Automerge {
     /// The list of unapplied changes that are not causally ready.
     queue: Vec<Change>,
     /// The history of changes that form this document, topologically sorted too.
-    history: Vec<Change>,
+    history: Vec<HistoryEntry>,
     /// Mapping from change hash to index into the history list.
     history_index: HashMap<ChangeHash, usize>,
     /// Graph of changes
@@ -184,6 +260,10 @@ pub struct Automerge {
     actor: Actor,
     /// The maximum operation counter this document has seen.
     max_op: u64,
+    /// The document at the point its history was trimmed to, see [`Automerge::trim_changes()`]
+    snapshot: Option<Snapshot>,
+    /// Objects which local transactions may not modify, see [`Automerge::freeze_object()`]
+    frozen: HashSet<ObjId>,
 }
 
 impl Automerge {
@@ -199,6 +279,8 @@ impl Automerge {
             deps: Default::default(),
             actor: Actor::Unused(ActorId::random()),
             max_op: 0,
+            snapshot: None,
+            frozen: HashSet::new(),
         }
     }
 
@@ -227,6 +309,18 @@ impl Automerge {
     }
 
     /// Set the actor id for this document.
+    ///
+    /// This is the builder form of [`Self::set_actor()`], for setting up a new document in one
+    /// expression:
+    ///
+    /// ```
+    /// # use automerge::{ActorId, Automerge};
+    /// let actor = ActorId::from([1, 2, 3]);
+    /// let doc = Automerge::new()
+    ///     .with_actor(actor.clone())
+    ///     .with_op_tree_node_size(32);
+    /// assert_eq!(doc.get_actor(), &actor);
+    /// ```
     pub fn with_actor(mut self, actor: ActorId) -> Self {
         self.actor = Actor::Unused(actor);
         self
@@ -238,6 +332,140 @@ impl Automerge {
         self
     }
 
+    /// Set the minimum degree of the B-tree nodes which store the operations of each object
+    ///
+    /// Nodes hold between `size - 1` and `2 * size - 1` operations. The default is
+    /// [`crate::op_tree::B`], larger nodes may be faster for documents with very long lists of
+    /// small values. This only affects performance, the contents of the document and the saved
+    /// format are unchanged. The operations of any existing objects are rebuilt into trees of the
+    /// new size, so it is cheapest to call this on a new document. The setting is not saved with
+    /// the document.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is less than 2
+    pub fn with_op_tree_node_size(mut self, size: usize) -> Self {
+        self.set_op_tree_node_size(size);
+        self
+    }
+
+    /// Like [`Self::with_op_tree_node_size()`] but for an existing reference
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is less than 2
+    pub fn set_op_tree_node_size(&mut self, size: usize) -> &mut Self {
+        assert!(
+            size >= 2,
+            "op tree nodes must have a minimum degree of at least 2"
+        );
+        self.ops.set_node_size(size);
+        self
+    }
+
+    /// Keep the operation trees of at most `max_resident` objects in memory
+    ///
+    /// The operations of each object are indexed by a B-tree, which for a document with many
+    /// objects of which only a few are in use can take up most of its memory. In this mode the
+    /// trees of the objects which were used least recently are dropped after each commit and each
+    /// call to [`Self::apply_changes()`], and their operations are kept compressed in the format
+    /// of a saved document. An object whose tree has been dropped is decoded again when it is
+    /// modified, which makes it resident again, or when it is read. Trees decoded by reads are
+    /// kept in a cache of the `max_resident` most recently read objects, which is trimmed at the
+    /// next commit or call to [`Self::spill_cold_objects()`] since reads can't free memory.
+    /// Saving the document and other reads of every object decode the objects they need without
+    /// caching them. This only affects performance: the contents of the document and the saved
+    /// format are unchanged. The root object is never dropped. The setting is not saved with the
+    /// document.
+    ///
+    /// ```
+    /// # use automerge::{transaction::Transactable, AutoCommit, ObjType, ReadDoc, ROOT};
+    /// let mut doc = AutoCommit::new().with_lazy_objects(1);
+    /// for i in 0..10 {
+    ///     let list = doc.put_object(ROOT, format!("list{}", i), ObjType::List).unwrap();
+    ///     doc.insert(&list, 0, i).unwrap();
+    ///     doc.commit();
+    /// }
+    /// assert_eq!(doc.spill_cold_objects(), 0);
+    ///
+    /// let (_, list) = doc.get(ROOT, "list3").unwrap().unwrap();
+    /// assert_eq!(doc.length(&list), 1);
+    /// ```
+    pub fn with_lazy_objects(mut self, max_resident: usize) -> Self {
+        self.set_lazy_objects(Some(max_resident));
+        self
+    }
+
+    /// Like [`Self::with_lazy_objects()`] but for an existing reference, `None` rebuilds every
+    /// object and keeps them all in memory
+    pub fn set_lazy_objects(&mut self, max_resident: Option<usize>) -> &mut Self {
+        self.ops.set_max_resident_trees(max_resident);
+        self
+    }
+
+    /// Drop the operation trees of the least recently used objects now, returning the number of
+    /// trees dropped
+    ///
+    /// This does nothing unless [`Self::with_lazy_objects()`] is set. Use it to free memory
+    /// straight away, for example after reading many objects, rather than after the next commit.
+    /// It also trims the cache of trees decoded by reads.
+    pub fn spill_cold_objects(&mut self) -> usize {
+        self.ops.spill_cold_trees()
+    }
+
+    /// Rebuild the in-memory indexes of the document and release the memory they no longer use
+    ///
+    /// This does what loading the output of [`Self::save()`] does to the memory use of a
+    /// document, without encoding and decoding it. The operations are moved so the operations of
+    /// each object are next to each other, as they are after loading, and the operation tree of
+    /// every object is built again from its operations with as few nodes as the node size allows,
+    /// where editing leaves nodes which were split half empty. The spare capacity of the history
+    /// and of the changes in it is released. The heads, the history and the result of every
+    /// read, including reads at earlier heads, are unchanged, and so is the output of
+    /// [`Self::save()`].
+    ///
+    /// Deleted values are kept. They are needed to read the document at earlier heads, and a
+    /// peer which hasn't seen the deletion may still send changes which refer to them, for
+    /// example an insertion after a deleted list element, so they can't be dropped without
+    /// breaking convergence.
+    ///
+    /// ```
+    /// # use automerge::{transaction::Transactable, AutoCommit, ObjType, ReadDoc, ROOT};
+    /// let mut doc = AutoCommit::new();
+    /// let text = doc.put_object(ROOT, "text", ObjType::Text).unwrap();
+    /// for _ in 0..100 {
+    ///     doc.splice_text(&text, 0, 0, "a").unwrap();
+    ///     doc.splice_text(&text, 0, 1, "").unwrap();
+    /// }
+    /// let heads = doc.get_heads();
+    /// let saved = doc.save();
+    ///
+    /// doc.compact();
+    /// assert_eq!(doc.get_heads(), heads);
+    /// assert_eq!(doc.save(), saved);
+    /// ```
+    pub fn compact(&mut self) {
+        self.ops.compact();
+        self.queue.shrink_to_fit();
+        for change in self.queue.iter_mut() {
+            change.shrink_to_fit();
+        }
+        for entry in self.history.iter_mut() {
+            if let HistoryEntry::Change(change) = entry {
+                change.shrink_to_fit();
+            }
+        }
+        self.history.shrink_to_fit();
+        self.history_index.shrink_to_fit();
+        self.change_graph.shrink_to_fit();
+        for seqs in self.states.values_mut() {
+            seqs.shrink_to_fit();
+        }
+        self.states.shrink_to_fit();
+        self.deps.shrink_to_fit();
+        self.frozen.shrink_to_fit();
+    }
+
     /// Get the current actor id of this document.
     pub fn get_actor(&self) -> &ActorId {
         match &self.actor {
@@ -251,9 +479,7 @@ impl Automerge {
             Actor::Unused(actor) => {
                 let index = self
                     .ops
-                    .osd
-                    .actors
-                    .cache(std::mem::replace(actor, ActorId::from(&[][..])));
+                    .import_actor(std::mem::replace(actor, ActorId::from(&[][..])));
                 self.actor = Actor::Cached(index);
                 index
             }
@@ -452,26 +678,51 @@ impl Automerge {
 
     /// Fork this document at the given heads
     ///
-    /// This will create a new actor ID for the forked document
+    /// The new document contains exactly the changes in the history of `heads` and shares no
+    /// state with this document. This will create a new actor ID for the forked document so
+    /// that changes made to it don't collide with changes made to this one.
+    ///
+    /// # Errors
+    ///
+    /// * [`AutomergeError::InvalidHash`] if any of `heads` is not in this document.
+    /// * [`AutomergeError::TrimmedChange`] if the history of `heads` includes some, but not all,
+    ///   of the changes removed by [`Self::trim_changes()`]
     pub fn fork_at(&self, heads: &[ChangeHash]) -> Result<Self, AutomergeError> {
         let mut seen = heads.iter().cloned().collect::<HashSet<_>>();
-        let mut heads = heads.to_vec();
+        let mut to_visit = heads.to_vec();
         let mut changes = vec![];
-        while let Some(hash) = heads.pop() {
+        let mut trimmed = None;
+        while let Some(hash) = to_visit.pop() {
             if let Some(idx) = self.history_index.get(&hash) {
-                let change = &self.history[*idx];
+                let Some(change) = self.history[*idx].change() else {
+                    trimmed = Some(hash);
+                    continue;
+                };
                 for dep in change.deps() {
-                    if !seen.contains(dep) {
-                        heads.push(*dep);
+                    if seen.insert(*dep) {
+                        to_visit.push(*dep);
                     }
                 }
                 changes.push(change);
-                seen.insert(hash);
             } else {
                 return Err(AutomergeError::InvalidHash(hash));
             }
         }
-        let mut f = Self::new();
+        let mut f = match (trimmed, &self.snapshot) {
+            (Some(hash), Some(snapshot)) => {
+                let clock = self.clock_at(heads);
+                if !snapshot
+                    .heads
+                    .iter()
+                    .all(|h| self.clock_includes(&clock, &self.history[self.history_index[h]]))
+                {
+                    return Err(AutomergeError::TrimmedChange(hash));
+                }
+                Self::load(&snapshot.bytes)?
+            }
+            (Some(hash), None) => return Err(AutomergeError::TrimmedChange(hash)),
+            (None, _) => Self::new(),
+        };
         f.set_actor(ActorId::random());
         f.apply_changes(changes.into_iter().rev().cloned())?;
         Ok(f)
@@ -508,8 +759,11 @@ impl Automerge {
         cursor: &Cursor,
         clock: Option<&Clock>,
     ) -> Result<OpId, AutomergeError> {
-        if let Some(idx) = self.ops.osd.actors.lookup(cursor.actor()) {
-            let opid = OpId::new(cursor.ctr(), idx);
+        let (ctr, actor) = cursor
+            .op()
+            .ok_or_else(|| AutomergeError::InvalidCursor(cursor.clone()))?;
+        if let Some(idx) = self.ops.osd.actors.lookup(actor) {
+            let opid = OpId::new(ctr, idx);
             match clock {
                 Some(clock) if !clock.covers(&opid) => {
                     Err(AutomergeError::InvalidCursor(cursor.clone()))
@@ -575,6 +829,63 @@ impl Automerge {
         )
     }
 
+    /// Load a document, failing with [`AutomergeError::LimitExceeded`] if it is larger than
+    /// `limits` allows
+    ///
+    /// Each chunk in `data` is checked against `limits` as it is parsed, before anything is
+    /// applied to the document. Compressed chunks and columns are only decompressed up to what is
+    /// left of [`Limits::max_decompressed_size`], and operations, objects and changes are counted
+    /// as they are decoded, so oversized input fails as soon as a bound is crossed rather than
+    /// after it has been read in full. Use this to load documents from untrusted sources. The
+    /// checks count what is stored in `data`, so a change which appears more than once counts
+    /// more than once.
+    pub fn load_with_limits(data: &[u8], limits: Limits) -> Result<Self, AutomergeError> {
+        let too_large =
+            || AutomergeError::LimitExceeded("decompressed bytes", limits.max_decompressed_size);
+        let mut inspection = storage::DocInspection::default();
+        let mut decompressed_len = 0;
+        let mut chunks = Vec::new();
+        let mut input = storage::parse::Input::new(data);
+        while !input.is_empty() {
+            let max_len = limits
+                .max_decompressed_size
+                .saturating_sub(decompressed_len);
+            let (remaining, chunk) =
+                storage::Chunk::parse_with_limit(input, max_len).map_err(|e| match e {
+                    storage::parse::ParseError::Error(e) if e.limit_exceeded() => too_large(),
+                    e => load::Error::Parse(Box::new(e)).into(),
+                })?;
+            if !chunk.checksum_valid() {
+                return Err(load::Error::BadChecksum.into());
+            }
+            decompressed_len += chunk.decompressed_len();
+            if decompressed_len > limits.max_decompressed_size {
+                return Err(too_large());
+            }
+            inspection.add_chunk(&chunk, &limits)?;
+            chunks.push(chunk);
+            input = remaining.reset();
+        }
+
+        let mut am = Self::new();
+        let mut first_chunk_was_doc = false;
+        let mut changes = Vec::new();
+        for (index, chunk) in chunks.into_iter().enumerate() {
+            match chunk {
+                storage::Chunk::Document(d) if index == 0 => {
+                    am = reconstruct_document(&d, VerificationMode::Check)?;
+                    first_chunk_was_doc = true;
+                }
+                chunk => load::load_chunk(chunk, &mut changes)?,
+            }
+        }
+        am.apply_changes(changes)?;
+        if !am.queue.is_empty() && !first_chunk_was_doc {
+            return Err(AutomergeError::MissingDeps);
+        }
+        Ok(am)
+    }
+
     /// Load a document, with options
     ///
     /// # Arguments
@@ -654,6 +965,74 @@ impl Automerge {
         Ok(am)
     }
 
+    /// Load a document and log patches which build up its current state to `patch_log`
+    ///
+    /// This is shorthand for [`Self::load_with_options()`] with [`LoadOptions::patch_log()`]. The
+    /// patches create each object before any patch which modifies it, so applying them in order
+    /// to an empty view reproduces the document.
+    ///
+    /// ```
+    /// # use automerge::{transaction::Transactable, AutoCommit, Automerge, ObjType, PatchLog, ROOT};
+    /// # use automerge::patches::TextRepresentation;
+    /// let mut doc = AutoCommit::new();
+    /// let list = doc.put_object(ROOT, "list", ObjType::List).unwrap();
+    /// doc.insert(&list, 0, "value").unwrap();
+    ///
+    /// let mut patch_log = PatchLog::active(TextRepresentation::String);
+    /// let loaded = Automerge::load_and_log_patches(&doc.save(), &mut patch_log).unwrap();
+    /// let patches = loaded.make_patches(&mut patch_log);
+    /// assert_eq!(patches.len(), 2);
+    /// assert_eq!(patches[0].obj, ROOT);
+    /// assert_eq!(patches[1].obj, list);
+    /// ```
+    pub fn load_and_log_patches(
+        data: &[u8],
+        patch_log: &mut PatchLog,
+    ) -> Result<Self, AutomergeError> {
+        Self::load_with_options(data, LoadOptions::new().patch_log(patch_log))
+    }
+
+    /// Load a document from `r`, one chunk at a time
+    ///
+    /// Unlike [`Self::load()`] this does not need the whole of the saved data in memory at once,
+    /// only the chunk currently being loaded. Each chunk's checksum is checked before it is
+    /// applied.
+    ///
+    /// # Errors
+    ///
+    /// * [`AutomergeError::Load`] if a chunk is invalid, `r` ends part way through a chunk or
+    ///   reading from `r` fails
+    /// * [`AutomergeError::MissingDeps`] if the data contains changes whose dependencies are not
+    ///   in the data, unless the first chunk was a document chunk (the same as [`Self::load()`])
+    pub fn load_from_reader<R: std::io::Read>(mut r: R) -> Result<Self, AutomergeError> {
+        let mut am = Self::new();
+        let mut first_chunk = true;
+        let mut first_chunk_was_doc = false;
+        while let Some(bytes) = load::read_chunk(&mut r)? {
+            if std::mem::take(&mut first_chunk) {
+                let (_, chunk) = storage::Chunk::parse(storage::parse::Input::new(&bytes))
+                    .map_err(|e| load::Error::Parse(Box::new(e)))?;
+                if !chunk.checksum_valid() {
+                    return Err(load::Error::BadChecksum.into());
+                }
+                if let storage::Chunk::Document(d) = chunk {
+                    tracing::trace!("first chunk is document chunk, inflating");
+                    am = reconstruct_document(&d, VerificationMode::Check)?;
+                    first_chunk_was_doc = true;
+                    continue;
+                }
+            }
+            match load::load_changes(storage::parse::Input::new(&bytes)) {
+                load::LoadedChanges::Complete(changes) => am.apply_changes(changes)?,
+                load::LoadedChanges::Partial { error, .. } => return Err(error.into()),
+            }
+        }
+        if !am.queue.is_empty() && !first_chunk_was_doc {
+            return Err(AutomergeError::MissingDeps);
+        }
+        Ok(am)
+    }
+
     /// Create the patches from a [`PatchLog`]
     ///
     /// See the documentation for [`PatchLog`] for more details on this
@@ -661,6 +1040,49 @@ impl Automerge {
         patch_log.make_patches(self)
     }
 
+    /// Like [`Self::make_patches()`] but with the patches grouped by the top level object they
+    /// are in
+    ///
+    /// Each patch is keyed by the object at the start of its path which is a direct child of the
+    /// root, or by [`crate::ROOT`] for patches which modify the root itself. The patches in each
+    /// group are in the order [`Self::make_patches()`] would return them.
+    ///
+    /// ```
+    /// # use automerge::{transaction::Transactable, AutoCommit, Automerge, ObjType, PatchLog, ROOT};
+    /// # use automerge::patches::TextRepresentation;
+    /// let mut doc = AutoCommit::new();
+    /// let todos = doc.put_object(ROOT, "todos", ObjType::List).unwrap();
+    /// let todo = doc.insert_object(&todos, 0, ObjType::Map).unwrap();
+    /// doc.put(&todo, "title", "write docs").unwrap();
+    ///
+    /// let mut other = Automerge::new();
+    /// let mut patch_log = PatchLog::active(TextRepresentation::String);
+    /// other
+    ///     .apply_changes_log_patches(doc.get_changes(&[]).into_iter().cloned(), &mut patch_log)
+    ///     .unwrap();
+    /// let groups = other.make_patches_grouped(&mut patch_log);
+    /// assert_eq!(groups[&ROOT].len(), 1);
+    /// assert_eq!(groups[&todos].len(), 2);
+    /// ```
+    pub fn make_patches_grouped(&self, patch_log: &mut PatchLog) -> HashMap<ExId, Vec<Patch>> {
+        patch_log.make_patches_grouped(self)
+    }
+
+    /// Like [`Self::make_patches()`] but passes each patch to `f` as it is generated rather than
+    /// collecting them all first
+    ///
+    /// Consecutive edits to the same object may be split over more patches than
+    /// [`Self::make_patches()`] would produce, applying the patches in order has the same result.
+    /// If `f` returns [`ControlFlow::Break`] no more patches are generated and this returns
+    /// [`ControlFlow::Break`].
+    pub fn make_patches_streaming<F: FnMut(Patch) -> ControlFlow<()>>(
+        &self,
+        patch_log: &mut PatchLog,
+        f: F,
+    ) -> ControlFlow<()> {
+        patch_log.make_patches_streaming(self, f)
+    }
+
     /// Get a set of [`Patch`]es which materialize the current state of the document
     ///
     /// This is a convienence method for [`doc.diff(&[], current_heads)`][diff]
@@ -720,6 +1142,146 @@ impl Automerge {
         Ok(delta)
     }
 
+    /// Load `chunks`, which have been parsed and had their checksums checked, as
+    /// [`Self::load_incremental_log_patches()`] loads the bytes they were parsed from, returning
+    /// the number of changes decoded from them which the document already had
+    ///
+    /// A chunk which can't be decoded is skipped along with the chunks after it.
+    pub(crate) fn load_chunks_log_patches(
+        &mut self,
+        chunks: Vec<storage::Chunk<'_>>,
+        patch_log: &mut PatchLog,
+    ) -> Result<usize, AutomergeError> {
+        let mut chunks = chunks.into_iter().peekable();
+        if self.is_empty() && matches!(chunks.peek(), Some(storage::Chunk::Document(_))) {
+            if let Some(storage::Chunk::Document(d)) = chunks.next() {
+                let doc =
+                    reconstruct_document(&d, VerificationMode::Check)?.with_actor(self.actor_id());
+                if patch_log.is_active() {
+                    current_state::log_current_state_patches(&doc, patch_log);
+                }
+                *self = doc;
+            }
+        }
+        let mut changes = Vec::new();
+        for chunk in chunks {
+            if let Err(error) = load::load_chunk(chunk, &mut changes) {
+                tracing::warn!(successful_chunks=changes.len(), err=?error, "partial load");
+                break;
+            }
+        }
+        let already_had = changes
+            .iter()
+            .filter(|change| {
+                self.history_index.contains_key(&change.hash())
+                    || self
+                        .queue
+                        .iter()
+                        .any(|queued| queued.hash() == change.hash())
+            })
+            .count();
+        self.apply_changes_log_patches(changes, patch_log)?;
+        Ok(already_had)
+    }
+
+    /// Like [`Self::load_incremental()`] but decode and apply the changes in `data`
+    /// `chunk_size` changes at a time, calling `on_group` with the hashes of the changes each
+    /// group applied
+    ///
+    /// [`Self::load_incremental()`] decodes every change in `data` before applying any of them.
+    /// This only decodes enough chunks to fill the next group, so roughly `chunk_size` decoded
+    /// changes are held at once, although a document chunk is always decoded whole. `on_group`
+    /// is the place to report progress or let other work run between groups. The document ends
+    /// up the same as if `data` had been passed to [`Self::load_incremental()`]. A change whose
+    /// dependencies haven't been applied yet is queued, as with [`Self::apply_changes()`], and
+    /// is reported with the group which applies it. A `chunk_size` of zero is treated as one.
+    ///
+    /// Returns the hashes of every change which was applied, in the order they were applied.
+    ///
+    /// ```
+    /// # use automerge::{transaction::Transactable, AutoCommit, Automerge, ROOT};
+    /// let mut source = AutoCommit::new();
+    /// for i in 0..5 {
+    ///     source.put(ROOT, "count", i).unwrap();
+    ///     source.commit();
+    /// }
+    /// let mut doc = Automerge::new();
+    /// let mut groups = Vec::new();
+    /// let applied = doc
+    ///     .load_incremental_chunked(&source.save_after(&[]), 2, |hashes| groups.push(hashes.len()))
+    ///     .unwrap();
+    /// assert_eq!(applied.len(), 5);
+    /// assert_eq!(groups, vec![2, 2, 1]);
+    /// assert_eq!(doc.get_heads(), source.get_heads());
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Any error [`Self::apply_changes()`] returns for a group. The groups before it remain
+    /// applied. As with [`Self::load_incremental()`], anything after the first malformed chunk in
+    /// `data` is ignored.
+    pub fn load_incremental_chunked<F: FnMut(&[ChangeHash])>(
+        &mut self,
+        data: &[u8],
+        chunk_size: usize,
+        on_group: F,
+    ) -> Result<Vec<ChangeHash>, AutomergeError> {
+        self.load_incremental_chunked_log_patches(
+            data,
+            chunk_size,
+            on_group,
+            &mut PatchLog::inactive(TextRepresentation::default()),
+        )
+    }
+
+    pub(crate) fn load_incremental_chunked_log_patches<F: FnMut(&[ChangeHash])>(
+        &mut self,
+        data: &[u8],
+        chunk_size: usize,
+        mut on_group: F,
+        patch_log: &mut PatchLog,
+    ) -> Result<Vec<ChangeHash>, AutomergeError> {
+        let chunk_size = chunk_size.max(1);
+        let start = self.num_applied_changes();
+        let mut data = storage::parse::Input::new(data);
+        let mut pending = Vec::new();
+        loop {
+            while pending.len() < chunk_size && !data.is_empty() {
+                match load::load_next_change(data, &mut pending) {
+                    Ok(remaining) => data = remaining.reset(),
+                    Err(error) => {
+                        tracing::warn!(err=?error, "partial load");
+                        data = storage::parse::Input::empty();
+                    }
+                }
+            }
+            if pending.is_empty() {
+                break;
+            }
+            let rest = pending.split_off(chunk_size.min(pending.len()));
+            let group_start = self.num_applied_changes();
+            self.apply_changes_log_patches(std::mem::replace(&mut pending, rest), patch_log)?;
+            on_group(&self.hashes_applied_since(group_start));
+        }
+        Ok(self.hashes_applied_since(start))
+    }
+
+    /// The hashes of the changes applied after the first `applied` changes, in the order they
+    /// were applied
+    pub(crate) fn hashes_applied_since(&self, applied: usize) -> Vec<ChangeHash> {
+        self.history
+            .get(applied..)
+            .unwrap_or_default()
+            .iter()
+            .map(|entry| entry.hash())
+            .collect()
+    }
+
+    /// The number of changes which have been applied to the document
+    pub(crate) fn num_applied_changes(&self) -> usize {
+        self.history.len()
+    }
+
     fn duplicate_seq(&self, change: &Change) -> bool {
         let mut dup = false;
         if let Some(actor_index) = self.ops.osd.actors.lookup(change.actor_id()) {
@@ -744,6 +1306,53 @@ impl Automerge {
         )
     }
 
+    /// Apply `changes` and pass the resulting patches to `f` one at a time
+    ///
+    /// All the changes are applied to the document before any patches are generated, so the
+    /// document is never left partially updated, only the generation of the patches is streamed.
+    /// The patches are logged to `patch_log`, which should be active, and are generated as by
+    /// [`Self::make_patches_streaming()`]. This is useful when a large remote change would
+    /// otherwise produce a huge [`Vec<Patch>`] all at once.
+    ///
+    /// ```
+    /// # use std::ops::ControlFlow;
+    /// # use automerge::{transaction::Transactable, AutoCommit, Automerge, ObjType, PatchLog, ROOT};
+    /// # use automerge::patches::TextRepresentation;
+    /// let mut remote = AutoCommit::new();
+    /// let text = remote.put_object(ROOT, "text", ObjType::Text).unwrap();
+    /// remote.splice_text(&text, 0, 0, "hello").unwrap();
+    ///
+    /// let mut doc = Automerge::new();
+    /// let mut patch_log = PatchLog::active(TextRepresentation::String);
+    /// let changes = remote.get_changes(&[]).into_iter().cloned();
+    /// let mut count = 0;
+    /// let flow = doc
+    ///     .apply_changes_streaming(changes, &mut patch_log, |_patch| {
+    ///         count += 1;
+    ///         ControlFlow::Continue(())
+    ///     })
+    ///     .unwrap();
+    /// assert_eq!(flow, ControlFlow::Continue(()));
+    /// assert_eq!(count, 2);
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`Self::apply_changes()`], in which case `f` is not called
+    pub fn apply_changes_streaming<I, F>(
+        &mut self,
+        changes: I,
+        patch_log: &mut PatchLog,
+        f: F,
+    ) -> Result<ControlFlow<()>, AutomergeError>
+    where
+        I: IntoIterator<Item = Change>,
+        F: FnMut(Patch) -> ControlFlow<()>,
+    {
+        self.apply_changes_log_patches(changes, patch_log)?;
+        Ok(self.make_patches_streaming(patch_log, f))
+    }
+
     /// Like [`Self::apply_changes()`] but log the resulting changes to the current state of the
     /// document to `patch_log`
     pub fn apply_changes_log_patches<I: IntoIterator<Item = Change>>(
@@ -775,6 +1384,145 @@ impl Automerge {
                 self.apply_change(c, patch_log)?;
             }
         }
+        self.ops.spill_cold_trees();
+        Ok(())
+    }
+
+    /// Apply a batch of changes, checking that every change can be applied before modifying the
+    /// document
+    ///
+    /// The dependencies of each change must either already be in the document or be elsewhere in
+    /// `changes`, in any order. If they are not, or two changes in the batch claim the same
+    /// sequence number for an actor, or an op in a change applies to an object which neither the
+    /// document nor an earlier change in the batch creates, this returns the index in `changes`
+    /// of the offending change and nothing is applied. Otherwise the changes are applied in
+    /// dependency order. Changes
+    /// already in the document are ignored, as are any changes queued by earlier calls to
+    /// [`Self::apply_changes()`].
+    #[allow(clippy::result_large_err)]
+    pub fn apply_changes_checked(
+        &mut self,
+        changes: Vec<Change>,
+    ) -> Result<(), (usize, AutomergeError)> {
+        // the first index of each change which isn't in the document yet
+        let mut batch = HashMap::with_capacity(changes.len());
+        let mut seqs = HashSet::new();
+        for (index, change) in changes.iter().enumerate() {
+            if self.history_index.contains_key(&change.hash())
+                || batch.insert(change.hash(), index).is_some()
+            {
+                continue;
+            }
+            if self.duplicate_seq(change) || !seqs.insert((change.actor_id(), change.seq())) {
+                return Err((
+                    index,
+                    AutomergeError::DuplicateSeqNumber(change.seq(), change.actor_id().clone()),
+                ));
+            }
+        }
+
+        // order the changes so each comes after its dependencies in the batch
+        let mut waiting_on = vec![0; changes.len()];
+        let mut dependents: HashMap<usize, Vec<usize>> = HashMap::new();
+        let mut order = Vec::with_capacity(batch.len());
+        for (index, change) in changes.iter().enumerate() {
+            if batch.get(&change.hash()) != Some(&index) {
+                continue;
+            }
+            for dep in change.deps() {
+                if self.history_index.contains_key(dep) {
+                    continue;
+                }
+                let Some(dep_index) = batch.get(dep) else {
+                    return Err((index, AutomergeError::MissingDeps));
+                };
+                waiting_on[index] += 1;
+                dependents.entry(*dep_index).or_default().push(index);
+            }
+            if waiting_on[index] == 0 {
+                order.push(index);
+            }
+        }
+        let mut next = 0;
+        while let Some(index) = order.get(next) {
+            for dependent in dependents.remove(index).unwrap_or_default() {
+                waiting_on[dependent] -= 1;
+                if waiting_on[dependent] == 0 {
+                    order.push(dependent);
+                }
+            }
+            next += 1;
+        }
+        if order.is_empty() {
+            return Ok(());
+        }
+        self.check_batch_objects_exist(&changes, &order)?;
+
+        // every change has been checked, so applying them can't fail part way through
+        let mut patch_log = PatchLog::inactive(TextRepresentation::default());
+        let mut changes = changes.into_iter().map(Some).collect::<Vec<_>>();
+        for index in order {
+            if let Some(change) = changes[index].take() {
+                self.apply_change(change, &mut patch_log)
+                    .map_err(|e| (index, e))?;
+            }
+        }
+        self.ops.spill_cold_trees();
+        Ok(())
+    }
+
+    /// Check that every op in `changes`, taken in `order`, applies to an object which is either
+    /// already in the document or created by an earlier op in the batch
+    ///
+    /// This is [`Self::check_objects_exist()`] for changes which haven't been imported, so the
+    /// actors they use may not be in the document yet.
+    #[allow(clippy::result_large_err)]
+    fn check_batch_objects_exist(
+        &self,
+        changes: &[Change],
+        order: &[usize],
+    ) -> Result<(), (usize, AutomergeError)> {
+        let to_exid = |counter: u64, actor: &ActorId| {
+            let index = self.ops.osd.actors.lookup(actor);
+            ExId::Id(
+                counter,
+                actor.clone(),
+                index.unwrap_or(self.ops.osd.actors.len()),
+            )
+        };
+        let mut created = HashSet::new();
+        for index in order {
+            let change = &changes[*index];
+            let actors = std::iter::once(change.actor_id())
+                .chain(change.other_actor_ids())
+                .collect::<Vec<_>>();
+            for (i, op) in change.iter_ops().enumerate() {
+                if !op.obj.is_root() {
+                    let counter = op.obj.opid().counter();
+                    let actor = actors[op.obj.opid().actor()];
+                    let exists = created.contains(&(counter, actor))
+                        || self.ops.osd.actors.lookup(actor).is_some_and(|actor| {
+                            self.ops
+                                .object_type(&ObjId(OpId::new(counter, actor)))
+                                .is_some()
+                        });
+                    if !exists {
+                        let id = change.start_op().get() + i as u64;
+                        return Err((
+                            *index,
+                            AutomergeError::MissingObject {
+                                obj: to_exid(counter, actor),
+                                op: to_exid(id, change.actor_id()),
+                            },
+                        ));
+                    }
+                }
+                // the actions which make a map, list, text or table
+                if matches!(op.action, 0 | 2 | 4 | 6) {
+                    created.insert((change.start_op().get() + i as u64, change.actor_id()));
+                }
+            }
+        }
         Ok(())
     }
 
@@ -784,6 +1532,7 @@ impl Automerge {
         patch_log: &mut PatchLog,
     ) -> Result<(), AutomergeError> {
         let ops = self.import_ops(&change);
+        self.check_objects_exist(&ops)?;
         self.update_history(change, ops.len());
         for (obj, op, pred) in ops {
             self.insert_op(&obj, op, &pred, patch_log)?;
@@ -791,6 +1540,24 @@ impl Automerge {
         Ok(())
     }
 
+    /// Check that every op in a change applies to an object which exists, either already in the
+    /// document or created by an earlier op in the same change
+    fn check_objects_exist(&self, ops: &[(ObjId, OpBuilder, OpIds)]) -> Result<(), AutomergeError> {
+        let mut created = HashSet::new();
+        for (obj, op, _) in ops {
+            if self.ops.object_type(obj).is_none() && !created.contains(obj) {
+                return Err(AutomergeError::MissingObject {
+                    obj: self.ops.id_to_exid(obj.0),
+                    op: self.ops.id_to_exid(op.id),
+                });
+            }
+            if let OpType::Make(_) = op.action {
+                created.insert(ObjId(op.id));
+            }
+        }
+        Ok(())
+    }
+
     fn is_causally_ready(&self, change: &Change) -> bool {
         change
             .deps()
@@ -810,14 +1577,14 @@ impl Automerge {
     }
 
     fn import_ops(&mut self, change: &Change) -> Vec<(ObjId, OpBuilder, OpIds)> {
-        let actor = self.ops.osd.actors.cache(change.actor_id().clone());
+        let actor = self.ops.import_actor(change.actor_id().clone());
         let mut actors = Vec::with_capacity(change.other_actor_ids().len() + 1);
         actors.push(actor);
         actors.extend(
             change
                 .other_actor_ids()
                 .iter()
-                .map(|a| self.ops.osd.actors.cache(a.clone()))
+                .map(|a| self.ops.import_actor(a.clone()))
                 .collect::<Vec<_>>(),
         );
         change
@@ -826,7 +1593,7 @@ impl Automerge {
             .map(|(i, c)| {
                 let id = OpId::new(change.start_op().get() + i as u64, actor);
                 let key = match &c.key {
-                    EncodedKey::Prop(n) => Key::Map(self.ops.osd.props.cache(n.to_string())),
+                    EncodedKey::Prop(n) => Key::Map(self.ops.import_prop(n)),
                     EncodedKey::Elem(e) if e.is_head() => Key::Seq(ElemId::head()),
                     EncodedKey::Elem(ElemId(o)) => {
                         Key::Seq(ElemId(OpId::new(o.counter(), actors[o.actor()])))
@@ -864,7 +1631,8 @@ impl Automerge {
             .collect()
     }
 
-    /// Takes all the changes in `other` which are not in `self` and applies them
+    /// Takes all the changes in `other` which are not in `self` and applies them, returning the
+    /// heads of `self` afterwards
     pub fn merge(&mut self, other: &mut Self) -> Result<Vec<ChangeHash>, AutomergeError> {
         self.merge_and_log_patches(
             other,
@@ -874,12 +1642,20 @@ impl Automerge {
 
     /// Takes all the changes in `other` which are not in `self` and applies them whilst logging
     /// the resulting changes to the current state of the document to `patch_log`
+    ///
+    /// The patches logged are the net effect of the merge, the same as logging the patches while
+    /// calling [`Self::apply_changes_log_patches()`] with the missing changes. Pass the log to
+    /// [`Self::make_patches()`] to find which objects and properties the merge touched without
+    /// diffing the whole document. Returns the heads of `self` after the merge.
     pub fn merge_and_log_patches(
         &mut self,
         other: &mut Self,
         patch_log: &mut PatchLog,
     ) -> Result<Vec<ChangeHash>, AutomergeError> {
         // TODO: Make this fallible and figure out how to do this transactionally
+        if let Some(snapshot) = other.snapshot_missing_from(self) {
+            self.load_incremental_log_patches(snapshot, patch_log)?;
+        }
         let changes = self
             .get_changes_added(other)
             .into_iter()
@@ -890,41 +1666,434 @@ impl Automerge {
         Ok(self.get_heads())
     }
 
-    /// Save the entirety of this document in a compact form.
-    pub fn save_with_options(&self, options: SaveOptions) -> Vec<u8> {
-        let heads = self.get_heads();
-        let c = self.history.iter();
-        let compress = if options.deflate {
-            None
-        } else {
-            Some(CompressConfig::None)
-        };
-        let mut bytes = crate::storage::save::save_document(
-            c,
-            self.ops.iter().map(|(objid, _, op)| (objid, op)),
-            &self.ops.osd.actors,
-            &self.ops.osd.props,
-            &heads,
-            compress,
-        );
-        if options.retain_orphans {
-            for orphaned in self.queue.iter() {
-                bytes.extend(orphaned.raw_bytes());
+    /// Apply the changes in `other` which are not in `self` and only modify `obj` or the objects
+    /// beneath it
+    ///
+    /// Changes which don't modify the subtree at all are left out, apart from changes with no ops
+    /// (e.g. the changes created by [`crate::AutoCommit::empty_change()`]) which the applied
+    /// changes depend on. Returns the hashes of the changes which were applied, nothing is
+    /// applied if this returns an error.
+    ///
+    /// Every change depends on the changes which came before it, so a change to the subtree made
+    /// after a change outside of it in `other` depends on that outside change and can't be merged
+    /// on its own.
+    ///
+    /// # Errors
+    ///
+    /// * [`AutomergeError::ChangeOutsideSubtree`] if one of the missing changes modifies objects
+    ///   both inside and outside the subtree, as applying part of a change isn't possible
+    /// * [`AutomergeError::MissingDeps`] if one of the changes to the subtree depends on a change
+    ///   which is left out, e.g. because `obj` was created by a change which isn't in `self`
+    /// * [`AutomergeError::InvalidObjId`] if `obj` isn't an object in `other`
+    pub fn merge_object(
+        &mut self,
+        other: &Automerge,
+        obj: &ExId,
+    ) -> Result<Vec<ChangeHash>, AutomergeError> {
+        let target = other.exid_to_obj(obj)?.id;
+        let mut in_subtree = HashMap::new();
+        // each missing change in causal order with whether it modifies the subtree, `None` for
+        // changes without ops
+        let mut added = Vec::new();
+        for change in self.get_changes_added(other) {
+            let (mut inside, mut outside) = (false, false);
+            for op_obj in other
+                .change_objects(change)
+                .ok_or(AutomergeError::MissingDeps)?
+            {
+                let found = *in_subtree
+                    .entry(op_obj)
+                    .or_insert_with(|| other.is_in_subtree(op_obj, target));
+                if found {
+                    inside = true;
+                } else {
+                    outside = true;
+                }
             }
+            if inside && outside {
+                return Err(AutomergeError::ChangeOutsideSubtree(change.hash()));
+            }
+            added.push((change, (inside || outside).then_some(inside)));
         }
-        bytes
-    }
-
-    /// Save the entirety of this document in a compact form.
-    pub fn save(&self) -> Vec<u8> {
-        self.save_with_options(SaveOptions::default())
+        // walk back from the changes to the subtree picking up the empty changes they depend on
+        let mut needed = HashSet::new();
+        let mut selected = Vec::new();
+        for (change, inside) in added.into_iter().rev() {
+            if inside.unwrap_or_else(|| needed.contains(&change.hash())) {
+                needed.extend(change.deps().iter().copied());
+                selected.push(change);
+            }
+        }
+        selected.reverse();
+        let hashes = selected.iter().map(|c| c.hash()).collect::<HashSet<_>>();
+        for change in &selected {
+            for dep in change.deps() {
+                if !hashes.contains(dep) && !self.has_change(dep) {
+                    return Err(AutomergeError::MissingDeps);
+                }
+            }
+        }
+        let applied = selected.iter().map(|c| c.hash()).collect();
+        self.apply_changes(selected.into_iter().cloned())?;
+        Ok(applied)
+    }
+
+    /// The object each op in `change` modifies, or `None` if `change` refers to an actor this
+    /// document doesn't know about
+    pub(crate) fn change_objects(&self, change: &Change) -> Option<Vec<ObjId>> {
+        let actors = std::iter::once(change.actor_id())
+            .chain(change.other_actor_ids())
+            .map(|a| self.ops.osd.actors.lookup(a))
+            .collect::<Option<Vec<_>>>()?;
+        Some(
+            change
+                .iter_ops()
+                .map(|op| {
+                    if op.obj.is_root() {
+                        ObjId::root()
+                    } else {
+                        let id = op.obj.opid();
+                        ObjId(OpId::new(id.counter(), actors[id.actor()]))
+                    }
+                })
+                .collect(),
+        )
     }
 
-    /// Save the document and attempt to load it before returning - slow!
-    pub fn save_and_verify(&self) -> Result<Vec<u8>, AutomergeError> {
-        let bytes = self.save();
-        Self::load(&bytes)?;
-        Ok(bytes)
+    /// The objects which applying `changes` would modify or create, without applying them
+    ///
+    /// This is the object of every op in `changes` plus the id of every object the changes
+    /// create, whether or not this document already has the changes, their dependencies or their
+    /// actors. Nothing is checked against the document, so an object which doesn't exist here
+    /// (because it was created by a change which isn't included) is still reported.
+    ///
+    /// ```
+    /// # use automerge::{transaction::Transactable, AutoCommit, ObjType, ROOT};
+    /// let mut doc = AutoCommit::new();
+    /// let list = doc.put_object(ROOT, "list", ObjType::List).unwrap();
+    /// doc.commit();
+    /// let mut other = doc.fork();
+    /// other.insert(&list, 0, "a").unwrap();
+    /// let changes = other.get_changes(&doc.get_heads()).into_iter().cloned().collect::<Vec<_>>();
+    ///
+    /// let affected = doc.document().objects_affected_by(&changes).unwrap();
+    /// assert_eq!(affected, [list].into_iter().collect());
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AutomergeError::InvalidObjId`] if an op in one of the changes refers to an actor
+    /// the change doesn't list
+    pub fn objects_affected_by(&self, changes: &[Change]) -> Result<HashSet<ExId>, AutomergeError> {
+        let mut objects = HashSet::new();
+        for change in changes {
+            let actors = std::iter::once(change.actor_id())
+                .chain(change.other_actor_ids())
+                .collect::<Vec<_>>();
+            let exid = |counter: u64, actor: usize| {
+                let actor = actors.get(actor).ok_or_else(|| {
+                    AutomergeError::InvalidObjId(format!("{}@<actor {}>", counter, actor))
+                })?;
+                let index = self.ops.osd.actors.lookup(*actor).unwrap_or(usize::MAX);
+                Ok::<_, AutomergeError>(ExId::Id(counter, (*actor).clone(), index))
+            };
+            for (op, counter) in change.iter_ops().zip(change.start_op().get()..) {
+                if op.obj.is_root() {
+                    objects.insert(ExId::Root);
+                } else {
+                    let id = op.obj.opid();
+                    objects.insert(exid(id.counter(), id.actor())?);
+                }
+                let action =
+                    OpType::from_action_and_value(op.action, op.val, op.mark_name, op.expand);
+                if let OpType::Make(_) = action {
+                    objects.insert(exid(counter, 0)?);
+                }
+            }
+        }
+        Ok(objects)
+    }
+
+    /// Stop local transactions from modifying `obj` or any object nested inside it
+    ///
+    /// Any [`crate::transaction::Transactable`] operation on a frozen object fails with
+    /// [`AutomergeError::ObjectFrozen`]. This is a guard against programming mistakes rather than
+    /// part of the document: it isn't saved or sent to other peers, and changes from other peers
+    /// to a frozen object are applied as usual. Forks of this document start with the same
+    /// objects frozen.
+    ///
+    /// ```
+    /// # use automerge::{transaction::Transactable, AutoCommit, AutomergeError, ObjType, ROOT};
+    /// let mut doc = AutoCommit::new();
+    /// let published = doc.put_object(ROOT, "published", ObjType::Map).unwrap();
+    /// let list = doc.put_object(&published, "list", ObjType::List).unwrap();
+    /// doc.freeze_object(&published).unwrap();
+    /// assert!(matches!(
+    ///     doc.insert(&list, 0, "oops"),
+    ///     Err(AutomergeError::ObjectFrozen(obj)) if obj == published
+    /// ));
+    /// doc.put(ROOT, "draft", "fine").unwrap();
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `obj` is not an object in this document
+    pub fn freeze_object<O: AsRef<ExId>>(&mut self, obj: O) -> Result<(), AutomergeError> {
+        let obj = self.exid_to_obj(obj.as_ref())?;
+        self.frozen.insert(obj.id);
+        Ok(())
+    }
+
+    /// Allow local transactions to modify `obj` again, see [`Self::freeze_object()`]
+    ///
+    /// Returns whether `obj` was frozen. Objects nested inside a frozen object can't be unfrozen
+    /// on their own, unfreeze the object which was passed to [`Self::freeze_object()`] instead.
+    pub fn unfreeze_object<O: AsRef<ExId>>(&mut self, obj: O) -> bool {
+        match self.exid_to_obj(obj.as_ref()) {
+            Ok(obj) => self.frozen.remove(&obj.id),
+            Err(_) => false,
+        }
+    }
+
+    /// Whether `obj` is frozen, either by [`Self::freeze_object()`] or because it is nested
+    /// inside a frozen object
+    pub fn is_frozen<O: AsRef<ExId>>(&self, obj: O) -> bool {
+        self.exid_to_obj(obj.as_ref())
+            .map(|obj| self.frozen_ancestor(obj.id).is_some())
+            .unwrap_or(false)
+    }
+
+    /// The frozen object `obj` is, or is nested inside
+    fn frozen_ancestor(&self, mut obj: ObjId) -> Option<ObjId> {
+        if self.frozen.is_empty() {
+            return None;
+        }
+        loop {
+            if self.frozen.contains(&obj) {
+                return Some(obj);
+            }
+            obj = self
+                .ops
+                .parent_object(&obj, TextRepresentation::default(), None)?
+                .obj;
+        }
+    }
+
+    /// Like [`Self::exid_to_obj()`] but fails with [`AutomergeError::ObjectFrozen`] if the object
+    /// is frozen
+    pub(crate) fn exid_to_writable_obj(&self, id: &ExId) -> Result<ObjMeta, AutomergeError> {
+        let obj = self.exid_to_obj(id)?;
+        match self.frozen_ancestor(obj.id) {
+            Some(frozen) => Err(AutomergeError::ObjectFrozen(self.id_to_exid(frozen.0))),
+            None => Ok(obj),
+        }
+    }
+
+    /// Whether `obj` is `ancestor` or one of the objects beneath it, including objects which
+    /// have since been deleted
+    pub(crate) fn is_in_subtree(&self, mut obj: ObjId, ancestor: ObjId) -> bool {
+        loop {
+            if obj == ancestor {
+                return true;
+            }
+            match self
+                .ops
+                .parent_object(&obj, TextRepresentation::default(), None)
+            {
+                Some(parent) => obj = parent.obj,
+                None => return false,
+            }
+        }
+    }
+
+    /// Compute the patches which [`Self::merge_and_log_patches()`] would log when merging
+    /// `other` into this document, without modifying either document
+    ///
+    /// The result is the same as passing `PatchLog::active(text_rep)` to
+    /// [`Self::merge_and_log_patches()`] and then calling [`Self::make_patches()`]. This works on
+    /// a copy of the document, so it costs as much as cloning `self` and performing the merge.
+    ///
+    /// ```
+    /// # use automerge::{transaction::Transactable, Automerge, PatchLog, ROOT};
+    /// # use automerge::patches::TextRepresentation;
+    /// let mut doc = Automerge::new();
+    /// let mut other = doc.fork();
+    /// other.transact::<_, _, automerge::AutomergeError>(|tx| {
+    ///     tx.put(ROOT, "key", "value")?;
+    ///     Ok(())
+    /// }).unwrap();
+    /// let preview = doc.merge_preview(&other, TextRepresentation::String).unwrap();
+    ///
+    /// let mut patch_log = PatchLog::active(TextRepresentation::String);
+    /// doc.merge_and_log_patches(&mut other, &mut patch_log).unwrap();
+    /// assert_eq!(preview, doc.make_patches(&mut patch_log));
+    /// ```
+    pub fn merge_preview(
+        &self,
+        other: &Self,
+        text_rep: TextRepresentation,
+    ) -> Result<Vec<Patch>, AutomergeError> {
+        let changes = self
+            .get_changes_added(other)
+            .into_iter()
+            .cloned()
+            .collect::<Vec<_>>();
+        let mut patch_log = PatchLog::active(text_rep);
+        let mut merged = self.clone();
+        if let Some(snapshot) = other.snapshot_missing_from(self) {
+            merged.load_incremental_log_patches(snapshot, &mut patch_log)?;
+        }
+        merged.apply_changes_log_patches(changes, &mut patch_log)?;
+        Ok(merged.make_patches(&mut patch_log))
+    }
+
+    /// Save the entirety of this document in a compact form.
+    pub fn save_with_options(&self, options: SaveOptions) -> Vec<u8> {
+        let compression = if options.deflate {
+            options.compression
+        } else {
+            CompressionLevel::None
+        };
+        let mut bytes = self.save_document_chunk(compression, || ());
+        if options.retain_orphans {
+            for orphaned in self.queue.iter() {
+                bytes.extend(orphaned.raw_bytes());
+            }
+        }
+        bytes
+    }
+
+    /// Encode the document chunk, calling `on_op` every time the encoder reads an op
+    fn save_document_chunk<F: Fn() + Clone>(
+        &self,
+        compression: CompressionLevel,
+        on_op: F,
+    ) -> Vec<u8> {
+        if let Some(snapshot) = &self.snapshot {
+            // the snapshot was saved with the default compression, anything else means encoding
+            // it again
+            let mut bytes = if compression == CompressionLevel::Default {
+                snapshot.bytes.clone()
+            } else {
+                Self::load(&snapshot.bytes)
+                    .expect("the snapshot was saved by Automerge::save()")
+                    .save_document_chunk(compression, on_op)
+            };
+            bytes.extend(self.save_after(&snapshot.heads));
+            return bytes;
+        }
+        let heads = self.get_heads();
+        let c = self.history.iter().filter_map(|entry| entry.change());
+        let compress = Some(compression.to_config());
+        let spilled = self.ops.rebuild_spilled();
+        crate::storage::save::save_document(
+            c,
+            self.ops
+                .iter(&spilled)
+                .map(|(objid, _, op)| (objid, op))
+                .inspect(move |_| on_op()),
+            &self.ops.osd.actors,
+            &self.ops.osd.props,
+            &heads,
+            compress,
+        )
+    }
+
+    /// Save the entirety of this document in a compact form.
+    pub fn save(&self) -> Vec<u8> {
+        self.save_with_options(SaveOptions::default())
+    }
+
+    /// Save the entirety of this document to `w`
+    ///
+    /// The output is byte for byte the same as [`Self::save()`], but the document is never held
+    /// in memory as a whole. The columns of the document are encoded one group at a time, for
+    /// example all the values or all the keys of the ops, and written to `w` as they are
+    /// encoded, so at most one group of columns is in memory at once. The header of the document
+    /// contains a checksum of everything after it, so the columns are encoded three times, which
+    /// makes this slower than [`Self::save()`]. Use it when memory matters more than time, for
+    /// example to save a very large document to a file.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AutomergeError::Io`] if writing to `w` fails
+    pub fn save_to_writer<W: std::io::Write>(&self, mut w: W) -> Result<(), AutomergeError> {
+        let options = SaveOptions::default();
+        if let Some(snapshot) = &self.snapshot {
+            w.write_all(&snapshot.bytes).map_err(AutomergeError::Io)?;
+            for change in self.get_changes(&snapshot.heads) {
+                w.write_all(change.raw_bytes())
+                    .map_err(AutomergeError::Io)?;
+            }
+        } else {
+            let heads = self.get_heads();
+            let spilled = self.ops.rebuild_spilled();
+            crate::storage::save::write_document(
+                self.history.iter().filter_map(|entry| entry.change()),
+                self.ops.iter(&spilled).map(|(objid, _, op)| (objid, op)),
+                &self.ops.osd.actors,
+                &self.ops.osd.props,
+                &heads,
+                Some(options.compression.to_config()),
+                &mut w,
+            )
+            .map_err(AutomergeError::Io)?;
+        }
+        if options.retain_orphans {
+            for orphaned in self.queue.iter() {
+                w.write_all(orphaned.raw_bytes())
+                    .map_err(AutomergeError::Io)?;
+            }
+        }
+        w.flush().map_err(AutomergeError::Io)
+    }
+
+    /// Like [`Self::save()`] but calls `progress(done, total)` as the document is encoded
+    ///
+    /// The output is byte for byte the same as [`Self::save()`]. Progress is measured in units
+    /// of work proportional to the number of ops in the document, `done` only increases and the
+    /// last call is always `progress(total, total)`, once the bytes are ready. Calls are made
+    /// periodically rather than for every op so the callback can afford to redraw a progress bar.
+    ///
+    /// ```
+    /// # use automerge::{transaction::Transactable, AutoCommit, ROOT};
+    /// let mut doc = AutoCommit::new();
+    /// doc.put(ROOT, "key", "value").unwrap();
+    /// let mut calls = Vec::new();
+    /// let bytes = doc
+    ///     .document()
+    ///     .save_with_progress(|done, total| calls.push((done, total)));
+    /// assert_eq!(bytes, doc.save());
+    /// let (done, total) = calls.last().unwrap();
+    /// assert_eq!(done, total);
+    /// ```
+    pub fn save_with_progress<F: FnMut(usize, usize)>(&self, progress: F) -> Vec<u8> {
+        const REPORT_EVERY: usize = 1024;
+        let num_ops = self.ops.len();
+        let total = num_ops * crate::storage::Document::op_encode_passes(num_ops);
+        let progress = std::cell::RefCell::new(progress);
+        let done = std::cell::Cell::new(0);
+        let options = SaveOptions::default();
+        let mut bytes = self.save_document_chunk(options.compression, || {
+            done.set(done.get() + 1);
+            if done.get() % REPORT_EVERY == 0 && done.get() < total {
+                (progress.borrow_mut())(done.get(), total);
+            }
+        });
+        if options.retain_orphans {
+            for orphaned in self.queue.iter() {
+                bytes.extend(orphaned.raw_bytes());
+            }
+        }
+        (progress.into_inner())(total, total);
+        bytes
+    }
+
+    /// Save the document and attempt to load it before returning - slow!
+    pub fn save_and_verify(&self) -> Result<Vec<u8>, AutomergeError> {
+        let bytes = self.save();
+        Self::load(&bytes)?;
+        Ok(bytes)
     }
 
     /// Save this document, but don't run it through `DEFLATE` afterwards
@@ -941,6 +2110,12 @@ impl Automerge {
     /// changes. This is useful if you know you have only made a small change since the last
     /// [`Self::save()`] and you want to immediately send it somewhere (e.g. you've inserted a
     /// single character in a text object).
+    ///
+    /// Unlike [`crate::AutoCommit::save_incremental()`] this doesn't depend on when the document
+    /// was last saved. The output contains exactly the changes which are not (transitive)
+    /// dependencies of `heads`, so loading it with [`Self::load_incremental()`] into a document
+    /// which is at `heads` brings that document up to date with this one. Any of `heads` which
+    /// are not in this document are ignored.
     pub fn save_after(&self, heads: &[ChangeHash]) -> Vec<u8> {
         let changes = self.get_changes(heads);
         let mut bytes = vec![];
@@ -993,7 +2168,7 @@ impl Automerge {
 
         change_indexes
             .into_iter()
-            .map(|i| &self.history[i])
+            .filter_map(|i| self.history[i].change())
             .collect()
     }
 
@@ -1003,20 +2178,208 @@ impl Automerge {
             .history
             .iter()
             .rev()
+            .filter_map(|entry| entry.change())
             .find(|c| c.actor_id() == self.get_actor());
     }
 
+    /// Every `(change, dependency)` pair in the history of this document
+    ///
+    /// Changes are visited in the order they were applied, which is a topological order, and the
+    /// dependencies of each change in the order they are stored in the change. Changes with no
+    /// dependencies never appear as the first element of a pair, and changes with no dependents
+    /// are the heads of the document. This reads the dependency graph the document already
+    /// maintains, so no changes are decoded.
+    ///
+    /// ```
+    /// # use automerge::{transaction::Transactable, AutoCommit, ROOT};
+    /// let mut doc = AutoCommit::new();
+    /// doc.put(ROOT, "a", 1).unwrap();
+    /// let first = doc.commit().unwrap();
+    /// doc.put(ROOT, "b", 2).unwrap();
+    /// let second = doc.commit().unwrap();
+    /// let edges = doc.document().change_graph_edges().collect::<Vec<_>>();
+    /// assert_eq!(edges, vec![(second, first)]);
+    /// ```
+    pub fn change_graph_edges(&self) -> impl Iterator<Item = (ChangeHash, ChangeHash)> + '_ {
+        self.change_graph.edges()
+    }
+
+    /// Report, for each actor, which of its changes this document has and which are missing
+    ///
+    /// Changes are only applied once all of their dependencies have been, so the changes from an
+    /// actor which have been applied always form a contiguous run of sequence numbers. Changes
+    /// received out of order wait in a queue, the gaps between them are the changes which need to
+    /// be requested from that actor. Unlike [`ReadDoc::get_missing_deps()`] this can identify
+    /// missing changes whose hashes aren't known. Changes missing from the end of an actor's
+    /// sequence can't be detected.
+    pub fn missing_deps_report(&self) -> BTreeMap<ActorId, SeqReport> {
+        let mut report = BTreeMap::new();
+        for (actor, seqs) in &self.states {
+            let entry: &mut SeqReport = report
+                .entry(self.ops.osd.actors[*actor].clone())
+                .or_default();
+            entry.applied = seqs.len() as u64;
+        }
+        for change in &self.queue {
+            let entry = report.entry(change.actor_id().clone()).or_default();
+            entry.queued.push(change.seq());
+        }
+        for entry in report.values_mut() {
+            entry.queued.sort_unstable();
+            entry.queued.dedup();
+            let mut next = entry.applied + 1;
+            for &seq in &entry.queued {
+                if seq > next {
+                    entry.missing.push(next..seq);
+                }
+                next = next.max(seq.saturating_add(1));
+            }
+        }
+        report
+    }
+
+    /// The size of every change in the document, in the order they were applied
+    ///
+    /// This only reads the metadata of each change, the operations aren't decoded. Changes which
+    /// haven't been compressed yet are compressed as the iterator reaches them in order to
+    /// measure them, without keeping the compressed bytes.
+    pub fn change_metadata(&self) -> impl Iterator<Item = ChangeStats> + '_ {
+        self.history
+            .iter()
+            .filter_map(|entry| entry.change())
+            .map(|change| ChangeStats {
+                hash: change.hash(),
+                actor: change.actor_id().clone(),
+                seq: change.seq(),
+                op_count: change.len(),
+                size: change.raw_bytes().len(),
+                compressed_size: change.compressed_len(),
+                message: change.message().cloned(),
+            })
+    }
+
     pub(crate) fn clock_at(&self, heads: &[ChangeHash]) -> Clock {
         self.change_graph.clock_for_heads(heads)
     }
 
+    /// Like [`Self::clock_at`] but returns `None` if `heads` are the current heads of the
+    /// document, which allows lookups to use the indexes in the op tree
+    ///
+    /// Only use this when there are no uncommitted ops in the document.
+    pub(crate) fn clock_at_unless_current(&self, heads: &[ChangeHash]) -> Option<Clock> {
+        if heads.len() == self.deps.len() && heads.iter().all(|h| self.deps.contains(h)) {
+            None
+        } else {
+            Some(self.clock_at(heads))
+        }
+    }
+
+    /// Compute the [`Clock`] for `heads` so it can be reused for several reads at the same point
+    /// in history
+    ///
+    /// Every `*_at` method of [`ReadDoc`] computes a clock from its heads, which means walking the
+    /// change graph. The `*_with_clock` methods accept a precomputed clock instead. Cloning a
+    /// clock is cheap.
+    ///
+    /// ```
+    /// # use automerge::{transaction::Transactable, AutoCommit, ReadDoc, ROOT};
+    /// let mut doc = AutoCommit::new();
+    /// doc.put(ROOT, "a", 1).unwrap();
+    /// doc.put(ROOT, "b", 2).unwrap();
+    /// let heads = doc.get_heads();
+    /// doc.put(ROOT, "a", 3).unwrap();
+    ///
+    /// let doc = doc.document();
+    /// let clock = doc.clock_for_heads(&heads).unwrap();
+    /// for key in doc.keys_with_clock(ROOT, &clock) {
+    ///     assert_eq!(
+    ///         doc.get_with_clock(ROOT, key.as_str(), &clock).unwrap(),
+    ///         doc.get_at(ROOT, key.as_str(), &heads).unwrap(),
+    ///     );
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AutomergeError::MissingHash`] if any of `heads` is not a change in this document
+    pub fn clock_for_heads(&self, heads: &[ChangeHash]) -> Result<Clock, AutomergeError> {
+        if let Some(missing) = heads.iter().find(|h| !self.history_index.contains_key(h)) {
+            return Err(AutomergeError::MissingHash(*missing));
+        }
+        Ok(self.clock_at(heads))
+    }
+
+    /// Like [`ReadDoc::get_at()`] but using a clock from [`Self::clock_for_heads()`]
+    pub fn get_with_clock<O: AsRef<ExId>, P: Into<Prop>>(
+        &self,
+        obj: O,
+        prop: P,
+        clock: &Clock,
+    ) -> Result<Option<(Value<'_>, ExId)>, AutomergeError> {
+        self.get_for(obj.as_ref(), prop.into(), Some(clock.clone()))
+    }
+
+    /// Like [`ReadDoc::get_all_at()`] but using a clock from [`Self::clock_for_heads()`]
+    pub fn get_all_with_clock<O: AsRef<ExId>, P: Into<Prop>>(
+        &self,
+        obj: O,
+        prop: P,
+        clock: &Clock,
+    ) -> Result<Vec<(Value<'_>, ExId)>, AutomergeError> {
+        self.get_all_for(obj, prop, Some(clock.clone()))
+    }
+
+    /// Like [`ReadDoc::keys_at()`] but using a clock from [`Self::clock_for_heads()`]
+    pub fn keys_with_clock<O: AsRef<ExId>>(&self, obj: O, clock: &Clock) -> Keys<'_> {
+        self.keys_for(obj.as_ref(), Some(clock.clone()))
+    }
+
+    /// Like [`ReadDoc::values_at()`] but using a clock from [`Self::clock_for_heads()`]
+    pub fn values_with_clock<O: AsRef<ExId>>(&self, obj: O, clock: &Clock) -> Values<'_> {
+        self.values_for(obj.as_ref(), Some(clock.clone()))
+    }
+
+    /// Like [`ReadDoc::map_range_at()`] but using a clock from [`Self::clock_for_heads()`]
+    pub fn map_range_with_clock<'a, O: AsRef<ExId>, R: RangeBounds<String> + 'a>(
+        &'a self,
+        obj: O,
+        range: R,
+        clock: &Clock,
+    ) -> MapRange<'a, R> {
+        self.map_range_for(obj.as_ref(), range, Some(clock.clone()))
+    }
+
+    /// Like [`ReadDoc::list_range_at()`] but using a clock from [`Self::clock_for_heads()`]
+    pub fn list_range_with_clock<O: AsRef<ExId>, R: RangeBounds<usize>>(
+        &self,
+        obj: O,
+        range: R,
+        clock: &Clock,
+    ) -> ListRange<'_, R> {
+        self.list_range_for(obj.as_ref(), range, Some(clock.clone()))
+    }
+
+    /// Like [`ReadDoc::length_at()`] but using a clock from [`Self::clock_for_heads()`]
+    pub fn length_with_clock<O: AsRef<ExId>>(&self, obj: O, clock: &Clock) -> usize {
+        self.length_for(obj.as_ref(), Some(clock.clone()))
+    }
+
+    /// Like [`ReadDoc::text_at()`] but using a clock from [`Self::clock_for_heads()`]
+    pub fn text_with_clock<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        clock: &Clock,
+    ) -> Result<String, AutomergeError> {
+        self.text_for(obj.as_ref(), Some(clock.clone()))
+    }
+
     fn get_isolated_actor_index(&mut self, level: usize) -> usize {
         if level == 0 {
             self.get_actor_index()
         } else {
             let base_actor = self.get_actor();
             let new_actor = base_actor.with_concurrency(level);
-            self.ops.osd.actors.cache(new_actor)
+            self.ops.import_actor(new_actor)
         }
     }
 
@@ -1047,7 +2410,7 @@ impl Automerge {
             .get(&actor)
             .and_then(|v| v.get(seq as usize - 1))
             .and_then(|&i| self.history.get(i))
-            .map(|c| c.hash())
+            .map(|entry| entry.hash())
             .ok_or(AutomergeError::InvalidSeq(seq))
     }
 
@@ -1056,7 +2419,7 @@ impl Automerge {
             .get(&actor_index)
             .and_then(|s| s.last())
             .and_then(|index| self.history.get(*index))
-            .map(|change| change.max_op())
+            .map(|entry| entry.max_op())
             .unwrap_or(0)
     }
 
@@ -1067,7 +2430,7 @@ impl Automerge {
 
         let history_index = self.history.len();
 
-        let actor_index = self.ops.osd.actors.cache(change.actor_id().clone());
+        let actor_index = self.ops.import_actor(change.actor_id().clone());
         self.states
             .entry(actor_index)
             .or_default()
@@ -1078,7 +2441,7 @@ impl Automerge {
             .add_change(&change, actor_index)
             .expect("Change's deps should already be in the document");
 
-        self.history.push(change);
+        self.history.push(HistoryEntry::Change(change));
 
         history_index
     }
@@ -1114,7 +2477,9 @@ impl Automerge {
             let counter = s[0..n]
                 .parse()
                 .map_err(|_| AutomergeError::InvalidObjIdFormat(s.to_owned()))?;
-            let actor = ActorId::from(hex::decode(&s[(n + 1)..]).unwrap());
+            let actor = hex::decode(&s[(n + 1)..])
+                .map(ActorId::from)
+                .map_err(|_| AutomergeError::InvalidObjIdFormat(s.to_owned()))?;
             let actor = self
                 .ops
                 .osd
@@ -1149,7 +2514,8 @@ impl Automerge {
             "pred",
             "succ"
         );
-        for (obj, _, op) in self.ops.iter() {
+        let spilled = self.ops.rebuild_spilled();
+        for (obj, _, op) in self.ops.iter(&spilled) {
             let id = self.to_short_string(*op.id());
             let obj = self.to_short_string(obj);
             let key = match *op.key() {
@@ -1253,6 +2619,28 @@ impl Automerge {
         patch_log.make_patches(self)
     }
 
+    /// Like [`Self::diff()`] but returns an error if any of the heads are not in this document
+    ///
+    /// [`Self::diff()`] ignores heads it doesn't know about, which makes a typo in a hash look
+    /// like an empty diff. `before` and `after` don't need to be related, if neither is an
+    /// ancestor of the other the patches still transform the state at `before` into the state at
+    /// `after`.
+    pub fn try_diff(
+        &self,
+        before_heads: &[ChangeHash],
+        after_heads: &[ChangeHash],
+        text_rep: TextRepresentation,
+    ) -> Result<Vec<Patch>, AutomergeError> {
+        if let Some(missing) = before_heads
+            .iter()
+            .chain(after_heads)
+            .find(|hash| !self.history_index.contains_key(hash))
+        {
+            return Err(AutomergeError::MissingHash(*missing));
+        }
+        Ok(self.diff(before_heads, after_heads, text_rep))
+    }
+
     /// Get the heads of this document.
     pub fn get_heads(&self) -> Vec<ChangeHash> {
         let mut deps: Vec<_> = self.deps.iter().copied().collect();
@@ -1260,10 +2648,149 @@ impl Automerge {
         deps
     }
 
+    /// The heads of this document as abbreviated hex hashes, for showing to people
+    ///
+    /// Like git's abbreviated commit hashes each head is shortened to the shortest prefix, of at
+    /// least 7 characters, which no other change in the document starts with. As changes are
+    /// added the prefix of a given hash may have to get longer, so store the full hash rather
+    /// than the prefix. The heads are in the same order as [`Self::get_heads()`].
+    ///
+    /// ```
+    /// # use automerge::{transaction::Transactable, AutoCommit, ROOT};
+    /// let mut doc = AutoCommit::new();
+    /// doc.put(ROOT, "key", "value").unwrap();
+    /// let head = doc.get_heads()[0];
+    /// let short = doc.document().get_heads_short();
+    /// assert_eq!(short.len(), 1);
+    /// assert_eq!(short[0].len(), 7);
+    /// assert!(head.to_string().starts_with(&short[0]));
+    /// ```
+    pub fn get_heads_short(&self) -> Vec<String> {
+        const MIN_LEN: usize = 7;
+        // the number of leading hex digits two hashes have in common
+        let common_digits = |a: &ChangeHash, b: &ChangeHash| {
+            let mut digits = 0;
+            for (x, y) in a.as_bytes().iter().zip(b.as_bytes()) {
+                if x == y {
+                    digits += 2;
+                } else {
+                    if x >> 4 == y >> 4 {
+                        digits += 1;
+                    }
+                    break;
+                }
+            }
+            digits
+        };
+        let mut known = self.history.iter().map(|c| c.hash()).collect::<Vec<_>>();
+        known.sort_unstable();
+        self.get_heads()
+            .into_iter()
+            .map(|head| {
+                // the hashes sharing the longest prefix with `head` are next to it in sorted
+                // order
+                let pos = known.partition_point(|h| h < &head);
+                let neighbours = [pos.checked_sub(1), Some(pos + 1)];
+                let len = neighbours
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|i| known.get(i))
+                    .map(|other| common_digits(&head, other) + 1)
+                    .fold(MIN_LEN, usize::max);
+                let mut short = head.to_string();
+                short.truncate(len);
+                short
+            })
+            .collect()
+    }
+
+    /// Get the heads of this document as they were immediately after the change `hash` was
+    /// applied
+    ///
+    /// This is `hash` plus any change applied before it which it does not depend on and which no
+    /// other change applied up to that point depends on. Changes are considered in the order
+    /// this document applied them, so documents which received the same changes in a different
+    /// order may return different heads.
+    ///
+    /// # Errors
+    ///
+    /// [`AutomergeError::MissingHash`] if `hash` is not a change in this document
+    pub fn get_heads_at_change(
+        &self,
+        hash: &ChangeHash,
+    ) -> Result<Vec<ChangeHash>, AutomergeError> {
+        let index = *self
+            .history_index
+            .get(hash)
+            .ok_or(AutomergeError::MissingHash(*hash))?;
+        let mut heads = HashSet::new();
+        for change in &self.history[..=index] {
+            for dep in change.deps() {
+                heads.remove(dep);
+            }
+            heads.insert(change.hash());
+        }
+        let mut heads: Vec<_> = heads.into_iter().collect();
+        heads.sort_unstable();
+        Ok(heads)
+    }
+
     pub fn get_changes(&self, have_deps: &[ChangeHash]) -> Vec<&Change> {
         self.get_changes_clock(have_deps)
     }
 
+    /// Get the changes which are ancestors of `to` but not of `from`, in the order they were
+    /// applied to this document
+    ///
+    /// These are the changes a peer which has `from` needs in order to reach `to`. `from` does
+    /// not have to be an ancestor of `to`, changes which are only ancestors of `from` are not
+    /// returned. Hashes in `from` which are not in this document are ignored, as for
+    /// [`Self::get_changes()`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AutomergeError::MissingHash`] if any of `to` is not a change in this document
+    pub fn get_changes_in_range(
+        &self,
+        from: &[ChangeHash],
+        to: &[ChangeHash],
+    ) -> Result<Vec<&Change>, AutomergeError> {
+        let to = self.clock_for_heads(to)?;
+        let from = self.clock_at(from);
+
+        let mut change_indexes: Vec<usize> = Vec::new();
+        // the changes an actor made which are ancestors of some heads are always a prefix of the
+        // actor's changes, ending at the seq in the clock for those heads
+        for (actor_index, actor_changes) in &self.states {
+            let seq = |clock: &Clock| clock.get_for_actor(actor_index).map_or(0, |c| c.seq);
+            let (start, end) = (seq(&from) as usize, seq(&to) as usize);
+            if start < end {
+                change_indexes.extend(&actor_changes[start..end]);
+            }
+        }
+        change_indexes.sort_unstable();
+
+        Ok(change_indexes
+            .into_iter()
+            .filter_map(|i| self.history[i].change())
+            .collect())
+    }
+
+    /// Get the changes authored by `actor`, in `seq` order
+    ///
+    /// Like [`Self::get_changes()`] this only returns changes which have been applied to the
+    /// document. An actor which is unknown to this document has no changes.
+    pub fn changes_by_actor<'a>(&'a self, actor: &ActorId) -> impl Iterator<Item = &'a Change> {
+        self.ops
+            .osd
+            .actors
+            .lookup(actor)
+            .and_then(|actor_index| self.states.get(&actor_index))
+            .into_iter()
+            .flatten()
+            .filter_map(|index| self.history.get(*index).and_then(|entry| entry.change()))
+    }
+
     /// Get changes in `other` that are not in `self`
     pub fn get_changes_added<'a>(&self, other: &'a Self) -> Vec<&'a Change> {
         // Depth-first traversal from the heads through the dependency graph,
@@ -1273,7 +2800,7 @@ impl Automerge {
         let mut seen_hashes = HashSet::new();
         let mut added_change_hashes = Vec::new();
         while let Some(hash) = stack.pop() {
-            if !seen_hashes.contains(&hash) && self.get_change_by_hash(&hash).is_none() {
+            if !seen_hashes.contains(&hash) && !self.has_change(&hash) {
                 seen_hashes.insert(hash);
                 added_change_hashes.push(hash);
                 if let Some(change) = other.get_change_by_hash(&hash) {
@@ -1290,47 +2817,189 @@ impl Automerge {
             .collect()
     }
 
-    /// Get the hash of the change that contains the given `opid`.
+    /// The heads of the history which `self` and `other` have in common
     ///
-    /// Returns [`None`] if the `opid`:
-    /// - is the root object id
-    /// - does not exist in this document
-    pub fn hash_for_opid(&self, exid: &ExId) -> Option<ChangeHash> {
-        match exid {
-            ExId::Root => None,
-            ExId::Id(..) => {
-                let opid = self.exid_to_opid(exid).ok()?;
-                let actor_indices = self.states.get(&opid.actor())?;
-                let change_index_index = actor_indices
-                    .binary_search_by(|change_index| {
-                        let change = self
-                            .history
-                            .get(*change_index)
-                            .expect("State index should refer to a valid change");
-                        let start = change.start_op().get();
-                        let len = change.len() as u64;
-                        if opid.counter() < start {
-                            Ordering::Greater
-                        } else if start + len <= opid.counter() {
-                            Ordering::Less
-                        } else {
-                            Ordering::Equal
-                        }
-                    })
-                    .ok()?;
-                let change_index = actor_indices.get(change_index_index).unwrap();
-                Some(self.history.get(*change_index).unwrap().hash())
+    /// This is the merge base of the two documents: every change which is an ancestor of (or
+    /// equal to) one of the returned heads is in both documents, and every change in both
+    /// documents is such an ancestor. The heads are sorted, and empty if the documents have no
+    /// changes in common.
+    ///
+    /// ```
+    /// # use automerge::{transaction::Transactable, AutoCommit, ROOT};
+    /// let mut doc = AutoCommit::new();
+    /// doc.put(ROOT, "key", "base").unwrap();
+    /// let base = doc.get_heads();
+    /// let mut fork = doc.fork();
+    /// doc.put(ROOT, "key", "mine").unwrap();
+    /// fork.put(ROOT, "key", "theirs").unwrap();
+    /// assert_eq!(
+    ///     doc.document().common_ancestor_heads(fork.document()),
+    ///     base
+    /// );
+    /// ```
+    pub fn common_ancestor_heads(&self, other: &Self) -> Vec<ChangeHash> {
+        let shared = self
+            .history
+            .iter()
+            .filter(|change| other.history_index.contains_key(&change.hash()))
+            .collect::<Vec<_>>();
+        let mut heads = shared.iter().map(|c| c.hash()).collect::<BTreeSet<_>>();
+        for change in shared {
+            for dep in change.deps() {
+                heads.remove(dep);
             }
         }
+        heads.into_iter().collect()
     }
 
-    fn calculate_marks(
+    /// Whether the change `ancestor` is one of `of` or a transitive dependency of one of them
+    ///
+    /// This uses the vector clock of `of` rather than walking every change, so it is cheap to
+    /// call repeatedly. Pass [`Self::get_heads()`] as `of` to check whether a change is part of
+    /// the current state of the document.
+    ///
+    /// ```
+    /// # use automerge::{transaction::Transactable, AutoCommit, ROOT};
+    /// let mut doc = AutoCommit::new();
+    /// doc.put(ROOT, "key", "one").unwrap();
+    /// let first = doc.get_heads();
+    /// let mut fork = doc.fork();
+    /// fork.put(ROOT, "key", "two").unwrap();
+    /// doc.merge(&mut fork).unwrap();
+    /// let heads = doc.get_heads();
+    /// assert!(doc.document().is_ancestor(&first[0], &heads).unwrap());
+    /// assert!(!doc.document().is_ancestor(&heads[0], &first).unwrap());
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AutomergeError::MissingHash`] if `ancestor` or any of `of` is not a change in
+    /// this document
+    pub fn is_ancestor(
         &self,
-        obj: &ExId,
-        clock: Option<Clock>,
-    ) -> Result<Vec<Mark<'_>>, AutomergeError> {
-        let obj = self.exid_to_obj(obj.as_ref())?;
-        let ops_by_key = self.ops().iter_ops(&obj.id).group_by(|o| o.elemid_or_key());
+        ancestor: &ChangeHash,
+        of: &[ChangeHash],
+    ) -> Result<bool, AutomergeError> {
+        if let Some(missing) = std::iter::once(ancestor)
+            .chain(of)
+            .find(|h| !self.history_index.contains_key(h))
+        {
+            return Err(AutomergeError::MissingHash(*missing));
+        }
+        let change = &self.history[self.history_index[ancestor]];
+        let Some(actor) = self.ops.osd.actors.lookup(change.actor_id()) else {
+            return Ok(false);
+        };
+        let clock = self.clock_at(of);
+        Ok(clock
+            .get_for_actor(&actor)
+            .is_some_and(|data| data.seq >= change.seq()))
+    }
+
+    /// Get the most recent value written by `actor` to `index` of the sequence `obj`
+    ///
+    /// Unlike [`ReadDoc::get`] this returns the actor's value even if it lost a conflict or has
+    /// since been overwritten. The element itself is still located by its visible index. Returns
+    /// [`None`] if `actor` has never written to that element.
+    pub fn get_by_actor<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        index: usize,
+        actor: &ActorId,
+        heads: Option<&[ChangeHash]>,
+    ) -> Result<Option<(Value<'_>, ExId)>, AutomergeError> {
+        let obj = self.exid_to_obj(obj.as_ref())?;
+        if !obj.typ.is_sequence() {
+            return Err(AutomergeError::InvalidOp(obj.typ));
+        }
+        let clock = heads.map(|heads| self.clock_at(heads));
+        let query = self.ops.search(
+            &obj.id,
+            query::Nth::new_for_actor(
+                index,
+                TextRepresentation::String.encoding(obj.typ),
+                clock.clone(),
+                actor,
+                &self.ops.osd,
+            ),
+        );
+        Ok(query.ops.last().map(|op| op.tagged_value(clock.as_ref())))
+    }
+
+    /// The number of concurrent visible values at `index` of the sequence `obj`
+    ///
+    /// This is the same as `doc.get_all(obj, index)?.len()` but doesn't build the values, which
+    /// makes it cheap to check whether an element is conflicted.
+    pub fn conflict_count<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        index: usize,
+        heads: Option<&[ChangeHash]>,
+    ) -> Result<usize, AutomergeError> {
+        let obj = self.exid_to_obj(obj.as_ref())?;
+        if !obj.typ.is_sequence() {
+            return Err(AutomergeError::InvalidOp(obj.typ));
+        }
+        let clock = heads.map(|heads| self.clock_at(heads));
+        let query = self.ops.search(
+            &obj.id,
+            query::Nth::new(
+                index,
+                TextRepresentation::String.encoding(obj.typ),
+                clock,
+                &self.ops.osd,
+            )
+            .count_only(),
+        );
+        Ok(query.conflict_count())
+    }
+
+    /// Get the hash of the change that contains the given `opid`.
+    ///
+    /// Returns [`None`] if the `opid`:
+    /// - is the root object id
+    /// - does not exist in this document
+    pub fn hash_for_opid(&self, exid: &ExId) -> Option<ChangeHash> {
+        match exid {
+            ExId::Root => None,
+            ExId::Id(..) => {
+                let opid = self.exid_to_opid(exid).ok()?;
+                self.change_for_opid(&opid).map(|change| change.hash())
+            }
+        }
+    }
+
+    /// The change containing `opid`, or `None` if it is not in a change yet
+    fn change_for_opid(&self, opid: &OpId) -> Option<&HistoryEntry> {
+        let actor_indices = self.states.get(&opid.actor())?;
+        let change_index_index = actor_indices
+            .binary_search_by(|change_index| {
+                let change = self
+                    .history
+                    .get(*change_index)
+                    .expect("State index should refer to a valid change");
+                let start = change.start_op().get();
+                let len = change.len() as u64;
+                if opid.counter() < start {
+                    Ordering::Greater
+                } else if start + len <= opid.counter() {
+                    Ordering::Less
+                } else {
+                    Ordering::Equal
+                }
+            })
+            .ok()?;
+        let change_index = actor_indices.get(change_index_index).unwrap();
+        self.history.get(*change_index)
+    }
+
+    fn calculate_marks(
+        &self,
+        obj: &ExId,
+        clock: Option<Clock>,
+    ) -> Result<Vec<Mark<'_>>, AutomergeError> {
+        let obj = self.exid_to_obj(obj.as_ref())?;
+        let ops_by_key = self.ops().iter_ops(&obj.id).group_by(|o| o.elemid_or_key());
         let mut index = 0;
         let mut marks = MarkStateMachine::default();
         let mut acc = MarkAccumulator::default();
@@ -1409,6 +3078,70 @@ impl Automerge {
             .unwrap_or_default()
     }
 
+    pub(crate) fn map_keys_in_insertion_order_for(
+        &self,
+        obj: &ExId,
+        clock: Option<Clock>,
+    ) -> Vec<String> {
+        self.exid_to_obj(obj)
+            .ok()
+            .filter(|obj| obj.typ == ObjType::Map)
+            .map(|obj| self.ops.keys_in_insertion_order(&obj.id, clock))
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn object_stats_for(
+        &self,
+        obj: &ExId,
+        clock: Option<Clock>,
+    ) -> Result<ObjStats, AutomergeError> {
+        let obj = self.exid_to_obj(obj)?;
+        Ok(self.ops.object_stats(&obj, clock))
+    }
+
+    pub(crate) fn tombstone_count_for(
+        &self,
+        obj: &ExId,
+        clock: Option<Clock>,
+    ) -> Result<usize, AutomergeError> {
+        let obj = self.exid_to_obj(obj)?;
+        Ok(self.ops.tombstone_count(&obj.id, clock.as_ref()))
+    }
+
+    pub(crate) fn total_tombstones_for(&self, clock: Option<Clock>) -> usize {
+        self.ops.total_tombstones(clock.as_ref())
+    }
+
+    pub(crate) fn keys_with_conflicts_for(
+        &self,
+        obj: &ExId,
+        clock: Option<Clock>,
+    ) -> KeysWithConflicts<'_> {
+        self.exid_to_obj(obj)
+            .ok()
+            .map(|obj| self.ops.keys_with_conflicts(&obj, clock))
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn map_values_sorted_by_for<K: Ord, F: Fn(&Value<'_>) -> K>(
+        &self,
+        obj: &ExId,
+        key_fn: F,
+        clock: Option<Clock>,
+    ) -> Result<Vec<(Prop, Value<'_>)>, AutomergeError> {
+        let meta = self.exid_to_obj(obj)?;
+        if meta.typ.is_sequence() {
+            return Err(AutomergeError::InvalidOp(meta.typ));
+        }
+        let mut values: Vec<_> = self
+            .map_range_for(obj, .., clock)
+            .map(|item| (Prop::Map(item.key.to_string()), item.value))
+            .collect();
+        // a stable sort, so ties stay in the order of the map keys
+        values.sort_by_cached_key(|(_, value)| key_fn(value));
+        Ok(values)
+    }
+
     pub(crate) fn map_range_for<'a, R: RangeBounds<String> + 'a>(
         &'a self,
         obj: &ExId,
@@ -1421,6 +3154,52 @@ impl Automerge {
             .unwrap_or_default()
     }
 
+    pub(crate) fn list_range_rev_for<R: RangeBounds<usize>>(
+        &self,
+        obj: &ExId,
+        range: R,
+        clock: Option<Clock>,
+    ) -> ListRangeRev<'_> {
+        self.exid_to_obj(obj)
+            .ok()
+            .map(|obj| {
+                self.ops.list_range_rev(
+                    &obj.id,
+                    range,
+                    TextRepresentation::Array.encoding(obj.typ),
+                    clock,
+                )
+            })
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn read_bytes_for<R: RangeBounds<usize>>(
+        &self,
+        obj: &ExId,
+        range: R,
+        clock: Option<Clock>,
+    ) -> Result<Vec<u8>, AutomergeError> {
+        let meta = self.exid_to_obj(obj)?;
+        if meta.typ != ObjType::List {
+            return Err(AutomergeError::InvalidOp(meta.typ));
+        }
+        self.list_range_for(obj, range, clock)
+            .map(|item| {
+                match &item.value {
+                    Value::Scalar(s) => match s.as_ref() {
+                        ScalarValue::Uint(n) => u8::try_from(*n).ok(),
+                        _ => None,
+                    },
+                    Value::Object(_) => None,
+                }
+                .ok_or_else(|| AutomergeError::InvalidValueType {
+                    expected: "a uint between 0 and 255".to_string(),
+                    unexpected: item.value.to_string(),
+                })
+            })
+            .collect()
+    }
+
     pub(crate) fn list_range_for<R: RangeBounds<usize>>(
         &self,
         obj: &ExId,
@@ -1440,6 +3219,48 @@ impl Automerge {
             .unwrap_or_default()
     }
 
+    pub(crate) fn find_indices_for<F: Fn(&Value<'_>) -> bool>(
+        &self,
+        obj: &ExId,
+        pred: F,
+        clock: Option<Clock>,
+    ) -> Result<Vec<usize>, AutomergeError> {
+        let meta = self.exid_to_obj(obj)?;
+        if !meta.typ.is_sequence() {
+            return Err(AutomergeError::InvalidOp(meta.typ));
+        }
+        Ok(self
+            .list_range_for(obj, .., clock)
+            .filter(|item| pred(&item.value))
+            .map(|item| item.index)
+            .collect())
+    }
+
+    pub(crate) fn list_endpoints_for(
+        &self,
+        obj: &ExId,
+        clock: Option<Clock>,
+    ) -> Result<Option<(crate::ListEndpoint<'_>, crate::ListEndpoint<'_>)>, AutomergeError> {
+        let meta = self.exid_to_obj(obj)?;
+        if !meta.typ.is_sequence() {
+            return Err(AutomergeError::InvalidOp(meta.typ));
+        }
+        let encoding = TextRepresentation::Array.encoding(meta.typ);
+        let mut index = 0;
+        let mut ends = None;
+        for top in self.ops.top_ops(&meta.id, clock.clone()) {
+            let (first, _) = ends.get_or_insert(((index, top.op), (index, top.op)));
+            ends = Some((*first, (index, top.op)));
+            index += top.op.width(encoding);
+        }
+        Ok(ends.map(|((first, a), (last, b))| {
+            (
+                (first, a.value_at(clock.as_ref())),
+                (last, b.value_at(clock.as_ref())),
+            )
+        }))
+    }
+
     pub(crate) fn values_for(&self, obj: &ExId, clock: Option<Clock>) -> Values<'_> {
         self.exid_to_obj(obj)
             .ok()
@@ -1447,6 +3268,27 @@ impl Automerge {
             .unwrap_or_default()
     }
 
+    pub(crate) fn entries_for(&self, obj: &ExId, clock: Option<Clock>) -> Entries<'_> {
+        self.exid_to_obj(obj)
+            .ok()
+            .map(|obj| {
+                Entries::new(
+                    self.ops.top_ops(&obj.id, clock.clone()),
+                    &self.ops,
+                    TextRepresentation::Array.encoding(obj.typ),
+                    clock,
+                )
+            })
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn values_ref_for(&self, obj: &ExId, clock: Option<Clock>) -> ValuesRef<'_> {
+        self.exid_to_obj(obj)
+            .ok()
+            .map(|obj| ValuesRef::new(self.ops.top_ops(&obj.id, clock.clone()), clock))
+            .unwrap_or_default()
+    }
+
     pub(crate) fn length_for(&self, obj: &ExId, clock: Option<Clock>) -> usize {
         // FIXME - is doc.length() for a text always the string length?
         self.exid_to_obj(obj)
@@ -1457,6 +3299,29 @@ impl Automerge {
             .unwrap_or(0)
     }
 
+    pub(crate) fn length_utf16_for(&self, obj: &ExId, clock: Option<Clock>) -> usize {
+        self.exid_to_obj(obj)
+            .map(|obj| {
+                let encoding = if obj.typ == ObjType::Text {
+                    ListEncoding::Utf16
+                } else {
+                    ListEncoding::List
+                };
+                self.ops.length(&obj.id, encoding, clock)
+            })
+            .unwrap_or(0)
+    }
+
+    pub(crate) fn length_grapheme_for(&self, obj: &ExId, clock: Option<Clock>) -> usize {
+        match self.exid_to_obj(obj) {
+            Ok(meta) if meta.typ == ObjType::Text => {
+                self.ops.text(&meta.id, clock).graphemes(true).count()
+            }
+            Ok(_) => self.length_for(obj, clock),
+            Err(_) => 0,
+        }
+    }
+
     pub(crate) fn text_for(
         &self,
         obj: &ExId,
@@ -1466,6 +3331,76 @@ impl Automerge {
         Ok(self.ops.text(&obj.id, clock))
     }
 
+    pub(crate) fn text_diff_for(
+        &self,
+        obj: &ExId,
+        before: &[ChangeHash],
+        after: &[ChangeHash],
+    ) -> Result<Vec<TextEdit>, AutomergeError> {
+        let meta = self.exid_to_obj(obj)?;
+        if meta.typ != ObjType::Text {
+            return Err(AutomergeError::InvalidOp(meta.typ));
+        }
+        if let Some(missing) = before
+            .iter()
+            .chain(after)
+            .find(|hash| !self.history_index.contains_key(hash))
+        {
+            return Err(AutomergeError::MissingHash(*missing));
+        }
+        let mut patch_log = PatchLog::active(TextRepresentation::String);
+        diff::log_object_diff(
+            self,
+            &meta,
+            &self.clock_at(before),
+            &self.clock_at(after),
+            &mut patch_log,
+        );
+        patch_log.heads = Some(after.to_vec());
+        let mut edits = Vec::new();
+        for patch in patch_log.make_patches(self) {
+            match patch.action {
+                PatchAction::SpliceText {
+                    index,
+                    value,
+                    marks,
+                } => {
+                    let text = value.make_string();
+                    let len = TextValue::width(&text);
+                    TextEdit::push(&mut edits, TextEdit::Insert { index, text });
+                    for (name, value) in marks.iter().flat_map(|m| m.iter()) {
+                        let name = name.to_string();
+                        let value = value.clone();
+                        let format = TextEdit::Format {
+                            index,
+                            len,
+                            name,
+                            value,
+                        };
+                        TextEdit::push(&mut edits, format);
+                    }
+                }
+                PatchAction::DeleteSeq { index, length } => {
+                    TextEdit::push(&mut edits, TextEdit::Delete { index, len: length });
+                }
+                PatchAction::Mark { marks } => {
+                    for mark in marks {
+                        let format = TextEdit::Format {
+                            index: mark.start,
+                            len: mark.end - mark.start,
+                            name: mark.name().to_string(),
+                            value: mark.value().clone(),
+                        };
+                        TextEdit::push(&mut edits, format);
+                    }
+                }
+                // block markers have no textual representation
+                _ => {}
+            }
+        }
+        Ok(edits)
+    }
+
     pub(crate) fn spans_for(
         &self,
         obj: &ExId,
@@ -1476,6 +3411,83 @@ impl Automerge {
         Ok(Spans::new(iter, self, clock))
     }
 
+    pub(crate) fn spans_range_for(
+        &self,
+        obj: &ExId,
+        range: Range<usize>,
+        clock: Option<Clock>,
+    ) -> Result<Spans<'_>, AutomergeError> {
+        let obj = self.exid_to_obj(obj)?;
+        let len = range.end.saturating_sub(range.start);
+        let start = if len == 0 {
+            None
+        } else {
+            self.ops
+                .seek_spans_start(&obj.id, range.start, clock.clone())
+        };
+        Ok(Spans::new_window(start, self, clock, range.start, len))
+    }
+
+    pub(crate) fn export_rich_text_for(
+        &self,
+        obj: &ExId,
+        range: Range<usize>,
+        clock: Option<Clock>,
+    ) -> Result<RichText, AutomergeError> {
+        let typ = self.exid_to_obj(obj)?.typ;
+        if typ != ObjType::Text {
+            return Err(AutomergeError::InvalidOp(typ));
+        }
+        let mut rich = RichText::default();
+        for span in self.spans_range_for(obj, range, clock)? {
+            if let Span::Text(text, marks) = span {
+                let marks = marks
+                    .iter()
+                    .flat_map(|marks| marks.iter())
+                    .filter(|(_, value)| !value.is_null())
+                    .map(|(name, value)| (name.to_string(), value.clone()))
+                    .collect();
+                rich.push(&text, marks);
+            }
+        }
+        Ok(rich)
+    }
+
+    pub(crate) fn char_to_utf16_for(
+        &self,
+        obj: &ExId,
+        index: usize,
+        clock: Option<Clock>,
+    ) -> Result<usize, AutomergeError> {
+        self.convert_text_index_for(obj, index, ListEncoding::Text, ListEncoding::Utf16, clock)
+    }
+
+    pub(crate) fn utf16_to_char_for(
+        &self,
+        obj: &ExId,
+        index: usize,
+        clock: Option<Clock>,
+    ) -> Result<usize, AutomergeError> {
+        self.convert_text_index_for(obj, index, ListEncoding::Utf16, ListEncoding::Text, clock)
+    }
+
+    pub(crate) fn convert_text_index_for(
+        &self,
+        obj: &ExId,
+        index: usize,
+        from: ListEncoding,
+        to: ListEncoding,
+        clock: Option<Clock>,
+    ) -> Result<usize, AutomergeError> {
+        let obj = self.exid_to_obj(obj)?;
+        if obj.typ != ObjType::Text {
+            return Err(AutomergeError::InvalidOp(obj.typ));
+        }
+        self.ops
+            .convert_index(&obj.id, index, from, to, clock)
+            .ok_or(AutomergeError::InvalidIndex(index))
+    }
+
     pub(crate) fn get_cursor_for(
         &self,
         obj: &ExId,
@@ -1486,20 +3498,96 @@ impl Automerge {
         if !obj.typ.is_sequence() {
             Err(AutomergeError::InvalidOp(obj.typ))
         } else {
-            let found = self.ops.seek_ops_by_prop(
-                &obj.id,
-                position.into(),
-                TextRepresentation::String.encoding(obj.typ),
-                clock.as_ref(),
-            );
+            let encoding = TextRepresentation::String.encoding(obj.typ);
+            let found =
+                self.ops
+                    .seek_ops_by_prop(&obj.id, position.into(), encoding, clock.as_ref());
             if let Some(op) = found.ops.last() {
                 Ok(Cursor::new(*op.id(), &self.ops.osd))
+            } else if position == self.ops.length(&obj.id, encoding, clock) {
+                Ok(Cursor::end())
             } else {
                 Err(AutomergeError::InvalidIndex(position))
             }
         }
     }
 
+    pub(crate) fn element_token_for(
+        &self,
+        obj: &ExId,
+        index: usize,
+        clock: Option<Clock>,
+    ) -> Result<String, AutomergeError> {
+        let obj = self.exid_to_obj(obj)?;
+        if !obj.typ.is_sequence() {
+            return Err(AutomergeError::InvalidOp(obj.typ));
+        }
+        let encoding = TextRepresentation::String.encoding(obj.typ);
+        let found = self
+            .ops
+            .seek_ops_by_prop(&obj.id, index.into(), encoding, clock.as_ref());
+        let elem = found
+            .ops
+            .last()
+            .and_then(|op| op.elemid())
+            .ok_or(AutomergeError::InvalidIndex(index))?;
+        Ok(Cursor::new(elem.0, &self.ops.osd).to_string())
+    }
+
+    pub(crate) fn visible_count_before_for(
+        &self,
+        obj: &ExId,
+        op_pos: usize,
+        clock: Option<Clock>,
+    ) -> usize {
+        self.exid_to_obj(obj)
+            .map(|obj| {
+                self.ops.visible_count_before(
+                    &obj.id,
+                    op_pos,
+                    TextRepresentation::String.encoding(obj.typ),
+                    clock,
+                )
+            })
+            .unwrap_or(0)
+    }
+
+    pub(crate) fn map_contains_key_for(
+        &self,
+        obj: &ExId,
+        key: &str,
+        clock: Option<Clock>,
+    ) -> Result<bool, AutomergeError> {
+        let obj = self.exid_to_obj(obj)?;
+        if obj.typ.is_sequence() {
+            return Err(AutomergeError::InvalidOp(obj.typ));
+        }
+        Ok(self.ops.has_visible_map_key(&obj.id, key, clock.as_ref()))
+    }
+
+    pub(crate) fn list_contains_index_for(
+        &self,
+        obj: &ExId,
+        index: usize,
+        clock: Option<Clock>,
+    ) -> Result<bool, AutomergeError> {
+        let typ = self.exid_to_obj(obj)?.typ;
+        if !typ.is_sequence() {
+            return Err(AutomergeError::InvalidOp(typ));
+        }
+        Ok(index < self.length_for(obj, clock))
+    }
+
+    pub(crate) fn index_of_token_for(
+        &self,
+        obj: &ExId,
+        token: &str,
+        clock: Option<Clock>,
+    ) -> Result<usize, AutomergeError> {
+        let cursor = Cursor::try_from(token)?;
+        self.get_cursor_position_for(obj, &cursor, clock)
+    }
+
     pub(crate) fn get_cursor_position_for(
         &self,
         obj: &ExId,
@@ -1507,34 +3595,201 @@ impl Automerge {
         clock: Option<Clock>,
     ) -> Result<usize, AutomergeError> {
         let obj = self.exid_to_obj(obj)?;
+        let encoding = TextRepresentation::String.encoding(obj.typ);
+        if cursor.is_end() {
+            if !obj.typ.is_sequence() {
+                return Err(AutomergeError::InvalidOp(obj.typ));
+            }
+            return Ok(self.ops.length(&obj.id, encoding, clock));
+        }
         let opid = self.cursor_to_opid(cursor, clock.as_ref())?;
         let found = self
             .ops
-            .seek_list_opid(
+            .seek_list_opid(&obj.id, opid, encoding, clock.as_ref())
+            .ok_or_else(|| AutomergeError::InvalidCursor(cursor.clone()))?;
+        Ok(found.index)
+    }
+
+    pub(crate) fn get_cursor_positions_for(
+        &self,
+        obj: &ExId,
+        cursors: &[Cursor],
+        clock: Option<Clock>,
+    ) -> Result<Vec<usize>, AutomergeError> {
+        let obj = self.exid_to_obj(obj)?;
+        if !obj.typ.is_sequence() {
+            return Err(AutomergeError::InvalidOp(obj.typ));
+        }
+        let encoding = TextRepresentation::String.encoding(obj.typ);
+        let ids = cursors
+            .iter()
+            .filter(|cursor| !cursor.is_end())
+            .map(|cursor| self.cursor_to_opid(cursor, clock.as_ref()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut found = self
+            .ops
+            .seek_list_opids(&obj.id, &ids, encoding, clock.as_ref())
+            .into_iter();
+        let mut length = None;
+        cursors
+            .iter()
+            .map(|cursor| {
+                if cursor.is_end() {
+                    Ok(*length
+                        .get_or_insert_with(|| self.ops.length(&obj.id, encoding, clock.clone())))
+                } else {
+                    found
+                        .next()
+                        .flatten()
+                        .ok_or_else(|| AutomergeError::InvalidCursor(cursor.clone()))
+                }
+            })
+            .collect()
+    }
+
+    pub(crate) fn get_cursor_position_and_value_for(
+        &self,
+        obj: &ExId,
+        cursor: &Cursor,
+        clock: Option<Clock>,
+    ) -> Result<(usize, Option<Value<'_>>), AutomergeError> {
+        let obj = self.exid_to_obj(obj)?;
+        let encoding = TextRepresentation::String.encoding(obj.typ);
+        if cursor.is_end() {
+            if !obj.typ.is_sequence() {
+                return Err(AutomergeError::InvalidOp(obj.typ));
+            }
+            return Ok((self.ops.length(&obj.id, encoding, clock), None));
+        }
+        let opid = self.cursor_to_opid(cursor, clock.as_ref())?;
+        let (index, winner) = self
+            .ops
+            .seek_list_element(&obj.id, opid, encoding, clock.as_ref())
+            .ok_or_else(|| AutomergeError::InvalidCursor(cursor.clone()))?;
+        Ok((index, winner.map(|op| op.value_at(clock.as_ref()))))
+    }
+
+    pub(crate) fn index_of_for(
+        &self,
+        obj: &ExId,
+        elem: &ExId,
+        clock: Option<Clock>,
+    ) -> Result<Option<usize>, AutomergeError> {
+        let obj = self.exid_to_obj(obj)?;
+        if !obj.typ.is_sequence() {
+            return Err(AutomergeError::InvalidOp(obj.typ));
+        }
+        let opid = self.exid_to_opid(elem)?;
+        let encoding = TextRepresentation::String.encoding(obj.typ);
+        Ok(self
+            .ops
+            .seek_list_element(&obj.id, opid, encoding, clock.as_ref())
+            .and_then(|(index, winner)| winner.map(|_| index)))
+    }
+
+    pub(crate) fn view_for(
+        &self,
+        obj: &ExId,
+        clock: Option<Clock>,
+    ) -> Result<ObjView<'_>, AutomergeError> {
+        let meta = self.exid_to_obj(obj)?;
+        Ok(ObjView::new(self, obj.clone(), meta, clock))
+    }
+
+    pub(crate) fn marks_for(
+        &self,
+        obj: &ExId,
+        clock: Option<Clock>,
+    ) -> Result<Vec<Mark<'_>>, AutomergeError> {
+        self.calculate_marks(obj, clock)
+    }
+
+    pub(crate) fn get_for(
+        &self,
+        obj: &ExId,
+        prop: Prop,
+        clock: Option<Clock>,
+    ) -> Result<Option<(Value<'_>, ExId)>, AutomergeError> {
+        let obj = self.exid_to_obj(obj)?;
+        Ok(self
+            .ops
+            .seek_ops_by_prop(
                 &obj.id,
-                opid,
+                prop,
                 TextRepresentation::String.encoding(obj.typ),
                 clock.as_ref(),
             )
-            .ok_or_else(|| AutomergeError::InvalidCursor(cursor.clone()))?;
-        Ok(found.index)
+            .ops
+            .into_iter()
+            .last()
+            .map(|op| op.tagged_value(clock.as_ref())))
+    }
+
+    pub(crate) fn value_provenance_for(
+        &self,
+        obj: &ExId,
+        prop: Prop,
+        clock: Option<Clock>,
+    ) -> Result<Option<ChangeHash>, AutomergeError> {
+        let obj = self.exid_to_obj(obj)?;
+        let winner = self
+            .ops
+            .seek_ops_by_prop(
+                &obj.id,
+                prop,
+                TextRepresentation::String.encoding(obj.typ),
+                clock.as_ref(),
+            )
+            .ops
+            .last()
+            .map(|op| *op.id());
+        Ok(winner
+            .and_then(|id| self.change_for_opid(&id))
+            .map(|change| change.hash()))
+    }
+
+    pub(crate) fn get_at_element_creation_for(
+        &self,
+        obj: &ExId,
+        prop: Prop,
+        list: &ExId,
+        cursor: &Cursor,
+        clock: Option<Clock>,
+    ) -> Result<Option<(Value<'_>, ExId)>, AutomergeError> {
+        let invalid = || AutomergeError::InvalidCursor(cursor.clone());
+        let list = self.exid_to_obj(list)?;
+        if cursor.is_end() {
+            return Err(invalid());
+        }
+        let opid = self.cursor_to_opid(cursor, clock.as_ref())?;
+        self.ops
+            .seek_list_opid(&list.id, opid, ListEncoding::List, clock.as_ref())
+            .ok_or_else(invalid)?;
+        let change = self.change_for_opid(&opid).ok_or_else(invalid)?;
+        let created = self.clock_at(&[change.hash()]);
+        self.get_for(obj, prop, Some(created))
     }
 
-    pub(crate) fn marks_for(
+    pub(crate) fn get_timestamp_for(
         &self,
         obj: &ExId,
+        prop: Prop,
         clock: Option<Clock>,
-    ) -> Result<Vec<Mark<'_>>, AutomergeError> {
-        self.calculate_marks(obj, clock)
+    ) -> Result<Option<i64>, AutomergeError> {
+        Ok(self
+            .get_for(obj, prop, clock)?
+            .and_then(|(value, _)| value.to_scalar()?.to_timestamp()))
     }
 
-    pub(crate) fn get_for(
+    pub(crate) fn get_by_timestamp_for(
         &self,
         obj: &ExId,
         prop: Prop,
         clock: Option<Clock>,
     ) -> Result<Option<(Value<'_>, ExId)>, AutomergeError> {
         let obj = self.exid_to_obj(obj)?;
+        // the ops are sorted by id and `max_by_key` returns the last of equal elements, so ties
+        // are won by the op which `get` would pick
         Ok(self
             .ops
             .seek_ops_by_prop(
@@ -1545,10 +3800,38 @@ impl Automerge {
             )
             .ops
             .into_iter()
-            .last()
+            .max_by_key(|op| {
+                self.change_for_opid(op.id())
+                    .map(|change| change.timestamp())
+                    .unwrap_or(i64::MAX)
+            })
             .map(|op| op.tagged_value(clock.as_ref())))
     }
 
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn get_many_for(
+        &self,
+        obj: &ExId,
+        props: &[Prop],
+        clock: Option<Clock>,
+    ) -> Result<Vec<(Prop, Option<(Value<'_>, ExId)>)>, AutomergeError> {
+        let obj = self.exid_to_obj(obj)?;
+        let encoding = TextRepresentation::String.encoding(obj.typ);
+        Ok(props
+            .iter()
+            .map(|prop| {
+                let value = self
+                    .ops
+                    .seek_ops_by_prop(&obj.id, prop.clone(), encoding, clock.as_ref())
+                    .ops
+                    .into_iter()
+                    .last()
+                    .map(|op| op.tagged_value(clock.as_ref()));
+                (prop.clone(), value)
+            })
+            .collect())
+    }
+
     pub(crate) fn get_all_for<O: AsRef<ExId>, P: Into<Prop>>(
         &self,
         obj: O,
@@ -1577,6 +3860,52 @@ impl Automerge {
         Ok(values)
     }
 
+    pub(crate) fn get_conflicts_for(
+        &self,
+        obj: &ExId,
+        prop: Prop,
+        clock: Option<Clock>,
+    ) -> Result<Vec<(ActorId, Value<'_>, ExId)>, AutomergeError> {
+        Ok(self
+            .get_all_for(obj, prop, clock)?
+            .into_iter()
+            .filter_map(|(value, id)| match &id {
+                ExId::Id(_, actor, _) => Some((actor.clone(), value, id)),
+                ExId::Root => None,
+            })
+            .collect())
+    }
+
+    /// The type of `obj`, or `None` if it had not been created as at `clock`
+    pub(crate) fn object_type_for(
+        &self,
+        obj: &ExId,
+        clock: Option<Clock>,
+    ) -> Result<Option<ObjType>, AutomergeError> {
+        let obj = self.exid_to_obj(obj)?;
+        let created = obj.id.is_root() || clock.map(|c| c.covers(&obj.id.0)).unwrap_or(true);
+        Ok(created.then_some(obj.typ))
+    }
+
+    pub(crate) fn marks_in_range_for(
+        &self,
+        obj: &ExId,
+        range: Range<usize>,
+        clock: Option<Clock>,
+    ) -> Result<Vec<Mark<'_>>, AutomergeError> {
+        let obj = self.exid_to_obj(obj)?;
+        if !obj.typ.is_sequence() {
+            return Err(AutomergeError::InvalidOp(obj.typ));
+        }
+        Ok(MarkStateMachine::marks_in_range(
+            self.ops.iter_ops(&obj.id),
+            range,
+            TextRepresentation::String.encoding(obj.typ),
+            clock.as_ref(),
+            self.osd(),
+        ))
+    }
+
     pub(crate) fn get_marks_for<O: AsRef<ExId>>(
         &self,
         obj: O,
@@ -1603,6 +3932,36 @@ impl Automerge {
         Ok(result)
     }
 
+    pub(crate) fn text_at_cursor_for(
+        &self,
+        obj: &ExId,
+        cursor: &Cursor,
+        clock: Option<Clock>,
+    ) -> Result<MarkSet, AutomergeError> {
+        let index = self.get_cursor_position_for(obj, cursor, clock.clone())?;
+        let len = self.length_for(obj, clock.clone());
+        if index < len {
+            return self.get_marks_for(obj, index, clock);
+        }
+        if len == 0 {
+            return Ok(MarkSet::default());
+        }
+        // the cursor points past the end of the sequence (its element has been deleted), so
+        // return the marks a character inserted here would get
+        let meta = self.exid_to_obj(obj)?;
+        let result = self
+            .ops
+            .search(
+                &meta.id,
+                query::InsertNth::new(index, TextRepresentation::String.encoding(meta.typ), clock),
+            )
+            .marks(&self.ops.osd)
+            .as_deref()
+            .cloned()
+            .unwrap_or_default();
+        Ok(result)
+    }
+
     fn convert_scalar_strings_to_text(&mut self) -> Result<(), AutomergeError> {
         struct Conversion {
             obj_id: ExId,
@@ -1610,11 +3969,11 @@ impl Automerge {
             text: smol_str::SmolStr,
         }
         let mut to_convert = Vec::new();
-        for (obj, ops) in self.ops.iter_objs() {
+        let spilled = self.ops.rebuild_spilled();
+        for (obj, ops) in self.ops.iter_objs(&spilled) {
             match obj.typ {
                 ObjType::Map | ObjType::List => {
                     for op in ops {
-                        let op = op.as_op(self.osd());
                         if !op.visible() {
                             continue;
                         }
@@ -1667,7 +4026,8 @@ impl Automerge {
         visible_objs.insert(crate::types::ObjId::root());
         paths.insert(ExId::Root, vec![]);
 
-        for (obj, ops) in self.ops.iter_objs() {
+        let spilled = self.ops.rebuild_spilled();
+        for (obj, ops) in self.ops.iter_objs(&spilled) {
             // Note that this works because the OpSet iterates in causal order,
             // which means that we have already seen the operation which
             // creates the object and added it to the visible_objs set if it
@@ -1675,8 +4035,7 @@ impl Automerge {
             if !visible_objs.contains(&obj.id) {
                 continue;
             }
-            for op_idx in ops {
-                let op = op_idx.as_op(self.osd());
+            for op in ops {
                 if op.visible_at(at.as_ref()) {
                     if let OpType::Make(_) = op.action() {
                         visible_objs.insert(op.id().into());
@@ -1717,6 +4076,18 @@ impl Automerge {
 }
 
 impl ReadDoc for Automerge {
+    fn view<O: AsRef<ExId>>(&self, obj: O) -> Result<ObjView<'_>, AutomergeError> {
+        self.view_for(obj.as_ref(), None)
+    }
+
+    fn view_at<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        heads: &[ChangeHash],
+    ) -> Result<ObjView<'_>, AutomergeError> {
+        self.view_for(obj.as_ref(), Some(self.clock_at(heads)))
+    }
+
     fn parents<O: AsRef<ExId>>(&self, obj: O) -> Result<Parents<'_>, AutomergeError> {
         self.parents_for(obj.as_ref(), None)
     }
@@ -1739,6 +4110,24 @@ impl ReadDoc for Automerge {
         self.keys_for(obj.as_ref(), Some(clock))
     }
 
+    fn keys_with_conflicts<O: AsRef<ExId>>(&self, obj: O) -> KeysWithConflicts<'_> {
+        self.keys_with_conflicts_for(obj.as_ref(), None)
+    }
+
+    fn map_keys_in_insertion_order<O: AsRef<ExId>>(&self, obj: O) -> Vec<String> {
+        self.map_keys_in_insertion_order_for(obj.as_ref(), None)
+    }
+
+    fn map_values_sorted_by<O: AsRef<ExId>, K: Ord, F: Fn(&Value<'_>) -> K>(
+        &self,
+        obj: O,
+        key_fn: F,
+        heads: Option<&[ChangeHash]>,
+    ) -> Result<Vec<(Prop, Value<'_>)>, AutomergeError> {
+        let clock = heads.map(|h| self.clock_at(h));
+        self.map_values_sorted_by_for(obj.as_ref(), key_fn, clock)
+    }
+
     fn map_range<'a, O: AsRef<ExId>, R: RangeBounds<String> + 'a>(
         &'a self,
         obj: O,
@@ -1775,6 +4164,51 @@ impl ReadDoc for Automerge {
         self.list_range_for(obj.as_ref(), range, Some(clock))
     }
 
+    fn find_indices<O: AsRef<ExId>, F: Fn(&Value<'_>) -> bool>(
+        &self,
+        obj: O,
+        pred: F,
+        heads: Option<&[ChangeHash]>,
+    ) -> Result<Vec<usize>, AutomergeError> {
+        let clock = heads.map(|h| self.clock_at(h));
+        self.find_indices_for(obj.as_ref(), pred, clock)
+    }
+
+    fn list_endpoints<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        heads: Option<&[ChangeHash]>,
+    ) -> Result<Option<(crate::ListEndpoint<'_>, crate::ListEndpoint<'_>)>, AutomergeError> {
+        let clock = heads.map(|h| self.clock_at(h));
+        self.list_endpoints_for(obj.as_ref(), clock)
+    }
+
+    fn list_range_rev<O: AsRef<ExId>, R: RangeBounds<usize>>(
+        &self,
+        obj: O,
+        range: R,
+    ) -> ListRangeRev<'_> {
+        self.list_range_rev_for(obj.as_ref(), range, None)
+    }
+
+    fn list_range_rev_at<O: AsRef<ExId>, R: RangeBounds<usize>>(
+        &self,
+        obj: O,
+        range: R,
+        heads: &[ChangeHash],
+    ) -> ListRangeRev<'_> {
+        let clock = self.clock_at(heads);
+        self.list_range_rev_for(obj.as_ref(), range, Some(clock))
+    }
+
+    fn read_bytes<O: AsRef<ExId>, R: RangeBounds<usize>>(
+        &self,
+        obj: O,
+        range: R,
+    ) -> Result<Vec<u8>, AutomergeError> {
+        self.read_bytes_for(obj.as_ref(), range, None)
+    }
+
     fn values<O: AsRef<ExId>>(&self, obj: O) -> Values<'_> {
         self.values_for(obj.as_ref(), None)
     }
@@ -1784,19 +4218,53 @@ impl ReadDoc for Automerge {
         self.values_for(obj.as_ref(), Some(clock))
     }
 
+    fn entries<O: AsRef<ExId>>(&self, obj: O) -> Entries<'_> {
+        self.entries_for(obj.as_ref(), None)
+    }
+
+    fn entries_at<O: AsRef<ExId>>(&self, obj: O, heads: &[ChangeHash]) -> Entries<'_> {
+        let clock = self.clock_at(heads);
+        self.entries_for(obj.as_ref(), Some(clock))
+    }
+
+    fn values_ref<O: AsRef<ExId>>(&self, obj: O) -> ValuesRef<'_> {
+        self.values_ref_for(obj.as_ref(), None)
+    }
+
+    fn objects(&self) -> Objects<'_> {
+        Objects::new(self, None)
+    }
+
     fn length<O: AsRef<ExId>>(&self, obj: O) -> usize {
         self.length_for(obj.as_ref(), None)
     }
 
     fn length_at<O: AsRef<ExId>>(&self, obj: O, heads: &[ChangeHash]) -> usize {
-        let clock = self.clock_at(heads);
-        self.length_for(obj.as_ref(), Some(clock))
+        let clock = self.clock_at_unless_current(heads);
+        self.length_for(obj.as_ref(), clock)
+    }
+
+    fn length_utf16<O: AsRef<ExId>>(&self, obj: O) -> usize {
+        self.length_utf16_for(obj.as_ref(), None)
+    }
+
+    fn length_grapheme<O: AsRef<ExId>>(&self, obj: O) -> usize {
+        self.length_grapheme_for(obj.as_ref(), None)
     }
 
     fn text<O: AsRef<ExId>>(&self, obj: O) -> Result<String, AutomergeError> {
         self.text_for(obj.as_ref(), None)
     }
 
+    fn text_diff<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        before: &[ChangeHash],
+        after: &[ChangeHash],
+    ) -> Result<Vec<TextEdit>, AutomergeError> {
+        self.text_diff_for(obj.as_ref(), before, after)
+    }
+
     fn spans<O: AsRef<ExId>>(&self, obj: O) -> Result<Spans<'_>, AutomergeError> {
         self.spans_for(obj.as_ref(), None)
     }
@@ -1810,6 +4278,41 @@ impl ReadDoc for Automerge {
         self.spans_for(obj.as_ref(), Some(clock))
     }
 
+    fn spans_range<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        range: Range<usize>,
+        heads: Option<&[ChangeHash]>,
+    ) -> Result<Spans<'_>, AutomergeError> {
+        let clock = heads.map(|heads| self.clock_at(heads));
+        self.spans_range_for(obj.as_ref(), range, clock)
+    }
+
+    fn export_rich_text<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        range: Range<usize>,
+        heads: Option<&[ChangeHash]>,
+    ) -> Result<RichText, AutomergeError> {
+        let clock = heads.map(|heads| self.clock_at(heads));
+        self.export_rich_text_for(obj.as_ref(), range, clock)
+    }
+
+    fn import_exid(&self, s: &str) -> Result<ExId, AutomergeError> {
+        let obj = self.import_obj(s)?;
+        self.exid_to_obj(&obj)
+            .map_err(|_| AutomergeError::InvalidObjId(s.to_owned()))?;
+        Ok(obj)
+    }
+
+    fn char_to_utf16<O: AsRef<ExId>>(&self, obj: O, index: usize) -> Result<usize, AutomergeError> {
+        self.char_to_utf16_for(obj.as_ref(), index, None)
+    }
+
+    fn utf16_to_char<O: AsRef<ExId>>(&self, obj: O, index: usize) -> Result<usize, AutomergeError> {
+        self.utf16_to_char_for(obj.as_ref(), index, None)
+    }
+
     fn get_cursor<O: AsRef<ExId>>(
         &self,
         obj: O,
@@ -1830,6 +4333,72 @@ impl ReadDoc for Automerge {
         self.get_cursor_position_for(obj.as_ref(), cursor, clock)
     }
 
+    fn get_cursor_positions<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        cursors: &[Cursor],
+        at: Option<&[ChangeHash]>,
+    ) -> Result<Vec<usize>, AutomergeError> {
+        let clock = at.map(|heads| self.clock_at(heads));
+        self.get_cursor_positions_for(obj.as_ref(), cursors, clock)
+    }
+
+    fn get_cursor_position_and_value<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        cursor: &Cursor,
+        at: Option<&[ChangeHash]>,
+    ) -> Result<(usize, Option<Value<'_>>), AutomergeError> {
+        let clock = at.map(|heads| self.clock_at(heads));
+        self.get_cursor_position_and_value_for(obj.as_ref(), cursor, clock)
+    }
+
+    fn index_of<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        elem: &ExId,
+        at: Option<&[ChangeHash]>,
+    ) -> Result<Option<usize>, AutomergeError> {
+        let clock = at.map(|heads| self.clock_at(heads));
+        self.index_of_for(obj.as_ref(), elem, clock)
+    }
+
+    fn element_token<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        index: usize,
+    ) -> Result<String, AutomergeError> {
+        self.element_token_for(obj.as_ref(), index, None)
+    }
+
+    fn index_of_token<O: AsRef<ExId>>(&self, obj: O, token: &str) -> Result<usize, AutomergeError> {
+        self.index_of_token_for(obj.as_ref(), token, None)
+    }
+
+    fn visible_count_before<O: AsRef<ExId>>(&self, obj: O, op_pos: usize) -> usize {
+        self.visible_count_before_for(obj.as_ref(), op_pos, None)
+    }
+
+    fn map_contains_key<O: AsRef<ExId>>(&self, obj: O, key: &str) -> Result<bool, AutomergeError> {
+        self.map_contains_key_for(obj.as_ref(), key, None)
+    }
+
+    fn value_provenance<O: AsRef<ExId>, P: Into<Prop>>(
+        &self,
+        obj: O,
+        prop: P,
+    ) -> Result<Option<ChangeHash>, AutomergeError> {
+        self.value_provenance_for(obj.as_ref(), prop.into(), None)
+    }
+
+    fn list_contains_index<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        index: usize,
+    ) -> Result<bool, AutomergeError> {
+        self.list_contains_index_for(obj.as_ref(), index, None)
+    }
+
     fn text_at<O: AsRef<ExId>>(
         &self,
         obj: O,
@@ -1852,6 +4421,14 @@ impl ReadDoc for Automerge {
         self.marks_for(obj.as_ref(), Some(clock))
     }
 
+    fn all_marks(&self) -> AllMarks<'_> {
+        AllMarks::new(self, None)
+    }
+
+    fn all_marks_at(&self, heads: &[ChangeHash]) -> AllMarks<'_> {
+        AllMarks::new(self, Some(self.clock_at(heads)))
+    }
+
     fn hydrate<O: AsRef<ExId>>(
         &self,
         obj: O,
@@ -1866,6 +4443,16 @@ impl ReadDoc for Automerge {
         })
     }
 
+    fn marks_in_range<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        range: Range<usize>,
+        heads: Option<&[ChangeHash]>,
+    ) -> Result<Vec<Mark<'_>>, AutomergeError> {
+        let clock = heads.map(|h| self.clock_at(h));
+        self.marks_in_range_for(obj.as_ref(), range, clock)
+    }
+
     fn get_marks<O: AsRef<ExId>>(
         &self,
         obj: O,
@@ -1876,6 +4463,16 @@ impl ReadDoc for Automerge {
         self.get_marks_for(obj.as_ref(), index, clock)
     }
 
+    fn text_at_cursor<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        cursor: &Cursor,
+        heads: Option<&[ChangeHash]>,
+    ) -> Result<MarkSet, AutomergeError> {
+        let clock = heads.map(|h| self.clock_at(h));
+        self.text_at_cursor_for(obj.as_ref(), cursor, clock)
+    }
+
     fn get<O: AsRef<ExId>, P: Into<Prop>>(
         &self,
         obj: O,
@@ -1912,6 +4509,50 @@ impl ReadDoc for Automerge {
         self.get_all_for(obj.as_ref(), prop.into(), clock)
     }
 
+    fn get_conflicts<O: AsRef<ExId>, P: Into<Prop>>(
+        &self,
+        obj: O,
+        prop: P,
+    ) -> Result<Vec<(ActorId, Value<'_>, ExId)>, AutomergeError> {
+        self.get_conflicts_for(obj.as_ref(), prop.into(), None)
+    }
+
+    fn get_timestamp<O: AsRef<ExId>, P: Into<Prop>>(
+        &self,
+        obj: O,
+        prop: P,
+    ) -> Result<Option<i64>, AutomergeError> {
+        self.get_timestamp_for(obj.as_ref(), prop.into(), None)
+    }
+
+    fn get_at_element_creation<O: AsRef<ExId>, P: Into<Prop>, L: AsRef<ExId>>(
+        &self,
+        obj: O,
+        prop: P,
+        list: L,
+        cursor: &Cursor,
+    ) -> Result<Option<(Value<'_>, ExId)>, AutomergeError> {
+        self.get_at_element_creation_for(obj.as_ref(), prop.into(), list.as_ref(), cursor, None)
+    }
+
+    fn get_by_timestamp<O: AsRef<ExId>, P: Into<Prop>>(
+        &self,
+        obj: O,
+        prop: P,
+    ) -> Result<Option<(Value<'_>, ExId)>, AutomergeError> {
+        self.get_by_timestamp_for(obj.as_ref(), prop.into(), None)
+    }
+
+    fn get_many_at<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        props: &[Prop],
+        heads: &[ChangeHash],
+    ) -> Result<Vec<(Prop, Option<(Value<'_>, ExId)>)>, AutomergeError> {
+        let clock = Some(self.clock_at(heads));
+        self.get_many_for(obj.as_ref(), props, clock)
+    }
+
     fn object_type<O: AsRef<ExId>>(&self, obj: O) -> Result<ObjType, AutomergeError> {
         let obj = obj.as_ref();
         let opid = self.exid_to_opid(obj)?;
@@ -1919,6 +4560,15 @@ impl ReadDoc for Automerge {
         typ.ok_or_else(|| AutomergeError::InvalidObjId(obj.to_string()))
     }
 
+    fn object_type_at<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        heads: &[ChangeHash],
+    ) -> Result<Option<ObjType>, AutomergeError> {
+        let clock = self.clock_at(heads);
+        self.object_type_for(obj.as_ref(), Some(clock))
+    }
+
     fn get_missing_deps(&self, heads: &[ChangeHash]) -> Vec<ChangeHash> {
         let in_queue: HashSet<_> = self.queue.iter().map(|change| change.hash()).collect();
         let mut missing = HashSet::new();
@@ -1948,6 +4598,7 @@ impl ReadDoc for Automerge {
         self.history_index
             .get(hash)
             .and_then(|index| self.history.get(*index))
+            .and_then(|entry| entry.change())
     }
 
     fn stats(&self) -> crate::read::Stats {
@@ -1956,6 +4607,18 @@ impl ReadDoc for Automerge {
             num_ops: self.ops.len() as u64,
         }
     }
+
+    fn object_stats<O: AsRef<ExId>>(&self, obj: O) -> Result<ObjStats, AutomergeError> {
+        self.object_stats_for(obj.as_ref(), None)
+    }
+
+    fn tombstone_count<O: AsRef<ExId>>(&self, obj: O) -> Result<usize, AutomergeError> {
+        self.tombstone_count_for(obj.as_ref(), None)
+    }
+
+    fn total_tombstones(&self) -> usize {
+        self.total_tombstones_for(None)
+    }
 }
 
 impl ReadDocInternal for Automerge {
@@ -1971,12 +4634,19 @@ impl Default for Automerge {
 }
 
 /// Options to pass to [`Automerge::save_with_options()`] and [`crate::AutoCommit::save_with_options()`]
+///
+/// This is `#[non_exhaustive]` so fields can be added without breaking anyone. Start from
+/// [`SaveOptions::default()`] and set the fields you need.
 #[derive(Debug)]
+#[non_exhaustive]
 pub struct SaveOptions {
-    /// Whether to apply DEFLATE compression to the RLE encoded columns in the document
+    /// Whether to apply DEFLATE compression to the RLE encoded columns in the document. If this
+    /// is `false` then `compression` is ignored.
     pub deflate: bool,
     /// Whether to save changes which we do not have the dependencies for
     pub retain_orphans: bool,
+    /// How hard to try to compress the columns of the document
+    pub compression: CompressionLevel,
 }
 
 impl std::default::Default for SaveOptions {
@@ -1984,6 +4654,39 @@ impl std::default::Default for SaveOptions {
         Self {
             deflate: true,
             retain_orphans: true,
+            compression: CompressionLevel::Default,
+        }
+    }
+}
+
+/// The DEFLATE compression level used when saving a document, see [`SaveOptions`]
+///
+/// The level only affects the size of the saved document and the time taken to save it, a
+/// document saved at any level loads the same way.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CompressionLevel {
+    /// Don't compress anything
+    None,
+    /// Compress as quickly as possible
+    Fast,
+    /// The level used by [`Automerge::save()`], a balance between speed and size
+    #[default]
+    Default,
+    /// Produce the smallest output, taking the most time
+    Best,
+}
+
+impl CompressionLevel {
+    fn to_config(self) -> CompressConfig {
+        let level = match self {
+            CompressionLevel::None => return CompressConfig::None,
+            CompressionLevel::Fast => flate2::Compression::fast(),
+            CompressionLevel::Default => flate2::Compression::default(),
+            CompressionLevel::Best => flate2::Compression::best(),
+        };
+        CompressConfig::Threshold {
+            threshold: storage::change::DEFLATE_MIN_SIZE,
+            level,
         }
     }
 }
@@ -2021,7 +4724,7 @@ pub(crate) fn reconstruct_document<'a>(
     let history_index = hashes_by_index.into_iter().map(|(k, v)| (v, k)).collect();
     Ok(Automerge {
         queue: vec![],
-        history: changes,
+        history: changes.into_iter().map(HistoryEntry::Change).collect(),
         history_index,
         states: actor_to_history,
         change_graph,
@@ -2029,5 +4732,7 @@ pub(crate) fn reconstruct_document<'a>(
         deps: heads.into_iter().collect(),
         actor: Actor::Unused(ActorId::random()),
         max_op,
+        snapshot: None,
+        frozen: HashSet::new(),
     })
 }
@@ -32,8 +32,8 @@ pub(crate) fn log_current_state_patches(doc: &Automerge, patch_log: &mut PatchLo
     // Effectively then we iterate over each object, then we group the operations in the object by
     // key and for each key find the visible operations for that key. Then we notify the patch log
     // for each of those visible operations.
-    for (obj, ops) in doc.ops().iter_objs() {
-        let ops = ops.map(|i| i.as_op(doc.osd()));
+    let spilled = doc.ops().rebuild_spilled();
+    for (obj, ops) in doc.ops().iter_objs(&spilled) {
         if obj.typ == ObjType::Text && matches!(patch_log.text_rep(), TextRepresentation::String) {
             log_text_patches(doc, patch_log, &obj, ops)
         } else if obj.typ.is_sequence() {
@@ -138,7 +138,15 @@ fn log_map_patches<'a, I: Iterator<Item = Op<'a>>>(
             if let Some(prop_index) = put.key.prop_index() {
                 if let Some(key) = doc.ops().osd.props.safe_get(prop_index) {
                     let conflict = i > 0;
-                    patch_log.put_map(obj.id, key, put.value.into(), put.id, conflict, false);
+                    patch_log.put_map(
+                        obj.id,
+                        key,
+                        put.value.into(),
+                        put.id,
+                        conflict,
+                        false,
+                        false,
+                    );
                 }
             }
         });
@@ -245,6 +253,7 @@ mod tests {
                                 key,
                                 value,
                                 conflict,
+                                ..
                             },
                         ..
                     } => acc.push(ObservedPatch::Put {
@@ -260,6 +269,7 @@ mod tests {
                                 index,
                                 value,
                                 conflict,
+                                ..
                             },
                         ..
                     } => acc.push(ObservedPatch::Put {
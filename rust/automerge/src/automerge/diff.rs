@@ -1,13 +1,15 @@
 use itertools::Itertools;
-use std::ops::RangeBounds;
+use std::ops::{Range, RangeBounds};
 use std::sync::Arc;
 
-use crate::iter::Keys;
-use crate::iter::ListRange;
 use crate::iter::MapRange;
-use crate::iter::Values;
+use crate::iter::{AllMarks, Objects};
+use crate::iter::{Entries, Values, ValuesRef};
+use crate::iter::{Keys, KeysWithConflicts};
+use crate::iter::{ListRange, ListRangeRev};
 use crate::marks::Mark;
-use crate::patches::TextRepresentation;
+use crate::op_tree::OpTreeOpIter;
+use crate::patches::{TextEdit, TextRepresentation};
 use crate::read::ReadDocInternal;
 use crate::types::ObjMeta;
 use crate::{
@@ -15,7 +17,8 @@ use crate::{
     patches::PatchLog,
     types::{Clock, ListEncoding, Op, Prop},
     value::Value,
-    Automerge, AutomergeError, ChangeHash, Cursor, ObjId as ExId, ObjType, OpType, ReadDoc,
+    ActorId, Automerge, AutomergeError, ChangeHash, Cursor, ObjId as ExId, ObjType, ObjView,
+    OpType, ReadDoc,
 };
 
 #[derive(Clone, Debug)]
@@ -118,25 +121,45 @@ impl<'a> Patch<'a> {
 }
 
 pub(crate) fn log_diff(doc: &Automerge, before: &Clock, after: &Clock, patch_log: &mut PatchLog) {
-    for (obj, ops) in doc.ops().iter_objs() {
-        let mut diff = RichTextDiff::new(doc);
-        let ops_by_key = ops.group_by(|o| o.as_op(doc.osd()).elemid_or_key());
-        let diffs = ops_by_key.into_iter().filter_map(|(_key, key_ops)| {
-            process(
-                key_ops.map(|i| i.as_op(doc.osd())),
-                before,
-                after,
-                &mut diff,
-            )
-        });
+    let spilled = doc.ops().rebuild_spilled();
+    for (obj, ops) in doc.ops().iter_objs(&spilled) {
+        log_obj_diff(doc, &obj, ops, before, after, patch_log);
+    }
+}
 
-        if obj.typ == ObjType::Text && matches!(patch_log.text_rep(), TextRepresentation::String) {
-            log_text_diff(patch_log, &obj, diffs)
-        } else if obj.typ.is_sequence() {
-            log_list_diff(patch_log, &obj, diffs);
-        } else {
-            log_map_diff(doc, patch_log, &obj, diffs);
-        }
+/// Like [`log_diff`] but only for the object `obj`
+pub(crate) fn log_object_diff(
+    doc: &Automerge,
+    obj: &ObjMeta,
+    before: &Clock,
+    after: &Clock,
+    patch_log: &mut PatchLog,
+) {
+    if let Some(ops) = doc.ops().iter_obj(&obj.id) {
+        log_obj_diff(doc, obj, ops, before, after, patch_log);
+    }
+}
+
+fn log_obj_diff(
+    doc: &Automerge,
+    obj: &ObjMeta,
+    ops: OpTreeOpIter<'_>,
+    before: &Clock,
+    after: &Clock,
+    patch_log: &mut PatchLog,
+) {
+    let mut diff = RichTextDiff::new(doc);
+    let ops_by_key = ops.group_by(|o| o.elemid_or_key());
+    let diffs = ops_by_key
+        .into_iter()
+        .filter_map(|(_key, key_ops)| process(key_ops, before, after, &mut diff));
+
+    if obj.typ == ObjType::Text && matches!(patch_log.text_rep(), TextRepresentation::String) {
+        log_text_diff(patch_log, obj, diffs)
+    } else if obj.typ.is_sequence() {
+        log_list_diff(patch_log, obj, diffs);
+    } else {
+        log_map_diff(doc, patch_log, obj, diffs);
     }
 }
 
@@ -160,6 +183,7 @@ fn log_list_diff<'a, I: Iterator<Item = Patch<'a>>>(
             let id = *after.op.id();
             let expose = after.cross_visible;
             patch_log.put_seq(obj.id, index, value, id, conflict, expose);
+            patch_log.previous_value(before.op, Some(before.clock));
             index + 1
         }
         Patch::Old {
@@ -242,14 +266,15 @@ fn log_map_diff<'a, I: Iterator<Item = Patch<'a>>>(
                 let id = *winner.op.id();
                 let conflict = winner.conflict;
                 let expose = winner.cross_visible;
-                patch_log.put_map(obj.id, key, value, id, conflict, expose)
+                patch_log.put_map(obj.id, key, value, id, conflict, expose, false)
             }
             Patch::Update { before, after, .. } => {
                 let conflict = !before.conflict && after.conflict;
                 let value = after.op.value_at(Some(after.clock)).into();
                 let id = *after.op.id();
                 let expose = after.cross_visible;
-                patch_log.put_map(obj.id, key, value, id, conflict, expose)
+                patch_log.put_map(obj.id, key, value, id, conflict, expose, true);
+                patch_log.previous_value(before.op, Some(before.clock));
             }
             Patch::Old { before, after, .. } => {
                 if !before.conflict && after.conflict {
@@ -259,7 +284,10 @@ fn log_map_diff<'a, I: Iterator<Item = Patch<'a>>>(
                     patch_log.increment_map(obj.id, key, n, *after.op.id());
                 }
             }
-            Patch::Delete(_) => patch_log.delete_map(obj.id, key),
+            Patch::Delete(before) => {
+                patch_log.delete_map(obj.id, key);
+                patch_log.previous_value(before.op, Some(before.clock));
+            }
         });
 }
 
@@ -331,6 +359,18 @@ impl<'a, 'b> AsRef<Automerge> for ReadDocAt<'a, 'b> {
 }
 
 impl<'a, 'b> ReadDoc for ReadDocAt<'a, 'b> {
+    fn view<O: AsRef<ExId>>(&self, obj: O) -> Result<ObjView<'_>, AutomergeError> {
+        self.doc.view_at(obj, self.heads)
+    }
+
+    fn view_at<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        heads: &[ChangeHash],
+    ) -> Result<ObjView<'_>, AutomergeError> {
+        self.doc.view_at(obj, heads)
+    }
+
     fn keys<O: AsRef<ExId>>(&self, obj: O) -> Keys<'_> {
         self.doc.keys_at(obj, self.heads)
     }
@@ -339,6 +379,27 @@ impl<'a, 'b> ReadDoc for ReadDocAt<'a, 'b> {
         self.doc.keys_at(obj, heads)
     }
 
+    fn keys_with_conflicts<O: AsRef<ExId>>(&self, obj: O) -> KeysWithConflicts<'_> {
+        let clock = self.doc.clock_at(self.heads);
+        self.doc.keys_with_conflicts_for(obj.as_ref(), Some(clock))
+    }
+
+    fn map_keys_in_insertion_order<O: AsRef<ExId>>(&self, obj: O) -> Vec<String> {
+        let clock = self.doc.clock_at(self.heads);
+        self.doc
+            .map_keys_in_insertion_order_for(obj.as_ref(), Some(clock))
+    }
+
+    fn map_values_sorted_by<O: AsRef<ExId>, K: Ord, F: Fn(&Value<'_>) -> K>(
+        &self,
+        obj: O,
+        key_fn: F,
+        heads: Option<&[ChangeHash]>,
+    ) -> Result<Vec<(Prop, Value<'_>)>, crate::AutomergeError> {
+        self.doc
+            .map_values_sorted_by(obj, key_fn, Some(heads.unwrap_or(self.heads)))
+    }
+
     fn map_range<'c, O: AsRef<ExId>, R: RangeBounds<String> + 'c>(
         &'c self,
         obj: O,
@@ -373,6 +434,51 @@ impl<'a, 'b> ReadDoc for ReadDocAt<'a, 'b> {
         self.doc.list_range_at(obj, range, heads)
     }
 
+    fn find_indices<O: AsRef<ExId>, F: Fn(&Value<'_>) -> bool>(
+        &self,
+        obj: O,
+        pred: F,
+        heads: Option<&[ChangeHash]>,
+    ) -> Result<Vec<usize>, AutomergeError> {
+        self.doc
+            .find_indices(obj, pred, Some(heads.unwrap_or(self.heads)))
+    }
+
+    fn list_endpoints<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        heads: Option<&[ChangeHash]>,
+    ) -> Result<Option<(crate::ListEndpoint<'_>, crate::ListEndpoint<'_>)>, AutomergeError> {
+        self.doc
+            .list_endpoints(obj, Some(heads.unwrap_or(self.heads)))
+    }
+
+    fn list_range_rev<O: AsRef<ExId>, R: RangeBounds<usize>>(
+        &self,
+        obj: O,
+        range: R,
+    ) -> ListRangeRev<'_> {
+        self.doc.list_range_rev_at(obj, range, self.heads)
+    }
+
+    fn list_range_rev_at<O: AsRef<ExId>, R: RangeBounds<usize>>(
+        &self,
+        obj: O,
+        range: R,
+        heads: &[ChangeHash],
+    ) -> ListRangeRev<'_> {
+        self.doc.list_range_rev_at(obj, range, heads)
+    }
+
+    fn read_bytes<O: AsRef<ExId>, R: RangeBounds<usize>>(
+        &self,
+        obj: O,
+        range: R,
+    ) -> Result<Vec<u8>, AutomergeError> {
+        self.doc
+            .read_bytes_for(obj.as_ref(), range, Some(self.doc.clock_at(self.heads)))
+    }
+
     fn values<O: AsRef<ExId>>(&self, obj: O) -> Values<'_> {
         self.doc.values_at(obj, self.heads)
     }
@@ -381,10 +487,37 @@ impl<'a, 'b> ReadDoc for ReadDocAt<'a, 'b> {
         self.doc.values_at(obj, heads)
     }
 
+    fn entries<O: AsRef<ExId>>(&self, obj: O) -> Entries<'_> {
+        self.doc.entries_at(obj, self.heads)
+    }
+
+    fn entries_at<O: AsRef<ExId>>(&self, obj: O, heads: &[ChangeHash]) -> Entries<'_> {
+        self.doc.entries_at(obj, heads)
+    }
+
+    fn values_ref<O: AsRef<ExId>>(&self, obj: O) -> ValuesRef<'_> {
+        let clock = self.doc.clock_at(self.heads);
+        self.doc.values_ref_for(obj.as_ref(), Some(clock))
+    }
+
+    fn objects(&self) -> Objects<'_> {
+        Objects::new(self.doc, Some(self.doc.clock_at(self.heads)))
+    }
+
     fn length<O: AsRef<ExId>>(&self, obj: O) -> usize {
         self.doc.length_at(obj, self.heads)
     }
 
+    fn length_utf16<O: AsRef<ExId>>(&self, obj: O) -> usize {
+        self.doc
+            .length_utf16_for(obj.as_ref(), Some(self.doc.clock_at(self.heads)))
+    }
+
+    fn length_grapheme<O: AsRef<ExId>>(&self, obj: O) -> usize {
+        self.doc
+            .length_grapheme_for(obj.as_ref(), Some(self.doc.clock_at(self.heads)))
+    }
+
     fn length_at<O: AsRef<ExId>>(&self, obj: O, heads: &[ChangeHash]) -> usize {
         self.doc.length_at(obj, heads)
     }
@@ -393,6 +526,14 @@ impl<'a, 'b> ReadDoc for ReadDocAt<'a, 'b> {
         self.doc.object_type(obj)
     }
 
+    fn object_type_at<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        heads: &[ChangeHash],
+    ) -> Result<Option<ObjType>, AutomergeError> {
+        self.doc.object_type_at(obj, heads)
+    }
+
     fn text<O: AsRef<ExId>>(&self, obj: O) -> Result<String, AutomergeError> {
         self.doc.text_at(obj, self.heads)
     }
@@ -417,6 +558,24 @@ impl<'a, 'b> ReadDoc for ReadDocAt<'a, 'b> {
         self.doc.marks_at(obj, heads)
     }
 
+    fn all_marks(&self) -> AllMarks<'_> {
+        AllMarks::new(self.doc, Some(self.doc.clock_at(self.heads)))
+    }
+
+    fn all_marks_at(&self, heads: &[ChangeHash]) -> AllMarks<'_> {
+        self.doc.all_marks_at(heads)
+    }
+
+    fn marks_in_range<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        range: Range<usize>,
+        heads: Option<&[ChangeHash]>,
+    ) -> Result<Vec<Mark<'_>>, AutomergeError> {
+        self.doc
+            .marks_in_range(obj, range, Some(heads.unwrap_or(self.heads)))
+    }
+
     fn get_marks<O: AsRef<ExId>>(
         &self,
         obj: O,
@@ -427,6 +586,30 @@ impl<'a, 'b> ReadDoc for ReadDocAt<'a, 'b> {
             .get_marks(obj, index, Some(heads.unwrap_or(self.heads)))
     }
 
+    fn text_at_cursor<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        cursor: &Cursor,
+        heads: Option<&[ChangeHash]>,
+    ) -> Result<MarkSet, AutomergeError> {
+        self.doc
+            .text_at_cursor(obj, cursor, Some(heads.unwrap_or(self.heads)))
+    }
+
+    fn import_exid(&self, s: &str) -> Result<ExId, AutomergeError> {
+        self.doc.import_exid(s)
+    }
+
+    fn char_to_utf16<O: AsRef<ExId>>(&self, obj: O, index: usize) -> Result<usize, AutomergeError> {
+        self.doc
+            .char_to_utf16_for(obj.as_ref(), index, Some(self.doc.clock_at(self.heads)))
+    }
+
+    fn utf16_to_char<O: AsRef<ExId>>(&self, obj: O, index: usize) -> Result<usize, AutomergeError> {
+        self.doc
+            .utf16_to_char_for(obj.as_ref(), index, Some(self.doc.clock_at(self.heads)))
+    }
+
     fn get_cursor<O: AsRef<ExId>>(
         &self,
         obj: O,
@@ -445,6 +628,78 @@ impl<'a, 'b> ReadDoc for ReadDocAt<'a, 'b> {
         self.doc.get_cursor_position(obj, cursor, at)
     }
 
+    fn get_cursor_positions<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        cursors: &[Cursor],
+        at: Option<&[ChangeHash]>,
+    ) -> Result<Vec<usize>, AutomergeError> {
+        self.doc.get_cursor_positions(obj, cursors, at)
+    }
+
+    fn get_cursor_position_and_value<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        cursor: &Cursor,
+        at: Option<&[ChangeHash]>,
+    ) -> Result<(usize, Option<Value<'_>>), AutomergeError> {
+        self.doc.get_cursor_position_and_value(obj, cursor, at)
+    }
+
+    fn index_of<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        elem: &ExId,
+        at: Option<&[ChangeHash]>,
+    ) -> Result<Option<usize>, AutomergeError> {
+        self.doc.index_of(obj, elem, at)
+    }
+
+    fn element_token<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        index: usize,
+    ) -> Result<String, AutomergeError> {
+        self.doc
+            .element_token_for(obj.as_ref(), index, Some(self.doc.clock_at(self.heads)))
+    }
+
+    fn index_of_token<O: AsRef<ExId>>(&self, obj: O, token: &str) -> Result<usize, AutomergeError> {
+        self.doc
+            .index_of_token_for(obj.as_ref(), token, Some(self.doc.clock_at(self.heads)))
+    }
+
+    fn value_provenance<O: AsRef<ExId>, P: Into<Prop>>(
+        &self,
+        obj: O,
+        prop: P,
+    ) -> Result<Option<ChangeHash>, AutomergeError> {
+        self.doc.value_provenance_for(
+            obj.as_ref(),
+            prop.into(),
+            Some(self.doc.clock_at(self.heads)),
+        )
+    }
+
+    fn map_contains_key<O: AsRef<ExId>>(&self, obj: O, key: &str) -> Result<bool, AutomergeError> {
+        self.doc
+            .map_contains_key_for(obj.as_ref(), key, Some(self.doc.clock_at(self.heads)))
+    }
+
+    fn list_contains_index<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        index: usize,
+    ) -> Result<bool, AutomergeError> {
+        self.doc
+            .list_contains_index_for(obj.as_ref(), index, Some(self.doc.clock_at(self.heads)))
+    }
+
+    fn visible_count_before<O: AsRef<ExId>>(&self, obj: O, op_pos: usize) -> usize {
+        self.doc
+            .visible_count_before_for(obj.as_ref(), op_pos, Some(self.doc.clock_at(self.heads)))
+    }
+
     fn get<O: AsRef<ExId>, P: Into<Prop>>(
         &self,
         obj: O,
@@ -479,6 +734,60 @@ impl<'a, 'b> ReadDoc for ReadDocAt<'a, 'b> {
         self.doc.get_all_at(obj, prop, heads)
     }
 
+    fn get_conflicts<O: AsRef<ExId>, P: Into<Prop>>(
+        &self,
+        obj: O,
+        prop: P,
+    ) -> Result<Vec<(ActorId, Value<'_>, ExId)>, AutomergeError> {
+        let clock = Some(self.doc.clock_at(self.heads));
+        self.doc.get_conflicts_for(obj.as_ref(), prop.into(), clock)
+    }
+
+    fn get_timestamp<O: AsRef<ExId>, P: Into<Prop>>(
+        &self,
+        obj: O,
+        prop: P,
+    ) -> Result<Option<i64>, AutomergeError> {
+        let clock = Some(self.doc.clock_at(self.heads));
+        self.doc.get_timestamp_for(obj.as_ref(), prop.into(), clock)
+    }
+
+    fn get_at_element_creation<O: AsRef<ExId>, P: Into<Prop>, L: AsRef<ExId>>(
+        &self,
+        obj: O,
+        prop: P,
+        list: L,
+        cursor: &Cursor,
+    ) -> Result<Option<(Value<'_>, ExId)>, AutomergeError> {
+        let clock = Some(self.doc.clock_at(self.heads));
+        self.doc.get_at_element_creation_for(
+            obj.as_ref(),
+            prop.into(),
+            list.as_ref(),
+            cursor,
+            clock,
+        )
+    }
+
+    fn get_by_timestamp<O: AsRef<ExId>, P: Into<Prop>>(
+        &self,
+        obj: O,
+        prop: P,
+    ) -> Result<Option<(Value<'_>, ExId)>, AutomergeError> {
+        let clock = self.doc.clock_at(self.heads);
+        self.doc
+            .get_by_timestamp_for(obj.as_ref(), prop.into(), Some(clock))
+    }
+
+    fn get_many_at<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        props: &[Prop],
+        heads: &[ChangeHash],
+    ) -> Result<Vec<(Prop, Option<(Value<'_>, ExId)>)>, AutomergeError> {
+        self.doc.get_many_at(obj, props, heads)
+    }
+
     fn parents<O: AsRef<ExId>>(&self, obj: O) -> Result<crate::Parents<'_>, AutomergeError> {
         self.doc.parents_at(obj, self.heads)
     }
@@ -499,6 +808,15 @@ impl<'a, 'b> ReadDoc for ReadDocAt<'a, 'b> {
         self.doc.get_change_by_hash(hash)
     }
 
+    fn text_diff<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        before: &[ChangeHash],
+        after: &[ChangeHash],
+    ) -> Result<Vec<TextEdit>, AutomergeError> {
+        self.doc.text_diff_for(obj.as_ref(), before, after)
+    }
+
     fn spans<O: AsRef<ExId>>(
         &self,
         obj: O,
@@ -514,6 +832,26 @@ impl<'a, 'b> ReadDoc for ReadDocAt<'a, 'b> {
         self.doc.spans_at(obj, heads)
     }
 
+    fn spans_range<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        range: std::ops::Range<usize>,
+        heads: Option<&[ChangeHash]>,
+    ) -> Result<crate::iter::Spans<'_>, crate::AutomergeError> {
+        self.doc
+            .spans_range(obj, range, Some(heads.unwrap_or(self.heads)))
+    }
+
+    fn export_rich_text<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        range: std::ops::Range<usize>,
+        heads: Option<&[ChangeHash]>,
+    ) -> Result<crate::marks::RichText, crate::AutomergeError> {
+        self.doc
+            .export_rich_text(obj, range, Some(heads.unwrap_or(self.heads)))
+    }
+
     fn hydrate<O: AsRef<ExId>>(
         &self,
         obj: O,
@@ -525,6 +863,24 @@ impl<'a, 'b> ReadDoc for ReadDocAt<'a, 'b> {
     fn stats(&self) -> crate::read::Stats {
         self.doc.stats()
     }
+
+    fn object_stats<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+    ) -> Result<crate::read::ObjStats, crate::AutomergeError> {
+        let clock = self.doc.clock_at(self.heads);
+        self.doc.object_stats_for(obj.as_ref(), Some(clock))
+    }
+
+    fn tombstone_count<O: AsRef<ExId>>(&self, obj: O) -> Result<usize, crate::AutomergeError> {
+        let clock = self.doc.clock_at(self.heads);
+        self.doc.tombstone_count_for(obj.as_ref(), Some(clock))
+    }
+
+    fn total_tombstones(&self) -> usize {
+        self.doc
+            .total_tombstones_for(Some(self.doc.clock_at(self.heads)))
+    }
 }
 
 impl<'a, 'b> ReadDocInternal for ReadDocAt<'a, 'b> {
@@ -612,7 +968,7 @@ mod tests {
                     },
                     path: ex_path_and(path, index),
                 },
-                PatchAction::DeleteMap { key } => ObservedPatch {
+                PatchAction::DeleteMap { key, .. } => ObservedPatch {
                     action: ObservedAction::DelMap,
                     path: ex_path_and(path, key),
                 },
@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+
+use crate::exid::ExId;
+use crate::op_set::{Op, OpSet};
+use crate::types::Key;
+use crate::AutomergeError;
+
+use super::Automerge;
+
+/// The result of [`Automerge::verify_integrity()`]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct IntegrityReport {
+    /// Objects whose operations don't match the operations obtained by applying the document's
+    /// changes to an empty document. This includes objects which only exist in one of the two.
+    pub mismatched_objects: Vec<ExId>,
+    /// Pairs of `(op, pred)` where `pred` is recorded as a predecessor of `op` but is not an
+    /// earlier operation on the same property of the same object
+    pub invalid_preds: Vec<(ExId, ExId)>,
+}
+
+impl IntegrityReport {
+    /// Whether no problems were found
+    pub fn is_ok(&self) -> bool {
+        self.mismatched_objects.is_empty() && self.invalid_preds.is_empty()
+    }
+}
+
+/// An op with all the document specific indices (actors, props) resolved so ops from different
+/// documents can be compared
+#[derive(Debug, PartialEq)]
+struct ResolvedOp {
+    id: ExId,
+    key: ResolvedKey,
+    insert: bool,
+    action: String,
+    pred: Vec<ExId>,
+    succ: Vec<ExId>,
+}
+
+#[derive(Debug, PartialEq)]
+enum ResolvedKey {
+    Map(String),
+    Seq(Option<ExId>),
+}
+
+impl ResolvedOp {
+    fn new(ops: &OpSet, op: Op<'_>) -> Self {
+        let key = match op.key() {
+            Key::Map(n) => ResolvedKey::Map(ops.osd.props[*n].clone()),
+            Key::Seq(e) if e.is_head() => ResolvedKey::Seq(None),
+            Key::Seq(e) => ResolvedKey::Seq(Some(ops.id_to_exid(e.0))),
+        };
+        ResolvedOp {
+            id: op.exid(),
+            key,
+            insert: op.insert(),
+            action: format!("{:?}", op.action()),
+            pred: op.pred().map(|p| p.exid()).collect(),
+            succ: op.succ().map(|s| s.exid()).collect(),
+        }
+    }
+}
+
+fn resolved_objects(doc: &Automerge) -> HashMap<ExId, Vec<ResolvedOp>> {
+    let spilled = doc.ops.rebuild_spilled();
+    doc.ops
+        .iter_objs(&spilled)
+        .map(|(obj, ops)| {
+            let ops = ops.map(|op| ResolvedOp::new(&doc.ops, op)).collect();
+            (doc.ops.id_to_exid(obj.id.0), ops)
+        })
+        .collect()
+}
+
+impl Automerge {
+    /// Check that the operations in this document are consistent with its changes
+    ///
+    /// This rebuilds the document from its changes and compares every object with the live
+    /// document, and checks that every operation's predecessors are earlier operations on the same
+    /// property. This is expensive, it is meant for occasional checks, e.g. after loading a
+    /// document from storage which may have been corrupted.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error from rebuilding the document if the changes don't apply cleanly, or the
+    /// snapshot left by [`Self::trim_changes()`] can't be loaded
+    pub fn verify_integrity(&self) -> Result<IntegrityReport, AutomergeError> {
+        let mut report = IntegrityReport::default();
+
+        // the changes removed by `trim_changes` are only in the snapshot of the trimmed history
+        let mut rebuilt = match &self.snapshot {
+            Some(snapshot) => Automerge::load(&snapshot.bytes)?,
+            None => Automerge::new(),
+        };
+        rebuilt.apply_changes(self.history.iter().filter_map(|e| e.change()).cloned())?;
+        let mut expected = resolved_objects(&rebuilt);
+        for (obj, ops) in resolved_objects(self) {
+            if expected.remove(&obj).as_ref() != Some(&ops) {
+                report.mismatched_objects.push(obj);
+            }
+        }
+        report.mismatched_objects.extend(expected.into_keys());
+        report.mismatched_objects.sort();
+
+        let spilled = self.ops.rebuild_spilled();
+        for (_, ops) in self.ops.iter_objs(&spilled) {
+            for op in ops {
+                for pred in op.pred() {
+                    let valid = pred.obj() == op.obj()
+                        && pred.elemid_or_key() == op.elemid_or_key()
+                        && pred.lamport_cmp(*op.id()).is_lt()
+                        && pred.succ().any(|s| s.id() == op.id());
+                    if !valid {
+                        report.invalid_preds.push((op.exid(), pred.exid()));
+                    }
+                }
+            }
+        }
+        Ok(report)
+    }
+}
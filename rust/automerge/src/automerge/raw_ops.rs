@@ -0,0 +1,320 @@
+use std::num::NonZeroU64;
+
+use crate::exid::ExId;
+use crate::legacy;
+use crate::types::{Key, ListEncoding, ObjId, OpId, OpType};
+use crate::{ActorId, AutomergeError, Change, ChangeHash, ExpandedChange, ObjType, ScalarValue};
+
+use super::Automerge;
+
+/// An operation constructed directly rather than through
+/// [`crate::transaction::Transactable`], see [`Automerge::apply_raw_ops()`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct RawOp {
+    /// The object the operation modifies
+    pub obj: ExId,
+    /// The property of `obj` the operation modifies
+    pub key: RawKey,
+    /// Whether this creates a new element in a sequence, inserted after `key`
+    pub insert: bool,
+    pub action: RawAction,
+    /// The operations this operation overwrites. These must be operations on the same property
+    /// of the same object.
+    pub pred: Vec<ExId>,
+}
+
+/// The property a [`RawOp`] modifies
+#[derive(Debug, Clone, PartialEq)]
+pub enum RawKey {
+    /// A key in a map
+    Map(String),
+    /// The start of a sequence, only valid for inserts
+    Head,
+    /// The element of a sequence created by the op with this id
+    Elem(ExId),
+}
+
+/// What a [`RawOp`] does
+#[derive(Debug, Clone, PartialEq)]
+pub enum RawAction {
+    /// Create a new object
+    Make(ObjType),
+    /// Set a scalar value
+    Put(ScalarValue),
+    /// Delete the values in `pred`
+    Delete,
+    /// Increment the counters in `pred`
+    Increment(i64),
+}
+
+/// What [`Automerge::apply_raw_ops()`] needs to know about an op earlier in the same batch
+struct BatchOp {
+    obj: ExId,
+    /// [`RawKey::Head`] for any insert, whatever it was inserted after
+    key: RawKey,
+    action: RawAction,
+}
+
+/// The property an op modifies, in a form which can be compared between ops in the document and
+/// ops in the batch
+#[derive(PartialEq)]
+enum ResolvedKey {
+    Map(String),
+    Elem(ExId),
+}
+
+impl Automerge {
+    /// The id the op at position `index` of the next call to [`Self::apply_raw_ops()`] with
+    /// `actor` will get
+    ///
+    /// Use this to refer to objects and elements created earlier in the same batch of ops.
+    pub fn raw_op_id(&self, actor: &ActorId, index: usize) -> ExId {
+        let actor_index = self.ops.osd.actors.lookup(actor).unwrap_or(usize::MAX);
+        ExId::Id(self.max_op + 1 + index as u64, actor.clone(), actor_index)
+    }
+
+    /// Apply operations constructed by hand as a single new change by `actor`
+    ///
+    /// This is an extension point for tools which translate edits from another system into
+    /// automerge and so need to choose the exact operations, for example to reproduce the
+    /// structure of the source document. Most applications should use
+    /// [`crate::transaction::Transactable`] instead, which can't produce invalid operations.
+    ///
+    /// The change depends on the current heads of the document and the operations are numbered
+    /// consecutively, use [`Self::raw_op_id()`] to refer to an op earlier in `ops`. Before
+    /// anything is applied every op is checked:
+    ///
+    /// * `obj` must be an object in the document or one created earlier in `ops`
+    /// * `key` must be [`RawKey::Map`] for maps and tables and [`RawKey::Head`] or
+    ///   [`RawKey::Elem`] for lists and text, in which case it must be an element of `obj`
+    /// * [`RawKey::Head`] and `insert` are only allowed together, and an insert can't overwrite
+    ///   anything
+    /// * every op in `pred` must be an earlier op on the same property of the same object, and
+    ///   deletes and increments must overwrite something
+    /// * increments must only overwrite counters
+    ///
+    /// ```
+    /// # use automerge::{Automerge, ActorId, ObjType, ReadDoc, ScalarValue, ROOT};
+    /// # use automerge::{RawAction, RawKey, RawOp};
+    /// let mut doc = Automerge::new();
+    /// let actor = ActorId::random();
+    /// let list = doc.raw_op_id(&actor, 0);
+    /// let ops = vec![
+    ///     RawOp {
+    ///         obj: ROOT,
+    ///         key: RawKey::Map("list".to_string()),
+    ///         insert: false,
+    ///         action: RawAction::Make(ObjType::List),
+    ///         pred: vec![],
+    ///     },
+    ///     RawOp {
+    ///         obj: list.clone(),
+    ///         key: RawKey::Head,
+    ///         insert: true,
+    ///         action: RawAction::Put(ScalarValue::Str("a".into())),
+    ///         pred: vec![],
+    ///     },
+    /// ];
+    /// doc.apply_raw_ops(actor, ops).unwrap();
+    /// let (_, list) = doc.get(ROOT, "list").unwrap().unwrap();
+    /// assert_eq!(doc.length(&list), 1);
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// * [`AutomergeError::InvalidRawOp`] with the position of the first op which fails the
+    ///   checks above. Nothing is applied in this case.
+    /// * Any error [`Self::apply_changes()`] returns for the resulting change
+    pub fn apply_raw_ops(
+        &mut self,
+        actor: ActorId,
+        ops: Vec<RawOp>,
+    ) -> Result<ChangeHash, AutomergeError> {
+        let start_op = self.max_op + 1;
+        let mut batch: Vec<BatchOp> = Vec::with_capacity(ops.len());
+        let mut operations = Vec::with_capacity(ops.len());
+        for (index, op) in ops.into_iter().enumerate() {
+            let invalid = |reason| AutomergeError::InvalidRawOp(index, reason);
+            let batch_index = |id: &ExId| match id {
+                ExId::Id(ctr, a, _) if *a == actor && *ctr >= start_op => {
+                    Some((*ctr - start_op) as usize)
+                }
+                _ => None,
+            };
+
+            let obj_type = match batch_index(&op.obj) {
+                Some(i) => match batch.get(i) {
+                    Some(BatchOp {
+                        action: RawAction::Make(typ),
+                        ..
+                    }) => *typ,
+                    _ => return Err(invalid("obj is not an earlier op which makes an object")),
+                },
+                None => {
+                    self.exid_to_obj(&op.obj)
+                        .map_err(|_| invalid("obj is not an object in the document"))?
+                        .typ
+                }
+            };
+            let seq = obj_type.is_sequence();
+            match (&op.key, seq) {
+                (RawKey::Map(_), false) | (RawKey::Head | RawKey::Elem(_), true) => {}
+                (RawKey::Map(_), true) => return Err(invalid("map key in a sequence")),
+                (_, false) => return Err(invalid("sequence key in a map")),
+            }
+            if op.insert && !seq {
+                return Err(invalid("insert into a map"));
+            }
+            if op.key == RawKey::Head && !op.insert {
+                return Err(invalid("head key without insert"));
+            }
+            if op.insert && !op.pred.is_empty() {
+                return Err(invalid("insert with pred"));
+            }
+            if let RawKey::Elem(elem) = &op.key {
+                let found = match batch_index(elem) {
+                    Some(i) => batch.get(i).map(|b| b.obj == op.obj && b.is_insert()),
+                    None => self.doc_op(&op.obj, elem).map(|doc_op| doc_op.insert),
+                };
+                if found != Some(true) {
+                    return Err(invalid("key is not an element of obj"));
+                }
+            }
+
+            let key = match &op.key {
+                RawKey::Map(k) => ResolvedKey::Map(k.clone()),
+                RawKey::Elem(e) => ResolvedKey::Elem(e.clone()),
+                RawKey::Head => ResolvedKey::Elem(self.raw_op_id(&actor, index)),
+            };
+            let mut pred = Vec::with_capacity(op.pred.len());
+            for p in &op.pred {
+                let (pred_key, is_counter) = match batch_index(p) {
+                    Some(i) if i < batch.len() && batch[i].obj == op.obj => {
+                        let b = &batch[i];
+                        let key = match &b.key {
+                            RawKey::Map(k) => ResolvedKey::Map(k.clone()),
+                            RawKey::Elem(e) => ResolvedKey::Elem(e.clone()),
+                            RawKey::Head => ResolvedKey::Elem(self.raw_op_id(&actor, i)),
+                        };
+                        let counter = matches!(b.action, RawAction::Put(ScalarValue::Counter(_)));
+                        (key, counter)
+                    }
+                    Some(_) => return Err(invalid("pred is not an earlier op on obj")),
+                    None => match self.doc_op(&op.obj, p) {
+                        Some(doc_op) => (doc_op.key, doc_op.counter),
+                        None => return Err(invalid("pred is not an op on obj")),
+                    },
+                };
+                if pred_key != key {
+                    return Err(invalid("pred is an op on a different property"));
+                }
+                if matches!(op.action, RawAction::Increment(_)) && !is_counter {
+                    return Err(invalid("increment of something which isn't a counter"));
+                }
+                let ExId::Id(ctr, pred_actor, _) = p else {
+                    return Err(invalid("pred is not an op on obj"));
+                };
+                pred.push(legacy::OpId(*ctr, pred_actor.clone()));
+            }
+            if pred.len() != pred.iter().collect::<std::collections::HashSet<_>>().len() {
+                return Err(invalid("duplicate pred"));
+            }
+            if matches!(op.action, RawAction::Delete | RawAction::Increment(_)) && pred.is_empty() {
+                return Err(invalid("delete or increment without pred"));
+            }
+
+            operations.push(legacy::Op {
+                action: match &op.action {
+                    RawAction::Make(typ) => legacy::OpType::Make(*typ),
+                    RawAction::Put(value) => legacy::OpType::Put(value.clone()),
+                    RawAction::Delete => legacy::OpType::Delete,
+                    RawAction::Increment(by) => legacy::OpType::Increment(*by),
+                },
+                obj: legacy_obj(&op.obj),
+                key: match &op.key {
+                    RawKey::Map(k) => legacy::Key::Map(k.into()),
+                    RawKey::Head => legacy::Key::Seq(legacy::ElementId::Head),
+                    RawKey::Elem(e) => legacy::Key::Seq(match legacy_obj(e) {
+                        legacy::ObjectId::Id(id) => legacy::ElementId::Id(id),
+                        legacy::ObjectId::Root => legacy::ElementId::Head,
+                    }),
+                },
+                pred: pred.into(),
+                insert: op.insert,
+            });
+            batch.push(BatchOp {
+                obj: op.obj,
+                key: if op.insert { RawKey::Head } else { op.key },
+                action: op.action,
+            });
+        }
+
+        let actor_index = self.ops.osd.actors.lookup(&actor);
+        let seq = actor_index
+            .and_then(|i| self.states.get(&i))
+            .map_or(0, |v| v.len()) as u64
+            + 1;
+        let mut deps = self.get_heads();
+        if let Some(last) = actor_index.and_then(|i| self.get_hash(i, seq - 1).ok()) {
+            if !deps.contains(&last) {
+                deps.push(last);
+            }
+        }
+        let change = Change::from(ExpandedChange {
+            operations,
+            actor_id: actor,
+            hash: None,
+            seq,
+            // SAFETY: max_op + 1 is never zero
+            start_op: NonZeroU64::new(start_op).unwrap(),
+            time: 0,
+            message: None,
+            deps,
+            extra_bytes: Vec::new(),
+        });
+        let hash = change.hash();
+        self.apply_changes([change])?;
+        Ok(hash)
+    }
+
+    /// The op `id` in the document, if it is an op on `obj`
+    fn doc_op(&self, obj: &ExId, id: &ExId) -> Option<DocOp> {
+        let obj = ObjId(self.exid_to_opid(obj).ok()?);
+        let id: OpId = self.exid_to_opid(id).ok()?;
+        let found = self
+            .ops
+            .seek_list_opid(&obj, id, ListEncoding::List, None)?;
+        let op = found.op;
+        if op.id() != &id {
+            return None;
+        }
+        let key = match op.elemid_or_key() {
+            Key::Map(prop) => ResolvedKey::Map(self.ops.osd.props[prop].clone()),
+            Key::Seq(elem) => ResolvedKey::Elem(self.id_to_exid(elem.0)),
+        };
+        Some(DocOp {
+            key,
+            insert: op.insert(),
+            counter: matches!(op.action(), OpType::Put(ScalarValue::Counter(_))),
+        })
+    }
+}
+
+struct DocOp {
+    key: ResolvedKey,
+    insert: bool,
+    counter: bool,
+}
+
+impl BatchOp {
+    fn is_insert(&self) -> bool {
+        self.key == RawKey::Head
+    }
+}
+
+fn legacy_obj(id: &ExId) -> legacy::ObjectId {
+    match id {
+        ExId::Root => legacy::ObjectId::Root,
+        ExId::Id(ctr, actor, _) => legacy::ObjectId::Id(legacy::OpId(*ctr, actor.clone())),
+    }
+}
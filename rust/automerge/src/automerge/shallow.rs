@@ -0,0 +1,189 @@
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::columnar::Key as DocOpKey;
+use crate::exid::ExId;
+use crate::storage::{self, load, parse};
+use crate::types::{OpId, OpType};
+use crate::{AutomergeError, ReadDoc, ScalarValue, Value, ROOT};
+
+use super::Automerge;
+
+/// A document of which only the keys of the root map have been read, see
+/// [`Automerge::load_shallow()`]
+#[derive(Debug, Clone)]
+pub struct ShallowDoc {
+    bytes: Vec<u8>,
+    root: BTreeMap<String, (Value<'static>, ExId)>,
+    doc: Option<Automerge>,
+}
+
+impl Automerge {
+    /// Read the keys of the root map of a saved document without loading the rest of it
+    ///
+    /// A document saved with [`Self::save()`] stores the operations of the root map before those
+    /// of any other object, so the current value of each key of the root map can be worked out
+    /// from the first few operations. Keys whose value is an object report only the type and id
+    /// of the object. Use [`ShallowDoc::document()`] to read anything deeper. That loads the
+    /// whole document from the bytes the [`ShallowDoc`] keeps, there is no way to load one object
+    /// on its own, so reading one value below the root costs as much as [`Self::load()`].
+    ///
+    /// If `data` is not a single document chunk, for example because changes were appended to it
+    /// with [`Self::save_after()`] or [`crate::AutoCommit::save_incremental()`], the root map
+    /// can't be read on its own, so the whole document is loaded straight away.
+    ///
+    /// ```
+    /// # use automerge::{transaction::Transactable, AutoCommit, Automerge, ObjType, ReadDoc};
+    /// # use automerge::{Value, ROOT};
+    /// let mut doc = AutoCommit::new();
+    /// doc.put(ROOT, "title", "notes").unwrap();
+    /// let list = doc.put_object(ROOT, "items", ObjType::List).unwrap();
+    /// doc.insert(&list, 0, "milk").unwrap();
+    ///
+    /// let mut shallow = Automerge::load_shallow(&doc.save()).unwrap();
+    /// assert_eq!(shallow.keys().collect::<Vec<_>>(), vec!["items", "title"]);
+    /// assert_eq!(shallow.get("items"), Some((Value::Object(ObjType::List), list.clone())));
+    /// assert!(!shallow.is_materialized());
+    ///
+    /// assert_eq!(shallow.document().unwrap().length(&list), 1);
+    /// assert!(shallow.is_materialized());
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns any error [`Self::load()`] returns if `data` is malformed. The operations after
+    /// those of the root map, and the hashes of the changes, aren't checked until the document
+    /// is materialized.
+    pub fn load_shallow(data: &[u8]) -> Result<ShallowDoc, AutomergeError> {
+        let doc = match storage::Chunk::parse(parse::Input::new(data)) {
+            Ok((remaining, storage::Chunk::Document(d))) if remaining.is_empty() => d,
+            _ => {
+                let doc = Self::load(data)?;
+                let root = doc
+                    .map_range(ROOT, ..)
+                    .map(|item| (item.key.to_string(), (item.value.into_owned(), item.id)))
+                    .collect();
+                return Ok(ShallowDoc {
+                    bytes: data.to_vec(),
+                    root,
+                    doc: Some(doc),
+                });
+            }
+        };
+        if !doc.checksum_valid() {
+            return Err(load::Error::BadChecksum.into());
+        }
+        let exid = |id: OpId| {
+            let actor = doc.actors().get(id.actor()).ok_or_else(|| {
+                load::Error::InvalidOpsColumns("an operation referenced a missing actor id".into())
+            })?;
+            Ok::<_, AutomergeError>(ExId::Id(id.counter(), actor.clone(), id.actor()))
+        };
+
+        // ops on the root map in (key, id) order and the value of every increment of them
+        let mut ops = Vec::new();
+        let mut increments = HashMap::new();
+        for op in doc.iter_ops() {
+            let op = op.map_err(|e| load::Error::InvalidOpsColumns(Box::new(e)))?;
+            if !op.object.is_root() {
+                break;
+            }
+            OpType::validate_action_and_value(op.action, &op.value)
+                .map_err(|e| load::Error::InvalidOpsColumns(Box::new(e)))?;
+            match OpType::from_action_and_value(op.action, op.value, op.mark_name, op.expand) {
+                OpType::Increment(by) => {
+                    increments.insert(op.id, by);
+                }
+                OpType::Delete | OpType::MarkBegin(..) | OpType::MarkEnd(_) => {}
+                action => {
+                    if let DocOpKey::Prop(key) = op.key {
+                        ops.push((key, op.id, action, op.succ));
+                    }
+                }
+            }
+        }
+
+        // an op is visible if it has only been incremented, and the last visible op on a key
+        // wins
+        let mut root = BTreeMap::new();
+        for (key, id, action, succ) in ops {
+            if !succ.iter().all(|s| increments.contains_key(s)) {
+                continue;
+            }
+            let value = match action {
+                OpType::Make(obj_type) => Value::Object(obj_type),
+                OpType::Put(mut value) => {
+                    if let ScalarValue::Counter(counter) = &mut value {
+                        for by in succ.iter().filter_map(|s| increments.get(s)) {
+                            counter.increment(*by);
+                        }
+                    }
+                    Value::Scalar(Cow::Owned(value))
+                }
+                _ => continue,
+            };
+            root.insert(key.to_string(), (value, exid(id)?));
+        }
+        Ok(ShallowDoc {
+            bytes: data.to_vec(),
+            root,
+            doc: None,
+        })
+    }
+}
+
+impl ShallowDoc {
+    /// The keys of the root map, in order
+    pub fn keys(&self) -> impl Iterator<Item = &str> + '_ {
+        self.root.keys().map(|k| k.as_str())
+    }
+
+    /// The number of keys in the root map
+    pub fn len(&self) -> usize {
+        self.root.len()
+    }
+
+    /// Whether the root map is empty
+    pub fn is_empty(&self) -> bool {
+        self.root.is_empty()
+    }
+
+    /// The value of `key` in the root map, as [`ReadDoc::get()`] would return it
+    ///
+    /// An object is returned as [`Value::Object`] with the id of the object, which can be used
+    /// with the [`Automerge`] returned by [`Self::document()`].
+    pub fn get(&self, key: &str) -> Option<(Value<'static>, ExId)> {
+        self.root.get(key).cloned()
+    }
+
+    /// Whether the whole document has been loaded
+    pub fn is_materialized(&self) -> bool {
+        self.doc.is_some()
+    }
+
+    /// The whole document, loading it the first time this is called
+    ///
+    /// This loads every object, not only the one being read, see [`Automerge::load_shallow()`].
+    ///
+    /// # Errors
+    ///
+    /// Returns any error [`Automerge::load()`] returns for the bytes this was loaded from
+    pub fn document(&mut self) -> Result<&Automerge, AutomergeError> {
+        if self.doc.is_none() {
+            self.doc = Some(Automerge::load(&self.bytes)?);
+        }
+        Ok(self.doc.as_ref().unwrap())
+    }
+
+    /// The whole document, loading it if [`Self::document()`] hasn't been called
+    ///
+    /// # Errors
+    ///
+    /// Returns any error [`Automerge::load()`] returns for the bytes this was loaded from
+    pub fn into_document(self) -> Result<Automerge, AutomergeError> {
+        match self.doc {
+            Some(doc) => Ok(doc),
+            None => Automerge::load(&self.bytes),
+        }
+    }
+}
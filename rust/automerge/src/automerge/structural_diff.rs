@@ -0,0 +1,155 @@
+use crate::hydrate;
+use crate::Prop;
+
+use super::Automerge;
+
+/// A difference between the values of two documents at the same path, see
+/// [`Automerge::structural_diff()`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum StructuralDiff {
+    /// `path` has a value in the other document but not in this one
+    Added {
+        path: Vec<Prop>,
+        value: hydrate::Value,
+    },
+    /// `path` has a value in this document but not in the other one
+    Removed {
+        path: Vec<Prop>,
+        value: hydrate::Value,
+    },
+    /// `path` has different values in the two documents
+    Changed {
+        path: Vec<Prop>,
+        old: hydrate::Value,
+        new: hydrate::Value,
+    },
+}
+
+impl Automerge {
+    /// Compare the current values of this document and `other`, which needn't share any history
+    ///
+    /// This is a diff of values, not of CRDT operations. Objects created independently in two
+    /// documents have unrelated ids, so objects are matched by their path from the root instead:
+    ///
+    /// * maps are compared key by key, in key order
+    /// * lists are compared element by element at the same index, so inserting an element at the
+    ///   start of a list shows up as a change to every element followed by an addition at the
+    ///   end
+    /// * text is compared as a whole string and reported as a single [`StructuralDiff::Changed`]
+    /// * a value which is a different type, or an object of a different type, in the two
+    ///   documents is reported as [`StructuralDiff::Changed`] and not compared any further
+    ///
+    /// Conflicts are resolved as [`crate::ReadDoc::get()`] resolves them and marks are ignored.
+    /// Use [`Self::merge_preview()`] to find out what a merge of related documents would change.
+    ///
+    /// ```
+    /// # use automerge::{transaction::Transactable, AutoCommit, ObjType, Prop, ROOT};
+    /// # use automerge::StructuralDiff;
+    /// let mut ours = AutoCommit::new();
+    /// let list = ours.put_object(ROOT, "list", ObjType::List).unwrap();
+    /// ours.insert(&list, 0, "a").unwrap();
+    /// ours.put(ROOT, "title", "draft").unwrap();
+    ///
+    /// let mut theirs = AutoCommit::new();
+    /// let list = theirs.put_object(ROOT, "list", ObjType::List).unwrap();
+    /// theirs.insert(&list, 0, "a").unwrap();
+    /// theirs.insert(&list, 1, "b").unwrap();
+    ///
+    /// let diff = ours.document().structural_diff(theirs.document());
+    /// assert_eq!(
+    ///     diff,
+    ///     vec![
+    ///         StructuralDiff::Added {
+    ///             path: vec![Prop::from("list"), Prop::Seq(1)],
+    ///             value: "b".into(),
+    ///         },
+    ///         StructuralDiff::Removed {
+    ///             path: vec![Prop::from("title")],
+    ///             value: "draft".into(),
+    ///         },
+    ///     ]
+    /// );
+    /// ```
+    pub fn structural_diff(&self, other: &Automerge) -> Vec<StructuralDiff> {
+        let mut diffs = Vec::new();
+        diff_values(
+            &mut Vec::new(),
+            &self.hydrate(None),
+            &other.hydrate(None),
+            &mut diffs,
+        );
+        diffs
+    }
+}
+
+fn diff_values(
+    path: &mut Vec<Prop>,
+    old: &hydrate::Value,
+    new: &hydrate::Value,
+    diffs: &mut Vec<StructuralDiff>,
+) {
+    match (old, new) {
+        (hydrate::Value::Map(old), hydrate::Value::Map(new)) => {
+            let mut keys = old.keys().chain(new.keys()).collect::<Vec<_>>();
+            keys.sort_unstable();
+            keys.dedup();
+            for key in keys {
+                path.push(Prop::Map(key.clone()));
+                match (old.get(key), new.get(key)) {
+                    (Some(old), Some(new)) => diff_values(path, old, new, diffs),
+                    (Some(old), None) => diffs.push(StructuralDiff::Removed {
+                        path: path.clone(),
+                        value: old.clone(),
+                    }),
+                    (None, Some(new)) => diffs.push(StructuralDiff::Added {
+                        path: path.clone(),
+                        value: new.clone(),
+                    }),
+                    (None, None) => {}
+                }
+                path.pop();
+            }
+        }
+        (hydrate::Value::List(old), hydrate::Value::List(new)) => {
+            let mut old = old.iter();
+            let mut new = new.iter();
+            let mut index = 0;
+            loop {
+                path.push(Prop::Seq(index));
+                match (old.next(), new.next()) {
+                    (Some(old), Some(new)) => diff_values(path, &old.value, &new.value, diffs),
+                    (Some(old), None) => diffs.push(StructuralDiff::Removed {
+                        path: path.clone(),
+                        value: old.value.clone(),
+                    }),
+                    (None, Some(new)) => diffs.push(StructuralDiff::Added {
+                        path: path.clone(),
+                        value: new.value.clone(),
+                    }),
+                    (None, None) => {
+                        path.pop();
+                        break;
+                    }
+                }
+                path.pop();
+                index += 1;
+            }
+        }
+        (hydrate::Value::Text(old_text), hydrate::Value::Text(new_text)) => {
+            if old_text.to_string() != new_text.to_string() {
+                diffs.push(StructuralDiff::Changed {
+                    path: path.clone(),
+                    old: old.clone(),
+                    new: new.clone(),
+                });
+            }
+        }
+        (hydrate::Value::Scalar(old_scalar), hydrate::Value::Scalar(new_scalar))
+            if old_scalar == new_scalar => {}
+        _ => diffs.push(StructuralDiff::Changed {
+            path: path.clone(),
+            old: old.clone(),
+            new: new.clone(),
+        }),
+    }
+}
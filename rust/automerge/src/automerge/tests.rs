@@ -6,7 +6,9 @@ use crate::iter::*;
 use crate::op_tree::B;
 use crate::transaction::Transactable;
 use crate::*;
+use std::borrow::Cow;
 use std::convert::TryInto;
+use std::ops::ControlFlow;
 
 use test_log::test;
 
@@ -47,6 +49,102 @@ fn test_set() -> Result<(), AutomergeError> {
     Ok(())
 }
 
+#[test]
+fn test_put_if_absent() -> Result<(), AutomergeError> {
+    let mut doc = Automerge::new();
+    let mut tx = doc.transaction();
+    assert!(tx.put_if_absent(ROOT, "a", 1)?);
+    assert!(!tx.put_if_absent(ROOT, "a", 2)?);
+    assert_eq!(tx.pending_ops(), 1);
+    assert_eq!(tx.get(ROOT, "a")?.unwrap().0, Value::int(1));
+
+    // deleted keys are absent
+    tx.delete(ROOT, "a")?;
+    assert!(tx.put_if_absent(ROOT, "a", 3)?);
+    assert_eq!(tx.get(ROOT, "a")?.unwrap().0, Value::int(3));
+
+    let list = tx.put_object(ROOT, "list", ObjType::List)?;
+    tx.insert(&list, 0, "a")?;
+    assert!(!tx.put_if_absent(&list, 0, "b")?);
+    assert!(tx.put_if_absent(&list, 1, "b").is_err());
+    assert!(tx.put_if_absent(&list, "key", "b").is_err());
+    tx.commit();
+
+    let mut doc = AutoCommit::new();
+    assert!(doc.put_if_absent(ROOT, "a", 1)?);
+    assert!(!doc.put_if_absent(ROOT, "a", 2)?);
+    assert_eq!(doc.get(ROOT, "a")?.unwrap().0, Value::int(1));
+    Ok(())
+}
+
+#[test]
+fn test_path_ops() -> Result<(), AutomergeError> {
+    let mut doc = AutoCommit::new();
+    let config = doc.put_object(ROOT, "config", ObjType::Map)?;
+    let list = doc.put_object(&config, "servers", ObjType::List)?;
+    let server = doc.insert_object(&list, 0, ObjType::Map)?;
+    doc.put(&server, "requests", ScalarValue::counter(1))?;
+    doc.put(&server, "name", "a")?;
+
+    let path = |leaf: &str| {
+        vec![
+            Prop::from("config"),
+            Prop::from("servers"),
+            0.into(),
+            leaf.into(),
+        ]
+    };
+    doc.increment_path(&path("requests"), 5)?;
+    assert_eq!(doc.get(&server, "requests")?.unwrap().0, Value::counter(6));
+    doc.put_path(&path("name"), "b")?;
+    assert_eq!(doc.get(&server, "name")?.unwrap().0, Value::str("b"));
+    doc.delete_path(&path("name"))?;
+    assert_eq!(doc.get(&server, "name")?, None);
+
+    // the leaf isn't a counter
+    doc.put(&server, "name", "a")?;
+    assert!(matches!(
+        doc.increment_path(&path("name"), 1),
+        Err(AutomergeError::InvalidOp(ObjType::Map))
+    ));
+    // an intermediate prop isn't an object
+    let bad_path = [
+        Prop::from("config"),
+        "servers".into(),
+        0.into(),
+        "name".into(),
+        "x".into(),
+    ];
+    assert!(matches!(
+        doc.put_path(&bad_path, 1),
+        Err(AutomergeError::InvalidPath { index: 3 })
+    ));
+    // an intermediate prop is missing
+    assert!(matches!(
+        doc.delete_path(&[Prop::from("missing"), "x".into()]),
+        Err(AutomergeError::InvalidPath { index: 0 })
+    ));
+    assert!(matches!(
+        doc.put_path(
+            &[Prop::from("config"), "servers".into(), 1.into(), "x".into()],
+            1
+        ),
+        Err(AutomergeError::InvalidPath { index: 2 })
+    ));
+    assert!(matches!(
+        doc.put_path(&[], 1),
+        Err(AutomergeError::InvalidPath { index: 0 })
+    ));
+
+    let mut doc = Automerge::new();
+    let mut tx = doc.transaction();
+    tx.put_path(&["a".into()], ScalarValue::counter(1))?;
+    tx.increment_path(&["a".into()], 2)?;
+    tx.commit();
+    assert_eq!(doc.get(ROOT, "a")?.unwrap().0, Value::counter(3));
+    Ok(())
+}
+
 #[test]
 fn test_list() -> Result<(), AutomergeError> {
     let mut doc = Automerge::new();
@@ -242,6 +340,44 @@ fn test_cursors() -> Result<(), AutomergeError> {
     Ok(())
 }
 
+#[test]
+fn test_end_cursor() -> Result<(), AutomergeError> {
+    let mut doc = Automerge::new();
+    let mut tx = doc.transaction();
+    let text = tx.put_object(ROOT, "text", ObjType::Text)?;
+    tx.splice_text(&text, 0, 0, "hello")?;
+    tx.commit();
+    let heads0 = doc.get_heads();
+
+    // a cursor at the length of the sequence is the end cursor
+    let cursor = doc.get_cursor(&text, 5, None)?;
+    assert_eq!(cursor, Cursor::end());
+    assert!(cursor.is_end());
+    assert_eq!(
+        doc.get_cursor(&text, 6, None),
+        Err(AutomergeError::InvalidIndex(6))
+    );
+
+    // it keeps tracking the end as the sequence grows
+    let mut tx = doc.transaction();
+    tx.splice_text(&text, 5, 0, " world")?;
+    tx.commit();
+    assert_eq!(doc.get_cursor_position(&text, &cursor, None)?, 11);
+    assert_eq!(doc.get_cursor_position(&text, &cursor, Some(&heads0))?, 5);
+
+    // and round trips as an explicit end marker
+    assert_eq!(cursor.to_string(), "end");
+    assert_eq!(Cursor::try_from("end")?, cursor);
+    assert_eq!(Cursor::try_from(cursor.to_bytes())?, cursor);
+
+    assert_eq!(
+        doc.get_cursor_position(ROOT, &cursor, None),
+        Err(AutomergeError::InvalidOp(ObjType::Map))
+    );
+
+    Ok(())
+}
+
 #[test]
 fn test_props_vals_at() -> Result<(), AutomergeError> {
     let mut doc = Automerge::new();
@@ -1031,6 +1167,319 @@ fn get_list_values() -> Result<(), AutomergeError> {
     Ok(())
 }
 
+#[test]
+fn list_range_window_matches_full_iteration() -> Result<(), AutomergeError> {
+    let mut doc1 = Automerge::new();
+    let mut tx = doc1.transaction();
+    let list = tx.put_object(ROOT, "list", ObjType::List)?;
+    for i in 0..(B as i64 * 8) {
+        tx.insert(&list, i as usize, i)?;
+    }
+    for i in (0..B * 2).step_by(3) {
+        tx.delete(&list, i)?;
+    }
+    tx.commit();
+
+    let v1 = doc1.get_heads();
+    let mut doc2 = doc1.fork();
+    let mut tx = doc1.transaction();
+    tx.put(&list, B * 3, "doc1")?;
+    tx.commit();
+    let mut tx = doc2.transaction();
+    tx.put(&list, B * 3, "doc2")?;
+    tx.commit();
+    doc1.merge(&mut doc2)?;
+
+    let len = doc1.length(&list);
+    for (start, end) in [
+        (0, 5),
+        (B, B * 4),
+        (B * 3, B * 3 + 1),
+        (len - 3, len + 10),
+        (7, 3),
+        (2, usize::MAX),
+    ] {
+        let window = doc1
+            .list_range(&list, start..end)
+            .map(|i| (i.index, i.value, i.id, i.conflict))
+            .collect::<Vec<_>>();
+        let full = doc1
+            .list_range(&list, start..)
+            .take_while(|i| i.index < end)
+            .map(|i| (i.index, i.value, i.id, i.conflict))
+            .collect::<Vec<_>>();
+        assert_eq!(window, full);
+
+        let window = doc1
+            .list_range_at(&list, start..end, &v1)
+            .map(|i| (i.index, i.value, i.id))
+            .collect::<Vec<_>>();
+        let full = doc1
+            .list_range_at(&list, start.., &v1)
+            .take_while(|i| i.index < end)
+            .map(|i| (i.index, i.value, i.id))
+            .collect::<Vec<_>>();
+        assert_eq!(window, full);
+    }
+
+    let item = doc1.list_range(&list, B * 3..=B * 3).next().unwrap();
+    assert!(item.conflict);
+    assert_eq!(Some((item.value, item.id)), doc1.get(&list, B * 3)?);
+
+    Ok(())
+}
+
+#[test]
+fn get_list_value_by_actor() -> Result<(), AutomergeError> {
+    let actor1 = ActorId::from(b"aaaa");
+    let actor2 = ActorId::from(b"bbbb");
+    let mut doc1 = Automerge::new().with_actor(actor1.clone());
+    let mut tx = doc1.transaction();
+    let list = tx.put_object(ROOT, "list", ObjType::List)?;
+    tx.insert(&list, 0, "a")?;
+    tx.insert(&list, 1, "b")?;
+    tx.commit();
+
+    let mut doc2 = doc1.fork().with_actor(actor2.clone());
+    let mut tx = doc1.transaction();
+    tx.put(&list, 1, "b1")?;
+    tx.commit();
+    let before_merge = doc1.get_heads();
+    let mut tx = doc2.transaction();
+    tx.put(&list, 1, "b2")?;
+    tx.commit();
+    doc1.merge(&mut doc2)?;
+
+    // actor2 wins the conflict but we can still read what actor1 wrote
+    assert_eq!(doc1.get(&list, 1)?.unwrap().0, Value::str("b2"));
+    let (value, _) = doc1.get_by_actor(&list, 1, &actor1, None)?.unwrap();
+    assert_eq!(value, Value::str("b1"));
+    let (value, _) = doc1.get_by_actor(&list, 1, &actor2, None)?.unwrap();
+    assert_eq!(value, Value::str("b2"));
+
+    let (value, _) = doc1
+        .get_by_actor(&list, 1, &actor1, Some(&before_merge))?
+        .unwrap();
+    assert_eq!(value, Value::str("b1"));
+    assert_eq!(
+        doc1.get_by_actor(&list, 1, &actor2, Some(&before_merge))?,
+        None
+    );
+    assert_eq!(doc1.get_by_actor(&list, 0, &actor2, None)?, None);
+    assert_eq!(doc1.get_by_actor(&list, 0, &ActorId::random(), None)?, None);
+
+    // overwritten values are still found
+    let mut tx = doc1.transaction();
+    tx.put(&list, 0, "a2")?;
+    tx.commit();
+    let mut tx = doc2.transaction();
+    tx.put(&list, 0, "a3")?;
+    tx.commit();
+    doc1.merge(&mut doc2)?;
+    let mut tx = doc1.transaction();
+    tx.put(&list, 0, "a4")?;
+    tx.commit();
+    let (value, _) = doc1.get_by_actor(&list, 0, &actor2, None)?.unwrap();
+    assert_eq!(value, Value::str("a3"));
+    let (value, _) = doc1.get_by_actor(&list, 0, &actor1, None)?.unwrap();
+    assert_eq!(value, Value::str("a4"));
+
+    Ok(())
+}
+
+#[test]
+fn get_by_actor_finds_ops_split_across_nodes() -> Result<(), AutomergeError> {
+    let actor1 = ActorId::from(b"aaaa");
+    let actor2 = ActorId::from(b"bbbb");
+    let mut doc1 = Automerge::new().with_actor(actor1.clone());
+    let mut tx = doc1.transaction();
+    let list = tx.put_object(ROOT, "list", ObjType::List)?;
+    for i in 0..40 {
+        tx.insert(&list, i, i as i64)?;
+    }
+    tx.commit();
+
+    let mut doc2 = doc1.fork().with_actor(actor2.clone());
+    let mut tx = doc2.transaction();
+    tx.put(&list, 20, "actor2")?;
+    tx.commit();
+    doc1.merge(&mut doc2)?;
+
+    // enough overwrites that the ops for element 20 fill more than one node of the op tree, with
+    // actor2's op in an earlier node than the visible one
+    for i in 0..40 {
+        let mut tx = doc1.transaction();
+        tx.put(&list, 20, i as i64)?;
+        tx.commit();
+    }
+
+    assert_eq!(doc1.get(&list, 20)?.unwrap().0, Value::int(39));
+    let (value, _) = doc1.get_by_actor(&list, 20, &actor2, None)?.unwrap();
+    assert_eq!(value, Value::str("actor2"));
+    let (value, _) = doc1.get_by_actor(&list, 21, &actor1, None)?.unwrap();
+    assert_eq!(value, Value::int(21));
+
+    Ok(())
+}
+
+#[test]
+fn list_conflict_count() -> Result<(), AutomergeError> {
+    let mut doc1 = Automerge::new();
+    let mut tx = doc1.transaction();
+    let list = tx.put_object(ROOT, "list", ObjType::List)?;
+    for i in 0..(B * 2) {
+        tx.insert(&list, i, i as i64)?;
+    }
+    tx.commit();
+    let heads = doc1.get_heads();
+
+    let mut doc2 = doc1.fork();
+    let mut doc3 = doc1.fork();
+    for doc in [&mut doc1, &mut doc2, &mut doc3] {
+        let mut tx = doc.transaction();
+        tx.put(&list, B + 1, "conflict")?;
+        tx.commit();
+    }
+    doc1.merge(&mut doc2)?;
+    doc1.merge(&mut doc3)?;
+
+    for index in [0, B, B + 1, B * 2 - 1] {
+        assert_eq!(
+            doc1.conflict_count(&list, index, None)?,
+            doc1.get_all(&list, index)?.len()
+        );
+    }
+    assert_eq!(doc1.conflict_count(&list, B + 1, None)?, 3);
+    assert_eq!(doc1.conflict_count(&list, B + 1, Some(&heads))?, 1);
+    assert_eq!(doc1.conflict_count(&list, B * 2, None)?, 0);
+    assert!(doc1.conflict_count(ROOT, 0, None).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn get_many_at_matches_get_at() -> Result<(), AutomergeError> {
+    let mut doc = AutoCommit::new();
+    doc.put(ROOT, "a", 1)?;
+    doc.put(ROOT, "b", 2)?;
+    let list = doc.put_object(ROOT, "list", ObjType::List)?;
+    doc.insert(&list, 0, "x")?;
+    let heads = doc.get_heads();
+    doc.put(ROOT, "a", 3)?;
+    doc.delete(ROOT, "b")?;
+    doc.put(ROOT, "c", 4)?;
+
+    let props = [
+        Prop::from("c"),
+        "a".into(),
+        "b".into(),
+        "list".into(),
+        "missing".into(),
+    ];
+    for heads in [heads, doc.get_heads()] {
+        let expected = props
+            .iter()
+            .map(|p| Ok((p.clone(), doc.get_at(ROOT, p.clone(), &heads)?)))
+            .collect::<Result<Vec<_>, AutomergeError>>()?;
+        assert_eq!(doc.get_many_at(ROOT, &props, &heads)?, expected);
+    }
+    let heads = doc.get_heads();
+    assert_eq!(
+        doc.get_many_at(&list, &[0.into(), 1.into()], &heads)?,
+        vec![(Prop::Seq(0), doc.get(&list, 0)?), (Prop::Seq(1), None)]
+    );
+    Ok(())
+}
+
+#[test]
+fn keys_with_conflicts_counts_concurrent_values() -> Result<(), AutomergeError> {
+    let mut doc1 = AutoCommit::new();
+    let list = doc1.put_object(ROOT, "list", ObjType::List)?;
+    doc1.insert(&list, 0, "a")?;
+    doc1.insert(&list, 1, "b")?;
+    doc1.put(ROOT, "x", 1)?;
+    doc1.put(ROOT, "y", 1)?;
+    let mut doc2 = doc1.fork();
+    let mut doc3 = doc1.fork();
+    doc1.put(ROOT, "x", 2)?;
+    doc1.put(&list, 1, "d")?;
+    doc2.put(ROOT, "x", 3)?;
+    doc3.put(ROOT, "x", 4)?;
+    doc2.put(ROOT, "z", 1)?;
+    doc2.put(&list, 1, "c")?;
+    doc3.delete(&list, 0)?;
+    doc1.merge(&mut doc2)?;
+    doc1.merge(&mut doc3)?;
+
+    let keys = doc1.keys_with_conflicts(ROOT).collect::<Vec<_>>();
+    assert_eq!(
+        keys,
+        vec![
+            ("list".into(), 1),
+            ("x".into(), 3),
+            ("y".into(), 1),
+            ("z".into(), 1)
+        ]
+    );
+    assert_eq!(
+        keys.iter().map(|(k, _)| k.to_string()).collect::<Vec<_>>(),
+        doc1.keys(ROOT).collect::<Vec<_>>()
+    );
+    assert_eq!(doc1.get_all(ROOT, "x")?.len(), 3);
+
+    let elems = doc1.keys_with_conflicts(&list).collect::<Vec<_>>();
+    assert_eq!(elems, vec![(0.into(), 2)]);
+    Ok(())
+}
+
+#[test]
+fn object_stats() -> Result<(), AutomergeError> {
+    let mut doc = AutoCommit::new();
+    let list = doc.put_object(ROOT, "list", ObjType::List)?;
+    assert_eq!(
+        doc.object_stats(&list)?,
+        ObjStats {
+            tree_depth: 0,
+            node_count: 0,
+            op_count: 0,
+            visible_count: 0,
+        }
+    );
+
+    let n = B * 10;
+    for i in 0..n {
+        doc.insert(&list, i, i as i64)?;
+    }
+    for _ in 0..10 {
+        doc.delete(&list, 0)?;
+    }
+    doc.put(ROOT, "a", 1)?;
+    doc.put(ROOT, "a", 2)?;
+    let heads = doc.get_heads();
+    doc.delete(ROOT, "a")?;
+
+    let stats = doc.object_stats(&list)?;
+    // deletes are recorded as successors of the ops they delete rather than as ops in the tree
+    assert_eq!(stats.op_count, n);
+    assert_eq!(stats.visible_count, n - 10);
+    assert!(stats.tree_depth > 1);
+    assert!(stats.node_count >= n / B);
+
+    let root = doc.object_stats(ROOT)?;
+    assert_eq!(root.op_count, 3);
+    assert_eq!(root.visible_count, 1);
+    assert_eq!(root.tree_depth, 1);
+    assert_eq!(root.node_count, 1);
+    let doc = doc.document();
+    let at = crate::automerge::diff::ReadDocAt { doc, heads: &heads };
+    assert_eq!(at.object_stats(ROOT)?.visible_count, 2);
+
+    assert!(doc
+        .object_stats(ExId::Id(100, ActorId::random(), 1))
+        .is_err());
+    Ok(())
+}
+
 #[test]
 fn get_range_values() -> Result<(), AutomergeError> {
     let mut doc1 = Automerge::new();
@@ -1389,8 +1838,9 @@ fn load_broken_list() {
     assert_eq!(doc.states, doc2.states);
     assert_eq!(doc.deps, doc2.deps);
     // dont to support PartialEq b/c it would have to use the iterator underdneath
-    let doc1_ops: Vec<_> = doc.ops.iter().collect();
-    let doc2_ops: Vec<_> = doc2.ops.iter().collect();
+    let (spilled1, spilled2) = (doc.ops.rebuild_spilled(), doc2.ops.rebuild_spilled());
+    let doc1_ops: Vec<_> = doc.ops.iter(&spilled1).collect();
+    let doc2_ops: Vec<_> = doc2.ops.iter(&spilled2).collect();
     assert_eq!(doc1_ops, doc2_ops);
     assert_eq!(doc.max_op, doc2.max_op);
 
@@ -1443,8 +1893,9 @@ fn load_broken_list_short() {
     assert_eq!(doc.max_op, doc2.max_op);
     assert_eq!(bytes, bytes2);
     assert_eq!(doc.ops.len(), doc2.ops.len());
-    let doc1_ops: Vec<_> = doc.ops.iter().collect();
-    let doc2_ops: Vec<_> = doc2.ops.iter().collect();
+    let (spilled1, spilled2) = (doc.ops.rebuild_spilled(), doc2.ops.rebuild_spilled());
+    let doc1_ops: Vec<_> = doc.ops.iter(&spilled1).collect();
+    let doc2_ops: Vec<_> = doc2.ops.iter(&spilled2).collect();
     assert_eq!(doc1_ops, doc2_ops);
 }
 
@@ -1502,7 +1953,8 @@ fn get_parent_objects() {
             obj: ROOT,
             typ: ObjType::Map,
             prop: Prop::Map("a".into()),
-            visible: true
+            visible: true,
+            is_conflicted: false,
         })
     );
     assert_eq!(
@@ -1511,7 +1963,8 @@ fn get_parent_objects() {
             obj: map,
             typ: ObjType::Map,
             prop: Prop::Map("b".into()),
-            visible: true
+            visible: true,
+            is_conflicted: false,
         })
     );
     assert_eq!(
@@ -1520,7 +1973,8 @@ fn get_parent_objects() {
             obj: list,
             typ: ObjType::List,
             prop: Prop::Seq(0),
-            visible: true
+            visible: true,
+            is_conflicted: false,
         })
     );
 }
@@ -1569,7 +2023,8 @@ fn parents_iterator() {
             obj: list,
             typ: ObjType::List,
             prop: Prop::Seq(0),
-            visible: true
+            visible: true,
+            is_conflicted: false,
         })
     );
     assert_eq!(
@@ -1578,7 +2033,8 @@ fn parents_iterator() {
             obj: map,
             typ: ObjType::Map,
             prop: Prop::Map("b".into()),
-            visible: true
+            visible: true,
+            is_conflicted: false,
         })
     );
     assert_eq!(
@@ -1587,12 +2043,58 @@ fn parents_iterator() {
             obj: ROOT,
             typ: ObjType::Map,
             prop: Prop::Map("a".into()),
-            visible: true
+            visible: true,
+            is_conflicted: false,
         })
     );
     assert_eq!(parents.next(), None);
 }
 
+#[test]
+fn parents_report_conflicts() -> Result<(), AutomergeError> {
+    let mut doc1 = AutoCommit::new();
+    let list = doc1.put_object(ROOT, "list", ObjType::List)?;
+    doc1.insert(&list, 0, "a")?;
+    let mut doc2 = doc1.fork();
+    let map1 = doc1.put_object(&list, 0, ObjType::Map)?;
+    let map2 = doc2.put_object(&list, 0, ObjType::Map)?;
+    let text = doc2.put_object(&map2, "text", ObjType::Text)?;
+    doc1.merge(&mut doc2)?;
+
+    let parents = doc1.parents(&text)?.collect::<Vec<_>>();
+    assert_eq!(
+        parents
+            .iter()
+            .map(|p| (p.prop.clone(), p.is_conflicted))
+            .collect::<Vec<_>>(),
+        vec![
+            (Prop::Map("text".into()), false),
+            (Prop::Seq(0), true),
+            (Prop::Map("list".into()), false),
+        ]
+    );
+    // both sides of the conflict are flagged, whichever one wins
+    assert!(doc1.parents(&map1)?.next().unwrap().is_conflicted);
+
+    // a deleted element is not conflicted even if the next element is
+    doc1.insert(&list, 0, "b")?;
+    let inner = doc1.put_object(&list, 0, ObjType::Map)?;
+    doc1.delete(&list, 0)?;
+    let parent = doc1.parents(&inner)?.next().unwrap();
+    assert!(!parent.visible);
+    assert!(!parent.is_conflicted);
+
+    assert_eq!(
+        doc1.parents(&text)?.path(),
+        vec![
+            (ROOT, Prop::Map("list".into())),
+            (list, Prop::Seq(0)),
+            (map2, Prop::Map("text".into())),
+        ]
+    );
+    Ok(())
+}
+
 #[test]
 fn can_insert_a_grapheme_into_text() {
     let mut doc = Automerge::new();
@@ -1669,6 +2171,7 @@ fn observe_counter_change_application_overwrite() {
                     ExId::Id(2, doc2.get_actor().clone(), 1)
                 ),
                 conflict: false,
+                old: None,
             }
         }]
     );
@@ -1710,6 +2213,7 @@ fn observe_counter_change_application() {
                     ExId::Id(1, doc.get_actor().clone(), 0)
                 ),
                 conflict: false,
+                old: None,
             },
             PatchAction::Increment {
                 prop: Prop::Map("counter".into()),
@@ -1791,3 +2295,3710 @@ fn hash_for_opid() {
     assert_eq!(doc.hash_for_opid(&id1), hash1);
     assert_eq!(doc.hash_for_opid(&id2), hash2);
 }
+
+#[test]
+fn trim_changes_drops_history_but_keeps_state_and_save() {
+    let mut doc = AutoCommit::new();
+    let text = doc.put_object(ROOT, "text", ObjType::Text).unwrap();
+    doc.splice_text(&text, 0, 0, "hello").unwrap();
+    // enough to be worth compressing
+    doc.put(ROOT, "padding", "padding ".repeat(100)).unwrap();
+    doc.commit();
+    let early = doc.get_heads();
+    let list = doc.put_object(ROOT, "list", ObjType::List).unwrap();
+    for i in 0..5 {
+        doc.insert(&list, i, i as i64).unwrap();
+        doc.commit();
+    }
+    let keep_from = doc.get_heads();
+    doc.splice_text(&text, 5, 0, " world").unwrap();
+    doc.commit();
+    let trimmed_hash = early[0];
+    let kept = doc
+        .get_changes(&keep_from)
+        .iter()
+        .map(|c| c.hash())
+        .collect::<Vec<_>>();
+    let current = doc.hydrate(ROOT, None).unwrap();
+    let at_early = doc.hydrate(ROOT, Some(&early)).unwrap();
+
+    doc.trim_changes(&keep_from).unwrap();
+    let mut after_trim = doc
+        .get_changes(&[])
+        .iter()
+        .map(|c| c.hash())
+        .collect::<Vec<_>>();
+    after_trim.retain(|h| !keep_from.contains(h));
+    assert_eq!(after_trim, kept);
+    assert!(doc
+        .get_changes(&[])
+        .iter()
+        .any(|c| c.hash() == keep_from[0]));
+    assert!(doc.get_change_by_hash(&trimmed_hash).is_none());
+    assert_eq!(doc.hydrate(ROOT, None).unwrap(), current);
+    assert_eq!(doc.hydrate(ROOT, Some(&early)).unwrap(), at_early);
+    assert_eq!(doc.hash_for_opid(&text), Some(trimmed_hash));
+    assert!(matches!(
+        doc.fork_at(&early),
+        Err(AutomergeError::TrimmedChange(_))
+    ));
+    assert_eq!(
+        doc.fork_at(&keep_from)
+            .unwrap()
+            .hydrate(ROOT, None)
+            .unwrap(),
+        doc.hydrate(ROOT, Some(&keep_from)).unwrap()
+    );
+    assert!(doc.document().verify_integrity().unwrap().is_ok());
+
+    // the trimmed document can still be edited and saved
+    doc.insert(&list, 0, "new").unwrap();
+    doc.commit();
+    let loaded = Automerge::load(&doc.save()).unwrap();
+    assert_eq!(loaded.get_heads(), doc.get_heads());
+    assert_eq!(loaded.hydrate(None), doc.hydrate(ROOT, None).unwrap());
+    assert!(loaded.get_change_by_hash(&trimmed_hash).is_some());
+    let mut written = Vec::new();
+    doc.document().save_to_writer(&mut written).unwrap();
+    assert_eq!(written, doc.save());
+    // the snapshot of the trimmed history is saved with the options of the save
+    let snapshot = doc.document().fork_at(&keep_from).unwrap();
+    for (deflate, compression) in [
+        (false, CompressionLevel::Default),
+        (true, CompressionLevel::Best),
+    ] {
+        let options = || SaveOptions {
+            deflate,
+            compression,
+            ..Default::default()
+        };
+        let expected = snapshot.save_with_options(options());
+        let bytes = doc.document().save_with_options(options());
+        assert!(bytes.starts_with(&expected));
+        assert_eq!(
+            Automerge::load(&bytes).unwrap().get_heads(),
+            doc.get_heads()
+        );
+    }
+}
+
+#[test]
+fn trim_changes_twice_keeps_the_earlier_cut() {
+    let mut doc = AutoCommit::new();
+    let mut heads = Vec::new();
+    for i in 0..6 {
+        doc.put(ROOT, "count", i).unwrap();
+        doc.commit();
+        heads.push(doc.get_heads());
+    }
+    doc.trim_changes(&heads[3]).unwrap();
+    assert_eq!(doc.get_changes(&[]).len(), 3);
+    // trimming less than before doesn't bring anything back
+    doc.trim_changes(&heads[1]).unwrap();
+    assert_eq!(doc.get_changes(&[]).len(), 3);
+    doc.trim_changes(&heads[4]).unwrap();
+    assert_eq!(doc.get_changes(&[]).len(), 2);
+    let loaded = Automerge::load(&doc.save()).unwrap();
+    assert_eq!(loaded.get_heads(), doc.get_heads());
+    assert_eq!(loaded.get(ROOT, "count").unwrap().unwrap().0, Value::int(5));
+}
+
+#[test]
+fn trim_changes_rejects_unknown_hashes() {
+    let mut doc = AutoCommit::new();
+    doc.put(ROOT, "key", "value").unwrap();
+    doc.commit();
+    let mut other = AutoCommit::new();
+    other.put(ROOT, "key", "other").unwrap();
+    let missing = other.commit().unwrap();
+    assert!(matches!(
+        doc.trim_changes(&[missing]),
+        Err(AutomergeError::MissingHash(h)) if h == missing
+    ));
+    assert_eq!(doc.get_changes(&[]).len(), 1);
+}
+
+#[test]
+fn merging_a_trimmed_doc_sends_the_trimmed_history() {
+    let mut doc = AutoCommit::new();
+    for i in 0..5 {
+        doc.put(ROOT, "count", i).unwrap();
+        doc.commit();
+    }
+    let mut partial = doc.fork();
+    partial.put(ROOT, "other", "value").unwrap();
+    partial.commit();
+    for i in 5..10 {
+        doc.put(ROOT, "count", i).unwrap();
+        doc.commit();
+    }
+    let keep_from = doc.get_heads();
+    doc.put(ROOT, "count", 10).unwrap();
+    doc.commit();
+    doc.trim_changes(&keep_from).unwrap();
+
+    let mut empty = AutoCommit::new();
+    empty.merge(&mut doc).unwrap();
+    assert_eq!(empty.get_heads(), doc.get_heads());
+    partial.merge(&mut doc).unwrap();
+    doc.merge(&mut partial).unwrap();
+    assert_eq!(partial.get_heads(), doc.get_heads());
+    assert_eq!(
+        partial.hydrate(ROOT, None).unwrap(),
+        doc.hydrate(ROOT, None).unwrap()
+    );
+}
+
+#[test]
+fn mark_and_return_range() -> Result<(), AutomergeError> {
+    let mut doc = AutoCommit::new();
+    let text = doc.put_object(ROOT, "text", ObjType::Text)?;
+    doc.splice_text(&text, 0, 0, "hello world")?;
+
+    let mark = marks::Mark::new("bold".to_string(), true, 6, 11);
+    let range = doc.mark_and_return_range(&text, mark, marks::ExpandMark::None)?;
+    assert_eq!(range, 6..11);
+
+    let mark = marks::Mark::new("comment".to_string(), "x", 5, 5);
+    let range = doc.mark_and_return_range(&text, mark, marks::ExpandMark::Both)?;
+    assert_eq!(range, 5..5);
+
+    // a zero width mark with no expansion is ignored
+    let mark = marks::Mark::new("comment".to_string(), "y", 3, 3);
+    let range = doc.mark_and_return_range(&text, mark, marks::ExpandMark::None)?;
+    assert_eq!(range, 3..3);
+
+    // the zero width mark doesn't cover any characters
+    let marks = doc.marks(&text)?;
+    assert_eq!(marks.len(), 1);
+
+    let mut doc = Automerge::new();
+    let mut tx = doc.transaction();
+    let text = tx.put_object(ROOT, "text", ObjType::Text)?;
+    tx.splice_text(&text, 0, 0, "hello")?;
+    let mark = marks::Mark::new("italic".to_string(), true, 1, 4);
+    assert_eq!(
+        tx.mark_and_return_range(&text, mark, marks::ExpandMark::After)?,
+        1..4
+    );
+    tx.commit();
+    Ok(())
+}
+
+#[test]
+fn spans_range_matches_spans() -> Result<(), AutomergeError> {
+    use crate::iter::Span;
+
+    // flatten spans into (char, marks) pairs so windows can be compared with the whole text
+    fn chars(spans: crate::iter::Spans<'_>) -> Vec<(char, Vec<(String, ScalarValue)>)> {
+        let mut result = Vec::new();
+        for span in spans {
+            match span {
+                Span::Text(text, marks) => {
+                    let marks = marks
+                        .map(|m| m.iter().map(|(k, v)| (k.to_string(), v.clone())).collect())
+                        .unwrap_or_default();
+                    result.extend(text.chars().map(|c| (c, Vec::clone(&marks))));
+                }
+                Span::Block(_) => result.push(('\n', vec![])),
+            }
+        }
+        result
+    }
+
+    let mut doc = AutoCommit::new();
+    let text = doc.put_object(ROOT, "text", ObjType::Text)?;
+    let content = "the quick brown fox jumps over the lazy dog ".repeat(20);
+    doc.splice_text(&text, 0, 0, &content)?;
+    let mark = marks::Mark::new("bold".to_string(), true, 4, 300);
+    doc.mark(&text, mark, marks::ExpandMark::None)?;
+    let mark = marks::Mark::new("italic".to_string(), true, 100, 120);
+    doc.mark(&text, mark, marks::ExpandMark::None)?;
+    doc.split_block(&text, 500)?;
+    let heads = doc.get_heads();
+    doc.splice_text(&text, 0, 10, "")?;
+
+    let all = chars(doc.spans(&text)?);
+    for range in [
+        0..5,
+        3..10,
+        50..110,
+        110..115,
+        250..600,
+        495..510,
+        800..1000,
+    ] {
+        let window = chars(doc.spans_range(&text, range.clone(), None)?);
+        let end = range.end.min(all.len());
+        let start = range.start.min(end);
+        assert_eq!(window, all[start..end], "range {:?}", range);
+    }
+
+    let all = chars(doc.spans_at(&text, &heads)?);
+    let window = chars(doc.spans_range(&text, 95..105, Some(&heads))?);
+    assert_eq!(window, all[95..105]);
+
+    // marks which start before the window are on the first span
+    let mut spans = doc.spans_range(&text, 102..104, None)?;
+    let Some(Span::Text(t, Some(marks))) = spans.next() else {
+        panic!("expected a text span");
+    };
+    assert_eq!(t, "s ");
+    assert_eq!(marks.len(), 2);
+    Ok(())
+}
+
+#[test]
+fn import_exid_round_trips_across_load() -> Result<(), AutomergeError> {
+    let mut doc = AutoCommit::new();
+    let list = doc.put_object(ROOT, "list", ObjType::List)?;
+    let map = doc.insert_object(&list, 0, ObjType::Map)?;
+    let stored = map.to_stable_string();
+
+    let loaded = AutoCommit::load(&doc.save())?;
+    let imported = loaded.import_exid(&stored)?;
+    assert_eq!(imported, map);
+    assert_eq!(loaded.object_type(&imported)?, ObjType::Map);
+    assert_eq!(loaded.import_exid(&ROOT.to_stable_string())?, ROOT);
+
+    for malformed in ["", "list", "x@abcd", "1@not-hex"] {
+        assert!(matches!(
+            loaded.import_exid(malformed),
+            Err(AutomergeError::InvalidObjIdFormat(_))
+        ));
+    }
+
+    // a well formed id for an object which was never in the document
+    let other = AutoCommit::new().put_object(ROOT, "x", ObjType::Map)?;
+    assert!(matches!(
+        loaded.import_exid(&other.to_stable_string()),
+        Err(AutomergeError::InvalidObjId(_))
+    ));
+    let counter = map
+        .to_stable_string()
+        .replacen(|c: char| c.is_ascii_digit(), "9", 1);
+    assert!(matches!(
+        loaded.import_exid(&counter),
+        Err(AutomergeError::InvalidObjId(_))
+    ));
+    Ok(())
+}
+
+#[test]
+fn nth_query_reports_progress() -> Result<(), AutomergeError> {
+    let mut doc = AutoCommit::new();
+    let list = doc.put_object(ROOT, "list", ObjType::List)?;
+    doc.splice(&list, 0, 0, (0..1000).map(ScalarValue::from))?;
+    let doc = doc.document();
+    let obj = doc.exid_to_obj(&list)?;
+
+    let query = query::Nth::new(500, types::ListEncoding::List, None, doc.osd());
+    assert_eq!(query.progress(), (0, 501));
+    let query = doc.ops().search(&obj.id, query);
+    assert_eq!(query.progress(), (500, 501));
+    Ok(())
+}
+
+#[test]
+fn query_can_skip_nodes() -> Result<(), AutomergeError> {
+    use crate::op_set::{Op, OpSetData};
+    use crate::op_tree::OpTreeNode;
+    use crate::query::{Index, ListState, QueryResult, TreeQuery};
+
+    /// Find the index of an element, only visiting the nodes which contain it
+    #[derive(Debug, Clone)]
+    struct FindElem {
+        target: OpId,
+        list_state: ListState,
+        visited: usize,
+        found: Option<usize>,
+    }
+
+    impl<'a> TreeQuery<'a> for FindElem {
+        fn query_node(
+            &mut self,
+            _child: &'a OpTreeNode,
+            index: &'a Index,
+            _osd: &'a OpSetData,
+        ) -> QueryResult {
+            if index.has_op(&self.target) {
+                QueryResult::Descend
+            } else {
+                QueryResult::SkipNode
+            }
+        }
+
+        fn query_element(&mut self, op: Op<'a>) -> QueryResult {
+            self.visited += 1;
+            if op.id() == &self.target {
+                self.found = Some(self.list_state.index());
+                return QueryResult::Finish;
+            }
+            self.list_state
+                .process_op(op, op.elemid_or_key(), op.visible_at(None));
+            QueryResult::Next
+        }
+
+        fn list_state_mut(&mut self) -> Option<&mut ListState> {
+            Some(&mut self.list_state)
+        }
+    }
+
+    let mut doc = AutoCommit::new();
+    let list = doc.put_object(ROOT, "list", ObjType::List)?;
+    doc.splice(&list, 0, 0, (0..1000).map(ScalarValue::from))?;
+    doc.splice(&list, 100, 50, [])?;
+    doc.put(&list, 10, "conflict")?;
+    let (_, elem) = doc.get(&list, 700)?.unwrap();
+    let doc = doc.document();
+    let obj = doc.exid_to_obj(&list)?;
+
+    let query = FindElem {
+        target: doc.exid_to_opid(&elem)?,
+        list_state: ListState::new(types::ListEncoding::List, usize::MAX),
+        visited: 0,
+        found: None,
+    };
+    let query = doc.ops().search(&obj.id, query);
+    assert_eq!(query.found, Some(700));
+    assert!(query.visited < 100);
+    Ok(())
+}
+
+#[test]
+fn length_at_current_heads() -> Result<(), AutomergeError> {
+    let mut doc = AutoCommit::new();
+    let text = doc.put_object(ROOT, "text", ObjType::Text)?;
+    doc.splice_text(&text, 0, 0, "hello world")?;
+    doc.splice_text(&text, 5, 6, "")?;
+    let heads = doc.get_heads();
+    assert_eq!(doc.length_at(&text, &heads), 5);
+    assert_eq!(doc.document().length_at(&text, &heads), 5);
+
+    // uncommitted changes are not part of `heads`
+    doc.splice_text(&text, 5, 0, " there")?;
+    assert_eq!(doc.length(&text), 11);
+    assert_eq!(doc.length_at(&text, &heads), 5);
+    doc.commit();
+    assert_eq!(doc.length_at(&text, &heads), 5);
+    let heads = doc.get_heads();
+    assert_eq!(doc.length_at(&text, &heads), 11);
+
+    // a conflicted character means the length has to be calculated from the ops
+    let mut other = doc.fork();
+    doc.put(&text, 0, "ü")?;
+    other.put(&text, 0, "y")?;
+    doc.merge(&mut other)?;
+    let before_put = doc.get_heads();
+    doc.splice_text(&text, 0, 0, "a")?;
+    doc.commit();
+    let heads = doc.get_heads();
+    assert_eq!(doc.length_at(&text, &heads), doc.length(&text));
+    assert_eq!(doc.length_at(&text, &before_put) + 1, doc.length(&text));
+    Ok(())
+}
+
+#[test]
+fn utf16_index_conversions() -> Result<(), AutomergeError> {
+    let mut doc = AutoCommit::new();
+    let text = doc.put_object(ROOT, "text", ObjType::Text)?;
+    doc.splice_text(&text, 0, 0, "a😀b")?;
+
+    assert_eq!(doc.char_to_utf16(&text, 0)?, 0);
+    assert_eq!(doc.char_to_utf16(&text, 2)?, 3);
+    assert_eq!(doc.char_to_utf16(&text, 3)?, 4);
+    assert!(matches!(
+        doc.char_to_utf16(&text, 4),
+        Err(AutomergeError::InvalidIndex(4))
+    ));
+    assert_eq!(doc.utf16_to_char(&text, 3)?, 2);
+    assert_eq!(doc.utf16_to_char(&text, 4)?, 3);
+    assert!(matches!(
+        doc.utf16_to_char(&text, 2),
+        Err(AutomergeError::InvalidIndex(2))
+    ));
+
+    doc.splice_text_utf16(&text, 3, 1, "c")?;
+    assert_eq!(doc.text(&text)?, "a😀c");
+    assert!(matches!(
+        doc.splice_text_utf16(&text, 2, 1, ""),
+        Err(AutomergeError::InvalidIndex(2))
+    ));
+    doc.splice_text_utf16(&text, 3, -2, "")?;
+    assert_eq!(doc.text(&text)?, "ac");
+
+    // long enough that the op tree has several nodes to skip
+    let long = doc.put_object(ROOT, "long", ObjType::Text)?;
+    doc.splice_text(&long, 0, 0, &"😀x".repeat(500))?;
+    for i in [0, 1, 2, 701, 999] {
+        let utf16 = (i / 2) * 3 + (i % 2) * 2;
+        assert_eq!(doc.char_to_utf16(&long, i)?, utf16);
+        assert_eq!(doc.utf16_to_char(&long, utf16)?, i);
+    }
+    let heads = doc.get_heads();
+    doc.splice_text(&long, 0, 0, "😀")?;
+    assert_eq!(doc.char_to_utf16(&long, 1)?, 2);
+    let at = crate::automerge::diff::ReadDocAt {
+        doc: doc.document(),
+        heads: &heads,
+    };
+    assert_eq!(at.char_to_utf16(&long, 1)?, 2);
+    assert_eq!(at.char_to_utf16(&long, 2)?, 3);
+    Ok(())
+}
+
+#[test]
+fn map_keys_in_insertion_order() -> Result<(), AutomergeError> {
+    let mut doc1 = AutoCommit::new().with_actor(ActorId::from([1]));
+    let form = doc1.put_object(ROOT, "form", ObjType::Map)?;
+    doc1.put(&form, "name", "")?;
+    doc1.put(&form, "email", "")?;
+    doc1.put(&form, "age", 0)?;
+    doc1.commit();
+
+    let mut doc2 = doc1.fork().with_actor(ActorId::from([2]));
+    doc1.put(&form, "zip", "")?;
+    doc2.put(&form, "address", "")?;
+    // overwriting or re-adding a key doesn't move it
+    doc1.put(&form, "name", "alice")?;
+    doc2.delete(&form, "email")?;
+    doc2.put(&form, "email", "alice@example.com")?;
+    doc1.merge(&mut doc2)?;
+    doc2.merge(&mut doc1)?;
+
+    let expected = vec!["name", "email", "age", "zip", "address"];
+    assert_eq!(doc1.map_keys_in_insertion_order(&form), expected);
+    assert_eq!(doc2.map_keys_in_insertion_order(&form), expected);
+    assert_eq!(
+        doc1.keys(&form).collect::<Vec<_>>(),
+        vec!["address", "age", "email", "name", "zip"]
+    );
+
+    doc1.delete(&form, "age")?;
+    assert_eq!(
+        doc1.map_keys_in_insertion_order(&form),
+        vec!["name", "email", "zip", "address"]
+    );
+    assert_eq!(doc1.map_keys_in_insertion_order(ROOT), vec!["form"]);
+    Ok(())
+}
+
+#[test]
+fn splice_objects_returns_ids_in_order() -> Result<(), AutomergeError> {
+    let mut doc = AutoCommit::new();
+    let list = doc.put_object(ROOT, "records", ObjType::List)?;
+    doc.insert(&list, 0, "first")?;
+    doc.insert(&list, 1, "last")?;
+
+    let ids = doc.splice_objects(&list, 1, vec![ObjType::Map, ObjType::List, ObjType::Map])?;
+    assert_eq!(ids.len(), 3);
+    for (i, id) in ids.iter().enumerate() {
+        assert_eq!(doc.get(&list, i + 1)?.map(|(_, id)| id), Some(id.clone()));
+    }
+    doc.put(&ids[0], "name", "alice")?;
+    doc.insert(&ids[1], 0, 1)?;
+    doc.put(&ids[2], "name", "bob")?;
+    doc.commit();
+
+    assert_eq!(doc.length(&list), 5);
+    assert_eq!(doc.get(&list, 4)?.unwrap().0, Value::str("last"));
+    assert_eq!(doc.object_type(&ids[1])?, ObjType::List);
+    assert_eq!(doc.get(&ids[2], "name")?.unwrap().0, Value::str("bob"));
+    assert!(doc.splice_objects(&list, 0, vec![])?.is_empty());
+    assert!(matches!(
+        doc.splice_objects(ROOT, 0, vec![ObjType::Map]),
+        Err(AutomergeError::InvalidOp(ObjType::Map))
+    ));
+    Ok(())
+}
+
+#[test]
+fn values_ref_matches_values() -> Result<(), AutomergeError> {
+    let mut doc = AutoCommit::new();
+    let list = doc.put_object(ROOT, "list", ObjType::List)?;
+    doc.insert(&list, 0, "a string")?;
+    doc.insert(&list, 1, ScalarValue::Bytes(vec![1, 2, 3]))?;
+    let inner = doc.insert_object(&list, 2, ObjType::Map)?;
+    doc.insert(&list, 3, ScalarValue::counter(1))?;
+    doc.commit();
+    let heads = doc.get_heads();
+    doc.increment(&list, 3, 5)?;
+
+    let refs = doc.values_ref(&list).collect::<Vec<_>>();
+    let values = doc.values(&list).map(|(v, _)| v).collect::<Vec<_>>();
+    assert_eq!(
+        refs.iter().map(|v| v.to_value()).collect::<Vec<_>>(),
+        values
+    );
+    assert!(matches!(
+        &refs[0],
+        ValueRef::Scalar(std::borrow::Cow::Borrowed(_))
+    ));
+    assert_eq!(refs[2], ValueRef::Object(ObjType::Map, inner));
+
+    let at = crate::automerge::diff::ReadDocAt {
+        doc: doc.document(),
+        heads: &heads,
+    };
+    assert_eq!(
+        at.values_ref(&list).last(),
+        Some(ValueRef::Scalar(std::borrow::Cow::Owned(
+            ScalarValue::counter(1)
+        )))
+    );
+    Ok(())
+}
+
+#[test]
+fn get_by_timestamp_prefers_latest_change() -> Result<(), AutomergeError> {
+    let mut doc1 = AutoCommit::new().with_actor(ActorId::from([2]));
+    doc1.put(ROOT, "status", "draft")?;
+    doc1.commit_with(CommitOptions::default().with_time(10));
+    let mut doc2 = doc1.fork().with_actor(ActorId::from([1]));
+    doc1.put(ROOT, "status", "older")?;
+    doc1.commit_with(CommitOptions::default().with_time(100));
+    doc2.put(ROOT, "status", "newer")?;
+    doc2.commit_with(CommitOptions::default().with_time(200));
+    doc1.merge(&mut doc2)?;
+
+    // the op id ordering favours actor 2
+    assert_eq!(doc1.get(ROOT, "status")?.unwrap().0, Value::str("older"));
+    assert_eq!(
+        doc1.get_by_timestamp(ROOT, "status")?.unwrap().0,
+        Value::str("newer")
+    );
+    doc2.merge(&mut doc1)?;
+    assert_eq!(
+        doc2.get_by_timestamp(ROOT, "status")?,
+        doc1.get_by_timestamp(ROOT, "status")?
+    );
+
+    // equal timestamps fall back to the op id ordering
+    let mut doc3 = doc1.fork().with_actor(ActorId::from([3]));
+    let mut doc4 = doc1.fork().with_actor(ActorId::from([4]));
+    doc3.put(ROOT, "tie", 3)?;
+    doc3.commit_with(CommitOptions::default().with_time(300));
+    doc4.put(ROOT, "tie", 4)?;
+    doc4.commit_with(CommitOptions::default().with_time(300));
+    doc3.merge(&mut doc4)?;
+    assert_eq!(doc3.get_by_timestamp(ROOT, "tie")?, doc3.get(ROOT, "tie")?);
+
+    // values from a transaction which is still open are read too
+    doc3.put(ROOT, "status", "pending")?;
+    assert_eq!(
+        doc3.get_by_timestamp(ROOT, "status")?.unwrap().0,
+        Value::str("pending")
+    );
+    assert_eq!(doc3.get_by_timestamp(ROOT, "missing")?, None);
+    Ok(())
+}
+
+#[test]
+fn marks_in_range_clips_to_range() -> Result<(), AutomergeError> {
+    let mut doc = AutoCommit::new();
+    let text = doc.put_object(ROOT, "text", ObjType::Text)?;
+    doc.splice_text(&text, 0, 0, "hello world")?;
+    doc.mark(
+        &text,
+        Mark::new("bold".into(), true, 0, 5),
+        marks::ExpandMark::None,
+    )?;
+    doc.mark(
+        &text,
+        Mark::new("italic".into(), true, 3, 8),
+        marks::ExpandMark::None,
+    )?;
+    doc.mark(
+        &text,
+        Mark::new("comment".into(), "a", 8, 8),
+        marks::ExpandMark::Both,
+    )?;
+    doc.mark(
+        &text,
+        Mark::new("comment".into(), "b", 2, 2),
+        marks::ExpandMark::Both,
+    )?;
+
+    let marks = doc
+        .marks_in_range(&text, 4..8, None)?
+        .into_iter()
+        .map(|m| m.into_owned())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        marks,
+        vec![
+            Mark::new("bold".into(), true, 4, 5),
+            Mark::new("italic".into(), true, 4, 8),
+            Mark::new("comment".into(), "a", 8, 8),
+        ]
+    );
+
+    let heads = doc.get_heads();
+    doc.unmark(&text, "italic", 0, 11, marks::ExpandMark::None)?;
+    assert_eq!(
+        doc.marks_in_range(&text, 4..8, None)?,
+        vec![
+            Mark::new("bold".into(), true, 4, 5),
+            Mark::new("comment".into(), "a", 8, 8),
+        ]
+    );
+    assert_eq!(doc.marks_in_range(&text, 4..8, Some(&heads))?, marks);
+
+    // the whole text gives the same marks as `marks` plus the empty ones
+    let all = doc.marks_in_range(&text, 0..11, None)?;
+    let mut non_empty = all
+        .into_iter()
+        .filter(|m| m.start != m.end)
+        .collect::<Vec<_>>();
+    let mut expected = doc.marks(&text)?;
+    non_empty.sort_by_key(|m| (m.start, m.name().to_string()));
+    expected.sort_by_key(|m| (m.start, m.name().to_string()));
+    assert_eq!(non_empty, expected);
+    Ok(())
+}
+
+#[test]
+fn object_type_at_before_creation() -> Result<(), AutomergeError> {
+    let mut doc = AutoCommit::new();
+    doc.put(ROOT, "title", "empty")?;
+    let before = doc.get_heads();
+    let list = doc.put_object(ROOT, "items", ObjType::List)?;
+    let after = doc.get_heads();
+
+    assert_eq!(doc.object_type_at(&list, &before)?, None);
+    assert_eq!(doc.object_type_at(&list, &after)?, Some(ObjType::List));
+    assert_eq!(doc.object_type_at(ROOT, &before)?, Some(ObjType::Map));
+    assert_eq!(doc.object_type_at(ROOT, &[])?, Some(ObjType::Map));
+    assert_eq!(doc.document().object_type_at(&list, &before)?, None);
+
+    let mut other = AutoCommit::new();
+    let unknown = other.put_object(ROOT, "other", ObjType::Map)?;
+    assert!(doc.object_type_at(&unknown, &after).is_err());
+    Ok(())
+}
+
+#[test]
+fn rollback_to_savepoint_keeps_earlier_ops() -> Result<(), AutomergeError> {
+    let mut doc = Automerge::new();
+    let mut tx = doc.transaction();
+    let text = tx.put_object(ROOT, "text", ObjType::Text)?;
+    tx.splice_text(&text, 0, 0, "hello")?;
+    tx.put(ROOT, "counter", ScalarValue::counter(1))?;
+    let savepoint = tx.savepoint();
+    tx.splice_text(&text, 5, 0, " world")?;
+    tx.splice_text(&text, 0, 1, "j")?;
+    tx.put_object(ROOT, "list", ObjType::List)?;
+    tx.increment(ROOT, "counter", 2)?;
+    assert_eq!(tx.rollback_to(savepoint), 10);
+    assert_eq!(tx.text(&text)?, "hello");
+    assert_eq!(tx.get(ROOT, "list")?, None);
+    tx.splice_text(&text, 5, 0, "!")?;
+    tx.commit();
+
+    assert_eq!(doc.text(&text)?, "hello!");
+    assert_eq!(doc.get(ROOT, "counter")?.unwrap().0, Value::counter(1));
+    let change = doc.get_last_local_change().unwrap();
+    assert_eq!(change.len(), 8);
+    let loaded = Automerge::load(&doc.save())?;
+    assert_eq!(loaded.text(&text)?, "hello!");
+    assert_eq!(loaded.get_heads(), doc.get_heads());
+    Ok(())
+}
+
+#[test]
+fn text_diff_replays_edits_between_heads() -> Result<(), AutomergeError> {
+    use crate::patches::TextEdit;
+
+    let mut doc = AutoCommit::new();
+    let text = doc.put_object(ROOT, "text", ObjType::Text)?;
+    doc.splice_text(&text, 0, 0, "hello world")?;
+    let before = doc.get_heads();
+    doc.splice_text(&text, 6, 5, "there")?;
+    doc.splice_text(&text, 11, 0, "!")?;
+    doc.mark(
+        &text,
+        Mark::new("bold".to_string(), true, 0, 5),
+        marks::ExpandMark::None,
+    )?;
+    let after = doc.get_heads();
+
+    let edits = doc.text_diff(&text, &before, &after)?;
+    assert_eq!(
+        edits,
+        vec![
+            TextEdit::Format {
+                index: 0,
+                len: 5,
+                name: "bold".to_string(),
+                value: ScalarValue::Boolean(true),
+            },
+            TextEdit::Insert {
+                index: 6,
+                text: "there!".to_string(),
+            },
+            TextEdit::Delete { index: 12, len: 5 },
+        ]
+    );
+
+    // the reverse diff undoes the edits
+    let mut replayed = doc.text_at(&text, &after)?.chars().collect::<Vec<_>>();
+    for edit in doc.text_diff(&text, &after, &before)? {
+        match edit {
+            TextEdit::Insert { index, text } => {
+                replayed.splice(index..index, text.chars());
+            }
+            TextEdit::Delete { index, len } => {
+                replayed.drain(index..index + len);
+            }
+            TextEdit::Format { .. } => {}
+        }
+    }
+    assert_eq!(replayed.into_iter().collect::<String>(), "hello world");
+
+    assert!(matches!(
+        doc.text_diff(ROOT, &before, &after),
+        Err(AutomergeError::InvalidOp(ObjType::Map))
+    ));
+    Ok(())
+}
+
+#[test]
+fn objects_walks_visible_objects_depth_first() -> Result<(), AutomergeError> {
+    let mut doc = AutoCommit::new();
+    let list = doc.put_object(ROOT, "list", ObjType::List)?;
+    let inner = doc.insert_object(&list, 0, ObjType::Map)?;
+    let text = doc.put_object(&inner, "text", ObjType::Text)?;
+    let other = doc.insert_object(&list, 1, ObjType::Table)?;
+    let deleted = doc.put_object(ROOT, "z", ObjType::Map)?;
+    doc.put_object(&deleted, "child", ObjType::List)?;
+    doc.delete(ROOT, "z")?;
+    doc.put(ROOT, "scalar", 1)?;
+
+    let objects = doc.objects().collect::<Vec<_>>();
+    assert_eq!(
+        objects,
+        vec![
+            (ROOT, ObjType::Map, None),
+            (list.clone(), ObjType::List, Some(ROOT)),
+            (inner.clone(), ObjType::Map, Some(list.clone())),
+            (text, ObjType::Text, Some(inner)),
+            (other, ObjType::Table, Some(list)),
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn splice_and_read_bytes() -> Result<(), AutomergeError> {
+    let mut doc = AutoCommit::new();
+    let buf = doc.put_object(ROOT, "attachment", ObjType::List)?;
+    doc.splice_bytes(&buf, 0, 0, &[0, 1, 2, 255])?;
+    doc.splice_bytes(&buf, 1, 2, b"hello")?;
+    assert_eq!(doc.read_bytes(&buf, ..)?, b"\x00hello\xff");
+    assert_eq!(doc.read_bytes(&buf, 1..3)?, b"he");
+
+    let saved = doc.save();
+    let loaded = Automerge::load(&saved)?;
+    assert_eq!(loaded.read_bytes(&buf, ..)?, b"\x00hello\xff");
+
+    doc.insert(&buf, 0, 256)?;
+    assert!(matches!(
+        doc.read_bytes(&buf, ..),
+        Err(AutomergeError::InvalidValueType { .. })
+    ));
+    assert_eq!(doc.read_bytes(&buf, 1..)?.len(), 7);
+    assert!(matches!(
+        doc.read_bytes(ROOT, ..),
+        Err(AutomergeError::InvalidOp(ObjType::Map))
+    ));
+    Ok(())
+}
+
+#[test]
+fn get_change_by_hash_borrows_from_history() -> Result<(), AutomergeError> {
+    let mut doc = Automerge::new();
+    let mut tx = doc.transaction();
+    tx.put(ROOT, "key", "value")?;
+    let (hash, _) = tx.commit();
+    let hash = hash.unwrap();
+
+    let first = doc.get_change_by_hash(&hash).unwrap();
+    let second = doc.get_change_by_hash(&hash).unwrap();
+    assert!(std::ptr::eq(first, second));
+    assert_eq!(first.hash(), hash);
+    assert!(doc.get_change_by_hash(&ChangeHash([0; 32])).is_none());
+    Ok(())
+}
+
+#[test]
+fn commit_with_overrides_staged_message_and_timestamp() -> Result<(), AutomergeError> {
+    let mut doc = Automerge::new();
+    let mut tx = doc.transaction();
+    tx.set_message("staged".to_string());
+    tx.set_timestamp(1);
+    tx.put(ROOT, "a", 1)?;
+    let (hash, _) = tx.commit_with(CommitOptions::default().with_time(2));
+    let change = doc.get_change_by_hash(&hash.unwrap()).unwrap();
+    assert_eq!(change.message().map(String::as_str), Some("staged"));
+    assert_eq!(change.timestamp(), 2);
+    Ok(())
+}
+
+#[test]
+fn entries_at_yields_props_as_at_heads() -> Result<(), AutomergeError> {
+    let mut doc = AutoCommit::new();
+    doc.put(ROOT, "a", 1)?;
+    doc.put(ROOT, "b", 2)?;
+    let list = doc.put_object(ROOT, "list", ObjType::List)?;
+    doc.insert(&list, 0, "x")?;
+    doc.insert(&list, 1, "y")?;
+    let heads = doc.get_heads();
+    doc.delete(ROOT, "a")?;
+    doc.put(ROOT, "b", 3)?;
+    doc.delete(&list, 0)?;
+
+    let map = doc
+        .entries_at(ROOT, &heads)
+        .map(|(prop, value, _)| (prop, value))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        map,
+        vec![
+            (Prop::Map("a".into()), Value::int(1)),
+            (Prop::Map("b".into()), Value::int(2)),
+            (Prop::Map("list".into()), Value::Object(ObjType::List)),
+        ]
+    );
+    let items = doc
+        .entries_at(&list, &heads)
+        .map(|(prop, value, _)| (prop, value))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        items,
+        vec![
+            (Prop::Seq(0), Value::str("x")),
+            (Prop::Seq(1), Value::str("y"))
+        ]
+    );
+    assert_eq!(
+        doc.entries(ROOT)
+            .map(|(prop, _, _)| prop)
+            .collect::<Vec<_>>(),
+        vec![Prop::Map("b".into()), Prop::Map("list".into())]
+    );
+    Ok(())
+}
+
+#[test]
+fn get_cursor_position_and_value_reads_the_element() -> Result<(), AutomergeError> {
+    let mut doc = AutoCommit::new();
+    let list = doc.put_object(ROOT, "list", ObjType::List)?;
+    doc.insert(&list, 0, "a")?;
+    doc.insert(&list, 1, "b")?;
+    doc.insert(&list, 2, "c")?;
+    let cursor = doc.get_cursor(&list, 1, None)?;
+    doc.put(&list, 1, "B")?;
+    doc.insert(&list, 0, "z")?;
+    let heads = doc.get_heads();
+
+    assert_eq!(
+        doc.get_cursor_position_and_value(&list, &cursor, None)?,
+        (2, Some(Value::str("B")))
+    );
+    doc.delete(&list, 2)?;
+    assert_eq!(
+        doc.get_cursor_position_and_value(&list, &cursor, None)?,
+        (2, None)
+    );
+    assert_eq!(
+        doc.get_cursor_position_and_value(&list, &cursor, Some(&heads))?,
+        (2, Some(Value::str("B")))
+    );
+    Ok(())
+}
+
+#[test]
+fn merge_preview_matches_merge_and_leaves_doc_unchanged() -> Result<(), AutomergeError> {
+    let mut doc1 = AutoCommit::new();
+    let text = doc1.put_object(ROOT, "text", ObjType::Text)?;
+    doc1.splice_text(&text, 0, 0, "hello")?;
+    doc1.put(ROOT, "key", 1)?;
+    let mut doc2 = doc1.fork();
+    doc1.splice_text(&text, 5, 0, " world")?;
+    doc1.put(ROOT, "key", 2)?;
+    doc2.splice_text(&text, 0, 1, "J")?;
+    doc2.put(ROOT, "key", 3)?;
+    doc1.commit();
+    doc2.commit();
+    let mut doc1 = doc1.document().clone();
+    let mut doc2 = doc2.document().clone();
+
+    let heads = doc1.get_heads();
+    let preview = doc1.merge_preview(&doc2, patches::TextRepresentation::String)?;
+    assert_eq!(doc1.get_heads(), heads);
+
+    let mut patch_log = PatchLog::active(patches::TextRepresentation::String);
+    doc1.merge_and_log_patches(&mut doc2, &mut patch_log)?;
+    assert!(!preview.is_empty());
+    assert_eq!(preview, doc1.make_patches(&mut patch_log));
+    Ok(())
+}
+
+#[test]
+fn object_ref_marks_merge_like_scalar_marks() -> Result<(), AutomergeError> {
+    let mut doc1 = AutoCommit::new();
+    let text = doc1.put_object(ROOT, "text", ObjType::Text)?;
+    doc1.splice_text(&text, 0, 0, "hello world")?;
+    let mut doc2 = doc1.fork();
+    let thread1 = doc1.put_object(ROOT, "thread1", ObjType::List)?;
+    doc1.mark(
+        &text,
+        Mark::new_object_ref("comment".to_string(), &thread1, 0, 5),
+        marks::ExpandMark::None,
+    )?;
+    let thread2 = doc2.put_object(ROOT, "thread2", ObjType::List)?;
+    doc2.mark(
+        &text,
+        Mark::new_object_ref("comment".to_string(), &thread2, 0, 5),
+        marks::ExpandMark::None,
+    )?;
+    doc1.merge(&mut doc2)?;
+    doc2.merge(&mut doc1)?;
+
+    let winner = doc1.marks(&text)?[0].value().to_str().unwrap().to_string();
+    assert_eq!(
+        doc2.marks(&text)?[0].value().to_str(),
+        Some(winner.as_str())
+    );
+    let (obj, typ) = doc1.import(&winner)?;
+    assert_eq!(typ, ObjType::List);
+    assert!(obj == thread1 || obj == thread2);
+    Ok(())
+}
+
+#[test]
+fn verify_integrity_detects_ops_missing_from_the_op_set() -> Result<(), AutomergeError> {
+    let mut doc = AutoCommit::new();
+    let list = doc.put_object(ROOT, "list", ObjType::List)?;
+    doc.insert(&list, 0, "a")?;
+    doc.put(ROOT, "key", 1)?;
+    doc.put(ROOT, "key", 2)?;
+    doc.commit();
+    let mut doc = doc.document().clone();
+    assert!(doc.verify_integrity()?.is_ok());
+
+    let loaded = Automerge::load(&doc.save())?;
+    assert!(loaded.verify_integrity()?.is_ok());
+
+    let obj = doc.exid_to_obj(&list)?.id;
+    doc.ops.remove(&obj, 0);
+    let report = doc.verify_integrity()?;
+    assert_eq!(report.mismatched_objects, vec![list]);
+    assert!(report.invalid_preds.is_empty());
+    Ok(())
+}
+
+#[test]
+fn verify_integrity_returns_the_error_from_rebuilding_the_document() -> Result<(), AutomergeError> {
+    let mut doc = AutoCommit::new();
+    doc.put(ROOT, "key", 1)?;
+    doc.commit();
+    doc.put(ROOT, "key", 2)?;
+    doc.commit();
+    let heads = doc.get_heads();
+    let mut doc = doc.document().clone();
+    doc.trim_changes(&heads)?;
+    assert!(doc.verify_integrity()?.is_ok());
+
+    doc.snapshot.as_mut().unwrap().bytes.truncate(10);
+    assert!(doc.verify_integrity().is_err());
+    Ok(())
+}
+
+#[test]
+fn marks_are_a_flat_list_which_round_trips() -> Result<(), AutomergeError> {
+    let mut doc = AutoCommit::new();
+    let text = doc.put_object(ROOT, "text", ObjType::Text)?;
+    doc.splice_text(&text, 0, 0, "hello world")?;
+    let bold = |start, end| Mark::new("bold".to_string(), true, start, end);
+    doc.mark(&text, bold(0, 3), marks::ExpandMark::None)?;
+    doc.mark(&text, bold(3, 5), marks::ExpandMark::None)?;
+    doc.mark(
+        &text,
+        Mark::new("link".to_string(), "a", 6, 11),
+        marks::ExpandMark::None,
+    )?;
+    doc.unmark(&text, "link", 6, 8, marks::ExpandMark::None)?;
+    doc.mark(&text, bold(4, 4), marks::ExpandMark::None)?;
+    let heads = doc.get_heads();
+    doc.splice_text(&text, 0, 11, "")?;
+
+    let marks = doc.marks_at(&text, &heads)?;
+    assert_eq!(
+        marks,
+        vec![bold(0, 5), Mark::new("link".to_string(), "a", 8, 11)]
+    );
+
+    let mut copy = AutoCommit::new();
+    let copy_text = copy.put_object(ROOT, "text", ObjType::Text)?;
+    copy.splice_text(&copy_text, 0, 0, "hello world")?;
+    for mark in marks {
+        copy.mark(&copy_text, mark.into_owned(), marks::ExpandMark::None)?;
+    }
+    assert_eq!(copy.marks(&copy_text)?, doc.marks_at(&text, &heads)?);
+    Ok(())
+}
+
+#[test]
+fn delete_range_removes_a_run_of_elements() -> Result<(), AutomergeError> {
+    let mut doc = AutoCommit::new();
+    let list = doc.put_object(ROOT, "list", ObjType::List)?;
+    doc.splice(&list, 0, 0, (0..6).map(ScalarValue::Int))?;
+    doc.delete_range(&list, 1..4)?;
+    let values = doc.values(&list).map(|(v, _)| v).collect::<Vec<_>>();
+    assert_eq!(values, vec![Value::int(0), Value::int(4), Value::int(5)]);
+
+    assert!(matches!(
+        doc.delete_range(&list, 2..4),
+        Err(AutomergeError::InvalidIndex(4))
+    ));
+    assert_eq!(doc.length(&list), 3);
+    assert!(matches!(
+        doc.delete_range(ROOT, 0..1),
+        Err(AutomergeError::InvalidOp(ObjType::Map))
+    ));
+    Ok(())
+}
+
+#[test]
+fn list_range_rev_yields_elements_newest_first() -> Result<(), AutomergeError> {
+    let mut doc = AutoCommit::new();
+    let list = doc.put_object(ROOT, "list", ObjType::List)?;
+    doc.splice(&list, 0, 0, (0..5).map(ScalarValue::Int))?;
+    let heads = doc.get_heads();
+    doc.delete(&list, 4)?;
+
+    let rev = |items: ListRangeRev<'_>| items.map(|i| i.index).collect::<Vec<_>>();
+    assert_eq!(rev(doc.list_range_rev(&list, ..)), vec![3, 2, 1, 0]);
+    assert_eq!(rev(doc.list_range_rev(&list, 1..3)), vec![2, 1]);
+    assert_eq!(
+        rev(doc.list_range_rev_at(&list, 2.., &heads)),
+        vec![4, 3, 2]
+    );
+
+    let forward = doc.list_range(&list, ..).collect::<Vec<_>>();
+    let mut backward = doc.list_range_rev(&list, ..).collect::<Vec<_>>();
+    backward.reverse();
+    assert_eq!(
+        forward
+            .iter()
+            .map(|i| (i.index, &i.value))
+            .collect::<Vec<_>>(),
+        backward
+            .iter()
+            .map(|i| (i.index, &i.value))
+            .collect::<Vec<_>>()
+    );
+    Ok(())
+}
+
+#[test]
+fn tombstone_count_counts_hidden_ops() -> Result<(), AutomergeError> {
+    let mut doc = AutoCommit::new();
+    let list = doc.put_object(ROOT, "list", ObjType::List)?;
+    doc.splice(&list, 0, 0, (0..5).map(ScalarValue::Int))?;
+    doc.put(ROOT, "key", 1)?;
+    let heads = doc.get_heads();
+    assert_eq!(doc.tombstone_count(&list)?, 0);
+
+    doc.delete_range(&list, 0..3)?;
+    doc.put(&list, 0, 10)?;
+    doc.put(ROOT, "key", 2)?;
+    assert_eq!(doc.tombstone_count(&list)?, 4);
+    assert_eq!(doc.tombstone_count(ROOT)?, 1);
+    assert_eq!(doc.total_tombstones(), 5);
+    assert_eq!(doc.length(&list), 2);
+
+    let doc = doc.document();
+    let at = crate::automerge::diff::ReadDocAt { doc, heads: &heads };
+    assert_eq!(at.total_tombstones(), 0);
+    Ok(())
+}
+
+#[test]
+fn applying_a_change_to_a_missing_object_names_the_op_and_object() -> Result<(), AutomergeError> {
+    let mut doc1 = AutoCommit::new();
+    let map = doc1.put_object(ROOT, "map", ObjType::Map)?;
+    doc1.commit();
+    doc1.put(&map, "key", "value")?;
+    doc1.commit();
+
+    // detach the change which writes into `map` from the change which created it
+    let mut expanded = doc1.get_last_local_change().unwrap().decode();
+    expanded.actor_id = ActorId::random();
+    expanded.seq = 1;
+    expanded.deps.clear();
+    expanded.hash = None;
+    let change = Change::from(expanded);
+
+    let mut doc2 = Automerge::new();
+    let err = doc2.apply_changes([change]).unwrap_err();
+    assert!(matches!(
+        &err,
+        AutomergeError::MissingObject { obj, .. } if obj == &map
+    ));
+    assert!(doc2.get_heads().is_empty());
+    Ok(())
+}
+
+#[test]
+fn put_object_init_populates_the_new_object() -> Result<(), AutomergeError> {
+    let mut doc = AutoCommit::new();
+    let config = doc.put_object_init(ROOT, "config", ObjType::Map, |doc, config| {
+        doc.put(config, "name", "test")?;
+        doc.put_object_init(config, "tags", ObjType::List, |doc, tags| {
+            doc.splice(tags, 0, 0, ["a".into(), "b".into()])
+        })?;
+        Ok(())
+    })?;
+    assert_eq!(doc.get(&config, "name")?.unwrap().0, Value::str("test"));
+    let (_, tags) = doc.get(&config, "tags")?.unwrap();
+    assert_eq!(doc.length(&tags), 2);
+
+    let err = doc
+        .put_object_init(ROOT, "bad", ObjType::Map, |doc, obj| doc.insert(obj, 0, 1))
+        .unwrap_err();
+    assert!(matches!(err, AutomergeError::InvalidOp(ObjType::Map)));
+    Ok(())
+}
+
+#[test]
+fn reads_with_a_precomputed_clock_match_reads_at_heads() -> Result<(), AutomergeError> {
+    let mut doc = AutoCommit::new();
+    let list = doc.put_object(ROOT, "list", ObjType::List)?;
+    doc.splice(&list, 0, 0, (0..3).map(ScalarValue::Int))?;
+    let text = doc.put_object(ROOT, "text", ObjType::Text)?;
+    doc.splice_text(&text, 0, 0, "hello")?;
+    let heads = doc.get_heads();
+    doc.delete(&list, 0)?;
+    doc.splice_text(&text, 5, 0, " world")?;
+    doc.put(ROOT, "new", true)?;
+
+    let doc = doc.document();
+    let clock = doc.clock_for_heads(&heads)?;
+    assert_eq!(
+        doc.keys_with_clock(ROOT, &clock).collect::<Vec<_>>(),
+        doc.keys_at(ROOT, &heads).collect::<Vec<_>>()
+    );
+    assert_eq!(doc.get_with_clock(ROOT, "new", &clock)?, None);
+    assert_eq!(doc.length_with_clock(&list, &clock), 3);
+    assert_eq!(
+        doc.list_range_with_clock(&list, .., &clock)
+            .map(|item| item.value)
+            .collect::<Vec<_>>(),
+        doc.values_with_clock(&list, &clock)
+            .map(|(v, _)| v)
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(doc.text_with_clock(&text, &clock)?, "hello");
+
+    let unknown = ChangeHash([1; 32]);
+    assert!(matches!(
+        doc.clock_for_heads(&[unknown]),
+        Err(AutomergeError::MissingHash(h)) if h == unknown
+    ));
+    Ok(())
+}
+
+#[test]
+fn index_of_finds_the_position_of_an_element() -> Result<(), AutomergeError> {
+    let mut doc = AutoCommit::new();
+    let list = doc.put_object(ROOT, "list", ObjType::List)?;
+    doc.splice(&list, 0, 0, ["a".into(), "b".into()])?;
+    let item = doc.insert_object(&list, 1, ObjType::Map)?;
+    let (_, b) = doc.get(&list, 2)?.unwrap();
+    assert_eq!(doc.index_of(&list, &item, None)?, Some(1));
+    assert_eq!(doc.index_of(&list, &b, None)?, Some(2));
+    let heads = doc.get_heads();
+
+    doc.delete(&list, 0)?;
+    doc.delete(&list, 1)?;
+    assert_eq!(doc.index_of(&list, &item, None)?, Some(0));
+    assert_eq!(doc.index_of(&list, &b, None)?, None);
+    assert_eq!(doc.index_of(&list, &b, Some(&heads))?, Some(2));
+
+    assert!(matches!(
+        doc.index_of(ROOT, &item, None),
+        Err(AutomergeError::InvalidOp(ObjType::Map))
+    ));
+    Ok(())
+}
+
+#[test]
+fn apply_changes_streaming_emits_the_same_patches() -> Result<(), AutomergeError> {
+    let mut remote = AutoCommit::new();
+    let text = remote.put_object(ROOT, "text", ObjType::Text)?;
+    remote.splice_text(&text, 0, 0, "hello")?;
+    let list = remote.put_object(ROOT, "list", ObjType::List)?;
+    remote.splice(&list, 0, 0, (0..10).map(ScalarValue::Int))?;
+    remote.put(ROOT, "key", "value")?;
+    let changes = remote
+        .get_changes(&[])
+        .into_iter()
+        .cloned()
+        .collect::<Vec<_>>();
+
+    let mut expected_doc = Automerge::new();
+    let mut patch_log = PatchLog::active(patches::TextRepresentation::String);
+    expected_doc.apply_changes_log_patches(changes.clone(), &mut patch_log)?;
+    let expected = expected_doc.make_patches(&mut patch_log);
+
+    let mut doc = Automerge::new();
+    let mut patch_log = PatchLog::active(patches::TextRepresentation::String);
+    let mut streamed = Vec::new();
+    let flow = doc.apply_changes_streaming(changes.clone(), &mut patch_log, |patch| {
+        streamed.push(patch);
+        ControlFlow::Continue(())
+    })?;
+    assert_eq!(flow, ControlFlow::Continue(()));
+    assert_eq!(streamed, expected);
+
+    let mut doc = Automerge::new();
+    let mut patch_log = PatchLog::active(patches::TextRepresentation::String);
+    let mut count = 0;
+    let flow = doc.apply_changes_streaming(changes, &mut patch_log, |_| {
+        count += 1;
+        ControlFlow::Break(())
+    })?;
+    assert_eq!(flow, ControlFlow::Break(()));
+    assert_eq!(count, 1);
+    // every change is applied even though patch generation stopped early
+    assert_eq!(doc.get_heads(), remote.get_heads());
+    Ok(())
+}
+
+#[test]
+fn missing_deps_report_finds_gaps_in_an_actors_sequence() -> Result<(), AutomergeError> {
+    let mut doc1 = AutoCommit::new();
+    for i in 0..7 {
+        doc1.put(ROOT, "key", i)?;
+        doc1.commit();
+    }
+    let changes = doc1
+        .get_changes(&[])
+        .into_iter()
+        .cloned()
+        .collect::<Vec<_>>();
+
+    let mut doc2 = Automerge::new();
+    doc2.apply_changes([0, 1, 4, 6].map(|i| changes[i].clone()))?;
+    let report = doc2.missing_deps_report();
+    assert_eq!(
+        report.get(doc1.get_actor()),
+        Some(&SeqReport {
+            applied: 2,
+            queued: vec![5, 7],
+            missing: vec![3..5, 6..7],
+        })
+    );
+
+    doc2.apply_changes([2, 3, 5].map(|i| changes[i].clone()))?;
+    let report = doc2.missing_deps_report();
+    assert_eq!(
+        report[doc1.get_actor()],
+        SeqReport {
+            applied: 7,
+            ..Default::default()
+        }
+    );
+    Ok(())
+}
+
+#[test]
+fn missing_deps_report_reports_huge_gaps_as_ranges() -> Result<(), AutomergeError> {
+    let mut doc1 = AutoCommit::new();
+    doc1.put(ROOT, "key", "value")?;
+    doc1.commit();
+    let mut expanded = doc1.get_last_local_change().unwrap().decode();
+    expanded.seq = u64::MAX;
+    expanded.deps = vec![ChangeHash([1; 32])];
+    expanded.hash = None;
+    let change = Change::from(expanded);
+
+    let mut doc2 = Automerge::new();
+    doc2.apply_changes([change])?;
+    let report = &doc2.missing_deps_report()[doc1.get_actor()];
+    assert_eq!(report.applied, 0);
+    assert_eq!(report.queued, vec![u64::MAX]);
+    assert_eq!(report.missing.len(), 1);
+    assert_eq!(report.missing[0], 1..u64::MAX);
+    Ok(())
+}
+
+#[test]
+fn expand_mark_controls_each_boundary_independently() -> Result<(), AutomergeError> {
+    for (before, after) in [(false, false), (true, false), (false, true), (true, true)] {
+        let expand = marks::ExpandMark::from(before, after);
+        assert_eq!((expand.before(), expand.after()), (before, after));
+
+        let mut doc = AutoCommit::new();
+        let text = doc.put_object(ROOT, "text", ObjType::Text)?;
+        doc.splice_text(&text, 0, 0, "abc")?;
+        let mark = Mark::new("bold".to_string(), true, 1, 2);
+        doc.mark(&text, mark, expand)?;
+        // insert at the end of the mark and then at the start
+        doc.splice_text(&text, 2, 0, "E")?;
+        doc.splice_text(&text, 1, 0, "S")?;
+        assert_eq!(doc.text(&text)?, "aSbEc");
+
+        let marks = doc.marks(&text)?;
+        assert_eq!(marks.len(), 1);
+        let start = if before { 1 } else { 2 };
+        let end = if after { 4 } else { 3 };
+        assert_eq!((marks[0].start, marks[0].end), (start, end), "{:?}", expand);
+    }
+    Ok(())
+}
+
+#[test]
+fn op_tree_node_size_does_not_change_the_document() -> Result<(), AutomergeError> {
+    let edit = |doc: &mut AutoCommit| -> Result<ExId, AutomergeError> {
+        let list = doc.put_object(ROOT, "list", ObjType::List)?;
+        for i in 0..200_usize {
+            doc.insert(&list, (i * 7) % (i + 1), i as i64)?;
+        }
+        for i in 0..100_usize {
+            doc.delete(&list, (i * 13) % (200 - i))?;
+        }
+        let text = doc.put_object(ROOT, "text", ObjType::Text)?;
+        doc.splice_text(&text, 0, 0, &"abcdefghij".repeat(20))?;
+        doc.splice_text(&text, 50, 100, "")?;
+        Ok(list)
+    };
+    let mut expected = AutoCommit::new().with_actor(ActorId::from([1]));
+    let list = edit(&mut expected)?;
+    for size in [2, 3, 64] {
+        let mut doc = AutoCommit::new()
+            .with_actor(ActorId::from([1]))
+            .with_op_tree_node_size(size);
+        edit(&mut doc)?;
+        assert_eq!(doc.save(), expected.save());
+    }
+
+    // rebuilding the trees of an existing document keeps its contents
+    let mut doc = Automerge::load(&expected.save())?;
+    doc.set_op_tree_node_size(2);
+    assert_eq!(
+        doc.list_range(&list, ..)
+            .map(|i| i.value)
+            .collect::<Vec<_>>(),
+        expected
+            .list_range(&list, ..)
+            .map(|i| i.value)
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(doc.save(), expected.save());
+    Ok(())
+}
+
+#[test]
+fn observe_ops_shows_the_staged_ops() -> Result<(), AutomergeError> {
+    use crate::transaction::{PendingKey, PendingOpView};
+
+    let mut doc = Automerge::new();
+    let mut tx = doc.transaction();
+    let list = tx.put_object(ROOT, "list", ObjType::List)?;
+    tx.insert(&list, 0, "a")?;
+    tx.delete(&list, 0)?;
+    let ops = tx.observe_ops().collect::<Vec<_>>();
+    assert_eq!(ops.len(), 3);
+    assert_eq!(
+        ops[0],
+        PendingOpView {
+            id: list.clone(),
+            obj: ROOT,
+            key: PendingKey::Map("list"),
+            insert: false,
+            action: &OpType::Make(ObjType::List),
+        }
+    );
+    assert_eq!(ops[1].obj, list);
+    assert_eq!(ops[1].key, PendingKey::Seq(None));
+    assert!(ops[1].insert);
+    assert_eq!(ops[1].action, &OpType::Put("a".into()));
+    assert_eq!(ops[2].key, PendingKey::Seq(Some(ops[1].id.clone())));
+    assert_eq!(ops[2].action, &OpType::Delete);
+
+    let savepoint = tx.savepoint();
+    tx.put(ROOT, "forbidden", 1)?;
+    assert_eq!(tx.observe_ops().count(), 4);
+    tx.rollback_to(savepoint);
+    assert_eq!(tx.observe_ops().count(), 3);
+    tx.commit();
+    Ok(())
+}
+
+#[test]
+fn get_changes_in_range_returns_changes_between_two_cuts() -> Result<(), AutomergeError> {
+    let mut doc = AutoCommit::new();
+    doc.put(ROOT, "a", 1)?;
+    doc.commit();
+    let start = doc.get_heads();
+    let mut fork = doc.fork();
+    doc.put(ROOT, "b", 2)?;
+    doc.commit();
+    let middle = doc.get_heads();
+    doc.put(ROOT, "c", 3)?;
+    doc.commit();
+    fork.put(ROOT, "d", 4)?;
+    fork.commit();
+    let fork_heads = fork.get_heads();
+    doc.merge(&mut fork)?;
+    let end = doc.get_heads();
+
+    let hashes = |changes: Vec<&Change>| changes.iter().map(|c| c.hash()).collect::<Vec<_>>();
+    let doc = doc.document();
+    assert_eq!(hashes(doc.get_changes_in_range(&start, &middle)?), middle);
+    assert_eq!(
+        hashes(doc.get_changes_in_range(&start, &end)?),
+        hashes(doc.get_changes(&start))
+    );
+    // the fork's own change isn't needed to go from the fork to `middle`
+    assert_eq!(
+        hashes(doc.get_changes_in_range(&fork_heads, &middle)?),
+        middle
+    );
+    assert!(doc.get_changes_in_range(&end, &start)?.is_empty());
+
+    let unknown = ChangeHash([7; 32]);
+    assert!(matches!(
+        doc.get_changes_in_range(&start, &[unknown]),
+        Err(AutomergeError::MissingHash(h)) if h == unknown
+    ));
+    Ok(())
+}
+
+#[test]
+fn change_metadata_reports_sizes_without_decoding() -> Result<(), AutomergeError> {
+    let mut doc = AutoCommit::new();
+    doc.put(ROOT, "a", 1)?;
+    doc.commit_with(CommitOptions::default().with_message("small"));
+    let list = doc.put_object(ROOT, "list", ObjType::List)?;
+    for i in 0..200 {
+        doc.insert(&list, i, "the same string over and over again")?;
+    }
+    doc.commit();
+
+    let mut changes = doc
+        .get_changes(&[])
+        .into_iter()
+        .cloned()
+        .collect::<Vec<_>>();
+    let stats = doc.document().change_metadata().collect::<Vec<_>>();
+    assert_eq!(stats.len(), 2);
+    for (stat, change) in stats.iter().zip(changes.iter_mut()) {
+        assert_eq!(stat.hash, change.hash());
+        assert_eq!(&stat.actor, change.actor_id());
+        assert_eq!(stat.seq, change.seq());
+        assert_eq!(stat.op_count, change.len());
+        assert_eq!(stat.size, change.raw_bytes().len());
+        assert_eq!(stat.compressed_size, change.bytes().len());
+    }
+    assert_eq!(stats[0].message.as_deref(), Some("small"));
+    assert_eq!(stats[1].op_count, 201);
+    assert!(stats[1].compressed_size < stats[1].size);
+    Ok(())
+}
+
+#[test]
+fn text_cow_is_invalidated_by_edits_and_rollback() -> Result<(), AutomergeError> {
+    let mut doc = AutoCommit::new();
+    let text = doc.put_object(ROOT, "text", ObjType::Text)?;
+    doc.splice_text(&text, 0, 0, "abc")?;
+    doc.commit();
+    assert_eq!(doc.text_cow(&text)?, "abc");
+
+    doc.splice_text(&text, 3, 0, "d")?;
+    assert_eq!(doc.text_cow(&text)?, "abcd");
+    doc.rollback();
+    assert_eq!(doc.text_cow(&text)?, "abc");
+    doc.splice_text(&text, 0, 1, "x")?;
+    assert_eq!(doc.text_cow(&text)?, "xbc");
+
+    let mut other = doc.fork();
+    other.splice_text(&text, 0, 0, "y")?;
+    doc.merge(&mut other)?;
+    assert_eq!(doc.text_cow(&text)?, "yxbc");
+
+    let heads = doc.get_heads();
+    doc.splice_text(&text, 0, 0, "z")?;
+    doc.commit();
+    doc.isolate(&heads);
+    assert!(matches!(doc.text_cow(&text)?, Cow::Owned(s) if s == "yxbc"));
+    Ok(())
+}
+
+#[test]
+fn text_cow_is_cached_per_object() -> Result<(), AutomergeError> {
+    let mut doc = AutoCommit::new();
+    let texts = (0..20)
+        .map(|i| {
+            let text = doc.put_object(ROOT, format!("text{}", i), ObjType::Text)?;
+            doc.splice_text(&text, 0, 0, &i.to_string())?;
+            Ok(text)
+        })
+        .collect::<Result<Vec<_>, AutomergeError>>()?;
+    doc.commit();
+
+    assert!(matches!(doc.text_cow(&texts[19])?, Cow::Owned(s) if s == "19"));
+    assert!(matches!(doc.text_cow(&texts[19])?, Cow::Borrowed("19")));
+    // changes to other objects leave the cached text alone
+    doc.splice_text(&texts[18], 0, 0, "x")?;
+    doc.put(ROOT, "key", "value")?;
+    assert!(matches!(doc.text_cow(&texts[19])?, Cow::Borrowed("19")));
+    assert!(matches!(doc.text_cow(&texts[18])?, Cow::Owned(s) if s == "x18"));
+    // maps are never cached
+    assert!(matches!(doc.text_cow(ROOT)?, Cow::Owned(_)));
+
+    // reading more objects than are cached drops the ones changed least recently
+    for text in &texts {
+        doc.text_cow(text)?;
+    }
+    assert!(matches!(doc.text_cow(&texts[0])?, Cow::Owned(s) if s == "0"));
+    assert!(matches!(doc.text_cow(&texts[17])?, Cow::Borrowed("17")));
+    Ok(())
+}
+
+#[test]
+fn obj_view_reads_match_read_doc() -> Result<(), AutomergeError> {
+    let mut doc = AutoCommit::new();
+    let map = doc.put_object(ROOT, "map", ObjType::Map)?;
+    doc.put(&map, "a", 1)?;
+    doc.put(&map, "b", "two")?;
+    doc.commit();
+    let heads = doc.get_heads();
+    doc.put(&map, "c", 3.0)?;
+    doc.delete(&map, "a")?;
+
+    let view = doc.view(&map)?;
+    assert_eq!(view.id(), &map);
+    assert_eq!(view.object_type(), ObjType::Map);
+    assert_eq!(view.length(), doc.length(&map));
+    assert_eq!(
+        view.keys().collect::<Vec<_>>(),
+        doc.keys(&map).collect::<Vec<_>>()
+    );
+    assert_eq!(
+        view.values().collect::<Vec<_>>(),
+        doc.values(&map).collect::<Vec<_>>()
+    );
+    assert_eq!(view.get("c"), doc.get(&map, "c")?);
+    assert_eq!(view.get(0), None);
+
+    let old = doc.view_at(&map, &heads)?;
+    assert_eq!(old.keys().collect::<Vec<_>>(), vec!["a", "b"]);
+    assert_eq!(old.get("c"), None);
+
+    let at = crate::automerge::diff::ReadDocAt {
+        doc: doc.document(),
+        heads: &heads,
+    };
+    assert_eq!(at.view(&map)?.get("a").map(|(v, _)| v), Some(1.into()));
+
+    let missing = ExId::Id(99, doc.get_actor().clone(), 0);
+    assert!(doc.view(&missing).is_err());
+    Ok(())
+}
+
+#[test]
+fn splice_text_with_ids_returns_the_inserted_elements() -> Result<(), AutomergeError> {
+    let mut doc = AutoCommit::new();
+    let text = doc.put_object(ROOT, "text", ObjType::Text)?;
+    doc.splice_text(&text, 0, 0, "ad")?;
+    let ids = doc.splice_text_with_ids(&text, 1, "bc")?;
+    assert_eq!(doc.text(&text)?, "abcd");
+    assert_eq!(ids.len(), 2);
+    for (offset, id) in ids.iter().enumerate() {
+        assert_eq!(doc.get(&text, 1 + offset)?.unwrap().1, *id);
+        assert_eq!(doc.index_of(&text, id, None)?, Some(1 + offset));
+    }
+
+    let mut tx_doc = Automerge::new();
+    let mut tx = tx_doc.transaction();
+    let text = tx.put_object(ROOT, "text", ObjType::Text)?;
+    tx.splice_text(&text, 0, 0, "xy")?;
+    let (first, last) = tx.splice_text_with_id_range(&text, 1, "123")?.unwrap();
+    assert_eq!(tx.index_of(&text, &first, None)?, Some(1));
+    assert_eq!(tx.index_of(&text, &last, None)?, Some(3));
+    assert_eq!(tx.splice_text_with_id_range(&text, 0, "")?, None);
+    assert!(tx.splice_text_with_ids(&text, 0, "")?.is_empty());
+    tx.commit();
+    assert_eq!(tx_doc.text(&text)?, "x123y");
+    Ok(())
+}
+
+#[test]
+fn merge_object_only_applies_changes_to_the_subtree() -> Result<(), AutomergeError> {
+    let mut doc = AutoCommit::new();
+    let notes = doc.put_object(ROOT, "notes", ObjType::Map)?;
+    doc.put(ROOT, "title", "draft")?;
+    doc.commit();
+    let mut fork = doc.fork();
+    let list = fork.put_object(&notes, "items", ObjType::List)?;
+    fork.commit();
+    fork.insert(&list, 0, "first")?;
+    fork.commit();
+    fork.put(ROOT, "title", "final")?;
+    fork.commit();
+
+    let mut doc = doc.document().clone();
+    let fork_doc = fork.document().clone();
+    let applied = doc.merge_object(&fork_doc, &notes)?;
+    assert_eq!(applied.len(), 2);
+    assert_eq!(doc.get(&list, 0)?.unwrap().0, "first".into());
+    assert_eq!(doc.get(ROOT, "title")?.unwrap().0, "draft".into());
+    // merging again finds nothing new to apply
+    assert!(doc.merge_object(&fork_doc, &notes)?.is_empty());
+
+    fork.put(&notes, "a", 1)?;
+    fork.put(ROOT, "b", 2)?;
+    fork.commit();
+    let mixed = fork.get_heads()[0];
+    let heads = doc.get_heads();
+    assert!(matches!(
+        doc.merge_object(fork.document(), &notes),
+        Err(AutomergeError::ChangeOutsideSubtree(h)) if h == mixed
+    ));
+    assert_eq!(doc.get_heads(), heads);
+    Ok(())
+}
+
+#[test]
+fn merge_object_rejects_changes_depending_on_other_objects() -> Result<(), AutomergeError> {
+    let mut doc = AutoCommit::new();
+    doc.commit();
+    let mut fork = doc.fork();
+    let created = fork.put_object(ROOT, "new", ObjType::Map)?;
+    fork.commit();
+    fork.put(&created, "key", "value")?;
+    fork.commit();
+    let mut doc = doc.document().clone();
+    assert!(matches!(
+        doc.merge_object(fork.document(), &created),
+        Err(AutomergeError::MissingDeps)
+    ));
+    Ok(())
+}
+
+#[test]
+fn merge_object_brings_along_the_empty_changes_it_depends_on() -> Result<(), AutomergeError> {
+    let mut doc = AutoCommit::new();
+    let notes = doc.put_object(ROOT, "notes", ObjType::Map)?;
+    doc.commit();
+    let mut fork = doc.fork();
+    let empty = fork.empty_change(CommitOptions::default());
+    fork.put(&notes, "key", "value")?;
+    fork.commit();
+    let edit = fork.get_heads()[0];
+
+    let mut doc = doc.document().clone();
+    let applied = doc.merge_object(fork.document(), &notes)?;
+    assert_eq!(applied, vec![empty, edit]);
+    assert_eq!(doc.get(&notes, "key")?.unwrap().0, "value".into());
+    assert_eq!(doc.get_heads(), vec![edit]);
+
+    // an empty change nothing depends on is left out
+    let mut fork = AutoCommit::load(&doc.save())?;
+    fork.empty_change(CommitOptions::default());
+    assert!(doc.merge_object(fork.document(), &notes)?.is_empty());
+
+    // trimmed changes are still dependencies which are present
+    let mut fork = AutoCommit::load(&doc.save())?;
+    fork.put(&notes, "other", "value")?;
+    fork.commit();
+    let mut tx = doc.transaction();
+    tx.put(ROOT, "title", "draft")?;
+    tx.commit();
+    doc.trim_changes(&doc.get_heads())?;
+    assert_eq!(doc.merge_object(fork.document(), &notes)?.len(), 1);
+    assert_eq!(doc.get(&notes, "other")?.unwrap().0, "value".into());
+    Ok(())
+}
+
+#[test]
+fn timestamps_can_be_read_and_bumped_in_place() -> Result<(), AutomergeError> {
+    let mut doc = AutoCommit::new();
+    doc.put(ROOT, "at", ScalarValue::Timestamp(100))?;
+    doc.put(ROOT, "int", 100)?;
+    doc.commit();
+    let heads = doc.get_heads();
+    assert_eq!(doc.get_timestamp(ROOT, "at")?, Some(100));
+    assert_eq!(doc.get_timestamp(ROOT, "int")?, None);
+    assert_eq!(doc.get_timestamp(ROOT, "missing")?, None);
+
+    assert_eq!(doc.bump_timestamp(ROOT, "at", -40)?, 60);
+    assert_eq!(doc.get_timestamp(ROOT, "at")?, Some(60));
+    assert_eq!(
+        doc.get(ROOT, "at")?.unwrap().0,
+        Value::Scalar(Cow::Owned(ScalarValue::Timestamp(60)))
+    );
+    assert!(matches!(
+        doc.bump_timestamp(ROOT, "int", 1),
+        Err(AutomergeError::InvalidValueType { .. })
+    ));
+    assert!(matches!(
+        doc.bump_timestamp(ROOT, "missing", 1),
+        Err(AutomergeError::InvalidValueType { .. })
+    ));
+    assert!(matches!(
+        doc.bump_timestamp(ROOT, "at", i64::MAX),
+        Err(AutomergeError::InvalidValueType { .. })
+    ));
+    doc.commit();
+    let at = crate::automerge::diff::ReadDocAt {
+        doc: doc.document(),
+        heads: &heads,
+    };
+    assert_eq!(at.get_timestamp(ROOT, "at")?, Some(100));
+    Ok(())
+}
+
+#[test]
+fn save_with_compression_levels() -> Result<(), AutomergeError> {
+    let mut doc = AutoCommit::new();
+    let list = doc.put_object(ROOT, "list", ObjType::List)?;
+    for i in 0..2000 {
+        doc.insert(&list, i, format!("item {}", i % 17))?;
+    }
+    doc.commit();
+    let doc = doc.document();
+
+    let save = |compression| {
+        doc.save_with_options(SaveOptions {
+            compression,
+            ..Default::default()
+        })
+    };
+    let none = save(CompressionLevel::None);
+    let fast = save(CompressionLevel::Fast);
+    let best = save(CompressionLevel::Best);
+    assert_eq!(save(CompressionLevel::Default), doc.save());
+    assert_eq!(none, doc.save_nocompress());
+    assert!(fast.len() < none.len());
+    assert!(best.len() <= fast.len());
+    for bytes in [none, fast, best] {
+        let loaded = Automerge::load(&bytes)?;
+        assert_eq!(loaded.get_heads(), doc.get_heads());
+        assert_eq!(loaded.hydrate(None), doc.hydrate(None));
+    }
+    Ok(())
+}
+
+#[test]
+fn get_conflicts_reports_the_author_of_each_value() -> Result<(), AutomergeError> {
+    let alice = ActorId::from(b"alice");
+    let bob = ActorId::from(b"bob");
+    let mut doc1 = AutoCommit::new().with_actor(alice.clone());
+    doc1.put(ROOT, "key", "base")?;
+    let mut doc2 = doc1.fork().with_actor(bob.clone());
+    doc1.put(ROOT, "key", "one")?;
+    doc2.put(ROOT, "key", "two")?;
+    let before_merge = doc1.get_heads();
+    doc1.merge(&mut doc2)?;
+
+    let conflicts = doc1.get_conflicts(ROOT, "key")?;
+    let all = doc1.get_all(ROOT, "key")?;
+    assert_eq!(conflicts.len(), 2);
+    for ((actor, value, id), (all_value, all_id)) in conflicts.iter().zip(all.iter()) {
+        assert_eq!(value, all_value);
+        assert_eq!(id, all_id);
+        let expected = if value.to_str() == Some("one") {
+            &alice
+        } else {
+            &bob
+        };
+        assert_eq!(actor, expected);
+    }
+
+    let authors = conflicts
+        .into_iter()
+        .map(|(actor, _, id)| (actor, id))
+        .collect::<Vec<_>>();
+    let mut raw = doc1.document().clone();
+    let raw_authors = raw
+        .get_conflicts(ROOT, "key")?
+        .into_iter()
+        .map(|(actor, _, id)| (actor, id))
+        .collect::<Vec<_>>();
+    assert_eq!(raw_authors, authors);
+    let tx = raw.transaction();
+    assert_eq!(tx.get_conflicts(ROOT, "key")?.len(), 2);
+    tx.rollback();
+
+    let heads = vec![before_merge[0]];
+    let at = crate::automerge::diff::ReadDocAt {
+        doc: doc1.document(),
+        heads: &heads,
+    };
+    let conflicts = at.get_conflicts(ROOT, "key")?;
+    assert_eq!(conflicts.len(), 1);
+    assert_eq!(conflicts[0].0, alice);
+    assert!(doc1.get_conflicts(ROOT, "missing")?.is_empty());
+    Ok(())
+}
+
+#[test]
+fn load_and_log_patches_creates_parents_before_children() -> Result<(), AutomergeError> {
+    let mut doc = AutoCommit::new();
+    let map = doc.put_object(ROOT, "map", ObjType::Map)?;
+    let list = doc.put_object(&map, "list", ObjType::List)?;
+    let text = doc.insert_object(&list, 0, ObjType::Text)?;
+    doc.splice_text(&text, 0, 0, "hello")?;
+    doc.insert(&list, 1, 1)?;
+    doc.put(ROOT, "key", "value")?;
+
+    let mut patch_log = PatchLog::active(TextRepresentation::String);
+    let loaded = Automerge::load_and_log_patches(&doc.save(), &mut patch_log)?;
+    let patches = loaded.make_patches(&mut patch_log);
+
+    let mut created = HashSet::from([ExId::Root]);
+    for patch in &patches {
+        assert!(
+            created.contains(&patch.obj),
+            "{:?} before its parent",
+            patch
+        );
+        match &patch.action {
+            PatchAction::PutMap { value, .. } | PatchAction::PutSeq { value, .. } => {
+                created.insert(value.1.clone());
+            }
+            PatchAction::Insert { values, .. } => {
+                created.extend(values.iter().map(|(_, id, _)| id.clone()));
+            }
+            _ => {}
+        }
+    }
+    assert!(created.contains(&text));
+
+    let mut empty = Automerge::new();
+    let mut replay_log = PatchLog::active(TextRepresentation::String);
+    empty.load_incremental_log_patches(&doc.save(), &mut replay_log)?;
+    assert_eq!(empty.make_patches(&mut replay_log), patches);
+    Ok(())
+}
+
+#[test]
+fn commit_if_heads_only_commits_at_the_expected_heads() -> Result<(), AutomergeError> {
+    let mut doc = Automerge::new();
+    let mut tx = doc.transaction();
+    tx.put(ROOT, "key", "one")?;
+    tx.commit();
+    let expected = doc.get_heads();
+
+    let mut tx = doc.transaction();
+    tx.put(ROOT, "key", "two")?;
+    let hash = tx.commit_if_heads(&expected)?;
+    assert_eq!(hash.map(|h| vec![h]), Some(doc.get_heads()));
+
+    let mut tx = doc.transaction();
+    tx.put(ROOT, "key", "three")?;
+    assert_eq!(tx.commit_if_heads(&expected)?, None);
+    assert_eq!(doc.get(ROOT, "key")?.unwrap().0, "two".into());
+
+    let unknown = ChangeHash([7; 32]);
+    let mut tx = doc.transaction();
+    tx.put(ROOT, "key", "four")?;
+    assert!(matches!(
+        tx.commit_if_heads(&[unknown]),
+        Err(AutomergeError::MissingHash(h)) if h == unknown
+    ));
+    assert_eq!(doc.get(ROOT, "key")?.unwrap().0, "two".into());
+    Ok(())
+}
+
+#[test]
+fn get_marks_picks_the_most_recent_of_overlapping_marks() -> Result<(), AutomergeError> {
+    let mut doc1 = AutoCommit::new().with_actor(ActorId::from(b"aaaa"));
+    let text = doc1.put_object(ROOT, "text", ObjType::Text)?;
+    doc1.splice_text(&text, 0, 0, "the quick brown fox jumps")?;
+    doc1.commit();
+    let mut doc2 = doc1.fork().with_actor(ActorId::from(b"bbbb"));
+    for i in 0..10 {
+        let mark = marks::Mark::new("bold".into(), i % 3 == 0, i, i + 8);
+        doc1.mark(&text, mark, marks::ExpandMark::None)?;
+        let mark = marks::Mark::new("color".into(), format!("c{}", i), i * 2, i * 2 + 5);
+        doc2.mark(&text, mark, marks::ExpandMark::None)?;
+        let mark = marks::Mark::new("bold".into(), true, 20 - i, 22 - i);
+        doc2.mark(&text, mark, marks::ExpandMark::None)?;
+    }
+    doc2.unmark(&text, "color", 3, 6, marks::ExpandMark::None)?;
+    let before_merge = doc1.get_heads();
+    doc1.merge(&mut doc2)?;
+
+    for heads in [None, Some(before_merge)] {
+        let marks = match &heads {
+            Some(h) => doc1.marks_at(&text, h)?,
+            None => doc1.marks(&text)?,
+        };
+        for index in 0..doc1.length(&text) {
+            let expected = marks
+                .iter()
+                .filter(|m| m.start <= index && index < m.end)
+                .map(|m| (m.name().to_string(), m.value().clone()))
+                .collect::<marks::MarkSet>();
+            let actual = doc1
+                .get_marks(&text, index, heads.as_deref())?
+                .iter()
+                .filter(|(_, value)| !value.is_null())
+                .map(|(name, value)| (name.to_string(), value.clone()))
+                .collect::<marks::MarkSet>();
+            assert_eq!(actual, expected, "at index {}", index);
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn common_ancestor_heads_finds_the_merge_base() -> Result<(), AutomergeError> {
+    let mut doc1 = AutoCommit::new();
+    doc1.put(ROOT, "key", "base")?;
+    doc1.commit();
+    let mut doc2 = doc1.fork();
+    let mut doc3 = doc1.fork();
+    doc2.put(ROOT, "two", 2)?;
+    doc2.commit();
+    doc3.put(ROOT, "three", 3)?;
+    doc3.commit();
+    // doc1 and doc2 both see the concurrent changes of doc2 and doc3
+    doc1.merge(&mut doc2)?;
+    doc1.merge(&mut doc3)?;
+    doc2.merge(&mut doc3)?;
+    let mut expected = doc2.get_heads();
+    expected.sort();
+    doc1.put(ROOT, "one", 1)?;
+    doc1.commit();
+    doc2.put(ROOT, "two", 22)?;
+    doc2.commit();
+
+    let base = doc1.document().common_ancestor_heads(doc2.document());
+    assert_eq!(base.len(), 2);
+    assert_eq!(base, expected);
+    assert_eq!(doc2.document().common_ancestor_heads(doc1.document()), base);
+
+    let mut unrelated = AutoCommit::new();
+    unrelated.put(ROOT, "key", "value")?;
+    unrelated.commit();
+    assert!(doc1
+        .document()
+        .common_ancestor_heads(unrelated.document())
+        .is_empty());
+    let copy = doc1.document().clone();
+    assert_eq!(
+        doc1.document().common_ancestor_heads(&copy),
+        doc1.get_heads()
+    );
+    Ok(())
+}
+
+#[test]
+fn apply_raw_ops_builds_a_change_from_valid_ops() -> Result<(), AutomergeError> {
+    let mut doc = Automerge::new();
+    let mut tx = doc.transaction();
+    let list = tx.put_object(ROOT, "list", ObjType::List)?;
+    tx.insert(&list, 0, "a")?;
+    tx.put(ROOT, "counter", ScalarValue::counter(1))?;
+    tx.commit();
+    let first = doc.get(&list, 0)?.unwrap().1;
+    let counter = doc.get(ROOT, "counter")?.unwrap().1;
+
+    let actor = ActorId::from(b"bridge");
+    let map = doc.raw_op_id(&actor, 0);
+    let elem = doc.raw_op_id(&actor, 2);
+    let ops = vec![
+        RawOp {
+            obj: ROOT,
+            key: RawKey::Map("map".into()),
+            insert: false,
+            action: RawAction::Make(ObjType::Map),
+            pred: vec![],
+        },
+        RawOp {
+            obj: map.clone(),
+            key: RawKey::Map("key".into()),
+            insert: false,
+            action: RawAction::Put("value".into()),
+            pred: vec![],
+        },
+        RawOp {
+            obj: list.clone(),
+            key: RawKey::Elem(first.clone()),
+            insert: true,
+            action: RawAction::Put("b".into()),
+            pred: vec![],
+        },
+        RawOp {
+            obj: list.clone(),
+            key: RawKey::Elem(elem.clone()),
+            insert: false,
+            action: RawAction::Put("c".into()),
+            pred: vec![elem],
+        },
+        RawOp {
+            obj: list.clone(),
+            key: RawKey::Elem(first.clone()),
+            insert: false,
+            action: RawAction::Delete,
+            pred: vec![first],
+        },
+        RawOp {
+            obj: ROOT,
+            key: RawKey::Map("counter".into()),
+            insert: false,
+            action: RawAction::Increment(5),
+            pred: vec![counter],
+        },
+    ];
+    let hash = doc.apply_raw_ops(actor.clone(), ops)?;
+    assert_eq!(doc.get_heads(), vec![hash]);
+    assert_eq!(doc.get_change_by_hash(&hash).unwrap().actor_id(), &actor);
+    assert_eq!(doc.get(&map, "key")?.unwrap().0, "value".into());
+    assert_eq!(doc.length(&list), 1);
+    assert_eq!(doc.get(&list, 0)?.unwrap().0, "c".into());
+    assert_eq!(doc.get(ROOT, "counter")?.unwrap().0, Value::counter(6));
+
+    // the change is an ordinary change, so it round trips
+    let reloaded = Automerge::load(&doc.save())?;
+    assert_eq!(reloaded.get(&list, 0)?.unwrap().0, "c".into());
+    Ok(())
+}
+
+#[test]
+fn apply_raw_ops_rejects_invalid_ops() -> Result<(), AutomergeError> {
+    let mut doc = Automerge::new();
+    let mut tx = doc.transaction();
+    let list = tx.put_object(ROOT, "list", ObjType::List)?;
+    tx.insert(&list, 0, "a")?;
+    tx.put(ROOT, "key", "value")?;
+    tx.commit();
+    let key_op = doc.get(ROOT, "key")?.unwrap().1;
+    let heads = doc.get_heads();
+    let actor = ActorId::from(b"bridge");
+    let put = |obj: &ExId, key: RawKey, pred: Vec<ExId>| RawOp {
+        obj: obj.clone(),
+        key,
+        insert: false,
+        action: RawAction::Put(1.into()),
+        pred,
+    };
+    let cases = vec![
+        (
+            vec![put(
+                &ExId::Id(99, ActorId::from(b"other"), 0),
+                RawKey::Map("a".into()),
+                vec![],
+            )],
+            "obj is not an object in the document",
+        ),
+        (
+            vec![put(&list, RawKey::Map("a".into()), vec![])],
+            "map key in a sequence",
+        ),
+        (
+            vec![put(&list, RawKey::Head, vec![])],
+            "head key without insert",
+        ),
+        (
+            vec![put(
+                &ROOT,
+                RawKey::Map("other".into()),
+                vec![key_op.clone()],
+            )],
+            "pred is an op on a different property",
+        ),
+        (
+            vec![RawOp {
+                action: RawAction::Increment(1),
+                ..put(&ROOT, RawKey::Map("key".into()), vec![key_op.clone()])
+            }],
+            "increment of something which isn't a counter",
+        ),
+        (
+            vec![RawOp {
+                action: RawAction::Delete,
+                ..put(&ROOT, RawKey::Map("key".into()), vec![])
+            }],
+            "delete or increment without pred",
+        ),
+        (
+            vec![put(
+                &doc.raw_op_id(&actor, 1),
+                RawKey::Map("a".into()),
+                vec![],
+            )],
+            "obj is not an earlier op which makes an object",
+        ),
+        (
+            vec![put(&list, RawKey::Elem(key_op.clone()), vec![])],
+            "key is not an element of obj",
+        ),
+    ];
+    for (ops, reason) in cases {
+        match doc.apply_raw_ops(actor.clone(), ops) {
+            Err(AutomergeError::InvalidRawOp(0, r)) => assert_eq!(r, reason),
+            other => panic!("expected {:?} but got {:?}", reason, other),
+        }
+        assert_eq!(doc.get_heads(), heads);
+    }
+
+    // a later op in the batch is checked against the earlier ones
+    let ops = vec![
+        put(&ROOT, RawKey::Map("a".into()), vec![]),
+        put(
+            &ROOT,
+            RawKey::Map("b".into()),
+            vec![doc.raw_op_id(&actor, 0)],
+        ),
+    ];
+    assert!(matches!(
+        doc.apply_raw_ops(actor, ops),
+        Err(AutomergeError::InvalidRawOp(1, _))
+    ));
+    assert_eq!(doc.get_heads(), heads);
+    Ok(())
+}
+
+#[test]
+fn get_at_element_creation_reads_at_the_inserting_change() -> Result<(), AutomergeError> {
+    let mut doc = AutoCommit::new();
+    let log = doc.put_object(ROOT, "log", ObjType::List)?;
+    let other = doc.put_object(ROOT, "other", ObjType::List)?;
+    doc.put(ROOT, "level", "debug")?;
+    doc.insert(&log, 0, "first")?;
+    doc.commit();
+    let before_second = doc.get_heads();
+    doc.put(ROOT, "level", "info")?;
+    doc.insert(&log, 1, "second")?;
+    doc.commit();
+    let first = doc.get_cursor(&log, 0, None)?;
+    let second = doc.get_cursor(&log, 1, None)?;
+    doc.put(ROOT, "level", "warn")?;
+    doc.delete(&log, 0)?;
+    doc.commit();
+
+    let level = |doc: &AutoCommit, cursor| {
+        doc.get_at_element_creation(ROOT, "level", &log, cursor)
+            .map(|v| v.map(|(value, _)| value.to_str().unwrap().to_string()))
+    };
+    assert_eq!(level(&doc, &first)?.as_deref(), Some("debug"));
+    assert_eq!(level(&doc, &second)?.as_deref(), Some("info"));
+    assert_eq!(
+        doc.get_at_element_creation(ROOT, "missing", &log, &first)?,
+        None
+    );
+    assert!(matches!(
+        doc.get_at_element_creation(ROOT, "level", &other, &first),
+        Err(AutomergeError::InvalidCursor(_))
+    ));
+    assert!(matches!(
+        doc.get_at_element_creation(ROOT, "level", &log, &Cursor::end()),
+        Err(AutomergeError::InvalidCursor(_))
+    ));
+
+    let at = crate::automerge::diff::ReadDocAt {
+        doc: doc.document(),
+        heads: &before_second,
+    };
+    assert!(at
+        .get_at_element_creation(ROOT, "level", &log, &first)?
+        .is_some());
+    assert!(matches!(
+        at.get_at_element_creation(ROOT, "level", &log, &second),
+        Err(AutomergeError::InvalidCursor(_))
+    ));
+
+    let mut raw = doc.document().clone();
+    let mut tx = raw.transaction();
+    tx.insert(&log, 0, "pending")?;
+    let pending = tx.get_cursor(&log, 0, None)?;
+    assert!(matches!(
+        tx.get_at_element_creation(ROOT, "level", &log, &pending),
+        Err(AutomergeError::InvalidCursor(_))
+    ));
+    tx.rollback();
+    Ok(())
+}
+
+#[test]
+fn get_heads_short_prefixes_are_unambiguous() -> Result<(), AutomergeError> {
+    let mut doc = AutoCommit::new();
+    doc.put(ROOT, "base", 0)?;
+    doc.commit();
+    let mut forks = (0..50).map(|_| doc.fork()).collect::<Vec<_>>();
+    for (i, fork) in forks.iter_mut().enumerate() {
+        fork.put(ROOT, "key", i as i64)?;
+        fork.commit();
+    }
+    for fork in &mut forks {
+        doc.merge(fork)?;
+    }
+
+    let heads = doc.get_heads();
+    let short = doc.document().get_heads_short();
+    assert_eq!(heads.len(), 50);
+    assert_eq!(short.len(), heads.len());
+    let all = doc
+        .get_changes(&[])
+        .into_iter()
+        .map(|c| c.hash().to_string())
+        .collect::<Vec<_>>();
+    for (head, prefix) in heads.iter().zip(&short) {
+        assert!(prefix.len() >= 7);
+        let head = head.to_string();
+        assert!(head.starts_with(prefix.as_str()));
+        let matching = all
+            .iter()
+            .filter(|h| h.starts_with(prefix.as_str()))
+            .count();
+        assert_eq!(matching, 1, "{} is ambiguous", prefix);
+    }
+    assert!(Automerge::new().get_heads_short().is_empty());
+    Ok(())
+}
+
+#[test]
+fn find_indices_matches_filtering_the_values() -> Result<(), AutomergeError> {
+    let mut doc = AutoCommit::new();
+    let list = doc.put_object(ROOT, "list", ObjType::List)?;
+    for i in 0..20 {
+        doc.insert(&list, i, i as i64)?;
+    }
+    doc.insert_object(&list, 5, ObjType::Map)?;
+    let heads = doc.get_heads();
+    doc.delete(&list, 0)?;
+    doc.put(&list, 3, 100)?;
+
+    let even = |v: &Value<'_>| v.to_i64().map(|n| n % 2 == 0).unwrap_or(false);
+    let expected = doc
+        .list_range(&list, ..)
+        .filter(|item| even(&item.value))
+        .map(|item| item.index)
+        .collect::<Vec<_>>();
+    assert_eq!(doc.find_indices(&list, even, None)?, expected);
+    assert_eq!(doc.find_indices(&list, |v| v.is_object(), None)?, vec![4]);
+    let expected_at_heads = doc
+        .list_range_at(&list, .., &heads)
+        .filter(|item| even(&item.value))
+        .map(|item| item.index)
+        .collect::<Vec<_>>();
+    assert_eq!(expected_at_heads.len(), 10);
+    assert_eq!(
+        doc.find_indices(&list, even, Some(&heads))?,
+        expected_at_heads
+    );
+
+    let text = doc.put_object(ROOT, "text", ObjType::Text)?;
+    doc.splice_text(&text, 0, 0, "banana")?;
+    let a = doc.find_indices(&text, |v| v.to_str() == Some("a"), None)?;
+    assert_eq!(a, vec![1, 3, 5]);
+
+    assert!(matches!(
+        doc.find_indices(ROOT, even, None),
+        Err(AutomergeError::InvalidOp(ObjType::Map))
+    ));
+    Ok(())
+}
+
+#[test]
+fn rename_key_copies_objects_and_scalars() -> Result<(), AutomergeError> {
+    let mut doc = AutoCommit::new();
+    let list = doc.put_object(ROOT, "old_list", ObjType::List)?;
+    doc.insert(&list, 0, "a")?;
+    let nested = doc.insert_object(&list, 1, ObjType::Map)?;
+    doc.put(&nested, "n", 1)?;
+    doc.put(ROOT, "count", ScalarValue::counter(3))?;
+    doc.put(ROOT, "taken", "x")?;
+    let expected = doc.hydrate(&list, None)?;
+
+    doc.rename_key(ROOT, "old_list", "new_list")?;
+    doc.rename_key(ROOT, "count", "total")?;
+    assert_eq!(
+        doc.keys(ROOT).collect::<Vec<_>>(),
+        vec!["new_list", "taken", "total"]
+    );
+    let (_, new_list) = doc.get(ROOT, "new_list")?.unwrap();
+    assert_eq!(doc.hydrate(&new_list, None)?, expected);
+    assert_eq!(
+        doc.get(ROOT, "total")?.unwrap().0,
+        Value::Scalar(Cow::Owned(ScalarValue::counter(3)))
+    );
+
+    assert!(matches!(
+        doc.rename_key(ROOT, "missing", "other"),
+        Err(AutomergeError::MissingKey(k)) if k == "missing"
+    ));
+    assert!(matches!(
+        doc.rename_key(ROOT, "total", "taken"),
+        Err(AutomergeError::KeyExists(k)) if k == "taken"
+    ));
+    assert!(matches!(
+        doc.rename_key(&new_list, "a", "b"),
+        Err(AutomergeError::InvalidOp(ObjType::List))
+    ));
+    Ok(())
+}
+
+#[test]
+fn rename_key_loses_concurrent_edits_to_the_old_object() -> Result<(), AutomergeError> {
+    let mut doc = AutoCommit::new();
+    let user = doc.put_object(ROOT, "usr", ObjType::Map)?;
+    doc.put(&user, "name", "alice")?;
+    doc.commit();
+    let mut other = doc.fork();
+
+    doc.rename_key(ROOT, "usr", "user")?;
+    other.put(&user, "email", "alice@example.com")?;
+    other.put(&user, "name", "alicia")?;
+    doc.merge(&mut other)?;
+
+    // the copy only has what the old object had when it was renamed
+    let (_, copy) = doc.get(ROOT, "user")?.unwrap();
+    assert_ne!(copy, user);
+    assert_eq!(doc.keys(&copy).collect::<Vec<_>>(), vec!["name"]);
+    assert_eq!(doc.get(&copy, "name")?.unwrap().0, Value::str("alice"));
+    assert_eq!(doc.get(ROOT, "usr")?, None);
+    // the concurrent edits went to the old object, which is deleted
+    assert_eq!(doc.get(&user, "name")?.unwrap().0, Value::str("alicia"));
+
+    // concurrent renames make two copies which conflict
+    let mut other = doc.fork();
+    doc.rename_key(ROOT, "user", "account")?;
+    other.rename_key(ROOT, "user", "account")?;
+    doc.merge(&mut other)?;
+    assert_eq!(doc.get_all(ROOT, "account")?.len(), 2);
+    assert_eq!(doc.get(ROOT, "user")?, None);
+    Ok(())
+}
+
+#[test]
+fn rename_key_keeps_the_marks_on_text() -> Result<(), AutomergeError> {
+    use crate::marks::{ExpandMark, Mark};
+    let mut doc = AutoCommit::new();
+    let note = doc.put_object(ROOT, "note", ObjType::Map)?;
+    let text = doc.put_object(&note, "body", ObjType::Text)?;
+    doc.splice_text(&text, 0, 0, "hello world")?;
+    doc.mark(
+        &text,
+        Mark::new("bold".to_string(), true, 0, 5),
+        ExpandMark::None,
+    )?;
+    let title = doc.put_object(ROOT, "title", ObjType::Text)?;
+    doc.splice_text(&title, 0, 0, "notes")?;
+    doc.mark(
+        &title,
+        Mark::new("link".to_string(), "https://example.com", 1, 4),
+        ExpandMark::None,
+    )?;
+    let marks = |doc: &AutoCommit, obj: &ExId| {
+        doc.marks(obj)
+            .unwrap()
+            .into_iter()
+            .map(|m| (m.name().to_string(), m.start, m.end, m.value().clone()))
+            .collect::<Vec<_>>()
+    };
+    let body_marks = marks(&doc, &text);
+    let title_marks = marks(&doc, &title);
+
+    doc.rename_key(ROOT, "note", "renamed_note")?;
+    doc.rename_key(ROOT, "title", "heading")?;
+    let (_, note) = doc.get(ROOT, "renamed_note")?.unwrap();
+    let (_, text) = doc.get(&note, "body")?.unwrap();
+    let (_, title) = doc.get(ROOT, "heading")?.unwrap();
+    assert_eq!(doc.text(&text)?, "hello world");
+    assert_eq!(marks(&doc, &text), body_marks);
+    assert_eq!(doc.text(&title)?, "notes");
+    assert_eq!(marks(&doc, &title), title_marks);
+    Ok(())
+}
+
+#[test]
+fn make_patches_grouped_buckets_by_top_level_object() -> Result<(), AutomergeError> {
+    let mut doc = AutoCommit::new();
+    let header = doc.put_object(ROOT, "header", ObjType::Map)?;
+    let title = doc.put_object(&header, "title", ObjType::Text)?;
+    doc.splice_text(&title, 0, 0, "hello")?;
+    let body = doc.put_object(ROOT, "body", ObjType::List)?;
+    let para = doc.insert_object(&body, 0, ObjType::Map)?;
+    doc.put(&para, "text", "world")?;
+    doc.put(ROOT, "version", 1)?;
+
+    let mut other = Automerge::new();
+    let mut patch_log = PatchLog::active(TextRepresentation::String);
+    other.apply_changes_log_patches(doc.get_changes(&[]).into_iter().cloned(), &mut patch_log)?;
+    let patches = other.make_patches(&mut patch_log.clone());
+    let groups = other.make_patches_grouped(&mut patch_log);
+
+    assert_eq!(
+        groups.keys().cloned().collect::<BTreeSet<_>>(),
+        [ROOT, header.clone(), body.clone()].into_iter().collect()
+    );
+    assert!(groups[&ROOT].iter().all(|p| p.obj == ROOT));
+    assert!(groups[&header].iter().any(|p| p.obj == title));
+    assert!(groups[&body].iter().any(|p| p.obj == para));
+    let mut regrouped = groups.into_values().flatten().collect::<Vec<_>>();
+    let mut patches = patches;
+    let key = |p: &Patch| format!("{:?}", p);
+    regrouped.sort_by_key(key);
+    patches.sort_by_key(key);
+    assert_eq!(regrouped, patches);
+    Ok(())
+}
+
+#[test]
+fn copy_and_delete_element_reorders_scalars_and_objects() -> Result<(), AutomergeError> {
+    let mut doc = AutoCommit::new();
+    let list = doc.put_object(ROOT, "list", ObjType::List)?;
+    doc.insert(&list, 0, "a")?;
+    let item = doc.insert_object(&list, 1, ObjType::Map)?;
+    doc.put(&item, "done", true)?;
+    doc.insert(&list, 2, "c")?;
+    let item_contents = doc.hydrate(&item, None)?;
+    doc.commit();
+    let changes = doc.get_changes(&[]).len();
+
+    doc.copy_and_delete_element(&list, 1, 0)?;
+    doc.copy_and_delete_element(&list, 1, 2)?;
+    doc.commit();
+    assert_eq!(doc.get_changes(&[]).len(), changes + 1);
+    let (value, moved) = doc.get(&list, 0)?.unwrap();
+    assert_eq!(value, Value::Object(ObjType::Map));
+    assert_eq!(doc.hydrate(&moved, None)?, item_contents);
+    assert_eq!(doc.get(&list, 1)?.unwrap().0.to_str(), Some("c"));
+    assert_eq!(doc.get(&list, 2)?.unwrap().0.to_str(), Some("a"));
+
+    assert!(matches!(
+        doc.copy_and_delete_element(&list, 3, 0),
+        Err(AutomergeError::InvalidIndex(3))
+    ));
+    assert!(matches!(
+        doc.copy_and_delete_element(&list, 0, 3),
+        Err(AutomergeError::InvalidIndex(3))
+    ));
+    assert!(matches!(
+        doc.copy_and_delete_element(ROOT, 0, 1),
+        Err(AutomergeError::InvalidOp(ObjType::Map))
+    ));
+    Ok(())
+}
+
+#[test]
+fn copy_and_delete_element_keeps_the_marks_on_text() -> Result<(), AutomergeError> {
+    use crate::marks::{ExpandMark, Mark};
+    let mut doc = AutoCommit::new();
+    let list = doc.put_object(ROOT, "list", ObjType::List)?;
+    let text = doc.insert_object(&list, 0, ObjType::Text)?;
+    doc.splice_text(&text, 0, 0, "hello world")?;
+    doc.mark(
+        &text,
+        Mark::new("bold".to_string(), true, 6, 11),
+        ExpandMark::None,
+    )?;
+    doc.insert(&list, 1, "after")?;
+
+    doc.copy_and_delete_element(&list, 0, 1)?;
+    assert_eq!(doc.get(&list, 0)?.unwrap().0.to_str(), Some("after"));
+    let (_, copy) = doc.get(&list, 1)?.unwrap();
+    assert_eq!(doc.text(&copy)?, "hello world");
+    let marks = doc
+        .marks(&copy)?
+        .into_iter()
+        .map(|m| (m.name().to_string(), m.start, m.end))
+        .collect::<Vec<_>>();
+    assert_eq!(marks, vec![("bold".to_string(), 6, 11)]);
+    Ok(())
+}
+
+#[test]
+fn save_with_progress_reports_monotonic_progress() -> Result<(), AutomergeError> {
+    let mut doc = AutoCommit::new();
+    let text = doc.put_object(ROOT, "text", ObjType::Text)?;
+    doc.splice_text(&text, 0, 0, &"x".repeat(2000))?;
+    let mut calls = Vec::new();
+    let bytes = doc
+        .document()
+        .save_with_progress(|done, total| calls.push((done, total)));
+    assert_eq!(bytes, doc.save());
+    assert!(calls.len() > 1);
+    let total = calls[0].1;
+    assert!(calls.iter().all(|(_, t)| *t == total));
+    assert!(calls.windows(2).all(|w| w[0].0 < w[1].0));
+    assert_eq!(calls.last(), Some(&(total, total)));
+    Ok(())
+}
+
+#[test]
+fn is_ancestor_follows_dependencies() -> Result<(), AutomergeError> {
+    let mut doc = AutoCommit::new();
+    doc.put(ROOT, "a", 1)?;
+    let base = doc.get_heads()[0];
+    let mut fork = doc.fork();
+    doc.put(ROOT, "b", 2)?;
+    let mine = doc.get_heads()[0];
+    fork.put(ROOT, "c", 3)?;
+    let theirs = fork.get_heads()[0];
+    doc.merge(&mut fork)?;
+    doc.put(ROOT, "d", 4)?;
+    let merged = doc.get_heads();
+
+    assert!(doc.is_ancestor(&base, &merged)?);
+    assert!(doc.is_ancestor(&mine, &merged)?);
+    assert!(doc.is_ancestor(&theirs, &merged)?);
+    assert!(doc.is_ancestor(&mine, &[mine])?);
+    assert!(doc.is_ancestor(&base, &[theirs])?);
+    assert!(!doc.is_ancestor(&mine, &[theirs])?);
+    assert!(!doc.is_ancestor(&merged[0], &[mine, theirs])?);
+    assert!(!doc.is_ancestor(&base, &[])?);
+
+    let unknown = ChangeHash([7; 32]);
+    assert!(matches!(
+        doc.is_ancestor(&unknown, &merged),
+        Err(AutomergeError::MissingHash(h)) if h == unknown
+    ));
+    assert!(matches!(
+        doc.is_ancestor(&base, &[unknown]),
+        Err(AutomergeError::MissingHash(h)) if h == unknown
+    ));
+    Ok(())
+}
+
+#[test]
+fn element_tokens_identify_elements_across_documents() -> Result<(), AutomergeError> {
+    let mut doc = AutoCommit::new();
+    let list = doc.put_object(ROOT, "list", ObjType::List)?;
+    doc.splice(&list, 0, 0, ["a".into(), "b".into(), "c".into()])?;
+    let b = doc.element_token(&list, 1)?;
+    // overwriting an element doesn't change its token
+    doc.put(&list, 1, "B")?;
+    assert_eq!(doc.element_token(&list, 1)?, b);
+
+    let mut peer = AutoCommit::load(&doc.save())?;
+    assert_eq!(peer.element_token(&list, 1)?, b);
+    peer.insert(&list, 0, "z")?;
+    doc.merge(&mut peer)?;
+    assert_eq!(doc.index_of_token(&list, &b)?, 2);
+
+    let heads = doc.get_heads();
+    doc.delete(&list, 2)?;
+    assert_eq!(doc.index_of_token(&list, &b)?, 2);
+    assert_eq!(doc.get(&list, 2)?.unwrap().0.to_str(), Some("c"));
+    let at = crate::automerge::diff::ReadDocAt {
+        doc: doc.document(),
+        heads: &heads,
+    };
+    assert_eq!(at.element_token(&list, 2)?, b);
+
+    assert!(matches!(
+        doc.element_token(&list, 3),
+        Err(AutomergeError::InvalidIndex(3))
+    ));
+    assert!(matches!(
+        doc.element_token(ROOT, 0),
+        Err(AutomergeError::InvalidOp(ObjType::Map))
+    ));
+    assert!(matches!(
+        doc.index_of_token(&list, "not a token"),
+        Err(AutomergeError::InvalidCursorFormat)
+    ));
+    Ok(())
+}
+
+#[test]
+fn f32_values_keep_their_type_through_save_and_load() -> Result<(), AutomergeError> {
+    let mut doc = AutoCommit::new();
+    doc.put(ROOT, "single", 0.1_f32)?;
+    doc.put(ROOT, "double", 0.1_f64)?;
+    let list = doc.put_object(ROOT, "list", ObjType::List)?;
+    doc.insert(&list, 0, f32::MAX)?;
+
+    let loaded = AutoCommit::load(&doc.save())?;
+    let (single, _) = loaded.get(ROOT, "single")?.unwrap();
+    assert_eq!(single, Value::f32(0.1));
+    assert!(single.is_f32());
+    assert_eq!(single.to_f32(), Some(0.1));
+    assert_eq!(single.to_f64(), Some(f64::from(0.1_f32)));
+    let (double, _) = loaded.get(ROOT, "double")?.unwrap();
+    assert_eq!(double, Value::f64(0.1));
+    assert_eq!(double.to_f32(), None);
+    assert_eq!(loaded.get(&list, 0)?.unwrap().0, Value::f32(f32::MAX));
+
+    let mut incremental = AutoCommit::new();
+    incremental.load_incremental(&doc.save_after(&[]))?;
+    assert_eq!(incremental.get(ROOT, "single")?.unwrap().0, Value::f32(0.1));
+
+    Ok(())
+}
+
+#[test]
+fn splice_text_with_encoding_measures_offsets_in_the_given_encoding() -> Result<(), AutomergeError>
+{
+    let mut doc = AutoCommit::new();
+    let text = doc.put_object(ROOT, "text", ObjType::Text)?;
+    // long enough that the op tree has several nodes
+    let filler = "ab".repeat(200);
+    doc.splice_text(&text, 0, 0, &format!("{}é🐻z", filler))?;
+    doc.delete(&text, 0)?;
+    let prefix = filler.len() - 1;
+
+    // "é" is 2 UTF-8 bytes, 1 UTF-16 unit and 1 code point, "🐻" 4, 2 and 1
+    doc.splice_text_with_encoding(&text, prefix + 2, 4, "X", TextEncoding::Utf8CodeUnit)?;
+    assert!(doc.text(&text)?.ends_with("éXz"));
+    doc.splice_text(&text, prefix + 1, 1, "🐻")?;
+    doc.splice_text_with_encoding(&text, prefix + 1, 2, "Y", TextEncoding::Utf16CodeUnit)?;
+    assert!(doc.text(&text)?.ends_with("éYz"));
+    doc.splice_text(&text, prefix + 1, 1, "🐻")?;
+    doc.splice_text_with_encoding(&text, prefix + 1, 1, "Z", TextEncoding::UnicodeCodePoint)?;
+    assert!(doc.text(&text)?.ends_with("éZz"));
+    doc.splice_text(&text, prefix + 1, 1, "🐻")?;
+
+    // the middle of a character
+    assert!(matches!(
+        doc.splice_text_with_encoding(&text, prefix + 1, 0, "!", TextEncoding::Utf8CodeUnit),
+        Err(AutomergeError::InvalidIndex(_))
+    ));
+    assert!(matches!(
+        doc.splice_text_with_encoding(&text, prefix + 3, 0, "!", TextEncoding::Utf8CodeUnit),
+        Err(AutomergeError::InvalidIndex(_))
+    ));
+    assert!(matches!(
+        doc.splice_text_with_encoding(&text, prefix + 1, 1, "!", TextEncoding::Utf16CodeUnit),
+        Err(AutomergeError::InvalidIndex(_))
+    ));
+    // past the end
+    let utf8_len = doc.text(&text)?.len();
+    assert!(matches!(
+        doc.splice_text_with_encoding(&text, utf8_len + 1, 0, "!", TextEncoding::Utf8CodeUnit),
+        Err(AutomergeError::InvalidIndex(_))
+    ));
+    doc.splice_text_with_encoding(&text, utf8_len, 0, "!", TextEncoding::Utf8CodeUnit)?;
+    assert!(doc.text(&text)?.ends_with("é🐻z!"));
+    assert!(matches!(
+        doc.splice_text_with_encoding(ROOT, 0, 0, "!", TextEncoding::Utf8CodeUnit),
+        Err(AutomergeError::InvalidOp(ObjType::Map))
+    ));
+    Ok(())
+}
+
+#[test]
+fn visible_count_before_matches_a_walk_of_the_ops() -> Result<(), AutomergeError> {
+    let mut doc1 = AutoCommit::new();
+    let list = doc1.put_object(ROOT, "list", ObjType::List)?;
+    let text = doc1.put_object(ROOT, "text", ObjType::Text)?;
+    for i in 0..300 {
+        doc1.insert(&list, i, i as i64)?;
+    }
+    doc1.splice_text(&text, 0, 0, &"a😀".repeat(150))?;
+    let mut doc2 = doc1.fork();
+    for i in (0..300).step_by(7) {
+        doc1.put(&list, i, "one")?;
+        doc2.put(&list, i, "two")?;
+    }
+    for i in (0..200).rev().step_by(5) {
+        doc1.delete(&list, i)?;
+        doc1.delete(&text, i)?;
+    }
+    doc1.merge(&mut doc2)?;
+
+    for obj in [&list, &text] {
+        let inner = doc1.document();
+        let obj_id = inner.exid_to_obj(obj)?;
+        let encoding = TextRepresentation::String.encoding(obj_id.typ);
+        let ops: Vec<_> = inner.ops().op_iter(&obj_id.id).unwrap().collect();
+        let mut expected = 0;
+        for pos in 0..ops.len() {
+            let key = ops[pos].elemid_or_key();
+            if pos > 0 && ops[pos - 1].elemid_or_key() != key {
+                // count the element which just ended, if any of its ops were visible
+                let prev = ops[..pos]
+                    .iter()
+                    .rev()
+                    .take_while(|op| op.elemid_or_key() == ops[pos - 1].elemid_or_key())
+                    .filter(|op| op.visible())
+                    .last();
+                expected += prev.map_or(0, |op| op.width(encoding));
+            }
+            assert_eq!(
+                inner.visible_count_before(obj, pos),
+                expected,
+                "pos {}",
+                pos
+            );
+        }
+        assert_eq!(
+            inner.visible_count_before(obj, ops.len()),
+            inner.length(obj)
+        );
+    }
+    assert_eq!(
+        doc1.visible_count_before(&list, usize::MAX),
+        doc1.length(&list)
+    );
+
+    let heads = doc1.get_heads();
+    doc1.splice(&list, 0, 5, [])?;
+    let at = crate::automerge::diff::ReadDocAt {
+        doc: doc1.document(),
+        heads: &heads,
+    };
+    assert_eq!(at.visible_count_before(&list, usize::MAX), at.length(&list));
+    assert_eq!(doc1.visible_count_before(ROOT, 0), 0);
+    Ok(())
+}
+
+#[test]
+fn map_contains_key_and_list_contains_index() -> Result<(), AutomergeError> {
+    let mut doc = AutoCommit::new();
+    doc.put(ROOT, "a", 1)?;
+    doc.put(ROOT, "b", 2)?;
+    doc.put(ROOT, "c", 3)?;
+    doc.delete(ROOT, "b")?;
+    let list = doc.put_object(ROOT, "list", ObjType::List)?;
+    doc.insert(&list, 0, "x")?;
+    doc.insert(&list, 1, "y")?;
+    let heads = doc.get_heads();
+    doc.delete(&list, 1)?;
+    doc.delete(ROOT, "a")?;
+
+    assert!(!doc.map_contains_key(ROOT, "a")?);
+    assert!(!doc.map_contains_key(ROOT, "b")?);
+    assert!(doc.map_contains_key(ROOT, "c")?);
+    assert!(!doc.map_contains_key(ROOT, "never")?);
+    assert!(doc.list_contains_index(&list, 0)?);
+    assert!(!doc.list_contains_index(&list, 1)?);
+
+    let at = crate::automerge::diff::ReadDocAt {
+        doc: doc.document(),
+        heads: &heads,
+    };
+    assert!(at.map_contains_key(ROOT, "a")?);
+    assert!(!at.map_contains_key(ROOT, "b")?);
+    assert!(at.list_contains_index(&list, 1)?);
+
+    assert!(matches!(
+        doc.map_contains_key(&list, "a"),
+        Err(AutomergeError::InvalidOp(ObjType::List))
+    ));
+    assert!(matches!(
+        doc.list_contains_index(ROOT, 0),
+        Err(AutomergeError::InvalidOp(ObjType::Map))
+    ));
+    Ok(())
+}
+
+#[test]
+fn get_cursor_positions_matches_get_cursor_position() -> Result<(), AutomergeError> {
+    let mut doc = AutoCommit::new();
+    let text = doc.put_object(ROOT, "text", ObjType::Text)?;
+    doc.splice_text(&text, 0, 0, &"abc😀".repeat(100))?;
+    let mut cursors = (0..400)
+        .step_by(13)
+        .map(|i| doc.get_cursor(&text, i, None))
+        .collect::<Result<Vec<_>, _>>()?;
+    cursors.push(Cursor::end());
+    cursors.push(cursors[3].clone());
+    let heads = doc.get_heads();
+    doc.splice_text(&text, 30, 50, "")?;
+    doc.splice_text(&text, 0, 0, "xyz")?;
+
+    for at in [None, Some(heads.as_slice())] {
+        let expected = cursors
+            .iter()
+            .map(|c| doc.get_cursor_position(&text, c, at))
+            .collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(doc.get_cursor_positions(&text, &cursors, at)?, expected);
+    }
+    assert_eq!(
+        doc.get_cursor_positions(&text, &[], None)?,
+        Vec::<usize>::new()
+    );
+
+    let other = doc.put_object(ROOT, "other", ObjType::List)?;
+    doc.insert(&other, 0, 1)?;
+    let foreign = doc.get_cursor(&other, 0, None)?;
+    assert!(matches!(
+        doc.get_cursor_positions(&text, &[cursors[0].clone(), foreign.clone()], None),
+        Err(AutomergeError::InvalidCursor(c)) if c == foreign
+    ));
+    assert!(matches!(
+        doc.get_cursor_positions(ROOT, &cursors, None),
+        Err(AutomergeError::InvalidOp(ObjType::Map))
+    ));
+    Ok(())
+}
+
+#[test]
+fn frozen_objects_reject_local_changes() -> Result<(), AutomergeError> {
+    let mut doc = AutoCommit::new();
+    let section = doc.put_object(ROOT, "section", ObjType::Map)?;
+    doc.put(&section, "title", "hello")?;
+    let text = doc.put_object(&section, "text", ObjType::Text)?;
+    doc.splice_text(&text, 0, 0, "abc")?;
+    let list = doc.put_object(&section, "list", ObjType::List)?;
+    doc.insert(&list, 0, 1)?;
+    doc.commit();
+    let mut other = doc.fork();
+
+    doc.freeze_object(&section)?;
+    assert!(doc.is_frozen(&section));
+    assert!(doc.is_frozen(&text));
+    assert!(!doc.is_frozen(ROOT));
+    let frozen = |r: Result<(), AutomergeError>| matches!(r, Err(AutomergeError::ObjectFrozen(obj)) if obj == section);
+    assert!(frozen(doc.put(&section, "title", "changed")));
+    assert!(frozen(doc.delete(&section, "title")));
+    assert!(frozen(
+        doc.put_object(&section, "new", ObjType::Map).map(|_| ())
+    ));
+    assert!(frozen(doc.splice_text(&text, 0, 1, "")));
+    assert!(frozen(doc.insert(&list, 0, 2)));
+    assert!(frozen(doc.splice(&list, 0, 1, [])));
+    assert!(frozen(doc.mark(
+        &text,
+        crate::marks::Mark::new("bold".to_string(), true, 0, 1),
+        crate::marks::ExpandMark::None
+    )));
+    // the containing object is not frozen
+    doc.put(ROOT, "other", "value")?;
+    doc.delete(ROOT, "section")?;
+    assert_eq!(doc.get(ROOT, "section")?, None);
+
+    // changes from elsewhere still apply
+    let mut doc = AutoCommit::load(&doc.save())?;
+    other.put(&section, "title", "remote")?;
+    let mut frozen_doc = other.fork();
+    frozen_doc.freeze_object(&section)?;
+    other.put(&section, "title", "remote again")?;
+    frozen_doc.merge(&mut other)?;
+    assert_eq!(
+        frozen_doc.get(&section, "title")?.unwrap().0.to_str(),
+        Some("remote again")
+    );
+
+    // freezing isn't saved
+    doc.freeze_object(ROOT)?;
+    assert!(AutoCommit::load(&doc.save())?.put(ROOT, "x", 1).is_ok());
+    assert!(doc.unfreeze_object(ROOT));
+    assert!(!doc.unfreeze_object(ROOT));
+    doc.put(ROOT, "x", 1)?;
+    Ok(())
+}
+
+#[test]
+fn value_provenance_is_the_change_which_set_the_winning_value() -> Result<(), AutomergeError> {
+    let mut doc1 = AutoCommit::new().with_actor(ActorId::from([1]));
+    let list = doc1.put_object(ROOT, "list", ObjType::List)?;
+    doc1.insert(&list, 0, "a")?;
+    doc1.insert(&list, 1, "b")?;
+    doc1.put(ROOT, "key", "one")?;
+    doc1.commit();
+    let first = doc1.get_heads()[0];
+
+    let mut doc2 = doc1.fork().with_actor(ActorId::from([2]));
+    doc2.put(ROOT, "key", "two")?;
+    doc2.put(&list, 1, "c")?;
+    doc2.commit();
+    let second = doc2.get_heads()[0];
+    doc1.put(ROOT, "key", "three")?;
+    doc1.commit();
+    let third = doc1.get_heads()[0];
+    doc1.merge(&mut doc2)?;
+
+    // actor 2 wins the conflict
+    assert_eq!(doc1.get(ROOT, "key")?.unwrap().0.to_str(), Some("two"));
+    assert_eq!(doc1.value_provenance(ROOT, "key")?, Some(second));
+    assert_eq!(doc1.value_provenance(&list, 0)?, Some(first));
+    assert_eq!(doc1.value_provenance(&list, 1)?, Some(second));
+    assert_eq!(doc1.value_provenance(ROOT, "list")?, Some(first));
+    assert_eq!(doc1.value_provenance(ROOT, "missing")?, None);
+    assert_eq!(doc1.value_provenance(&list, 5)?, None);
+
+    let heads = vec![third];
+    let at = crate::automerge::diff::ReadDocAt {
+        doc: doc1.document(),
+        heads: &heads,
+    };
+    assert_eq!(at.value_provenance(ROOT, "key")?, Some(third));
+    assert_eq!(at.value_provenance(&list, 1)?, Some(first));
+
+    doc1.put(ROOT, "key", "uncommitted")?;
+    assert_eq!(doc1.value_provenance(ROOT, "key")?, None);
+    Ok(())
+}
+
+#[test]
+fn splice_iter_matches_splice() -> Result<(), AutomergeError> {
+    let values = |n: i64| (0..n).map(ScalarValue::from);
+    let mut by_vec = AutoCommit::new().with_actor(ActorId::from([1]));
+    let list = by_vec.put_object(ROOT, "list", ObjType::List)?;
+    let text = by_vec.put_object(ROOT, "text", ObjType::Text)?;
+    by_vec.splice_text(&text, 0, 0, "hello world")?;
+    by_vec.commit();
+    let mut by_iter = by_vec.fork().with_actor(ActorId::from([1]));
+    by_vec.update_diff_cursor();
+    by_iter.update_diff_cursor();
+
+    by_vec.splice(&list, 0, 0, values(100).collect::<Vec<_>>())?;
+    by_iter.splice_iter(&list, 0, 0, values(100))?;
+    by_vec.splice(&list, 10, 20, values(5).collect::<Vec<_>>())?;
+    by_iter.splice_iter(&list, 10, 20, values(5))?;
+    by_vec.splice(&list, 80, isize::MAX, Vec::new())?;
+    by_iter.splice_iter(&list, 80, usize::MAX, std::iter::empty())?;
+    by_vec.splice(&text, 5, 1, ["-".into(), "!".into()])?;
+    by_iter.splice_iter(&text, 5, 1, ["-".into(), "!".into()].into_iter())?;
+
+    assert_eq!(by_iter.diff_incremental(), by_vec.diff_incremental());
+    by_vec.commit_with(crate::transaction::CommitOptions::default().with_time(0));
+    by_iter.commit_with(crate::transaction::CommitOptions::default().with_time(0));
+    assert_eq!(by_iter.save(), by_vec.save());
+    assert_eq!(by_iter.length(&list), 80);
+    assert_eq!(by_iter.text(&text)?, "hello-!world");
+    Ok(())
+}
+
+#[test]
+fn export_rich_text_clips_marks_and_round_trips() {
+    use crate::marks::{ExpandMark, Mark};
+    let mut doc = AutoCommit::new();
+    let text = doc.put_object(ROOT, "text", ObjType::Text).unwrap();
+    doc.splice_text(&text, 0, 0, "hello world").unwrap();
+    doc.mark(
+        &text,
+        Mark::new("bold".to_string(), true, 0, 5),
+        ExpandMark::None,
+    )
+    .unwrap();
+    doc.mark(
+        &text,
+        Mark::new("italic".to_string(), true, 3, 8),
+        ExpandMark::None,
+    )
+    .unwrap();
+    doc.mark(
+        &text,
+        Mark::new("link".to_string(), "https://example.com", 6, 11),
+        ExpandMark::None,
+    )
+    .unwrap();
+
+    let rich = doc.export_rich_text(&text, 2..9, None).unwrap();
+    assert_eq!(rich.text(), "llo wor");
+    let runs: Vec<(&str, Vec<&str>)> = rich
+        .runs
+        .iter()
+        .map(|run| {
+            (
+                run.text.as_str(),
+                run.marks.keys().map(|k| k.as_str()).collect(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        runs,
+        vec![
+            ("l", vec!["bold"]),
+            ("lo", vec!["bold", "italic"]),
+            (" ", vec!["italic"]),
+            ("wo", vec!["italic", "link"]),
+            ("r", vec!["link"]),
+        ]
+    );
+
+    let json = serde_json::to_string(&rich).unwrap();
+    let rich: crate::marks::RichText = serde_json::from_str(&json).unwrap();
+
+    let mut other = AutoCommit::new();
+    let pasted = other.put_object(ROOT, "text", ObjType::Text).unwrap();
+    other.splice_text(&pasted, 0, 0, "[]").unwrap();
+    other.insert_rich_text(&pasted, 1, &rich).unwrap();
+    assert_eq!(other.text(&pasted).unwrap(), "[llo wor]");
+    let marks: Vec<_> = other
+        .marks(&pasted)
+        .unwrap()
+        .into_iter()
+        .map(|m| (m.name().to_string(), m.start, m.end, m.value().clone()))
+        .collect();
+    assert_eq!(
+        marks,
+        vec![
+            ("bold".to_string(), 1, 4, ScalarValue::from(true)),
+            ("italic".to_string(), 2, 7, ScalarValue::from(true)),
+            (
+                "link".to_string(),
+                5,
+                8,
+                ScalarValue::from("https://example.com")
+            ),
+        ]
+    );
+
+    assert!(matches!(
+        doc.export_rich_text(ROOT, 0..1, None),
+        Err(AutomergeError::InvalidOp(ObjType::Map))
+    ));
+}
+
+#[test]
+fn rich_text_mark_ranges_measure_non_ascii_text_like_the_document() {
+    use crate::marks::{ExpandMark, Mark};
+    let mut doc = AutoCommit::new();
+    let text = doc.put_object(ROOT, "text", ObjType::Text).unwrap();
+    doc.splice_text(&text, 0, 0, "😀 héllo 日本語 👍🏽!").unwrap();
+    let len = doc.length(&text);
+    let mark = |doc: &mut AutoCommit, name: &str, start, end| {
+        doc.mark(
+            &text,
+            Mark::new(name.to_string(), true, start, end),
+            ExpandMark::None,
+        )
+        .unwrap()
+    };
+    mark(&mut doc, "bold", 0, len / 2);
+    mark(&mut doc, "italic", len / 3, len - 1);
+
+    let rich = doc.export_rich_text(&text, 0..len, None).unwrap();
+    let mut other = AutoCommit::new();
+    let pasted = other.put_object(ROOT, "text", ObjType::Text).unwrap();
+    other.insert_rich_text(&pasted, 0, &rich).unwrap();
+    assert_eq!(other.text(&pasted).unwrap(), doc.text(&text).unwrap());
+    let marks = |doc: &AutoCommit, obj: &ExId| {
+        doc.marks(obj)
+            .unwrap()
+            .into_iter()
+            .map(|m| (m.name().to_string(), m.start, m.end))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(marks(&other, &pasted), marks(&doc, &text));
+}
+
+#[test]
+fn length_utf16_and_grapheme_measure_text() {
+    let mut doc = AutoCommit::new();
+    let text = doc.put_object(ROOT, "text", ObjType::Text).unwrap();
+    doc.splice_text(&text, 0, 0, "ae\u{301}😀👍🏽").unwrap();
+    let heads = doc.get_heads();
+    doc.splice_text(&text, 0, 1, "").unwrap();
+
+    assert_eq!(doc.length_utf16(&text), 2 + 2 + 4);
+    assert_eq!(doc.length_grapheme(&text), 3);
+
+    let inner = doc.document();
+    let at = crate::automerge::diff::ReadDocAt {
+        doc: inner,
+        heads: &heads,
+    };
+    assert_eq!(at.length_utf16(&text), 1 + 2 + 2 + 4);
+    assert_eq!(at.length_grapheme(&text), 4);
+
+    let list = doc.put_object(ROOT, "list", ObjType::List).unwrap();
+    doc.insert(&list, 0, "😀").unwrap();
+    assert_eq!(doc.length_utf16(&list), 1);
+    assert_eq!(doc.length_grapheme(&list), 1);
+}
+
+#[test]
+fn lazy_objects_rebuild_spilled_trees_transparently() {
+    let build = |lazy: bool| {
+        let mut doc = AutoCommit::new().with_actor(ActorId::from([1]));
+        if lazy {
+            doc = doc.with_lazy_objects(3);
+        }
+        for i in 0..20 {
+            let map = doc
+                .put_object(ROOT, format!("map{}", i), ObjType::Map)
+                .unwrap();
+            let text = doc.put_object(&map, "text", ObjType::Text).unwrap();
+            doc.splice_text(&text, 0, 0, &format!("hello {}", i))
+                .unwrap();
+            doc.commit_with(crate::transaction::CommitOptions::default().with_time(0));
+        }
+        doc
+    };
+    let mut eager = build(false);
+    let mut doc = build(true);
+    assert_eq!(doc.document().ops.spilled_tree_count(), 41 - 3);
+    // the ops of spilled objects are dropped from memory
+    assert!(doc.document().ops.resident_op_count() * 4 < eager.document().ops.resident_op_count());
+
+    let text_of = |doc: &AutoCommit, i: usize| {
+        let (_, map) = doc.get(ROOT, format!("map{}", i)).unwrap().unwrap();
+        let (_, text) = doc.get(&map, "text").unwrap().unwrap();
+        text
+    };
+    for i in 0..20 {
+        let text = text_of(&doc, i);
+        assert_eq!(doc.text(&text).unwrap(), format!("hello {}", i));
+    }
+    assert_eq!(doc.document().ops.spilled_tree_count(), 0);
+    // reads don't make trees resident again, the trees they rebuilt are cached and the cache is
+    // trimmed to the same limit
+    assert_eq!(doc.spill_cold_objects(), 0);
+    assert_eq!(doc.document().ops.spilled_tree_count(), 41 - 3 - 3);
+    // saving rebuilds the objects without caching them
+    assert_eq!(doc.save(), eager.save());
+    assert_eq!(doc.document().ops.spilled_tree_count(), 41 - 3 - 3);
+
+    let cold = text_of(&doc, 0);
+    let mut inner = doc.document().clone();
+    let mut tx = inner.transaction();
+    tx.splice_text(&cold, 0, 0, ">").unwrap();
+    tx.rollback();
+    assert_eq!(inner.text(&cold).unwrap(), "hello 0");
+    assert_eq!(inner.save(), eager.save());
+
+    // the ops of an object rebuilt in the middle of a transaction go before the ops the
+    // transaction has already made
+    let fork = doc.fork();
+    for doc in [&mut doc, &mut eager] {
+        doc.put(ROOT, "before", 1).unwrap();
+        doc.splice_text(&cold, 5, 0, ",").unwrap();
+        doc.commit_with(crate::transaction::CommitOptions::default().with_time(0));
+    }
+    assert_eq!(doc.text(&cold).unwrap(), "hello, 0");
+    assert_eq!(fork.text(&cold).unwrap(), "hello 0");
+    assert_eq!(doc.save(), eager.save());
+    // the parent of the edited text is still spilled
+    assert_eq!(
+        doc.parents(&cold).unwrap().path(),
+        eager.parents(&cold).unwrap().path()
+    );
+
+    doc.set_lazy_objects(None);
+    assert_eq!(doc.document().ops.spilled_tree_count(), 0);
+    assert_eq!(
+        doc.document().ops.resident_op_count(),
+        eager.document().ops.resident_op_count()
+    );
+    assert_eq!(
+        doc.hydrate(ROOT, None).unwrap(),
+        eager.hydrate(ROOT, None).unwrap()
+    );
+    assert_eq!(doc.save(), eager.save());
+}
+
+#[test]
+fn lazy_objects_reattach_resident_children_of_rebuilt_objects() {
+    let build = |lazy: bool| {
+        let mut doc = AutoCommit::new().with_actor(ActorId::from([1]));
+        if lazy {
+            doc = doc.with_lazy_objects(3);
+        }
+        let list = doc.put_object(ROOT, "list", ObjType::List).unwrap();
+        let child = doc.insert_object(&list, 0, ObjType::Map).unwrap();
+        let other = doc.put_object(ROOT, "other", ObjType::Map).unwrap();
+        doc.commit_with(crate::transaction::CommitOptions::default().with_time(0));
+        for i in 0..5 {
+            doc.put(&child, "x", i).unwrap();
+            doc.put(&other, "x", i).unwrap();
+            doc.commit_with(crate::transaction::CommitOptions::default().with_time(0));
+        }
+        (doc, list, child)
+    };
+    let (mut eager, _, _) = build(false);
+    let (mut doc, list, child) = build(true);
+    assert_eq!(doc.document().ops.spilled_tree_count(), 1);
+
+    // the list is rebuilt after the transaction has made an op, the child map which is still
+    // resident gets its parent op back
+    for doc in [&mut doc, &mut eager] {
+        doc.put(&child, "y", 1).unwrap();
+        doc.insert(&list, 1, "z").unwrap();
+        doc.commit_with(crate::transaction::CommitOptions::default().with_time(0));
+    }
+    assert_eq!(
+        doc.parents(&child).unwrap().path(),
+        vec![(ROOT, Prop::Map("list".into())), (list, Prop::Seq(0))]
+    );
+    assert_eq!(
+        doc.hydrate(ROOT, None).unwrap(),
+        eager.hydrate(ROOT, None).unwrap()
+    );
+    assert_eq!(doc.save(), eager.save());
+}
+
+#[test]
+fn list_endpoints_finds_first_and_last_visible_elements() {
+    let mut doc = AutoCommit::new();
+    let list = doc.put_object(ROOT, "list", ObjType::List).unwrap();
+    assert_eq!(doc.list_endpoints(&list, None).unwrap(), None);
+    doc.insert(&list, 0, "only").unwrap();
+    assert_eq!(
+        doc.list_endpoints(&list, None).unwrap(),
+        Some(((0, Value::from("only")), (0, Value::from("only"))))
+    );
+    for i in 1..5 {
+        doc.insert(&list, i, i as i64).unwrap();
+    }
+    let heads = doc.get_heads();
+    doc.delete(&list, 0).unwrap();
+    doc.delete(&list, 3).unwrap();
+    assert_eq!(
+        doc.list_endpoints(&list, None).unwrap(),
+        Some(((0, Value::from(1)), (2, Value::from(3))))
+    );
+    assert_eq!(
+        doc.list_endpoints(&list, Some(&heads)).unwrap(),
+        Some(((0, Value::from("only")), (4, Value::from(4))))
+    );
+
+    let text = doc.put_object(ROOT, "text", ObjType::Text).unwrap();
+    doc.splice_text(&text, 0, 0, "a😀b").unwrap();
+    assert_eq!(
+        doc.list_endpoints(&text, None).unwrap(),
+        Some(((0, Value::from("a")), (2, Value::from("b"))))
+    );
+    assert!(matches!(
+        doc.list_endpoints(ROOT, None),
+        Err(AutomergeError::InvalidOp(ObjType::Map))
+    ));
+}
+
+#[test]
+fn map_values_sorted_by_breaks_ties_by_key() {
+    let mut doc = AutoCommit::new();
+    let records = doc.put_object(ROOT, "records", ObjType::Map).unwrap();
+    for (key, age) in [("dora", 31), ("bob", 25), ("carl", 31), ("ann", 40)] {
+        doc.put(&records, key, age).unwrap();
+    }
+    let heads = doc.get_heads();
+    doc.put(&records, "ann", 20).unwrap();
+    doc.put_object(&records, "eve", ObjType::List).unwrap();
+
+    let sorted = |doc: &AutoCommit, heads: Option<&[ChangeHash]>| -> Vec<String> {
+        doc.map_values_sorted_by(&records, |v| v.to_i64(), heads)
+            .unwrap()
+            .into_iter()
+            .map(|(prop, _)| prop.to_string())
+            .collect()
+    };
+    assert_eq!(
+        sorted(&doc, None),
+        vec!["eve", "ann", "bob", "carl", "dora"]
+    );
+    assert_eq!(
+        sorted(&doc, Some(&heads)),
+        vec!["bob", "carl", "dora", "ann"]
+    );
+
+    let list = doc.put_object(ROOT, "list", ObjType::List).unwrap();
+    assert!(matches!(
+        doc.map_values_sorted_by(&list, |v| v.to_i64(), None),
+        Err(AutomergeError::InvalidOp(ObjType::List))
+    ));
+}
+
+#[test]
+fn clear_deletes_every_visible_value() {
+    let mut doc1 = AutoCommit::new();
+    let map = doc1.put_object(ROOT, "map", ObjType::Map).unwrap();
+    let list = doc1.put_object(ROOT, "list", ObjType::List).unwrap();
+    let text = doc1.put_object(ROOT, "text", ObjType::Text).unwrap();
+    doc1.put(&map, "a", 1).unwrap();
+    doc1.put(&map, "b", 2).unwrap();
+    doc1.splice(&list, 0, 0, [1.into(), 2.into()]).unwrap();
+    doc1.splice_text(&text, 0, 0, "h😀llo").unwrap();
+    let mut doc2 = doc1.fork();
+    doc2.put(&map, "a", 3).unwrap();
+    doc1.put(&map, "a", 4).unwrap();
+    doc1.merge(&mut doc2).unwrap();
+    assert_eq!(doc1.get_all(&map, "a").unwrap().len(), 2);
+
+    for obj in [&map, &list, &text] {
+        doc1.clear(obj).unwrap();
+        assert_eq!(doc1.length(obj), 0);
+    }
+    assert_eq!(doc1.get_all(&map, "a").unwrap().len(), 0);
+    assert_eq!(doc1.text(&text).unwrap(), "");
+
+    doc1.commit();
+    for obj in [&map, &list, &text] {
+        doc1.clear(obj).unwrap();
+    }
+    assert_eq!(doc1.pending_ops(), 0);
+}
+
+#[test]
+fn commit_extra_metadata_survives_save_load_and_sync() {
+    let mut doc1 = AutoCommit::new();
+    doc1.put(ROOT, "a", 1).unwrap();
+    let hash = doc1
+        .commit_with(
+            crate::transaction::CommitOptions::default()
+                .with_extra("client", "cli/1.2")
+                .with_extra("device", 7_u64),
+        )
+        .unwrap();
+    doc1.put(ROOT, "b", 2).unwrap();
+    let plain = doc1.commit().unwrap();
+    let empty =
+        doc1.empty_change(crate::transaction::CommitOptions::default().with_extra("merge", true));
+
+    let expected: std::collections::BTreeMap<String, ScalarValue> = [
+        ("client".to_string(), ScalarValue::Str("cli/1.2".into())),
+        ("device".to_string(), ScalarValue::Uint(7)),
+    ]
+    .into_iter()
+    .collect();
+    let mut loaded = AutoCommit::load(&doc1.save()).unwrap();
+    let mut synced = AutoCommit::new();
+    synced.merge(&mut doc1).unwrap();
+    for doc in [&mut doc1, &mut loaded, &mut synced] {
+        let change = doc.get_change_by_hash(&hash).unwrap();
+        assert_eq!(change.extra_metadata(), expected);
+        let change = doc.get_change_by_hash(&plain).unwrap();
+        assert!(change.extra_bytes().is_empty());
+        assert!(change.extra_metadata().is_empty());
+        let change = doc.get_change_by_hash(&empty).unwrap();
+        assert_eq!(
+            change.extra_metadata().get("merge"),
+            Some(&ScalarValue::Boolean(true))
+        );
+    }
+}
+
+#[test]
+fn typed_getters_check_the_type() {
+    let mut doc = AutoCommit::new();
+    doc.put(ROOT, "str", "hello").unwrap();
+    doc.put(ROOT, "int", -3).unwrap();
+    doc.put(ROOT, "uint", 3_u64).unwrap();
+    doc.put(ROOT, "f32", ScalarValue::F32(1.5)).unwrap();
+    doc.put(ROOT, "bool", true).unwrap();
+    doc.put(ROOT, "bytes", ScalarValue::Bytes(vec![1, 2]))
+        .unwrap();
+    let map = doc.put_object(ROOT, "map", ObjType::Map).unwrap();
+
+    assert_eq!(doc.get_str(ROOT, "str").unwrap(), Some("hello".to_string()));
+    assert_eq!(doc.get_int(ROOT, "int").unwrap(), Some(-3));
+    assert_eq!(doc.get_uint(ROOT, "uint").unwrap(), Some(3));
+    assert_eq!(doc.get_f64(ROOT, "f32").unwrap(), Some(1.5));
+    assert_eq!(doc.get_bool(ROOT, "bool").unwrap(), Some(true));
+    assert_eq!(doc.get_bytes(ROOT, "bytes").unwrap(), Some(vec![1, 2]));
+    assert_eq!(doc.get_bool(&map, "missing").unwrap(), None);
+
+    assert!(matches!(
+        doc.get_uint(ROOT, "int"),
+        Err(AutomergeError::InvalidValueType { .. })
+    ));
+    assert!(matches!(
+        doc.get_str(ROOT, "map"),
+        Err(AutomergeError::InvalidValueType { .. })
+    ));
+
+    let heads = doc.get_heads();
+    doc.put(ROOT, "str", 1).unwrap();
+    let at = crate::automerge::diff::ReadDocAt {
+        doc: doc.document(),
+        heads: &heads,
+    };
+    assert_eq!(at.get_str(ROOT, "str").unwrap(), Some("hello".to_string()));
+    assert!(doc.get_str(ROOT, "str").is_err());
+}
+
+#[test]
+fn structural_diff_compares_unrelated_documents_by_path() {
+    let build = |title: &str, text: &str, nested: Option<i64>| {
+        let mut doc = AutoCommit::new();
+        doc.put(ROOT, "title", title).unwrap();
+        let body = doc.put_object(ROOT, "body", ObjType::Text).unwrap();
+        doc.splice_text(&body, 0, 0, text).unwrap();
+        let map = doc.put_object(ROOT, "map", ObjType::Map).unwrap();
+        if let Some(n) = nested {
+            doc.put(&map, "n", n).unwrap();
+        }
+        doc
+    };
+    let mut ours = build("same", "hello", Some(1));
+    let mut theirs = build("same", "hello", Some(1));
+    assert_eq!(ours.document().structural_diff(theirs.document()), vec![]);
+
+    let mut theirs = build("same", "hello world", None);
+    theirs.put(ROOT, "title", 1).unwrap();
+    let diff = ours.document().structural_diff(theirs.document());
+    assert_eq!(diff.len(), 3);
+    assert!(matches!(
+        &diff[0],
+        StructuralDiff::Changed { path, old: hydrate::Value::Text(_), new: hydrate::Value::Text(_) }
+            if path == &vec![Prop::from("body")]
+    ));
+    assert_eq!(
+        diff[1],
+        StructuralDiff::Removed {
+            path: vec![Prop::from("map"), Prop::from("n")],
+            value: 1.into(),
+        }
+    );
+    assert_eq!(
+        diff[2],
+        StructuralDiff::Changed {
+            path: vec![Prop::from("title")],
+            old: "same".into(),
+            new: 1.into(),
+        }
+    );
+}
+
+#[test]
+fn all_marks_groups_marks_by_object() {
+    use crate::marks::{ExpandMark, Mark};
+    let mut doc = AutoCommit::new();
+    let a = doc.put_object(ROOT, "a", ObjType::Text).unwrap();
+    let list = doc.put_object(ROOT, "list", ObjType::List).unwrap();
+    let b = doc.insert_object(&list, 0, ObjType::Text).unwrap();
+    doc.put_object(ROOT, "unmarked", ObjType::Text).unwrap();
+    doc.splice_text(&a, 0, 0, "hello").unwrap();
+    doc.splice_text(&b, 0, 0, "world").unwrap();
+    doc.mark(
+        &a,
+        Mark::new("bold".to_string(), true, 0, 2),
+        ExpandMark::None,
+    )
+    .unwrap();
+    let heads = doc.get_heads();
+    doc.mark(
+        &b,
+        Mark::new("comment".to_string(), "c1", 1, 3),
+        ExpandMark::None,
+    )
+    .unwrap();
+
+    let all = doc.all_marks().collect::<Vec<_>>();
+    assert_eq!(
+        all.iter().map(|(obj, _)| obj.clone()).collect::<Vec<_>>(),
+        vec![a.clone(), b.clone()]
+    );
+    assert_eq!(all[1].1, doc.marks(&b).unwrap());
+
+    let before = doc.all_marks_at(&heads).collect::<Vec<_>>();
+    assert_eq!(before.len(), 1);
+    assert_eq!(before[0].0, a);
+
+    doc.unmark(&a, "bold", 0, 2, ExpandMark::None).unwrap();
+    assert_eq!(doc.all_marks().count(), 1);
+}
+
+#[test]
+fn load_incremental_chunked_matches_load_incremental() {
+    let mut source = AutoCommit::new();
+    source.put(ROOT, "base", 0).unwrap();
+    let mut data = source.save();
+    let base_heads = source.get_heads();
+    let mut changes = Vec::new();
+    for i in 0..7 {
+        source.put(ROOT, "count", i).unwrap();
+        changes.push(source.commit().unwrap());
+    }
+    // a document chunk followed by the changes in reverse order, so most of them have to wait
+    // for their dependencies
+    for hash in changes.iter().rev() {
+        data.extend(source.get_change_by_hash(hash).unwrap().raw_bytes());
+    }
+
+    let mut expected = AutoCommit::new();
+    expected.load_incremental(&data).unwrap();
+
+    let mut doc = AutoCommit::new();
+    let mut groups = Vec::new();
+    let applied = doc
+        .load_incremental_chunked(&data, 3, |hashes| groups.push(hashes.to_vec()))
+        .unwrap();
+    assert_eq!(doc.get_heads(), expected.get_heads());
+    assert_eq!(
+        doc.hydrate(&ROOT, None).unwrap(),
+        expected.hydrate(&ROOT, None).unwrap()
+    );
+    assert_eq!(applied.len(), 8);
+    assert_eq!(applied, groups.concat());
+    // the first group is the document chunk, nothing applies until the first change arrives
+    assert_eq!(groups[0], base_heads);
+    assert_eq!(groups[1], Vec::<ChangeHash>::new());
+    assert_eq!(groups.last().unwrap(), &changes);
+
+    // loading the same data again applies nothing
+    assert!(doc
+        .load_incremental_chunked(&data, 0, |_| {})
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn objects_affected_by_includes_created_objects_from_unknown_actors() {
+    let mut doc = AutoCommit::new();
+    let list = doc.put_object(ROOT, "list", ObjType::List).unwrap();
+    doc.commit();
+
+    let mut other = doc.fork();
+    let map = other.insert_object(&list, 0, ObjType::Map).unwrap();
+    let text = other.put_object(&map, "text", ObjType::Text).unwrap();
+    other.splice_text(&text, 0, 0, "hello").unwrap();
+    other.commit();
+    other.put(ROOT, "done", true).unwrap();
+    let changes = other
+        .get_changes(&doc.get_heads())
+        .into_iter()
+        .cloned()
+        .collect::<Vec<_>>();
+
+    let affected = doc.document().objects_affected_by(&changes).unwrap();
+    let expected = [ROOT, list, map.clone(), text.clone()]
+        .into_iter()
+        .collect::<std::collections::HashSet<_>>();
+    assert_eq!(affected, expected);
+
+    // the ids agree with those of the objects once the changes are applied
+    doc.apply_changes(changes).unwrap();
+    assert_eq!(doc.get(&map, "text").unwrap().unwrap().1, text);
+}
+
+#[test]
+fn load_shallow_reads_the_root_map_like_a_full_load() {
+    let mut doc = AutoCommit::new();
+    doc.put(ROOT, "title", "draft").unwrap();
+    doc.put(ROOT, "title", "final").unwrap();
+    doc.put(ROOT, "gone", 1).unwrap();
+    doc.delete(ROOT, "gone").unwrap();
+    doc.put(ROOT, "count", ScalarValue::counter(1)).unwrap();
+    doc.increment(ROOT, "count", 5).unwrap();
+    let map = doc.put_object(ROOT, "map", ObjType::Map).unwrap();
+    let list = doc.put_object(&map, "list", ObjType::List).unwrap();
+    doc.insert(&list, 0, "a").unwrap();
+    doc.commit();
+    let mut other = doc.fork();
+    other.put(ROOT, "title", "theirs").unwrap();
+    doc.put(ROOT, "title", "ours").unwrap();
+    doc.merge(&mut other).unwrap();
+    let bytes = doc.save();
+
+    let full = Automerge::load(&bytes).unwrap();
+    let expected = full
+        .map_range(ROOT, ..)
+        .map(|item| (item.key.to_string(), item.value.into_owned(), item.id))
+        .collect::<Vec<_>>();
+
+    let mut shallow = Automerge::load_shallow(&bytes).unwrap();
+    assert!(!shallow.is_materialized());
+    let root = shallow
+        .keys()
+        .map(|k| {
+            let (value, id) = shallow.get(k).unwrap();
+            (k.to_string(), value, id)
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(root, expected);
+    assert_eq!(shallow.get("gone"), None);
+    assert_eq!(shallow.len(), 3);
+
+    let (_, list) = shallow
+        .document()
+        .unwrap()
+        .get(&map, "list")
+        .unwrap()
+        .unwrap();
+    assert_eq!(shallow.document().unwrap().length(&list), 1);
+    assert!(shallow.is_materialized());
+}
+
+#[test]
+fn load_shallow_loads_everything_when_changes_follow_the_document() {
+    let mut doc = AutoCommit::new();
+    doc.put(ROOT, "a", 1).unwrap();
+    let mut bytes = doc.save();
+    doc.put(ROOT, "b", 2).unwrap();
+    bytes.extend(doc.save_incremental());
+
+    let shallow = Automerge::load_shallow(&bytes).unwrap();
+    assert!(shallow.is_materialized());
+    assert_eq!(shallow.keys().collect::<Vec<_>>(), vec!["a", "b"]);
+
+    assert!(Automerge::load_shallow(&[]).unwrap().is_empty());
+    assert!(Automerge::load_shallow(&bytes[..bytes.len() - 1]).is_err());
+}
+
+#[test]
+fn compact_keeps_heads_reads_and_saved_bytes() {
+    let mut doc = AutoCommit::new().with_lazy_objects(2);
+    let text = doc.put_object(ROOT, "text", ObjType::Text).unwrap();
+    doc.splice_text(&text, 0, 0, "hello world").unwrap();
+    let mut lists = Vec::new();
+    for i in 0..5 {
+        let list = doc
+            .put_object(ROOT, format!("list{}", i), ObjType::List)
+            .unwrap();
+        for j in 0..10 {
+            doc.insert(&list, j, j as i64).unwrap();
+        }
+        doc.commit();
+        lists.push(list);
+    }
+    let before = doc.get_heads();
+    doc.splice_text(&text, 0, 6, "").unwrap();
+    for list in &lists {
+        doc.delete(list, 3).unwrap();
+        doc.put(list, 0, "first").unwrap();
+    }
+    doc.put(ROOT, "list0", "replaced").unwrap();
+    // read a spilled object so its tree is rebuilt
+    assert_eq!(doc.length(&lists[1]), 9);
+
+    let heads = doc.get_heads();
+    let current = doc.hydrate(ROOT, None).unwrap();
+    let earlier = doc.hydrate(ROOT, Some(&before)).unwrap();
+    let saved = doc.save();
+
+    doc.compact();
+    assert_eq!(doc.get_heads(), heads);
+    assert_eq!(doc.hydrate(ROOT, None).unwrap(), current);
+    assert_eq!(doc.hydrate(ROOT, Some(&before)).unwrap(), earlier);
+    assert_eq!(doc.text(&text).unwrap(), "world");
+    assert_eq!(doc.list_range(&lists[0], ..).count(), 9);
+    assert_eq!(doc.save(), saved);
+
+    // the compacted document can still be edited
+    doc.insert(&lists[2], 0, "new").unwrap();
+    doc.splice_text(&text, 5, 0, "!").unwrap();
+    assert_eq!(doc.length(&lists[2]), 10);
+    assert_eq!(doc.text(&text).unwrap(), "world!");
+}
+
+#[test]
+fn compact_packs_op_trees_into_fewer_nodes() {
+    let mut doc = AutoCommit::new().with_op_tree_node_size(2);
+    let text = doc.put_object(ROOT, "text", ObjType::Text).unwrap();
+    // inserting in the middle splits nodes and leaves them half full
+    for i in 0..200 {
+        doc.splice_text(&text, i / 2, 0, "a").unwrap();
+    }
+    doc.commit();
+    let stats = doc.object_stats(&text).unwrap();
+    let mut uncompacted = doc.fork();
+
+    doc.compact();
+    let compacted = doc.object_stats(&text).unwrap();
+    assert!(compacted.node_count < stats.node_count);
+    assert!(compacted.tree_depth <= stats.tree_depth);
+    assert_eq!(compacted.op_count, stats.op_count);
+    assert_eq!(doc.document().ops.resident_op_count(), 201);
+    assert_eq!(doc.text(&text).unwrap(), "a".repeat(200));
+
+    // the packed nodes are split and merged by later edits like any other
+    for doc in [&mut doc, &mut uncompacted] {
+        for i in 0..50 {
+            doc.splice_text(&text, i * 3, 1, "bc").unwrap();
+        }
+        for i in 0..50 {
+            doc.splice_text(&text, i, 2, "").unwrap();
+        }
+    }
+    assert_eq!(doc.text(&text).unwrap(), uncompacted.text(&text).unwrap());
+    assert_eq!(
+        doc.hydrate(ROOT, None).unwrap(),
+        uncompacted.hydrate(ROOT, None).unwrap()
+    );
+    assert!(doc.document().verify_integrity().unwrap().is_ok());
+}
@@ -0,0 +1,243 @@
+use std::num::NonZeroU64;
+
+use crate::clock::Clock;
+use crate::{ActorId, AutomergeError, Change, ChangeHash};
+
+use super::Automerge;
+
+/// A change in the history of a document
+// most entries are changes, boxing them would cost an allocation per change to save space on
+// the trimmed ones
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone, PartialEq)]
+pub(super) enum HistoryEntry {
+    Change(Change),
+    /// A change whose contents were dropped by [`Automerge::trim_changes()`]
+    Trimmed(TrimmedChange),
+}
+
+/// What is kept of a change when it is trimmed, enough to find the change an op belongs to and
+/// to walk the history through it
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct TrimmedChange {
+    hash: ChangeHash,
+    actor: ActorId,
+    seq: u64,
+    start_op: NonZeroU64,
+    len: usize,
+    timestamp: i64,
+    deps: Vec<ChangeHash>,
+}
+
+/// The document at the point its history was trimmed to
+#[derive(Debug, Clone)]
+pub(super) struct Snapshot {
+    /// The heads the history was trimmed to by [`Automerge::trim_changes()`]
+    pub(super) heads: Vec<ChangeHash>,
+    /// The document at `heads`, as returned by [`Automerge::save()`]
+    pub(super) bytes: Vec<u8>,
+}
+
+impl HistoryEntry {
+    /// The change, unless it has been trimmed
+    pub(super) fn change(&self) -> Option<&Change> {
+        match self {
+            Self::Change(change) => Some(change),
+            Self::Trimmed(_) => None,
+        }
+    }
+
+    pub(super) fn is_trimmed(&self) -> bool {
+        matches!(self, Self::Trimmed(_))
+    }
+
+    pub(super) fn hash(&self) -> ChangeHash {
+        match self {
+            Self::Change(change) => change.hash(),
+            Self::Trimmed(trimmed) => trimmed.hash,
+        }
+    }
+
+    pub(super) fn actor_id(&self) -> &ActorId {
+        match self {
+            Self::Change(change) => change.actor_id(),
+            Self::Trimmed(trimmed) => &trimmed.actor,
+        }
+    }
+
+    pub(super) fn seq(&self) -> u64 {
+        match self {
+            Self::Change(change) => change.seq(),
+            Self::Trimmed(trimmed) => trimmed.seq,
+        }
+    }
+
+    pub(super) fn start_op(&self) -> NonZeroU64 {
+        match self {
+            Self::Change(change) => change.start_op(),
+            Self::Trimmed(trimmed) => trimmed.start_op,
+        }
+    }
+
+    pub(super) fn len(&self) -> usize {
+        match self {
+            Self::Change(change) => change.len(),
+            Self::Trimmed(trimmed) => trimmed.len,
+        }
+    }
+
+    pub(super) fn max_op(&self) -> u64 {
+        self.start_op().get() + (self.len() as u64) - 1
+    }
+
+    pub(super) fn timestamp(&self) -> i64 {
+        match self {
+            Self::Change(change) => change.timestamp(),
+            Self::Trimmed(trimmed) => trimmed.timestamp,
+        }
+    }
+
+    pub(super) fn deps(&self) -> &[ChangeHash] {
+        match self {
+            Self::Change(change) => change.deps(),
+            Self::Trimmed(trimmed) => &trimmed.deps,
+        }
+    }
+}
+
+impl From<&Change> for TrimmedChange {
+    fn from(change: &Change) -> Self {
+        Self {
+            hash: change.hash(),
+            actor: change.actor_id().clone(),
+            seq: change.seq(),
+            start_op: change.start_op(),
+            len: change.len(),
+            timestamp: change.timestamp(),
+            deps: change.deps().to_vec(),
+        }
+    }
+}
+
+impl Automerge {
+    /// Drop the changes which are ancestors of `keep_from` from the history of the document
+    ///
+    /// The current state of the document is unchanged, and the history can still be read at any
+    /// heads, but the changes themselves are gone: [`Self::get_changes()`] and
+    /// [`ReadDoc::get_change_by_hash()`](crate::ReadDoc::get_change_by_hash) only return
+    /// `keep_from` and the changes which aren't ancestors of it. This is for documents whose
+    /// change log has grown much larger than the state it produces.
+    ///
+    /// The document at `keep_from` is kept as a saved snapshot, which is far smaller than the
+    /// changes it replaces. [`Self::save()`] returns the snapshot followed by the remaining
+    /// changes, so loading it gives back a document with the whole history. When syncing with a
+    /// peer which is missing trimmed changes, or merging into a document which is, the snapshot
+    /// is sent instead of the changes. This needs a peer which supports V2 sync messages, a peer
+    /// which only supports V1 messages can't receive the trimmed changes at all.
+    ///
+    /// Trimming at a point before an earlier trim keeps the earlier cut as well.
+    ///
+    /// ```
+    /// # use automerge::{transaction::Transactable, AutoCommit, Automerge, ReadDoc, ROOT};
+    /// let mut doc = AutoCommit::new();
+    /// for i in 0..10 {
+    ///     doc.put(ROOT, "count", i).unwrap();
+    ///     doc.commit();
+    /// }
+    /// let keep_from = doc.get_heads();
+    /// doc.put(ROOT, "count", 10).unwrap();
+    /// doc.commit();
+    ///
+    /// doc.trim_changes(&keep_from).unwrap();
+    /// assert_eq!(doc.get_changes(&[]).len(), 2);
+    ///
+    /// let loaded = Automerge::load(&doc.save()).unwrap();
+    /// assert_eq!(loaded.get_heads(), doc.get_heads());
+    /// assert_eq!(loaded.get_changes(&[]).len(), 11);
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AutomergeError::MissingHash`] if any of `keep_from` is not a change in this
+    /// document
+    pub fn trim_changes(&mut self, keep_from: &[ChangeHash]) -> Result<(), AutomergeError> {
+        if let Some(missing) = keep_from
+            .iter()
+            .find(|h| !self.history_index.contains_key(h))
+        {
+            return Err(AutomergeError::MissingHash(*missing));
+        }
+        let mut heads = keep_from.to_vec();
+        if let Some(snapshot) = &self.snapshot {
+            heads.extend(&snapshot.heads);
+        }
+        heads.sort_unstable();
+        heads.dedup();
+        // an earlier cut which is an ancestor of `keep_from` is trimmed as well
+        let all_heads = heads.clone();
+        heads.retain(|head| {
+            let others = all_heads
+                .iter()
+                .filter(|h| *h != head)
+                .copied()
+                .collect::<Vec<_>>();
+            let entry = &self.history[self.history_index[head]];
+            !self.clock_includes(&self.clock_at(&others), entry)
+        });
+
+        let clock = self.clock_at(&heads);
+        let to_trim = self
+            .history
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| !entry.is_trimmed() && !heads.contains(&entry.hash()))
+            .filter(|(_, entry)| self.clock_includes(&clock, entry))
+            .map(|(index, _)| index)
+            .collect::<Vec<_>>();
+        if to_trim.is_empty() {
+            return Ok(());
+        }
+
+        let bytes = self.fork_at(&heads)?.save();
+        for index in to_trim {
+            let entry = &mut self.history[index];
+            if let HistoryEntry::Change(change) = entry {
+                *entry = HistoryEntry::Trimmed(TrimmedChange::from(&*change));
+            }
+        }
+        self.snapshot = Some(Snapshot { heads, bytes });
+        Ok(())
+    }
+
+    /// Whether `entry` is in the history of the heads `clock` was computed for
+    pub(super) fn clock_includes(&self, clock: &Clock, entry: &HistoryEntry) -> bool {
+        self.ops
+            .osd
+            .actors
+            .lookup(entry.actor_id())
+            .and_then(|actor| clock.get_for_actor(&actor))
+            .is_some_and(|data| data.seq >= entry.seq())
+    }
+
+    /// Whether the change with `hash` has been trimmed by [`Self::trim_changes()`]
+    pub(crate) fn is_trimmed(&self, hash: &ChangeHash) -> bool {
+        self.history_index
+            .get(hash)
+            .is_some_and(|index| self.history[*index].is_trimmed())
+    }
+
+    /// Whether the change with `hash` has been applied to this document, even if it has since
+    /// been trimmed
+    pub(crate) fn has_change(&self, hash: &ChangeHash) -> bool {
+        self.history_index.contains_key(hash)
+    }
+
+    /// The snapshot of the trimmed history, if `self` has trimmed changes which `other` hasn't
+    /// applied
+    pub(super) fn snapshot_missing_from(&self, other: &Self) -> Option<&[u8]> {
+        self.snapshot
+            .as_ref()
+            .filter(|snapshot| snapshot.heads.iter().any(|h| !other.has_change(h)))
+            .map(|snapshot| snapshot.bytes.as_slice())
+    }
+}
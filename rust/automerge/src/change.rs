@@ -1,12 +1,13 @@
-use std::{borrow::Cow, num::NonZeroU64};
+use std::{borrow::Cow, collections::BTreeMap, num::NonZeroU64};
 
 use crate::{
     columnar::Key as StoredKey,
     storage::{
-        change::{Unverified, Verified},
+        change::{extra_metadata, Unverified, Verified},
         parse, Change as StoredChange, ChangeOp, Chunk, Compressed, ReadChangeOpError,
     },
     types::{ActorId, ChangeHash, ElemId},
+    ScalarValue,
 };
 
 #[derive(Clone, Debug, PartialEq)]
@@ -104,6 +105,21 @@ impl Change {
         }
     }
 
+    /// The length of [`Self::bytes()`], compressing the change if that hasn't happened yet
+    ///
+    /// Unlike [`Self::bytes()`] this doesn't cache the compressed bytes, so it can be used on a
+    /// shared reference.
+    pub fn compressed_len(&self) -> usize {
+        match &self.compression {
+            CompressionState::Compressed(c) => c.bytes().len(),
+            CompressionState::TooSmallToCompress => self.stored.bytes().len(),
+            CompressionState::NotCompressed => match self.stored.compress() {
+                Some(c) => c.bytes().len(),
+                None => self.stored.bytes().len(),
+            },
+        }
+    }
+
     pub fn raw_bytes(&self) -> &[u8] {
         self.stored.bytes()
     }
@@ -112,10 +128,24 @@ impl Change {
         self.stored.iter_ops()
     }
 
+    /// Release the spare capacity of the buffers this change owns, see
+    /// [`crate::Automerge::compact()`]
+    pub(crate) fn shrink_to_fit(&mut self) {
+        self.stored.shrink_to_fit();
+    }
+
     pub fn extra_bytes(&self) -> &[u8] {
         self.stored.extra_bytes()
     }
 
+    /// The metadata attached to this change with [`crate::transaction::CommitOptions::with_extra()`]
+    ///
+    /// This is stored in the [`Self::extra_bytes()`] of the change. The map is empty if the change
+    /// has no metadata, or if the extra bytes were written by something else.
+    pub fn extra_metadata(&self) -> BTreeMap<String, ScalarValue> {
+        extra_metadata::decode(self.extra_bytes()).unwrap_or_default()
+    }
+
     // TODO replace all uses of this with TryFrom<&[u8]>
     pub fn from_bytes(bytes: Vec<u8>) -> Result<Self, LoadError> {
         Self::try_from(&bytes[..])
@@ -368,7 +398,7 @@ pub(crate) mod gen {
         let mut all_actors = vec![this_actor];
         all_actors.extend(other_actors);
         let mut osd = OpSetData::from_actors(all_actors);
-        osd.props.cache("someprop".to_string());
+        osd.import_prop("someprop");
         let root_id = ObjId::root();
         (0_u64..10)
             .prop_map(|num_ops| {
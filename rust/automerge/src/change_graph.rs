@@ -58,6 +58,13 @@ impl ChangeGraph {
         }
     }
 
+    pub(crate) fn shrink_to_fit(&mut self) {
+        self.nodes.shrink_to_fit();
+        self.edges.shrink_to_fit();
+        self.hashes.shrink_to_fit();
+        self.clock_cache.shrink_to_fit();
+    }
+
     pub(crate) fn add_change(
         &mut self,
         change: &Change,
@@ -134,6 +141,20 @@ impl ChangeGraph {
         })
     }
 
+    /// Every `(change, dependency)` pair in the graph, in the order the changes were added
+    pub(crate) fn edges(&self) -> impl Iterator<Item = (ChangeHash, ChangeHash)> + '_ {
+        (0..self.nodes.len()).flat_map(move |idx| {
+            let node = NodeIdx(idx as u32);
+            let hash = self.node_hash(node);
+            self.parents(node)
+                .map(move |parent| (hash, self.node_hash(parent)))
+        })
+    }
+
+    fn node_hash(&self, node: NodeIdx) -> ChangeHash {
+        self.hashes[self.nodes[node.0 as usize].hash_idx.0 as usize]
+    }
+
     fn heads_to_nodes(&self, heads: &[ChangeHash]) -> Vec<NodeIdx> {
         heads
             .iter()
@@ -287,6 +308,25 @@ mod tests {
         assert_eq!(changes, expected_changes);
     }
 
+    #[test]
+    fn edges() {
+        let mut builder = TestGraphBuilder::new();
+        let actor1 = builder.actor();
+        let actor2 = builder.actor();
+        let change1 = builder.change(&actor1, 10, &[]);
+        let change2 = builder.change(&actor2, 20, &[change1]);
+        let change3 = builder.change(&actor1, 10, &[change1]);
+        let change4 = builder.change(&actor1, 10, &[change2, change3]);
+        let graph = builder.build();
+
+        let edges = graph.edges().collect::<Vec<_>>();
+        assert_eq!(edges[..2], [(change2, change1), (change3, change1)]);
+        // dependencies are stored sorted by hash
+        let mut deps = [change2, change3];
+        deps.sort();
+        assert_eq!(edges[2..], [(change4, deps[0]), (change4, deps[1])]);
+    }
+
     struct TestGraphBuilder {
         actors: Vec<ActorId>,
         changes: Vec<Change>,
@@ -323,7 +363,7 @@ mod tests {
             parents: &[ChangeHash],
         ) -> ChangeHash {
             let mut osd = OpSetData::from_actors(self.actors.clone());
-            let key = osd.props.cache("key".to_string());
+            let key = osd.import_prop("key");
 
             let start_op = parents
                 .iter()
@@ -18,8 +18,12 @@ impl PartialOrd for ClockData {
 }
 
 /// Vector clock mapping actor indices to the max op counter of the changes created by that actor.
+///
+/// A clock identifies a point in the history of a document in the same way as a set of heads,
+/// see [`crate::Automerge::clock_for_heads()`]. It is only meaningful for the document it was
+/// obtained from.
 #[derive(Default, Debug, Clone, PartialEq)]
-pub(crate) struct Clock(im::HashMap<usize, ClockData, FxBuildHasher>);
+pub struct Clock(im::HashMap<usize, ClockData, FxBuildHasher>);
 
 // A general clock is greater if it has one element the other does not or has a counter higher than
 // the other for a given actor.
@@ -231,19 +231,20 @@ impl<'a> Iterator for ValueIter<'a> {
                         Ok(ScalarValue::Str(val))
                     }),
                     ValueType::Float => self.parse_raw(val_meta, |bytes| {
-                        if val_meta.length() != 8 {
-                            return Err(DecodeColumnError::invalid_value(
+                        // SAFETY: parse_raw() calls read_bytes(val_meta.length()) so the
+                        //         conversions succeed for the lengths they are matched on
+                        match val_meta.length() {
+                            8 => Ok(ScalarValue::F64(f64::from_le_bytes(
+                                bytes.try_into().unwrap(),
+                            ))),
+                            4 => Ok(ScalarValue::F32(f32::from_le_bytes(
+                                bytes.try_into().unwrap(),
+                            ))),
+                            other => Err(DecodeColumnError::invalid_value(
                                 "value",
-                                format!("float should have length 8, had {0}", val_meta.length()),
-                            ));
+                                format!("float should have length 4 or 8, had {0}", other),
+                            )),
                         }
-                        let raw: [u8; 8] = bytes
-                            .try_into()
-                            // SAFETY: parse_raw() calls read_bytes(val_meta.length()) and we have
-                            //         checked that val_meta.length() == 8
-                            .unwrap();
-                        let val = f64::from_le_bytes(raw);
-                        Ok(ScalarValue::F64(val))
                     }),
                     ValueType::Counter => self.parse_input(val_meta, |input| {
                         leb128_i64(input).map(|(i, n)| (i, ScalarValue::Counter(n.into())))
@@ -361,6 +362,7 @@ fn encode_val<S: Sink>(out: &mut RawEncoder<S>, val: &ScalarValue) -> usize {
         ScalarValue::Boolean(_) => 0,
         ScalarValue::Timestamp(i) => out.append(*i),
         ScalarValue::F64(f) => out.append(*f),
+        ScalarValue::F32(f) => out.append(*f),
         ScalarValue::Counter(i) => out.append(i.start),
         ScalarValue::Str(s) => out.append(RawBytes::from(s.as_bytes())),
         ScalarValue::Bytes(b) => out.append(RawBytes::from(&b[..])),
@@ -421,6 +423,7 @@ impl From<&ScalarValue> for ValueMeta {
             }),
             ScalarValue::Timestamp(i) => Self((lebsize(*i) << 4) | 9),
             ScalarValue::F64(_) => Self((8 << 4) | 5),
+            ScalarValue::F32(_) => Self((4 << 4) | 5),
             ScalarValue::Counter(i) => Self((lebsize(i.start) << 4) | 8),
             ScalarValue::Str(s) => Self(((s.as_bytes().len() as u64) << 4) | 6),
             ScalarValue::Bytes(b) => Self(((b.len() as u64) << 4) | 7),
@@ -454,7 +457,7 @@ impl From<&ScalarValue> for ValueType {
                 false => ValueType::False,
             },
             ScalarValue::Timestamp(_) => ValueType::Timestamp,
-            ScalarValue::F64(_) => ValueType::Float,
+            ScalarValue::F64(_) | ScalarValue::F32(_) => ValueType::Float,
             ScalarValue::Counter(_) => ValueType::Counter,
             ScalarValue::Str(_) => ValueType::String,
             ScalarValue::Bytes(_) => ValueType::Bytes,
@@ -165,6 +165,7 @@ pub(crate) fn scalar_value() -> impl Strategy<Value = ScalarValue> + Clone {
         any::<u64>().prop_map(ScalarValue::Uint),
         encodable_int().prop_map(ScalarValue::Int),
         any::<f64>().prop_map(ScalarValue::F64),
+        any::<f32>().prop_map(ScalarValue::F32),
         smol_str().prop_map(ScalarValue::Str),
         any::<Vec<u8>>().prop_map(ScalarValue::Bytes),
         encodable_int().prop_map(|i| ScalarValue::Counter(i.into())),
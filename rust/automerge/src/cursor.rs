@@ -16,35 +16,58 @@ use std::fmt;
 ///
 /// A cursor is obtained from [`ReadDoc::get_cursor()`] and dereferenced with
 /// [`ReadDoc::get_cursor_position()`].
+///
+/// [`Self::end()`] is a special cursor which isn't anchored to any element, it always resolves
+/// to the current length of the sequence. It is also what [`ReadDoc::get_cursor()`] returns for a
+/// position equal to the length of the sequence.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Cursor(CursorInner);
+
 #[derive(Clone, PartialEq, Debug)]
-pub struct Cursor {
-    ctr: u64,
-    actor: ActorId,
+enum CursorInner {
+    Op { ctr: u64, actor: ActorId },
+    End,
 }
 
 const SERIALIZATION_VERSION_TAG: u8 = 0;
+const END_SERIALIZATION_TAG: u8 = 1;
+const END_STR: &str = "end";
 
 impl Cursor {
     pub(crate) fn new(id: OpId, osd: &OpSetData) -> Self {
-        Self {
+        Self(CursorInner::Op {
             ctr: id.counter(),
             actor: osd.actors.cache[id.actor()].clone(),
-        }
+        })
     }
 
-    pub(crate) fn actor(&self) -> &ActorId {
-        &self.actor
+    /// A cursor which always points at the end of the sequence
+    pub fn end() -> Self {
+        Self(CursorInner::End)
     }
 
-    pub(crate) fn ctr(&self) -> u64 {
-        self.ctr
+    /// Whether this is the [`Self::end()`] cursor
+    pub fn is_end(&self) -> bool {
+        self.0 == CursorInner::End
+    }
+
+    /// The counter and actor of the element this cursor is anchored to, `None` for
+    /// [`Self::end()`]
+    pub(crate) fn op(&self) -> Option<(u64, &ActorId)> {
+        match &self.0 {
+            CursorInner::Op { ctr, actor } => Some((*ctr, actor)),
+            CursorInner::End => None,
+        }
     }
 
     fn from_str(s: &str) -> Option<Self> {
+        if s == END_STR {
+            return Some(Self::end());
+        }
         let n = s.find('@')?;
         let ctr = s[0..n].parse().ok()?;
         let actor = s[(n + 1)..].try_into().ok()?;
-        Some(Cursor { ctr, actor })
+        Some(Cursor(CursorInner::Op { ctr, actor }))
     }
 
     pub fn to_bytes(&self) -> Vec<u8> {
@@ -56,21 +79,29 @@ impl Cursor {
         // |  1 byte   | unsigned leb128 | variable      | unsigned leb128  |
         // '----------------------------------------------------------------'
         //
-        // Version is currently always `0`
+        // Version is currently always `0`. The end cursor is serialized as the single byte `1`
         //
-        let actor_bytes = self.actor.to_bytes();
-        let mut bytes = Vec::with_capacity(actor_bytes.len() + 4 + 4 + 1);
-        bytes.push(SERIALIZATION_VERSION_TAG);
-        leb128::write::unsigned(&mut bytes, actor_bytes.len() as u64).unwrap();
-        bytes.extend_from_slice(actor_bytes);
-        leb128::write::unsigned(&mut bytes, self.ctr).unwrap();
-        bytes
+        match &self.0 {
+            CursorInner::Op { ctr, actor } => {
+                let actor_bytes = actor.to_bytes();
+                let mut bytes = Vec::with_capacity(actor_bytes.len() + 4 + 4 + 1);
+                bytes.push(SERIALIZATION_VERSION_TAG);
+                leb128::write::unsigned(&mut bytes, actor_bytes.len() as u64).unwrap();
+                bytes.extend_from_slice(actor_bytes);
+                leb128::write::unsigned(&mut bytes, *ctr).unwrap();
+                bytes
+            }
+            CursorInner::End => vec![END_SERIALIZATION_TAG],
+        }
     }
 }
 
 impl fmt::Display for Cursor {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}@{}", self.ctr, self.actor)
+        match &self.0 {
+            CursorInner::Op { ctr, actor } => write!(f, "{}@{}", ctr, actor),
+            CursorInner::End => write!(f, "{}", END_STR),
+        }
     }
 }
 
@@ -97,6 +128,9 @@ impl<'a> TryFrom<&'a [u8]> for Cursor {
         let i = parse::Input::new(value);
         let (i, version) =
             parse::take1::<()>(i).map_err(|_| AutomergeError::InvalidCursorFormat)?;
+        if version == END_SERIALIZATION_TAG && i.is_empty() {
+            return Ok(Self::end());
+        }
         if version != SERIALIZATION_VERSION_TAG {
             return Err(AutomergeError::InvalidCursorFormat);
         }
@@ -106,10 +140,10 @@ impl<'a> TryFrom<&'a [u8]> for Cursor {
             .map_err(|_| AutomergeError::InvalidCursorFormat)?;
         let (_i, ctr) = parse::leb128_u64::<parse::leb128::Error>(i)
             .map_err(|_| AutomergeError::InvalidCursorFormat)?;
-        Ok(Self {
+        Ok(Self(CursorInner::Op {
             ctr,
             actor: actor.into(),
-        })
+        }))
     }
 }
 
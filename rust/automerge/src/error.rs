@@ -1,13 +1,16 @@
+use crate::exid::ExId;
 use crate::storage::load::Error as LoadError;
 use crate::types::{ActorId, ScalarValue};
 use crate::value::DataType;
-use crate::{ChangeHash, Cursor, LoadChangeError, ObjType, PatchAction};
+use crate::{ChangeHash, Cursor, LoadChangeError, ObjType, PatchAction, Prop};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum AutomergeError {
     #[error(transparent)]
     ChangeGraph(#[from] crate::change_graph::MissingDep),
+    #[error("the checksum of change {hash} does not match its contents")]
+    ChecksumMismatch { hash: ChangeHash },
     #[error("failed to load compressed data: {0}")]
     Deflate(#[source] std::io::Error),
     #[error("duplicate seq {0} found for actor {1}")]
@@ -22,6 +25,10 @@ pub enum AutomergeError {
     InvalidCharacter(usize),
     #[error("invalid hash {0}")]
     InvalidHash(ChangeHash),
+    #[error("raw op {0} is invalid: {1}")]
+    InvalidRawOp(usize, &'static str),
+    #[error("i/o error: {0}")]
+    Io(#[source] std::io::Error),
     #[error("index {0} is out of bounds")]
     InvalidIndex(usize),
     #[error("invalid obj id `{0}`")]
@@ -41,20 +48,41 @@ pub enum AutomergeError {
         expected: String,
         unexpected: String,
     },
+    #[error("op {op} references the object {obj} which does not exist")]
+    MissingObject { obj: ExId, op: ExId },
+    #[error("the document exceeds the limit of {1} {0}")]
+    LimitExceeded(&'static str, usize),
     #[error(transparent)]
     Load(#[from] LoadError),
     #[error(transparent)]
     LoadChangeError(#[from] LoadChangeError),
     #[error("increment operations must be against a counter value")]
     MissingCounter,
+    #[error("key `{0}` does not exist")]
+    MissingKey(String),
+    #[error("key `{0}` already exists")]
+    KeyExists(String),
+    #[error("object {0} is frozen")]
+    ObjectFrozen(ExId),
     #[error("hash {0} does not correspond to a change in this document")]
     MissingHash(ChangeHash),
+    #[error("change {0} has been trimmed from this document")]
+    TrimmedChange(ChangeHash),
     #[error("change's deps should already be in the document")]
     MissingDeps,
+    #[error("change {0} modifies objects both inside and outside the object being merged")]
+    ChangeOutsideSubtree(ChangeHash),
     #[error("compressed chunk was not a change")]
     NonChangeCompressed,
     #[error("id was not an object id")]
     NotAnObject,
+    /// The prop at `index` in a path passed to a method such as
+    /// [`crate::transaction::Transactable::put_path()`] does not refer to an object, or the
+    /// path is empty and `index` is 0
+    #[error("the path has no object at prop {index}")]
+    InvalidPath { index: usize },
+    #[error("patch path {0:?} does not lead to an object")]
+    UnresolvedPatchPath(Vec<Prop>),
     #[error(transparent)]
     HydrateError(#[from] HydrateError),
 }
@@ -126,9 +154,9 @@ pub enum HydrateError {
     #[error("invalid op appied to list")]
     InvalidListOp,
     #[error("invalid op applied to map: {0}")]
-    InvalidTextOp(PatchAction),
+    InvalidTextOp(Box<PatchAction>),
     #[error("invalid prop in patch: {0}")]
-    ApplyInvalidProp(PatchAction),
+    ApplyInvalidProp(Box<PatchAction>),
 }
 
 #[derive(Error, Debug)]
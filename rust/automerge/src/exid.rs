@@ -68,6 +68,15 @@ impl ExId {
         }
     }
 
+    /// Encode this object ID as a string which does not depend on the document it came from
+    ///
+    /// The string is `_root` for the root object and `{counter}@{actor}` otherwise, where `actor`
+    /// is the hex encoded actor ID. It can be turned back in to an `ExId` for any document
+    /// containing the object with [`crate::ReadDoc::import_exid()`].
+    pub fn to_stable_string(&self) -> String {
+        self.to_string()
+    }
+
     pub(crate) fn to_internal_obj(&self) -> ObjId {
         match self {
             ExId::Root => ObjId::root(),
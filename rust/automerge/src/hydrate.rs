@@ -49,11 +49,11 @@ impl Value {
         match (path.next(), self) {
             (Some(Prop::Seq(n)), Value::List(list)) => list
                 .get_mut(*n)
-                .ok_or_else(|| HydrateError::ApplyInvalidProp(patch.clone()))?
+                .ok_or_else(|| HydrateError::ApplyInvalidProp(Box::new(patch.clone())))?
                 .apply(path, patch),
             (Some(Prop::Map(s)), Value::Map(map)) => map
                 .get_mut(s)
-                .ok_or_else(|| HydrateError::ApplyInvalidProp(patch.clone()))?
+                .ok_or_else(|| HydrateError::ApplyInvalidProp(Box::new(patch.clone())))?
                 .apply(path, patch),
             (None, Value::Map(map)) => map.apply(patch),
             (None, Value::List(list)) => list.apply(patch),
@@ -184,6 +184,37 @@ impl Automerge {
     }
 }
 
+/// Serializes as plain data, the same as [`crate::AutoSerde`]: maps (with their keys sorted) and
+/// lists as their values, text as a string and scalars as in [`ScalarValue`]
+impl serde::Serialize for Value {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        use serde::ser::{SerializeMap, SerializeSeq};
+        match self {
+            Value::Scalar(v) => v.serialize(serializer),
+            Value::Map(map) => {
+                let mut entries = map.iter().collect::<Vec<_>>();
+                entries.sort_by_key(|(key, _)| *key);
+                let mut ser = serializer.serialize_map(Some(entries.len()))?;
+                for (key, value) in entries {
+                    ser.serialize_entry(key, &value.value)?;
+                }
+                ser.end()
+            }
+            Value::List(list) => {
+                let mut ser = serializer.serialize_seq(Some(list.len()))?;
+                for value in list.iter() {
+                    ser.serialize_element(&value.value)?;
+                }
+                ser.end()
+            }
+            Value::Text(text) => serializer.serialize_str(&text.to_string()),
+        }
+    }
+}
+
 #[macro_export]
 macro_rules! hydrate_map {
     {} => {
@@ -227,6 +258,7 @@ impl From<&Value> for wasm_bindgen::JsValue {
                 ScalarValue::Int(v) => (*v as f64).into(),
                 ScalarValue::Uint(v) => (*v as f64).into(),
                 ScalarValue::F64(v) => (*v).into(),
+                ScalarValue::F32(v) => (*v).into(),
                 ScalarValue::Counter(v) => (f64::from(v)).into(),
                 ScalarValue::Timestamp(v) => Date::new(&(*v as f64).into()).into(),
                 ScalarValue::Boolean(v) => (*v).into(),
@@ -41,6 +41,7 @@ impl List {
                 index,
                 value,
                 conflict,
+                ..
             } => {
                 *self
                     .0
@@ -23,7 +23,7 @@ impl Map {
 
     pub(crate) fn apply(&mut self, patch: PatchAction) -> Result<(), HydrateError> {
         match patch {
-            PatchAction::DeleteMap { key } => {
+            PatchAction::DeleteMap { key, .. } => {
                 self.0.remove(&key);
                 Ok(())
             }
@@ -31,6 +31,7 @@ impl Map {
                 key,
                 value,
                 conflict,
+                ..
             } => {
                 self.0
                     .insert(key, MapValue::new(value.0.into(), value.1, conflict));
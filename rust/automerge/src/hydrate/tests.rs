@@ -38,3 +38,31 @@ fn simple_hydrate() -> Result<(), AutomergeError> {
     );
     Ok(())
 }
+
+#[test]
+fn hydrated_values_serialize_like_autoserde() -> Result<(), AutomergeError> {
+    let mut doc = AutoCommit::new();
+    let list = doc.put_object(ROOT, "list", ObjType::List)?;
+    doc.insert(&list, 0, 1)?;
+    let map = doc.insert_object(&list, 1, ObjType::Map)?;
+    doc.put(&map, "b", true)?;
+    doc.put(&map, "a", "str")?;
+    let text = doc.put_object(ROOT, "text", ObjType::Text)?;
+    doc.splice_text(&text, 0, 0, "hello")?;
+    doc.put(ROOT, "counter", ScalarValue::counter(3))?;
+    let heads = doc.get_heads();
+    doc.put(ROOT, "later", 1)?;
+
+    let hydrated = serde_json::to_value(doc.hydrate(ROOT, Some(&heads))?).unwrap();
+    assert_eq!(
+        hydrated,
+        serde_json::json!({
+            "counter": 3,
+            "list": [1, {"a": "str", "b": true}],
+            "text": "hello",
+        })
+    );
+    let full = serde_json::to_string(&doc.hydrate(ROOT, None)?).unwrap();
+    assert_eq!(full, serde_json::to_string(&AutoSerde::from(&doc)).unwrap());
+    Ok(())
+}
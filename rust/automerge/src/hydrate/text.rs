@@ -35,7 +35,7 @@ impl Text {
             PatchAction::Mark { marks: _ } => {
                 todo!()
             }
-            p => Err(HydrateError::InvalidTextOp(p)),
+            p => Err(HydrateError::InvalidTextOp(Box::new(p))),
         }
     }
 
@@ -1,4 +1,5 @@
 use itertools::Itertools;
+use std::borrow::Borrow;
 use std::collections::HashMap;
 use std::hash::Hash;
 use std::ops::Index;
@@ -40,7 +41,11 @@ where
         }
     }
 
-    pub(crate) fn lookup(&self, item: &T) -> Option<usize> {
+    pub(crate) fn lookup<Q>(&self, item: &Q) -> Option<usize>
+    where
+        T: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
         self.lookup.get(item).cloned()
     }
 
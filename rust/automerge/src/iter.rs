@@ -1,15 +1,19 @@
+mod all_marks;
 mod keys;
 mod list_range;
 mod map_range;
+mod objects;
 mod spans;
 mod top_ops;
 mod values;
 
-pub use keys::Keys;
-pub use list_range::{ListRange, ListRangeItem};
+pub use all_marks::AllMarks;
+pub use keys::{Keys, KeysWithConflicts};
+pub use list_range::{ListRange, ListRangeItem, ListRangeRev};
 pub use map_range::{MapRange, MapRangeItem};
+pub use objects::Objects;
 pub use spans::{Span, Spans};
-pub use values::Values;
+pub use values::{Entries, Values, ValuesRef};
 
 pub(crate) use spans::{SpanInternal, SpansInternal};
 pub(crate) use top_ops::{TopOp, TopOps};
@@ -0,0 +1,53 @@
+use std::fmt;
+
+use crate::exid::ExId;
+use crate::iter::Objects;
+use crate::marks::Mark;
+use crate::types::Clock;
+use crate::Automerge;
+
+/// Iterator created by the [`crate::ReadDoc::all_marks()`] and
+/// [`crate::ReadDoc::all_marks_at()`] methods
+///
+/// Yields the id of each text or list object which has marks along with its marks, in the order
+/// [`Objects`] visits the objects.
+pub struct AllMarks<'a> {
+    doc: &'a Automerge,
+    clock: Option<Clock>,
+    objects: Objects<'a>,
+}
+
+impl<'a> AllMarks<'a> {
+    pub(crate) fn new(doc: &'a Automerge, clock: Option<Clock>) -> Self {
+        Self {
+            doc,
+            objects: Objects::new(doc, clock.clone()),
+            clock,
+        }
+    }
+}
+
+impl<'a> fmt::Debug for AllMarks<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AllMarks").finish()
+    }
+}
+
+impl<'a> Iterator for AllMarks<'a> {
+    type Item = (ExId, Vec<Mark<'a>>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for (obj, typ, _) in self.objects.by_ref() {
+            if !typ.is_sequence() {
+                continue;
+            }
+            // the object was just found in the document so this can't fail
+            if let Ok(marks) = self.doc.marks_for(&obj, self.clock.clone()) {
+                if !marks.is_empty() {
+                    return Some((obj, marks));
+                }
+            }
+        }
+        None
+    }
+}
@@ -1,6 +1,7 @@
 use std::fmt;
 
 use crate::op_set::OpSet;
+use crate::types::{ListEncoding, Prop};
 
 use super::TopOps;
 
@@ -25,3 +26,37 @@ impl<'a> Iterator for Keys<'a> {
             .and_then(|(i, op_set)| i.next().map(|top| op_set.to_string(top.op.elemid_or_key())))
     }
 }
+
+/// Iterator created by the [`crate::ReadDoc::keys_with_conflicts()`] method
+///
+/// Yields each visible key along with the number of concurrent visible ops for that key. For
+/// a sequence the keys are the indices of the elements.
+#[derive(Default)]
+pub struct KeysWithConflicts<'a> {
+    pub(crate) iter: Option<(TopOps<'a>, &'a OpSet, Option<ListEncoding>)>,
+    pub(crate) index: usize,
+}
+
+impl<'a> fmt::Debug for KeysWithConflicts<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeysWithConflicts").finish()
+    }
+}
+
+impl<'a> Iterator for KeysWithConflicts<'a> {
+    type Item = (Prop, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (iter, op_set, encoding) = self.iter.as_mut()?;
+        let top = iter.next()?;
+        let prop = match encoding {
+            Some(encoding) => {
+                let index = self.index;
+                self.index += top.op.width(*encoding);
+                Prop::Seq(index)
+            }
+            None => Prop::Map(op_set.to_string(top.op.elemid_or_key())),
+        };
+        Some((prop, top.count))
+    }
+}
@@ -4,6 +4,7 @@ use std::sync::Arc;
 
 use crate::exid::ExId;
 use crate::marks::MarkSet;
+use crate::query::NthRangeElement;
 use crate::types::Clock;
 use crate::types::ListEncoding;
 use crate::value::Value;
@@ -14,6 +15,7 @@ use super::{TopOp, TopOps};
 #[derive(Clone)]
 pub struct ListRange<'a, R: RangeBounds<usize>> {
     iter: Option<ListRangeInner<'a, R>>,
+    window: std::vec::IntoIter<ListRangeItem<'a>>,
 }
 
 impl<'a, R: RangeBounds<usize>> ListRange<'a, R> {
@@ -31,10 +33,65 @@ impl<'a, R: RangeBounds<usize>> ListRange<'a, R> {
                 range,
                 clock,
             }),
+            window: Default::default(),
+        }
+    }
+
+    /// Iterate over elements which have already been found by the `NthRange` query
+    pub(crate) fn from_elements(elements: Vec<NthRangeElement<'a>>, clock: Option<Clock>) -> Self {
+        Self {
+            iter: None,
+            window: range_items(elements, clock).into_iter(),
+        }
+    }
+}
+
+fn range_items(elements: Vec<NthRangeElement<'_>>, clock: Option<Clock>) -> Vec<ListRangeItem<'_>> {
+    elements
+        .into_iter()
+        .map(|e| ListRangeItem {
+            index: e.index,
+            value: e.op.value_at(clock.as_ref()),
+            id: e.op.exid(),
+            conflict: e.conflict,
+            marks: e.marks,
+        })
+        .collect()
+}
+
+/// Iterator created by the [`crate::ReadDoc::list_range_rev()`] and
+/// [`crate::ReadDoc::list_range_rev_at()`] methods
+///
+/// Yields the same items as [`ListRange`] but in descending order of index. The elements in the
+/// range are found with a single walk of the sequence and buffered, so only the requested range
+/// is held in memory, not the whole sequence.
+#[derive(Clone, Default)]
+pub struct ListRangeRev<'a> {
+    window: std::iter::Rev<std::vec::IntoIter<ListRangeItem<'a>>>,
+}
+
+impl<'a> ListRangeRev<'a> {
+    pub(crate) fn from_elements(elements: Vec<NthRangeElement<'a>>, clock: Option<Clock>) -> Self {
+        Self {
+            window: range_items(elements, clock).into_iter().rev(),
         }
     }
 }
 
+impl<'a> fmt::Debug for ListRangeRev<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ListRangeRev").finish()
+    }
+}
+
+impl<'a> Iterator for ListRangeRev<'a> {
+    type Item = ListRangeItem<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.window.next()
+    }
+}
+
 #[derive(Clone)]
 struct ListRangeInner<'a, R: RangeBounds<usize>> {
     iter: TopOps<'a>,
@@ -46,7 +103,10 @@ struct ListRangeInner<'a, R: RangeBounds<usize>> {
 
 impl<'a, R: RangeBounds<usize>> Default for ListRange<'a, R> {
     fn default() -> Self {
-        ListRange { iter: None }
+        ListRange {
+            iter: None,
+            window: Default::default(),
+        }
     }
 }
 
@@ -60,11 +120,15 @@ impl<'a, R: RangeBounds<usize>> Iterator for ListRange<'a, R> {
     type Item = ListRangeItem<'a>;
 
     fn next(&mut self) -> Option<Self::Item> {
+        if let Some(item) = self.window.next() {
+            return Some(item);
+        }
         self.iter.as_mut().and_then(|inner| {
             for TopOp {
                 op,
                 conflict,
                 marks,
+                ..
             } in inner.iter.by_ref()
             {
                 let index = inner.state;
@@ -86,7 +150,7 @@ impl<'a, R: RangeBounds<usize>> Iterator for ListRange<'a, R> {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ListRangeItem<'a> {
     pub index: usize,
     pub value: Value<'a>,
@@ -0,0 +1,51 @@
+use std::fmt;
+
+use crate::exid::ExId;
+use crate::types::{Clock, ObjId};
+use crate::value::Value;
+use crate::{Automerge, ObjType};
+
+/// Iterator created by the [`crate::ReadDoc::objects()`] method
+///
+/// Yields each object along with its type and the object it is contained in, the root comes first
+/// and has no parent. Objects are visited depth first with the children of each object in the
+/// order its values are returned by [`crate::ReadDoc::values()`].
+pub struct Objects<'a> {
+    doc: &'a Automerge,
+    clock: Option<Clock>,
+    stack: Vec<(ObjId, ExId, ObjType, Option<ExId>)>,
+}
+
+impl<'a> Objects<'a> {
+    pub(crate) fn new(doc: &'a Automerge, clock: Option<Clock>) -> Self {
+        Self {
+            doc,
+            clock,
+            stack: vec![(ObjId::root(), ExId::Root, ObjType::Map, None)],
+        }
+    }
+}
+
+impl<'a> fmt::Debug for Objects<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Objects").finish()
+    }
+}
+
+impl<'a> Iterator for Objects<'a> {
+    type Item = (ExId, ObjType, Option<ExId>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (obj, exid, typ, parent) = self.stack.pop()?;
+        let start = self.stack.len();
+        for top in self.doc.ops().top_ops(&obj, self.clock.clone()) {
+            if let Value::Object(child_typ) = top.op.value_at(self.clock.as_ref()) {
+                let child = ObjId(*top.op.id());
+                self.stack
+                    .push((child, top.op.exid(), child_typ, Some(exid.clone())));
+            }
+        }
+        self.stack[start..].reverse();
+        Some((exid, typ, parent))
+    }
+}
@@ -2,7 +2,8 @@
 use crate::marks::{MarkSet, MarkStateMachine};
 //use crate::port::HasMetadata;
 use crate::op_set::Op;
-use crate::op_tree::{OpTreeIter, OpTreeOpIter};
+use crate::op_tree::OpTreeOpIter;
+use crate::query::RichTextQueryState;
 use crate::types::Clock;
 use crate::types::{Key, ListEncoding, ObjType, OpId, OpType};
 use crate::Automerge;
@@ -31,10 +32,42 @@ where
     doc: &'a Automerge,
     clock: Option<Clock>,
     state: SpansState<'a>,
+    window: Option<Window>,
 }
 
-/// A sequence of block markers and text spans. Returned by [`crate::ReadDoc::spans`] and
-/// [`crate::ReadDoc::spans_at`]
+/// Limits the ops processed by [`SpansInternal`] to a fixed number of visible characters
+#[derive(Debug)]
+struct Window {
+    remaining: usize,
+    last_key: Option<Key>,
+    exhausted: bool,
+}
+
+impl Window {
+    /// Whether `op`, which must be visible or a mark, is part of the window
+    fn admit(&mut self, op: Op<'_>) -> bool {
+        if self.exhausted {
+            return false;
+        }
+        if op.is_mark() {
+            return true;
+        }
+        let key = op.elemid_or_key();
+        if self.last_key == Some(key) {
+            return true;
+        }
+        if self.remaining == 0 {
+            self.exhausted = true;
+            return false;
+        }
+        self.remaining = self.remaining.saturating_sub(op.width(ListEncoding::Text));
+        self.last_key = Some(key);
+        true
+    }
+}
+
+/// A sequence of block markers and text spans. Returned by [`crate::ReadDoc::spans`],
+/// [`crate::ReadDoc::spans_at`] and [`crate::ReadDoc::spans_range`]
 pub struct Spans<'a> {
     internal: Option<SpansInternal<'a, OpTreeOpIter<'a>>>,
 }
@@ -73,6 +106,38 @@ where
             doc,
             clock,
             state: Default::default(),
+            window: None,
+        }
+    }
+
+    /// Like [`Self::new`] but for an iterator which starts at `index` with the marks in
+    /// `open_marks` already open, and which stops after `len` visible characters
+    fn new_window(
+        iter: I,
+        doc: &'a Automerge,
+        clock: Option<Clock>,
+        index: usize,
+        open_marks: RichTextQueryState<'a>,
+        len: usize,
+    ) -> Self {
+        let mut state = SpansState {
+            index,
+            ..Default::default()
+        };
+        for (id, data) in open_marks.iter() {
+            state.marks.mark_begin(*id, data, doc.osd());
+        }
+        state.current_marks = state.marks.current().cloned();
+        Self {
+            iter,
+            doc,
+            clock,
+            state,
+            window: Some(Window {
+                remaining: len,
+                last_key: None,
+                exhausted: false,
+            }),
         }
     }
 }
@@ -163,6 +228,11 @@ where
             if !(op.is_mark() || op.visible_at(self.clock.as_ref())) {
                 continue;
             }
+            if let Some(window) = self.window.as_mut() {
+                if !window.admit(op) {
+                    break;
+                }
+            }
             let key = op.elemid_or_key();
             match &self.state.key {
                 Some(k) if k != &key => {
@@ -191,13 +261,28 @@ where
 
 impl<'a> Spans<'a> {
     pub(crate) fn new(
-        iter: Option<OpTreeIter<'a>>,
+        iter: Option<OpTreeOpIter<'a>>,
+        doc: &'a Automerge,
+        clock: Option<Clock>,
+    ) -> Self {
+        Spans {
+            internal: iter.map(|i| SpansInternal::new(i, doc, clock)),
+        }
+    }
+
+    /// Spans over `len` characters of a text object, `iter` must start at the element at `index`
+    /// and `open_marks` are the marks which are open there
+    pub(crate) fn new_window(
+        start: Option<(OpTreeOpIter<'a>, RichTextQueryState<'a>)>,
         doc: &'a Automerge,
         clock: Option<Clock>,
+        index: usize,
+        len: usize,
     ) -> Self {
-        let op_iter = iter.map(|i| OpTreeOpIter::new(i, doc.osd()));
         Spans {
-            internal: op_iter.map(|i| SpansInternal::new(i, doc, clock)),
+            internal: start.map(|(iter, open_marks)| {
+                SpansInternal::new_window(iter, doc, clock, index, open_marks, len)
+            }),
         }
     }
 }
@@ -32,6 +32,8 @@ pub(crate) struct TopOpsInner<'a> {
 pub(crate) struct TopOp<'a> {
     pub(crate) op: Op<'a>,
     pub(crate) conflict: bool,
+    /// The number of visible ops for this key
+    pub(crate) count: usize,
     pub(crate) marks: Option<Arc<MarkSet>>,
 }
 
@@ -84,7 +86,10 @@ impl<'a> Iterator for TopOpsInner<'a> {
                         }
                     }
                     Some(_) => {
-                        result_op = self.last_op.take().map(|(_op_pos, op, marks)| (op, marks));
+                        result_op = self
+                            .last_op
+                            .take()
+                            .map(|(_op_pos, op, marks)| (op, marks, self.num_ops));
                         if visible {
                             self.last_op = Some((self.pos, op, self.marks.current().cloned()));
                             self.num_ops = 1;
@@ -110,13 +115,17 @@ impl<'a> Iterator for TopOpsInner<'a> {
                     break;
                 }
             } else {
-                result_op = self.last_op.take().map(|(_op_pos, op, marks)| (op, marks));
+                result_op = self
+                    .last_op
+                    .take()
+                    .map(|(_op_pos, op, marks)| (op, marks, self.num_ops));
                 break;
             }
         }
-        result_op.map(|(op, marks)| TopOp {
+        result_op.map(|(op, marks, count)| TopOp {
             op,
-            conflict: self.num_ops > 1,
+            conflict: count > 1,
+            count,
             marks,
         })
     }
@@ -1,8 +1,10 @@
 use std::fmt;
 
 use crate::exid::ExId;
-use crate::types::Clock;
-use crate::value::Value;
+use crate::op_set::OpSet;
+use crate::types::{Clock, Key, ListEncoding};
+use crate::value::{Value, ValueRef};
+use crate::Prop;
 
 use super::TopOps;
 
@@ -35,3 +37,102 @@ impl<'a> Iterator for Values<'a> {
             .and_then(|(i, clock)| i.next().map(|top| top.op.tagged_value(clock.as_ref())))
     }
 }
+
+/// Iterator created by the [`crate::ReadDoc::values_ref()`] method
+#[derive(Default)]
+pub struct ValuesRef<'a> {
+    iter: Option<(TopOps<'a>, Option<Clock>)>,
+}
+
+impl<'a> ValuesRef<'a> {
+    pub(crate) fn new(iter: TopOps<'a>, clock: Option<Clock>) -> Self {
+        Self {
+            iter: Some((iter, clock)),
+        }
+    }
+}
+
+impl<'a> fmt::Debug for ValuesRef<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ValuesRef").finish()
+    }
+}
+
+impl<'a> Iterator for ValuesRef<'a> {
+    type Item = ValueRef<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (iter, clock) = self.iter.as_mut()?;
+        let top = iter.next()?;
+        match top.op.value_at(clock.as_ref()) {
+            Value::Object(typ) => Some(ValueRef::Object(typ, top.op.exid())),
+            Value::Scalar(s) => Some(ValueRef::Scalar(s)),
+        }
+    }
+}
+
+/// Iterator created by the [`crate::ReadDoc::entries()`] and [`crate::ReadDoc::entries_at()`] methods
+///
+/// Yields `(prop, value, exid)` tuples. For sequences the prop is the index of the element,
+/// counting each element as one regardless of the object type.
+#[derive(Default)]
+pub struct Entries<'a> {
+    iter: Option<EntriesInner<'a>>,
+}
+
+struct EntriesInner<'a> {
+    iter: TopOps<'a>,
+    op_set: &'a OpSet,
+    encoding: ListEncoding,
+    index: usize,
+    clock: Option<Clock>,
+}
+
+impl<'a> Entries<'a> {
+    pub(crate) fn new(
+        iter: TopOps<'a>,
+        op_set: &'a OpSet,
+        encoding: ListEncoding,
+        clock: Option<Clock>,
+    ) -> Self {
+        Self {
+            iter: Some(EntriesInner {
+                iter,
+                op_set,
+                encoding,
+                index: 0,
+                clock,
+            }),
+        }
+    }
+}
+
+impl<'a> fmt::Debug for Entries<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Entries").finish()
+    }
+}
+
+impl<'a> Iterator for Entries<'a> {
+    type Item = (Prop, Value<'a>, ExId);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let inner = self.iter.as_mut()?;
+        for top in inner.iter.by_ref() {
+            let prop = match top.op.key() {
+                Key::Map(n) => match inner.op_set.osd.props.safe_get(*n) {
+                    Some(prop) => Prop::Map(prop.clone()),
+                    None => continue,
+                },
+                Key::Seq(_) => {
+                    let index = inner.index;
+                    inner.index += top.op.width(inner.encoding);
+                    Prop::Seq(index)
+                }
+            };
+            let (value, id) = top.op.tagged_value(inner.clock.as_ref());
+            return Some((prop, value, id));
+        }
+        None
+    }
+}
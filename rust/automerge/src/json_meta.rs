@@ -0,0 +1,368 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use serde::de::{self, DeserializeSeed, MapAccess, SeqAccess, Visitor};
+use serde::ser::{SerializeMap, SerializeSeq};
+
+use crate::hydrate::{List, Map, Text, Value};
+use crate::transaction::Transactable;
+use crate::{Automerge, AutomergeError, ChangeHash, ObjId, ObjType, ScalarValue, ROOT};
+
+const TYPE: &str = "@type";
+const VALUE: &str = "@value";
+const TYPE_CODE: &str = "@typeCode";
+
+/// The contents of a document as JSON which preserves the types of all values, see
+/// [`Automerge::to_json_with_meta()`]
+///
+/// Maps, lists, strings, booleans, null, 64-bit floats and signed integers are serialized as the
+/// matching JSON value. Every other value is serialized as an object with an `"@type"` key and an
+/// `"@value"` key:
+///
+/// | value                | `"@type"`   | `"@value"`                     |
+/// |----------------------|-------------|--------------------------------|
+/// | text object          | `"text"`    | the text as a string           |
+/// | counter              | `"counter"` | the current value as a number  |
+/// | timestamp            | `"timestamp"` | milliseconds since the epoch |
+/// | unsigned integer     | `"uint"`    | the number                     |
+/// | 32-bit float         | `"f32"`     | the number                     |
+/// | bytes                | `"bytes"`   | the bytes as a hex string      |
+/// | unknown scalar       | `"unknown"` | the bytes as a hex string, with the type in `"@typeCode"` |
+/// | map with an `"@type"` or `"@value"` key | `"map"` | the map        |
+///
+/// Conflicts are resolved to the winning value and marks are not included.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonWithMeta(Map);
+
+impl JsonWithMeta {
+    /// The contents of the root map
+    pub fn root(&self) -> &Map {
+        &self.0
+    }
+}
+
+impl Automerge {
+    /// Export the contents of the document, as at `heads` if given, as a [`JsonWithMeta`]
+    ///
+    /// ```
+    /// # use automerge::{transaction::Transactable, AutoCommit, Automerge, JsonWithMeta, ReadDoc, ScalarValue, ROOT};
+    /// let mut doc = AutoCommit::new();
+    /// doc.put(ROOT, "count", ScalarValue::counter(1)).unwrap();
+    /// let json = serde_json::to_string(&doc.document().to_json_with_meta(None)).unwrap();
+    /// assert_eq!(json, r#"{"count":{"@type":"counter","@value":1}}"#);
+    ///
+    /// let imported: JsonWithMeta = serde_json::from_str(&json).unwrap();
+    /// let doc = Automerge::from_json_with_meta(imported).unwrap();
+    /// assert_eq!(doc.get(ROOT, "count").unwrap().unwrap().0, ScalarValue::counter(1).into());
+    /// ```
+    pub fn to_json_with_meta(&self, heads: Option<&[ChangeHash]>) -> JsonWithMeta {
+        match self.hydrate(heads) {
+            Value::Map(map) => JsonWithMeta(map),
+            _ => JsonWithMeta(Map::default()),
+        }
+    }
+
+    /// Create a new document containing the values in `json`
+    ///
+    /// The values are written in a single change by a random actor. Strings become
+    /// [`ScalarValue::Str`], use a `"text"` value to create a text object.
+    ///
+    /// # Errors
+    ///
+    /// This only fails if a value can't be written to the document, which shouldn't happen
+    pub fn from_json_with_meta(json: JsonWithMeta) -> Result<Automerge, AutomergeError> {
+        let mut doc = Automerge::new();
+        let mut tx = doc.transaction();
+        write_map(&mut tx, &ROOT, &json.0)?;
+        tx.commit();
+        Ok(doc)
+    }
+}
+
+fn write_map<T: Transactable>(tx: &mut T, obj: &ObjId, map: &Map) -> Result<(), AutomergeError> {
+    for (key, value) in map.iter() {
+        match &value.value {
+            Value::Scalar(s) => tx.put(obj, key.as_str(), s.clone())?,
+            value => {
+                let child = tx.put_object(obj, key.as_str(), obj_type(value))?;
+                write_object(tx, &child, value)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn write_object<T: Transactable>(
+    tx: &mut T,
+    obj: &ObjId,
+    value: &Value,
+) -> Result<(), AutomergeError> {
+    match value {
+        Value::Map(map) => write_map(tx, obj, map),
+        Value::List(list) => {
+            for (index, item) in list.iter().enumerate() {
+                match &item.value {
+                    Value::Scalar(s) => tx.insert(obj, index, s.clone())?,
+                    value => {
+                        let child = tx.insert_object(obj, index, obj_type(value))?;
+                        write_object(tx, &child, value)?;
+                    }
+                }
+            }
+            Ok(())
+        }
+        Value::Text(text) => tx.splice_text(obj, 0, 0, &text.to_string()),
+        Value::Scalar(_) => Ok(()),
+    }
+}
+
+fn obj_type(value: &Value) -> ObjType {
+    match value {
+        Value::List(_) => ObjType::List,
+        Value::Text(_) => ObjType::Text,
+        _ => ObjType::Map,
+    }
+}
+
+impl serde::Serialize for JsonWithMeta {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        PlainMap(&self.0).serialize(serializer)
+    }
+}
+
+struct Meta<'a>(&'a Value);
+
+struct PlainMap<'a>(&'a Map);
+
+struct Tagged<'a, V> {
+    typ: &'a str,
+    value: V,
+    type_code: Option<u8>,
+}
+
+impl<'a> serde::Serialize for PlainMap<'a> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut entries = self.0.iter().collect::<Vec<_>>();
+        entries.sort_by_key(|(key, _)| *key);
+        let mut ser = serializer.serialize_map(Some(entries.len()))?;
+        for (key, value) in entries {
+            ser.serialize_entry(key, &Meta(&value.value))?;
+        }
+        ser.end()
+    }
+}
+
+impl<'a, V: serde::Serialize> serde::Serialize for Tagged<'a, V> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let len = if self.type_code.is_some() { 3 } else { 2 };
+        let mut ser = serializer.serialize_map(Some(len))?;
+        ser.serialize_entry(TYPE, self.typ)?;
+        if let Some(code) = self.type_code {
+            ser.serialize_entry(TYPE_CODE, &code)?;
+        }
+        ser.serialize_entry(VALUE, &self.value)?;
+        ser.end()
+    }
+}
+
+fn tagged<V>(typ: &str, value: V) -> Tagged<'_, V> {
+    Tagged {
+        typ,
+        value,
+        type_code: None,
+    }
+}
+
+impl<'a> serde::Serialize for Meta<'a> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.0 {
+            Value::Map(map) if map.contains_key(TYPE) || map.contains_key(VALUE) => {
+                tagged("map", PlainMap(map)).serialize(serializer)
+            }
+            Value::Map(map) => PlainMap(map).serialize(serializer),
+            Value::List(list) => {
+                let mut ser = serializer.serialize_seq(Some(list.len()))?;
+                for item in list.iter() {
+                    ser.serialize_element(&Meta(&item.value))?;
+                }
+                ser.end()
+            }
+            Value::Text(text) => tagged("text", text.to_string()).serialize(serializer),
+            Value::Scalar(s) => match s {
+                ScalarValue::Counter(c) => tagged("counter", i64::from(c)).serialize(serializer),
+                ScalarValue::Timestamp(t) => tagged("timestamp", t).serialize(serializer),
+                ScalarValue::Uint(n) => tagged("uint", n).serialize(serializer),
+                ScalarValue::F32(n) => tagged("f32", n).serialize(serializer),
+                ScalarValue::Bytes(b) => tagged("bytes", hex::encode(b)).serialize(serializer),
+                ScalarValue::Unknown { type_code, bytes } => Tagged {
+                    typ: "unknown",
+                    value: hex::encode(bytes),
+                    type_code: Some(*type_code),
+                }
+                .serialize(serializer),
+                other => other.serialize(serializer),
+            },
+        }
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for JsonWithMeta {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match deserializer.deserialize_any(MetaVisitor { plain: false })? {
+            Value::Map(map) => Ok(JsonWithMeta(map)),
+            _ => Err(de::Error::custom("the root of a document must be a map")),
+        }
+    }
+}
+
+/// Reads a value, if `plain` is true a map is read as a map even if it has an `"@type"` key. This
+/// is used for the `"@value"` of a tagged value, which can only be a map for the `"map"` type.
+#[derive(Clone, Copy)]
+struct MetaVisitor {
+    plain: bool,
+}
+
+impl<'de> DeserializeSeed<'de> for MetaVisitor {
+    type Value = Value;
+
+    fn deserialize<D: serde::Deserializer<'de>>(self, deserializer: D) -> Result<Value, D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+struct OwnedMeta(Value);
+
+impl<'de> serde::Deserialize<'de> for OwnedMeta {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        MetaVisitor { plain: false }
+            .deserialize(deserializer)
+            .map(OwnedMeta)
+    }
+}
+
+impl<'de> Visitor<'de> for MetaVisitor {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a JSON value with optional type metadata")
+    }
+
+    fn visit_bool<E: de::Error>(self, v: bool) -> Result<Value, E> {
+        Ok(Value::Scalar(ScalarValue::Boolean(v)))
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<Value, E> {
+        Ok(Value::Scalar(ScalarValue::Int(v)))
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Value, E> {
+        Ok(Value::Scalar(match i64::try_from(v) {
+            Ok(v) => ScalarValue::Int(v),
+            Err(_) => ScalarValue::Uint(v),
+        }))
+    }
+
+    fn visit_f64<E: de::Error>(self, v: f64) -> Result<Value, E> {
+        Ok(Value::Scalar(ScalarValue::F64(v)))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Value, E> {
+        Ok(Value::Scalar(ScalarValue::Str(v.into())))
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Value, E> {
+        Ok(Value::Scalar(ScalarValue::Null))
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Value, E> {
+        self.visit_unit()
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Value, A::Error> {
+        let mut values = Vec::new();
+        while let Some(OwnedMeta(value)) = seq.next_element()? {
+            values.push(value);
+        }
+        Ok(Value::List(List::from(values)))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut access: A) -> Result<Value, A::Error> {
+        let mut entries = HashMap::new();
+        while let Some(key) = access.next_key::<String>()? {
+            let plain = !self.plain && key == VALUE;
+            let value = access.next_value_seed(MetaVisitor { plain })?;
+            entries.insert(key, value);
+        }
+        if self.plain {
+            return Ok(Value::Map(Map::from(entries)));
+        }
+        let typ = match entries.remove(TYPE) {
+            None => return Ok(Value::Map(Map::from(entries))),
+            Some(Value::Scalar(ScalarValue::Str(typ))) => typ,
+            Some(_) => return Err(de::Error::custom("\"@type\" must be a string")),
+        };
+        let value = entries
+            .remove(VALUE)
+            .ok_or_else(|| de::Error::missing_field(VALUE))?;
+        let invalid = || de::Error::custom(format!("invalid \"@value\" for \"@type\" {}", typ));
+        let int = |value: &Value| match value {
+            Value::Scalar(ScalarValue::Int(n)) => Some(*n),
+            _ => None,
+        };
+        let bytes = |value: &Value| match value {
+            Value::Scalar(ScalarValue::Str(s)) => hex::decode(s.as_bytes()).ok(),
+            _ => None,
+        };
+        let scalar = match typ.as_str() {
+            "map" => return Ok(value),
+            "text" => match value {
+                Value::Scalar(ScalarValue::Str(s)) => {
+                    return Ok(Value::Text(Text::from(s.as_str())))
+                }
+                _ => return Err(invalid()),
+            },
+            "counter" => ScalarValue::counter(int(&value).ok_or_else(invalid)?),
+            "timestamp" => ScalarValue::Timestamp(int(&value).ok_or_else(invalid)?),
+            "uint" => match value {
+                Value::Scalar(ScalarValue::Uint(n)) => ScalarValue::Uint(n),
+                Value::Scalar(ScalarValue::Int(n)) if n >= 0 => ScalarValue::Uint(n as u64),
+                _ => return Err(invalid()),
+            },
+            "f32" => match value {
+                Value::Scalar(ScalarValue::F64(n)) => ScalarValue::F32(n as f32),
+                Value::Scalar(ScalarValue::Int(n)) => ScalarValue::F32(n as f32),
+                Value::Scalar(ScalarValue::Uint(n)) => ScalarValue::F32(n as f32),
+                _ => return Err(invalid()),
+            },
+            "bytes" => ScalarValue::Bytes(bytes(&value).ok_or_else(invalid)?),
+            "unknown" => {
+                let type_code = entries
+                    .remove(TYPE_CODE)
+                    .as_ref()
+                    .and_then(int)
+                    .and_then(|code| u8::try_from(code).ok())
+                    .ok_or_else(|| de::Error::missing_field(TYPE_CODE))?;
+                ScalarValue::Unknown {
+                    type_code,
+                    bytes: bytes(&value).ok_or_else(invalid)?,
+                }
+            }
+            other => {
+                return Err(de::Error::unknown_variant(
+                    other,
+                    &[
+                        "map",
+                        "text",
+                        "counter",
+                        "timestamp",
+                        "uint",
+                        "f32",
+                        "bytes",
+                        "unknown",
+                    ],
+                ))
+            }
+        };
+        Ok(Value::Scalar(scalar))
+    }
+}
@@ -212,6 +212,7 @@ impl<'de> Deserialize<'de> for Op {
                         Some(ScalarValue::Int(n)) => Ok(OpType::Increment(n)),
                         Some(ScalarValue::Uint(n)) => Ok(OpType::Increment(n as i64)),
                         Some(ScalarValue::F64(n)) => Ok(OpType::Increment(n as i64)),
+                        Some(ScalarValue::F32(n)) => Ok(OpType::Increment(n as i64)),
                         Some(ScalarValue::Counter(n)) => Ok(OpType::Increment(n.into())),
                         Some(ScalarValue::Timestamp(n)) => Ok(OpType::Increment(n)),
                         Some(ScalarValue::Bytes(s)) => {
@@ -347,6 +348,24 @@ mod tests {
                     pred: Vec::new().into(),
                 }),
             },
+            Scenario {
+                name: "Set with F32",
+                json: serde_json::json!({
+                    "action": "set",
+                    "obj": "_root",
+                    "key": "somekey",
+                    "value": 1.5,
+                    "datatype": "float32",
+                    "pred": []
+                }),
+                expected: Ok(Op {
+                    action: OpType::Put(ScalarValue::F32(1.5)),
+                    obj: ObjectId::Root,
+                    key: "somekey".into(),
+                    insert: false,
+                    pred: Vec::new().into(),
+                }),
+            },
             Scenario {
                 name: "Set with string",
                 json: serde_json::json!({
@@ -270,8 +270,10 @@ mod exid;
 pub mod hydrate;
 mod indexed_cache;
 pub mod iter;
+mod json_meta;
 mod legacy;
 pub mod marks;
+mod obj_view;
 mod op_set;
 pub mod op_tree;
 mod parents;
@@ -279,7 +281,7 @@ pub mod patches;
 mod query;
 mod read;
 mod sequence_tree;
-mod storage;
+pub mod storage;
 pub mod sync;
 mod text_diff;
 mod text_value;
@@ -289,24 +291,30 @@ mod value;
 #[cfg(feature = "optree-visualisation")]
 mod visualisation;
 
-pub use crate::automerge::{Automerge, LoadOptions, OnPartialLoad, SaveOptions, StringMigration};
+pub use crate::automerge::{
+    Automerge, ChangeStats, CompressionLevel, IntegrityReport, Limits, LoadOptions, OnPartialLoad,
+    RawAction, RawKey, RawOp, SaveOptions, SeqReport, ShallowDoc, StringMigration, StructuralDiff,
+};
 pub use autocommit::AutoCommit;
 pub use autoserde::AutoSerde;
 pub use change::{Change, LoadError as LoadChangeError};
+pub use clock::Clock;
 pub use cursor::Cursor;
 pub use error::AutomergeError;
 pub use error::InvalidActorId;
 pub use error::InvalidChangeHashSlice;
 pub use exid::{ExId as ObjId, ObjIdFromBytesError};
+pub use json_meta::JsonWithMeta;
 pub use legacy::Change as ExpandedChange;
+pub use obj_view::ObjView;
 pub use parents::{Parent, Parents};
 pub use patches::{Patch, PatchAction, PatchLog};
-pub use read::ReadDoc;
+pub use read::{ListEndpoint, ObjStats, ReadDoc};
 pub use sequence_tree::SequenceTree;
 pub use storage::VerificationMode;
 pub use transaction::BlockOrText;
-pub use types::{ActorId, ChangeHash, ObjType, OpType, ParseChangeHashError, Prop};
-pub use value::{ScalarValue, Value};
+pub use types::{ActorId, ChangeHash, ObjType, OpType, ParseChangeHashError, Prop, TextEncoding};
+pub use value::{ScalarValue, Value, ValueRef};
 
 /// The object ID for the root map of a document
 pub const ROOT: ObjId = ObjId::Root;
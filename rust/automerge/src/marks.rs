@@ -3,12 +3,17 @@ use std::fmt;
 use std::fmt::Display;
 use std::sync::Arc;
 
+use crate::exid::ExId;
+use crate::op_set::Op;
 use crate::op_tree::OpSetData;
 use crate::query::RichTextQueryState;
-use crate::types::{OpId, OpType};
+use crate::text_value::TextValue;
+use crate::types::{Clock, ListEncoding, OpId, OpType};
 use crate::value::ScalarValue;
+use itertools::Itertools;
 use std::borrow::Cow;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Range;
 
 /// Marks let you store out-of-bound information about sequences.
 ///
@@ -143,15 +148,32 @@ impl MarkSet {
         MarkSet { marks: diff }
     }
 
+    /// The marks which apply at the point where the query finished
+    ///
+    /// For a single point the value of each mark is the value of the most recent open mark with
+    /// that name, so rather than replaying every open mark through a [`MarkStateMachine`] (which
+    /// keeps all of them sorted) this just finds the most recent one for each name.
     pub(crate) fn from_query_state(
         q: &RichTextQueryState<'_>,
         osd: &OpSetData,
     ) -> Option<Arc<Self>> {
-        let mut marks = MarkStateMachine::default();
+        let mut winners: HashMap<&SmolStr, (OpId, &MarkData)> = HashMap::new();
         for (id, mark_data) in q.iter() {
-            marks.mark_begin(*id, mark_data, osd);
+            match winners.get(&mark_data.name) {
+                Some((winner, _)) if osd.lamport_cmp(*winner, *id).is_gt() => {}
+                _ => {
+                    winners.insert(&mark_data.name, (*id, *mark_data));
+                }
+            }
         }
-        marks.current().cloned()
+        if winners.is_empty() {
+            return None;
+        }
+        let marks = winners
+            .into_values()
+            .map(|(_, data)| (data.name.clone(), data.value.clone()))
+            .collect();
+        Some(Arc::new(MarkSet { marks }))
     }
 }
 
@@ -183,6 +205,31 @@ impl<'a> Mark<'a> {
         }
     }
 
+    /// Create a mark whose value refers to the object `obj`, e.g. a comment thread attached to a
+    /// range of text
+    ///
+    /// The reference is stored as the string form of the object id (the same form as
+    /// [`ExId`]'s `Display` implementation), so the mark merges like any other mark with the same
+    /// name and other implementations can read it without knowing about references. Resolve the
+    /// value back to an object with [`crate::Automerge::import()`].
+    ///
+    /// ```
+    /// # use automerge::{marks::{ExpandMark, Mark}, transaction::Transactable, AutoCommit, ObjType, ReadDoc, ROOT};
+    /// let mut doc = AutoCommit::new();
+    /// let text = doc.put_object(ROOT, "text", ObjType::Text).unwrap();
+    /// doc.splice_text(&text, 0, 0, "hello world").unwrap();
+    /// let comment = doc.put_object(ROOT, "comment", ObjType::Map).unwrap();
+    /// doc.mark(&text, Mark::new_object_ref("comment".to_string(), &comment, 0, 5), ExpandMark::None)
+    ///     .unwrap();
+    ///
+    /// let marks = doc.marks(&text).unwrap();
+    /// let id = marks[0].value().to_str().unwrap();
+    /// assert_eq!(doc.import(id).unwrap(), (comment, ObjType::Map));
+    /// ```
+    pub fn new_object_ref(name: String, obj: &ExId, start: usize, end: usize) -> Mark<'static> {
+        Mark::new(name, obj.to_string(), start, end)
+    }
+
     pub(crate) fn from_data(start: usize, end: usize, data: &MarkData) -> Mark<'_> {
         Mark {
             data: Cow::Borrowed(data),
@@ -208,6 +255,80 @@ impl<'a> Mark<'a> {
     }
 }
 
+/// A piece of text along with its marks, for copying formatted text between documents
+///
+/// This is created by [`crate::ReadDoc::export_rich_text()`] and inserted into a text object by
+/// [`crate::transaction::Transactable::insert_rich_text()`]. The text is a list of runs, each with
+/// the marks which cover the whole run. Adjacent runs always have different marks.
+///
+/// Block markers are not included. Mark values are serialized with the serde implementation of
+/// [`ScalarValue`], which doesn't distinguish counters and timestamps from integers.
+#[derive(Debug, Clone, PartialEq, Default, serde::Serialize, serde::Deserialize)]
+pub struct RichText {
+    /// The runs of text, in order
+    pub runs: Vec<RichTextRun>,
+}
+
+/// A run of text with the same marks, see [`RichText`]
+#[derive(Debug, Clone, PartialEq, Default, serde::Serialize, serde::Deserialize)]
+pub struct RichTextRun {
+    /// The text of the run
+    pub text: String,
+    /// The name and value of every mark which covers the whole run
+    pub marks: BTreeMap<String, ScalarValue>,
+}
+
+impl RichText {
+    /// Add `text` with `marks` to the end, merging it into the last run if that has the same
+    /// marks
+    pub fn push(&mut self, text: &str, marks: BTreeMap<String, ScalarValue>) {
+        if text.is_empty() {
+            return;
+        }
+        match self.runs.last_mut() {
+            Some(last) if last.marks == marks => last.text.push_str(text),
+            _ => self.runs.push(RichTextRun {
+                text: text.to_string(),
+                marks,
+            }),
+        }
+    }
+
+    /// The text of all the runs
+    pub fn text(&self) -> String {
+        self.runs.iter().map(|run| run.text.as_str()).collect()
+    }
+
+    /// Each mark as a range of [`Self::text()`], with runs of the same mark and value merged into
+    /// one range
+    ///
+    /// The ranges are measured in the same units as indexes into a text object, so they can be
+    /// passed to [`crate::transaction::Transactable::splice_text_with_marks()`].
+    pub fn mark_ranges(&self) -> Vec<(Range<usize>, &str, ScalarValue)> {
+        let mut open: BTreeMap<&str, (usize, &ScalarValue)> = BTreeMap::new();
+        let mut ranges = Vec::new();
+        let mut offset = 0;
+        for run in &self.runs {
+            open.retain(|name, (start, value)| {
+                let keep = run.marks.get(*name) == Some(*value);
+                if !keep {
+                    ranges.push((*start..offset, *name, (*value).clone()));
+                }
+                keep
+            });
+            for (name, value) in &run.marks {
+                open.entry(name.as_str()).or_insert((offset, value));
+            }
+            offset += TextValue::width(&run.text);
+        }
+        for (name, (start, value)) in open {
+            ranges.push((start..offset, name, value.clone()));
+        }
+        ranges.sort_by_key(|(range, name, _)| (range.start, *name));
+        ranges
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Default)]
 pub(crate) struct MarkStateMachine<'a> {
     state: Vec<(OpId, &'a MarkData)>,
@@ -284,6 +405,70 @@ impl<'a> MarkStateMachine<'a> {
         result
     }
 
+    /// Resolve the marks on the sequence whose ops are `ops`, in order, which overlap `range`
+    ///
+    /// Each mark is clipped to `range`. The ops are walked once, stopping after the end of the
+    /// range. Marks which cover no characters are included if they are inside `range`,
+    /// including at either end of it.
+    pub(crate) fn marks_in_range<I>(
+        ops: I,
+        range: Range<usize>,
+        encoding: ListEncoding,
+        clock: Option<&Clock>,
+        osd: &OpSetData,
+    ) -> Vec<Mark<'a>>
+    where
+        I: Iterator<Item = Op<'a>>,
+    {
+        let mut machine = MarkStateMachine::default();
+        let mut acc = MarkAccumulator::default();
+        let mut empty = Vec::new();
+        // the index each open mark began at
+        let mut begun = HashMap::new();
+        let mut index = 0;
+        for (_key, key_ops) in &ops.group_by(|o| o.elemid_or_key()) {
+            let Some(op) = key_ops.filter(|o| o.visible_or_mark(clock)).last() else {
+                continue;
+            };
+            match op.action() {
+                OpType::Make(_) | OpType::Put(_) => {
+                    let len = op.width(encoding);
+                    if index < range.end && index + len > range.start {
+                        if let Some(marks) = machine.current() {
+                            let start = index.max(range.start);
+                            let end = (index + len).min(range.end);
+                            acc.add(start, end - start, marks);
+                        }
+                    }
+                    index += len;
+                    if index > range.end {
+                        break;
+                    }
+                }
+                OpType::MarkBegin(_, data) => {
+                    begun.insert(*op.id(), (index, data));
+                    machine.mark_begin(*op.id(), data, osd);
+                }
+                OpType::MarkEnd(_) => {
+                    let zero_width = begun
+                        .remove(&op.id().prev())
+                        .filter(|(start, _)| *start == index);
+                    if let Some((_, data)) = zero_width {
+                        if range.start <= index && index <= range.end && !data.value.is_null() {
+                            empty.push(Mark::from_data(index, index, data));
+                        }
+                    }
+                    machine.mark_end(*op.id(), osd);
+                }
+                OpType::Increment(_) | OpType::Delete => {}
+            }
+        }
+        let mut marks: Vec<Mark<'a>> = acc.into_iter_no_unmark().collect();
+        marks.extend(empty);
+        marks.sort_by(|a, b| (a.start, a.end, a.name()).cmp(&(b.start, b.end, b.name())));
+        marks
+    }
+
     fn find(&self, target: OpId, osd: &OpSetData) -> Result<usize, usize> {
         self.state
             .binary_search_by(|probe| osd.lamport_cmp(probe.0, target))
@@ -328,11 +513,19 @@ impl Display for MarkData {
 /// mark should also inherit the mark.
 /// See <https://www.inkandswitch.com/peritext/> for details and
 /// suggestions of which value to use for which operations when building a rich text editor.
+///
+/// The start and end of the mark are controlled independently, use [`ExpandMark::from()`] to
+/// build the variant from a flag for each boundary. For example bold is usually
+/// [`ExpandMark::After`] whilst links are [`ExpandMark::None`].
 #[derive(PartialEq, Debug, Clone, Copy)]
 pub enum ExpandMark {
+    /// Text inserted at the start of the mark inherits it, text inserted at the end doesn't
     Before,
+    /// Text inserted at the end of the mark inherits it, text inserted at the start doesn't
     After,
+    /// Text inserted at either boundary inherits the mark
     Both,
+    /// Text inserted at either boundary doesn't inherit the mark
     None,
 }
 
@@ -343,6 +536,8 @@ impl Default for ExpandMark {
 }
 
 impl ExpandMark {
+    /// The variant which expands at the start of the mark if `before` is true and at the end if
+    /// `after` is true
     pub fn from(before: bool, after: bool) -> Self {
         match (before, after) {
             (true, true) => Self::Both,
@@ -351,9 +546,11 @@ impl ExpandMark {
             (false, false) => Self::None,
         }
     }
+    /// Whether text inserted at the start of the mark inherits it
     pub fn before(&self) -> bool {
         matches!(self, Self::Before | Self::Both)
     }
+    /// Whether text inserted at the end of the mark inherits it
     pub fn after(&self) -> bool {
         matches!(self, Self::After | Self::Both)
     }
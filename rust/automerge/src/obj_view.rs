@@ -0,0 +1,85 @@
+use crate::exid::ExId;
+use crate::iter::{Keys, Values};
+use crate::patches::TextRepresentation;
+use crate::types::{Clock, ObjMeta};
+use crate::{Automerge, ObjType, Prop, Value};
+
+/// A handle for reading one object, see [`crate::ReadDoc::view()`]
+///
+/// The object ID is resolved once when the view is created rather than on every read, which
+/// helps when the same object is read many times. The view borrows the document, so it must be
+/// dropped before the document can be modified. Reads see the document as it was scoped when the
+/// view was created, e.g. at the heads passed to [`crate::ReadDoc::view_at()`].
+#[derive(Debug, Clone)]
+pub struct ObjView<'a> {
+    doc: &'a Automerge,
+    id: ExId,
+    obj: ObjMeta,
+    clock: Option<Clock>,
+}
+
+impl<'a> ObjView<'a> {
+    pub(crate) fn new(doc: &'a Automerge, id: ExId, obj: ObjMeta, clock: Option<Clock>) -> Self {
+        Self {
+            doc,
+            id,
+            obj,
+            clock,
+        }
+    }
+
+    /// The ID of the object this view reads
+    pub fn id(&self) -> &ExId {
+        &self.id
+    }
+
+    /// The type of the object this view reads
+    pub fn object_type(&self) -> ObjType {
+        self.obj.typ
+    }
+
+    /// Like [`crate::ReadDoc::get()`] for this object
+    ///
+    /// Returns [`None`] if there is no value at `prop`, including when `prop` is the wrong kind
+    /// of prop for this object.
+    pub fn get<P: Into<Prop>>(&self, prop: P) -> Option<(Value<'a>, ExId)> {
+        let prop = prop.into();
+        if matches!(prop, Prop::Seq(_)) != self.obj.typ.is_sequence() {
+            return None;
+        }
+        self.doc
+            .ops()
+            .seek_ops_by_prop(
+                &self.obj.id,
+                prop,
+                TextRepresentation::String.encoding(self.obj.typ),
+                self.clock.as_ref(),
+            )
+            .ops
+            .into_iter()
+            .last()
+            .map(|op| op.tagged_value(self.clock.as_ref()))
+    }
+
+    /// Like [`crate::ReadDoc::length()`] for this object
+    pub fn length(&self) -> usize {
+        self.doc.ops().length(
+            &self.obj.id,
+            TextRepresentation::String.encoding(self.obj.typ),
+            self.clock.clone(),
+        )
+    }
+
+    /// Like [`crate::ReadDoc::keys()`] for this object
+    pub fn keys(&self) -> Keys<'a> {
+        self.doc.ops().keys(&self.obj.id, self.clock.clone())
+    }
+
+    /// Like [`crate::ReadDoc::values()`] for this object
+    pub fn values(&self) -> Values<'a> {
+        Values::new(
+            self.doc.ops().top_ops(&self.obj.id, self.clock.clone()),
+            self.clock.clone(),
+        )
+    }
+}
@@ -2,30 +2,39 @@ use crate::clock::Clock;
 use crate::error::AutomergeError;
 use crate::exid::ExId;
 use crate::indexed_cache::IndexedCache;
-use crate::iter::{Keys, ListRange, MapRange, TopOps};
+use crate::iter::{Keys, KeysWithConflicts, ListRange, ListRangeRev, MapRange, TopOps};
 use crate::marks::MarkSet;
-use crate::op_tree::OpTreeIter;
 use crate::op_tree::{
     self, FoundOpId, FoundOpWithPatchLog, FoundOpWithoutPatchLog, LastInsert, OpTree,
     OpTreeInternal, OpsFound,
 };
+use crate::op_tree::{OpTreeIter, OpTreeOpIter};
 use crate::parents::Parents;
 use crate::patches::TextRepresentation;
-use crate::query::{ChangeVisibility, TreeQuery};
+use crate::query::{
+    ChangeVisibility, Nth, NthRange, RichTextQueryState, SeekOpIds, TreeQuery, VisibleBefore,
+};
+use crate::read::ObjStats;
 use crate::text_value::TextValue;
 use crate::types::{
     self, ActorId, Export, Exportable, Key, ListEncoding, ObjId, ObjMeta, OpId, OpIds, OpType, Prop,
 };
 use crate::ObjType;
 use fxhash::FxBuildHasher;
+use itertools::Itertools;
 use std::borrow::Borrow;
 use std::cmp::Ordering;
 use std::collections::HashMap;
-use std::ops::RangeBounds;
+use std::ops::{Bound, RangeBounds};
+use std::sync::atomic::{AtomicU64, Ordering::Relaxed};
 use std::sync::Arc;
 
+mod lazy;
 mod op;
 
+pub(crate) use lazy::RebuiltObjects;
+use lazy::{LazyTrees, SpilledTree};
+
 pub(crate) use op::{Op, OpBuilder, OpDepIdx, OpDepRaw, OpIdx, OpRaw};
 
 pub(crate) type OpSet = OpSetInternal;
@@ -40,8 +49,15 @@ impl OpIdxRange {
     pub(crate) fn len(&self) -> usize {
         (self.end - self.start) as usize
     }
+
+    fn truncate(&mut self, len: usize) {
+        self.end = self.end.min(self.start + len as u32);
+    }
 }
 
+/// The largest range [`OpSetInternal::list_range`] collects eagerly with [`NthRange`]
+const EAGER_LIST_RANGE: usize = 1024;
+
 #[derive(Debug, Clone)]
 pub(crate) struct OpSetInternal {
     /// The map of objects to their type and ops.
@@ -50,6 +66,12 @@ pub(crate) struct OpSetInternal {
     length: usize,
     /// Metadata about the operations in this opset.
     pub(crate) osd: OpSetData,
+    /// The minimum degree of the nodes in the op trees
+    node_size: usize,
+    /// The number of times an op tree has been changed, see [`Self::last_edit()`]
+    edits: u64,
+    /// Set if the op trees of cold objects are dropped, see [`Self::set_max_resident_trees()`]
+    lazy: Option<LazyTrees>,
 }
 
 impl OpSetInternal {
@@ -60,6 +82,9 @@ impl OpSetInternal {
             trees,
             length: 0,
             osd: OpSetData::from_actors(actors),
+            node_size: op_tree::B,
+            edits: 0,
+            lazy: None,
         }
     }
 
@@ -69,13 +94,147 @@ impl OpSetInternal {
         OpSetInternal {
             trees,
             length: 0,
-            osd: OpSetData {
-                actors: IndexedCache::new(),
-                props: IndexedCache::new(),
-                ops: Vec::new(),
-                op_deps: Vec::new(),
-            },
+            osd: OpSetData::default(),
+            node_size: op_tree::B,
+            edits: 0,
+            lazy: None,
+        }
+    }
+
+    /// Use nodes of minimum degree `node_size` for every op tree, rebuilding any existing trees
+    pub(crate) fn set_node_size(&mut self, node_size: usize) {
+        self.node_size = node_size;
+        for tree in self.trees.values_mut() {
+            tree.set_node_size(node_size, &self.osd);
+        }
+        if let Some(lazy) = &mut self.lazy {
+            lazy.clear_cache();
+        }
+    }
+
+    /// Put the ops in the order of the trees they are in, rebuild every resident op tree with
+    /// full nodes and release spare capacity, see [`crate::Automerge::compact()`]
+    ///
+    /// This must not be called while a transaction is open.
+    pub(crate) fn compact(&mut self) {
+        if let Some(lazy) = &mut self.lazy {
+            // trees rebuilt by reads since they were spilled are rebuilt again when next used
+            lazy.clear_cache();
+            lazy.spilled.shrink_to_fit();
+            lazy.last_used.shrink_to_fit();
+            lazy.detached.shrink_to_fit();
+        }
+        self.repack();
+        for tree in self.trees.values_mut() {
+            tree.rebuild(self.node_size, &self.osd);
         }
+        self.trees.shrink_to_fit();
+    }
+
+    /// Keep the op trees of at most `max_resident` objects in memory, or all of them if
+    /// `max_resident` is `None`
+    ///
+    /// The trees of the objects which were used least recently are dropped by
+    /// [`Self::spill_cold_trees()`], their ops are encoded and removed from the op set data, and
+    /// rebuilt when the object is next used. The root is never dropped.
+    pub(crate) fn set_max_resident_trees(&mut self, max_resident: Option<usize>) {
+        match max_resident {
+            Some(max_resident) => {
+                self.lazy
+                    .get_or_insert_with(|| LazyTrees::new(max_resident))
+                    .max_resident = max_resident;
+                self.spill_cold_trees();
+            }
+            None => {
+                let spilled: Vec<_> = self
+                    .lazy
+                    .iter()
+                    .flat_map(|lazy| lazy.spilled.keys().copied())
+                    .collect();
+                for obj in spilled {
+                    self.unspill(&obj);
+                }
+                self.lazy = None;
+            }
+        }
+    }
+
+    /// Drop the op trees of the least recently used objects until at most the number set by
+    /// [`Self::set_max_resident_trees()`] are left, returning the number of trees dropped
+    ///
+    /// Trees rebuilt by reads since they were dropped stay dropped, they are kept in a cache of
+    /// the same size which is trimmed to the most recently read trees here.
+    pub(crate) fn spill_cold_trees(&mut self) -> usize {
+        let Some(lazy) = &mut self.lazy else {
+            return 0;
+        };
+        let tick = lazy.tick.get_mut();
+        for obj in self.trees.keys() {
+            lazy.last_used
+                .entry(*obj)
+                .or_insert_with(|| AtomicU64::new(*tick));
+        }
+        *tick += 1;
+        lazy.trim_cache();
+        let max_resident = lazy.max_resident.max(1);
+        if self.trees.len() <= max_resident {
+            return 0;
+        }
+        let mut cold: Vec<_> = self
+            .trees
+            .keys()
+            .filter(|obj| !obj.is_root())
+            .map(|obj| (lazy.last_used[obj].load(Relaxed), *obj))
+            .collect();
+        cold.sort_unstable_by(|a, b| {
+            a.0.cmp(&b.0)
+                .then_with(|| self.osd.lamport_cmp(a.1 .0, b.1 .0))
+        });
+        let spill = self.trees.len() - max_resident;
+        for (_, obj) in cold.into_iter().take(spill) {
+            if let Some(tree) = self.trees.remove(&obj) {
+                lazy.last_used.remove(&obj);
+                lazy.spilled.insert(obj, SpilledTree::new(&tree, &self.osd));
+            }
+        }
+        self.repack();
+        spill
+    }
+
+    /// The number of objects whose op trees have been dropped by [`Self::spill_cold_trees()`]
+    /// and not rebuilt by a read since
+    #[cfg(test)]
+    pub(crate) fn spilled_tree_count(&self) -> usize {
+        self.lazy.as_ref().map_or(0, |lazy| {
+            lazy.spilled
+                .values()
+                .filter(|spilled| spilled.cached.get().is_none())
+                .count()
+        })
+    }
+
+    /// The number of ops in the op set data, the ops of spilled objects aren't in it
+    #[cfg(test)]
+    pub(crate) fn resident_op_count(&self) -> usize {
+        self.osd.ops.len()
+    }
+
+    /// The op tree of `obj` and the op set data its ops are in, rebuilding it if it has been
+    /// dropped
+    fn tree(&self, obj: &ObjId) -> Option<(&OpTree, &OpSetData)> {
+        if let Some(tree) = self.trees.get(obj) {
+            if let Some(lazy) = &self.lazy {
+                lazy.touch(obj);
+            }
+            return Some((tree, &self.osd));
+        }
+        let rebuilt = self.lazy.as_ref()?.read(obj, self.node_size, &self.osd)?;
+        Some((&rebuilt.tree, &rebuilt.osd))
+    }
+
+    /// Make sure the op tree of `obj` is in `self.trees` so it can be modified
+    fn unspill(&mut self, obj: &ObjId) {
+        self.unspill_before(obj, None);
     }
 
     pub(crate) fn id_to_exid(&self, id: OpId) -> ExId {
@@ -90,28 +249,34 @@ impl OpSetInternal {
         }
     }
 
-    pub(crate) fn iter(&self) -> Iter<'_> {
-        let mut objs: Vec<_> = self.trees.iter().map(|t| (t.0, t.1.objtype, t.1)).collect();
+    /// Every op in the opset, `rebuilt` are the spilled objects from [`Self::rebuild_spilled()`]
+    pub(crate) fn iter<'a>(&'a self, rebuilt: &'a RebuiltObjects) -> Iter<'a> {
+        let mut objs: Vec<_> = self
+            .all_trees(rebuilt)
+            .map(|(obj, tree, osd)| (obj, tree.objtype, tree, osd))
+            .collect();
         objs.sort_by(|a, b| self.osd.lamport_cmp((a.0).0, (b.0).0));
         Iter {
             opset: self,
             trees: objs.into_iter(),
             current: None,
-            osd: &self.osd,
         }
     }
 
-    pub(crate) fn iter_obj(&self, obj: &ObjId) -> Option<OpTreeIter<'_>> {
-        self.trees.get(obj).map(|t| t.iter())
+    pub(crate) fn iter_obj(&self, obj: &ObjId) -> Option<OpTreeOpIter<'_>> {
+        self.tree(obj)
+            .map(|(tree, osd)| OpTreeOpIter::new(tree.iter(), osd))
     }
 
-    /// Iterate over objects in the opset in causal order
-    pub(crate) fn iter_objs(&self) -> impl Iterator<Item = (ObjMeta, OpTreeIter<'_>)> + '_ {
-        // TODO
+    /// Iterate over objects in the opset in causal order, `rebuilt` are the spilled objects from
+    /// [`Self::rebuild_spilled()`]
+    pub(crate) fn iter_objs<'a>(
+        &'a self,
+        rebuilt: &'a RebuiltObjects,
+    ) -> impl Iterator<Item = (ObjMeta, OpTreeOpIter<'a>)> + 'a {
         let mut objs: Vec<_> = self
-            .trees
-            .iter()
-            .map(|t| (ObjMeta::new(*t.0, t.1.objtype), t.1))
+            .all_trees(rebuilt)
+            .map(|(obj, tree, osd)| (ObjMeta::new(*obj, tree.objtype), tree, osd))
             .collect();
         objs.sort_by(|a, b| self.osd.lamport_cmp((a.0).id, (b.0).id));
         IterObjs {
@@ -120,12 +285,7 @@ impl OpSetInternal {
     }
 
     pub(crate) fn iter_ops(&self, obj: &ObjId) -> impl Iterator<Item = Op<'_>> {
-        self.trees
-            .get(obj)
-            .map(|o| o.iter())
-            .into_iter()
-            .flatten()
-            .map(|idx| idx.as_op(&self.osd))
+        self.iter_obj(obj).into_iter().flatten()
     }
 
     pub(crate) fn parents(
@@ -150,9 +310,9 @@ impl OpSetInternal {
     ) -> Option<FoundOpId<'_>> {
         let obj = idx.as_op(&self.osd).obj();
         let typ = self.obj_type(obj)?;
-        self.trees.get(obj).and_then(|tree| {
+        self.tree(obj).and_then(|(tree, osd)| {
             tree.internal
-                .seek_idx(idx, text_rep.encoding(typ), clock, &self.osd)
+                .seek_idx(idx, text_rep.encoding(typ), clock, osd)
         })
     }
 
@@ -163,9 +323,36 @@ impl OpSetInternal {
         encoding: ListEncoding,
         clock: Option<&Clock>,
     ) -> Option<FoundOpId<'_>> {
-        self.trees
-            .get(obj)
-            .and_then(|tree| tree.internal.seek_list_opid(id, encoding, clock, &self.osd))
+        self.tree(obj)
+            .and_then(|(tree, osd)| tree.internal.seek_list_opid(id, encoding, clock, osd))
+    }
+
+    /// The index of the element of `obj` each op in `ids` belongs to, in one walk of the object
+    pub(crate) fn seek_list_opids(
+        &self,
+        obj: &ObjId,
+        ids: &[OpId],
+        encoding: ListEncoding,
+        clock: Option<&Clock>,
+    ) -> Vec<Option<usize>> {
+        match self.tree(obj) {
+            Some((tree, osd)) => tree
+                .internal
+                .search(SeekOpIds::new(ids, encoding, clock), osd)
+                .found(),
+            None => vec![None; ids.len()],
+        }
+    }
+
+    pub(crate) fn seek_list_element(
+        &self,
+        obj: &ObjId,
+        id: OpId,
+        encoding: ListEncoding,
+        clock: Option<&Clock>,
+    ) -> Option<(usize, Option<Op<'_>>)> {
+        self.tree(obj)
+            .and_then(|(tree, osd)| tree.internal.seek_list_element(id, encoding, clock, osd))
     }
 
     pub(crate) fn parent_object(
@@ -174,8 +361,26 @@ impl OpSetInternal {
         text_rep: TextRepresentation,
         clock: Option<&Clock>,
     ) -> Option<Parent> {
-        let idx = self.trees.get(obj)?.parent?;
-        let found = self.seek_idx(idx, text_rep, clock)?;
+        let parent = match self.trees.get(obj) {
+            Some(tree) => tree.parent,
+            None => self.lazy.as_ref()?.spilled.get(obj)?.parent,
+        };
+        let found = match parent {
+            Some(idx) => self.seek_idx(idx, text_rep, clock)?,
+            None => {
+                // the parent object is spilled so the op which made `obj` is only in its rebuilt
+                // tree
+                let lazy = self.lazy.as_ref()?;
+                let parent = lazy.detached.get(obj)?;
+                let typ = self.obj_type(parent)?;
+                let rebuilt = lazy.read(parent, self.node_size, &self.osd)?;
+                let idx = *rebuilt.makes.get(obj)?;
+                rebuilt
+                    .tree
+                    .internal
+                    .seek_idx(idx, text_rep.encoding(typ), clock, &rebuilt.osd)?
+            }
+        };
         let obj = *found.op.obj();
         let typ = self.obj_type(&obj)?;
         let prop = found.op.map_prop().unwrap_or(Prop::Seq(found.index));
@@ -183,11 +388,25 @@ impl OpSetInternal {
         Some(Parent {
             obj,
             prop,
+            key: found.op.elemid_or_key(),
             visible,
             typ,
         })
     }
 
+    /// Whether there is more than one visible value at the location of `parent`
+    pub(crate) fn parent_is_conflicted(
+        &self,
+        parent: &Parent,
+        text_rep: TextRepresentation,
+        clock: Option<&Clock>,
+    ) -> bool {
+        let encoding = text_rep.encoding(parent.typ);
+        let found = self.seek_ops_by_prop(&parent.obj, parent.prop.clone(), encoding, clock);
+        // if the element in a sequence has been deleted then `prop` refers to the next element
+        found.ops.len() > 1 && found.ops[0].elemid_or_key() == parent.key
+    }
+
     pub(crate) fn seek_ops_by_prop<'a>(
         &'a self,
         obj: &ObjId,
@@ -195,22 +414,81 @@ impl OpSetInternal {
         encoding: ListEncoding,
         clock: Option<&Clock>,
     ) -> OpsFound<'a> {
-        self.trees
-            .get(obj)
-            .and_then(|tree| {
-                tree.internal
-                    .seek_ops_by_prop(&self.osd, prop, encoding, clock)
-            })
+        self.tree(obj)
+            .and_then(|(tree, osd)| tree.internal.seek_ops_by_prop(osd, prop, encoding, clock))
             .unwrap_or_default()
     }
 
+    pub(crate) fn has_visible_map_key(
+        &self,
+        obj: &ObjId,
+        key: &str,
+        clock: Option<&Clock>,
+    ) -> bool {
+        self.tree(obj)
+            .map(|(tree, osd)| tree.internal.has_visible_map_key(osd, key, clock))
+            .unwrap_or(false)
+    }
+
     pub(crate) fn op_iter<'a>(&'a self, obj: &ObjId) -> Option<OpIter<'a>> {
-        self.trees.get(obj).map(|tree| OpIter {
+        self.tree(obj).map(|(tree, osd)| OpIter {
             iter: tree.iter(),
-            osd: &self.osd,
+            osd,
         })
     }
 
+    /// An iterator over the ops of the text object `obj` starting at the element at `index`,
+    /// along with the marks which are open at that point. Returns `None` if `index` is past the
+    /// end of the object.
+    pub(crate) fn seek_spans_start(
+        &self,
+        obj: &ObjId,
+        index: usize,
+        clock: Option<Clock>,
+    ) -> Option<(OpTreeOpIter<'_>, RichTextQueryState<'_>)> {
+        let (tree, osd) = self.tree(obj)?;
+        let mut iter = tree.iter();
+        if index == 0 {
+            return Some((OpTreeOpIter::new(iter, osd), Default::default()));
+        }
+        // skip the shortcut in `Self::search` as we need the ids of the open marks, not just the
+        // resulting mark set
+        let query = Nth::new(index, ListEncoding::Text, clock, osd).with_marks();
+        let query = tree.internal.search(query, osd);
+        let pos = *query.ops_pos.first()?;
+        if pos > 0 {
+            iter.nth(pos - 1);
+        }
+        Some((
+            OpTreeOpIter::new(iter, osd),
+            query.into_mark_state().unwrap_or_default(),
+        ))
+    }
+
+    /// Convert `index` in the text object `obj` from the `from` encoding to the `to` encoding.
+    /// Returns `None` if `index` is past the end of the object or falls inside an element, for
+    /// example between the two halves of a surrogate pair.
+    pub(crate) fn convert_index(
+        &self,
+        obj: &ObjId,
+        index: usize,
+        from: ListEncoding,
+        to: ListEncoding,
+        clock: Option<Clock>,
+    ) -> Option<usize> {
+        if index == self.length(obj, from, clock.clone()) {
+            return Some(self.length(obj, to, clock));
+        }
+        let query = self.search(
+            obj,
+            Nth::new(index, from, clock, &self.osd).with_secondary(to),
+        );
+        if query.ops.is_empty() || query.index() != index {
+            return None;
+        }
+        query.secondary_index()
+    }
+
     pub(crate) fn top_ops<'a>(&'a self, obj: &ObjId, clock: Option<Clock>) -> TopOps<'a> {
         self.op_iter(obj)
             .map(|iter| TopOps::new(iter, clock))
@@ -224,9 +502,9 @@ impl OpSetInternal {
         op: Op<'a>,
         pred: &OpIds,
     ) -> FoundOpWithPatchLog<'a> {
-        if let Some(tree) = self.trees.get(&obj.id) {
+        if let Some((tree, osd)) = self.tree(&obj.id) {
             tree.internal
-                .find_op_with_patch_log(op, pred, encoding, &self.osd)
+                .find_op_with_patch_log(op, pred, encoding, osd)
         } else {
             Default::default()
         }
@@ -238,8 +516,8 @@ impl OpSetInternal {
         op: Op<'_>,
         pred: &OpIds,
     ) -> FoundOpWithoutPatchLog {
-        if let Some(tree) = self.trees.get(obj) {
-            tree.internal.find_op_without_patch_log(op, pred, &self.osd)
+        if let Some((tree, osd)) = self.tree(obj) {
+            tree.internal.find_op_without_patch_log(op, pred, osd)
         } else {
             Default::default()
         }
@@ -249,11 +527,11 @@ impl OpSetInternal {
     where
         Q: TreeQuery<'a>,
     {
-        if let Some(tree) = self.trees.get(obj) {
-            if query.can_shortcut_search(tree, &self.osd) {
+        if let Some((tree, osd)) = self.tree(obj) {
+            if query.can_shortcut_search(tree, osd) {
                 query
             } else {
-                tree.internal.search(query, &self.osd)
+                tree.internal.search(query, osd)
             }
         } else {
             query
@@ -262,8 +540,11 @@ impl OpSetInternal {
 
     /// Add `op` as a successor to each op at `op_indices` in `obj`
     pub(crate) fn add_succ(&mut self, obj: &ObjId, op_indices: &[usize], op: OpIdx) {
+        self.edits += 1;
+        self.unspill(obj);
         if let Some(tree) = self.trees.get_mut(obj) {
             tree.last_insert = None;
+            tree.edited = self.edits;
             for i in op_indices {
                 if let Some(idx) = tree.internal.get(*i) {
                     let old_vis = idx.as_op(&self.osd).visible();
@@ -285,8 +566,11 @@ impl OpSetInternal {
     }
 
     pub(crate) fn remove_succ(&mut self, obj: &ObjId, index: usize, op: OpIdx) {
+        self.edits += 1;
+        self.unspill(obj);
         if let Some(tree) = self.trees.get_mut(obj) {
             tree.last_insert = None;
+            tree.edited = self.edits;
             if let Some(idx) = tree.internal.get(index) {
                 let old_vis = idx.as_op(&self.osd).visible();
                 self.osd.remove_inc(idx, op);
@@ -307,13 +591,22 @@ impl OpSetInternal {
 
     pub(crate) fn remove(&mut self, obj: &ObjId, index: usize) {
         // this happens on rollback - be sure to go back to the old state
+        self.edits += 1;
+        self.unspill(obj);
         let tree = self.trees.get_mut(obj).unwrap();
         self.length -= 1;
         tree.last_insert = None;
+        tree.edited = self.edits;
         let idx = tree.internal.remove(index, &self.osd);
         let op = idx.as_op(&self.osd);
         if let OpType::Make(_) = op.action() {
-            self.trees.remove(&op.id().into());
+            let child = op.id().into();
+            self.trees.remove(&child);
+            if let Some(lazy) = &mut self.lazy {
+                lazy.spilled.remove(&child);
+                lazy.last_used.remove(&child);
+                lazy.detached.remove(&child);
+            }
         }
     }
 
@@ -330,6 +623,7 @@ impl OpSetInternal {
         key: Key,
         marks: Option<Arc<MarkSet>>,
     ) {
+        self.unspill(obj);
         if let Some(tree) = self.trees.get_mut(obj) {
             tree.last_insert = Some(LastInsert {
                 index,
@@ -351,37 +645,58 @@ impl OpSetInternal {
         op: OpBuilder,
         range: &mut OpIdxRange,
     ) -> OpIdx {
+        self.unspill_before(&obj, Some(range));
         let idx = self.osd.push(obj, op);
         range.end += 1;
         assert!(idx.get() >= range.start as usize && idx.get() < range.end as usize);
         idx
     }
 
+    /// Drop the ops in `range` after the first `len`. The ops must already have been removed
+    /// from the op trees and must be the last ops in the opset.
+    pub(crate) fn truncate_range(&mut self, range: &mut OpIdxRange, len: usize) {
+        assert_eq!(self.osd.ops.len(), range.end as usize);
+        range.truncate(len);
+        self.osd.ops.truncate(range.end as usize);
+    }
+
     pub(crate) fn add_indexes(&mut self) {
         for (_, tree) in self.trees.iter_mut() {
             if tree.objtype.is_sequence() {
                 tree.add_index(&self.osd)
             }
         }
+        if let Some(lazy) = &mut self.lazy {
+            for spilled in lazy.spilled.values_mut() {
+                if spilled.objtype.is_sequence() {
+                    spilled.has_index = true;
+                    spilled.cached.take();
+                }
+            }
+        }
     }
 
     #[tracing::instrument(skip(self, index))]
     pub(crate) fn insert(&mut self, index: usize, obj: &ObjId, idx: OpIdx) {
+        self.edits += 1;
         let op = idx.as_op(&self.osd);
         if let OpType::Make(typ) = op.action() {
             self.trees.insert(
                 op.id().into(),
                 OpTree {
-                    internal: OpTreeInternal::new(*typ),
+                    internal: OpTreeInternal::with_node_size(*typ, self.node_size),
                     objtype: *typ,
                     last_insert: None,
                     parent: Some(idx),
+                    edited: self.edits,
                 },
             );
         }
 
+        self.unspill(obj);
         if let Some(tree) = self.trees.get_mut(obj) {
             tree.last_insert = None;
+            tree.edited = self.edits;
             tree.internal.insert(index, idx, &self.osd);
             self.length += 1;
         } else {
@@ -390,21 +705,25 @@ impl OpSetInternal {
     }
 
     pub(crate) fn load_idx(&mut self, obj: &ObjId, idx: OpIdx) -> Result<(), AutomergeError> {
+        self.edits += 1;
         let op = idx.as_op(&self.osd);
         if let OpType::Make(typ) = op.action() {
             self.trees.insert(
                 op.id().into(),
                 OpTree {
-                    internal: OpTreeInternal::new(*typ),
+                    internal: OpTreeInternal::with_node_size(*typ, self.node_size),
                     objtype: *typ,
                     last_insert: None,
                     parent: Some(idx),
+                    edited: self.edits,
                 },
             );
         }
 
+        self.unspill(obj);
         if let Some(tree) = self.trees.get_mut(obj) {
             tree.last_insert = None;
+            tree.edited = self.edits;
             tree.internal.insert(tree.len(), idx, &self.osd);
             self.length += 1;
             Ok(())
@@ -413,13 +732,28 @@ impl OpSetInternal {
         }
     }
 
+    /// A value which changes whenever the ops of `obj` do, or `None` if there is no such object.
+    /// Only values from the same op set can be compared.
+    pub(crate) fn last_edit(&self, obj: &ObjId) -> Option<u64> {
+        if let Some(tree) = self.trees.get(obj) {
+            return Some(tree.edited);
+        }
+        self.lazy
+            .as_ref()?
+            .spilled
+            .get(obj)
+            .map(|spilled| spilled.edited)
+    }
+
     pub(crate) fn object_type(&self, id: &ObjId) -> Option<ObjType> {
-        self.trees.get(id).map(|tree| tree.objtype)
+        self.obj_type(id)
     }
 
     pub(crate) fn obj_type(&self, id: &ObjId) -> Option<ObjType> {
-        let objtype = self.trees.get(id).map(|tree| tree.objtype)?;
-        Some(objtype)
+        match self.trees.get(id) {
+            Some(tree) => Some(tree.objtype),
+            None => Some(self.lazy.as_ref()?.spilled.get(id)?.objtype),
+        }
     }
 
     /// Return a graphviz representation of the opset.
@@ -450,7 +784,7 @@ impl OpSetInternal {
         encoding: ListEncoding,
         clock: Option<Clock>,
     ) -> usize {
-        if let Some(tree) = self.trees.get(obj) {
+        if let Some((tree, _)) = self.tree(obj) {
             match (&clock, tree.index(encoding)) {
                 // no clock and a clean index? - use it
                 (None, Some(index)) => index.visible_len(encoding),
@@ -464,6 +798,19 @@ impl OpSetInternal {
         }
     }
 
+    /// The number of visible elements (in `encoding`) of `obj` before the element which the op
+    /// at position `pos` of the object's tree belongs to
+    pub(crate) fn visible_count_before(
+        &self,
+        obj: &ObjId,
+        pos: usize,
+        encoding: ListEncoding,
+        clock: Option<Clock>,
+    ) -> usize {
+        self.search(obj, VisibleBefore::new(pos, encoding, clock))
+            .count()
+    }
+
     pub(crate) fn text(&self, obj: &ObjId, clock: Option<Clock>) -> String {
         self.top_ops(obj, clock)
             .map(|top| top.op.as_str())
@@ -476,6 +823,83 @@ impl OpSetInternal {
         }
     }
 
+    /// The visible keys of the map `obj` ordered by the id of the first op to set each key, ties
+    /// between concurrent ops are broken by actor
+    pub(crate) fn keys_in_insertion_order(&self, obj: &ObjId, clock: Option<Clock>) -> Vec<String> {
+        let mut keys: Vec<_> = self
+            .iter_ops(obj)
+            .group_by(|op| op.elemid_or_key())
+            .into_iter()
+            .filter_map(|(key, ops)| {
+                let mut first = None;
+                let mut visible = false;
+                for op in ops.filter(|op| clock.as_ref().map(|c| c.covers(op.id())).unwrap_or(true))
+                {
+                    first.get_or_insert(*op.id());
+                    visible |= op.visible_at(clock.as_ref());
+                }
+                first.filter(|_| visible).map(|id| (id, key))
+            })
+            .collect();
+        keys.sort_by(|a, b| self.osd.lamport_cmp(a.0, b.0));
+        keys.into_iter()
+            .map(|(_, key)| self.to_string(key))
+            .collect()
+    }
+
+    pub(crate) fn object_stats(&self, obj: &ObjMeta, clock: Option<Clock>) -> ObjStats {
+        let (tree_depth, node_count) = self
+            .tree(&obj.id)
+            .and_then(|(tree, _)| tree.internal.root_node.as_ref())
+            .map(|node| node.depth_and_node_count())
+            .unwrap_or_default();
+        let visible_count = if obj.typ.is_sequence() {
+            self.length(&obj.id, ListEncoding::List, clock)
+        } else {
+            self.top_ops(&obj.id, clock).count()
+        };
+        ObjStats {
+            tree_depth,
+            node_count,
+            op_count: self.tree(&obj.id).map_or(0, |(tree, _)| tree.len()),
+            visible_count,
+        }
+    }
+
+    /// The number of ops in the op tree of `obj` that are not visible at `clock`, i.e. deleted or
+    /// overwritten values and increments
+    pub(crate) fn tombstone_count(&self, obj: &ObjId, clock: Option<&Clock>) -> usize {
+        self.iter_ops(obj)
+            .filter(|op| clock.map_or(true, |c| c.covers(op.id())) && !op.visible_or_mark(clock))
+            .count()
+    }
+
+    /// The number of ops that are not visible at `clock` in every object, see
+    /// [`Self::tombstone_count()`]
+    pub(crate) fn total_tombstones(&self, clock: Option<&Clock>) -> usize {
+        let spilled = self.rebuild_spilled();
+        self.iter(&spilled)
+            .filter(|(_, _, op)| {
+                clock.map_or(true, |c| c.covers(op.id())) && !op.visible_or_mark(clock)
+            })
+            .count()
+    }
+
+    pub(crate) fn keys_with_conflicts<'a>(
+        &'a self,
+        obj: &ObjMeta,
+        clock: Option<Clock>,
+    ) -> KeysWithConflicts<'a> {
+        let encoding = obj
+            .typ
+            .is_sequence()
+            .then(|| TextRepresentation::String.encoding(obj.typ));
+        KeysWithConflicts {
+            iter: Some((self.top_ops(&obj.id, clock), self, encoding)),
+            index: 0,
+        }
+    }
+
     pub(crate) fn list_range<R: RangeBounds<usize>>(
         &self,
         obj: &ObjId,
@@ -483,7 +907,51 @@ impl OpSetInternal {
         encoding: ListEncoding,
         clock: Option<Clock>,
     ) -> ListRange<'_, R> {
-        ListRange::new(self.top_ops(obj, clock.clone()), encoding, range, clock)
+        let start = match range.start_bound() {
+            Bound::Included(n) => *n,
+            Bound::Excluded(n) => n.saturating_add(1),
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(n) => Some(n.saturating_add(1)),
+            Bound::Excluded(n) => Some(*n),
+            Bound::Unbounded => None,
+        };
+        if let Some(end) = end.filter(|end| end.saturating_sub(start) <= EAGER_LIST_RANGE) {
+            // a small bounded range can skip straight to `start` and stop at `end`. Larger ranges
+            // (e.g. `0..usize::MAX`) stay lazy so we don't collect the whole list up front
+            let query = self.search(
+                obj,
+                NthRange::new(start, end, encoding, clock.clone(), &self.osd),
+            );
+            ListRange::from_elements(query.elements, clock)
+        } else {
+            ListRange::new(self.top_ops(obj, clock.clone()), encoding, range, clock)
+        }
+    }
+
+    pub(crate) fn list_range_rev<R: RangeBounds<usize>>(
+        &self,
+        obj: &ObjId,
+        range: R,
+        encoding: ListEncoding,
+        clock: Option<Clock>,
+    ) -> ListRangeRev<'_> {
+        let start = match range.start_bound() {
+            Bound::Included(n) => *n,
+            Bound::Excluded(n) => n.saturating_add(1),
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(n) => n.saturating_add(1),
+            Bound::Excluded(n) => *n,
+            Bound::Unbounded => self.length(obj, encoding, clock.clone()),
+        };
+        let query = self.search(
+            obj,
+            NthRange::new(start, end, encoding, clock.clone(), &self.osd),
+        );
+        ListRangeRev::from_elements(query.elements, clock)
     }
     pub(crate) fn map_range<R: RangeBounds<String>>(
         &self,
@@ -509,53 +977,51 @@ impl Default for OpSetInternal {
     }
 }
 
-impl<'a> IntoIterator for &'a OpSetInternal {
-    type Item = (&'a ObjId, ObjType, Op<'a>);
-
-    type IntoIter = Iter<'a>;
-
-    fn into_iter(self) -> Self::IntoIter {
-        self.iter()
-    }
-}
-
 pub(crate) struct IterObjs<'a> {
-    trees: std::vec::IntoIter<(ObjMeta, &'a op_tree::OpTree)>,
+    trees: std::vec::IntoIter<(ObjMeta, &'a OpTree, &'a OpSetData)>,
 }
 
 impl<'a> Iterator for IterObjs<'a> {
-    type Item = (ObjMeta, OpTreeIter<'a>);
+    type Item = (ObjMeta, OpTreeOpIter<'a>);
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.trees.next().map(|(id, tree)| (id, tree.iter()))
+        self.trees
+            .next()
+            .map(|(id, tree, osd)| (id, OpTreeOpIter::new(tree.iter(), osd)))
     }
 }
 
 #[derive(Clone)]
 pub(crate) struct Iter<'a> {
     opset: &'a OpSet,
-    trees: std::vec::IntoIter<(&'a ObjId, ObjType, &'a op_tree::OpTree)>,
-    current: Option<(&'a ObjId, ObjType, OpTreeIter<'a>)>,
-    osd: &'a OpSetData,
+    trees: std::vec::IntoIter<(&'a ObjId, ObjType, &'a OpTree, &'a OpSetData)>,
+    current: Option<(&'a ObjId, ObjType, OpIter<'a>)>,
 }
 
 impl<'a> Iterator for Iter<'a> {
     type Item = (&'a ObjId, ObjType, Op<'a>);
 
     fn next(&mut self) -> Option<Self::Item> {
-        if let Some((id, typ, tree)) = &mut self.current {
-            if let Some(idx) = tree.next() {
-                let next = idx.as_op(self.osd);
-                return Some((id, *typ, next));
+        if let Some((id, typ, ops)) = &mut self.current {
+            if let Some(op) = ops.next() {
+                return Some((id, *typ, op));
             }
         }
 
         loop {
-            self.current = self.trees.next().map(|o| (o.0, o.1, o.2.iter()));
-            if let Some((obj, typ, tree)) = &mut self.current {
-                if let Some(idx) = tree.next() {
-                    let next = idx.as_op(self.osd);
-                    return Some((obj, *typ, next));
+            self.current = self.trees.next().map(|(obj, typ, tree, osd)| {
+                (
+                    obj,
+                    typ,
+                    OpIter {
+                        iter: tree.iter(),
+                        osd,
+                    },
+                )
+            });
+            if let Some((obj, typ, ops)) = &mut self.current {
+                if let Some(op) = ops.next() {
+                    return Some((obj, *typ, op));
                 }
             } else {
                 return None;
@@ -572,8 +1038,10 @@ impl<'a> ExactSizeIterator for Iter<'a> {
 
 #[derive(Clone, Debug)]
 pub(crate) struct OpSetData {
-    pub(crate) actors: IndexedCache<ActorId>,
-    pub(crate) props: IndexedCache<String>,
+    /// These are shared with the trees of spilled objects rebuilt by reads, add to them with
+    /// [`OpSetInternal::import_actor()`] and [`OpSetInternal::import_prop()`]
+    pub(crate) actors: Arc<IndexedCache<ActorId>>,
+    pub(crate) props: Arc<IndexedCache<String>>,
     ops: Vec<OpRaw>,
     op_deps: Vec<OpDepRaw>,
 }
@@ -581,8 +1049,8 @@ pub(crate) struct OpSetData {
 impl Default for OpSetData {
     fn default() -> Self {
         Self {
-            actors: IndexedCache::new(),
-            props: IndexedCache::new(),
+            actors: Arc::new(IndexedCache::new()),
+            props: Arc::new(IndexedCache::new()),
             ops: Vec::new(),
             op_deps: Vec::new(),
         }
@@ -816,8 +1284,8 @@ impl OpSetData {
 
     pub(crate) fn from_actors(actors: Vec<ActorId>) -> Self {
         Self {
-            props: IndexedCache::new(),
-            actors: actors.into_iter().collect(),
+            props: Arc::new(IndexedCache::new()),
+            actors: Arc::new(actors.into_iter().collect()),
             ops: Vec::new(),
             op_deps: Vec::new(),
         }
@@ -846,7 +1314,7 @@ impl OpSetData {
     }
 
     pub(crate) fn import_prop<S: Borrow<str>>(&mut self, key: S) -> usize {
-        self.props.cache(key.borrow().to_string())
+        Arc::make_mut(&mut self.props).cache(key.borrow().to_string())
     }
 }
 
@@ -855,6 +1323,7 @@ pub(crate) struct Parent {
     pub(crate) obj: ObjId,
     pub(crate) typ: ObjType,
     pub(crate) prop: Prop,
+    pub(crate) key: Key,
     pub(crate) visible: bool,
 }
 
@@ -905,10 +1374,10 @@ pub(crate) mod tests {
     /// internally visible ops.
     pub(crate) fn optree_with_only_internally_visible_ops() -> (OpSet, OpBuilder, OpIds) {
         let mut set = OpSet::new();
-        let actor = set.osd.actors.cache(ActorId::random());
-        let a = set.osd.props.cache("a".to_string());
-        let b = set.osd.props.cache("b".to_string());
-        let c = set.osd.props.cache("c".to_string());
+        let actor = set.import_actor(ActorId::random());
+        let a = set.import_prop("a");
+        let b = set.import_prop("b");
+        let c = set.import_prop("c");
 
         let mut counter = 0;
         let mut last_idx = None;
@@ -0,0 +1,557 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering::Relaxed};
+use std::sync::{Arc, OnceLock};
+
+use fxhash::FxBuildHasher;
+
+use super::{OpDepIdx, OpIdx, OpIdxRange, OpSetData, OpSetInternal, OpTree};
+use crate::columnar::Key as DocOpKey;
+use crate::indexed_cache::IndexedCache;
+use crate::storage::convert::op_as_docop;
+use crate::storage::DocOpColumns;
+use crate::types::{ActorId, Key, ObjId, ObjType, OpBuilder, OpType};
+
+/// The bookkeeping for dropping the op trees of objects which haven't been used recently
+#[derive(Debug)]
+pub(super) struct LazyTrees {
+    /// The most op trees to keep in `OpSetInternal::trees`, and the most trees rebuilt by reads
+    /// to keep in the `cached` field of the spilled objects
+    pub(super) max_resident: usize,
+    /// The objects whose op trees have been dropped
+    pub(super) spilled: HashMap<ObjId, SpilledTree, FxBuildHasher>,
+    /// The value of `tick` when each resident op tree was last used
+    pub(super) last_used: HashMap<ObjId, AtomicU64, FxBuildHasher>,
+    /// The objects whose parent object is spilled, mapped to the parent. The ops of a spilled
+    /// object are dropped so the `parent` of these is `None`, the op which made them is found by
+    /// rebuilding the parent.
+    pub(super) detached: HashMap<ObjId, ObjId, FxBuildHasher>,
+    pub(super) tick: AtomicU64,
+}
+
+impl LazyTrees {
+    pub(super) fn new(max_resident: usize) -> Self {
+        Self {
+            max_resident,
+            spilled: Default::default(),
+            last_used: Default::default(),
+            detached: Default::default(),
+            tick: AtomicU64::new(0),
+        }
+    }
+
+    pub(super) fn touch(&self, obj: &ObjId) {
+        if let Some(last_used) = self.last_used.get(obj) {
+            last_used.store(self.tick.fetch_add(1, Relaxed), Relaxed);
+        }
+    }
+
+    /// The op tree of the spilled object `obj`, rebuilding it if it isn't in the read cache
+    pub(super) fn read(&self, obj: &ObjId, node_size: usize, osd: &OpSetData) -> Option<&Rebuilt> {
+        let spilled = self.spilled.get(obj)?;
+        spilled
+            .last_read
+            .store(self.tick.fetch_add(1, Relaxed), Relaxed);
+        Some(
+            spilled
+                .cached
+                .get_or_init(|| spilled.rebuild(*obj, node_size, osd)),
+        )
+    }
+
+    /// Drop the trees rebuilt by reads which were read least recently until at most
+    /// `max_resident` are left
+    ///
+    /// Reads only have a shared reference so they can't drop anything, the cache can grow past
+    /// the limit between two calls of this.
+    pub(super) fn trim_cache(&mut self) {
+        let mut cached: Vec<_> = self
+            .spilled
+            .iter()
+            .filter(|(_, spilled)| spilled.cached.get().is_some())
+            .map(|(obj, spilled)| (spilled.last_read.load(Relaxed), *obj))
+            .collect();
+        let max_cached = self.max_resident.max(1);
+        if cached.len() <= max_cached {
+            return;
+        }
+        cached.sort_unstable();
+        for (_, obj) in &cached[..cached.len() - max_cached] {
+            if let Some(spilled) = self.spilled.get_mut(obj) {
+                spilled.cached.take();
+            }
+        }
+    }
+
+    pub(super) fn clear_cache(&mut self) {
+        for spilled in self.spilled.values_mut() {
+            spilled.cached.take();
+        }
+    }
+
+    /// Point the op set data of the cached trees at empty caches, so that the actors and props of
+    /// the op set aren't shared and can be added to without copying them
+    fn unshare_caches(&mut self) {
+        for rebuilt in self.spilled.values_mut().filter_map(|s| s.cached.get_mut()) {
+            rebuilt.osd.actors = Arc::new(IndexedCache::new());
+            rebuilt.osd.props = Arc::new(IndexedCache::new());
+        }
+    }
+
+    /// Undo [`Self::unshare_caches()`]
+    fn share_caches(&mut self, osd: &OpSetData) {
+        for rebuilt in self.spilled.values_mut().filter_map(|s| s.cached.get_mut()) {
+            rebuilt.osd.actors = osd.actors.clone();
+            rebuilt.osd.props = osd.props.clone();
+        }
+    }
+}
+
+impl Clone for LazyTrees {
+    fn clone(&self) -> Self {
+        Self {
+            max_resident: self.max_resident,
+            spilled: self.spilled.clone(),
+            last_used: self
+                .last_used
+                .iter()
+                .map(|(obj, tick)| (*obj, AtomicU64::new(tick.load(Relaxed))))
+                .collect(),
+            detached: self.detached.clone(),
+            tick: AtomicU64::new(self.tick.load(Relaxed)),
+        }
+    }
+}
+
+/// An object whose op tree has been dropped, its ops are kept encoded in the document format and
+/// removed from [`OpSetData`]
+#[derive(Debug)]
+pub(super) struct SpilledTree {
+    pub(super) objtype: ObjType,
+    /// The op which made the object, only set while the parent object is resident
+    pub(super) parent: Option<OpIdx>,
+    pub(super) has_index: bool,
+    /// The [`OpTree::edited`] of the dropped tree
+    pub(super) edited: u64,
+    /// The number of ops in the object's tree
+    len: usize,
+    cols: DocOpColumns,
+    data: Vec<u8>,
+    /// The op tree rebuilt by a read since the tree was dropped
+    pub(super) cached: OnceLock<Rebuilt>,
+    /// The value of `LazyTrees::tick` when the object was last read
+    last_read: AtomicU64,
+}
+
+impl Clone for SpilledTree {
+    fn clone(&self) -> Self {
+        Self {
+            objtype: self.objtype,
+            parent: self.parent,
+            has_index: self.has_index,
+            edited: self.edited,
+            len: self.len,
+            cols: self.cols.clone(),
+            data: self.data.clone(),
+            cached: OnceLock::new(),
+            last_read: AtomicU64::new(0),
+        }
+    }
+}
+
+/// The op tree of a spilled object rebuilt with its own op set data, which shares the actors and
+/// props of the op set
+#[derive(Debug)]
+pub(crate) struct Rebuilt {
+    pub(super) osd: OpSetData,
+    pub(super) tree: OpTree,
+    /// The ops in `tree` which make objects
+    pub(super) makes: HashMap<ObjId, OpIdx, FxBuildHasher>,
+}
+
+impl SpilledTree {
+    pub(super) fn new(tree: &OpTree, osd: &OpSetData) -> Self {
+        let actors: HashMap<_, _> = (0..osd.actors.len()).map(|i| (i, i)).collect();
+        let ops: Vec<_> = tree.iter().collect();
+        let mut data = Vec::new();
+        let cols = DocOpColumns::encode(
+            ops.iter()
+                .map(|idx| op_as_docop(&actors, &osd.props, idx.as_op(osd))),
+            &mut data,
+        );
+        Self {
+            objtype: tree.objtype,
+            parent: tree.parent,
+            has_index: tree.internal.has_index,
+            edited: tree.edited,
+            len: ops.len(),
+            cols,
+            data,
+            cached: OnceLock::new(),
+            last_read: AtomicU64::new(0),
+        }
+    }
+
+    /// Decode the ops of `obj`, recreating the ops which delete them as loading a document does
+    pub(super) fn rebuild(&self, obj: ObjId, node_size: usize, osd: &OpSetData) -> Rebuilt {
+        let mut rebuilt = OpSetData {
+            actors: osd.actors.clone(),
+            props: osd.props.clone(),
+            ops: Vec::with_capacity(self.len),
+            op_deps: Vec::new(),
+        };
+        let mut ids = HashMap::with_capacity_and_hasher(self.len, FxBuildHasher::default());
+        let mut makes = HashMap::default();
+        let mut succs = Vec::new();
+        for op in self.cols.iter(&self.data) {
+            let op = op.expect("spilled ops were encoded by SpilledTree::new");
+            let key = match op.key {
+                DocOpKey::Prop(prop) => Key::Map(
+                    osd.props
+                        .lookup(prop.as_str())
+                        .expect("props are never removed"),
+                ),
+                DocOpKey::Elem(elem) => Key::Seq(elem),
+            };
+            let action =
+                OpType::from_action_and_value(op.action, op.value, op.mark_name, op.expand);
+            let is_make = matches!(action, OpType::Make(_));
+            let idx = rebuilt.push(
+                obj,
+                OpBuilder {
+                    id: op.id,
+                    action,
+                    key,
+                    insert: op.insert,
+                },
+            );
+            ids.insert(op.id, idx);
+            if is_make {
+                makes.insert(ObjId(op.id), idx);
+            }
+            if !op.succ.is_empty() {
+                succs.push((idx, op.succ));
+            }
+        }
+        let mut deletes = HashMap::<_, _, FxBuildHasher>::default();
+        for (pred, succ) in succs {
+            for id in succ {
+                let succ = match ids.get(&id) {
+                    Some(idx) => *idx,
+                    None => *deletes.entry(id).or_insert_with(|| {
+                        let key = pred.as_op(&rebuilt).elemid_or_key();
+                        rebuilt.push(
+                            obj,
+                            OpBuilder {
+                                id,
+                                insert: false,
+                                key,
+                                action: OpType::Delete,
+                            },
+                        )
+                    }),
+                };
+                rebuilt.add_pred(pred, succ);
+            }
+        }
+        let tree = OpTree::from_ops(
+            self.objtype,
+            None,
+            self.has_index,
+            node_size,
+            (0..self.len).map(OpIdx::new),
+            &rebuilt,
+        );
+        Rebuilt {
+            osd: rebuilt,
+            tree,
+            makes,
+        }
+    }
+}
+
+/// The trees of the spilled objects rebuilt for one pass over the whole document, see
+/// [`OpSetInternal::rebuild_spilled()`]
+#[derive(Debug, Default)]
+pub(crate) struct RebuiltObjects(HashMap<ObjId, Rebuilt, FxBuildHasher>);
+
+impl OpSetData {
+    /// Move the ops of `other` into these at `at`, the ops after `at` are moved after them
+    fn transplant(&mut self, other: OpSetData, at: usize) {
+        let deps = self.op_deps.len();
+        let dep = |idx: Option<OpDepIdx>| idx.map(|idx| OpDepIdx::new(idx.get() + deps));
+        let op = |idx: OpIdx| OpIdx::new(idx.get() + at);
+        self.ops.splice(
+            at..at,
+            other.ops.into_iter().map(|mut raw| {
+                raw.pred = dep(raw.pred);
+                raw.succ = dep(raw.succ);
+                raw
+            }),
+        );
+        self.op_deps
+            .extend(other.op_deps.into_iter().map(|mut raw| {
+                raw.pred = op(raw.pred);
+                raw.succ = op(raw.succ);
+                raw.next_pred = dep(raw.next_pred);
+                raw.next_succ = dep(raw.next_succ);
+                raw.last_pred = dep(raw.last_pred);
+                raw.last_succ = dep(raw.last_succ);
+                raw
+            }));
+    }
+}
+
+impl OpSetInternal {
+    /// Rebuild every spilled object which isn't in the read cache, without caching it
+    ///
+    /// This is for reads which go over every object, such as saving, so they don't fill the
+    /// cache with the whole document.
+    pub(crate) fn rebuild_spilled(&self) -> RebuiltObjects {
+        let Some(lazy) = &self.lazy else {
+            return RebuiltObjects::default();
+        };
+        RebuiltObjects(
+            lazy.spilled
+                .iter()
+                .filter(|(_, spilled)| spilled.cached.get().is_none())
+                .map(|(obj, spilled)| (*obj, spilled.rebuild(*obj, self.node_size, &self.osd)))
+                .collect(),
+        )
+    }
+
+    /// Every op tree and the op set data its ops are in
+    pub(super) fn all_trees<'a>(
+        &'a self,
+        rebuilt: &'a RebuiltObjects,
+    ) -> impl Iterator<Item = (&'a ObjId, &'a OpTree, &'a OpSetData)> + 'a {
+        let spilled = self.lazy.iter().flat_map(|lazy| lazy.spilled.iter());
+        self.trees
+            .iter()
+            .map(|(obj, tree)| (obj, tree, &self.osd))
+            .chain(spilled.map(move |(obj, spilled)| {
+                let rebuilt = rebuilt
+                    .0
+                    .get(obj)
+                    .or_else(|| spilled.cached.get())
+                    .expect("spilled objects are rebuilt by rebuild_spilled");
+                (obj, &rebuilt.tree, &rebuilt.osd)
+            }))
+    }
+
+    /// The index of `actor` in the actors of the op set, adding it if it is new
+    pub(crate) fn import_actor(&mut self, actor: ActorId) -> usize {
+        if let Some(index) = self.osd.actors.lookup(&actor) {
+            return index;
+        }
+        self.update_caches(|osd| Arc::make_mut(&mut osd.actors).cache(actor))
+    }
+
+    /// The index of `prop` in the props of the op set, adding it if it is new
+    pub(crate) fn import_prop(&mut self, prop: &str) -> usize {
+        if let Some(index) = self.osd.props.lookup(prop) {
+            return index;
+        }
+        self.update_caches(|osd| Arc::make_mut(&mut osd.props).cache(prop.to_string()))
+    }
+
+    /// Add to the actors or props, which are shared with the trees in the read cache, the cached
+    /// trees get the new entries too so they resolve the indices of the op set
+    fn update_caches<R, F: FnOnce(&mut OpSetData) -> R>(&mut self, f: F) -> R {
+        if let Some(lazy) = &mut self.lazy {
+            lazy.unshare_caches();
+        }
+        let result = f(&mut self.osd);
+        if let Some(lazy) = &mut self.lazy {
+            lazy.share_caches(&self.osd);
+        }
+        result
+    }
+
+    /// Move the op tree of the spilled object `obj` back into `self.trees`. If `pending` is set
+    /// the ops of `obj` go before the ops of that transaction, so they stay the last ops in the op
+    /// set.
+    pub(super) fn unspill_before(&mut self, obj: &ObjId, pending: Option<&mut OpIdxRange>) {
+        let mut spilled = match &mut self.lazy {
+            Some(lazy) => match lazy.spilled.remove(obj) {
+                Some(spilled) => spilled,
+                None => {
+                    lazy.touch(obj);
+                    return;
+                }
+            },
+            None => return,
+        };
+        let rebuilt = match spilled.cached.take() {
+            Some(rebuilt) => rebuilt,
+            None => spilled.rebuild(*obj, self.node_size, &self.osd),
+        };
+        let Rebuilt {
+            osd,
+            mut tree,
+            makes,
+        } = rebuilt;
+        let at = match pending {
+            Some(range) => {
+                let at = range.start as usize;
+                self.shift_pending(range, osd.ops.len());
+                at
+            }
+            _ => self.osd.ops.len(),
+        };
+        self.osd.transplant(osd, at);
+        tree.map_ops(|idx| OpIdx::new(idx.get() + at));
+        tree.parent = spilled.parent;
+        tree.edited = spilled.edited;
+        self.trees.insert(*obj, tree);
+        let Some(lazy) = &mut self.lazy else {
+            return;
+        };
+        for (child, idx) in makes {
+            if lazy.detached.get(&child) == Some(obj) {
+                lazy.detached.remove(&child);
+                let parent = Some(OpIdx::new(idx.get() + at));
+                if let Some(tree) = self.trees.get_mut(&child) {
+                    tree.parent = parent;
+                } else if let Some(spilled) = lazy.spilled.get_mut(&child) {
+                    spilled.parent = parent;
+                }
+            }
+        }
+        let tick = lazy.tick.get_mut();
+        lazy.last_used.insert(*obj, AtomicU64::new(*tick));
+        *tick += 1;
+    }
+
+    /// Move the ops of the open transaction in `range`, which are the last ops in the op set, `by`
+    /// places along to make room for the ops of an object before them
+    fn shift_pending(&mut self, range: &mut OpIdxRange, by: usize) {
+        let start = range.start as usize;
+        let shift = |idx: OpIdx| {
+            if idx.get() >= start {
+                OpIdx::new(idx.get() + by)
+            } else {
+                idx
+            }
+        };
+        let mut objs = HashSet::<_, FxBuildHasher>::default();
+        let mut children = Vec::new();
+        let mut deps = Vec::new();
+        for raw in &self.osd.ops[start..] {
+            objs.insert(raw.obj);
+            if let OpType::Make(_) = raw.op.action {
+                children.push(ObjId(raw.op.id));
+            }
+            let mut next = raw.pred;
+            while let Some(dep) = next {
+                deps.push(dep);
+                next = self.osd.op_deps[dep.get()].next_pred;
+            }
+            let mut next = raw.succ;
+            while let Some(dep) = next {
+                deps.push(dep);
+                next = self.osd.op_deps[dep.get()].next_succ;
+            }
+        }
+        deps.sort_unstable_by_key(|dep| dep.get());
+        deps.dedup_by_key(|dep| dep.get());
+        for dep in deps {
+            let raw = &mut self.osd.op_deps[dep.get()];
+            raw.pred = shift(raw.pred);
+            raw.succ = shift(raw.succ);
+        }
+        for obj in objs {
+            if let Some(tree) = self.trees.get_mut(&obj) {
+                tree.map_ops(shift);
+            }
+        }
+        for child in children {
+            if let Some(tree) = self.trees.get_mut(&child) {
+                tree.parent = tree.parent.map(shift);
+            }
+        }
+        range.start += by as u32;
+        range.end += by as u32;
+    }
+
+    /// Drop the ops of the spilled objects from the op set data, keeping the ops of the resident
+    /// trees and the ops which delete them in the order of the trees
+    pub(super) fn repack(&mut self) {
+        const DROPPED: u32 = u32::MAX;
+        let mut new_pos = vec![DROPPED; self.osd.ops.len()];
+        let mut order = Vec::new();
+        let mut objs: Vec<_> = self.trees.keys().copied().collect();
+        objs.sort_by(|a, b| self.osd.lamport_cmp(a.0, b.0));
+        for obj in &objs {
+            let tree = &self.trees[obj];
+            for idx in tree.iter() {
+                new_pos[idx.get()] = order.len() as u32;
+                order.push(idx);
+            }
+            for idx in tree.iter() {
+                for succ in idx.as_op(&self.osd).succ() {
+                    if new_pos[succ.idx().get()] == DROPPED {
+                        new_pos[succ.idx().get()] = order.len() as u32;
+                        order.push(succ.idx());
+                    }
+                }
+            }
+        }
+        let kept = |idx: OpIdx| {
+            Some(new_pos[idx.get()])
+                .filter(|pos| *pos != DROPPED)
+                .map(|pos| OpIdx::new(pos as usize))
+        };
+
+        let mut deps = Vec::new();
+        for (succ, idx) in order.iter().enumerate() {
+            for pred in idx.as_op(&self.osd).pred() {
+                let pred = kept(pred.idx()).expect("the preds of kept ops are kept");
+                deps.push((pred, OpIdx::new(succ)));
+            }
+        }
+
+        let mut detached = Vec::new();
+        for (obj, tree) in self.trees.iter_mut() {
+            if let Some(idx) = tree.parent {
+                tree.parent = kept(idx);
+                if tree.parent.is_none() {
+                    detached.push((*obj, self.osd.ops[idx.get()].obj));
+                }
+            }
+        }
+        if let Some(lazy) = &mut self.lazy {
+            for (obj, spilled) in lazy.spilled.iter_mut() {
+                if let Some(idx) = spilled.parent {
+                    spilled.parent = kept(idx);
+                    if spilled.parent.is_none() {
+                        detached.push((*obj, self.osd.ops[idx.get()].obj));
+                    }
+                }
+            }
+            lazy.detached.extend(detached);
+        }
+
+        let mut old: Vec<_> = std::mem::take(&mut self.osd.ops)
+            .into_iter()
+            .map(Some)
+            .collect();
+        self.osd.ops = order
+            .iter()
+            .map(|idx| {
+                let mut raw = old[idx.get()].take().expect("ops are kept once");
+                raw.pred = None;
+                raw.succ = None;
+                raw.pred_len = 0;
+                raw.succ_len = 0;
+                raw
+            })
+            .collect();
+        self.osd.op_deps = Vec::with_capacity(deps.len());
+        for (pred, succ) in deps {
+            self.osd.add_dep(pred, succ);
+        }
+        for tree in self.trees.values_mut() {
+            tree.map_ops(|idx| kept(idx).expect("the ops of resident trees are kept"));
+        }
+    }
+}
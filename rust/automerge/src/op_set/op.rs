@@ -226,10 +226,12 @@ impl<'a> Op<'a> {
     }
 
     pub(crate) fn width(&self, encoding: ListEncoding) -> usize {
-        if encoding == ListEncoding::List {
-            1
-        } else {
-            self.raw().width as usize
+        match encoding {
+            ListEncoding::List => 1,
+            ListEncoding::Text => self.raw().width as usize,
+            ListEncoding::Utf16 => self.as_str().encode_utf16().count(),
+            ListEncoding::Utf8 => self.as_str().len(),
+            ListEncoding::CodePoints => self.as_str().chars().count(),
         }
     }
 
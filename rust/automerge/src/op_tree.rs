@@ -34,6 +34,9 @@ pub(crate) struct OpTree {
     /// short circuit the query if the follow op is another
     /// insert or delete at the same spot
     pub(crate) last_insert: Option<LastInsert>,
+    /// The value of `OpSetInternal::edits` when an op was last added to or removed from the tree
+    /// or had its successors changed
+    pub(crate) edited: u64,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -47,11 +50,50 @@ pub(crate) struct LastInsert {
 
 impl OpTree {
     pub(crate) fn new(objtype: ObjType) -> Self {
+        Self::with_node_size(objtype, B)
+    }
+
+    pub(crate) fn with_node_size(objtype: ObjType, node_size: usize) -> Self {
         Self {
-            internal: OpTreeInternal::new(objtype),
+            internal: OpTreeInternal::with_node_size(objtype, node_size),
             objtype,
             parent: None,
             last_insert: None,
+            edited: 0,
+        }
+    }
+
+    /// Rebuild the tree with nodes of minimum degree `node_size`
+    pub(crate) fn set_node_size(&mut self, node_size: usize, osd: &OpSetData) {
+        if node_size != self.internal.node_size {
+            self.rebuild(node_size, osd);
+        }
+    }
+
+    /// Rebuild the tree from its ops with nodes of minimum degree `node_size`
+    pub(crate) fn rebuild(&mut self, node_size: usize, osd: &OpSetData) {
+        let ops: Vec<_> = self.internal.iter().collect();
+        self.internal =
+            OpTreeInternal::from_elements(&ops, self.internal.has_index, node_size, osd);
+        self.last_insert = None;
+    }
+
+    /// Build a tree from the ops of an object, in the order they appear in the object's tree
+    pub(crate) fn from_ops<I: IntoIterator<Item = OpIdx>>(
+        objtype: ObjType,
+        parent: Option<OpIdx>,
+        has_index: bool,
+        node_size: usize,
+        ops: I,
+        osd: &OpSetData,
+    ) -> Self {
+        let ops: Vec<_> = ops.into_iter().collect();
+        Self {
+            internal: OpTreeInternal::from_elements(&ops, has_index, node_size, osd),
+            objtype,
+            parent,
+            last_insert: None,
+            edited: 0,
         }
     }
 
@@ -63,6 +105,14 @@ impl OpTree {
         self.internal.len()
     }
 
+    /// Point the tree at the positions `f` gives for its ops, for when the ops are moved in the
+    /// op set data
+    pub(crate) fn map_ops<F: FnMut(OpIdx) -> OpIdx>(&mut self, mut f: F) {
+        if let Some(root) = self.internal.root_node.as_mut() {
+            root.map_ops(&mut f);
+        }
+    }
+
     pub(crate) fn add_index(&mut self, osd: &OpSetData) {
         self.internal.has_index = true;
         if let Some(root) = self.internal.root_node.as_mut() {
@@ -143,14 +193,17 @@ impl<'a> FoundOpWithPatchLog<'a> {
         if op.is_delete() {
             match (self.before, self.overwritten, self.after) {
                 (None, Some(over), None) => match key {
-                    Prop::Map(k) => patch_log.delete_map(obj.id, &k),
+                    Prop::Map(k) => {
+                        patch_log.delete_map(obj.id, &k);
+                        patch_log.previous_value(over, None);
+                    }
                     Prop::Seq(index) => patch_log.delete_seq(
                         obj.id,
                         index,
                         over.width(patch_log.text_rep().encoding(obj.typ)),
                     ),
                 },
-                (Some(before), Some(_), None) => {
+                (Some(before), Some(over), None) => {
                     let conflict = self.num_before > 1;
                     patch_log.put(
                         obj.id,
@@ -159,7 +212,9 @@ impl<'a> FoundOpWithPatchLog<'a> {
                         *before.id(),
                         conflict,
                         true,
+                        true,
                     );
+                    patch_log.previous_value(over, None);
                 }
                 _ => { /* do nothing */ }
             }
@@ -184,7 +239,19 @@ impl<'a> FoundOpWithPatchLog<'a> {
                     patch_log.flag_conflict(obj.id, &key);
                 }
             } else {
-                patch_log.put(obj.id, &key, op.value().into(), *op.id(), conflict, false);
+                let replaced = self.overwritten.is_some();
+                patch_log.put(
+                    obj.id,
+                    &key,
+                    op.value().into(),
+                    *op.id(),
+                    conflict,
+                    false,
+                    replaced,
+                );
+                if let Some(over) = self.overwritten {
+                    patch_log.previous_value(over, None);
+                }
             }
         }
     }
@@ -194,15 +261,40 @@ impl<'a> FoundOpWithPatchLog<'a> {
 pub(crate) struct OpTreeInternal {
     pub(crate) root_node: Option<OpTreeNode>,
     pub(crate) has_index: bool,
+    /// The minimum degree of the nodes in the tree
+    pub(crate) node_size: usize,
 }
 
 impl OpTreeInternal {
-    /// Construct a new, empty, sequence.
-    pub(crate) fn new(obj_type: ObjType) -> Self {
+    /// Construct a new, empty, sequence whose nodes have minimum degree `node_size`
+    pub(crate) fn with_node_size(obj_type: ObjType, node_size: usize) -> Self {
         let has_index = obj_type.is_sequence();
         Self {
             root_node: None,
             has_index,
+            node_size,
+        }
+    }
+
+    /// Build the sequence `elements` in one go, rather than inserting them one at a time, in as
+    /// few nodes of minimum degree `node_size` as it fits in
+    pub(crate) fn from_elements(
+        elements: &[OpIdx],
+        has_index: bool,
+        node_size: usize,
+        osd: &OpSetData,
+    ) -> Self {
+        let root_node = (!elements.is_empty()).then(|| {
+            let mut height = 1;
+            while OpTreeNode::capacity(height, node_size) < elements.len() {
+                height += 1;
+            }
+            OpTreeNode::from_elements(elements, height, has_index, osd, node_size)
+        });
+        Self {
+            root_node,
+            has_index,
+            node_size,
         }
     }
 
@@ -382,6 +474,28 @@ impl OpTreeInternal {
         })
     }
 
+    /// Find the index of the element inserted by `opid` and the op which holds its current value,
+    /// if the element is visible
+    pub(crate) fn seek_list_element<'a>(
+        &'a self,
+        opid: OpId,
+        encoding: ListEncoding,
+        clock: Option<&Clock>,
+        osd: &'a OpSetData,
+    ) -> Option<(usize, Option<Op<'a>>)> {
+        let query = self.search(query::OpIdSearch::opid(opid, encoding, clock), osd);
+        let pos = query.found()?;
+        let mut iter = self.iter();
+        let op = iter.nth(pos).map(|idx| idx.as_op(osd))?;
+        let index = query.index_for(op);
+        let winner = std::iter::once(op)
+            .chain(iter.map(|idx| idx.as_op(osd)))
+            .take_while(|e| e.elemid_or_key() == op.elemid_or_key())
+            .filter(|e| e.visible_at(clock))
+            .last();
+        Some((index, winner))
+    }
+
     pub(crate) fn find_op_with_patch_log<'a>(
         &'a self,
         op: Op<'a>,
@@ -464,6 +578,25 @@ impl OpTreeInternal {
         })
     }
 
+    /// Whether there is a visible op for `key_name`, stopping at the first one found
+    pub(crate) fn has_visible_map_key(
+        &self,
+        osd: &OpSetData,
+        key_name: &str,
+        clock: Option<&Clock>,
+    ) -> bool {
+        let Some(prop) = osd.props.lookup(key_name) else {
+            return false;
+        };
+        let key = Key::Map(prop);
+        let pos = self.binary_search_by(osd, |o| o.key_cmp(&key));
+        self.iter()
+            .skip(pos)
+            .map(|idx| idx.as_op(osd))
+            .take_while(|op| op.key_cmp(&key) == Ordering::Equal)
+            .any(|op| op.visible_at(clock))
+    }
+
     pub(crate) fn seek_ops_by_index<'a>(
         &'a self,
         osd: &'a OpSetData,
@@ -505,6 +638,12 @@ impl OpTreeInternal {
             if let Some(index) = root.index.as_ref() {
                 match query.query_node(root, index, osd) {
                     QueryResult::Descend => root.search(&mut query, osd),
+                    QueryResult::SkipNode => {
+                        if let Some(list_state) = query.list_state_mut() {
+                            list_state.skip_node(root, index, osd);
+                        }
+                        true
+                    }
                     _ => true,
                 }
             } else {
@@ -538,7 +677,7 @@ impl OpTreeInternal {
             #[cfg(debug_assertions)]
             root.check();
 
-            if root.is_full() {
+            if root.is_full(self.node_size) {
                 let original_len = root.len();
                 let new_root = OpTreeNode::new(root.index.is_some());
 
@@ -548,7 +687,7 @@ impl OpTreeInternal {
                 root.length += old_root.len();
                 root.index.clone_from(&old_root.index);
                 root.children.push(old_root);
-                root.split_child(0, osd);
+                root.split_child(0, osd, self.node_size);
 
                 assert_eq!(original_len, root.len());
 
@@ -561,14 +700,14 @@ impl OpTreeInternal {
                     (&mut root.children[0], index)
                 };
                 root.length += 1;
-                child.insert_into_non_full_node(insertion_index, element, osd);
+                child.insert_into_non_full_node(insertion_index, element, osd, self.node_size);
                 root.index_insert(element.as_op(osd))
             } else {
-                root.insert_into_non_full_node(index, element, osd)
+                root.insert_into_non_full_node(index, element, osd, self.node_size)
             }
         } else {
             let mut root = OpTreeNode::new(self.has_index);
-            root.insert_into_non_full_node(index, element, osd);
+            root.insert_into_non_full_node(index, element, osd, self.node_size);
             self.root_node = Some(root)
         }
         assert_eq!(self.len(), old_len + 1, "{:#?}", self);
@@ -595,7 +734,7 @@ impl OpTreeInternal {
         if let Some(root) = self.root_node.as_mut() {
             #[cfg(debug_assertions)]
             let len = root.check();
-            let old = root.remove(index, osd);
+            let old = root.remove(index, osd, self.node_size);
 
             if root.elements.is_empty() {
                 if root.is_leaf() {
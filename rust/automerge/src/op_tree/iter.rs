@@ -394,7 +394,7 @@ mod tests {
     }
 
     fn make_optree(actions: &[Action], osd: &OpSetData) -> super::OpTreeInternal {
-        let mut optree = OpTreeInternal::new(ObjType::List);
+        let mut optree = OpTreeInternal::with_node_size(ObjType::List, crate::op_tree::B);
         for action in actions {
             match action {
                 Action::Insert(index, idx) => {
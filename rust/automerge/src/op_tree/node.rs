@@ -11,6 +11,8 @@ use crate::{
     types::OpId,
 };
 
+/// The default minimum degree of the nodes in an op tree, see
+/// [`crate::Automerge::with_op_tree_node_size()`]
 pub const B: usize = 16;
 
 #[derive(Clone, Debug)]
@@ -32,6 +34,63 @@ impl OpTreeNode {
         }
     }
 
+    /// The most elements a node of height `height`, counting a leaf as height 1, can hold below
+    /// and including itself
+    pub(crate) fn capacity(height: usize, b: usize) -> usize {
+        let max_elements = 2 * b - 1;
+        (1..height).fold(max_elements, |below, _| {
+            max_elements + (max_elements + 1) * below
+        })
+    }
+
+    /// Build a node of height `height` holding `elements`, which must fit, with as few children
+    /// as possible and the elements spread evenly between them so every leaf is at the same depth
+    pub(crate) fn from_elements(
+        elements: &[OpIdx],
+        height: usize,
+        has_index: bool,
+        osd: &OpSetData,
+        b: usize,
+    ) -> Self {
+        let mut node = Self::new(has_index);
+        node.length = elements.len();
+        if height == 1 {
+            node.elements = elements.to_vec();
+        } else {
+            let child_capacity = Self::capacity(height - 1, b);
+            let num_children =
+                ((elements.len() + 1 + child_capacity) / (child_capacity + 1)).max(2);
+            let in_children = elements.len() - (num_children - 1);
+            let mut start = 0;
+            for i in 0..num_children {
+                let len = in_children / num_children + usize::from(i < in_children % num_children);
+                node.children.push(Self::from_elements(
+                    &elements[start..start + len],
+                    height - 1,
+                    has_index,
+                    osd,
+                    b,
+                ));
+                start += len;
+                if i + 1 < num_children {
+                    node.elements.push(elements[start]);
+                    start += 1;
+                }
+            }
+        }
+        node.reindex(osd);
+        node
+    }
+
+    pub(crate) fn map_ops<F: FnMut(OpIdx) -> OpIdx>(&mut self, f: &mut F) {
+        for idx in self.elements.iter_mut() {
+            *idx = f(*idx);
+        }
+        for child in self.children.iter_mut() {
+            child.map_ops(f);
+        }
+    }
+
     fn search_element<'a, 'b: 'a, Q>(
         &'b self,
         query: &mut Q,
@@ -72,6 +131,11 @@ impl OpTreeNode {
                         }
                         QueryResult::Finish => return true,
                         QueryResult::Next => (),
+                        QueryResult::SkipNode => {
+                            if let Some(list_state) = query.list_state_mut() {
+                                list_state.skip_node(child, index, m);
+                            }
+                        }
                     }
                 } else if child.search(query, m) {
                     return true;
@@ -88,6 +152,16 @@ impl OpTreeNode {
         self.length
     }
 
+    /// The depth of the tree below and including this node and the number of nodes in it
+    pub(crate) fn depth_and_node_count(&self) -> (usize, usize) {
+        self.children.iter().map(|c| c.depth_and_node_count()).fold(
+            (1, 1),
+            |(depth, count), (child_depth, child_count)| {
+                (depth.max(child_depth + 1), count + child_count)
+            },
+        )
+    }
+
     fn reindex(&mut self, osd: &OpSetData) {
         if self.index.is_some() {
             let mut index = Index::new();
@@ -108,8 +182,8 @@ impl OpTreeNode {
         self.children.is_empty()
     }
 
-    pub(crate) fn is_full(&self) -> bool {
-        self.elements.len() >= 2 * B - 1
+    pub(crate) fn is_full(&self, b: usize) -> bool {
+        self.elements.len() >= 2 * b - 1
     }
 
     /// Returns the child index and the given index adjusted for the cumulative index before that
@@ -171,8 +245,9 @@ impl OpTreeNode {
         index: usize,
         element: OpIdx,
         osd: &OpSetData,
+        b: usize,
     ) {
-        assert!(!self.is_full());
+        assert!(!self.is_full(b));
 
         self.index_insert(element.as_op(osd));
 
@@ -183,15 +258,15 @@ impl OpTreeNode {
             let (child_index, sub_index) = self.find_child_index(index);
             let child = &mut self.children[child_index];
 
-            if child.is_full() {
-                self.split_child(child_index, osd);
+            if child.is_full(b) {
+                self.split_child(child_index, osd, b);
 
                 // child structure has changed so we need to find the index again
                 let (child_index, sub_index) = self.find_child_index(index);
                 let child = &mut self.children[child_index];
-                child.insert_into_non_full_node(sub_index, element, osd);
+                child.insert_into_non_full_node(sub_index, element, osd, b);
             } else {
-                child.insert_into_non_full_node(sub_index, element, osd);
+                child.insert_into_non_full_node(sub_index, element, osd, b);
             }
             self.length += 1;
         }
@@ -200,22 +275,22 @@ impl OpTreeNode {
 
     // A utility function to split the child `full_child_index` of this node
     // Note that `full_child_index` must be full when this function is called.
-    pub(crate) fn split_child(&mut self, full_child_index: usize, m: &OpSetData) {
+    pub(crate) fn split_child(&mut self, full_child_index: usize, m: &OpSetData, b: usize) {
         let original_len_self = self.len();
 
         let full_child = &mut self.children[full_child_index];
 
-        // Create a new node which is going to store (B-1) keys
+        // Create a new node which is going to store (b-1) keys
         // of the full child.
         let mut successor_sibling = OpTreeNode::new(self.index.is_some());
 
         let original_len = full_child.len();
-        assert!(full_child.is_full());
+        assert!(full_child.is_full(b));
 
-        successor_sibling.elements = full_child.elements.split_off(B);
+        successor_sibling.elements = full_child.elements.split_off(b);
 
         if !full_child.is_leaf() {
-            successor_sibling.children = full_child.children.split_off(B);
+            successor_sibling.children = full_child.children.split_off(b);
         }
 
         let middle = full_child.elements.pop().unwrap();
@@ -257,27 +332,28 @@ impl OpTreeNode {
         index: usize,
         element_index: usize,
         m: &OpSetData,
+        b: usize,
     ) -> OpIdx {
         self.length -= 1;
-        if self.children[element_index].elements.len() >= B {
+        if self.children[element_index].elements.len() >= b {
             let total_index = self.cumulative_index(element_index);
             // recursively delete index - 1 in predecessor_node
-            let predecessor = self.children[element_index].remove(index - 1 - total_index, m);
+            let predecessor = self.children[element_index].remove(index - 1 - total_index, m, b);
             // replace element with that one
             mem::replace(&mut self.elements[element_index], predecessor)
-        } else if self.children[element_index + 1].elements.len() >= B {
+        } else if self.children[element_index + 1].elements.len() >= b {
             // recursively delete index + 1 in successor_node
             let total_index = self.cumulative_index(element_index + 1);
-            let successor = self.children[element_index + 1].remove(index + 1 - total_index, m);
+            let successor = self.children[element_index + 1].remove(index + 1 - total_index, m, b);
             // replace element with that one
             mem::replace(&mut self.elements[element_index], successor)
         } else {
             let middle_element = self.elements.remove(element_index);
             let successor_child = self.children.remove(element_index + 1);
-            self.children[element_index].merge(middle_element, successor_child, m);
+            self.children[element_index].merge(middle_element, successor_child, m, b);
 
             let total_index = self.cumulative_index(element_index);
-            self.children[element_index].remove(index - total_index, m)
+            self.children[element_index].remove(index - total_index, m, b)
         }
     }
 
@@ -293,20 +369,21 @@ impl OpTreeNode {
         index: usize,
         mut child_index: usize,
         osd: &OpSetData,
+        b: usize,
     ) -> OpIdx {
-        if self.children[child_index].elements.len() < B
+        if self.children[child_index].elements.len() < b
             && if child_index > 0 {
-                self.children[child_index - 1].elements.len() < B
+                self.children[child_index - 1].elements.len() < b
             } else {
                 true
             }
             && if child_index + 1 < self.children.len() {
-                self.children[child_index + 1].elements.len() < B
+                self.children[child_index + 1].elements.len() < b
             } else {
                 true
             }
         {
-            // if the child and its immediate siblings have B-1 elements merge the child
+            // if the child and its immediate siblings have b-1 elements merge the child
             // with one sibling, moving an element from this node into the new merged node
             // to be the median
 
@@ -317,21 +394,21 @@ impl OpTreeNode {
                 let successor = self.children.remove(child_index);
                 child_index -= 1;
 
-                self.children[child_index].merge(middle, successor, osd);
+                self.children[child_index].merge(middle, successor, osd, b);
             } else {
                 let middle = self.elements.remove(child_index);
 
                 // use the sucessor sibling
                 let successor = self.children.remove(child_index + 1);
 
-                self.children[child_index].merge(middle, successor, osd);
+                self.children[child_index].merge(middle, successor, osd, b);
             }
-        } else if self.children[child_index].elements.len() < B {
+        } else if self.children[child_index].elements.len() < b {
             if child_index > 0
                 && self
                     .children
                     .get(child_index - 1)
-                    .map_or(false, |c| c.elements.len() >= B)
+                    .map_or(false, |c| c.elements.len() >= b)
             {
                 let last_element = self.children[child_index - 1].elements.pop().unwrap();
                 assert!(!self.children[child_index - 1].elements.is_empty());
@@ -357,7 +434,7 @@ impl OpTreeNode {
             } else if self
                 .children
                 .get(child_index + 1)
-                .map_or(false, |c| c.elements.len() >= B)
+                .map_or(false, |c| c.elements.len() >= b)
             {
                 let first_element = self.children[child_index + 1].elements.remove(0);
                 self.children[child_index + 1].index_remove(first_element.as_op(osd));
@@ -384,7 +461,7 @@ impl OpTreeNode {
         }
         self.length -= 1;
         let total_index = self.cumulative_index(child_index);
-        self.children[child_index].remove(index - total_index, osd)
+        self.children[child_index].remove(index - total_index, osd, b)
     }
 
     pub(crate) fn check(&self) -> usize {
@@ -394,7 +471,7 @@ impl OpTreeNode {
         l
     }
 
-    pub(crate) fn remove(&mut self, index: usize, osd: &OpSetData) -> OpIdx {
+    pub(crate) fn remove(&mut self, index: usize, osd: &OpSetData, b: usize) -> OpIdx {
         let original_len = self.len();
         if self.is_leaf() {
             let v = self.remove_from_leaf(index);
@@ -417,6 +494,7 @@ impl OpTreeNode {
                             index,
                             min(child_index, self.elements.len() - 1),
                             osd,
+                            b,
                         );
                         self.index_remove(v.as_op(osd));
                         self.index_regenerate_block(osd);
@@ -425,7 +503,7 @@ impl OpTreeNode {
                         return v;
                     }
                     Ordering::Greater => {
-                        let v = self.remove_from_internal_child(index, child_index, osd);
+                        let v = self.remove_from_internal_child(index, child_index, osd, b);
                         self.index_remove(v.as_op(osd));
                         self.index_regenerate_block(osd);
                         assert_eq!(original_len, self.len() + 1);
@@ -444,7 +522,7 @@ impl OpTreeNode {
         }
     }
 
-    fn merge(&mut self, middle: OpIdx, successor_sibling: OpTreeNode, osd: &OpSetData) {
+    fn merge(&mut self, middle: OpIdx, successor_sibling: OpTreeNode, osd: &OpSetData, b: usize) {
         if let Some(index) = &mut self.index {
             if let Some(succ_index) = &successor_sibling.index {
                 index.insert(middle.as_op(osd));
@@ -456,7 +534,7 @@ impl OpTreeNode {
         self.children.extend(successor_sibling.children);
         self.length += successor_sibling.length + 1;
         self.index_regenerate_block(osd);
-        assert!(self.is_full());
+        assert!(self.is_full(b));
     }
 
     fn block(&self) -> Option<OpId> {
@@ -24,8 +24,8 @@ impl<'a> Parents<'a> {
     /// Return the path this `Parents` represents
     ///
     /// This is _not_ in reverse order.
-    pub fn path(self) -> Vec<(ExId, Prop)> {
-        let mut path = self
+    pub fn path(mut self) -> Vec<(ExId, Prop)> {
+        let mut path = std::iter::from_fn(|| self.next_parent(false))
             .map(|Parent { obj, prop, .. }| (obj, prop))
             .collect::<Vec<_>>();
         path.reverse();
@@ -33,11 +33,11 @@ impl<'a> Parents<'a> {
     }
 
     /// Like `path` but returns `None` if the target is not visible
-    pub fn visible_path(self) -> Option<Vec<(ExId, Prop)>> {
+    pub fn visible_path(mut self) -> Option<Vec<(ExId, Prop)>> {
         let mut path = Vec::new();
-        for Parent {
+        while let Some(Parent {
             obj, prop, visible, ..
-        } in self
+        }) = self.next_parent(false)
         {
             if !visible {
                 return None;
@@ -47,24 +47,25 @@ impl<'a> Parents<'a> {
         path.reverse();
         Some(path)
     }
-}
 
-impl<'a> Iterator for Parents<'a> {
-    type Item = Parent;
-
-    fn next(&mut self) -> Option<Self::Item> {
+    fn next_parent(&mut self, check_conflicts: bool) -> Option<Parent> {
         if self.obj.is_root() {
             return None;
         }
+        let parent = self
+            .ops
+            .parent_object(&self.obj, self.text_rep, self.clock.as_ref())?;
+        let is_conflicted = check_conflicts
+            && self
+                .ops
+                .parent_is_conflicted(&parent, self.text_rep, self.clock.as_ref());
         let op_set::Parent {
             obj,
             typ,
             prop,
             visible,
             ..
-        } = self
-            .ops
-            .parent_object(&self.obj, self.text_rep, self.clock.as_ref())?;
+        } = parent;
         self.obj = obj;
         let obj = self.ops.id_to_exid(self.obj.0);
         Some(Parent {
@@ -72,10 +73,19 @@ impl<'a> Iterator for Parents<'a> {
             typ,
             prop,
             visible,
+            is_conflicted,
         })
     }
 }
 
+impl<'a> Iterator for Parents<'a> {
+    type Item = Parent;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_parent(true)
+    }
+}
+
 /// A component of a path to an object
 #[derive(Debug, PartialEq, Eq)]
 pub struct Parent {
@@ -91,6 +101,11 @@ pub struct Parent {
     /// deleted or because there is a conflict on this (object, property) pair and this value does
     /// not win the conflict.
     pub visible: bool,
+    /// Whether there is more than one visible value at `prop` in `obj`
+    ///
+    /// [`Parents::path()`] and [`Parents::visible_path()`] don't check for conflicts so they are
+    /// cheaper if you don't need this.
+    pub is_conflicted: bool,
 }
 
 #[cfg(test)]
@@ -122,12 +137,14 @@ mod tests {
                     prop: Prop::Map("list".to_string()),
                     visible: true,
                     typ: ObjType::Map,
+                    is_conflicted: false,
                 },
                 Parent {
                     obj: list,
                     prop: Prop::Seq(0),
                     visible: false,
                     typ: ObjType::List,
+                    is_conflicted: false,
                 },
             ]
         );
@@ -1,9 +1,11 @@
 mod patch;
 mod patch_builder;
 mod patch_log;
+mod text_edit;
 pub use patch::{Patch, PatchAction};
 pub(crate) use patch_builder::PatchBuilder;
 pub use patch_log::PatchLog;
+pub use text_edit::TextEdit;
 
 use crate::{types::ListEncoding, ObjType};
 
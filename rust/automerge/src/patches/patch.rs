@@ -35,6 +35,10 @@ pub enum PatchAction {
         /// the "winning" value of the conflict. The conflicting values can be obtained with
         /// [`crate::ReadDoc::get_all`]
         conflict: bool,
+        /// The value which was replaced, if any. This is only recorded if the
+        /// [`PatchLog`](super::PatchLog) was created with
+        /// [`PatchLog::with_previous_values()`](super::PatchLog::with_previous_values)
+        old: Option<Value<'static>>,
     },
     /// An index in a sequence was updated
     PutSeq {
@@ -46,6 +50,10 @@ pub enum PatchAction {
         /// the "winning" value of the conflict. The conflicting values can be obtained with
         /// [`crate::ReadDoc::get_all`]
         conflict: bool,
+        /// The value which was replaced. This is only recorded if the
+        /// [`PatchLog`](super::PatchLog) was created with
+        /// [`PatchLog::with_previous_values()`](super::PatchLog::with_previous_values)
+        old: Option<Value<'static>>,
     },
     /// One or more elements were inserted into a sequence
     Insert {
@@ -75,7 +83,13 @@ pub enum PatchAction {
         prop: Prop,
     },
     /// A key was deleted from a map
-    DeleteMap { key: String },
+    DeleteMap {
+        key: String,
+        /// The value which was deleted. This is only recorded if the
+        /// [`PatchLog`](super::PatchLog) was created with
+        /// [`PatchLog::with_previous_values()`](super::PatchLog::with_previous_values)
+        old: Option<Value<'static>>,
+    },
     /// One or more indices were removed from a sequence
     DeleteSeq { index: usize, length: usize },
     /// Some marks within a text object were added or removed
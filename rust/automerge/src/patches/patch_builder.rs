@@ -55,6 +55,12 @@ impl<'a, R: ReadDoc> PatchBuilder<'a, R> {
         std::mem::take(&mut self.patches)
     }
 
+    /// Remove every patch except the last one, which may still be extended by later events
+    pub(crate) fn take_finished_patches(&mut self) -> std::vec::Drain<'_, Patch> {
+        let finished = self.patches.len().saturating_sub(1);
+        self.patches.drain(..finished)
+    }
+
     pub(crate) fn insert(
         &mut self,
         obj: ObjId,
@@ -162,6 +168,13 @@ impl<'a, R: ReadDoc> PatchBuilder<'a, R> {
                     *tail_length += length;
                     return;
                 }
+                // deleting backwards from the start of the previous delete, e.g. a run of
+                // backspaces
+                if index + length == *tail_index {
+                    *tail_index = index;
+                    *tail_length += length;
+                    return;
+                }
             }
             _ => {}
         }
@@ -171,10 +184,11 @@ impl<'a, R: ReadDoc> PatchBuilder<'a, R> {
         }
     }
 
-    pub(crate) fn delete_map(&mut self, obj: ObjId, key: &str) {
+    pub(crate) fn delete_map(&mut self, obj: ObjId, key: &str, old: Option<Value<'static>>) {
         if let Some(path) = self.get_path(&obj) {
             let action = PatchAction::DeleteMap {
                 key: key.to_owned(),
+                old,
             };
             self.push(Patch { obj, path, action })
         }
@@ -186,6 +200,7 @@ impl<'a, R: ReadDoc> PatchBuilder<'a, R> {
         prop: Prop,
         tagged_value: (Value<'_>, ObjId),
         conflict: bool,
+        old: Option<Value<'static>>,
     ) {
         if let Some(path) = self.get_path(&obj) {
             let value = (tagged_value.0.to_owned(), tagged_value.1);
@@ -194,11 +209,13 @@ impl<'a, R: ReadDoc> PatchBuilder<'a, R> {
                     key,
                     value,
                     conflict,
+                    old,
                 },
                 Prop::Seq(index) => PatchAction::PutSeq {
                     index,
                     value,
                     conflict,
+                    old,
                 },
             };
             self.push(Patch { obj, path, action })
@@ -3,11 +3,13 @@ use crate::exid::ExId;
 use crate::hydrate::Value;
 use crate::iter::{ListRangeItem, MapRangeItem};
 use crate::marks::{MarkAccumulator, MarkSet};
+use crate::op_set::Op;
 use crate::read::ReadDocInternal;
-use crate::types::{ObjId, ObjType, OpId, Prop};
+use crate::types::{Clock, ObjId, ObjType, OpId, Prop};
 use crate::{Automerge, ChangeHash, Patch, ReadDoc};
 use std::collections::BTreeSet;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::ops::ControlFlow;
 use std::sync::Arc;
 
 use super::{PatchBuilder, TextRepresentation};
@@ -46,6 +48,8 @@ pub struct PatchLog {
     expose: HashSet<OpId>,
     active: bool,
     text_rep: TextRepresentation,
+    object_filter: Option<Vec<ExId>>,
+    previous_values: bool,
     pub(crate) heads: Option<Vec<ChangeHash>>,
 }
 
@@ -56,12 +60,17 @@ pub(crate) enum Event {
         value: Value,
         id: OpId,
         conflict: bool,
+        old: Option<crate::Value<'static>>,
+        /// Whether the put replaced a visible value, as reported by the caller which logged it,
+        /// whether or not that value is recorded in `old`
+        replaced: bool,
     },
     PutSeq {
         index: usize,
         value: Value,
         id: OpId,
         conflict: bool,
+        old: Option<crate::Value<'static>>,
     },
     DeleteSeq {
         index: usize,
@@ -69,6 +78,7 @@ pub(crate) enum Event {
     },
     DeleteMap {
         key: String,
+        old: Option<crate::Value<'static>>,
     },
     Splice {
         index: usize,
@@ -122,6 +132,8 @@ impl PatchLog {
             events: vec![],
             heads: None,
             text_rep,
+            object_filter: None,
+            previous_values: false,
         }
     }
 
@@ -143,6 +155,48 @@ impl PatchLog {
         Self::new(true, text_rep)
     }
 
+    /// Only emit patches for the objects in `roots` and the objects beneath them
+    ///
+    /// Changes to other objects are still recorded by the log, but [`Automerge::make_patches()`]
+    /// and [`crate::AutoCommit::make_patches()`] will not return any patches for them. This is
+    /// useful when a UI only renders a small part of a large document.
+    pub fn with_object_filter(mut self, roots: Vec<ExId>) -> Self {
+        self.object_filter = Some(roots);
+        self
+    }
+
+    /// Record the value which each put or map delete replaced
+    ///
+    /// The previous values are returned in the `old` field of [`PatchAction::PutMap`],
+    /// [`PatchAction::PutSeq`] and [`PatchAction::DeleteMap`]. This is off by default because it
+    /// requires reading the replaced value for every change. Deletions from sequences are
+    /// coalesced into ranges and so don't carry previous values, use
+    /// [`ReadDoc::list_range_at()`] with the heads from before the change instead.
+    ///
+    /// [`PatchAction::PutMap`]: crate::PatchAction::PutMap
+    /// [`PatchAction::PutSeq`]: crate::PatchAction::PutSeq
+    /// [`PatchAction::DeleteMap`]: crate::PatchAction::DeleteMap
+    pub fn with_previous_values(mut self, setting: bool) -> Self {
+        self.previous_values = setting;
+        self
+    }
+
+    /// Attach the value of `old` as it was at `clock` to the put or map delete which was just
+    /// logged, if this log records previous values
+    pub(crate) fn previous_value(&mut self, old: Op<'_>, clock: Option<&Clock>) {
+        if !self.previous_values {
+            return;
+        }
+        match self.events.last_mut() {
+            Some((_, Event::PutMap { old: slot, .. }))
+            | Some((_, Event::PutSeq { old: slot, .. }))
+            | Some((_, Event::DeleteMap { old: slot, .. })) => {
+                *slot = Some(old.value_at(clock).into_owned());
+            }
+            _ => {}
+        }
+    }
+
     pub(crate) fn set_active(&mut self, setting: bool) {
         self.active = setting
     }
@@ -156,8 +210,13 @@ impl PatchLog {
     }
 
     pub(crate) fn delete_map(&mut self, obj: ObjId, key: &str) {
-        self.events
-            .push((obj, Event::DeleteMap { key: key.into() }))
+        self.events.push((
+            obj,
+            Event::DeleteMap {
+                key: key.into(),
+                old: None,
+            },
+        ))
     }
 
     pub(crate) fn increment(&mut self, obj: ObjId, prop: &Prop, value: i64, id: OpId) {
@@ -199,6 +258,8 @@ impl PatchLog {
         self.events.push((obj, Event::FlagConflictSeq { index }))
     }
 
+    /// Log a put, `replaced` is whether the key or index had a visible value before the put
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn put(
         &mut self,
         obj: ObjId,
@@ -207,13 +268,16 @@ impl PatchLog {
         id: OpId,
         conflict: bool,
         expose: bool,
+        replaced: bool,
     ) {
         match prop {
-            Prop::Map(key) => self.put_map(obj, key, value, id, conflict, expose),
+            Prop::Map(key) => self.put_map(obj, key, value, id, conflict, expose, replaced),
             Prop::Seq(index) => self.put_seq(obj, *index, value, id, conflict, expose),
         }
     }
 
+    /// Log a put to a map, `replaced` is whether the key had a visible value before the put
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn put_map(
         &mut self,
         obj: ObjId,
@@ -222,6 +286,7 @@ impl PatchLog {
         id: OpId,
         conflict: bool,
         expose: bool,
+        replaced: bool,
     ) {
         if expose && value.is_object() {
             self.expose.insert(id);
@@ -233,6 +298,8 @@ impl PatchLog {
                 value,
                 id,
                 conflict,
+                old: None,
+                replaced,
             },
         ))
     }
@@ -256,6 +323,7 @@ impl PatchLog {
                 value,
                 id,
                 conflict,
+                old: None,
             },
         ))
     }
@@ -320,25 +388,99 @@ impl PatchLog {
     }
 
     pub(crate) fn make_patches(&mut self, doc: &Automerge) -> Vec<Patch> {
+        let mut patches = Vec::new();
+        let _ = self.emit_patches(doc, false, &mut |patch| {
+            patches.push(patch);
+            ControlFlow::Continue(())
+        });
+        patches
+    }
+
+    /// Like [`Self::make_patches()`] but with the patches grouped by the child of the root which
+    /// contains the object they modify, patches to the root itself are grouped under
+    /// [`crate::ROOT`]
+    pub(crate) fn make_patches_grouped(&mut self, doc: &Automerge) -> HashMap<ExId, Vec<Patch>> {
+        let mut groups: HashMap<ExId, Vec<Patch>> = HashMap::new();
+        for patch in self.make_patches(doc) {
+            let top = match patch.path.get(1) {
+                Some((obj, _)) => obj.clone(),
+                None => patch.obj.clone(),
+            };
+            groups.entry(top).or_default().push(patch);
+        }
+        groups
+    }
+
+    /// Like [`Self::make_patches()`] but passes each patch to `f` as soon as no later event can
+    /// change it, stopping early if `f` returns [`ControlFlow::Break`]
+    pub(crate) fn make_patches_streaming<F: FnMut(Patch) -> ControlFlow<()>>(
+        &mut self,
+        doc: &Automerge,
+        mut f: F,
+    ) -> ControlFlow<()> {
+        self.emit_patches(doc, true, &mut f)
+    }
+
+    fn emit_patches(
+        &mut self,
+        doc: &Automerge,
+        streaming: bool,
+        f: &mut dyn FnMut(Patch) -> ControlFlow<()>,
+    ) -> ControlFlow<()> {
         self.events.sort_by(|a, b| doc.ops().osd.lamport_cmp(a, b));
+        self.cancel_put_then_delete();
         let expose = ExposeQueue(self.expose.iter().map(|id| doc.id_to_exid(*id)).collect());
+        let roots = self.object_filter.as_ref();
+        let mut emit = |patch: Patch| {
+            let included = roots.map_or(true, |roots| {
+                roots.contains(&patch.obj) || patch.path.iter().any(|(obj, _)| roots.contains(obj))
+            });
+            if included {
+                f(patch)
+            } else {
+                ControlFlow::Continue(())
+            }
+        };
         if let Some(heads) = self.heads.as_ref() {
             let read_doc = ReadDocAt { doc, heads };
-            Self::make_patches_inner(&self.events, expose, doc, &read_doc, self.text_rep)
+            Self::make_patches_inner(
+                &self.events,
+                expose,
+                doc,
+                &read_doc,
+                self.text_rep,
+                streaming,
+                &mut emit,
+            )
         } else {
-            Self::make_patches_inner(&self.events, expose, doc, doc, self.text_rep)
+            Self::make_patches_inner(
+                &self.events,
+                expose,
+                doc,
+                doc,
+                self.text_rep,
+                streaming,
+                &mut emit,
+            )
         }
     }
 
-    fn make_patches_inner<R: ReadDocInternal>(
+    fn make_patches_inner<R: ReadDocInternal, F: FnMut(Patch) -> ControlFlow<()>>(
         events: &[(ObjId, Event)],
         mut expose_queue: ExposeQueue,
         doc: &Automerge,
         read_doc: &R,
         text_rep: TextRepresentation,
-    ) -> Vec<Patch> {
+        streaming: bool,
+        emit: &mut F,
+    ) -> ControlFlow<()> {
         let mut patch_builder = PatchBuilder::new(read_doc, Some(events.len()));
         for (obj, event) in events {
+            if streaming {
+                patch_builder
+                    .take_finished_patches()
+                    .try_for_each(&mut *emit)?;
+            }
             let exid = doc.id_to_exid(obj.0);
             // ignore events on objects in the expose queue
             // incremental updates are ignored and a observation
@@ -355,12 +497,15 @@ impl PatchLog {
                     value,
                     id,
                     conflict,
+                    old,
+                    ..
                 } => {
                     let opid = doc.id_to_exid(*id);
-                    patch_builder.put(exid, key.into(), (value.into(), opid), *conflict);
+                    let value = (value.into(), opid);
+                    patch_builder.put(exid, key.into(), value, *conflict, old.clone());
                 }
-                Event::DeleteMap { key } => {
-                    patch_builder.delete_map(exid, key);
+                Event::DeleteMap { key, old } => {
+                    patch_builder.delete_map(exid, key, old.clone());
                 }
                 Event::IncrementMap { key, n, id } => {
                     let opid = doc.id_to_exid(*id);
@@ -374,9 +519,11 @@ impl PatchLog {
                     value,
                     id,
                     conflict,
+                    old,
                 } => {
                     let opid = doc.id_to_exid(*id);
-                    patch_builder.put(exid, index.into(), (value.into(), opid), *conflict);
+                    let value = (value.into(), opid);
+                    patch_builder.put(exid, index.into(), value, *conflict, old.clone());
                 }
                 Event::Insert {
                     index,
@@ -413,7 +560,73 @@ impl PatchLog {
         // any objects exposed AFTER all other events get exposed here
         expose_queue.flush_queue(&mut patch_builder, doc, read_doc, text_rep);
 
-        patch_builder.take_patches()
+        patch_builder.take_patches().into_iter().try_for_each(emit)
+    }
+
+    /// Remove puts to map keys which are deleted again later in the log
+    ///
+    /// A put which is followed by a delete of the same key, with no other events on that key in
+    /// between, is dropped. If the put replaced a value which was visible before the put then
+    /// the delete is kept and reports that value as the one it removed, otherwise the key was
+    /// never visible and the delete is dropped too. This runs on the sorted events, where all the
+    /// events for one object are adjacent and in the order they happened.
+    fn cancel_put_then_delete(&mut self) {
+        let mut events: Vec<Option<(ObjId, Event)>> = Vec::with_capacity(self.events.len());
+        let mut by_key: HashMap<(ObjId, String), Vec<usize>> = HashMap::new();
+        for (obj, event) in self.events.drain(..) {
+            let key = match &event {
+                Event::PutMap { key, .. }
+                | Event::IncrementMap { key, .. }
+                | Event::FlagConflictMap { key } => key.clone(),
+                Event::DeleteMap { key, old } => {
+                    let stack = by_key.entry((obj, key.clone())).or_default();
+                    let mut old = old.clone();
+                    let mut visible_before = true;
+                    while let Some(&prev) = stack.last() {
+                        let Some((_, Event::PutMap { .. })) = events[prev] else {
+                            break;
+                        };
+                        stack.pop();
+                        if let Some((
+                            _,
+                            Event::PutMap {
+                                id,
+                                conflict,
+                                old: put_old,
+                                replaced,
+                                ..
+                            },
+                        )) = events[prev].take()
+                        {
+                            self.expose.remove(&id);
+                            old = put_old;
+                            visible_before = conflict || replaced;
+                        }
+                        if !visible_before {
+                            break;
+                        }
+                    }
+                    if visible_before {
+                        stack.push(events.len());
+                        events.push(Some((
+                            obj,
+                            Event::DeleteMap {
+                                key: key.clone(),
+                                old,
+                            },
+                        )));
+                    }
+                    continue;
+                }
+                _ => {
+                    events.push(Some((obj, event)));
+                    continue;
+                }
+            };
+            by_key.entry((obj, key)).or_default().push(events.len());
+            events.push(Some((obj, event)));
+        }
+        self.events = events.into_iter().flatten().collect();
     }
 
     pub(crate) fn truncate(&mut self) {
@@ -422,12 +635,25 @@ impl PatchLog {
         self.expose.clear();
     }
 
+    pub(crate) fn events_len(&self) -> usize {
+        self.events.len()
+    }
+
+    /// Forget the events recorded after the first `events` along with any objects from the
+    /// removed `ops` which were queued for exposure
+    pub(crate) fn rollback_to(&mut self, events: usize, ops: &[OpId]) {
+        self.events.truncate(events);
+        self.expose.retain(|id| !ops.contains(id));
+    }
+
     pub(crate) fn branch(&mut self) -> Self {
         Self {
             active: self.active,
             expose: HashSet::new(),
             events: Default::default(),
             text_rep: self.text_rep,
+            object_filter: self.object_filter.clone(),
+            previous_values: self.previous_values,
             heads: None,
         }
     }
@@ -541,7 +767,7 @@ impl ExposeQueue {
                     if value.is_object() {
                         self.insert(id.clone());
                     }
-                    patch_builder.put(exid.clone(), key.into(), (value, id), conflict);
+                    patch_builder.put(exid.clone(), key.into(), (value, id), conflict, None);
                 }
             }
         }
@@ -0,0 +1,58 @@
+use crate::text_value::TextValue;
+use crate::ScalarValue;
+
+/// A single edit to a text object, as returned by [`crate::ReadDoc::text_diff()`]
+///
+/// Indexes are in the same units as the other text methods and are relative to the text as it is
+/// after all the preceding edits have been applied, so replaying the edits in order on the text
+/// at the `before` heads produces the text at the `after` heads.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TextEdit {
+    /// `text` was inserted at `index`
+    Insert { index: usize, text: String },
+    /// `len` characters were removed starting at `index`
+    Delete { index: usize, len: usize },
+    /// The mark `name` was set to `value` for the `len` characters starting at `index`. A
+    /// [`ScalarValue::Null`] value means the mark was removed.
+    Format {
+        index: usize,
+        len: usize,
+        name: String,
+        value: ScalarValue,
+    },
+}
+
+impl TextEdit {
+    /// Append `edit` to `edits`, merging it into the last edit if they form a single run
+    pub(crate) fn push(edits: &mut Vec<TextEdit>, edit: TextEdit) {
+        match (edits.last_mut(), edit) {
+            (Some(TextEdit::Insert { index, text }), TextEdit::Insert { index: i, text: t })
+                if *index + TextValue::width(text) == i =>
+            {
+                text.push_str(&t);
+            }
+            (Some(TextEdit::Delete { index, len }), TextEdit::Delete { index: i, len: l })
+                if *index == i =>
+            {
+                *len += l;
+            }
+            (
+                Some(TextEdit::Format {
+                    index,
+                    len,
+                    name,
+                    value,
+                }),
+                TextEdit::Format {
+                    index: i,
+                    len: l,
+                    name: n,
+                    value: v,
+                },
+            ) if *index + *len == i && *name == n && *value == v => {
+                *len += l;
+            }
+            (_, edit) => edits.push(edit),
+        }
+    }
+}
@@ -9,14 +9,20 @@ use std::fmt::Debug;
 mod insert;
 mod list_state;
 mod nth;
+mod nth_range;
 mod opid;
 mod seek_mark;
+mod seek_opids;
+mod visible_before;
 
 pub(crate) use insert::InsertNth;
-pub(crate) use list_state::{ListState, RichTextQueryState};
+pub(crate) use list_state::{ListState, QueriedMarks, RichTextQueryState};
 pub(crate) use nth::Nth;
+pub(crate) use nth_range::{NthRange, NthRangeElement};
 pub(crate) use opid::{OpIdSearch, SimpleOpIdSearch};
 pub(crate) use seek_mark::SeekMark;
+pub(crate) use seek_opids::SeekOpIds;
+pub(crate) use visible_before::VisibleBefore;
 
 // use a struct for the args for clarity as they are passed up the update chain in the optree
 #[derive(Debug, Clone)]
@@ -43,6 +49,11 @@ pub(crate) trait TreeQuery<'a>: Clone + Debug {
     fn query_element(&mut self, _op: Op<'a>) -> QueryResult {
         panic!("invalid element query")
     }
+
+    /// The list state to advance when [`Self::query_node`] returns [`QueryResult::SkipNode`]
+    fn list_state_mut(&mut self) -> Option<&mut ListState> {
+        None
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -50,16 +61,26 @@ pub(crate) enum QueryResult {
     Next,
     Descend,
     Finish,
+    /// Don't visit the node or its children and advance the query's
+    /// [`TreeQuery::list_state_mut`] past it. Unlike [`QueryResult::Next`] the query doesn't have
+    /// to update its own state.
+    SkipNode,
 }
 
 #[derive(Clone, Debug, PartialEq)]
 struct TextWidth {
     width: usize,
+    utf16: usize,
+    utf8: usize,
+    code_points: usize,
 }
 
 impl TextWidth {
     fn add_op(&mut self, op: Op<'_>) {
         self.width += op.width(ListEncoding::Text);
+        self.utf16 += op.width(ListEncoding::Utf16);
+        self.utf8 += op.width(ListEncoding::Utf8);
+        self.code_points += op.width(ListEncoding::CodePoints);
     }
 
     fn remove_op(&mut self, op: Op<'_>) {
@@ -91,10 +112,18 @@ impl TextWidth {
         // Really this is a sign that we should be tracking the type of the Index (List or Text) at
         // the type level, but for now we just look the other way.
         self.width = self.width.saturating_sub(op.width(ListEncoding::Text));
+        self.utf16 = self.utf16.saturating_sub(op.width(ListEncoding::Utf16));
+        self.utf8 = self.utf8.saturating_sub(op.width(ListEncoding::Utf8));
+        self.code_points = self
+            .code_points
+            .saturating_sub(op.width(ListEncoding::CodePoints));
     }
 
     fn merge(&mut self, other: &TextWidth) {
         self.width += other.width;
+        self.utf16 += other.utf16;
+        self.utf8 += other.utf8;
+        self.code_points += other.code_points;
     }
 }
 
@@ -120,7 +149,12 @@ impl Index {
     pub(crate) fn new() -> Self {
         Index {
             visible: Default::default(),
-            visible_text: TextWidth { width: 0 },
+            visible_text: TextWidth {
+                width: 0,
+                utf16: 0,
+                utf8: 0,
+                code_points: 0,
+            },
             ops: Default::default(),
             never_seen_puts: true,
             mark_begin: Default::default(),
@@ -134,9 +168,17 @@ impl Index {
         match encoding {
             ListEncoding::List => self.visible.len(),
             ListEncoding::Text => self.visible_text.width,
+            ListEncoding::Utf16 => self.visible_text.utf16,
+            ListEncoding::Utf8 => self.visible_text.utf8,
+            ListEncoding::CodePoints => self.visible_text.code_points,
         }
     }
 
+    /// Whether the op `id` is in this node or below it
+    pub(crate) fn has_op(&self, id: &OpId) -> bool {
+        self.ops.contains(id)
+    }
+
     pub(crate) fn has_visible(&self, seen: &Key) -> bool {
         self.visible.contains_key(seen)
     }
@@ -2,9 +2,7 @@ use crate::error::AutomergeError;
 use crate::marks::MarkSet;
 use crate::op_set::Op;
 use crate::op_tree::OpTreeNode;
-use crate::query::{
-    Index, ListState, OpSetData, OpTree, QueryResult, RichTextQueryState, TreeQuery,
-};
+use crate::query::{Index, ListState, OpSetData, OpTree, QueriedMarks, QueryResult, TreeQuery};
 use crate::types::{Clock, Key, ListEncoding, OpType, HEAD};
 use std::fmt::Debug;
 use std::sync::Arc;
@@ -18,18 +16,6 @@ pub(crate) struct InsertNth<'a> {
     marks: QueriedMarks<'a>,
 }
 
-#[derive(Debug, Clone, PartialEq)]
-enum QueriedMarks<'a> {
-    FromQuery(RichTextQueryState<'a>),
-    FromLastSeen(Arc<MarkSet>),
-}
-
-impl<'a> std::default::Default for QueriedMarks<'a> {
-    fn default() -> Self {
-        QueriedMarks::FromQuery(Default::default())
-    }
-}
-
 #[derive(Clone, PartialEq)]
 struct Loc<'a> {
     key: Key,
@@ -90,10 +76,7 @@ impl<'a> InsertNth<'a> {
     }
 
     pub(crate) fn marks(&self, osd: &OpSetData) -> Option<Arc<MarkSet>> {
-        match self.marks {
-            QueriedMarks::FromQuery(ref state) => MarkSet::from_query_state(state, osd),
-            QueriedMarks::FromLastSeen(ref rt) => Some(rt.clone()),
-        }
+        self.marks.marks(osd)
     }
 
     pub(crate) fn pos(&self) -> usize {
@@ -153,7 +136,7 @@ impl<'a> TreeQuery<'a> for InsertNth<'a> {
             if last.index + last.width == self.list_state.target() {
                 self.candidates.push(Loc::new(last.pos + 1, last.key));
                 if let Some(marks) = &last.marks {
-                    self.marks = QueriedMarks::FromLastSeen(marks.clone());
+                    self.marks = QueriedMarks::FromLastSeen(Some(marks.clone()));
                 }
                 return true;
             }
@@ -169,11 +152,8 @@ impl<'a> TreeQuery<'a> for InsertNth<'a> {
     ) -> QueryResult {
         self.list_state.check_if_node_is_clean(index);
         if self.clock.is_none() {
-            let marks = match &mut self.marks {
-                QueriedMarks::FromQuery(state) => Some(state),
-                QueriedMarks::FromLastSeen(_) => None,
-            };
-            self.list_state.process_node(child, index, osd, marks)
+            self.list_state
+                .process_node(child, index, osd, self.marks.state_mut())
         } else {
             QueryResult::Descend
         }
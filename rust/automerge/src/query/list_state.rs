@@ -1,5 +1,5 @@
 use crate::clock::Clock;
-use crate::marks::MarkData;
+use crate::marks::{MarkData, MarkSet};
 use crate::op_set::{Op, OpSetData};
 use crate::op_tree::{LastInsert, OpTreeNode};
 use crate::query::{Index, QueryResult};
@@ -7,6 +7,7 @@ use crate::types::{Key, ListEncoding, OpId, OpType};
 use crate::ObjType;
 use fxhash::FxBuildHasher;
 use std::collections::HashMap;
+use std::sync::Arc;
 
 #[derive(Debug, Clone, Default, PartialEq)]
 pub(crate) struct RichTextQueryState<'a> {
@@ -35,7 +36,7 @@ impl<'a> RichTextQueryState<'a> {
         }
     }
 
-    pub(crate) fn iter(&self) -> impl Iterator<Item = (&OpId, &&MarkData)> {
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (&OpId, &&'a MarkData)> + '_ {
         self.map.iter()
     }
 
@@ -48,6 +49,36 @@ impl<'a> RichTextQueryState<'a> {
     }
 }
 
+/// The marks found by a query - either accumulated while walking the tree or taken from the
+/// snapshot cached at the last insert
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum QueriedMarks<'a> {
+    FromQuery(RichTextQueryState<'a>),
+    FromLastSeen(Option<Arc<MarkSet>>),
+}
+
+impl<'a> std::default::Default for QueriedMarks<'a> {
+    fn default() -> Self {
+        QueriedMarks::FromQuery(Default::default())
+    }
+}
+
+impl<'a> QueriedMarks<'a> {
+    pub(crate) fn marks(&self, osd: &OpSetData) -> Option<Arc<MarkSet>> {
+        match self {
+            QueriedMarks::FromQuery(state) => MarkSet::from_query_state(state, osd),
+            QueriedMarks::FromLastSeen(marks) => marks.clone(),
+        }
+    }
+
+    pub(crate) fn state_mut(&mut self) -> Option<&mut RichTextQueryState<'a>> {
+        match self {
+            QueriedMarks::FromQuery(state) => Some(state),
+            QueriedMarks::FromLastSeen(_) => None,
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub(crate) struct ListState {
     encoding: ListEncoding,
@@ -57,6 +88,16 @@ pub(crate) struct ListState {
     target: usize,
     index: usize,
     pos: usize,
+    secondary: Option<SecondaryIndex>,
+}
+
+/// The index of the visible elements in a second encoding, counted alongside the one being
+/// searched. Only text objects are counted.
+#[derive(Debug, Clone, PartialEq)]
+struct SecondaryIndex {
+    encoding: ListEncoding,
+    index: usize,
+    last_width: usize,
 }
 
 // There are two indexes being tracked in lists
@@ -74,9 +115,20 @@ impl ListState {
             index: 0,
             pos: 0,
             never_seen_puts: true,
+            secondary: None,
         }
     }
 
+    /// Also count the visible elements in `encoding`, see [`Self::secondary_last_index`]
+    pub(crate) fn with_secondary(mut self, encoding: ListEncoding) -> Self {
+        self.secondary = Some(SecondaryIndex {
+            encoding,
+            index: 0,
+            last_width: 0,
+        });
+        self
+    }
+
     pub(crate) fn was_last_seen(&self, key: Key) -> bool {
         self.last_seen == Some(key)
     }
@@ -132,10 +184,17 @@ impl ListState {
         }
         self.index += num_vis;
         self.pos += node.len();
+        self.advance_secondary(index);
         self.process_marks(index, marks);
         QueryResult::Next
     }
 
+    fn advance_secondary(&mut self, index: &Index) {
+        if let Some(secondary) = self.secondary.as_mut() {
+            secondary.index += index.visible_len(secondary.encoding);
+        }
+    }
+
     fn process_list_node<'a>(
         &mut self,
         node: &'a OpTreeNode,
@@ -143,6 +202,37 @@ impl ListState {
         osd: &OpSetData,
         marks: Option<&mut RichTextQueryState<'a>>,
     ) -> QueryResult {
+        let num_vis = self.list_node_visible(index);
+
+        if self.index + num_vis >= self.target {
+            // if we've reached out target - decend
+            return QueryResult::Descend;
+        }
+
+        self.advance_list_node(node, index, osd, num_vis);
+        self.process_marks(index, marks);
+        QueryResult::Next
+    }
+
+    /// Move past `node` without visiting any of its elements, regardless of the target
+    pub(crate) fn skip_node(&mut self, node: &OpTreeNode, index: &Index, osd: &OpSetData) {
+        if self.encoding == ListEncoding::List {
+            let num_vis = self.list_node_visible(index);
+            self.advance_list_node(node, index, osd, num_vis);
+        } else {
+            self.index += index.visible_len(self.encoding);
+            self.pos += node.len();
+            self.advance_secondary(index);
+        }
+    }
+
+    /// Whether [`Self::skip_node`] can be trusted, text nodes which have seen puts have to be
+    /// descended into, see [`Self::process_node`]
+    pub(crate) fn can_skip_node(&self) -> bool {
+        self.encoding == ListEncoding::List || self.never_seen_puts
+    }
+
+    fn list_node_visible(&self, index: &Index) -> usize {
         let mut num_vis = index.visible.len();
         if let Some(last_seen) = self.last_seen {
             // the elemid `last_seen` is counted in this node's index
@@ -152,12 +242,16 @@ impl ListState {
                 num_vis -= 1;
             }
         }
+        num_vis
+    }
 
-        if self.index + num_vis >= self.target {
-            // if we've reached out target - decend
-            return QueryResult::Descend;
-        }
-
+    fn advance_list_node(
+        &mut self,
+        node: &OpTreeNode,
+        index: &Index,
+        osd: &OpSetData,
+        num_vis: usize,
+    ) {
         self.index += num_vis;
         self.pos += node.len();
 
@@ -174,12 +268,20 @@ impl ListState {
         } else if self.last_seen.is_some() && Some(last_elemid) != self.last_seen {
             self.last_seen = None;
         }
-        self.process_marks(index, marks);
-        QueryResult::Next
     }
 
     pub(crate) fn process_op(&mut self, op: Op<'_>, current: Key, visible: bool) {
         if visible {
+            if let Some(secondary) = self.secondary.as_mut() {
+                let width = op.width(secondary.encoding);
+                if self.never_seen_puts || self.last_seen != Some(current) {
+                    secondary.last_width = width;
+                    secondary.index += width;
+                } else if width != secondary.last_width {
+                    secondary.index = secondary.index + width - secondary.last_width;
+                    secondary.last_width = width;
+                }
+            }
             if self.never_seen_puts {
                 // clean sequnces are simple - only insert and deletes
                 self.last_width = op.width(self.encoding);
@@ -219,6 +321,25 @@ impl ListState {
         self.index - self.last_width
     }
 
+    /// How far through the sequence this state has got, as `(last_index, target)`
+    ///
+    /// This is for reporting the progress of long running queries and has no effect on the search.
+    #[cfg(test)]
+    pub(crate) fn progress(&self) -> (usize, usize) {
+        (self.last_index(), self.target)
+    }
+
+    pub(crate) fn has_secondary(&self) -> bool {
+        self.secondary.is_some()
+    }
+
+    /// The equivalent of [`Self::last_index`] in the encoding passed to [`Self::with_secondary`]
+    pub(crate) fn secondary_last_index(&self) -> Option<usize> {
+        self.secondary
+            .as_ref()
+            .map(|secondary| secondary.index - secondary.last_width)
+    }
+
     pub(crate) fn done(&self) -> bool {
         self.index >= self.target
     }
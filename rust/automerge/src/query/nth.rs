@@ -1,9 +1,11 @@
 use crate::error::AutomergeError;
-use crate::marks::{MarkSet, MarkStateMachine};
+use crate::marks::MarkSet;
 use crate::op_set::Op;
 use crate::op_tree::{OpTree, OpTreeNode};
-use crate::query::{Index, ListState, OpSetData, QueryResult, RichTextQueryState, TreeQuery};
-use crate::types::{Clock, Key, ListEncoding};
+use crate::query::{
+    Index, ListState, OpSetData, QueriedMarks, QueryResult, RichTextQueryState, TreeQuery,
+};
+use crate::types::{ActorId, Clock, Key, ListEncoding};
 use std::fmt::Debug;
 use std::sync::Arc;
 
@@ -13,7 +15,10 @@ use std::sync::Arc;
 pub(crate) struct Nth<'a> {
     list_state: ListState,
     clock: Option<Clock>,
-    marks: Option<RichTextQueryState<'a>>,
+    marks: Option<QueriedMarks<'a>>,
+    actor: Option<ActorFilter<'a>>,
+    /// when set only the number of visible ops is recorded, `ops` and `ops_pos` stay empty
+    count_only: Option<usize>,
     // TODO: put osd in all queries - take out of API
     osd: &'a OpSetData,
     pub(crate) ops: Vec<Op<'a>>,
@@ -31,25 +36,66 @@ impl<'a> Nth<'a> {
             list_state: ListState::new(encoding, target + 1),
             clock,
             marks: None,
+            actor: None,
+            count_only: None,
             osd,
             ops: vec![],
             ops_pos: vec![],
         }
     }
 
+    /// Like [`Nth::new`] but rather than collecting the visible ops at `target` this collects the
+    /// most recent op written by `actor` to that element, whether or not it is the winning op
+    pub(crate) fn new_for_actor(
+        target: usize,
+        encoding: ListEncoding,
+        clock: Option<Clock>,
+        actor: &ActorId,
+        osd: &'a OpSetData,
+    ) -> Self {
+        let mut nth = Self::new(target, encoding, clock, osd);
+        nth.actor = Some(ActorFilter {
+            actor: osd.actors.lookup(actor),
+            key: None,
+            found: None,
+        });
+        nth
+    }
+
+    /// Also work out the index of the target in `encoding`, see [`Nth::secondary_index`]
+    pub(crate) fn with_secondary(mut self, encoding: ListEncoding) -> Self {
+        self.list_state = self.list_state.with_secondary(encoding);
+        self
+    }
+
     pub(crate) fn with_marks(mut self) -> Self {
         self.marks = Some(Default::default());
         self
     }
 
+    /// Only count the visible ops at the target rather than collecting them. Use
+    /// [`Nth::conflict_count`] to read the result
+    pub(crate) fn count_only(mut self) -> Self {
+        self.count_only = Some(0);
+        self
+    }
+
+    /// The number of visible ops at the target, more than one means the element is conflicted
+    pub(crate) fn conflict_count(&self) -> usize {
+        self.count_only.unwrap_or(self.ops.len())
+    }
+
     pub(crate) fn marks(&self) -> Option<Arc<MarkSet>> {
-        let mut marks = MarkStateMachine::default();
-        if let Some(m) = &self.marks {
-            for (id, mark_data) in m.iter() {
-                marks.mark_begin(*id, mark_data, self.osd);
-            }
+        self.marks.as_ref().and_then(|m| m.marks(self.osd))
+    }
+
+    /// The marks open at the target, if they were accumulated while walking the tree rather than
+    /// taken from the cache of the last insert
+    pub(crate) fn into_mark_state(self) -> Option<RichTextQueryState<'a>> {
+        match self.marks {
+            Some(QueriedMarks::FromQuery(state)) => Some(state),
+            _ => None,
         }
-        marks.current().cloned()
     }
 
     /// Get the key
@@ -68,23 +114,48 @@ impl<'a> Nth<'a> {
         self.list_state.last_index()
     }
 
+    /// The index of the target in the encoding passed to [`Nth::with_secondary`]
+    pub(crate) fn secondary_index(&self) -> Option<usize> {
+        self.list_state.secondary_last_index()
+    }
+
     pub(crate) fn pos(&self) -> usize {
         self.list_state.pos()
     }
+
+    /// See [`ListState::progress`], the target is one past the index being searched for
+    #[cfg(test)]
+    pub(crate) fn progress(&self) -> (usize, usize) {
+        self.list_state.progress()
+    }
+
+    fn push(&mut self, op: Op<'a>, pos: usize) {
+        if let Some(count) = self.count_only.as_mut() {
+            *count += 1;
+        } else {
+            self.ops.push(op);
+            self.ops_pos.push(pos);
+        }
+    }
 }
 
 impl<'a> TreeQuery<'a> for Nth<'a> {
     fn can_shortcut_search(&mut self, tree: &'a OpTree, osd: &'a OpSetData) -> bool {
-        if self.marks.is_some() {
-            // we could cache marks data but we're not now
+        if self.actor.is_some() || self.list_state.has_secondary() {
+            return false;
+        }
+        if self.marks.is_some() && self.clock.is_some() {
+            // the cached marks are only valid for the current state of the document
             return false;
         }
         if let Some(last) = &tree.last_insert {
             if last.index == self.list_state.target().saturating_sub(1) {
                 if let Some(idx) = tree.internal.get(last.pos) {
+                    if self.marks.is_some() {
+                        self.marks = Some(QueriedMarks::FromLastSeen(last.marks.clone()));
+                    }
                     self.list_state.seek(last);
-                    self.ops.push(idx.as_op(osd));
-                    self.ops_pos.push(last.pos);
+                    self.push(idx.as_op(osd), last.pos);
                     return true;
                 }
             }
@@ -99,9 +170,15 @@ impl<'a> TreeQuery<'a> for Nth<'a> {
         osd: &OpSetData,
     ) -> QueryResult {
         self.list_state.check_if_node_is_clean(index);
-        if self.clock.is_none() {
-            self.list_state
-                .process_node(child, index, osd, self.marks.as_mut())
+        // the ops of the target element can span nodes, skipping a node could hide the ops the
+        // actor filter is looking for
+        if self.clock.is_none() && self.actor.is_none() {
+            self.list_state.process_node(
+                child,
+                index,
+                osd,
+                self.marks.as_mut().and_then(|m| m.state_mut()),
+            )
         } else {
             QueryResult::Descend
         }
@@ -111,17 +188,48 @@ impl<'a> TreeQuery<'a> for Nth<'a> {
         if op.insert() && self.list_state.done() {
             QueryResult::Finish
         } else {
-            if let Some(m) = self.marks.as_mut() {
+            if let Some(m) = self.marks.as_mut().and_then(|m| m.state_mut()) {
                 m.process(op, self.clock.as_ref())
             }
             let visible = op.visible_at(self.clock.as_ref());
             let key = op.elemid_or_key();
+            if let Some(filter) = self.actor.as_mut() {
+                filter.process(op, key, self.list_state.pos(), self.clock.as_ref());
+                self.list_state.process_op(op, key, visible);
+                if self.list_state.done() {
+                    if let Some((op, pos)) = filter.found {
+                        self.ops = vec![op];
+                        self.ops_pos = vec![pos];
+                    }
+                }
+                return QueryResult::Next;
+            }
             self.list_state.process_op(op, key, visible);
             if visible && self.list_state.done() {
-                self.ops.push(op);
-                self.ops_pos.push(self.list_state.pos().saturating_sub(1));
+                self.push(op, self.list_state.pos().saturating_sub(1));
             }
             QueryResult::Next
         }
     }
 }
+
+/// Tracks the most recent op by a single actor for the element currently being visited
+#[derive(Debug, Clone)]
+struct ActorFilter<'a> {
+    actor: Option<usize>,
+    key: Option<Key>,
+    found: Option<(Op<'a>, usize)>,
+}
+
+impl<'a> ActorFilter<'a> {
+    fn process(&mut self, op: Op<'a>, key: Key, pos: usize, clock: Option<&Clock>) {
+        if self.key != Some(key) {
+            self.key = Some(key);
+            self.found = None;
+        }
+        // ops for an element are sorted by id so the last match is the most recent
+        if Some(op.id().actor()) == self.actor && clock.map(|c| c.covers(op.id())).unwrap_or(true) {
+            self.found = Some((op, pos));
+        }
+    }
+}
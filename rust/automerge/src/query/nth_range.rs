@@ -0,0 +1,103 @@
+use crate::marks::{MarkSet, MarkStateMachine};
+use crate::op_set::Op;
+use crate::op_tree::OpTreeNode;
+use crate::query::{Index, ListState, OpSetData, QueryResult, TreeQuery};
+use crate::types::{Clock, Key, ListEncoding};
+use std::fmt::Debug;
+use std::sync::Arc;
+
+/// The NthRange query walks the tree collecting the visible elements with an index in
+/// `start..end`. Like [`super::Nth`] it skips the parts of the tree before `start` and it stops
+/// as soon as it reaches the first element past `end`
+#[derive(Debug, Clone)]
+pub(crate) struct NthRange<'a> {
+    list_state: ListState,
+    start: usize,
+    end: usize,
+    clock: Option<Clock>,
+    marks: MarkStateMachine<'a>,
+    osd: &'a OpSetData,
+    pub(crate) elements: Vec<NthRangeElement<'a>>,
+}
+
+/// A single visible element found by [`NthRange`]
+#[derive(Debug, Clone)]
+pub(crate) struct NthRangeElement<'a> {
+    pub(crate) key: Key,
+    pub(crate) index: usize,
+    /// The winning op for this element
+    pub(crate) op: Op<'a>,
+    pub(crate) conflict: bool,
+    pub(crate) marks: Option<Arc<MarkSet>>,
+}
+
+impl<'a> NthRange<'a> {
+    pub(crate) fn new(
+        start: usize,
+        end: usize,
+        encoding: ListEncoding,
+        clock: Option<Clock>,
+        osd: &'a OpSetData,
+    ) -> Self {
+        NthRange {
+            list_state: ListState::new(encoding, start + 1),
+            start,
+            end,
+            clock,
+            marks: Default::default(),
+            osd,
+            elements: vec![],
+        }
+    }
+}
+
+impl<'a> TreeQuery<'a> for NthRange<'a> {
+    fn query_node(
+        &mut self,
+        child: &'a OpTreeNode,
+        index: &'a Index,
+        osd: &OpSetData,
+    ) -> QueryResult {
+        self.list_state.check_if_node_is_clean(index);
+        if self.clock.is_none() {
+            self.list_state.process_node(child, index, osd, None)
+        } else {
+            QueryResult::Descend
+        }
+    }
+
+    fn query_element(&mut self, op: Op<'a>) -> QueryResult {
+        if op.insert() && self.list_state.index() >= self.end {
+            return QueryResult::Finish;
+        }
+        // marks are only tracked when reading at a clock, matching `TopOps`
+        if let Some(c) = &self.clock {
+            if c.covers(op.id()) {
+                self.marks.process(*op.id(), op.action(), self.osd);
+            }
+        }
+        let visible = op.visible_at(self.clock.as_ref());
+        let key = op.elemid_or_key();
+        self.list_state.process_op(op, key, visible);
+        if visible && self.list_state.last_index() >= self.start {
+            let index = self.list_state.last_index();
+            let marks = self.marks.current().cloned();
+            match self.elements.last_mut() {
+                Some(e) if e.key == key => {
+                    e.index = index;
+                    e.op = op;
+                    e.conflict = true;
+                    e.marks = marks;
+                }
+                _ => self.elements.push(NthRangeElement {
+                    key,
+                    index,
+                    op,
+                    conflict: false,
+                    marks,
+                }),
+            }
+        }
+        QueryResult::Next
+    }
+}
@@ -109,7 +109,7 @@ impl<'a> TreeQuery<'a> for OpIdSearch<'a> {
             QueryResult::Descend
         } else {
             match &self.target {
-                SearchTarget::OpId(id, _) if !index.ops.contains(id) => {
+                SearchTarget::OpId(id, _) if !index.has_op(id) => {
                     self.list_state
                         .process_node(child, index, osd, Some(&mut self.marks));
                     QueryResult::Next
@@ -0,0 +1,73 @@
+use crate::op_set::{Op, OpSetData};
+use crate::op_tree::OpTreeNode;
+use crate::query::{Index, ListState, QueryResult, TreeQuery};
+use crate::types::{Clock, ListEncoding, OpId};
+use std::collections::HashMap;
+
+/// Find the index of the elements inserted by several ops in one walk of the tree. Nodes which
+/// don't contain any of the ops are skipped as in [`crate::query::OpIdSearch`].
+#[derive(Debug, Clone)]
+pub(crate) struct SeekOpIds<'a> {
+    list_state: ListState,
+    clock: Option<&'a Clock>,
+    /// The ops still to be found, with the positions in `found` to record their index at
+    remaining: HashMap<OpId, Vec<usize>>,
+    found: Vec<Option<usize>>,
+}
+
+impl<'a> SeekOpIds<'a> {
+    pub(crate) fn new(targets: &[OpId], encoding: ListEncoding, clock: Option<&'a Clock>) -> Self {
+        let mut remaining: HashMap<OpId, Vec<usize>> = HashMap::new();
+        for (i, id) in targets.iter().enumerate() {
+            remaining.entry(*id).or_default().push(i);
+        }
+        SeekOpIds {
+            list_state: ListState::new(encoding, usize::MAX),
+            clock,
+            remaining,
+            found: vec![None; targets.len()],
+        }
+    }
+
+    /// The index of the element inserted by each target, in the order the targets were given, or
+    /// [`None`] if the target is not in the tree
+    pub(crate) fn found(self) -> Vec<Option<usize>> {
+        self.found
+    }
+}
+
+impl<'a> TreeQuery<'a> for SeekOpIds<'a> {
+    fn query_node(
+        &mut self,
+        child: &'a OpTreeNode,
+        index: &'a Index,
+        osd: &'a OpSetData,
+    ) -> QueryResult {
+        self.list_state.check_if_node_is_clean(index);
+        if self.clock.is_some() || self.remaining.keys().any(|id| index.has_op(id)) {
+            QueryResult::Descend
+        } else {
+            self.list_state.process_node(child, index, osd, None)
+        }
+    }
+
+    fn query_element(&mut self, op: Op<'a>) -> QueryResult {
+        if let Some(positions) = self.remaining.remove(op.id()) {
+            let idx = if self.list_state.was_last_seen(op.elemid_or_key()) {
+                self.list_state.last_index()
+            } else {
+                self.list_state.index()
+            };
+            for i in positions {
+                self.found[i] = Some(idx);
+            }
+            if self.remaining.is_empty() {
+                return QueryResult::Finish;
+            }
+        }
+        let key = op.elemid_or_key();
+        self.list_state
+            .process_op(op, key, op.visible_at(self.clock));
+        QueryResult::Next
+    }
+}
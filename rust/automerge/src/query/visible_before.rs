@@ -0,0 +1,70 @@
+use crate::op_set::{Op, OpSetData};
+use crate::op_tree::OpTreeNode;
+use crate::query::{Index, ListState, QueryResult, TreeQuery};
+use crate::types::{Clock, ListEncoding};
+
+/// Count the visible elements before the op at a position in the tree. This is the [`ListState`]
+/// accounting of [`crate::query::Nth`] but it stops at an op position rather than at an index.
+#[derive(Debug, Clone)]
+pub(crate) struct VisibleBefore {
+    list_state: ListState,
+    clock: Option<Clock>,
+    target_pos: usize,
+    found: Option<usize>,
+}
+
+impl VisibleBefore {
+    pub(crate) fn new(target_pos: usize, encoding: ListEncoding, clock: Option<Clock>) -> Self {
+        VisibleBefore {
+            list_state: ListState::new(encoding, usize::MAX),
+            clock,
+            target_pos,
+            found: None,
+        }
+    }
+
+    /// The index of the element the op at the target position belongs to, or the visible length
+    /// of the sequence if the target is past the last op
+    pub(crate) fn count(&self) -> usize {
+        self.found.unwrap_or_else(|| self.list_state.index())
+    }
+}
+
+impl<'a> TreeQuery<'a> for VisibleBefore {
+    fn query_node(
+        &mut self,
+        child: &'a OpTreeNode,
+        index: &'a Index,
+        _osd: &'a OpSetData,
+    ) -> QueryResult {
+        self.list_state.check_if_node_is_clean(index);
+        if self.clock.is_none()
+            && self.list_state.pos() + child.len() <= self.target_pos
+            && self.list_state.can_skip_node()
+        {
+            QueryResult::SkipNode
+        } else {
+            QueryResult::Descend
+        }
+    }
+
+    fn query_element(&mut self, op: Op<'a>) -> QueryResult {
+        let key = op.elemid_or_key();
+        if self.list_state.pos() >= self.target_pos {
+            // the op may be a later op for an element which has already been counted
+            self.found = Some(if self.list_state.was_last_seen(key) {
+                self.list_state.last_index()
+            } else {
+                self.list_state.index()
+            });
+            return QueryResult::Finish;
+        }
+        let visible = op.visible_at(self.clock.as_ref());
+        self.list_state.process_op(op, key, visible);
+        QueryResult::Next
+    }
+
+    fn list_state_mut(&mut self) -> Option<&mut ListState> {
+        Some(&mut self.list_state)
+    }
+}
@@ -3,13 +3,20 @@ use crate::{
     exid::ExId,
     hydrate,
     iter::Spans,
-    iter::{Keys, ListRange, MapRange, Values},
-    marks::{Mark, MarkSet},
+    iter::{
+        AllMarks, Entries, Keys, KeysWithConflicts, ListRange, ListRangeRev, MapRange, Objects,
+        Values, ValuesRef,
+    },
+    marks::{Mark, MarkSet, RichText},
     parents::Parents,
-    Change, ChangeHash, Cursor, ObjType, Prop, Value,
+    patches::TextEdit,
+    ActorId, Change, ChangeHash, Cursor, ObjType, ObjView, Prop, ScalarValue, Value,
 };
 
-use std::{collections::HashMap, ops::RangeBounds};
+use std::{
+    collections::HashMap,
+    ops::{Range, RangeBounds},
+};
 
 /// Methods for reading values from an automerge document
 ///
@@ -44,7 +51,8 @@ pub trait ReadDoc {
 
     /// Get the keys of the object `obj`.
     ///
-    /// For a map this returns the keys of the map.
+    /// For a map this returns the keys of the map, in lexicographic order of their UTF-8 bytes,
+    /// so every replica with the same changes sees the same order.
     /// For a list this returns the element ids (opids) encoded as strings.
     fn keys<O: AsRef<ExId>>(&self, obj: O) -> Keys<'_>;
 
@@ -53,6 +61,52 @@ pub trait ReadDoc {
     /// See [`Self::keys()`]
     fn keys_at<O: AsRef<ExId>>(&self, obj: O, heads: &[ChangeHash]) -> Keys<'_>;
 
+    /// Get the keys of the object `obj` along with the number of conflicting values for each key
+    ///
+    /// The keys are in the same order as [`Self::keys()`]. A key with a single value has a count
+    /// of 1, a key with concurrent values has a count of the number of values which
+    /// [`Self::get_all()`] would return. For a sequence the keys are the indices of the elements.
+    fn keys_with_conflicts<O: AsRef<ExId>>(&self, obj: O) -> KeysWithConflicts<'_>;
+
+    /// Get the keys of the map `obj` in the order they were first set, rather than sorted as
+    /// [`Self::keys()`] returns them
+    ///
+    /// A key which was deleted and set again keeps its original position. Keys first set
+    /// concurrently are ordered by the actor of the op which set them, so every replica agrees
+    /// on the order. If `obj` is not a map this returns an empty `Vec`.
+    fn map_keys_in_insertion_order<O: AsRef<ExId>>(&self, obj: O) -> Vec<String>;
+
+    /// The keys and values of the map `obj`, sorted by the key `key_fn` derives from each value
+    ///
+    /// This reads the whole map and then sorts it. Values with equal sort keys are in the order of
+    /// their map keys, the order of [`Self::keys()`], so the result is the same on every replica.
+    /// Where a key has conflicting values only the winning value, as returned by
+    /// [`Self::get()`], is included.
+    ///
+    /// ```
+    /// # use automerge::{transaction::Transactable, AutoCommit, Prop, ReadDoc, ROOT};
+    /// let mut doc = AutoCommit::new();
+    /// doc.put(ROOT, "b", 3).unwrap();
+    /// doc.put(ROOT, "a", 7).unwrap();
+    /// doc.put(ROOT, "c", 3).unwrap();
+    /// let sorted = doc
+    ///     .map_values_sorted_by(ROOT, |v| v.to_i64(), None)
+    ///     .unwrap();
+    /// let keys: Vec<_> = sorted.into_iter().map(|(prop, _)| prop).collect();
+    /// assert_eq!(keys, vec![Prop::from("b"), Prop::from("c"), Prop::from("a")]);
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// * [`AutomergeError::InvalidObjId`] if `obj` is not an object in the document
+    /// * [`AutomergeError::InvalidOp`] if `obj` is a sequence
+    fn map_values_sorted_by<O: AsRef<ExId>, K: Ord, F: Fn(&Value<'_>) -> K>(
+        &self,
+        obj: O,
+        key_fn: F,
+        heads: Option<&[ChangeHash]>,
+    ) -> Result<Vec<(Prop, Value<'_>)>, AutomergeError>;
+
     /// Iterate over the keys and values of the map `obj` in the given range.
     ///
     /// If the object correspoding to `obj` is a list then this will return an empty iterator
@@ -104,6 +158,103 @@ pub trait ReadDoc {
         heads: &[ChangeHash],
     ) -> ListRange<'_, R>;
 
+    /// The indices of the elements of the list or text `obj` whose value satisfies `pred`
+    ///
+    /// This walks the sequence once, like [`Self::list_range()`], without collecting the values.
+    /// The indices are in ascending order and, as with [`Self::list_range()`], count each
+    /// element of a text object as one.
+    ///
+    /// ```
+    /// # use automerge::{transaction::Transactable, AutoCommit, ObjType, ReadDoc, ROOT};
+    /// let mut doc = AutoCommit::new();
+    /// let list = doc.put_object(ROOT, "list", ObjType::List).unwrap();
+    /// for (i, n) in [3, 8, 1, 9].into_iter().enumerate() {
+    ///     doc.insert(&list, i, n).unwrap();
+    /// }
+    /// let big = doc
+    ///     .find_indices(&list, |v| v.to_i64().unwrap_or(0) > 5, None)
+    ///     .unwrap();
+    /// assert_eq!(big, vec![1, 3]);
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// * [`AutomergeError::InvalidObjId`] if `obj` is not an object in the document
+    /// * [`AutomergeError::InvalidOp`] if `obj` is not a sequence
+    fn find_indices<O: AsRef<ExId>, F: Fn(&Value<'_>) -> bool>(
+        &self,
+        obj: O,
+        pred: F,
+        heads: Option<&[ChangeHash]>,
+    ) -> Result<Vec<usize>, AutomergeError>;
+
+    /// The first and last visible elements of the list or text `obj` with their indices, or
+    /// `None` if it is empty
+    ///
+    /// Both ends are found in one walk of the sequence. As with [`Self::list_range()`] each
+    /// element of a text object counts as one. If the sequence has one element it is both the
+    /// first and the last.
+    ///
+    /// ```
+    /// # use automerge::{transaction::Transactable, AutoCommit, ObjType, ReadDoc, ROOT};
+    /// let mut doc = AutoCommit::new();
+    /// let list = doc.put_object(ROOT, "list", ObjType::List).unwrap();
+    /// assert_eq!(doc.list_endpoints(&list, None).unwrap(), None);
+    /// for (i, n) in [3, 8, 1].into_iter().enumerate() {
+    ///     doc.insert(&list, i, n).unwrap();
+    /// }
+    /// let ((first, a), (last, b)) = doc.list_endpoints(&list, None).unwrap().unwrap();
+    /// assert_eq!((first, a.to_i64(), last, b.to_i64()), (0, Some(3), 2, Some(1)));
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// * [`AutomergeError::InvalidObjId`] if `obj` is not an object in the document
+    /// * [`AutomergeError::InvalidOp`] if `obj` is not a sequence
+    fn list_endpoints<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        heads: Option<&[ChangeHash]>,
+    ) -> Result<Option<(ListEndpoint<'_>, ListEndpoint<'_>)>, AutomergeError>;
+
+    /// Iterate over the indexes and values of the list or text `obj` in the given range, starting
+    /// from the end of the range
+    ///
+    /// This yields the same items as [`Self::list_range()`] in descending order of index.
+    fn list_range_rev<O: AsRef<ExId>, R: RangeBounds<usize>>(
+        &self,
+        obj: O,
+        range: R,
+    ) -> ListRangeRev<'_>;
+
+    /// Iterate over the indexes and values of the list or text `obj` in the given range as at
+    /// `heads`, starting from the end of the range
+    ///
+    /// See [`Self::list_range_rev()`]
+    fn list_range_rev_at<O: AsRef<ExId>, R: RangeBounds<usize>>(
+        &self,
+        obj: O,
+        range: R,
+        heads: &[ChangeHash],
+    ) -> ListRangeRev<'_>;
+
+    /// Read the bytes in `range` of a list written with
+    /// [`crate::transaction::Transactable::splice_bytes()`]
+    ///
+    /// Each byte is an element of the list, so this reads every element in `range` and copies it
+    /// into the returned buffer.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AutomergeError::InvalidOp`] if `obj` is not a list and
+    /// [`AutomergeError::InvalidValueType`] if any element in the range is not a
+    /// [`crate::ScalarValue::Uint`] which fits in a byte.
+    fn read_bytes<O: AsRef<ExId>, R: RangeBounds<usize>>(
+        &self,
+        obj: O,
+        range: R,
+    ) -> Result<Vec<u8>, AutomergeError>;
+
     /// Iterate over the values in a map, list, or text object
     ///
     /// The returned iterator yields `(value, exid)` tuples, where the second element
@@ -118,31 +269,157 @@ pub trait ReadDoc {
     /// See [`Self::values()`]
     fn values_at<O: AsRef<ExId>>(&self, obj: O, heads: &[ChangeHash]) -> Values<'_>;
 
+    /// Iterate over the properties of a map, list, or text object along with their values
+    ///
+    /// The returned iterator yields `(prop, value, exid)` tuples. For sequences the prop is the
+    /// index of the element. See [`Entries`].
+    fn entries<O: AsRef<ExId>>(&self, obj: O) -> Entries<'_>;
+
+    /// Iterate over the properties of a map, list, or text object along with their values as at
+    /// `heads`
+    ///
+    /// This computes the state at `heads` once rather than for every property as repeated calls
+    /// to [`Self::get_at()`] would. Properties which were deleted as at `heads` are skipped.
+    ///
+    /// See [`Self::entries()`]
+    fn entries_at<O: AsRef<ExId>>(&self, obj: O, heads: &[ChangeHash]) -> Entries<'_>;
+
+    /// Iterate over the values in a map, list, or text object without cloning them
+    ///
+    /// This yields the same values as [`Self::values()`] but scalars are borrowed from the
+    /// document and only objects come with an id. Use this to scan large objects.
+    fn values_ref<O: AsRef<ExId>>(&self, obj: O) -> ValuesRef<'_>;
+
+    /// Iterate over every visible object in the document, starting from the root
+    ///
+    /// Each item is the id of the object, its type and the id of the object which contains it,
+    /// which is [`None`] for the root. The order is deterministic, see [`Objects`]. Only the
+    /// winning value of a conflicted property is followed, as with [`Self::values()`].
+    fn objects(&self) -> Objects<'_>;
+
     /// Get the length of the given object.
     ///
     /// If the given object is not in this document this method will return `0`
+    ///
+    /// For lists and for text which has only been edited by splicing the length is read from
+    /// the visible length cached in the op tree and so takes constant time.
     fn length<O: AsRef<ExId>>(&self, obj: O) -> usize;
 
     /// Get the length of the given object as at `heads`
     ///
-    /// If the given object is not in this document this method will return `0`
+    /// If the given object is not in this document this method will return `0`. If `heads` are
+    /// the current heads of the document this is as fast as [`Self::length()`], otherwise the
+    /// object has to be walked.
     ///
     /// See [`Self::length()`]
     fn length_at<O: AsRef<ExId>>(&self, obj: O, heads: &[ChangeHash]) -> usize;
 
+    /// Get the length of the text object `obj` in UTF-16 code units, as a JavaScript string
+    /// would measure it
+    ///
+    /// Like [`Self::length()`] this is read from the widths cached in the op tree, so it doesn't
+    /// build the string. For objects which are not text this is the same as [`Self::length()`].
+    ///
+    /// ```
+    /// # use automerge::{transaction::Transactable, AutoCommit, ObjType, ReadDoc, ROOT};
+    /// let mut doc = AutoCommit::new();
+    /// let text = doc.put_object(ROOT, "text", ObjType::Text).unwrap();
+    /// doc.splice_text(&text, 0, 0, "a😀").unwrap();
+    /// assert_eq!(doc.length_utf16(&text), 3);
+    /// ```
+    fn length_utf16<O: AsRef<ExId>>(&self, obj: O) -> usize;
+
+    /// Get the length of the text object `obj` in extended grapheme clusters, i.e. the
+    /// characters a user would see
+    ///
+    /// Grapheme boundaries depend on the neighbouring characters so this has to segment the
+    /// visible text, which takes time linear in its length. For objects which are not text this
+    /// is the same as [`Self::length()`].
+    ///
+    /// ```
+    /// # use automerge::{transaction::Transactable, AutoCommit, ObjType, ReadDoc, ROOT};
+    /// let mut doc = AutoCommit::new();
+    /// let text = doc.put_object(ROOT, "text", ObjType::Text).unwrap();
+    /// doc.splice_text(&text, 0, 0, "e\u{301}👍🏽").unwrap();
+    /// assert_eq!(doc.length_grapheme(&text), 2);
+    /// ```
+    fn length_grapheme<O: AsRef<ExId>>(&self, obj: O) -> usize;
+
     /// Get the type of this object, if it is an object.
     fn object_type<O: AsRef<ExId>>(&self, obj: O) -> Result<ObjType, AutomergeError>;
 
+    /// Get the type of the object `obj` as at `heads`
+    ///
+    /// Returns `Ok(None)` if `obj` is an object in this document which had not been created as at
+    /// `heads`, and an error if it is not an object in this document at all.
+    ///
+    /// See [`Self::object_type()`]
+    fn object_type_at<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        heads: &[ChangeHash],
+    ) -> Result<Option<ObjType>, AutomergeError>;
+
     /// Get all marks on a current sequence
+    ///
+    /// Unlike [`Self::spans()`] this returns a flat list of marks, each with a `start`, `end`,
+    /// name and value, ordered by name and then by position. Adjacent ranges with the same name
+    /// and value are merged into one mark, and ranges which cover no characters or which have
+    /// been unmarked are omitted, so applying each returned mark to the same text with
+    /// [`crate::transaction::Transactable::mark()`] reproduces the formatting.
     fn marks<O: AsRef<ExId>>(&self, obj: O) -> Result<Vec<Mark<'_>>, AutomergeError>;
 
     /// Get all marks on a sequence at a given heads
+    ///
+    /// See [`Self::marks()`]
     fn marks_at<O: AsRef<ExId>>(
         &self,
         obj: O,
         heads: &[ChangeHash],
     ) -> Result<Vec<Mark<'_>>, AutomergeError>;
 
+    /// Get the marks of every current sequence in the document
+    ///
+    /// Each item is the id of a text or list object and its marks, as [`Self::marks()`] would
+    /// return them. Objects are in the order [`Self::objects()`] visits them and objects with no
+    /// marks are left out. This is useful to find, for example, every comment in a document.
+    ///
+    /// ```
+    /// # use automerge::{transaction::Transactable, AutoCommit, ObjType, ReadDoc, ROOT};
+    /// # use automerge::marks::{ExpandMark, Mark};
+    /// let mut doc = AutoCommit::new();
+    /// let plain = doc.put_object(ROOT, "plain", ObjType::Text).unwrap();
+    /// doc.splice_text(&plain, 0, 0, "nothing here").unwrap();
+    /// let notes = doc.put_object(ROOT, "notes", ObjType::Map).unwrap();
+    /// let text = doc.put_object(&notes, "text", ObjType::Text).unwrap();
+    /// doc.splice_text(&text, 0, 0, "hello world").unwrap();
+    /// doc.mark(&text, Mark::new("comment".to_string(), "check this", 0, 5), ExpandMark::None)
+    ///     .unwrap();
+    ///
+    /// let all = doc.all_marks().collect::<Vec<_>>();
+    /// assert_eq!(all.len(), 1);
+    /// assert_eq!(all[0].0, text);
+    /// assert_eq!(all[0].1[0].name(), "comment");
+    /// ```
+    fn all_marks(&self) -> AllMarks<'_>;
+
+    /// Get the marks of every sequence in the document as at `heads`
+    ///
+    /// See [`Self::all_marks()`]
+    fn all_marks_at(&self, heads: &[ChangeHash]) -> AllMarks<'_>;
+
+    /// Get every mark on the sequence `obj` which overlaps `range`, clipped to `range`
+    ///
+    /// This is the same as [`Self::marks()`] restricted to `range`, except that marks which
+    /// cover no characters are also returned if they are inside the range or at either end of
+    /// it. Unlike calling [`Self::get_marks()`] for each index this walks the sequence once.
+    fn marks_in_range<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        range: Range<usize>,
+        heads: Option<&[ChangeHash]>,
+    ) -> Result<Vec<Mark<'_>>, AutomergeError>;
+
     fn get_marks<O: AsRef<ExId>>(
         &self,
         obj: O,
@@ -150,6 +427,18 @@ pub trait ReadDoc {
         heads: Option<&[ChangeHash]>,
     ) -> Result<MarkSet, AutomergeError>;
 
+    /// Get the marks which apply to the character at `cursor`
+    ///
+    /// If the element the cursor points at has been deleted and there are no visible characters
+    /// after it then this returns the marks a character inserted at the end of the sequence
+    /// would get.
+    fn text_at_cursor<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        cursor: &Cursor,
+        heads: Option<&[ChangeHash]>,
+    ) -> Result<MarkSet, AutomergeError>;
+
     /// Get the string represented by the given text object.
     fn text<O: AsRef<ExId>>(&self, obj: O) -> Result<String, AutomergeError>;
 
@@ -161,6 +450,23 @@ pub trait ReadDoc {
         heads: &[ChangeHash],
     ) -> Result<String, AutomergeError>;
 
+    /// Get the edits which turn the text object `obj` as at `before` into the text as at
+    /// `after`
+    ///
+    /// Consecutive inserts, deletes and formatting changes are coalesced into runs, see
+    /// [`TextEdit`] for how to apply them. Changes to block markers are not included.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AutomergeError::InvalidOp`] if `obj` is not a text object and
+    /// [`AutomergeError::MissingHash`] if any of the heads are not in this document.
+    fn text_diff<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        before: &[ChangeHash],
+        after: &[ChangeHash],
+    ) -> Result<Vec<TextEdit>, AutomergeError>;
+
     /// Return the sequence of text and block markers in the text object `obj`
     fn spans<O: AsRef<ExId>>(&self, obj: O) -> Result<Spans<'_>, AutomergeError>;
 
@@ -171,12 +477,89 @@ pub trait ReadDoc {
         heads: &[ChangeHash],
     ) -> Result<Spans<'_>, AutomergeError>;
 
+    /// Return the text and block markers in `range` of the text object `obj`, optionally as at
+    /// `heads`
+    ///
+    /// This only visits the part of the object inside `range`, so it is much cheaper than
+    /// [`Self::spans()`] for rendering a small window of a large document. Text which straddles
+    /// the ends of the range is cut at the range boundaries and marks which begin before
+    /// `range.start` are included on the first span.
+    fn spans_range<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        range: Range<usize>,
+        heads: Option<&[ChangeHash]>,
+    ) -> Result<Spans<'_>, AutomergeError>;
+
+    /// Copy the text in `range` of the text object `obj` along with its marks, optionally as at
+    /// `heads`
+    ///
+    /// Marks which only partly overlap `range` are clipped to it. Insert the result into a text
+    /// object, in this document or another, with
+    /// [`crate::transaction::Transactable::insert_rich_text()`].
+    ///
+    /// ```
+    /// # use automerge::{marks::{ExpandMark, Mark}, transaction::Transactable, AutoCommit, ObjType, ReadDoc, ROOT};
+    /// let mut doc = AutoCommit::new();
+    /// let text = doc.put_object(ROOT, "text", ObjType::Text).unwrap();
+    /// doc.splice_text(&text, 0, 0, "hello world").unwrap();
+    /// doc.mark(&text, Mark::new("bold".to_string(), true, 0, 5), ExpandMark::None).unwrap();
+    /// let copied = doc.export_rich_text(&text, 3..8, None).unwrap();
+    /// assert_eq!(copied.text(), "lo wo");
+    ///
+    /// let mut other = AutoCommit::new();
+    /// let pasted = other.put_object(ROOT, "text", ObjType::Text).unwrap();
+    /// other.insert_rich_text(&pasted, 0, &copied).unwrap();
+    /// let marks = other.marks(&pasted).unwrap();
+    /// assert_eq!((marks[0].name(), marks[0].start, marks[0].end), ("bold", 0, 2));
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AutomergeError::InvalidOp`] if `obj` is not a text object
+    fn export_rich_text<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        range: Range<usize>,
+        heads: Option<&[ChangeHash]>,
+    ) -> Result<RichText, AutomergeError>;
+
+    /// Parse a string created by [`ExId::to_stable_string()`] into an object ID for this document
+    ///
+    /// # Errors
+    ///
+    /// * [`AutomergeError::InvalidObjIdFormat`] if `s` is not a valid encoding of an object ID
+    /// * [`AutomergeError::InvalidObjId`] if the object `s` refers to is not in this document
+    fn import_exid(&self, s: &str) -> Result<ExId, AutomergeError>;
+
+    /// Convert a character index in the text object `obj`, as used by [`Self::text()`] and
+    /// [`crate::transaction::Transactable::splice_text()`], to an offset in UTF-16 code units
+    ///
+    /// ### Errors
+    ///
+    /// * [`AutomergeError::InvalidOp`] if `obj` is not a text object
+    /// * [`AutomergeError::InvalidIndex`] if `index` is past the end of the text
+    fn char_to_utf16<O: AsRef<ExId>>(&self, obj: O, index: usize) -> Result<usize, AutomergeError>;
+
+    /// Convert an offset in UTF-16 code units in the text object `obj` to a character index, the
+    /// inverse of [`Self::char_to_utf16()`]
+    ///
+    /// ### Errors
+    ///
+    /// * [`AutomergeError::InvalidOp`] if `obj` is not a text object
+    /// * [`AutomergeError::InvalidIndex`] if `index` is past the end of the text or is in the
+    ///   middle of a surrogate pair
+    fn utf16_to_char<O: AsRef<ExId>>(&self, obj: O, index: usize) -> Result<usize, AutomergeError>;
+
     /// Obtain the stable address (Cursor) for a [`usize`] position in a Sequence (either [`ObjType::List`] or [`ObjType::Text`]).
     ///
     /// Example use cases:
     /// 1. User cursor tracking, to maintain contextual position while merging remote changes.
     /// 2. Indexing sentences in a text field.
     ///
+    /// A `position` equal to the length of the sequence returns [`Cursor::end()`], which keeps
+    /// pointing at the end of the sequence as elements are appended.
+    ///
     /// To reverse the operation, see [`Self::get_cursor_position()`].
     fn get_cursor<O: AsRef<ExId>>(
         &self,
@@ -197,6 +580,175 @@ pub trait ReadDoc {
         at: Option<&[ChangeHash]>,
     ) -> Result<usize, AutomergeError>;
 
+    /// Translate several [`Cursor`]s in the same sequence into absolute positions
+    ///
+    /// This gives the same positions as calling [`Self::get_cursor_position()`] for each cursor,
+    /// in the same order as `cursors`, but walks the sequence once for all of them. Cursors
+    /// pointing at deleted elements resolve to the position the element would have had.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AutomergeError::InvalidCursor`] for the first cursor which doesn't point into
+    /// `obj` and [`AutomergeError::InvalidOp`] if `obj` is not a sequence
+    fn get_cursor_positions<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        cursors: &[Cursor],
+        at: Option<&[ChangeHash]>,
+    ) -> Result<Vec<usize>, AutomergeError>;
+
+    /// Translate a [`Cursor`] into an absolute position along with the value of the element it
+    /// points at, in a single walk of the sequence.
+    ///
+    /// If the element the cursor points at has been deleted the position is where the element
+    /// would have been and the value is [`None`]. See [`Self::get_cursor_position()`].
+    fn get_cursor_position_and_value<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        cursor: &Cursor,
+        at: Option<&[ChangeHash]>,
+    ) -> Result<(usize, Option<Value<'_>>), AutomergeError>;
+
+    /// Find the index of the list element `elem` in the sequence `obj`
+    ///
+    /// `elem` is the id of the element, i.e. the id returned by [`Self::get()`] for the element
+    /// or the id of an object inserted into the list. Returns [`None`] if the element has been
+    /// deleted or does not exist in `obj` at `at`. This is the inverse of looking up an element
+    /// by index.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `obj` does not exist or is not a sequence, or if `elem` refers to an
+    /// actor which is not in this document
+    fn index_of<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        elem: &ExId,
+        at: Option<&[ChangeHash]>,
+    ) -> Result<Option<usize>, AutomergeError>;
+
+    /// Get a token identifying the element at `index` of the sequence `obj`
+    ///
+    /// The token encodes the actor and counter of the operation which inserted the element, so
+    /// it is the same in every document which contains that element, including after the
+    /// document is saved and loaded again. It is formatted like a [`Cursor`] for the element, so
+    /// it can also be parsed into one. Use [`Self::index_of_token()`] to find the element again.
+    ///
+    /// ```
+    /// # use automerge::{transaction::Transactable, AutoCommit, Automerge, ObjType, ReadDoc, ROOT};
+    /// let mut doc = AutoCommit::new();
+    /// let list = doc.put_object(ROOT, "list", ObjType::List).unwrap();
+    /// doc.splice(&list, 0, 0, ["a".into(), "b".into()]).unwrap();
+    /// let token = doc.element_token(&list, 1).unwrap();
+    ///
+    /// let mut loaded = AutoCommit::load(&doc.save()).unwrap();
+    /// loaded.insert(&list, 0, "z").unwrap();
+    /// assert_eq!(loaded.index_of_token(&list, &token).unwrap(), 2);
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AutomergeError::InvalidOp`] if `obj` is not a sequence and
+    /// [`AutomergeError::InvalidIndex`] if `index` is not less than its length
+    fn element_token<O: AsRef<ExId>>(&self, obj: O, index: usize)
+        -> Result<String, AutomergeError>;
+
+    /// Find the current index of the element identified by `token`, see
+    /// [`Self::element_token()`]
+    ///
+    /// If the element has been deleted this is the index it would have had, i.e. the index of
+    /// the first surviving element after it.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AutomergeError::InvalidCursorFormat`] if `token` can't be parsed and
+    /// [`AutomergeError::InvalidCursor`] if it doesn't identify an element of `obj`
+    fn index_of_token<O: AsRef<ExId>>(&self, obj: O, token: &str) -> Result<usize, AutomergeError>;
+
+    /// The number of visible elements of the sequence `obj` before the element which the op at
+    /// `op_pos` belongs to
+    ///
+    /// `op_pos` is a position in all the ops of `obj` as they are stored, including ops which
+    /// have been overwritten or deleted, as returned in the `ops_pos` of internal queries. This
+    /// converts it to the index of the element without walking the sequence a second time: the
+    /// cached indexes of the op tree are used for every node before `op_pos`. For text the count
+    /// is in the same units as [`Self::length()`].
+    ///
+    /// If `op_pos` is past the last op of `obj` this is the length of `obj`, and if `obj` is not in
+    /// this document this method will return `0`.
+    fn visible_count_before<O: AsRef<ExId>>(&self, obj: O, op_pos: usize) -> usize;
+
+    /// The hash of the change which set the current value of `prop` in `obj`
+    ///
+    /// If the value is conflicted this is the change which set the winning value, the one
+    /// [`Self::get()`] returns. The change is found from the id of the op which set the value so
+    /// no history has to be scanned. Returns [`None`] if there is no value, or if the value was
+    /// set in a transaction which hasn't been committed yet.
+    ///
+    /// ```
+    /// # use automerge::{transaction::Transactable, AutoCommit, ReadDoc, ROOT};
+    /// let mut doc = AutoCommit::new();
+    /// doc.put(ROOT, "title", "draft").unwrap();
+    /// doc.put(ROOT, "author", "alice").unwrap();
+    /// doc.commit();
+    /// let first = doc.get_heads()[0];
+    /// doc.put(ROOT, "title", "final").unwrap();
+    /// doc.commit();
+    /// assert_eq!(doc.value_provenance(ROOT, "author").unwrap(), Some(first));
+    /// assert_eq!(doc.value_provenance(ROOT, "title").unwrap(), Some(doc.get_heads()[0]));
+    /// ```
+    fn value_provenance<O: AsRef<ExId>, P: Into<Prop>>(
+        &self,
+        obj: O,
+        prop: P,
+    ) -> Result<Option<ChangeHash>, AutomergeError>;
+
+    /// Whether the map `obj` has a visible value for `key`
+    ///
+    /// This is the same as `doc.get(obj, key)?.is_some()` but stops at the first visible op for
+    /// `key` without building its value.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AutomergeError::InvalidOp`] if `obj` is a sequence
+    fn map_contains_key<O: AsRef<ExId>>(&self, obj: O, key: &str) -> Result<bool, AutomergeError>;
+
+    /// Whether `index` is in bounds for the sequence `obj`, i.e. less than [`Self::length()`]
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AutomergeError::InvalidOp`] if `obj` is not a sequence
+    fn list_contains_index<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        index: usize,
+    ) -> Result<bool, AutomergeError>;
+
+    /// Get a handle for reading `obj` repeatedly without looking up its ID each time
+    ///
+    /// ```
+    /// # use automerge::{transaction::Transactable, AutoCommit, ObjType, ReadDoc, ROOT};
+    /// let mut doc = AutoCommit::new();
+    /// let list = doc.put_object(ROOT, "list", ObjType::List).unwrap();
+    /// doc.insert(&list, 0, 1).unwrap();
+    /// doc.insert(&list, 1, 2).unwrap();
+    /// let view = doc.view(&list).unwrap();
+    /// assert_eq!(view.length(), 2);
+    /// assert_eq!(view.get(1).unwrap().0, 2.into());
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `obj` does not exist
+    fn view<O: AsRef<ExId>>(&self, obj: O) -> Result<ObjView<'_>, AutomergeError>;
+
+    /// Like [`Self::view()`] but every read through the view sees the document at `heads`
+    fn view_at<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        heads: &[ChangeHash],
+    ) -> Result<ObjView<'_>, AutomergeError>;
+
     /// Get a value out of the document.
     ///
     /// This returns a tuple of `(value, object ID)`. This is for two reasons:
@@ -226,6 +778,12 @@ pub trait ReadDoc {
         heads: &[ChangeHash],
     ) -> Result<Option<(Value<'_>, ExId)>, AutomergeError>;
 
+    /// Read the object `obj` and everything it contains, as at `heads` if given, into a
+    /// [`hydrate::Value`]
+    ///
+    /// Conflicts are resolved to the visible winner, as for [`Self::get()`]. The result can be
+    /// updated with the patches for later changes using [`hydrate::Value::apply_patches()`] and
+    /// it implements [`serde::Serialize`].
     fn hydrate<O: AsRef<ExId>>(
         &self,
         obj: O,
@@ -243,6 +801,195 @@ pub trait ReadDoc {
         prop: P,
     ) -> Result<Vec<(Value<'_>, ExId)>, AutomergeError>;
 
+    /// Get the value at `prop` in `obj`, resolving conflicts by the timestamp of the change which
+    /// made each value rather than by op id
+    ///
+    /// [`Self::get()`] returns the value with the greatest op id, which is not necessarily the
+    /// value written most recently by the clock on the wall. This returns the conflicting value
+    /// whose change has the latest timestamp instead. Values which have the same timestamp are
+    /// ordered by op id as [`Self::get()`] orders them, so every replica with the same changes
+    /// picks the same value. Values written in a transaction which has not been committed yet are
+    /// treated as newer than any other value. This only changes how conflicts are read, the
+    /// stored ops and the result of [`Self::get()`] are unaffected.
+    fn get_by_timestamp<O: AsRef<ExId>, P: Into<Prop>>(
+        &self,
+        obj: O,
+        prop: P,
+    ) -> Result<Option<(Value<'_>, ExId)>, AutomergeError>;
+
+    /// Get the value of `prop` in `obj` as milliseconds since the epoch, if it is a
+    /// [`ScalarValue::Timestamp`](crate::ScalarValue::Timestamp)
+    ///
+    /// Returns [`None`] if there is no value or the value is not a timestamp.
+    fn get_timestamp<O: AsRef<ExId>, P: Into<Prop>>(
+        &self,
+        obj: O,
+        prop: P,
+    ) -> Result<Option<i64>, AutomergeError>;
+
+    /// Get the value of `prop` in `obj` if it is a string
+    ///
+    /// This and the other typed getters ([`Self::get_int()`], [`Self::get_uint()`],
+    /// [`Self::get_f64()`], [`Self::get_bool()`] and [`Self::get_bytes()`]) save matching on the
+    /// result of [`Self::get()`]. Returns [`None`] if there is no value. Numbers are not coerced,
+    /// an int is not a uint.
+    ///
+    /// ```
+    /// # use automerge::{transaction::Transactable, AutoCommit, AutomergeError, ReadDoc, ROOT};
+    /// let mut doc = AutoCommit::new();
+    /// doc.put(ROOT, "name", "alice").unwrap();
+    /// doc.put(ROOT, "age", 42).unwrap();
+    /// assert_eq!(doc.get_str(ROOT, "name").unwrap(), Some("alice".to_string()));
+    /// assert_eq!(doc.get_int(ROOT, "age").unwrap(), Some(42));
+    /// assert_eq!(doc.get_str(ROOT, "missing").unwrap(), None);
+    /// assert!(matches!(
+    ///     doc.get_str(ROOT, "age"),
+    ///     Err(AutomergeError::InvalidValueType { .. })
+    /// ));
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AutomergeError::InvalidValueType`] if the value is not a string, including if it
+    /// is an object
+    fn get_str<O: AsRef<ExId>, P: Into<Prop>>(
+        &self,
+        obj: O,
+        prop: P,
+    ) -> Result<Option<String>, AutomergeError> {
+        typed(self.get(obj, prop)?, "a string", |s| match s {
+            ScalarValue::Str(s) => Some(s.to_string()),
+            _ => None,
+        })
+    }
+
+    /// Get the value of `prop` in `obj` if it is a signed integer, see [`Self::get_str()`]
+    fn get_int<O: AsRef<ExId>, P: Into<Prop>>(
+        &self,
+        obj: O,
+        prop: P,
+    ) -> Result<Option<i64>, AutomergeError> {
+        typed(self.get(obj, prop)?, "an int", |s| match s {
+            ScalarValue::Int(n) => Some(*n),
+            _ => None,
+        })
+    }
+
+    /// Get the value of `prop` in `obj` if it is an unsigned integer, see [`Self::get_str()`]
+    fn get_uint<O: AsRef<ExId>, P: Into<Prop>>(
+        &self,
+        obj: O,
+        prop: P,
+    ) -> Result<Option<u64>, AutomergeError> {
+        typed(self.get(obj, prop)?, "a uint", |s| match s {
+            ScalarValue::Uint(n) => Some(*n),
+            _ => None,
+        })
+    }
+
+    /// Get the value of `prop` in `obj` if it is a float of either width, see [`Self::get_str()`]
+    fn get_f64<O: AsRef<ExId>, P: Into<Prop>>(
+        &self,
+        obj: O,
+        prop: P,
+    ) -> Result<Option<f64>, AutomergeError> {
+        typed(self.get(obj, prop)?, "a float", |s| match s {
+            ScalarValue::F64(n) => Some(*n),
+            ScalarValue::F32(n) => Some(f64::from(*n)),
+            _ => None,
+        })
+    }
+
+    /// Get the value of `prop` in `obj` if it is a boolean, see [`Self::get_str()`]
+    fn get_bool<O: AsRef<ExId>, P: Into<Prop>>(
+        &self,
+        obj: O,
+        prop: P,
+    ) -> Result<Option<bool>, AutomergeError> {
+        typed(self.get(obj, prop)?, "a boolean", |s| match s {
+            ScalarValue::Boolean(b) => Some(*b),
+            _ => None,
+        })
+    }
+
+    /// Get the value of `prop` in `obj` if it is a byte array, see [`Self::get_str()`]
+    fn get_bytes<O: AsRef<ExId>, P: Into<Prop>>(
+        &self,
+        obj: O,
+        prop: P,
+    ) -> Result<Option<Vec<u8>>, AutomergeError> {
+        typed(self.get(obj, prop)?, "bytes", |s| match s {
+            ScalarValue::Bytes(b) => Some(b.clone()),
+            _ => None,
+        })
+    }
+
+    /// Get the value of `prop` in `obj` as it was when the element of `list` which `cursor`
+    /// points at was created
+    ///
+    /// The value is read at the heads made of just the change which inserted the element, so it
+    /// includes everything that change and its dependencies did. This is useful for reading
+    /// context about an entry in a sequence, such as the settings in effect when a line of a log
+    /// was written. The element may have been deleted since.
+    ///
+    /// ```
+    /// # use automerge::{transaction::Transactable, AutoCommit, ObjType, ReadDoc, ROOT};
+    /// let mut doc = AutoCommit::new();
+    /// let log = doc.put_object(ROOT, "log", ObjType::List).unwrap();
+    /// doc.put(ROOT, "level", "debug").unwrap();
+    /// doc.insert(&log, 0, "first line").unwrap();
+    /// doc.commit();
+    /// let cursor = doc.get_cursor(&log, 0, None).unwrap();
+    /// doc.put(ROOT, "level", "info").unwrap();
+    ///
+    /// let (level, _) = doc
+    ///     .get_at_element_creation(ROOT, "level", &log, &cursor)
+    ///     .unwrap()
+    ///     .unwrap();
+    /// assert_eq!(level.to_str(), Some("debug"));
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AutomergeError::InvalidCursor`] if `cursor` does not point at an element of
+    /// `list` which has been committed, or points at the end of the sequence
+    fn get_at_element_creation<O: AsRef<ExId>, P: Into<Prop>, L: AsRef<ExId>>(
+        &self,
+        obj: O,
+        prop: P,
+        list: L,
+        cursor: &Cursor,
+    ) -> Result<Option<(Value<'_>, ExId)>, AutomergeError>;
+
+    /// Like [`Self::get_all()`] but with the actor which set each value
+    ///
+    /// ```
+    /// # use automerge::{transaction::Transactable, ActorId, AutoCommit, ReadDoc, ROOT};
+    /// let mut alice = AutoCommit::new().with_actor(ActorId::from(b"alice"));
+    /// let mut bob = alice.fork().with_actor(ActorId::from(b"bob"));
+    /// alice.put(ROOT, "color", "red").unwrap();
+    /// bob.put(ROOT, "color", "blue").unwrap();
+    /// alice.merge(&mut bob).unwrap();
+    ///
+    /// let conflicts = alice.get_conflicts(ROOT, "color").unwrap();
+    /// let authors = conflicts
+    ///     .iter()
+    ///     .map(|(actor, value, _)| (actor.clone(), value.to_str().unwrap().to_string()))
+    ///     .collect::<Vec<_>>();
+    /// assert_eq!(
+    ///     authors,
+    ///     vec![
+    ///         (ActorId::from(b"alice"), "red".to_string()),
+    ///         (ActorId::from(b"bob"), "blue".to_string()),
+    ///     ]
+    /// );
+    /// ```
+    fn get_conflicts<O: AsRef<ExId>, P: Into<Prop>>(
+        &self,
+        obj: O,
+        prop: P,
+    ) -> Result<Vec<(ActorId, Value<'_>, ExId)>, AutomergeError>;
+
     /// Get all possibly conflicting values for a key as at `heads`
     ///
     /// See [`Self::get_all()`]
@@ -253,15 +1000,50 @@ pub trait ReadDoc {
         heads: &[ChangeHash],
     ) -> Result<Vec<(Value<'_>, ExId)>, AutomergeError>;
 
+    /// Get the values of several props of `obj` as at `heads`
+    ///
+    /// This is the same as calling [`Self::get_at()`] for each prop but only works out the state
+    /// of the document at `heads` once. The result is in the same order as `props`.
+    #[allow(clippy::type_complexity)]
+    fn get_many_at<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        props: &[Prop],
+        heads: &[ChangeHash],
+    ) -> Result<Vec<(Prop, Option<(Value<'_>, ExId)>)>, AutomergeError>;
+
     /// Get the hashes of the changes in this document that aren't transitive dependencies of the
     /// given `heads`.
     fn get_missing_deps(&self, heads: &[ChangeHash]) -> Vec<ChangeHash>;
 
     /// Get a change by its hash.
+    ///
+    /// The returned change is borrowed from the document's history, nothing is decoded or
+    /// cloned, so this is cheap enough to call repeatedly for read-only inspection. Use
+    /// [`Change::clone`] if you need an owned copy.
     fn get_change_by_hash(&self, hash: &ChangeHash) -> Option<&Change>;
 
     /// Return some statistics about the document
     fn stats(&self) -> Stats;
+
+    /// Return some statistics about the op tree of `obj`
+    ///
+    /// This is intended for diagnostics, e.g. to spot an object whose op tree is much larger than
+    /// its visible contents.
+    fn object_stats<O: AsRef<ExId>>(&self, obj: O) -> Result<ObjStats, AutomergeError>;
+
+    /// The number of operations in the op tree of `obj` which are not visible, e.g. deleted list
+    /// elements and overwritten map values
+    ///
+    /// These operations are kept so that concurrent changes can be merged and are retained by
+    /// [`crate::Automerge::save()`], so this can't be reduced by saving and loading. A large count
+    /// relative to [`Self::length()`] means reads of `obj` walk mostly hidden operations, which
+    /// is a signal that copying the visible contents into a fresh object may be worthwhile.
+    fn tombstone_count<O: AsRef<ExId>>(&self, obj: O) -> Result<usize, AutomergeError>;
+
+    /// The sum of [`Self::tombstone_count()`] over every object in the document, including
+    /// objects which have been deleted
+    fn total_tombstones(&self) -> usize;
 }
 
 pub(crate) trait ReadDocInternal: ReadDoc {
@@ -269,6 +1051,25 @@ pub(crate) trait ReadDocInternal: ReadDoc {
     fn live_obj_paths(&self) -> HashMap<ExId, Vec<(ExId, Prop)>>;
 }
 
+/// An element of a sequence and its index, see [`ReadDoc::list_endpoints()`]
+pub type ListEndpoint<'a> = (usize, Value<'a>);
+
+/// Statistics about the op tree of a single object
+///
+/// This is returned by [`ReadDoc::object_stats()`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ObjStats {
+    /// The number of levels in the op tree, 0 for an object with no ops
+    pub tree_depth: usize,
+    /// The number of nodes in the op tree
+    pub node_count: usize,
+    /// The number of ops in the op tree, including deleted and overwritten ones. Deletes themselves
+    /// are not stored in the tree.
+    pub op_count: usize,
+    /// The number of visible keys in a map or elements in a sequence
+    pub visible_count: usize,
+}
+
 /// Statistics about the document
 ///
 /// This is returned by [`ReadDoc::stats()`]
@@ -279,3 +1080,24 @@ pub struct Stats {
     /// The number of changes in the change graph for the document
     pub num_changes: u64,
 }
+
+/// The scalar in `value` converted by `f`, for the typed getters such as [`ReadDoc::get_str()`]
+///
+/// Returns [`AutomergeError::InvalidValueType`] naming `expected` if `f` returns `None`.
+fn typed<T, F: FnOnce(&ScalarValue) -> Option<T>>(
+    value: Option<(Value<'_>, ExId)>,
+    expected: &str,
+    f: F,
+) -> Result<Option<T>, AutomergeError> {
+    let Some((value, _)) = value else {
+        return Ok(None);
+    };
+    value
+        .to_scalar()
+        .and_then(f)
+        .map(Some)
+        .ok_or_else(|| AutomergeError::InvalidValueType {
+            expected: expected.to_string(),
+            unexpected: value.to_string(),
+        })
+}
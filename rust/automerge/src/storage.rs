@@ -1,20 +1,30 @@
+//! Inspecting the binary format of saved documents
+
 use std::ops::Range;
 
 pub(crate) mod change;
+mod change_reader;
 mod chunk;
 mod columns;
+pub(crate) mod compat;
 pub(crate) mod convert;
 mod document;
+pub(crate) mod inspect;
 pub(crate) mod load;
 pub(crate) mod parse;
 pub(crate) mod save;
 
+pub use change_reader::ChangeReader;
+pub use compat::{compatibility_check, ChunkKind, ColumnGroup, CompatReport, UnknownColumn};
+pub use inspect::{inspect, DocInspection};
 pub use load::VerificationMode;
 pub(crate) use {
     change::{AsChangeOp, Change, ChangeOp, Compressed, ReadChangeOpError},
     chunk::{CheckSum, Chunk, ChunkType, Header},
-    columns::{Columns, MismatchingColumn, RawColumn, RawColumns},
-    document::{AsChangeMeta, AsDocOp, ChangeMetadata, CompressConfig, DocOp, Document},
+    columns::{ColumnSpec, Columns, MismatchingColumn, RawColumn, RawColumns},
+    document::{
+        AsChangeMeta, AsDocOp, ChangeMetadata, CompressConfig, DocOp, DocOpColumns, Document,
+    },
 };
 
 fn shift_range(range: Range<usize>, by: usize) -> Range<usize> {
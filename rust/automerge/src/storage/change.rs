@@ -2,7 +2,7 @@ use std::{borrow::Cow, io::Write, marker::PhantomData, num::NonZeroU64, ops::Ran
 
 use crate::{convert, ActorId, ChangeHash, ScalarValue};
 
-use super::{parse, shift_range, CheckSum, ChunkType, Columns, Header, RawColumns};
+use super::{parse, shift_range, CheckSum, ChunkType, ColumnSpec, Columns, Header, RawColumns};
 
 mod change_op_columns;
 use change_op_columns::ChangeOpsColumns;
@@ -11,6 +11,7 @@ pub(crate) use change_op_columns::{ChangeOp, ReadChangeOpError};
 mod change_actors;
 pub(crate) use change_actors::PredOutOfOrder;
 mod compressed;
+pub(crate) mod extra_metadata;
 mod op_with_change_actors;
 pub(crate) use compressed::Compressed;
 
@@ -231,6 +232,10 @@ impl<'a, O: OpReadState> Change<'a, O> {
         &self.other_actors
     }
 
+    pub(crate) fn unknown_op_columns(&self) -> impl Iterator<Item = ColumnSpec> + '_ {
+        self.ops_meta.unknown_columns()
+    }
+
     pub(crate) fn start_op(&self) -> NonZeroU64 {
         self.start_op
     }
@@ -267,6 +272,15 @@ impl<'a, O: OpReadState> Change<'a, O> {
         &self.bytes
     }
 
+    /// Release the spare capacity of the buffers this change owns
+    pub(crate) fn shrink_to_fit(&mut self) {
+        if let Cow::Owned(bytes) = &mut self.bytes {
+            bytes.shrink_to_fit();
+        }
+        self.dependencies.shrink_to_fit();
+        self.other_actors.shrink_to_fit();
+    }
+
     pub(crate) fn hash(&self) -> ChangeHash {
         self.header.hash()
     }
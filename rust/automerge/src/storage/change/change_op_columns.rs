@@ -125,9 +125,15 @@ pub(crate) struct ChangeOpsColumns {
     pred: OpIdListRange,
     expand: MaybeBooleanRange,
     mark_name: RleRange<smol_str::SmolStr>,
+    unknown: Vec<ColumnSpec>,
 }
 
 impl ChangeOpsColumns {
+    /// The columns which this version of automerge doesn't know how to read
+    pub(crate) fn unknown_columns(&self) -> impl Iterator<Item = ColumnSpec> + '_ {
+        self.unknown.iter().copied()
+    }
+
     pub(crate) fn iter<'a>(&self, data: &'a [u8]) -> ChangeOpsIter<'a> {
         ChangeOpsIter {
             failed: false,
@@ -184,6 +190,7 @@ impl ChangeOpsColumns {
             pred,
             expand,
             mark_name,
+            unknown: Vec::new(),
         }
     }
 
@@ -247,6 +254,7 @@ impl ChangeOpsColumns {
             pred,
             expand,
             mark_name,
+            unknown: Vec::new(),
         }
     }
 
@@ -436,7 +444,7 @@ impl TryFrom<Columns> for ChangeOpsColumns {
         let mut pred_ctr: Option<DeltaRange> = None;
         let mut expand: Option<MaybeBooleanRange> = None;
         let mut mark_name: Option<RleRange<smol_str::SmolStr>> = None;
-        let mut other = Columns::empty();
+        let mut unknown = Vec::new();
 
         for (index, col) in columns.into_iter().enumerate() {
             match (col.id(), col.col_type()) {
@@ -494,7 +502,7 @@ impl TryFrom<Columns> for ChangeOpsColumns {
                 (MARK_NAME_COL_ID, ColumnType::String) => mark_name = Some(col.range().into()),
                 (other_type, other_col) => {
                     tracing::warn!(typ=?other_type, id=?other_col, "unknown column");
-                    other.append(col);
+                    unknown.push(col.spec());
                 }
             }
         }
@@ -519,6 +527,7 @@ impl TryFrom<Columns> for ChangeOpsColumns {
             pred,
             expand: expand.unwrap_or_else(|| (0..0).into()),
             mark_name: mark_name.unwrap_or_else(|| (0..0).into()),
+            unknown,
         })
     }
 }
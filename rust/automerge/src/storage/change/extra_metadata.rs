@@ -0,0 +1,77 @@
+use std::{borrow::Cow, collections::BTreeMap};
+
+use crate::{
+    columnar::column_range::ValueRange,
+    storage::parse::{self, leb128_u64},
+    ScalarValue,
+};
+
+/// The first byte of the extra bytes of a change which hold a metadata map
+const METADATA_TAG: u8 = 1;
+
+/// Encode `metadata` for the extra bytes of a change
+///
+/// The encoding is the tag byte, the length of the metadata column of a value column as an
+/// unsigned leb128 and then the value column itself. The values in the column alternate between
+/// a key, as a string, and its value. Older versions of automerge keep the extra bytes of a
+/// change as they are, so the metadata survives being loaded and saved by them.
+pub(crate) fn encode(metadata: &BTreeMap<String, ScalarValue>) -> Vec<u8> {
+    let values = metadata
+        .iter()
+        .flat_map(|(k, v)| [Cow::Owned(ScalarValue::Str(k.into())), Cow::Borrowed(v)]);
+    let mut cols = Vec::new();
+    let range = ValueRange::encode(values, &mut cols);
+    let mut out = vec![METADATA_TAG];
+    leb128::write::unsigned(&mut out, range.meta_range().end() as u64).unwrap();
+    out.extend(cols);
+    out
+}
+
+/// Decode the extra bytes of a change written by [`encode()`]
+///
+/// Returns `None` if the extra bytes are empty or weren't written by [`encode()`]
+pub(crate) fn decode(bytes: &[u8]) -> Option<BTreeMap<String, ScalarValue>> {
+    let (rest, meta_len) = match bytes.split_first() {
+        Some((&METADATA_TAG, rest)) => {
+            let (i, meta_len) = leb128_u64::<parse::leb128::Error>(parse::Input::new(rest)).ok()?;
+            (i.unconsumed_bytes(), usize::try_from(meta_len).ok()?)
+        }
+        _ => return None,
+    };
+    if meta_len > rest.len() {
+        return None;
+    }
+    let range = ValueRange::new((0..meta_len).into(), (meta_len..rest.len()).into());
+    let mut values = range.iter(rest);
+    let mut metadata = BTreeMap::new();
+    while let Some(key) = values.next() {
+        let ScalarValue::Str(key) = key.ok()? else {
+            return None;
+        };
+        let value = values.next()?.ok()?;
+        metadata.insert(key.to_string(), value);
+    }
+    Some(metadata)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn metadata_round_trips() {
+        let mut metadata = BTreeMap::new();
+        metadata.insert("client".to_string(), ScalarValue::Str("cli/1.2".into()));
+        metadata.insert("device".to_string(), ScalarValue::Uint(7));
+        metadata.insert("draft".to_string(), ScalarValue::Boolean(true));
+        assert_eq!(decode(&encode(&metadata)), Some(metadata));
+        assert_eq!(decode(&encode(&BTreeMap::new())), Some(BTreeMap::new()));
+    }
+
+    #[test]
+    fn other_extra_bytes_are_not_metadata() {
+        assert_eq!(decode(&[]), None);
+        assert_eq!(decode(&[0, 1, 2]), None);
+        assert_eq!(decode(&[METADATA_TAG, 10]), None);
+    }
+}
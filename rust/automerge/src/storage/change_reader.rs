@@ -0,0 +1,96 @@
+use crate::storage::{self, load::Error, parse};
+use crate::{AutomergeError, Change};
+
+/// An iterator over the changes in saved data which parses one chunk at a time
+///
+/// Unlike [`crate::Automerge::load()`] this never builds the operations of a document, each
+/// change chunk is parsed and checked into a [`Change`] as the iterator reaches it. This makes it
+/// a cheap way to forward the output of [`crate::Automerge::save_after()`] or
+/// [`crate::AutoCommit::save_incremental()`] to other peers, or to pick out the changes to apply.
+///
+/// The changes in a document chunk can only be recovered by reconstructing the operations of the
+/// document, so document chunks are skipped, see [`Self::skipped_document_chunks()`].
+///
+/// ```
+/// # use automerge::{transaction::Transactable, AutoCommit, ROOT};
+/// # use automerge::storage::ChangeReader;
+/// let mut doc = AutoCommit::new();
+/// doc.put(ROOT, "key", "value").unwrap();
+/// doc.commit();
+/// doc.put(ROOT, "key", "other").unwrap();
+/// let bytes = doc.save_after(&[]);
+///
+/// let hashes = ChangeReader::from_bytes(&bytes)
+///     .map(|change| change.map(|c| c.hash()))
+///     .collect::<Result<Vec<_>, _>>()
+///     .unwrap();
+/// assert_eq!(hashes.len(), 2);
+/// assert_eq!(hashes.last(), doc.get_heads().first());
+/// ```
+///
+/// # Errors
+///
+/// The iterator yields [`AutomergeError::Load`] if a chunk is malformed or has a bad checksum,
+/// after which it yields nothing more.
+#[derive(Debug)]
+pub struct ChangeReader<'a> {
+    data: parse::Input<'a>,
+    skipped_document_chunks: usize,
+}
+
+impl<'a> ChangeReader<'a> {
+    /// Read the changes in `bytes`, one chunk at a time
+    pub fn from_bytes(bytes: &'a [u8]) -> Self {
+        Self {
+            data: parse::Input::new(bytes),
+            skipped_document_chunks: 0,
+        }
+    }
+
+    /// The number of document chunks the iterator has skipped so far
+    pub fn skipped_document_chunks(&self) -> usize {
+        self.skipped_document_chunks
+    }
+
+    fn read_chunk(&mut self) -> Result<Option<Change>, Error> {
+        let (remaining, chunk) =
+            storage::Chunk::parse(self.data).map_err(|e| Error::Parse(Box::new(e)))?;
+        self.data = remaining.reset();
+        if !chunk.checksum_valid() {
+            return Err(Error::BadChecksum);
+        }
+        let change = match chunk {
+            storage::Chunk::Document(_) => {
+                self.skipped_document_chunks += 1;
+                return Ok(None);
+            }
+            storage::Chunk::Change(change) => {
+                Change::new_from_unverified(change.into_owned(), None)
+            }
+            storage::Chunk::CompressedChange(change, compressed) => {
+                Change::new_from_unverified(change.into_owned(), Some(compressed.into_owned()))
+            }
+        };
+        change
+            .map(Some)
+            .map_err(|e| Error::InvalidChangeColumns(Box::new(e)))
+    }
+}
+
+impl<'a> Iterator for ChangeReader<'a> {
+    type Item = Result<Change, AutomergeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.data.is_empty() {
+            match self.read_chunk() {
+                Ok(Some(change)) => return Some(Ok(change)),
+                Ok(None) => {}
+                Err(e) => {
+                    self.data = parse::Input::empty();
+                    return Some(Err(e.into()));
+                }
+            }
+        }
+        None
+    }
+}
@@ -18,7 +18,7 @@ pub(crate) enum Chunk<'a> {
 
 pub(crate) mod error {
     use super::parse;
-    use crate::storage::{change, document};
+    use crate::storage::{change, columns::raw_column, document};
 
     #[derive(thiserror::Error, Debug)]
     pub(crate) enum Chunk {
@@ -34,6 +34,22 @@ pub(crate) mod error {
         Document(#[from] document::ParseError),
         #[error("unable to decompresse compressed chunk")]
         Deflate,
+        #[error("the decompressed chunk was longer than {0} bytes")]
+        TooLarge(usize),
+    }
+
+    impl Chunk {
+        /// Whether this error is because the chunk decompressed to more than the limit passed
+        /// to [`super::Chunk::parse_with_limit()`]
+        pub(crate) fn limit_exceeded(&self) -> bool {
+            matches!(
+                self,
+                Self::TooLarge(_)
+                    | Self::Document(document::ParseError::RawColumns(
+                        raw_column::ParseError::TooLarge(_),
+                    ))
+            )
+        }
     }
 
     #[derive(thiserror::Error, Debug)]
@@ -50,6 +66,15 @@ pub(crate) mod error {
 impl<'a> Chunk<'a> {
     pub(crate) fn parse(
         input: parse::Input<'a>,
+    ) -> parse::ParseResult<'a, Chunk<'a>, error::Chunk> {
+        Self::parse_with_limit(input, usize::MAX)
+    }
+
+    /// Like [`Self::parse()`] but fails with [`error::Chunk::TooLarge`] as soon as the
+    /// decompressed chunk would be longer than `max_len` bytes, rather than decompressing it all
+    pub(crate) fn parse_with_limit(
+        input: parse::Input<'a>,
+        max_len: usize,
     ) -> parse::ParseResult<'a, Chunk<'a>, error::Chunk> {
         let (i, header) = Header::parse::<error::Chunk>(input)?;
         let parse::Split {
@@ -68,7 +93,7 @@ impl<'a> Chunk<'a> {
             }
             ChunkType::Document => {
                 let (remaining, doc) =
-                    Document::parse(chunk_input, header).map_err(|e| e.lift())?;
+                    Document::parse(chunk_input, header, max_len).map_err(|e| e.lift())?;
                 if !remaining.is_empty() {
                     return Err(parse::ParseError::Error(error::Chunk::LeftoverData));
                 }
@@ -76,11 +101,15 @@ impl<'a> Chunk<'a> {
             }
             ChunkType::Compressed => {
                 let compressed = &input.unconsumed_bytes()[header.data_bytes()];
-                let mut decoder = flate2::bufread::DeflateDecoder::new(compressed);
+                let mut decoder = flate2::bufread::DeflateDecoder::new(compressed)
+                    .take((max_len as u64).saturating_add(1));
                 let mut decompressed = Vec::new();
                 decoder
                     .read_to_end(&mut decompressed)
                     .map_err(|_| parse::ParseError::Error(error::Chunk::Deflate))?;
+                if decompressed.len() > max_len {
+                    return Err(parse::ParseError::Error(error::Chunk::TooLarge(max_len)));
+                }
                 let inner_header = header.with_data(ChunkType::Change, &decompressed);
                 let mut inner_chunk = Vec::with_capacity(inner_header.len() + decompressed.len());
                 inner_header.write(&mut inner_chunk);
@@ -108,6 +137,23 @@ impl<'a> Chunk<'a> {
             }
         }
     }
+
+    /// The length of the chunk once decompressed
+    pub(crate) fn decompressed_len(&self) -> usize {
+        match self {
+            Self::Document(d) => d.decompressed_len(),
+            Self::Change(c) | Self::CompressedChange(c, _) => c.bytes().len(),
+        }
+    }
+
+    /// The hash of the contents of the chunk, for a compressed change this is the hash of the
+    /// uncompressed change
+    pub(crate) fn hash(&self) -> ChangeHash {
+        match self {
+            Self::Document(d) => d.hash(),
+            Self::Change(c) | Self::CompressedChange(c, _) => c.hash(),
+        }
+    }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -192,6 +238,33 @@ impl Header {
         }
     }
 
+    /// A hasher which has been given the chunk type and length, for data which is hashed in
+    /// pieces before being passed to [`Self::from_hasher()`]
+    pub(crate) fn hasher(chunk_type: ChunkType, data_len: usize) -> Sha256 {
+        let mut prefix = vec![u8::from(chunk_type)];
+        leb128::write::unsigned(&mut prefix, data_len as u64).unwrap();
+        let mut hasher = Sha256::new();
+        hasher.update(prefix);
+        hasher
+    }
+
+    /// Like [`Self::new()`] for `data_len` bytes of data which have been given to `hasher`, see
+    /// [`Self::hasher()`]
+    pub(crate) fn from_hasher(chunk_type: ChunkType, data_len: usize, hasher: Sha256) -> Self {
+        let array: [u8; 32] = hasher.finalize().into();
+        let hash = ChangeHash(array);
+        Self {
+            hash,
+            checksum: hash.checksum().into(),
+            data_len,
+            header_size: MAGIC_BYTES.len()
+                + 4 // checksum
+                + 1 // chunk type
+                + (ulebsize(data_len as u64) as usize),
+            chunk_type,
+        }
+    }
+
     /// Returns a header with the same checksum but with a different chunk type and data length.
     /// This is primarily useful when processing compressed chunks, where the checksum is actually
     /// derived from the uncompressed data.
@@ -46,15 +46,19 @@ impl<T: compression::ColumnCompression> RawColumn<T> {
         self.data.clone()
     }
 
-    fn compress(&self, input: &[u8], out: &mut Vec<u8>, threshold: usize) -> (ColumnSpec, usize) {
+    fn compress(
+        &self,
+        input: &[u8],
+        out: &mut Vec<u8>,
+        threshold: usize,
+        level: flate2::Compression,
+    ) -> (ColumnSpec, usize) {
         let (spec, len) = if self.data.len() < threshold || self.spec.deflate() {
             out.extend(&input[self.data.clone()]);
             (self.spec, self.data.len())
         } else {
-            let mut deflater = flate2::bufread::DeflateEncoder::new(
-                &input[self.data.clone()],
-                flate2::Compression::default(),
-            );
+            let mut deflater =
+                flate2::bufread::DeflateEncoder::new(&input[self.data.clone()], level);
             //This unwrap should be okay as we're reading and writing to in memory buffers
             (self.spec.deflated(), deflater.read_to_end(out).unwrap())
         };
@@ -73,13 +77,18 @@ impl<T: compression::ColumnCompression> RawColumn<T> {
         }
     }
 
+    /// Decompress this column onto the end of `out`, stopping as soon as `out` would become
+    /// longer than `max_len`
     fn decompress(
         &self,
         input: &[u8],
         out: &mut Vec<u8>,
+        max_len: usize,
     ) -> Result<(ColumnSpec, usize), ParseError> {
         let len = if self.spec.deflate() {
-            let mut inflater = flate2::bufread::DeflateDecoder::new(&input[self.data.clone()]);
+            let remaining = max_len.saturating_sub(out.len()) as u64;
+            let mut inflater = flate2::bufread::DeflateDecoder::new(&input[self.data.clone()])
+                .take(remaining.saturating_add(1));
             inflater.read_to_end(out).map_err(ParseError::Deflate)?
         } else {
             out.extend(&input[self.data.clone()]);
@@ -118,11 +127,12 @@ impl<T: compression::ColumnCompression> RawColumns<T> {
         input: &[u8],
         out: &mut Vec<u8>,
         threshold: usize,
+        level: flate2::Compression,
     ) -> RawColumns<compression::Unknown> {
         let mut result = Vec::with_capacity(self.0.len());
         let mut start = 0;
         for col in &self.0 {
-            let (spec, len) = col.compress(input, out, threshold);
+            let (spec, len) = col.compress(input, out, threshold, level);
             result.push(RawColumn {
                 spec,
                 data: start..(start + len),
@@ -138,12 +148,16 @@ impl<T: compression::ColumnCompression> RawColumns<T> {
     /// # Returns
     /// The `RawColumns` corresponding to the data written to `out`
     ///
+    /// # Errors
+    /// * [`ParseError::TooLarge`] if `out` would become longer than `max_len`
+    ///
     /// # Panics
     /// * If any of the ranges in `self` is outside the bounds of `input`
     pub(crate) fn uncompress(
         &self,
         input: &[u8],
         out: &mut Vec<u8>,
+        max_len: usize,
     ) -> Result<RawColumns<compression::Uncompressed>, ParseError> {
         let mut result = Vec::with_capacity(self.0.len());
         let mut start = 0;
@@ -152,8 +166,11 @@ impl<T: compression::ColumnCompression> RawColumns<T> {
                 out.extend(&input[decomp.data.clone()]);
                 (decomp.spec, decomp.data.len())
             } else {
-                col.decompress(input, out)?
+                col.decompress(input, out, max_len)?
             };
+            if out.len() > max_len {
+                return Err(ParseError::TooLarge(max_len));
+            }
             result.push(RawColumn {
                 spec,
                 data: start..(start + len),
@@ -199,6 +216,8 @@ pub(crate) enum ParseError {
     Leb128(#[from] parse::leb128::Error),
     #[error(transparent)]
     Deflate(#[from] std::io::Error),
+    #[error("the decompressed columns were longer than {0} bytes")]
+    TooLarge(usize),
 }
 
 impl RawColumns<compression::Unknown> {
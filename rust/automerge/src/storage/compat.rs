@@ -0,0 +1,234 @@
+use crate::storage::{self, load, load::Error, parse, ChunkType, ColumnSpec, MAGIC_BYTES};
+use crate::AutomergeError;
+
+/// The type of a chunk in a saved document, see [`compatibility_check()`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkKind {
+    /// A document chunk, produced by [`crate::Automerge::save()`]
+    Document,
+    /// A single change
+    Change,
+    /// A single change, compressed with DEFLATE
+    CompressedChange,
+    /// A chunk type this version of automerge doesn't know about
+    Unknown(u8),
+}
+
+/// Which set of columns an [`UnknownColumn`] was found in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ColumnGroup {
+    /// The change metadata of a document chunk
+    DocumentChanges,
+    /// The operations of a document chunk
+    DocumentOps,
+    /// The operations of a change chunk
+    ChangeOps,
+}
+
+impl std::fmt::Display for ColumnGroup {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::DocumentChanges => write!(f, "document change"),
+            Self::DocumentOps => write!(f, "document op"),
+            Self::ChangeOps => write!(f, "change op"),
+        }
+    }
+}
+
+/// A column which this version of automerge would ignore when loading
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct UnknownColumn {
+    /// Where the column was found
+    pub group: ColumnGroup,
+    /// The id of the column
+    pub id: u32,
+    /// The type of the column, the low three bits of the column specification
+    pub column_type: u8,
+}
+
+impl UnknownColumn {
+    fn new(group: ColumnGroup, spec: ColumnSpec) -> Self {
+        Self {
+            group,
+            id: spec.id().into(),
+            column_type: spec.col_type().into(),
+        }
+    }
+}
+
+/// The formats used by the chunks in a saved document, see [`compatibility_check()`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CompatReport {
+    /// The type of every chunk, in the order they appear
+    pub chunks: Vec<ChunkKind>,
+    /// The columns which aren't understood by this version of automerge, sorted and without
+    /// duplicates
+    pub unknown_columns: Vec<UnknownColumn>,
+}
+
+impl CompatReport {
+    /// Whether this version of automerge understands everything in the document
+    pub fn is_supported(&self) -> bool {
+        self.unknown_columns.is_empty()
+            && !self
+                .chunks
+                .iter()
+                .any(|c| matches!(c, ChunkKind::Unknown(_)))
+    }
+
+    /// A description of each part of the document this version of automerge doesn't
+    /// understand, suitable for showing to a user
+    pub fn unsupported_features(&self) -> Vec<String> {
+        let mut chunk_types = self
+            .chunks
+            .iter()
+            .filter_map(|c| match c {
+                ChunkKind::Unknown(t) => Some(*t),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        chunk_types.sort_unstable();
+        chunk_types.dedup();
+        let chunks = chunk_types
+            .into_iter()
+            .map(|t| format!("unknown chunk type {}", t));
+        let columns = self.unknown_columns.iter().map(|c| {
+            format!(
+                "unknown {} column {} of type {}",
+                c.group,
+                c.id,
+                storage::columns::ColumnType::from(c.column_type)
+            )
+        });
+        chunks.chain(columns).collect()
+    }
+}
+
+/// Find out whether this version of automerge can load everything in `bytes`
+///
+/// Chunks of an unknown type and columns with an unknown id make
+/// [`crate::Automerge::load()`] fail or silently drop data respectively. This reads the chunk
+/// headers and column layouts without decoding any operations and reports what it finds, so a
+/// loader can refuse a document written by a newer version of automerge with a clear message.
+/// The checksum of every chunk of a known type is verified.
+///
+/// ```
+/// # use automerge::{transaction::Transactable, AutoCommit, ROOT};
+/// # use automerge::storage::ChunkKind;
+/// let mut doc = AutoCommit::new();
+/// doc.put(ROOT, "key", "value").unwrap();
+/// let report = automerge::storage::compatibility_check(&doc.save()).unwrap();
+/// assert_eq!(report.chunks, vec![ChunkKind::Document]);
+/// assert!(report.is_supported());
+/// assert!(report.unsupported_features().is_empty());
+/// ```
+///
+/// # Errors
+///
+/// Returns [`AutomergeError::Load`] if a chunk of a known type is malformed or has a bad
+/// checksum, or if `bytes` ends part way through a chunk
+pub fn compatibility_check(bytes: &[u8]) -> Result<CompatReport, AutomergeError> {
+    let mut report = CompatReport::default();
+    let mut input = bytes;
+    while let Some(chunk_bytes) = load::read_chunk(&mut input)? {
+        let kind = match ChunkType::try_from(chunk_bytes[MAGIC_BYTES.len() + 4]) {
+            Ok(ChunkType::Document) => ChunkKind::Document,
+            Ok(ChunkType::Change) => ChunkKind::Change,
+            Ok(ChunkType::Compressed) => ChunkKind::CompressedChange,
+            Err(other) => {
+                report.chunks.push(ChunkKind::Unknown(other));
+                continue;
+            }
+        };
+        report.chunks.push(kind);
+        let (_, chunk) = storage::Chunk::parse(parse::Input::new(&chunk_bytes))
+            .map_err(|e| Error::Parse(Box::new(e)))?;
+        if !chunk.checksum_valid() {
+            return Err(Error::BadChecksum.into());
+        }
+        match chunk {
+            storage::Chunk::Document(d) => {
+                report.unknown_columns.extend(
+                    d.unknown_change_columns()
+                        .map(|c| UnknownColumn::new(ColumnGroup::DocumentChanges, c)),
+                );
+                report.unknown_columns.extend(
+                    d.unknown_op_columns()
+                        .map(|c| UnknownColumn::new(ColumnGroup::DocumentOps, c)),
+                );
+            }
+            storage::Chunk::Change(c) | storage::Chunk::CompressedChange(c, _) => {
+                report.unknown_columns.extend(
+                    c.unknown_op_columns()
+                        .map(|c| UnknownColumn::new(ColumnGroup::ChangeOps, c)),
+                );
+            }
+        }
+    }
+    report.unknown_columns.sort_unstable();
+    report.unknown_columns.dedup();
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::Header;
+
+    /// A change chunk with no ops and one empty op column with id 100
+    fn change_with_unknown_column() -> Vec<u8> {
+        let mut data = vec![
+            0, // no deps
+            1, 1, // actor
+            1, // seq
+            1, // start op
+            0, // time
+            0, // message
+            0, // other actors
+            1, // one op column
+        ];
+        leb128::write::unsigned(&mut data, (100 << 4) | 2).unwrap();
+        data.push(0); // of length zero
+        let mut chunk = Vec::new();
+        Header::new(ChunkType::Change, &data).write(&mut chunk);
+        chunk.extend(data);
+        chunk
+    }
+
+    #[test]
+    fn reports_unknown_columns_and_chunks() {
+        let mut bytes = change_with_unknown_column();
+        bytes.extend(change_with_unknown_column());
+        // a chunk of type 7 with no data
+        bytes.extend(MAGIC_BYTES);
+        bytes.extend([0, 0, 0, 0, 7, 0]);
+
+        let report = compatibility_check(&bytes).unwrap();
+        assert_eq!(
+            report.chunks,
+            vec![ChunkKind::Change, ChunkKind::Change, ChunkKind::Unknown(7)]
+        );
+        assert_eq!(
+            report.unknown_columns,
+            vec![UnknownColumn {
+                group: ColumnGroup::ChangeOps,
+                id: 100,
+                column_type: 2,
+            }]
+        );
+        assert!(!report.is_supported());
+        assert_eq!(
+            report.unsupported_features(),
+            vec![
+                "unknown chunk type 7".to_string(),
+                "unknown change op column 100 of type Integer".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn truncated_chunk_is_an_error() {
+        let bytes = change_with_unknown_column();
+        assert!(compatibility_check(&bytes[..bytes.len() - 1]).is_err());
+    }
+}
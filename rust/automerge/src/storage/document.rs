@@ -1,12 +1,13 @@
 use std::{borrow::Cow, ops::Range};
 
-use super::{parse, shift_range, ChunkType, Columns, Header, RawColumns};
+use sha2::Digest;
+
+use super::{columns, parse, shift_range, ChunkType, ColumnSpec, Columns, Header, RawColumns};
 
 use crate::{convert, ActorId, ChangeHash};
 
 mod doc_op_columns;
-use doc_op_columns::DocOpColumns;
-pub(crate) use doc_op_columns::{AsDocOp, DocOp, ReadDocOpError};
+pub(crate) use doc_op_columns::{AsDocOp, DocOp, DocOpColumns, ReadDocOpError};
 mod doc_change_columns;
 use doc_change_columns::DocChangeColumns;
 pub(crate) use doc_change_columns::{AsChangeMeta, ChangeMetadata, ReadChangeError};
@@ -15,7 +16,11 @@ mod compression;
 #[allow(dead_code)]
 pub(crate) enum CompressConfig {
     None,
-    Threshold(usize),
+    /// Deflate columns longer than `threshold` bytes at `level`
+    Threshold {
+        threshold: usize,
+        level: flate2::Compression,
+    },
 }
 
 #[derive(Debug, Clone)]
@@ -53,8 +58,8 @@ pub(crate) enum ParseError {
 
 impl<'a> Document<'a> {
     /// Parse a document chunk. Input must be the entire chunk including the header and magic
-    /// bytes but the header must already have been parsed. That is to say, this is expected to be
-    /// used like so:
+    /// bytes but the header must already have been parsed. Compressed columns may decompress to
+    /// at most `max_len` bytes. That is to say, this is expected to be used like so:
     ///
     /// ```rust,ignore
     /// # use automerge::storage::{parse::{ParseResult, Input}, Document, Header};
@@ -62,12 +67,13 @@ impl<'a> Document<'a> {
     /// let chunkbytes: &[u8] = todo!();
     /// let input = Input::new(chunkbytes);
     /// let (i, header) = Header::parse(input)?;
-    /// let (i, doc) = Document::parse(i, header)?;
+    /// let (i, doc) = Document::parse(i, header, usize::MAX)?;
     /// # }
     /// ```
     pub(crate) fn parse(
         input: parse::Input<'a>,
         header: Header,
+        max_len: usize,
     ) -> parse::ParseResult<'a, Document<'a>, ParseError> {
         let i = input;
 
@@ -160,20 +166,23 @@ impl<'a> Document<'a> {
             compressed,
             changes,
             ops,
-        } = compression::decompress(compression::Args {
-            prefix: prefix.start,
-            suffix: suffix.start,
-            original: Cow::Borrowed(input.bytes()),
-            changes: compression::Cols {
-                data: changes,
-                raw_columns: change_meta,
-            },
-            ops: compression::Cols {
-                data: ops,
-                raw_columns: ops_meta,
+        } = compression::decompress(
+            compression::Args {
+                prefix: prefix.start,
+                suffix: suffix.start,
+                original: Cow::Borrowed(input.bytes()),
+                changes: compression::Cols {
+                    data: changes,
+                    raw_columns: change_meta,
+                },
+                ops: compression::Cols {
+                    data: ops,
+                    raw_columns: ops_meta,
+                },
+                extra_args: (),
             },
-            extra_args: (),
-        })
+            max_len,
+        )
         .map_err(|e| parse::ParseError::Error(ParseError::RawColumns(e)))?;
 
         let ops_layout = Columns::parse(op_bytes.len(), ops.iter()).map_err(|e| {
@@ -211,6 +220,11 @@ impl<'a> Document<'a> {
         ))
     }
 
+    /// The number of times [`Self::new()`] iterates over `num_ops` ops
+    pub(crate) fn op_encode_passes(num_ops: usize) -> usize {
+        DocOpColumns::encode_passes(num_ops)
+    }
+
     pub(crate) fn new<'b, I, C, IC, D, O>(
         mut actors: Vec<ActorId>,
         heads_with_indices: Vec<(ChangeHash, usize)>,
@@ -233,15 +247,7 @@ impl<'a> Document<'a> {
         actors.sort_unstable();
 
         let mut data = Vec::with_capacity(ops_out.len() + change_out.len());
-        leb128::write::unsigned(&mut data, actors.len() as u64).unwrap();
-        for actor in &actors {
-            leb128::write::unsigned(&mut data, actor.to_bytes().len() as u64).unwrap();
-            data.extend(actor.to_bytes());
-        }
-        leb128::write::unsigned(&mut data, heads_with_indices.len() as u64).unwrap();
-        for (head, _) in &heads_with_indices {
-            data.extend(head.as_bytes());
-        }
+        write_prefix(&actors, &heads_with_indices, &mut data);
         let prefix_len = data.len();
 
         change_meta.raw_columns().write(&mut data);
@@ -254,13 +260,7 @@ impl<'a> Document<'a> {
         data.extend(ops_out);
         let suffix_start = data.len();
 
-        let head_indices = heads_with_indices
-            .iter()
-            .map(|(_, i)| *i as u64)
-            .collect::<Vec<_>>();
-        for index in &head_indices {
-            leb128::write::unsigned(&mut data, *index).unwrap();
-        }
+        let head_indices = write_suffix(&heads_with_indices, &mut data);
 
         let header = Header::new(ChunkType::Document, &data);
         let mut bytes = Vec::with_capacity(data.len() + header.len());
@@ -271,7 +271,7 @@ impl<'a> Document<'a> {
         let op_bytes = shift_range(ops_start..ops_end, header.len());
         let change_bytes = shift_range(change_start..change_end, header.len());
 
-        let compressed_bytes = if let CompressConfig::Threshold(threshold) = compress {
+        let compressed_bytes = if let CompressConfig::Threshold { threshold, level } = compress {
             let compressed = Cow::Owned(compression::compress(compression::Args {
                 prefix: prefix_len + header.len(),
                 suffix: suffix_start + header.len(),
@@ -286,6 +286,7 @@ impl<'a> Document<'a> {
                 original: Cow::Borrowed(&bytes),
                 extra_args: compression::CompressArgs {
                     threshold,
+                    level,
                     original_header_len: header_len,
                 },
             }));
@@ -308,6 +309,70 @@ impl<'a> Document<'a> {
         }
     }
 
+    /// Write the chunk [`Self::new()`] would create to `w`, without building it in memory
+    ///
+    /// The column metadata and the checksum in the header of the chunk depend on all of the
+    /// column data, so the columns are encoded three times: once to find their lengths, once to
+    /// hash them and once to write them. Only one group of columns, see
+    /// [`DocOpColumns::encode_groups()`], is held in memory at a time.
+    pub(crate) fn write<'b, I, C, IC, D, O, W>(
+        mut actors: Vec<ActorId>,
+        heads_with_indices: Vec<(ChangeHash, usize)>,
+        ops: I,
+        changes: IC,
+        compress: CompressConfig,
+        w: &mut W,
+    ) -> std::io::Result<()>
+    where
+        I: Iterator<Item = D> + Clone,
+        O: convert::OpId<usize>,
+        D: AsDocOp<'b, OpId = O>,
+        C: AsChangeMeta<'b>,
+        IC: Iterator<Item = C> + Clone,
+        W: std::io::Write + ?Sized,
+    {
+        actors.sort_unstable();
+        let mut prefix = Vec::new();
+        write_prefix(&actors, &heads_with_indices, &mut prefix);
+        let mut suffix = Vec::new();
+        write_suffix(&heads_with_indices, &mut suffix);
+
+        let mut change_specs = Vec::new();
+        let mut op_specs = Vec::new();
+        let mut columns_len = 0;
+        encode_column_groups(&ops, &changes, &compress, |of_ops, specs, data| {
+            if of_ops {
+                op_specs.extend_from_slice(specs);
+            } else {
+                change_specs.extend_from_slice(specs);
+            }
+            columns_len += data.len();
+            Ok(())
+        })?;
+        let mut meta = Vec::new();
+        raw_columns(&change_specs).write(&mut meta);
+        raw_columns(&op_specs).write(&mut meta);
+        let data_len = prefix.len() + meta.len() + columns_len + suffix.len();
+
+        let mut hasher = Header::hasher(ChunkType::Document, data_len);
+        hasher.update(&prefix);
+        hasher.update(&meta);
+        encode_column_groups(&ops, &changes, &compress, |_, _, data| {
+            hasher.update(data);
+            Ok(())
+        })?;
+        hasher.update(&suffix);
+        let header = Header::from_hasher(ChunkType::Document, data_len, hasher);
+
+        let mut header_bytes = Vec::with_capacity(header.len());
+        header.write(&mut header_bytes);
+        w.write_all(&header_bytes)?;
+        w.write_all(&prefix)?;
+        w.write_all(&meta)?;
+        encode_column_groups(&ops, &changes, &compress, |_, _, data| w.write_all(data))?;
+        w.write_all(&suffix)
+    }
+
     pub(crate) fn iter_ops(
         &'a self,
     ) -> impl Iterator<Item = Result<DocOp, ReadDocOpError>> + Clone + 'a {
@@ -333,6 +398,15 @@ impl<'a> Document<'a> {
         self.header.checksum_valid()
     }
 
+    /// The length of the chunk with any compressed columns decompressed
+    pub(crate) fn decompressed_len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub(crate) fn hash(&self) -> ChangeHash {
+        self.header.hash()
+    }
+
     pub(crate) fn actors(&self) -> &[ActorId] {
         &self.actors
     }
@@ -340,4 +414,89 @@ impl<'a> Document<'a> {
     pub(crate) fn heads(&self) -> &[ChangeHash] {
         &self.heads
     }
+
+    pub(crate) fn unknown_op_columns(&self) -> impl Iterator<Item = ColumnSpec> + '_ {
+        self.op_metadata.unknown_columns()
+    }
+
+    pub(crate) fn unknown_change_columns(&self) -> impl Iterator<Item = ColumnSpec> + '_ {
+        self.change_metadata.unknown_columns()
+    }
+}
+
+/// Write the actors and heads which begin the data of a document chunk
+fn write_prefix(actors: &[ActorId], heads_with_indices: &[(ChangeHash, usize)], out: &mut Vec<u8>) {
+    leb128::write::unsigned(out, actors.len() as u64).unwrap();
+    for actor in actors {
+        leb128::write::unsigned(out, actor.to_bytes().len() as u64).unwrap();
+        out.extend(actor.to_bytes());
+    }
+    leb128::write::unsigned(out, heads_with_indices.len() as u64).unwrap();
+    for (head, _) in heads_with_indices {
+        out.extend(head.as_bytes());
+    }
+}
+
+/// Write the head indices which end the data of a document chunk, returning them
+fn write_suffix(heads_with_indices: &[(ChangeHash, usize)], out: &mut Vec<u8>) -> Vec<u64> {
+    let head_indices = heads_with_indices
+        .iter()
+        .map(|(_, i)| *i as u64)
+        .collect::<Vec<_>>();
+    for index in &head_indices {
+        leb128::write::unsigned(out, *index).unwrap();
+    }
+    head_indices
+}
+
+/// Encode the change columns and then the op columns of a document one group at a time,
+/// compressing them as [`Document::new()`] would, and call `f` with whether the group holds op
+/// columns, the specification and length of each column in the group and their data
+fn encode_column_groups<'b, I, C, IC, D, O, F>(
+    ops: &I,
+    changes: &IC,
+    compress: &CompressConfig,
+    mut f: F,
+) -> std::io::Result<()>
+where
+    I: Iterator<Item = D> + Clone,
+    O: convert::OpId<usize>,
+    D: AsDocOp<'b, OpId = O>,
+    C: AsChangeMeta<'b>,
+    IC: Iterator<Item = C> + Clone,
+    F: FnMut(bool, &[(ColumnSpec, usize)], &[u8]) -> std::io::Result<()>,
+{
+    let mut compressed = Vec::new();
+    let mut group =
+        |of_ops: bool, raw: RawColumns<columns::compression::Uncompressed>, data: &[u8]| {
+            match compress {
+                CompressConfig::Threshold { threshold, level } => {
+                    compressed.clear();
+                    let raw = raw.compress(data, &mut compressed, *threshold, *level);
+                    f(of_ops, &specs(&raw), &compressed)
+                }
+                CompressConfig::None => f(of_ops, &specs(&raw), data),
+            }
+        };
+    DocChangeColumns::encode_groups(changes.clone(), |raw, data| group(false, raw, data))?;
+    DocOpColumns::encode_groups(ops.clone(), |raw, data| group(true, raw, data))
+}
+
+/// The specification and length of each column in `raw`
+fn specs<T: columns::compression::ColumnCompression>(
+    raw: &RawColumns<T>,
+) -> Vec<(ColumnSpec, usize)> {
+    raw.iter().map(|c| (c.spec(), c.data().len())).collect()
+}
+
+/// Column metadata for columns with the given specifications and lengths, in order
+fn raw_columns(specs: &[(ColumnSpec, usize)]) -> RawColumns<columns::compression::Unknown> {
+    specs
+        .iter()
+        .scan(0, |start, (spec, len)| {
+            let range = *start..*start + len;
+            *start += len;
+            Some((*spec, range))
+        })
+        .collect()
 }
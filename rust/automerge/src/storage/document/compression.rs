@@ -22,6 +22,7 @@ pub(super) struct Args<'a, T: compression::ColumnCompression, DirArgs> {
 
 pub(super) struct CompressArgs {
     pub(super) threshold: usize,
+    pub(super) level: flate2::Compression,
     pub(super) original_header_len: usize,
 }
 
@@ -29,6 +30,7 @@ pub(super) struct CompressArgs {
 pub(super) fn compress(args: Args<'_, compression::Uncompressed, CompressArgs>) -> Vec<u8> {
     let header_len = args.extra_args.original_header_len;
     let threshold = args.extra_args.threshold;
+    let level = args.extra_args.level;
     // Wrap in a closure so we can use `?` in the construction but still force the compiler
     // to check that the error type is `Infallible`
     let result: Result<_, Infallible> = (|| {
@@ -36,6 +38,7 @@ pub(super) fn compress(args: Args<'_, compression::Uncompressed, CompressArgs>)
             args,
             Compressing {
                 threshold,
+                level,
                 header_len,
             },
         )
@@ -48,8 +51,11 @@ pub(super) fn compress(args: Args<'_, compression::Uncompressed, CompressArgs>)
     result.unwrap()
 }
 
+/// Decompress a document chunk, failing if the decompressed columns would be longer than
+/// `max_len`
 pub(super) fn decompress<'a>(
     args: Args<'a, compression::Unknown, ()>,
+    max_len: usize,
 ) -> Result<Decompressed<'a>, raw_column::ParseError> {
     match (
         args.changes.raw_columns.uncompressed(),
@@ -64,7 +70,7 @@ pub(super) fn decompress<'a>(
             op_bytes: args.ops.data,
         }),
         _ => Ok(
-            Compression::<'a, Decompressing, _>::new(args, Decompressing)
+            Compression::<'a, Decompressing, _>::new(args, Decompressing { max_len })
                 .changes()?
                 .ops()?
                 .write_data()
@@ -127,6 +133,7 @@ trait Direction: std::fmt::Debug {
 #[derive(Debug)]
 struct Compressing {
     threshold: usize,
+    level: flate2::Compression,
     header_len: usize,
 }
 
@@ -144,9 +151,9 @@ impl Direction for Compressing {
         meta_out: &mut Vec<u8>,
     ) -> Result<Cols<Self::Out>, Self::Error> {
         let start = out.len();
-        let raw_columns = cols
-            .raw_columns
-            .compress(&input[cols.data.clone()], out, self.threshold);
+        let raw_columns =
+            cols.raw_columns
+                .compress(&input[cols.data.clone()], out, self.threshold, self.level);
         raw_columns.write(meta_out);
         Ok(Cols {
             data: start..out.len(),
@@ -156,7 +163,10 @@ impl Direction for Compressing {
 }
 
 #[derive(Debug)]
-struct Decompressing;
+struct Decompressing {
+    /// The most column data to decompress
+    max_len: usize,
+}
 
 impl Direction for Decompressing {
     type Error = raw_column::ParseError;
@@ -172,9 +182,9 @@ impl Direction for Decompressing {
         meta_out: &mut Vec<u8>,
     ) -> Result<Cols<Self::Out>, raw_column::ParseError> {
         let start = out.len();
-        let raw_columns = cols
-            .raw_columns
-            .uncompress(&input[cols.data.clone()], out)?;
+        let raw_columns =
+            cols.raw_columns
+                .uncompress(&input[cols.data.clone()], out, self.max_len)?;
         raw_columns.write(meta_out);
         Ok(Cols {
             data: start..out.len(),
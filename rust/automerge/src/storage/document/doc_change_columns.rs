@@ -61,11 +61,15 @@ pub(crate) struct DocChangeColumns {
     message: RleRange<smol_str::SmolStr>,
     deps: DepsRange,
     extra: ValueRange,
-    #[allow(dead_code)]
     other: Columns,
 }
 
 impl DocChangeColumns {
+    /// The columns which this version of automerge doesn't know how to read
+    pub(crate) fn unknown_columns(&self) -> impl Iterator<Item = ColumnSpec> + '_ {
+        self.other.iter().map(|c| c.spec())
+    }
+
     pub(crate) fn iter<'a>(&self, data: &'a [u8]) -> DocChangeColumnIter<'a> {
         DocChangeColumnIter {
             actors: self.actor.decoder(data),
@@ -115,6 +119,61 @@ impl DocChangeColumns {
         }
     }
 
+    /// Encode `changes` one group of related columns at a time, see
+    /// [`super::DocOpColumns::encode_groups()`]
+    pub(crate) fn encode_groups<'a, I, C, E, F>(changes: I, mut f: F) -> Result<(), E>
+    where
+        C: AsChangeMeta<'a>,
+        I: Iterator<Item = C> + Clone,
+        F: FnMut(RawColumns<compression::Uncompressed>, &[u8]) -> Result<(), E>,
+    {
+        let mut out = Vec::new();
+        let mut group = |encode: &mut dyn FnMut(&mut Self, &mut Vec<u8>)| {
+            let mut cols = Self::empty();
+            out.clear();
+            encode(&mut cols, &mut out);
+            f(cols.raw_columns(), &out)
+        };
+        group(&mut |c, out| {
+            c.actor = RleRange::encode(changes.clone().map(|c| Some(c.actor())), out)
+        })?;
+        group(&mut |c, out| {
+            c.seq = DeltaRange::encode(changes.clone().map(|c| Some(c.seq() as i64)), out)
+        })?;
+        group(&mut |c, out| {
+            c.max_op = DeltaRange::encode(changes.clone().map(|c| Some(c.max_op() as i64)), out)
+        })?;
+        group(&mut |c, out| {
+            c.time = DeltaRange::encode(changes.clone().map(|c| Some(c.timestamp())), out)
+        })?;
+        group(&mut |c, out| {
+            c.message = RleRange::encode(changes.clone().map(|c| c.message()), out)
+        })?;
+        group(&mut |c, out| c.deps = DepsRange::encode(changes.clone().map(|c| c.deps()), out))?;
+        group(&mut |c, out| {
+            c.extra = ValueRange::encode(
+                changes
+                    .clone()
+                    .map(|c| Cow::Owned(ScalarValue::Bytes(c.extra().to_vec()))),
+                out,
+            )
+        })
+    }
+
+    /// Columns which are all empty
+    fn empty() -> Self {
+        Self {
+            actor: (0..0).into(),
+            seq: (0..0).into(),
+            max_op: (0..0).into(),
+            time: (0..0).into(),
+            message: (0..0).into(),
+            deps: DepsRange::new((0..0).into(), (0..0).into()),
+            extra: ValueRange::new((0..0).into(), (0..0).into()),
+            other: Columns::empty(),
+        }
+    }
+
     pub(crate) fn raw_columns(&self) -> RawColumns<compression::Uncompressed> {
         let mut cols = vec![
             RawColumn::new(
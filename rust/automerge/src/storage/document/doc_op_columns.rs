@@ -54,7 +54,6 @@ pub(crate) struct DocOpColumns {
     action: RleRange<u64>,
     val: ValueRange,
     succ: OpIdListRange,
-    #[allow(dead_code)]
     other: Columns,
     expand: MaybeBooleanRange,
     mark_name: RleRange<smol_str::SmolStr>,
@@ -92,13 +91,73 @@ impl DocOpColumns {
         O: convert::OpId<usize>,
         C: AsDocOp<'a, OpId = O>,
     {
-        if ops.len() > 30000 {
+        if Self::encode_passes(ops.len()) == 1 {
             Self::encode_rowwise(ops, out)
         } else {
             Self::encode_columnwise(ops, out)
         }
     }
 
+    /// Encode `ops` one group of related columns at a time, calling `f` with the raw columns of
+    /// each group and the data they refer to
+    ///
+    /// Concatenating the raw columns and data of every group gives the same column metadata and
+    /// data as [`Self::encode()`], but only one group is held in memory at a time. This iterates
+    /// over the ops once per group.
+    pub(crate) fn encode_groups<'a, I, C, O, E, F>(ops: I, mut f: F) -> Result<(), E>
+    where
+        I: Iterator<Item = C> + Clone,
+        O: convert::OpId<usize>,
+        C: AsDocOp<'a, OpId = O>,
+        F: FnMut(RawColumns<compression::Uncompressed>, &[u8]) -> Result<(), E>,
+    {
+        let mut out = Vec::new();
+        let mut group = |encode: &mut dyn FnMut(&mut Self, &mut Vec<u8>)| {
+            let mut cols = Self::empty();
+            out.clear();
+            encode(&mut cols, &mut out);
+            f(cols.raw_columns(), &out)
+        };
+        group(&mut |c, out| c.obj = ObjIdRange::encode(ops.clone().map(|o| o.obj()), out))?;
+        group(&mut |c, out| c.key = KeyRange::encode(ops.clone().map(|o| o.key()), out))?;
+        group(&mut |c, out| c.id = OpIdRange::encode(ops.clone().map(|o| o.id()), out))?;
+        group(&mut |c, out| c.insert = BooleanRange::encode(ops.clone().map(|o| o.insert()), out))?;
+        group(&mut |c, out| {
+            c.action = RleRange::encode(ops.clone().map(|o| Some(o.action())), out)
+        })?;
+        group(&mut |c, out| c.val = ValueRange::encode(ops.clone().map(|o| o.val()), out))?;
+        group(&mut |c, out| c.succ = OpIdListRange::encode(ops.clone().map(|o| o.succ()), out))?;
+        group(&mut |c, out| {
+            c.expand = MaybeBooleanRange::encode(ops.clone().map(|o| o.expand()), out)
+        })?;
+        group(&mut |c, out| c.mark_name = RleRange::encode(ops.clone().map(|o| o.mark_name()), out))
+    }
+
+    /// Columns which are all empty
+    fn empty() -> Self {
+        Self {
+            obj: None,
+            key: KeyRange::new((0..0).into(), (0..0).into(), (0..0).into()),
+            id: OpIdRange::new((0..0).into(), (0..0).into()),
+            insert: (0..0).into(),
+            action: (0..0).into(),
+            val: ValueRange::new((0..0).into(), (0..0).into()),
+            succ: OpIdListRange::new((0..0).into(), (0..0).into(), (0..0).into()),
+            expand: (0..0).into(),
+            mark_name: (0..0).into(),
+            other: Columns::empty(),
+        }
+    }
+
+    /// The number of times [`Self::encode()`] iterates over `num_ops` ops
+    pub(crate) fn encode_passes(num_ops: usize) -> usize {
+        if num_ops > 30000 {
+            1
+        } else {
+            9
+        }
+    }
+
     fn encode_columnwise<'a, I, O, C>(ops: I, out: &mut Vec<u8>) -> DocOpColumns
     where
         I: Iterator<Item = C> + Clone,
@@ -195,6 +254,11 @@ impl DocOpColumns {
         }
     }
 
+    /// The columns which this version of automerge doesn't know how to read
+    pub(crate) fn unknown_columns(&self) -> impl Iterator<Item = ColumnSpec> + '_ {
+        self.other.iter().map(|c| c.spec())
+    }
+
     pub(crate) fn iter<'a>(&self, data: &'a [u8]) -> DocOpColumnIter<'a> {
         DocOpColumnIter {
             id: self.id.iter(data),
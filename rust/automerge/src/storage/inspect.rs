@@ -0,0 +1,134 @@
+use std::collections::BTreeSet;
+
+use crate::storage::{self, load::Error, parse};
+use crate::{ActorId, AutomergeError, ChangeHash, Limits};
+
+/// A summary of the chunks in a saved document, see [`inspect()`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DocInspection {
+    /// The number of document chunks
+    pub document_chunks: usize,
+    /// The number of change chunks, compressed or not
+    pub change_chunks: usize,
+    /// The union of the heads recorded in the document chunks, sorted
+    pub document_heads: Vec<ChangeHash>,
+    /// Every actor referenced by any chunk, sorted
+    pub actors: Vec<ActorId>,
+    /// The total number of changes in all the chunks
+    pub num_changes: usize,
+    /// The total number of operations stored in all the chunks
+    ///
+    /// Document chunks don't store deletions as separate operations, so this can be less than
+    /// the number of operations in the changes the document was made from.
+    pub num_ops: usize,
+    /// The total number of operations which create an object, including objects which were later
+    /// deleted
+    pub num_objects: usize,
+}
+
+impl DocInspection {
+    fn check(&self, limits: &Limits) -> Result<(), AutomergeError> {
+        if self.num_ops > limits.max_ops {
+            Err(AutomergeError::LimitExceeded("ops", limits.max_ops))
+        } else if self.num_objects > limits.max_objects {
+            Err(AutomergeError::LimitExceeded("objects", limits.max_objects))
+        } else if self.num_changes > limits.max_change_count {
+            Err(AutomergeError::LimitExceeded(
+                "changes",
+                limits.max_change_count,
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn add_op(&mut self, action: u64, limits: &Limits) -> Result<(), AutomergeError> {
+        self.num_ops += 1;
+        if matches!(action, 0 | 2 | 4 | 6) {
+            self.num_objects += 1;
+        }
+        self.check(limits)
+    }
+
+    /// Count the changes and operations in `chunk`, failing as soon as the counts exceed
+    /// `limits`
+    pub(crate) fn add_chunk(
+        &mut self,
+        chunk: &storage::Chunk<'_>,
+        limits: &Limits,
+    ) -> Result<(), AutomergeError> {
+        match chunk {
+            storage::Chunk::Document(d) => {
+                self.document_chunks += 1;
+                for change in d.iter_changes() {
+                    change.map_err(|e| Error::InvalidChangeColumns(Box::new(e)))?;
+                    self.num_changes += 1;
+                    self.check(limits)?;
+                }
+                for op in d.iter_ops() {
+                    let op = op.map_err(|e| Error::InvalidOpsColumns(Box::new(e)))?;
+                    self.add_op(op.action, limits)?;
+                }
+            }
+            storage::Chunk::Change(change) | storage::Chunk::CompressedChange(change, _) => {
+                self.change_chunks += 1;
+                self.num_changes += 1;
+                self.check(limits)?;
+                for op in change.iter_ops() {
+                    let op = op.map_err(|e| Error::InvalidChangeColumns(Box::new(e)))?;
+                    self.add_op(op.action, limits)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Summarise the chunks in `bytes` without loading them into a document
+///
+/// This reads the chunk headers and the metadata columns of each chunk, it doesn't construct
+/// the operations of the document, so it is much cheaper than [`crate::Automerge::load()`]. The
+/// checksum of every chunk is verified.
+///
+/// ```
+/// # use automerge::{transaction::Transactable, AutoCommit, ROOT};
+/// let mut doc = AutoCommit::new();
+/// doc.put(ROOT, "key", "value").unwrap();
+/// let heads = doc.get_heads();
+/// let inspection = automerge::storage::inspect(&doc.save()).unwrap();
+/// assert_eq!(inspection.document_chunks, 1);
+/// assert_eq!(inspection.document_heads, heads);
+/// assert_eq!(inspection.num_changes, 1);
+/// ```
+///
+/// # Errors
+///
+/// Returns [`AutomergeError::Load`] if any chunk is malformed or has a bad checksum
+pub fn inspect(bytes: &[u8]) -> Result<DocInspection, AutomergeError> {
+    let mut inspection = DocInspection::default();
+    let mut heads = BTreeSet::new();
+    let mut actors = BTreeSet::new();
+    let mut data = parse::Input::new(bytes);
+    while !data.is_empty() {
+        let (remaining, chunk) =
+            storage::Chunk::parse(data).map_err(|e| Error::Parse(Box::new(e)))?;
+        if !chunk.checksum_valid() {
+            return Err(Error::BadChecksum.into());
+        }
+        match &chunk {
+            storage::Chunk::Document(d) => {
+                heads.extend(d.heads().iter().copied());
+                actors.extend(d.actors().iter().cloned());
+            }
+            storage::Chunk::Change(change) | storage::Chunk::CompressedChange(change, _) => {
+                actors.insert(change.actor().clone());
+                actors.extend(change.other_actors().iter().cloned());
+            }
+        }
+        inspection.add_chunk(&chunk, &Limits::default())?;
+        data = remaining.reset();
+    }
+    inspection.document_heads = heads.into_iter().collect();
+    inspection.actors = actors.into_iter().collect();
+    Ok(inspection)
+}
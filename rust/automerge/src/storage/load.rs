@@ -1,8 +1,10 @@
+use std::io::Read;
+
 use tracing::instrument;
 
 use crate::{
     change::Change,
-    storage::{self, parse},
+    storage::{self, chunk, parse, MAGIC_BYTES},
 };
 
 pub(crate) mod change_collector;
@@ -25,6 +27,10 @@ pub enum Error {
     InflateDocument(Box<dyn std::error::Error + Send + Sync + 'static>),
     #[error("bad checksum")]
     BadChecksum,
+    #[error("the data ended in the middle of a chunk")]
+    Truncated,
+    #[error("unable to read chunk: {0}")]
+    Io(#[source] std::io::Error),
 }
 
 pub(crate) enum LoadedChanges<'a> {
@@ -69,7 +75,7 @@ pub(crate) fn load_changes<'a>(mut data: parse::Input<'a>) -> LoadedChanges<'a>
     LoadedChanges::Complete(changes)
 }
 
-fn load_next_change<'a>(
+pub(crate) fn load_next_change<'a>(
     data: parse::Input<'a>,
     changes: &mut Vec<Change>,
 ) -> Result<parse::Input<'a>, Error> {
@@ -77,6 +83,15 @@ fn load_next_change<'a>(
     if !chunk.checksum_valid() {
         return Err(Error::BadChecksum);
     }
+    load_chunk(chunk, changes)?;
+    Ok(remaining)
+}
+
+/// Decode the changes in a chunk whose checksum has already been checked
+pub(crate) fn load_chunk(
+    chunk: storage::Chunk<'_>,
+    changes: &mut Vec<Change>,
+) -> Result<(), Error> {
     match chunk {
         storage::Chunk::Document(d) => {
             tracing::trace!("loading document chunk");
@@ -106,5 +121,68 @@ fn load_next_change<'a>(
             changes.push(change);
         }
     };
-    Ok(remaining)
+    Ok(())
+}
+
+/// Read the bytes of the next chunk (including the header) from `r`
+///
+/// Returns `Ok(None)` if `r` ends before the start of a chunk and [`Error::Truncated`] if it ends
+/// part way through one. The checksum is not checked here, that happens when the chunk is
+/// parsed.
+pub(crate) fn read_chunk<R: Read>(r: &mut R) -> Result<Option<Vec<u8>>, Error> {
+    // magic bytes, checksum and chunk type
+    let mut bytes = vec![0; MAGIC_BYTES.len() + 4 + 1];
+    match read_fully(r, &mut bytes)? {
+        0 => return Ok(None),
+        n if n < bytes.len() => return Err(Error::Truncated),
+        _ => {}
+    }
+    if bytes[..MAGIC_BYTES.len()] != MAGIC_BYTES {
+        return Err(Error::Parse(Box::new(
+            chunk::error::Header::InvalidMagicBytes,
+        )));
+    }
+
+    // the length of the chunk data as an unsigned leb128
+    let mut data_len: u64 = 0;
+    let mut shift = 0;
+    loop {
+        let mut byte = [0];
+        if read_fully(r, &mut byte)? == 0 {
+            return Err(Error::Truncated);
+        }
+        bytes.push(byte[0]);
+        if shift > 63 || (shift == 63 && byte[0] > 1) {
+            return Err(Error::Parse(Box::new(parse::leb128::Error::Leb128TooLarge)));
+        }
+        data_len |= u64::from(byte[0] & 0x7f) << shift;
+        if byte[0] & 0x80 == 0 {
+            break;
+        }
+        shift += 7;
+    }
+
+    // don't trust `data_len` enough to allocate it up front
+    let header_len = bytes.len();
+    r.take(data_len)
+        .read_to_end(&mut bytes)
+        .map_err(Error::Io)?;
+    if ((bytes.len() - header_len) as u64) < data_len {
+        return Err(Error::Truncated);
+    }
+    Ok(Some(bytes))
+}
+
+/// Like `Read::read_exact` but returns the number of bytes read if `r` ends first
+fn read_fully<R: Read>(r: &mut R, buf: &mut [u8]) -> Result<usize, Error> {
+    let mut read = 0;
+    while read < buf.len() {
+        match r.read(&mut buf[read..]) {
+            Ok(0) => break,
+            Ok(n) => read += n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(Error::Io(e)),
+        }
+    }
+    Ok(read)
 }
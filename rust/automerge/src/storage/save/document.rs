@@ -27,6 +27,71 @@ pub(crate) fn save_document<'a, I, O>(
     heads: &[ChangeHash],
     config: Option<CompressConfig>,
 ) -> Vec<u8>
+where
+    I: Iterator<Item = &'a Change> + Clone + 'a,
+    O: Iterator<Item = (&'a ObjId, Op<'a>)> + Clone + ExactSizeIterator,
+{
+    let mut bytes = Vec::new();
+    // writing to a `Vec` can't fail
+    encode_document(
+        changes,
+        ops,
+        actors,
+        props,
+        heads,
+        config,
+        Output::Bytes(&mut bytes),
+    )
+    .unwrap();
+    bytes
+}
+
+/// Like [`save_document()`] but writes the document chunk to `w` without building it in memory,
+/// see [`Document::write()`]
+///
+/// # Panics
+///
+/// As for [`save_document()`]
+#[tracing::instrument(skip(changes, ops, actors, props, config, w))]
+pub(crate) fn write_document<'a, I, O>(
+    changes: I,
+    ops: O,
+    actors: &'a IndexedCache<ActorId>,
+    props: &IndexedCache<String>,
+    heads: &[ChangeHash],
+    config: Option<CompressConfig>,
+    w: &mut dyn std::io::Write,
+) -> std::io::Result<()>
+where
+    I: Iterator<Item = &'a Change> + Clone + 'a,
+    O: Iterator<Item = (&'a ObjId, Op<'a>)> + Clone + ExactSizeIterator,
+{
+    encode_document(
+        changes,
+        ops,
+        actors,
+        props,
+        heads,
+        config,
+        Output::Writer(w),
+    )
+}
+
+/// Where [`encode_document()`] puts the document chunk
+enum Output<'w> {
+    Bytes(&'w mut Vec<u8>),
+    Writer(&'w mut dyn std::io::Write),
+}
+
+fn encode_document<'a, I, O>(
+    changes: I,
+    ops: O,
+    actors: &'a IndexedCache<ActorId>,
+    props: &IndexedCache<String>,
+    heads: &[ChangeHash],
+    config: Option<CompressConfig>,
+    output: Output<'_>,
+) -> std::io::Result<()>
 where
     I: Iterator<Item = &'a Change> + Clone + 'a,
     O: Iterator<Item = (&'a ObjId, Op<'a>)> + Clone + ExactSizeIterator,
@@ -54,14 +119,21 @@ where
         graph: &hash_graph,
     });
 
-    let doc = Document::new(
-        actor_ids,
-        hash_graph.heads_with_indices(heads.to_vec()),
-        doc_ops,
-        changes,
-        config.unwrap_or(CompressConfig::Threshold(DEFLATE_MIN_SIZE)),
-    );
-    doc.into_bytes()
+    let heads_with_indices = hash_graph.heads_with_indices(heads.to_vec());
+    let config = config.unwrap_or(CompressConfig::Threshold {
+        threshold: DEFLATE_MIN_SIZE,
+        level: flate2::Compression::default(),
+    });
+    match output {
+        Output::Bytes(bytes) => {
+            *bytes =
+                Document::new(actor_ids, heads_with_indices, doc_ops, changes, config).into_bytes();
+            Ok(())
+        }
+        Output::Writer(w) => {
+            Document::write(actor_ids, heads_with_indices, doc_ops, changes, config, w)
+        }
+    }
 }
 
 struct HashGraph {
@@ -72,8 +72,9 @@ use serde::ser::SerializeMap;
 use std::collections::{HashMap, HashSet};
 
 use crate::{
+    exid::ExId,
     patches::{PatchLog, TextRepresentation},
-    storage::{parse, ReadChangeOpError},
+    storage::{self, parse, ReadChangeOpError},
     Automerge, AutomergeError, Change, ChangeHash, ReadDoc,
 };
 
@@ -87,7 +88,7 @@ mod v1_compat_test;
 
 pub use bloom::{BloomFilter, DecodeError as DecodeBloomError};
 pub use state::DecodeError as DecodeStateError;
-pub use state::{Have, State};
+pub use state::{Have, State, Stats, SyncEvent, SyncTrace};
 
 /// A document which can take part in the sync protocol
 ///
@@ -106,11 +107,23 @@ pub trait SyncDoc {
     fn generate_sync_message(&self, sync_state: &mut State) -> Option<Message>;
 
     /// Apply a received sync message to this document and `sync_state`
+    ///
+    /// Returns the hashes of the changes which were applied as a result of this message, in the
+    /// order they were applied, which respects their dependencies. This includes changes which
+    /// were received earlier but were waiting for the dependencies this message delivered. If
+    /// the message applied no changes the returned vector is empty.
+    ///
+    /// The checksum of every change in the message is checked against its contents before any of
+    /// them are applied. If one doesn't match this returns
+    /// [`AutomergeError::ChecksumMismatch`] with the hash of the bad change as received, after
+    /// applying the changes before it if
+    /// [`State::with_apply_changes_before_bad_checksum()`] is set. `sync_state` is left as it was,
+    /// the message is treated as if it never arrived.
     fn receive_sync_message(
         &mut self,
         sync_state: &mut State,
         message: Message,
-    ) -> Result<(), AutomergeError>;
+    ) -> Result<Vec<ChangeHash>, AutomergeError>;
 
     /// Apply a received sync message to this document and `sync_state`, logging any changes that
     /// are made to `patch_log`
@@ -125,12 +138,14 @@ pub trait SyncDoc {
     /// * `message` - The [`Message`] to receive
     /// * `patch_log` - A [`PatchLog`] which will be updated with any changes that are made to the
     ///                 current state of the document due to the received sync message
+    ///
+    /// Returns the hashes of the applied changes, see [`Self::receive_sync_message()`]
     fn receive_sync_message_log_patches(
         &mut self,
         sync_state: &mut State,
         message: Message,
         patch_log: &mut PatchLog,
-    ) -> Result<(), AutomergeError>;
+    ) -> Result<Vec<ChangeHash>, AutomergeError>;
 }
 
 const MESSAGE_TYPE_SYNC: u8 = 0x42; // first byte of a sync message, for identification
@@ -185,7 +200,7 @@ impl SyncDoc for Automerge {
                 if !first_have
                     .last_sync
                     .iter()
-                    .all(|hash| self.get_change_by_hash(hash).is_some())
+                    .all(|hash| self.has_change(hash))
                 {
                     let reset_msg = Message {
                         heads: our_heads,
@@ -198,11 +213,13 @@ impl SyncDoc for Automerge {
                         ]),
                         version: MessageVersion::V1,
                     };
+                    sync_state.emit(SyncEvent::RequestedReset);
                     return Some(reset_msg);
                 }
             }
         }
 
+        let mut whole_document = false;
         let (message_builder, sent_hashes) = if let (Some(their_have), Some(their_need)) = (
             sync_state.their_have.as_ref(),
             sync_state.their_need.as_ref(),
@@ -213,7 +230,15 @@ impl SyncDoc for Automerge {
                 .map(|h| h.is_empty())
                 .unwrap_or(false)
                 && !sync_state.have_responded
-                && sync_state.supports_v2_messages();
+                && sync_state.supports_v2_messages()
+                && sync_state
+                    .max_changes_per_message
+                    .map(|max| self.stats().num_changes as usize <= max)
+                    .unwrap_or(true);
+            // changes removed by `trim_changes` can only be sent as part of the whole document
+            let send_doc = send_doc
+                || (sync_state.supports_v2_messages()
+                    && their_need.iter().any(|hash| self.is_trimmed(hash)));
 
             if send_doc {
                 let hashes = self
@@ -221,15 +246,19 @@ impl SyncDoc for Automerge {
                     .iter()
                     .map(|c| c.hash())
                     .collect::<Vec<_>>();
+                whole_document = true;
                 (MessageBuilder::new_v2(self.save()), hashes)
             } else {
                 let all_changes = self
                     .get_changes_to_send(their_have, their_need)
                     .expect("Should have only used hashes that are in the document");
+                let all_changes =
+                    self.prioritize_changes(all_changes, &sync_state.priority_objects);
                 // deduplicate the changes to send with those we have already sent and clone it now
                 let changes = all_changes
                     .into_iter()
-                    .filter(|change| !sync_state.sent_hashes.contains(&change.hash()));
+                    .filter(|change| !sync_state.sent_hashes.contains(&change.hash()))
+                    .take(sync_state.max_changes_per_message.unwrap_or(usize::MAX));
                 let hashes = changes.clone().map(|c| c.hash()).collect::<Vec<_>>();
                 if sync_state.supports_v2_messages() {
                     let encoded = changes
@@ -257,9 +286,11 @@ impl SyncDoc for Automerge {
 
         if heads_unchanged && sync_state.have_responded {
             if heads_equal && !message_builder.has_changes_to_send() {
+                sync_state.emit(SyncEvent::NothingToSend { in_flight: false });
                 return None;
             }
             if sync_state.in_flight {
+                sync_state.emit(SyncEvent::NothingToSend { in_flight: true });
                 return None;
             }
         }
@@ -274,6 +305,12 @@ impl SyncDoc for Automerge {
 
         sync_state.have_responded = true;
         sync_state.last_sent_heads.clone_from(&our_heads);
+        sync_state.emit(SyncEvent::SentMessage {
+            changes: sent_hashes.len(),
+            whole_document,
+            bloom_filters: our_have.len(),
+            need: our_need.len(),
+        });
         sync_state.sent_hashes.extend(sent_hashes);
 
         let sync_message = message_builder
@@ -291,7 +328,7 @@ impl SyncDoc for Automerge {
         &mut self,
         sync_state: &mut State,
         message: Message,
-    ) -> Result<(), AutomergeError> {
+    ) -> Result<Vec<ChangeHash>, AutomergeError> {
         let mut patch_log = PatchLog::inactive(TextRepresentation::default());
         self.receive_sync_message_inner(sync_state, message, &mut patch_log)
     }
@@ -301,7 +338,7 @@ impl SyncDoc for Automerge {
         sync_state: &mut State,
         message: Message,
         patch_log: &mut PatchLog,
-    ) -> Result<(), AutomergeError> {
+    ) -> Result<Vec<ChangeHash>, AutomergeError> {
         self.receive_sync_message_inner(sync_state, message, patch_log)
     }
 }
@@ -316,6 +353,45 @@ impl Automerge {
         }
     }
 
+    /// Move the changes which touch `objs`, and the changes they depend on, to the front of
+    /// `changes`, otherwise keeping the order of `changes`
+    fn prioritize_changes<'a>(&self, changes: Vec<&'a Change>, objs: &[ExId]) -> Vec<&'a Change> {
+        let targets = objs
+            .iter()
+            .filter_map(|obj| self.exid_to_obj(obj).ok())
+            .map(|meta| meta.id)
+            .collect::<Vec<_>>();
+        if targets.is_empty() {
+            return changes;
+        }
+        let mut touched = HashMap::new();
+        let mut prioritized = HashSet::new();
+        // dependencies come before their dependents so walking backwards visits a change's
+        // dependents before the change itself
+        for change in changes.iter().rev() {
+            let hash = change.hash();
+            let is_priority = prioritized.contains(&hash)
+                || self
+                    .change_objects(change)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .any(|obj| {
+                        *touched
+                            .entry(obj)
+                            .or_insert_with(|| targets.iter().any(|t| self.is_in_subtree(obj, *t)))
+                    });
+            if is_priority {
+                prioritized.insert(hash);
+                prioritized.extend(change.deps().iter().copied());
+            }
+        }
+        let (mut first, rest): (Vec<_>, Vec<_>) = changes
+            .into_iter()
+            .partition(|c| prioritized.contains(&c.hash()));
+        first.extend(rest);
+        first
+    }
+
     fn get_changes_to_send(
         &self,
         have: &[Have],
@@ -392,9 +468,9 @@ impl Automerge {
         sync_state: &mut State,
         message: Message,
         patch_log: &mut PatchLog,
-    ) -> Result<(), AutomergeError> {
-        sync_state.in_flight = false;
+    ) -> Result<Vec<ChangeHash>, AutomergeError> {
         let before_heads = self.get_heads();
+        let applied_before = self.num_applied_changes();
 
         let Message {
             heads: message_heads,
@@ -405,22 +481,47 @@ impl Automerge {
             ..
         } = message;
 
+        let changes_is_empty = message_changes.is_empty();
+        let num_message_changes = message_changes.len();
+        let (valid_chunks, bad_checksum) = parse_until_bad_checksum(&message_changes.0);
+        if let Some(hash) = bad_checksum {
+            // the message is rejected so the sync state is left as it was before it arrived, only
+            // the document gets the changes before the bad one
+            if sync_state.apply_changes_before_bad_checksum {
+                for chunks in valid_chunks {
+                    self.load_chunks_log_patches(chunks, patch_log)?;
+                }
+            }
+            sync_state.emit(SyncEvent::ReceivedMessage {
+                changes: num_message_changes,
+                applied: self.num_applied_changes() - applied_before,
+                their_heads: message_heads.len(),
+                their_need: message_need.len(),
+            });
+            return Err(AutomergeError::ChecksumMismatch { hash });
+        }
+
+        sync_state.in_flight = false;
         if let Some(caps) = supported_capabilities {
             sync_state.their_capabilities = Some(caps);
         }
-
-        let changes_is_empty = message_changes.is_empty();
-        if !changes_is_empty {
-            for change in &message_changes.0 {
-                self.load_incremental_log_patches(change, patch_log)?;
+        if !valid_chunks.is_empty() {
+            let missing_before = self.get_missing_deps(&[]);
+            for chunks in valid_chunks {
+                sync_state.stats.changes_resent +=
+                    self.load_chunks_log_patches(chunks, patch_log)? as u64;
             }
+            sync_state.stats.bloom_false_positives += self
+                .get_missing_deps(&[])
+                .iter()
+                .filter(|hash| !missing_before.contains(hash))
+                .count() as u64;
             sync_state.shared_heads = advance_heads(
                 &before_heads.iter().collect(),
                 &self.get_heads().into_iter().collect(),
                 &sync_state.shared_heads,
             );
         }
-
         // trim down the sent hashes to those that we know they haven't seen
         self.filter_changes(&message_heads, &mut sync_state.sent_hashes)?;
 
@@ -430,7 +531,7 @@ impl Automerge {
 
         let known_heads = message_heads
             .iter()
-            .filter(|head| self.get_change_by_hash(head).is_some())
+            .filter(|head| self.has_change(head))
             .collect::<Vec<_>>();
         if known_heads.len() == message_heads.len() {
             sync_state.shared_heads.clone_from(&message_heads);
@@ -438,6 +539,7 @@ impl Automerge {
             if message_heads.is_empty() {
                 sync_state.last_sent_heads = Default::default();
                 sync_state.sent_hashes = Default::default();
+                sync_state.emit(SyncEvent::PeerHasNoChanges);
             }
         } else {
             sync_state.shared_heads = sync_state
@@ -450,11 +552,18 @@ impl Automerge {
                 .collect::<Vec<_>>();
         }
 
+        let applied = self.hashes_applied_since(applied_before);
+        sync_state.emit(SyncEvent::ReceivedMessage {
+            changes: num_message_changes,
+            applied: applied.len(),
+            their_heads: message_heads.len(),
+            their_need: message_need.len(),
+        });
+
         sync_state.their_have = Some(message_have);
         sync_state.their_heads = Some(message_heads);
         sync_state.their_need = Some(message_need);
-
-        Ok(())
+        Ok(applied)
     }
 }
 
@@ -739,6 +848,41 @@ fn advance_heads(
     advanced_heads
 }
 
+/// Parse the chunks in each entry of `changes` up to the first one whose checksum doesn't match
+/// its contents, and the hash of that chunk
+///
+/// Every chunk is parsed and checked once. As with [`Automerge::load_incremental()`], bytes which
+/// can't be parsed end the entry they are in.
+fn parse_until_bad_checksum(
+    changes: &[Vec<u8>],
+) -> (Vec<Vec<storage::Chunk<'_>>>, Option<ChangeHash>) {
+    let mut valid = Vec::with_capacity(changes.len());
+    for bytes in changes {
+        let mut chunks = Vec::new();
+        let mut input = parse::Input::new(bytes);
+        while !input.is_empty() {
+            let (remaining, chunk) = match storage::Chunk::parse(input) {
+                Ok(parsed) => parsed,
+                Err(error) => {
+                    tracing::warn!(successful_chunks=chunks.len(), err=?error, "partial load");
+                    break;
+                }
+            };
+            if !chunk.checksum_valid() {
+                let hash = chunk.hash();
+                if !chunks.is_empty() {
+                    valid.push(chunks);
+                }
+                return (valid, Some(hash));
+            }
+            chunks.push(chunk);
+            input = remaining.reset();
+        }
+        valid.push(chunks);
+    }
+    (valid, None)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -858,6 +1002,348 @@ mod tests {
         assert!(doc.sync().generate_sync_message(&mut sync_state).is_none());
     }
 
+    #[test]
+    fn reset_sent_allows_resending() {
+        let mut doc1 = crate::AutoCommit::new();
+        doc1.put(crate::ROOT, "key", "value").unwrap();
+        let mut doc2 = crate::AutoCommit::new();
+        let mut s1 = State::new();
+        let mut s2 = State::new();
+
+        let msg = doc1.sync().generate_sync_message(&mut s1).unwrap();
+        doc2.sync().receive_sync_message(&mut s2, msg).unwrap();
+        let msg = doc2.sync().generate_sync_message(&mut s2).unwrap();
+        doc1.sync().receive_sync_message(&mut s1, msg).unwrap();
+        let msg = doc1.sync().generate_sync_message(&mut s1).unwrap();
+        assert!(!msg.changes.is_empty());
+        assert!(doc1.sync().generate_sync_message(&mut s1).is_none());
+
+        // pretend the message with the change was lost
+        let shared_heads = s1.shared_heads.clone();
+        s1.reset_sent();
+        assert_eq!(s1.shared_heads, shared_heads);
+        let msg = doc1.sync().generate_sync_message(&mut s1).unwrap();
+        assert!(!msg.changes.is_empty());
+        doc2.sync().receive_sync_message(&mut s2, msg).unwrap();
+        assert_eq!(doc1.get_heads(), doc2.get_heads());
+    }
+
+    #[test]
+    fn settings_and_stats_are_not_compared() {
+        use std::hash::{Hash, Hasher};
+        let hash = |state: &State| {
+            let mut hasher = std::collections::hash_map::DefaultHasher::new();
+            state.hash(&mut hasher);
+            hasher.finish()
+        };
+        let state = State::new();
+        let mut configured = State::new()
+            .with_max_changes_per_message(Some(1))
+            .with_priority_objects(vec![crate::ROOT])
+            .with_apply_changes_before_bad_checksum(true)
+            .with_trace(SyncTrace::new(|_| ()));
+        configured.stats.changes_resent = 1;
+        assert_eq!(configured, state);
+        assert_eq!(hash(&configured), hash(&state));
+        assert_eq!(configured.max_changes_per_message(), Some(1));
+        assert_eq!(configured.priority_objects(), &[crate::ROOT]);
+        assert!(configured.apply_changes_before_bad_checksum());
+
+        configured.in_flight = true;
+        assert_ne!(configured, state);
+    }
+
+    #[test]
+    fn refresh_after_local_changes_resumes_from_a_persisted_state() {
+        let mut doc1 = crate::AutoCommit::new().with_actor(ActorId::random());
+        let mut doc2 = crate::AutoCommit::new().with_actor(ActorId::random());
+        let mut s1 = State::new();
+        let mut s2 = State::new();
+        doc1.put(crate::ROOT, "x", 1).unwrap();
+        let saved_before_sync = doc1.save();
+        doc2.put(crate::ROOT, "y", 1).unwrap();
+        sync(&mut doc1, &mut doc2, &mut s1, &mut s2);
+        let shared_heads = doc1.get_heads();
+        let mut s1 = State::decode(&s1.encode()).unwrap();
+        let mut s2 = State::decode(&s2.encode()).unwrap();
+
+        // both sides change while offline
+        doc1.put(crate::ROOT, "a", 1).unwrap();
+        doc2.put(crate::ROOT, "b", 1).unwrap();
+        s1.refresh_after_local_changes(doc1.document());
+        s2.refresh_after_local_changes(doc2.document());
+        assert_eq!(s1.shared_heads, shared_heads);
+
+        let msg = doc1.sync().generate_sync_message(&mut s1).unwrap();
+        assert!(msg.changes.is_empty());
+        assert_eq!(msg.have.len(), 1);
+        assert_eq!(msg.have[0].last_sync, shared_heads);
+        doc2.sync().receive_sync_message(&mut s2, msg).unwrap();
+        sync(&mut doc1, &mut doc2, &mut s1, &mut s2);
+        assert_eq!(doc1.get_heads(), doc2.get_heads());
+
+        // the document was restored from a save which predates the persisted sync state
+        let mut restored = crate::AutoCommit::load(&saved_before_sync).unwrap();
+        let mut s1 = State::decode(&s1.encode()).unwrap();
+        let mut s2 = State::decode(&s2.encode()).unwrap();
+        s1.refresh_after_local_changes(restored.document());
+        s2.refresh_after_local_changes(doc2.document());
+        assert!(s1.shared_heads.is_empty());
+        sync(&mut restored, &mut doc2, &mut s1, &mut s2);
+        assert_eq!(restored.get_heads(), doc2.get_heads());
+        assert_eq!(restored.get(crate::ROOT, "b").unwrap().unwrap().0, 1.into());
+
+        // shared heads whose changes have been trimmed are still in the document
+        let shared_heads = doc2.get_heads();
+        let mut s2 = State::decode(&s2.encode()).unwrap();
+        assert_eq!(s2.shared_heads, shared_heads);
+        doc2.put(crate::ROOT, "c", 1).unwrap();
+        doc2.commit();
+        let heads = doc2.get_heads();
+        doc2.trim_changes(&heads).unwrap();
+        assert!(doc2.get_change_by_hash(&shared_heads[0]).is_none());
+        s2.refresh_after_local_changes(doc2.document());
+        assert_eq!(s2.shared_heads, shared_heads);
+    }
+
+    #[test]
+    fn stats_count_missing_dependencies_and_duplicate_changes() {
+        let mut doc1 = crate::AutoCommit::new();
+        doc1.put(crate::ROOT, "key", 1).unwrap();
+        doc1.commit();
+        doc1.put(crate::ROOT, "key", 2).unwrap();
+        doc1.commit();
+        let changes = doc1
+            .get_changes(&[])
+            .into_iter()
+            .map(|c| c.raw_bytes().to_vec())
+            .collect::<Vec<_>>();
+        let message = |changes: Vec<Vec<u8>>| Message {
+            heads: vec![],
+            need: vec![],
+            have: vec![],
+            changes: changes.into(),
+            supported_capabilities: None,
+            version: MessageVersion::V1,
+        };
+
+        let mut doc2 = crate::AutoCommit::new();
+        doc2.put(crate::ROOT, "other", 1).unwrap();
+        doc2.commit();
+        let mut s2 = State::new();
+
+        // the first change was left out as if the bloom filter said we had it
+        doc2.sync()
+            .receive_sync_message(&mut s2, message(vec![changes[1].clone()]))
+            .unwrap();
+        assert_eq!(s2.stats().bloom_false_positives, 1);
+        assert_eq!(s2.stats().changes_resent, 0);
+
+        doc2.sync()
+            .receive_sync_message(&mut s2, message(vec![changes[0].clone()]))
+            .unwrap();
+        doc2.sync()
+            .receive_sync_message(&mut s2, message(changes))
+            .unwrap();
+        assert_eq!(
+            s2.stats(),
+            Stats {
+                bloom_false_positives: 1,
+                changes_resent: 2,
+            }
+        );
+        assert_eq!(doc2.get(crate::ROOT, "key").unwrap().unwrap().0, 2.into());
+
+        // every change in a saved document counts, not just the entry it was sent in
+        doc2.sync()
+            .receive_sync_message(&mut s2, message(vec![doc1.save()]))
+            .unwrap();
+        assert_eq!(s2.stats().changes_resent, 4);
+    }
+
+    #[test]
+    fn trace_is_told_about_each_step() {
+        let events = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let trace = {
+            let events = events.clone();
+            SyncTrace::new(move |event| events.lock().unwrap().push(event))
+        };
+        // fixed actors and times so that a bloom filter false positive can't change the order of
+        // events
+        let commit_options = || crate::transaction::CommitOptions::default().with_time(0);
+        let mut doc1 = crate::AutoCommit::new().with_actor(crate::ActorId::from([1]));
+        doc1.put(crate::ROOT, "key", "value").unwrap();
+        doc1.commit_with(commit_options());
+        let mut doc2 = crate::AutoCommit::new().with_actor(crate::ActorId::from([2]));
+        doc2.put(crate::ROOT, "other", "value").unwrap();
+        doc2.commit_with(commit_options());
+        let mut s1 = State::new().with_trace(trace);
+        let mut s2 = State::new();
+        sync(&mut doc1, &mut doc2, &mut s1, &mut s2);
+        assert_eq!(doc1.get_heads(), doc2.get_heads());
+
+        let events = events.lock().unwrap().clone();
+        assert_eq!(
+            events[0],
+            SyncEvent::SentMessage {
+                changes: 0,
+                whole_document: false,
+                bloom_filters: 1,
+                need: 0,
+            }
+        );
+        // each side sends its change and asks for the other's
+        assert!(events.contains(&SyncEvent::SentMessage {
+            changes: 1,
+            whole_document: false,
+            bloom_filters: 1,
+            need: 1,
+        }));
+        assert!(events.contains(&SyncEvent::ReceivedMessage {
+            changes: 1,
+            applied: 1,
+            their_heads: 1,
+            their_need: 1,
+        }));
+        assert_eq!(
+            events.last(),
+            Some(&SyncEvent::NothingToSend { in_flight: false })
+        );
+        // the trace isn't part of the state
+        assert!(State::decode(&s1.encode()).unwrap().trace.is_none());
+        assert_eq!(s1.clone(), s1);
+    }
+
+    #[test]
+    fn receive_sync_message_returns_applied_hashes_in_order() {
+        let mut doc1 = crate::AutoCommit::new();
+        doc1.put(crate::ROOT, "key", 1).unwrap();
+        let first = doc1.commit().unwrap();
+        doc1.put(crate::ROOT, "key", 2).unwrap();
+        let second = doc1.commit().unwrap();
+        let changes = doc1
+            .get_changes(&[])
+            .into_iter()
+            .map(|c| c.raw_bytes().to_vec())
+            .collect::<Vec<_>>();
+        let message = |changes: Vec<Vec<u8>>| Message {
+            heads: vec![],
+            need: vec![],
+            have: vec![],
+            changes: changes.into(),
+            supported_capabilities: None,
+            version: MessageVersion::V1,
+        };
+
+        let mut doc2 = crate::AutoCommit::new();
+        let mut s2 = State::new();
+        let applied = doc2
+            .sync()
+            .receive_sync_message(&mut s2, message(vec![changes[1].clone()]))
+            .unwrap();
+        assert!(applied.is_empty());
+
+        // the queued change is applied once its dependency arrives
+        let applied = doc2
+            .sync()
+            .receive_sync_message(&mut s2, message(vec![changes[0].clone()]))
+            .unwrap();
+        assert_eq!(applied, vec![first, second]);
+
+        let applied = doc2
+            .sync()
+            .receive_sync_message(&mut s2, message(vec![]))
+            .unwrap();
+        assert!(applied.is_empty());
+    }
+
+    #[test]
+    fn max_changes_per_message_limits_each_message() {
+        let mut doc1 = crate::AutoCommit::new();
+        for i in 0..10 {
+            doc1.put(crate::ROOT, "key", i).unwrap();
+            doc1.commit();
+        }
+        let mut doc2 = crate::AutoCommit::new();
+        let mut s1 = State::new().with_max_changes_per_message(Some(3));
+        let mut s2 = State::new();
+
+        let mut iterations = 0;
+        loop {
+            let a_to_b = doc1.sync().generate_sync_message(&mut s1);
+            let b_to_a = doc2.sync().generate_sync_message(&mut s2);
+            if a_to_b.is_none() && b_to_a.is_none() {
+                break;
+            }
+            assert!(iterations < 20, "failed to sync");
+            if let Some(msg) = a_to_b {
+                let before = doc2.get_changes(&[]).len();
+                doc2.sync().receive_sync_message(&mut s2, msg).unwrap();
+                assert!(doc2.get_changes(&[]).len() - before <= 3);
+            }
+            if let Some(msg) = b_to_a {
+                doc1.sync().receive_sync_message(&mut s1, msg).unwrap();
+            }
+            iterations += 1;
+        }
+        assert_eq!(doc1.get_heads(), doc2.get_heads());
+    }
+
+    #[test]
+    fn priority_objects_are_sent_first() {
+        use crate::transaction::Transactable;
+
+        let mut doc1 = crate::AutoCommit::new();
+        let focus = doc1
+            .put_object(crate::ROOT, "focus", crate::ObjType::Map)
+            .unwrap();
+        doc1.commit();
+        let mut editor = doc1.fork();
+        for i in 0..10 {
+            doc1.put(crate::ROOT, "other", i).unwrap();
+            doc1.commit();
+        }
+        let nested = editor
+            .put_object(&focus, "nested", crate::ObjType::Map)
+            .unwrap();
+        editor.put(&nested, "key", "value").unwrap();
+        editor.commit();
+        doc1.merge(&mut editor).unwrap();
+
+        let mut doc2 = crate::AutoCommit::new();
+        let mut s1 = State::new()
+            .with_max_changes_per_message(Some(2))
+            .with_priority_objects(vec![focus]);
+        let mut s2 = State::new();
+
+        let mut first_changes = None;
+        let mut iterations = 0;
+        loop {
+            let a_to_b = doc1.sync().generate_sync_message(&mut s1);
+            let b_to_a = doc2.sync().generate_sync_message(&mut s2);
+            if a_to_b.is_none() && b_to_a.is_none() {
+                break;
+            }
+            assert!(iterations < 20, "failed to sync");
+            if let Some(msg) = a_to_b {
+                doc2.sync().receive_sync_message(&mut s2, msg).unwrap();
+                if first_changes.is_none() && !doc2.get_heads().is_empty() {
+                    first_changes = Some(doc2.get_changes(&[]).len());
+                    let nested = doc2.get(crate::ROOT, "focus").unwrap().unwrap().1;
+                    let nested = doc2.get(&nested, "nested").unwrap().unwrap().1;
+                    assert!(doc2.get(&nested, "key").unwrap().is_some());
+                    assert!(doc2.get(crate::ROOT, "other").unwrap().is_none());
+                }
+            }
+            if let Some(msg) = b_to_a {
+                doc1.sync().receive_sync_message(&mut s1, msg).unwrap();
+            }
+            iterations += 1;
+        }
+        assert_eq!(first_changes, Some(2));
+        assert_eq!(doc1.get_heads(), doc2.get_heads());
+    }
+
     #[test]
     fn first_response_is_some_even_if_no_changes() {
         // The first time we generate a sync message for a given peer we should always send a
@@ -1252,10 +1738,10 @@ mod tests {
                 panic!("failed to sync in {} iterations", MAX_ITER);
             }
             if let Some(msg) = a_to_b {
-                b.sync().receive_sync_message(b_sync_state, msg).unwrap()
+                b.sync().receive_sync_message(b_sync_state, msg).unwrap();
             }
             if let Some(msg) = b_to_a {
-                a.sync().receive_sync_message(a_sync_state, msg).unwrap()
+                a.sync().receive_sync_message(a_sync_state, msg).unwrap();
             }
             iterations += 1;
         }
@@ -1287,4 +1773,139 @@ mod tests {
         let (_, chunk) = Chunk::parse(Input::new(&changes.0[0])).unwrap();
         assert!(matches!(chunk, Chunk::Document(_)));
     }
+
+    #[test]
+    fn trimmed_doc_syncs_with_a_peer_missing_the_trimmed_changes() {
+        let mut doc1 = crate::AutoCommit::new();
+        for i in 0..10 {
+            doc1.put(crate::ROOT, "count", i).unwrap();
+            doc1.commit();
+        }
+        let mut full = doc1.fork();
+        let mut partial = crate::AutoCommit::new();
+        partial
+            .apply_changes(doc1.get_changes(&[])[..3].iter().cloned().cloned())
+            .unwrap();
+        partial.put(crate::ROOT, "other", "value").unwrap();
+        partial.commit();
+        let keep_from = doc1.get_heads();
+        doc1.put(crate::ROOT, "count", 10).unwrap();
+        doc1.commit();
+        doc1.trim_changes(&keep_from).unwrap();
+
+        for peer in [&mut full, &mut partial] {
+            let mut s1 = State::new();
+            let mut s2 = State::new();
+            sync(&mut doc1, peer, &mut s1, &mut s2);
+            assert_eq!(doc1.get_heads(), peer.get_heads());
+            assert_eq!(
+                doc1.hydrate(crate::ROOT, None).unwrap(),
+                peer.hydrate(crate::ROOT, None).unwrap()
+            );
+        }
+        assert_eq!(partial.get_changes(&[]).len(), 12);
+    }
+
+    #[test]
+    fn receive_sync_message_reports_the_change_with_a_bad_checksum() {
+        let mut source = crate::AutoCommit::new();
+        for value in ["aaaa", "bbbb", "cccc"] {
+            source.put(crate::ROOT, value, value).unwrap();
+            source.commit();
+        }
+        let changes: Vec<Vec<u8>> = source
+            .get_changes(&[])
+            .into_iter()
+            .map(|c| c.raw_bytes().to_vec())
+            .collect();
+        let mut corrupt = changes[1].clone();
+        let at = corrupt.windows(4).rposition(|w| w == b"bbbb").unwrap();
+        corrupt[at..at + 4].copy_from_slice(b"zzzz");
+        let heads = source.get_heads();
+        let first = source.get_changes(&[])[0].hash();
+        let message = |changes: ChunkList| Message {
+            heads: heads.clone(),
+            need: Vec::new(),
+            have: Vec::new(),
+            changes,
+            supported_capabilities: None,
+            version: MessageVersion::V1,
+        };
+
+        let separate = ChunkList::from(vec![
+            changes[0].clone(),
+            corrupt.clone(),
+            changes[2].clone(),
+        ]);
+        let together = ChunkList::from([changes[0].clone(), corrupt, changes[2].clone()].concat());
+        for (changes, apply_valid) in [
+            (separate.clone(), false),
+            (separate, true),
+            (together.clone(), false),
+            (together, true),
+        ] {
+            let mut doc = crate::AutoCommit::new();
+            doc.put(crate::ROOT, "local", 1).unwrap();
+            doc.commit();
+            let events = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+            let mut state = State::new()
+                .with_apply_changes_before_bad_checksum(apply_valid)
+                .with_trace({
+                    let events = events.clone();
+                    SyncTrace::new(move |event| events.lock().unwrap().push(event))
+                });
+            state.in_flight = true;
+            let before = state.clone();
+            let hash = match doc
+                .sync()
+                .receive_sync_message(&mut state, message(changes))
+            {
+                Err(AutomergeError::ChecksumMismatch { hash }) => hash,
+                other => panic!("unexpected result {:?}", other),
+            };
+            assert!(source.get_change_by_hash(&hash).is_none());
+            assert_eq!(doc.get_change_by_hash(&first).is_some(), apply_valid);
+            assert_eq!(doc.get(crate::ROOT, "bbbb").unwrap(), None);
+            assert_eq!(doc.get(crate::ROOT, "cccc").unwrap(), None);
+            // nothing the rejected message says is recorded
+            assert_eq!(state, before);
+            assert_eq!(state.their_heads, None);
+            assert_eq!(state.stats(), Stats::default());
+            assert!(matches!(
+                events.lock().unwrap().as_slice(),
+                [SyncEvent::ReceivedMessage { applied, .. }] if *applied == usize::from(apply_valid)
+            ));
+        }
+    }
+
+    #[test]
+    fn compacted_doc_syncs_with_an_uncompacted_peer() {
+        let mut doc1 = crate::AutoCommit::new();
+        let list = doc1
+            .put_object(crate::ROOT, "list", crate::ObjType::List)
+            .unwrap();
+        for i in 0..20 {
+            doc1.insert(&list, i, i as i64).unwrap();
+        }
+        doc1.commit();
+        let mut doc2 = doc1.fork();
+
+        // doc2 inserts after elements that doc1 deletes concurrently
+        for i in (0..20).step_by(2) {
+            doc1.delete(&list, i / 2).unwrap();
+        }
+        doc2.insert(&list, 5, "after four").unwrap();
+        doc2.insert(&list, 1, "after zero").unwrap();
+        doc1.compact();
+
+        let mut s1 = State::new();
+        let mut s2 = State::new();
+        sync(&mut doc1, &mut doc2, &mut s1, &mut s2);
+        assert_eq!(doc1.get_heads(), doc2.get_heads());
+        assert_eq!(
+            doc1.hydrate(crate::ROOT, None).unwrap(),
+            doc2.hydrate(crate::ROOT, None).unwrap()
+        );
+        assert_eq!(doc1.length(&list), 12);
+    }
 }
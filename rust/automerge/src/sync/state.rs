@@ -1,10 +1,11 @@
 use std::collections::BTreeSet;
+use std::sync::{Arc, Mutex};
 
 #[cfg(doc)]
 use super::SyncDoc;
 use super::{encode_hashes, BloomFilter, Capability};
 use crate::storage::parse;
-use crate::ChangeHash;
+use crate::{exid::ExId, Automerge, ChangeHash};
 
 const SYNC_STATE_TYPE: u8 = 0x43; // first byte of an encoded sync state, for identification
 
@@ -29,7 +30,12 @@ impl From<parse::leb128::Error> for DecodeError {
 /// This should be persisted using [`Self::encode()`] when you know you will be interacting with the
 /// same peer in multiple sessions. [`Self::encode()`] only encodes state which should be reused
 /// across connections.
-#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+///
+/// The settings which only affect how we sync with the peer, such as
+/// [`Self::with_max_changes_per_message()`], are set with methods rather than fields. They are
+/// not encoded, and neither the settings nor [`Self::stats()`] take part in comparing or hashing
+/// states.
+#[derive(Debug, Clone, Default)]
 pub struct State {
     /// The hashes which we know both peers have
     pub shared_heads: Vec<ChangeHash>,
@@ -60,6 +66,156 @@ pub struct State {
 
     /// The capabilities the other side has said they have
     pub their_capabilities: Option<Vec<Capability>>,
+
+    /// See [`Self::with_max_changes_per_message()`]
+    pub(crate) max_changes_per_message: Option<usize>,
+    /// See [`Self::with_priority_objects()`]
+    pub(crate) priority_objects: Vec<ExId>,
+    /// See [`Self::with_apply_changes_before_bad_checksum()`]
+    pub(crate) apply_changes_before_bad_checksum: bool,
+    /// See [`Self::stats()`]
+    pub(crate) stats: Stats,
+    /// See [`Self::with_trace()`]
+    pub(crate) trace: Option<SyncTrace>,
+}
+
+impl PartialEq for State {
+    fn eq(&self, other: &Self) -> bool {
+        // listing every field makes adding one without deciding whether it is compared an error
+        let Self {
+            shared_heads,
+            last_sent_heads,
+            their_heads,
+            their_need,
+            their_have,
+            sent_hashes,
+            in_flight,
+            have_responded,
+            their_capabilities,
+            max_changes_per_message: _,
+            priority_objects: _,
+            apply_changes_before_bad_checksum: _,
+            stats: _,
+            trace: _,
+        } = self;
+        *shared_heads == other.shared_heads
+            && *last_sent_heads == other.last_sent_heads
+            && *their_heads == other.their_heads
+            && *their_need == other.their_need
+            && *their_have == other.their_have
+            && *sent_hashes == other.sent_hashes
+            && *in_flight == other.in_flight
+            && *have_responded == other.have_responded
+            && *their_capabilities == other.their_capabilities
+    }
+}
+
+impl Eq for State {}
+
+impl std::hash::Hash for State {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        // the same fields as `PartialEq`
+        self.shared_heads.hash(state);
+        self.last_sent_heads.hash(state);
+        self.their_heads.hash(state);
+        self.their_need.hash(state);
+        self.their_have.hash(state);
+        self.sent_hashes.hash(state);
+        self.in_flight.hash(state);
+        self.have_responded.hash(state);
+        self.their_capabilities.hash(state);
+    }
+}
+
+/// Something which happened while synchronising with a peer, passed to the callback set with
+/// [`State::with_trace()`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SyncEvent {
+    /// [`SyncDoc::generate_sync_message()`] produced a message
+    SentMessage {
+        /// The number of changes in the message
+        changes: usize,
+        /// Whether the changes were sent as a whole saved document rather than change by change
+        whole_document: bool,
+        /// The number of bloom filters summarising our changes in the message
+        bloom_filters: usize,
+        /// The number of changes we asked the peer for
+        need: usize,
+    },
+    /// [`SyncDoc::generate_sync_message()`] had nothing new to send
+    NothingToSend {
+        /// Whether this was because an earlier message is still waiting for a response
+        in_flight: bool,
+    },
+    /// The peer's last sync point refers to changes we don't have, so
+    /// [`SyncDoc::generate_sync_message()`] asked the peer to start over and send everything
+    RequestedReset,
+    /// [`SyncDoc::receive_sync_message()`] processed a message
+    ReceivedMessage {
+        /// The number of changes in the message
+        changes: usize,
+        /// The number of changes which were applied to the document as a result, this can be
+        /// fewer than `changes` if some were duplicates or are waiting for their dependencies
+        applied: usize,
+        /// The number of heads the peer reported
+        their_heads: usize,
+        /// The number of changes the peer asked us for
+        their_need: usize,
+    },
+    /// The peer reported that it has no changes at all, so we forgot what we have sent it and
+    /// will send everything again
+    PeerHasNoChanges,
+}
+
+/// The callback set with [`State::with_trace()`]
+///
+/// Cloning a [`State`] shares the callback between the clones.
+#[derive(Clone)]
+pub struct SyncTrace(Arc<Mutex<dyn FnMut(SyncEvent) + Send>>);
+
+impl SyncTrace {
+    /// Wrap `f` so it can be passed to [`State::with_trace()`]
+    ///
+    /// `f` is called with each [`SyncEvent`] while a lock on it is held, so that clones of the
+    /// [`State`] on other threads don't call it at the same time. `f` must not generate or
+    /// receive sync messages with a [`State`] which shares this trace, that would wait for the
+    /// lock `f` is holding and deadlock.
+    pub fn new<F: FnMut(SyncEvent) + Send + 'static>(f: F) -> Self {
+        SyncTrace(Arc::new(Mutex::new(f)))
+    }
+
+    fn call(&self, event: SyncEvent) {
+        // `f` is `FnMut` so the lock has to be held while it runs, see `new`
+        if let Ok(mut f) = self.0.lock() {
+            f(event)
+        }
+    }
+}
+
+impl std::fmt::Debug for SyncTrace {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("SyncTrace")
+    }
+}
+
+/// Counters describing how efficient synchronisation with a peer has been
+///
+/// These are only for diagnostics, they are not sent to the peer or included in
+/// [`State::encode()`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Stats {
+    /// An estimate of the number of changes the peer did not send us because our bloom filter
+    /// wrongly reported that we already had them
+    ///
+    /// The bloom filter itself can't tell us this, so what is counted is the dependencies which
+    /// are newly missing after applying the changes in a message, which have to be requested
+    /// again in a later round. A false positive for a change nothing we were sent depends on
+    /// isn't counted, and a dependency missing for any other reason, such as a peer which sends
+    /// changes out of order, is.
+    pub bloom_false_positives: u64,
+    /// The number of changes the peer sent us which we already had, counting each change in a
+    /// message which contains several, such as a saved document
+    pub changes_resent: u64,
 }
 
 /// A summary of the changes that the sender of the message already has.
@@ -94,6 +250,140 @@ impl State {
         }
     }
 
+    /// Forget what we have sent to the peer so the next
+    /// [`SyncDoc::generate_sync_message()`] produces a fresh message
+    ///
+    /// This clears [`Self::last_sent_heads`], [`Self::sent_hashes`] and [`Self::in_flight`] but
+    /// leaves [`Self::shared_heads`] and everything we know about the other end intact. This is
+    /// useful when recovering from a dropped connection or a peer which reports that it lost
+    /// messages.
+    pub fn reset_sent(&mut self) {
+        self.last_sent_heads = Vec::new();
+        self.sent_hashes = BTreeSet::new();
+        self.in_flight = false;
+    }
+
+    /// Prepare a sync state which was persisted in an earlier session for a new connection to
+    /// the same peer, given the document as it is now
+    ///
+    /// Everything learned from the peer in the earlier session is forgotten, as the peer may have
+    /// changed since, along with what we sent it (see [`Self::reset_sent()`]). The peer tells us
+    /// its new heads and what it has in its first message.
+    ///
+    /// [`Self::shared_heads`] are kept, so the first message summarises only the changes made
+    /// locally since the last sync rather than the whole document. Any of them which `doc`
+    /// doesn't contain, for example because the document was restored from a save made before
+    /// the sync state was persisted, are dropped. Claiming to have them would stop the peer from
+    /// sending them again. Changes removed by [`Automerge::trim_changes()`] are still contained
+    /// in `doc`, so they are kept.
+    pub fn refresh_after_local_changes(&mut self, doc: &Automerge) {
+        // `has_change` rather than `get_change_by_hash`, which doesn't find trimmed changes
+        self.shared_heads.retain(|hash| doc.has_change(hash));
+        self.their_heads = None;
+        self.their_need = None;
+        self.their_have = None;
+        self.their_capabilities = None;
+        self.have_responded = false;
+        self.reset_sent();
+    }
+
+    /// Limit the number of changes [`SyncDoc::generate_sync_message()`] puts in a single message
+    ///
+    /// Any remaining changes are sent in subsequent messages as the other end responds. [`None`],
+    /// the default, means there is no limit. This is a property of the connection and so is not
+    /// included in [`Self::encode()`].
+    pub fn with_max_changes_per_message(mut self, max: Option<usize>) -> Self {
+        self.set_max_changes_per_message(max);
+        self
+    }
+
+    /// See [`Self::with_max_changes_per_message()`]
+    pub fn set_max_changes_per_message(&mut self, max: Option<usize>) -> &mut Self {
+        self.max_changes_per_message = max;
+        self
+    }
+
+    /// See [`Self::with_max_changes_per_message()`]
+    pub fn max_changes_per_message(&self) -> Option<usize> {
+        self.max_changes_per_message
+    }
+
+    /// Send the changes to `objects` first
+    ///
+    /// Changes which modify one of these objects, or an object nested inside one of them, are
+    /// sent by [`SyncDoc::generate_sync_message()`] ahead of other changes along with the changes
+    /// they depend on. This only changes the order in which changes are sent, so it matters most
+    /// when [`Self::with_max_changes_per_message()`] splits the changes over several messages.
+    /// Objects the document doesn't contain are ignored. This is not included in
+    /// [`Self::encode()`].
+    pub fn with_priority_objects(mut self, objects: Vec<ExId>) -> Self {
+        self.set_priority_objects(objects);
+        self
+    }
+
+    /// See [`Self::with_priority_objects()`]
+    pub fn set_priority_objects(&mut self, objects: Vec<ExId>) -> &mut Self {
+        self.priority_objects = objects;
+        self
+    }
+
+    /// See [`Self::with_priority_objects()`]
+    pub fn priority_objects(&self) -> &[ExId] {
+        &self.priority_objects
+    }
+
+    /// Whether [`SyncDoc::receive_sync_message()`] applies the changes in a message before one
+    /// whose checksum doesn't match its contents
+    ///
+    /// If this is `false`, the default, none of the changes in the message are applied,
+    /// otherwise the changes before the bad one are. Either way
+    /// [`crate::AutomergeError::ChecksumMismatch`] is returned. This is not included in
+    /// [`Self::encode()`].
+    pub fn with_apply_changes_before_bad_checksum(mut self, apply: bool) -> Self {
+        self.set_apply_changes_before_bad_checksum(apply);
+        self
+    }
+
+    /// See [`Self::with_apply_changes_before_bad_checksum()`]
+    pub fn set_apply_changes_before_bad_checksum(&mut self, apply: bool) -> &mut Self {
+        self.apply_changes_before_bad_checksum = apply;
+        self
+    }
+
+    /// See [`Self::with_apply_changes_before_bad_checksum()`]
+    pub fn apply_changes_before_bad_checksum(&self) -> bool {
+        self.apply_changes_before_bad_checksum
+    }
+
+    /// Call `trace` with each step of the protocol, see [`SyncEvent`]
+    ///
+    /// This is for diagnosing sync problems. There is no trace by default and it is not included
+    /// in [`Self::encode()`].
+    pub fn with_trace(mut self, trace: SyncTrace) -> Self {
+        self.set_trace(Some(trace));
+        self
+    }
+
+    /// See [`Self::with_trace()`], [`None`] removes the trace
+    pub fn set_trace(&mut self, trace: Option<SyncTrace>) -> &mut Self {
+        self.trace = trace;
+        self
+    }
+
+    /// The diagnostic counters accumulated by [`SyncDoc::receive_sync_message()`]
+    ///
+    /// These are not included in [`Self::encode()`].
+    pub fn stats(&self) -> Stats {
+        self.stats
+    }
+
+    /// Pass `event` to the trace, if there is one
+    pub(crate) fn emit(&self, event: SyncEvent) {
+        if let Some(trace) = &self.trace {
+            trace.call(event)
+        }
+    }
+
     pub(crate) fn parse(input: parse::Input<'_>) -> parse::ParseResult<'_, Self, DecodeError> {
         let (i, record_type) = parse::take1(input)?;
         if record_type != SYNC_STATE_TYPE {
@@ -116,6 +406,11 @@ impl State {
                 in_flight: false,
                 have_responded: false,
                 their_capabilities: None,
+                max_changes_per_message: None,
+                priority_objects: Vec::new(),
+                apply_changes_before_bad_checksum: false,
+                stats: Stats::default(),
+                trace: None,
             },
         ))
     }
@@ -571,7 +571,7 @@ fn sync_v1_to_v2(
             let (_, decoded) = crate::sync::Message::parse(Input::new(&encoded))
                 .expect("v1 message should decode as a v2 message");
             tracing::debug!(decoded=?decoded, "receiving decoded message on v2");
-            v2.receive_sync_message(b_sync_state, decoded).unwrap()
+            v2.receive_sync_message(b_sync_state, decoded).unwrap();
         }
         let b_to_a = v2.generate_sync_message(b_sync_state);
         let b_to_a_is_none = b_to_a.is_none();
@@ -618,7 +618,7 @@ fn sync_v2_to_v1(
             let encoded = msg.encode();
             let decoded = crate::sync::Message::decode(&encoded)
                 .expect("v1 message should decode as a v2 message");
-            v2.receive_sync_message(v2_sync_state, decoded).unwrap()
+            v2.receive_sync_message(v2_sync_state, decoded).unwrap();
         }
         if a_to_b_is_none && b_to_a_is_none {
             break;
@@ -3,7 +3,6 @@ use unicode_segmentation::UnicodeSegmentation;
 use crate::{
     clock::Clock,
     iter::{SpanInternal, SpansInternal},
-    op_tree::OpTreeOpIter,
     text_value::TextValue,
     transaction::TransactionInner,
     Automerge, BlockOrText, ObjId as ExId, PatchLog, ReadDoc,
@@ -362,11 +361,7 @@ fn spans_as_grapheme(
     text: &crate::types::ObjId,
     clock: Option<Clock>,
 ) -> Result<Vec<BlockOrGrapheme>, crate::AutomergeError> {
-    let spans_internal = SpansInternal::new(
-        OpTreeOpIter::new(doc.ops().iter_obj(text).unwrap(), doc.osd()),
-        doc,
-        clock.clone(),
-    );
+    let spans_internal = SpansInternal::new(doc.ops().iter_obj(text).unwrap(), doc, clock.clone());
     let mut result = Vec::with_capacity(spans_internal.size_hint().0);
     for span in spans_internal {
         match span {
@@ -1,13 +1,15 @@
 mod commit;
 mod inner;
 mod manual_transaction;
+mod pending_op;
 mod result;
 mod transactable;
 
 pub use self::commit::CommitOptions;
 pub use self::transactable::{BlockOrText, Transactable};
 pub(crate) use inner::{TransactionArgs, TransactionInner};
-pub use manual_transaction::Transaction;
+pub use manual_transaction::{Savepoint, Transaction};
+pub use pending_op::{PendingKey, PendingOpView};
 pub use result::Failure;
 pub use result::Success;
 
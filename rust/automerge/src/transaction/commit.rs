@@ -1,10 +1,20 @@
+use std::collections::BTreeMap;
+
+use crate::ScalarValue;
+
 /// Optional metadata for a commit.
+///
+/// This is `#[non_exhaustive]` so fields can be added without breaking anyone. Use
+/// [`CommitOptions::default()`] and the `with_*` or `set_*` methods to build it.
 #[derive(Debug, Default)]
+#[non_exhaustive]
 pub struct CommitOptions {
     /// A message which describes the commit
     pub message: Option<String>,
     /// The unix timestamp (in seconds) of the commit (purely advisory, not used in conflict resolution)
     pub time: Option<i64>,
+    /// Metadata to store with the commit, set with [`Self::with_extra()`]
+    pub(crate) extra: BTreeMap<String, ScalarValue>,
 }
 
 impl CommitOptions {
@@ -31,4 +41,24 @@ impl CommitOptions {
         self.time = Some(time);
         self
     }
+
+    /// Add a metadata entry to the commit.
+    ///
+    /// The metadata isn't part of the document, it's stored alongside the change and is
+    /// preserved by saving, loading and syncing. Read it back with
+    /// [`crate::Change::extra_metadata()`].
+    pub fn with_extra<S: Into<String>, V: Into<ScalarValue>>(mut self, key: S, value: V) -> Self {
+        self.extra.insert(key.into(), value.into());
+        self
+    }
+
+    /// Add a metadata entry to the commit.
+    pub fn set_extra<S: Into<String>, V: Into<ScalarValue>>(
+        &mut self,
+        key: S,
+        value: V,
+    ) -> &mut Self {
+        self.extra.insert(key.into(), value.into());
+        self
+    }
 }
@@ -1,5 +1,6 @@
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
 use std::num::NonZeroU64;
+use std::ops::Range;
 use std::sync::Arc;
 
 use crate::exid::ExId;
@@ -9,6 +10,8 @@ use crate::op_set::{ChangeOpIter, OpIdx, OpIdxRange};
 use crate::patches::{PatchLog, TextRepresentation};
 use crate::query::{self, OpIdSearch};
 use crate::storage::Change as StoredChange;
+use crate::text_value::TextValue;
+use crate::transaction::CommitOptions;
 use crate::types::{Clock, Key, ListEncoding, ObjMeta, OpId};
 use crate::{op_tree::OpSetData, types::OpBuilder, Automerge, Change, ChangeHash, Prop};
 use crate::{AutomergeError, ObjType, OpType, ReadDoc, ScalarValue};
@@ -20,6 +23,7 @@ pub(crate) struct TransactionInner {
     start_op: NonZeroU64,
     time: i64,
     message: Option<String>,
+    extra: BTreeMap<String, ScalarValue>,
     deps: Vec<ChangeHash>,
     scope: Option<Clock>,
     idx_range: OpIdxRange,
@@ -59,6 +63,7 @@ impl TransactionInner {
             start_op,
             time: 0,
             message: None,
+            extra: BTreeMap::new(),
             idx_range,
             deps,
             scope,
@@ -69,16 +74,29 @@ impl TransactionInner {
     pub(crate) fn empty(
         doc: &mut Automerge,
         args: TransactionArgs,
-        message: Option<String>,
-        time: Option<i64>,
+        options: CommitOptions,
     ) -> ChangeHash {
-        Self::new(args).commit_impl(doc, message, time)
+        let mut tx = Self::new(args);
+        tx.set_extra(options.extra);
+        tx.commit_impl(doc, options.message, options.time)
     }
 
     pub(crate) fn pending_ops(&self) -> usize {
         self.idx_range.len()
     }
 
+    pub(crate) fn set_message(&mut self, message: String) {
+        self.message = Some(message);
+    }
+
+    pub(crate) fn set_time(&mut self, time: i64) {
+        self.time = time;
+    }
+
+    pub(crate) fn set_extra(&mut self, extra: BTreeMap<String, ScalarValue>) {
+        self.extra = extra;
+    }
+
     /// Commit the operations performed in this transaction, returning the hashes corresponding to
     /// the new heads.
     ///
@@ -121,21 +139,29 @@ impl TransactionInner {
             tracing::trace!(commit=?hash, ?ops, deps=?change.deps(), "committing transaction");
         }
         doc.update_history(change, num_ops);
+        doc.ops_mut().spill_cold_trees();
         //debug_assert_eq!(doc.get_heads(), vec![hash]);
         hash
     }
 
-    fn operations<'a>(&self, osd: &'a OpSetData) -> ChangeOpIter<'a> {
+    pub(crate) fn operations<'a>(&self, osd: &'a OpSetData) -> ChangeOpIter<'a> {
         osd.get_ops(self.idx_range)
     }
 
     #[tracing::instrument(skip(self, osd))]
     pub(crate) fn export(self, osd: &OpSetData) -> Change {
-        use crate::storage::{change::PredOutOfOrder, convert::op_as_actor_id};
+        use crate::storage::{
+            change::{extra_metadata, PredOutOfOrder},
+            convert::op_as_actor_id,
+        };
 
         let actor = osd.actors.get(self.actor).clone();
         let deps = self.deps.clone();
-        let stored = match StoredChange::builder()
+        let mut builder = StoredChange::builder();
+        if !self.extra.is_empty() {
+            builder = builder.with_extra_bytes(extra_metadata::encode(&self.extra));
+        }
+        let stored = match builder
             .with_actor(actor)
             .with_seq(self.seq)
             .with_start_op(self.start_op)
@@ -164,11 +190,37 @@ impl TransactionInner {
     /// operations.
     pub(crate) fn rollback(self, doc: &mut Automerge) -> usize {
         let num = self.pending_ops();
+        self.undo_ops(doc, 0);
+        num
+    }
+
+    /// Undo the operations added in this transaction after the first `keep` operations and drop
+    /// the patch log events recorded after the first `events`, returning the number of cancelled
+    /// operations.
+    pub(crate) fn rollback_to(
+        &mut self,
+        doc: &mut Automerge,
+        patch_log: &mut PatchLog,
+        keep: usize,
+        events: usize,
+    ) -> usize {
+        let num = self.pending_ops().saturating_sub(keep);
+        if num > 0 {
+            let removed = self.undo_ops(doc, keep);
+            doc.ops_mut().truncate_range(&mut self.idx_range, keep);
+            patch_log.rollback_to(events, &removed);
+        }
+        num
+    }
+
+    /// Undo the operations added in this transaction after the first `keep` operations,
+    /// returning the ids of the removed operations.
+    fn undo_ops(&self, doc: &mut Automerge, keep: usize) -> Vec<OpId> {
         // remove in reverse order so sets are removed before makes etc...
         let encoding = ListEncoding::List; // encoding doesnt matter here - we dont care what the index is
-        let ops: Vec<_> = self
+        let mut ops: Vec<_> = self
             .operations(doc.osd())
-            .rev()
+            .skip(keep)
             .map(|op| {
                 (
                     op.idx(),
@@ -178,6 +230,8 @@ impl TransactionInner {
                 )
             })
             .collect();
+        ops.reverse();
+        let mut removed = Vec::with_capacity(ops.len());
         for (idx, obj, opid, pred) in ops.into_iter() {
             for pred_id in &pred {
                 if let Some(p) = doc
@@ -195,9 +249,9 @@ impl TransactionInner {
             {
                 doc.ops_mut().remove(&obj, pos);
             }
+            removed.push(opid);
         }
-
-        num
+        removed
     }
 
     /// Set the value of property `P` to value `V` in object `obj`.
@@ -221,7 +275,7 @@ impl TransactionInner {
         prop: P,
         value: V,
     ) -> Result<(), AutomergeError> {
-        let obj = doc.exid_to_obj(ex_obj)?;
+        let obj = doc.exid_to_writable_obj(ex_obj)?;
         let value = value.into();
         let prop = prop.into();
         match (&prop, obj.typ) {
@@ -234,6 +288,37 @@ impl TransactionInner {
         Ok(())
     }
 
+    pub(crate) fn put_if_absent<P: Into<Prop>, V: Into<ScalarValue>>(
+        &mut self,
+        doc: &mut Automerge,
+        patch_log: &mut PatchLog,
+        ex_obj: &ExId,
+        prop: P,
+        value: V,
+    ) -> Result<bool, AutomergeError> {
+        let obj = doc.exid_to_writable_obj(ex_obj)?;
+        match (prop.into(), obj.typ) {
+            (Prop::Map(key), ObjType::Map) => {
+                let action = OpType::Put(value.into());
+                let op = self.local_map_op(doc, patch_log, &obj, key, action, true)?;
+                Ok(op.is_some())
+            }
+            (Prop::Seq(index), ObjType::List | ObjType::Text) => {
+                // every index in a sequence has a value, so there is never anything to put as
+                // long as the index exists
+                let osd = doc.osd();
+                doc.ops()
+                    .search(
+                        &obj.id,
+                        query::Nth::new(index, ListEncoding::List, self.scope.clone(), osd),
+                    )
+                    .key()?;
+                Ok(false)
+            }
+            _ => Err(AutomergeError::InvalidOp(obj.typ)),
+        }
+    }
+
     /// Set the value of property `P` to value `V` in object `obj`.
     ///
     /// # Returns
@@ -255,7 +340,7 @@ impl TransactionInner {
         prop: P,
         value: ObjType,
     ) -> Result<ExId, AutomergeError> {
-        let obj = doc.exid_to_obj(ex_obj)?;
+        let obj = doc.exid_to_writable_obj(ex_obj)?;
         let prop = prop.into();
         match (&prop, obj.typ) {
             (Prop::Map(_), ObjType::Map) => Ok(()),
@@ -317,7 +402,7 @@ impl TransactionInner {
         index: usize,
         value: V,
     ) -> Result<(), AutomergeError> {
-        let obj = doc.exid_to_obj(ex_obj)?;
+        let obj = doc.exid_to_writable_obj(ex_obj)?;
         if !matches!(obj.typ, ObjType::List | ObjType::Text) {
             return Err(AutomergeError::InvalidOp(obj.typ));
         }
@@ -335,7 +420,7 @@ impl TransactionInner {
         index: usize,
         value: ObjType,
     ) -> Result<ExId, AutomergeError> {
-        let obj = doc.exid_to_obj(ex_obj)?;
+        let obj = doc.exid_to_writable_obj(ex_obj)?;
         if !matches!(obj.typ, ObjType::List | ObjType::Text) {
             return Err(AutomergeError::InvalidOp(obj.typ));
         }
@@ -343,6 +428,54 @@ impl TransactionInner {
         Ok(idx.as_op(doc.osd()).exid())
     }
 
+    /// Insert a new object of each type in `objs` one after the other starting at `index`,
+    /// looking up the insert position only once
+    pub(crate) fn splice_objects(
+        &mut self,
+        doc: &mut Automerge,
+        patch_log: &mut PatchLog,
+        ex_obj: &ExId,
+        index: usize,
+        objs: Vec<ObjType>,
+    ) -> Result<Vec<ExId>, AutomergeError> {
+        let obj = doc.exid_to_writable_obj(ex_obj)?;
+        if !matches!(obj.typ, ObjType::List | ObjType::Text) {
+            return Err(AutomergeError::InvalidOp(obj.typ));
+        }
+        if objs.is_empty() {
+            return Ok(Vec::new());
+        }
+        let encoding = patch_log.text_rep().encoding(obj.typ);
+        let query = doc.ops().search(
+            &obj.id,
+            query::InsertNth::new(index, encoding, self.scope.clone()),
+        );
+        let marks = query.marks(doc.osd());
+        let mut key = query.key()?;
+        let mut cursor = index;
+
+        let mut ids = Vec::with_capacity(objs.len());
+        for (pos, typ) in (query.pos()..).zip(objs) {
+            let op = OpBuilder {
+                id: self.next_id(),
+                action: typ.into(),
+                key,
+                insert: true,
+            };
+            key = op.id.into();
+
+            let idx = doc
+                .ops_mut()
+                .load_with_range(obj.id, op, &mut self.idx_range);
+            doc.ops_mut().insert(pos, &obj.id, idx);
+
+            self.finalize_op(doc, patch_log, &obj, Prop::Seq(cursor), idx, marks.clone());
+            cursor += idx.as_op(doc.osd()).width(encoding);
+            ids.push(idx.as_op(doc.osd()).exid());
+        }
+        Ok(ids)
+    }
+
     fn do_insert(
         &mut self,
         doc: &mut Automerge,
@@ -391,7 +524,7 @@ impl TransactionInner {
         action: OpType,
     ) -> Result<Option<OpIdx>, AutomergeError> {
         match prop {
-            Prop::Map(s) => self.local_map_op(doc, patch_log, obj, s, action),
+            Prop::Map(s) => self.local_map_op(doc, patch_log, obj, s, action, false),
             Prop::Seq(n) => self.local_list_op(doc, patch_log, obj, n, action),
         }
     }
@@ -403,9 +536,10 @@ impl TransactionInner {
         obj: &ObjMeta,
         prop: String,
         action: OpType,
+        only_if_absent: bool,
     ) -> Result<Option<OpIdx>, AutomergeError> {
         let id = self.next_id();
-        let prop_index = doc.ops_mut().osd.props.cache(prop.clone());
+        let prop_index = doc.ops_mut().import_prop(&prop);
         let key = Key::Map(prop_index);
         let prop: Prop = prop.into();
         let query = doc.ops().seek_ops_by_prop(
@@ -419,6 +553,10 @@ impl TransactionInner {
             return Ok(None);
         }
 
+        if only_if_absent && !query.ops.is_empty() {
+            return Ok(None);
+        }
+
         if query.ops.len() == 1 && query.ops[0].is_noop(&action) {
             return Ok(None);
         }
@@ -511,7 +649,7 @@ impl TransactionInner {
         prop: P,
         value: i64,
     ) -> Result<(), AutomergeError> {
-        let obj = doc.exid_to_obj(obj)?;
+        let obj = doc.exid_to_writable_obj(obj)?;
         self.local_op(doc, patch_log, &obj, prop.into(), OpType::Increment(value))?;
         Ok(())
     }
@@ -523,7 +661,7 @@ impl TransactionInner {
         ex_obj: &ExId,
         prop: P,
     ) -> Result<(), AutomergeError> {
-        let obj = doc.exid_to_obj(ex_obj)?;
+        let obj = doc.exid_to_writable_obj(ex_obj)?;
         let prop = prop.into();
         if obj.typ == ObjType::Text {
             let index = prop.as_index().ok_or(AutomergeError::InvalidOp(obj.typ))?;
@@ -534,7 +672,7 @@ impl TransactionInner {
                     obj,
                     index,
                     del: 1,
-                    values: vec![],
+                    values: std::iter::empty(),
                     splice_type: SpliceType::Text(""),
                 },
             )?;
@@ -555,11 +693,10 @@ impl TransactionInner {
         del: isize,
         vals: impl IntoIterator<Item = ScalarValue>,
     ) -> Result<(), AutomergeError> {
-        let obj = doc.exid_to_obj(ex_obj)?;
+        let obj = doc.exid_to_writable_obj(ex_obj)?;
         if !matches!(obj.typ, ObjType::List | ObjType::Text) {
             return Err(AutomergeError::InvalidOp(obj.typ));
         }
-        let values = vals.into_iter().collect();
         self.inner_splice(
             doc,
             patch_log,
@@ -567,7 +704,7 @@ impl TransactionInner {
                 obj,
                 index,
                 del,
-                values,
+                values: vals,
                 splice_type: SpliceType::List,
             },
         )?;
@@ -584,11 +721,10 @@ impl TransactionInner {
         del: isize,
         text: &str,
     ) -> Result<(), AutomergeError> {
-        let obj = doc.exid_to_obj(ex_obj)?;
+        let obj = doc.exid_to_writable_obj(ex_obj)?;
         if obj.typ != ObjType::Text {
             return Err(AutomergeError::InvalidOp(obj.typ));
         }
-        let values = text.chars().map(ScalarValue::from).collect();
         self.inner_splice(
             doc,
             patch_log,
@@ -596,13 +732,232 @@ impl TransactionInner {
                 obj,
                 index,
                 del,
-                values,
+                values: text.chars().map(ScalarValue::from),
                 splice_type: SpliceType::Text(text),
             },
         )
     }
 
-    fn inner_splice(
+    pub(crate) fn splice_text_utf16(
+        &mut self,
+        doc: &mut Automerge,
+        patch_log: &mut PatchLog,
+        ex_obj: &ExId,
+        index: usize,
+        del: isize,
+        text: &str,
+    ) -> Result<(), AutomergeError> {
+        self.splice_text_with_encoding(
+            doc,
+            patch_log,
+            ex_obj,
+            index,
+            del,
+            text,
+            ListEncoding::Utf16,
+        )
+    }
+
+    /// Like [`Self::splice_text()`] but `index` and `del` are measured in `encoding`
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn splice_text_with_encoding(
+        &mut self,
+        doc: &mut Automerge,
+        patch_log: &mut PatchLog,
+        ex_obj: &ExId,
+        index: usize,
+        del: isize,
+        text: &str,
+        encoding: ListEncoding,
+    ) -> Result<(), AutomergeError> {
+        let (start, end) = if del < 0 {
+            let start = index
+                .checked_sub(del.unsigned_abs())
+                .ok_or(AutomergeError::InvalidIndex(index))?;
+            (start, index)
+        } else {
+            (index, index + del as usize)
+        };
+        let to_char = |index| {
+            doc.convert_text_index_for(
+                ex_obj,
+                index,
+                encoding,
+                ListEncoding::Text,
+                self.scope.clone(),
+            )
+        };
+        let start_char = to_char(start)?;
+        let end_char = to_char(end)?;
+        self.splice_text(
+            doc,
+            patch_log,
+            ex_obj,
+            start_char,
+            (end_char - start_char) as isize,
+            text,
+        )
+    }
+
+    /// Insert `text` at `index` and return the ids of every inserted character, in order
+    pub(crate) fn splice_text_with_ids(
+        &mut self,
+        doc: &mut Automerge,
+        patch_log: &mut PatchLog,
+        ex_obj: &ExId,
+        index: usize,
+        text: &str,
+    ) -> Result<Vec<ExId>, AutomergeError> {
+        let ids = self.insert_text(doc, patch_log, ex_obj, index, text)?;
+        Ok(ids
+            .map(|(first, last)| {
+                std::iter::successors(Some(first), |id| (*id != last).then(|| id.next()))
+                    .map(|id| doc.id_to_exid(id))
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+
+    /// Insert `text` at `index` and return the ids of the first and last inserted characters, or
+    /// `None` if `text` is empty
+    pub(crate) fn splice_text_with_id_range(
+        &mut self,
+        doc: &mut Automerge,
+        patch_log: &mut PatchLog,
+        ex_obj: &ExId,
+        index: usize,
+        text: &str,
+    ) -> Result<Option<(ExId, ExId)>, AutomergeError> {
+        let ids = self.insert_text(doc, patch_log, ex_obj, index, text)?;
+        Ok(ids.map(|(first, last)| (doc.id_to_exid(first), doc.id_to_exid(last))))
+    }
+
+    /// The ops inserted for the characters of `text` are created one after the other so their
+    /// ids are the consecutive run between the first and last
+    fn insert_text(
+        &mut self,
+        doc: &mut Automerge,
+        patch_log: &mut PatchLog,
+        ex_obj: &ExId,
+        index: usize,
+        text: &str,
+    ) -> Result<Option<(OpId, OpId)>, AutomergeError> {
+        let first = self.next_id();
+        self.splice_text(doc, patch_log, ex_obj, index, 0, text)?;
+        let next = self.next_id();
+        Ok((next != first).then(|| (first, next.minus(1))))
+    }
+
+    /// Insert a string into a text object along with marks covering ranges of the inserted text
+    ///
+    /// The position of the insert is only looked up once, rather than once for the text and twice
+    /// for each mark.
+    pub(crate) fn splice_text_with_marks(
+        &mut self,
+        doc: &mut Automerge,
+        patch_log: &mut PatchLog,
+        ex_obj: &ExId,
+        index: usize,
+        text: &str,
+        marks: &[(Range<usize>, &str, ScalarValue)],
+    ) -> Result<(), AutomergeError> {
+        let obj = doc.exid_to_writable_obj(ex_obj)?;
+        if obj.typ != ObjType::Text {
+            return Err(AutomergeError::InvalidOp(obj.typ));
+        }
+        let len = TextValue::width(text);
+        for (range, _, _) in marks {
+            if range.start > range.end || range.end > len {
+                return Err(AutomergeError::InvalidIndex(range.end));
+            }
+        }
+        let marks = marks
+            .iter()
+            .filter(|(range, _, _)| !range.is_empty())
+            .map(|(range, name, value)| {
+                Mark::new(name.to_string(), value.clone(), range.start, range.end)
+            })
+            .collect::<Vec<_>>();
+        if text.is_empty() {
+            return Ok(());
+        }
+
+        let encoding = ListEncoding::Text;
+        let query = doc.ops().search(
+            &obj.id,
+            query::InsertNth::new(index, encoding, self.scope.clone()),
+        );
+        let start_pos = query.pos();
+        let before_key = query.key()?;
+        let insert_marks = query.marks(doc.osd());
+
+        let mut key = before_key;
+        let mut chars = Vec::new();
+        for (pos, c) in (start_pos..).zip(text.chars()) {
+            let op = self.next_insert(key, c.into());
+            key = op.id.into();
+            let idx = doc
+                .ops_mut()
+                .load_with_range(obj.id, op, &mut self.idx_range);
+            doc.ops_mut().insert(pos, &obj.id, idx);
+            let op = idx.as_op(doc.osd());
+            chars.push((*op.id(), op.width(encoding)));
+        }
+
+        // Each mark needs consecutive ids for its begin and end ops so the anchors are inserted
+        // after the characters. An anchor goes immediately after the character it follows, we
+        // already know where that character is so we don't need to search for it again.
+        let mut anchored_after = Vec::new();
+        let expand = ExpandMark::default();
+        for mark in &marks {
+            let begin = OpType::MarkBegin(expand.before(), mark.data.clone().into_owned());
+            let end = OpType::MarkEnd(expand.after());
+            for (offset, action) in [(mark.start, begin), (mark.end, end)] {
+                // the number of characters before the anchor, rounding up if `offset` is in the
+                // middle of a character
+                let mut before = 0;
+                let mut width = 0;
+                while width < offset {
+                    width += chars[before].1;
+                    before += 1;
+                }
+                let key = match before {
+                    0 => before_key,
+                    n => chars[n - 1].0.into(),
+                };
+                let pos =
+                    start_pos + before + anchored_after.iter().filter(|a| **a < before).count();
+                let op = OpBuilder {
+                    id: self.next_id(),
+                    action,
+                    key,
+                    insert: true,
+                };
+                let idx = doc
+                    .ops_mut()
+                    .load_with_range(obj.id, op, &mut self.idx_range);
+                doc.ops_mut().insert(pos, &obj.id, idx);
+                anchored_after.push(before);
+            }
+        }
+
+        if patch_log.is_active() {
+            if matches!(patch_log.text_rep(), TextRepresentation::String) {
+                patch_log.splice(obj.id, index, text, insert_marks);
+            } else {
+                for (i, (c, (id, _))) in text.chars().zip(chars).enumerate() {
+                    patch_log.insert(obj.id, index + i, ScalarValue::from(c).into(), id, false);
+                }
+            }
+            for mark in marks {
+                let (start, len) = (index + mark.start, mark.len());
+                patch_log.mark(obj.id, start, len, &mark.into_mark_set());
+            }
+        }
+        Ok(())
+    }
+
+    fn inner_splice<I: IntoIterator<Item = ScalarValue>>(
         &mut self,
         doc: &mut Automerge,
         patch_log: &mut PatchLog,
@@ -612,7 +967,7 @@ impl TransactionInner {
             mut del,
             values,
             splice_type,
-        }: SpliceArgs<'_>,
+        }: SpliceArgs<'_, I>,
     ) -> Result<(), AutomergeError> {
         if del < 0 {
             if let Some(n) = index.checked_add_signed(del) {
@@ -666,7 +1021,8 @@ impl TransactionInner {
 
         // do the insert query for the first item and then
         // insert the remaining ops one after the other
-        if !values.is_empty() {
+        let mut values = values.into_iter().peekable();
+        if values.peek().is_some() {
             let query = doc.ops().search(
                 &obj.id,
                 query::InsertNth::new(index, encoding, self.scope.clone()),
@@ -676,9 +1032,18 @@ impl TransactionInner {
             let marks = query.marks(doc.osd());
             let mut cursor = index;
             let mut width = 0;
-
-            for v in &values {
-                let op = self.next_insert(key, v.clone());
+            // text is logged as a single splice once everything is inserted, anything else is
+            // logged value by value as it is inserted so the values don't have to be collected
+            let log_each = patch_log.is_active()
+                && !matches!(
+                    (&splice_type, patch_log.text_rep()),
+                    (SpliceType::Text(_), TextRepresentation::String)
+                );
+
+            for (offset, v) in values.enumerate() {
+                let logged = log_each.then(|| v.clone());
+                let op = self.next_insert(key, v);
+                let opid = op.id;
 
                 key = op.id.into();
 
@@ -690,26 +1055,20 @@ impl TransactionInner {
                 width = idx.as_op(doc.osd()).width(encoding);
                 cursor += width;
                 pos += 1;
+
+                if let Some(v) = logged {
+                    patch_log.insert(obj.id, index + offset, v.into(), opid, false);
+                }
             }
 
             doc.ops_mut()
                 .hint(&obj.id, cursor - width, pos - 1, width, key, marks.clone());
 
-            if patch_log.is_active() {
-                match splice_type {
-                    SpliceType::Text(text)
-                        if matches!(patch_log.text_rep(), TextRepresentation::String) =>
-                    {
-                        patch_log.splice(obj.id, index, text, marks);
-                    }
-                    SpliceType::List | SpliceType::Text(..) => {
-                        let mut opid = self.next_id().minus(values.len());
-                        for (offset, v) in values.iter().enumerate() {
-                            opid = opid.next();
-                            patch_log.insert(obj.id, index + offset, v.clone().into(), opid, false);
-                        }
-                    }
+            match splice_type {
+                SpliceType::Text(text) if patch_log.is_active() && !log_each => {
+                    patch_log.splice(obj.id, index, text, marks);
                 }
+                _ => {}
             }
         }
         Ok(())
@@ -723,6 +1082,42 @@ impl TransactionInner {
         mark: Mark<'_>,
         expand: ExpandMark,
     ) -> Result<(), AutomergeError> {
+        self.insert_mark(doc, patch_log, ex_obj, mark, expand)?;
+        Ok(())
+    }
+
+    pub(crate) fn mark_and_return_range(
+        &mut self,
+        doc: &mut Automerge,
+        patch_log: &mut PatchLog,
+        ex_obj: &ExId,
+        mark: Mark<'_>,
+        expand: ExpandMark,
+    ) -> Result<Range<usize>, AutomergeError> {
+        let start = mark.start;
+        let Some((begin, end)) = self.insert_mark(doc, patch_log, ex_obj, mark, expand)? else {
+            return Ok(start..start);
+        };
+        let text_rep = patch_log.text_rep();
+        let clock = self.scope.as_ref();
+        let begin = doc.ops().seek_idx(begin, text_rep, clock).map(|f| f.index);
+        let end = doc.ops().seek_idx(end, text_rep, clock).map(|f| f.index);
+        match (begin, end) {
+            (Some(begin), Some(end)) => Ok(begin..end),
+            _ => Err(AutomergeError::InvalidIndex(start)),
+        }
+    }
+
+    /// Insert the begin and end anchors of `mark`, returning them or `None` if the mark is a
+    /// no-op
+    fn insert_mark(
+        &mut self,
+        doc: &mut Automerge,
+        patch_log: &mut PatchLog,
+        ex_obj: &ExId,
+        mark: Mark<'_>,
+        expand: ExpandMark,
+    ) -> Result<Option<(OpIdx, OpIdx)>, AutomergeError> {
         if mark.start == mark.end && expand == ExpandMark::None {
             // In peritext terms this is the same as a mark which has a begin anchor before one
             // character and an end anchor after the character preceding that character. E.g in the
@@ -734,13 +1129,13 @@ impl TransactionInner {
             //
             // A mark from 1 to 1 with expand set to none would begin at the anchor point before
             // "b" and end at the anchor point after "a". This is nonsensical so we ignore it.
-            return Ok(());
+            return Ok(None);
         }
-        let obj = doc.exid_to_obj(ex_obj)?;
+        let obj = doc.exid_to_writable_obj(ex_obj)?;
         let action = OpType::MarkBegin(expand.before(), mark.data.clone().into_owned());
 
-        self.do_insert(doc, patch_log, &obj, mark.start, action)?;
-        self.do_insert(
+        let begin = self.do_insert(doc, patch_log, &obj, mark.start, action)?;
+        let end = self.do_insert(
             doc,
             patch_log,
             &obj,
@@ -750,7 +1145,7 @@ impl TransactionInner {
         if patch_log.is_active() {
             patch_log.mark(obj.id, mark.start, mark.len(), &mark.into_mark_set());
         }
-        Ok(())
+        Ok(Some((begin, end)))
     }
 
     #[allow(clippy::too_many_arguments)]
@@ -775,7 +1170,7 @@ impl TransactionInner {
         ex_obj: &ExId,
         index: usize,
     ) -> Result<ExId, AutomergeError> {
-        let obj = doc.exid_to_obj(ex_obj)?;
+        let obj = doc.exid_to_writable_obj(ex_obj)?;
         if obj.typ != ObjType::Text {
             return Err(AutomergeError::InvalidOp(obj.typ));
         }
@@ -825,7 +1220,7 @@ impl TransactionInner {
         text: &ExId,
         index: usize,
     ) -> Result<(), AutomergeError> {
-        let text_obj = doc.exid_to_obj(text)?;
+        let text_obj = doc.exid_to_writable_obj(text)?;
 
         if text_obj.typ != ObjType::Text {
             return Err(AutomergeError::InvalidOp(text_obj.typ));
@@ -940,10 +1335,26 @@ impl TransactionInner {
                     Prop::Seq(index) => patch_log.delete_seq(obj.id, index, 1),
                     Prop::Map(key) => patch_log.delete_map(obj.id, &key),
                 }
+                // the pred with the largest id is the value which was visible
+                if let Some(old) = op.pred().last() {
+                    patch_log.previous_value(old, None);
+                }
             } else if let Some(value) = op.get_increment_value() {
                 patch_log.increment(obj.id, &prop, value, *op.id());
             } else {
-                patch_log.put(obj.id, &prop, op.value().into(), *op.id(), false, false);
+                let replaced = op.pred().len() > 0;
+                patch_log.put(
+                    obj.id,
+                    &prop,
+                    op.value().into(),
+                    *op.id(),
+                    false,
+                    false,
+                    replaced,
+                );
+                if let Some(old) = op.pred().last() {
+                    patch_log.previous_value(old, None);
+                }
             }
         }
     }
@@ -955,7 +1366,7 @@ impl TransactionInner {
         obj: &ExId,
         new_value: &crate::hydrate::Value,
     ) -> Result<(), crate::error::UpdateObjectError> {
-        let obj_meta = doc.exid_to_obj(obj)?;
+        let obj_meta = doc.exid_to_writable_obj(obj)?;
         match (obj_meta.typ, new_value) {
             (ObjType::Map, crate::hydrate::Value::Map(map)) => {
                 Ok(self.update_map(doc, patch_log, obj, map)?)
@@ -984,7 +1395,7 @@ impl TransactionInner {
         new_value: &crate::hydrate::Map,
     ) -> Result<(), AutomergeError> {
         let mut delenda = HashSet::new();
-        let obj = doc.exid_to_obj(map)?;
+        let obj = doc.exid_to_writable_obj(map)?;
         let current_vals = doc
             .ops()
             .map_range(&obj.id, .., self.scope.clone())
@@ -1152,11 +1563,11 @@ impl<'a> SpliceType<'a> {
     }
 }
 
-struct SpliceArgs<'a> {
+struct SpliceArgs<'a, I> {
     obj: ObjMeta,
     index: usize,
     del: isize,
-    values: Vec<ScalarValue>,
+    values: I,
     splice_type: SpliceType<'a>,
 }
 
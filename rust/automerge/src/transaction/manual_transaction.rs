@@ -1,15 +1,18 @@
-use std::ops::RangeBounds;
+use std::ops::{Range, RangeBounds};
 
 use crate::exid::ExId;
 use crate::iter::Spans;
-use crate::iter::{Keys, ListRange, MapRange, Values};
-use crate::marks::{ExpandMark, Mark, MarkSet};
-use crate::patches::PatchLog;
+use crate::iter::{
+    AllMarks, Entries, Keys, KeysWithConflicts, ListRange, ListRangeRev, MapRange, Objects, Values,
+    ValuesRef,
+};
+use crate::marks::{ExpandMark, Mark, MarkSet, RichText};
+use crate::patches::{PatchLog, TextEdit};
 use crate::types::Clock;
-use crate::{hydrate, AutomergeError};
+use crate::{hydrate, ActorId, AutomergeError, ObjStats, ObjView, TextEncoding};
 use crate::{Automerge, ChangeHash, Cursor, ObjType, Parents, Prop, ReadDoc, ScalarValue, Value};
 
-use super::{CommitOptions, Transactable, TransactionArgs, TransactionInner};
+use super::{CommitOptions, PendingOpView, Transactable, TransactionArgs, TransactionInner};
 
 /// A transaction on a document.
 /// Transactions group operations into a single change so that no other operations can happen
@@ -32,6 +35,15 @@ pub struct Transaction<'a> {
     doc: &'a mut Automerge,
 }
 
+/// A point in a [`Transaction`] which can be returned to with [`Transaction::rollback_to`].
+///
+/// Created from [`Transaction::savepoint()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Savepoint {
+    ops: usize,
+    events: usize,
+}
+
 impl<'a> Transaction<'a> {
     pub(crate) fn new(doc: &'a mut Automerge, args: TransactionArgs, patch_log: PatchLog) -> Self {
         Self {
@@ -50,6 +62,32 @@ impl<'a> Transaction<'a> {
     pub fn hash_for_opid(&self, opid: &ExId) -> Option<ChangeHash> {
         self.doc.hash_for_opid(opid)
     }
+
+    /// Iterate over the operations made in this transaction so far, in the order they were made
+    ///
+    /// This borrows the operations from the document rather than copying them. Together with
+    /// [`Self::rollback()`] this can be used to validate a transaction before committing it.
+    ///
+    /// ```
+    /// # use automerge::{transaction::{PendingKey, Transactable}, Automerge, ROOT};
+    /// let mut doc = Automerge::new();
+    /// let mut tx = doc.transaction();
+    /// tx.put(ROOT, "admin", true).unwrap();
+    /// if tx.observe_ops().any(|op| op.key == PendingKey::Map("admin")) {
+    ///     tx.rollback();
+    /// } else {
+    ///     tx.commit();
+    /// }
+    /// assert!(doc.get_heads().is_empty());
+    /// ```
+    pub fn observe_ops(&self) -> impl Iterator<Item = PendingOpView<'_>> + '_ {
+        let ops = self.doc.ops();
+        self.inner
+            .as_ref()
+            .into_iter()
+            .flat_map(move |inner| inner.operations(&ops.osd))
+            .map(move |op| PendingOpView::new(ops, op))
+    }
 }
 
 impl<'a> Transaction<'a> {
@@ -58,7 +96,7 @@ impl<'a> Transaction<'a> {
         args: TransactionArgs,
         opts: CommitOptions,
     ) -> ChangeHash {
-        TransactionInner::empty(doc, args, opts.message, opts.time)
+        TransactionInner::empty(doc, args, opts)
     }
 }
 
@@ -94,18 +132,122 @@ impl<'a> Transaction<'a> {
     /// tx.commit_with(CommitOptions::default().with_message("Create todos list").with_time(now));
     /// ```
     pub fn commit_with(mut self, options: CommitOptions) -> (Option<ChangeHash>, PatchLog) {
-        let tx = self.inner.take().unwrap();
+        let mut tx = self.inner.take().unwrap();
+        tx.set_extra(options.extra);
         let hash = tx.commit(self.doc, options.message, options.time);
         // TODO - remove this clone
         (hash, self.patch_log.clone())
     }
 
+    /// Commit this transaction only if it was started at `expected`, otherwise roll it back
+    ///
+    /// `expected` is compared with the heads this transaction was started from, ignoring order.
+    /// This allows compare and swap style updates: a caller reads the document at some heads,
+    /// decides what to change and only commits if nothing has been added to the document in the
+    /// meantime. Returns `Ok(None)` if the heads don't match or if there was nothing to commit.
+    ///
+    /// ```
+    /// # use automerge::{transaction::Transactable, Automerge, ROOT};
+    /// let mut doc = Automerge::new();
+    /// let expected = doc.get_heads();
+    /// doc.transact::<_, _, automerge::AutomergeError>(|tx| {
+    ///     tx.put(ROOT, "key", "someone else")?;
+    ///     Ok(())
+    /// }).unwrap();
+    ///
+    /// let mut tx = doc.transaction();
+    /// tx.put(ROOT, "key", "mine").unwrap();
+    /// assert_eq!(tx.commit_if_heads(&expected).unwrap(), None);
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AutomergeError::MissingHash`] if `expected` contains a hash which is not in the
+    /// document, the transaction is rolled back in this case too
+    pub fn commit_if_heads(
+        mut self,
+        expected: &[ChangeHash],
+    ) -> Result<Option<ChangeHash>, AutomergeError> {
+        let tx = self.inner.take().unwrap();
+        if let Some(missing) = expected
+            .iter()
+            .find(|h| self.doc.get_change_by_hash(h).is_none())
+        {
+            tx.rollback(self.doc);
+            return Err(AutomergeError::MissingHash(*missing));
+        }
+        let mut expected = expected.to_vec();
+        expected.sort();
+        expected.dedup();
+        let mut heads = tx.get_deps();
+        heads.sort();
+        if heads != expected {
+            tx.rollback(self.doc);
+            return Ok(None);
+        }
+        Ok(tx.commit(self.doc, None, None))
+    }
+
+    /// Set the message to use when this transaction is committed.
+    ///
+    /// A message passed to [`Self::commit_with`] takes precedence over this one.
+    ///
+    /// ```
+    /// # use automerge::{transaction::Transactable, Automerge, ReadDoc, ROOT};
+    /// let mut doc = Automerge::new();
+    /// let mut tx = doc.transaction();
+    /// tx.set_message("Add a key".to_string());
+    /// tx.set_timestamp(1000);
+    /// tx.put(ROOT, "key", "value").unwrap();
+    /// let (hash, _) = tx.commit();
+    /// let change = doc.get_change_by_hash(&hash.unwrap()).unwrap();
+    /// assert_eq!(change.message().map(String::as_str), Some("Add a key"));
+    /// assert_eq!(change.timestamp(), 1000);
+    /// ```
+    pub fn set_message(&mut self, message: String) {
+        self.inner.as_mut().unwrap().set_message(message)
+    }
+
+    /// Set the timestamp to use when this transaction is committed.
+    ///
+    /// A time passed to [`Self::commit_with`] takes precedence over this one.
+    pub fn set_timestamp(&mut self, timestamp: i64) {
+        self.inner.as_mut().unwrap().set_time(timestamp)
+    }
+
     /// Undo the operations added in this transaction, returning the number of cancelled
     /// operations.
     pub fn rollback(mut self) -> usize {
         self.inner.take().unwrap().rollback(self.doc)
     }
 
+    /// Record the current state of the transaction so that operations added after this point can
+    /// be undone with [`Self::rollback_to`] without aborting the whole transaction.
+    ///
+    /// ```
+    /// # use automerge::{transaction::Transactable, Automerge, ROOT, ReadDoc};
+    /// let mut doc = Automerge::new();
+    /// let mut tx = doc.transaction();
+    /// tx.put(ROOT, "kept", 1).unwrap();
+    /// let savepoint = tx.savepoint();
+    /// tx.put(ROOT, "discarded", 2).unwrap();
+    /// assert_eq!(tx.rollback_to(savepoint), 1);
+    /// tx.commit();
+    /// assert_eq!(doc.keys(ROOT).collect::<Vec<_>>(), vec!["kept"]);
+    /// ```
+    pub fn savepoint(&mut self) -> Savepoint {
+        Savepoint {
+            ops: self.pending_ops(),
+            events: self.patch_log.events_len(),
+        }
+    }
+
+    /// Undo the operations added since `savepoint` was taken, returning the number of cancelled
+    /// operations. Operations added before the savepoint are kept.
+    pub fn rollback_to(&mut self, savepoint: Savepoint) -> usize {
+        self.do_tx(|tx, doc, hist| tx.rollback_to(doc, hist, savepoint.ops, savepoint.events))
+    }
+
     fn do_tx<F, O>(&mut self, f: F) -> O
     where
         F: FnOnce(&mut TransactionInner, &mut Automerge, &mut PatchLog) -> O,
@@ -124,6 +266,18 @@ impl<'a> Transaction<'a> {
 }
 
 impl<'a> ReadDoc for Transaction<'a> {
+    fn view<O: AsRef<ExId>>(&self, obj: O) -> Result<ObjView<'_>, AutomergeError> {
+        self.doc.view_for(obj.as_ref(), self.get_scope(None))
+    }
+
+    fn view_at<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        heads: &[ChangeHash],
+    ) -> Result<ObjView<'_>, AutomergeError> {
+        self.doc.view_for(obj.as_ref(), self.get_scope(Some(heads)))
+    }
+
     fn keys<O: AsRef<ExId>>(&self, obj: O) -> Keys<'_> {
         self.doc.keys_for(obj.as_ref(), self.get_scope(None))
     }
@@ -132,6 +286,26 @@ impl<'a> ReadDoc for Transaction<'a> {
         self.doc.keys_for(obj.as_ref(), self.get_scope(Some(heads)))
     }
 
+    fn keys_with_conflicts<O: AsRef<ExId>>(&self, obj: O) -> KeysWithConflicts<'_> {
+        self.doc
+            .keys_with_conflicts_for(obj.as_ref(), self.get_scope(None))
+    }
+
+    fn map_keys_in_insertion_order<O: AsRef<ExId>>(&self, obj: O) -> Vec<String> {
+        self.doc
+            .map_keys_in_insertion_order_for(obj.as_ref(), self.get_scope(None))
+    }
+
+    fn map_values_sorted_by<O: AsRef<ExId>, K: Ord, F: Fn(&Value<'_>) -> K>(
+        &self,
+        obj: O,
+        key_fn: F,
+        heads: Option<&[ChangeHash]>,
+    ) -> Result<Vec<(Prop, Value<'_>)>, AutomergeError> {
+        self.doc
+            .map_values_sorted_by_for(obj.as_ref(), key_fn, self.get_scope(heads))
+    }
+
     fn map_range<'b, O: AsRef<ExId>, R: RangeBounds<String> + 'b>(
         &'b self,
         obj: O,
@@ -170,6 +344,53 @@ impl<'a> ReadDoc for Transaction<'a> {
             .list_range_for(obj.as_ref(), range, self.get_scope(Some(heads)))
     }
 
+    fn find_indices<O: AsRef<ExId>, F: Fn(&Value<'_>) -> bool>(
+        &self,
+        obj: O,
+        pred: F,
+        heads: Option<&[ChangeHash]>,
+    ) -> Result<Vec<usize>, AutomergeError> {
+        self.doc
+            .find_indices_for(obj.as_ref(), pred, self.get_scope(heads))
+    }
+
+    fn list_endpoints<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        heads: Option<&[ChangeHash]>,
+    ) -> Result<Option<(crate::ListEndpoint<'_>, crate::ListEndpoint<'_>)>, AutomergeError> {
+        self.doc
+            .list_endpoints_for(obj.as_ref(), self.get_scope(heads))
+    }
+
+    fn list_range_rev<O: AsRef<ExId>, R: RangeBounds<usize>>(
+        &self,
+        obj: O,
+        range: R,
+    ) -> ListRangeRev<'_> {
+        self.doc
+            .list_range_rev_for(obj.as_ref(), range, self.get_scope(None))
+    }
+
+    fn list_range_rev_at<O: AsRef<ExId>, R: RangeBounds<usize>>(
+        &self,
+        obj: O,
+        range: R,
+        heads: &[ChangeHash],
+    ) -> ListRangeRev<'_> {
+        self.doc
+            .list_range_rev_for(obj.as_ref(), range, self.get_scope(Some(heads)))
+    }
+
+    fn read_bytes<O: AsRef<ExId>, R: RangeBounds<usize>>(
+        &self,
+        obj: O,
+        range: R,
+    ) -> Result<Vec<u8>, AutomergeError> {
+        self.doc
+            .read_bytes_for(obj.as_ref(), range, self.get_scope(None))
+    }
+
     fn values<O: AsRef<ExId>>(&self, obj: O) -> Values<'_> {
         self.doc.values_for(obj.as_ref(), self.get_scope(None))
     }
@@ -179,10 +400,37 @@ impl<'a> ReadDoc for Transaction<'a> {
             .values_for(obj.as_ref(), self.get_scope(Some(heads)))
     }
 
+    fn entries<O: AsRef<ExId>>(&self, obj: O) -> Entries<'_> {
+        self.doc.entries_for(obj.as_ref(), self.get_scope(None))
+    }
+
+    fn entries_at<O: AsRef<ExId>>(&self, obj: O, heads: &[ChangeHash]) -> Entries<'_> {
+        self.doc
+            .entries_for(obj.as_ref(), self.get_scope(Some(heads)))
+    }
+
+    fn values_ref<O: AsRef<ExId>>(&self, obj: O) -> ValuesRef<'_> {
+        self.doc.values_ref_for(obj.as_ref(), self.get_scope(None))
+    }
+
+    fn objects(&self) -> Objects<'_> {
+        Objects::new(self.doc, self.get_scope(None))
+    }
+
     fn length<O: AsRef<ExId>>(&self, obj: O) -> usize {
         self.doc.length_for(obj.as_ref(), self.get_scope(None))
     }
 
+    fn length_utf16<O: AsRef<ExId>>(&self, obj: O) -> usize {
+        self.doc
+            .length_utf16_for(obj.as_ref(), self.get_scope(None))
+    }
+
+    fn length_grapheme<O: AsRef<ExId>>(&self, obj: O) -> usize {
+        self.doc
+            .length_grapheme_for(obj.as_ref(), self.get_scope(None))
+    }
+
     fn length_at<O: AsRef<ExId>>(&self, obj: O, heads: &[ChangeHash]) -> usize {
         self.doc
             .length_for(obj.as_ref(), self.get_scope(Some(heads)))
@@ -192,6 +440,15 @@ impl<'a> ReadDoc for Transaction<'a> {
         self.doc.object_type(obj)
     }
 
+    fn object_type_at<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        heads: &[ChangeHash],
+    ) -> Result<Option<ObjType>, AutomergeError> {
+        self.doc
+            .object_type_for(obj.as_ref(), self.get_scope(Some(heads)))
+    }
+
     fn text<O: AsRef<ExId>>(&self, obj: O) -> Result<String, AutomergeError> {
         self.doc.text_for(obj.as_ref(), self.get_scope(None))
     }
@@ -204,6 +461,15 @@ impl<'a> ReadDoc for Transaction<'a> {
         self.doc.text_for(obj.as_ref(), self.get_scope(Some(heads)))
     }
 
+    fn text_diff<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        before: &[ChangeHash],
+        after: &[ChangeHash],
+    ) -> Result<Vec<TextEdit>, AutomergeError> {
+        self.doc.text_diff_for(obj.as_ref(), before, after)
+    }
+
     fn spans<O: AsRef<ExId>>(&self, obj: O) -> Result<Spans<'_>, AutomergeError> {
         self.doc.spans_for(obj.as_ref(), self.get_scope(None))
     }
@@ -217,6 +483,40 @@ impl<'a> ReadDoc for Transaction<'a> {
             .spans_for(obj.as_ref(), self.get_scope(Some(heads)))
     }
 
+    fn spans_range<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        range: Range<usize>,
+        heads: Option<&[ChangeHash]>,
+    ) -> Result<Spans<'_>, AutomergeError> {
+        self.doc
+            .spans_range_for(obj.as_ref(), range, self.get_scope(heads))
+    }
+
+    fn export_rich_text<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        range: Range<usize>,
+        heads: Option<&[ChangeHash]>,
+    ) -> Result<RichText, AutomergeError> {
+        self.doc
+            .export_rich_text_for(obj.as_ref(), range, self.get_scope(heads))
+    }
+
+    fn import_exid(&self, s: &str) -> Result<ExId, AutomergeError> {
+        self.doc.import_exid(s)
+    }
+
+    fn char_to_utf16<O: AsRef<ExId>>(&self, obj: O, index: usize) -> Result<usize, AutomergeError> {
+        self.doc
+            .char_to_utf16_for(obj.as_ref(), index, self.get_scope(None))
+    }
+
+    fn utf16_to_char<O: AsRef<ExId>>(&self, obj: O, index: usize) -> Result<usize, AutomergeError> {
+        self.doc
+            .utf16_to_char_for(obj.as_ref(), index, self.get_scope(None))
+    }
+
     fn get_cursor<O: AsRef<ExId>>(
         &self,
         obj: O,
@@ -237,6 +537,78 @@ impl<'a> ReadDoc for Transaction<'a> {
             .get_cursor_position_for(obj.as_ref(), address, self.get_scope(at))
     }
 
+    fn get_cursor_positions<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        cursors: &[Cursor],
+        at: Option<&[ChangeHash]>,
+    ) -> Result<Vec<usize>, AutomergeError> {
+        self.doc
+            .get_cursor_positions_for(obj.as_ref(), cursors, self.get_scope(at))
+    }
+
+    fn get_cursor_position_and_value<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        address: &Cursor,
+        at: Option<&[ChangeHash]>,
+    ) -> Result<(usize, Option<Value<'_>>), AutomergeError> {
+        self.doc
+            .get_cursor_position_and_value_for(obj.as_ref(), address, self.get_scope(at))
+    }
+
+    fn index_of<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        elem: &ExId,
+        at: Option<&[ChangeHash]>,
+    ) -> Result<Option<usize>, AutomergeError> {
+        self.doc
+            .index_of_for(obj.as_ref(), elem, self.get_scope(at))
+    }
+
+    fn element_token<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        index: usize,
+    ) -> Result<String, AutomergeError> {
+        self.doc
+            .element_token_for(obj.as_ref(), index, self.get_scope(None))
+    }
+
+    fn index_of_token<O: AsRef<ExId>>(&self, obj: O, token: &str) -> Result<usize, AutomergeError> {
+        self.doc
+            .index_of_token_for(obj.as_ref(), token, self.get_scope(None))
+    }
+
+    fn visible_count_before<O: AsRef<ExId>>(&self, obj: O, op_pos: usize) -> usize {
+        self.doc
+            .visible_count_before_for(obj.as_ref(), op_pos, self.get_scope(None))
+    }
+
+    fn value_provenance<O: AsRef<ExId>, P: Into<Prop>>(
+        &self,
+        obj: O,
+        prop: P,
+    ) -> Result<Option<ChangeHash>, AutomergeError> {
+        self.doc
+            .value_provenance_for(obj.as_ref(), prop.into(), self.get_scope(None))
+    }
+
+    fn map_contains_key<O: AsRef<ExId>>(&self, obj: O, key: &str) -> Result<bool, AutomergeError> {
+        self.doc
+            .map_contains_key_for(obj.as_ref(), key, self.get_scope(None))
+    }
+
+    fn list_contains_index<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        index: usize,
+    ) -> Result<bool, AutomergeError> {
+        self.doc
+            .list_contains_index_for(obj.as_ref(), index, self.get_scope(None))
+    }
+
     fn marks<O: AsRef<ExId>>(&self, obj: O) -> Result<Vec<Mark<'_>>, AutomergeError> {
         self.doc.marks_for(obj.as_ref(), self.get_scope(None))
     }
@@ -250,6 +622,14 @@ impl<'a> ReadDoc for Transaction<'a> {
             .marks_for(obj.as_ref(), self.get_scope(Some(heads)))
     }
 
+    fn all_marks(&self) -> AllMarks<'_> {
+        AllMarks::new(self.doc, self.get_scope(None))
+    }
+
+    fn all_marks_at(&self, heads: &[ChangeHash]) -> AllMarks<'_> {
+        AllMarks::new(self.doc, self.get_scope(Some(heads)))
+    }
+
     fn hydrate<O: AsRef<ExId>>(
         &self,
         obj: O,
@@ -258,6 +638,16 @@ impl<'a> ReadDoc for Transaction<'a> {
         self.doc.hydrate_obj(obj.as_ref(), heads)
     }
 
+    fn marks_in_range<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        range: Range<usize>,
+        heads: Option<&[ChangeHash]>,
+    ) -> Result<Vec<Mark<'_>>, AutomergeError> {
+        self.doc
+            .marks_in_range_for(obj.as_ref(), range, self.get_scope(heads))
+    }
+
     fn get_marks<O: AsRef<ExId>>(
         &self,
         obj: O,
@@ -268,6 +658,16 @@ impl<'a> ReadDoc for Transaction<'a> {
             .get_marks_for(obj.as_ref(), index, self.get_scope(heads))
     }
 
+    fn text_at_cursor<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        cursor: &Cursor,
+        heads: Option<&[ChangeHash]>,
+    ) -> Result<MarkSet, AutomergeError> {
+        self.doc
+            .text_at_cursor_for(obj.as_ref(), cursor, self.get_scope(heads))
+    }
+
     fn get<O: AsRef<ExId>, P: Into<Prop>>(
         &self,
         obj: O,
@@ -306,6 +706,59 @@ impl<'a> ReadDoc for Transaction<'a> {
             .get_all_for(obj.as_ref(), prop.into(), self.get_scope(Some(heads)))
     }
 
+    fn get_conflicts<O: AsRef<ExId>, P: Into<Prop>>(
+        &self,
+        obj: O,
+        prop: P,
+    ) -> Result<Vec<(ActorId, Value<'_>, ExId)>, AutomergeError> {
+        self.doc
+            .get_conflicts_for(obj.as_ref(), prop.into(), self.get_scope(None))
+    }
+
+    fn get_timestamp<O: AsRef<ExId>, P: Into<Prop>>(
+        &self,
+        obj: O,
+        prop: P,
+    ) -> Result<Option<i64>, AutomergeError> {
+        self.doc
+            .get_timestamp_for(obj.as_ref(), prop.into(), self.get_scope(None))
+    }
+
+    fn get_at_element_creation<O: AsRef<ExId>, P: Into<Prop>, L: AsRef<ExId>>(
+        &self,
+        obj: O,
+        prop: P,
+        list: L,
+        cursor: &Cursor,
+    ) -> Result<Option<(Value<'_>, ExId)>, AutomergeError> {
+        self.doc.get_at_element_creation_for(
+            obj.as_ref(),
+            prop.into(),
+            list.as_ref(),
+            cursor,
+            self.get_scope(None),
+        )
+    }
+
+    fn get_by_timestamp<O: AsRef<ExId>, P: Into<Prop>>(
+        &self,
+        obj: O,
+        prop: P,
+    ) -> Result<Option<(Value<'_>, ExId)>, AutomergeError> {
+        self.doc
+            .get_by_timestamp_for(obj.as_ref(), prop.into(), self.get_scope(None))
+    }
+
+    fn get_many_at<O: AsRef<ExId>>(
+        &self,
+        obj: O,
+        props: &[Prop],
+        heads: &[ChangeHash],
+    ) -> Result<Vec<(Prop, Option<(Value<'_>, ExId)>)>, AutomergeError> {
+        self.doc
+            .get_many_for(obj.as_ref(), props, self.get_scope(Some(heads)))
+    }
+
     fn parents<O: AsRef<ExId>>(&self, obj: O) -> Result<Parents<'_>, AutomergeError> {
         self.doc.parents_for(obj.as_ref(), self.get_scope(None))
     }
@@ -330,6 +783,20 @@ impl<'a> ReadDoc for Transaction<'a> {
     fn stats(&self) -> crate::read::Stats {
         self.doc.stats()
     }
+
+    fn object_stats<O: AsRef<ExId>>(&self, obj: O) -> Result<ObjStats, AutomergeError> {
+        self.doc
+            .object_stats_for(obj.as_ref(), self.get_scope(None))
+    }
+
+    fn tombstone_count<O: AsRef<ExId>>(&self, obj: O) -> Result<usize, AutomergeError> {
+        self.doc
+            .tombstone_count_for(obj.as_ref(), self.get_scope(None))
+    }
+
+    fn total_tombstones(&self) -> usize {
+        self.doc.total_tombstones_for(self.get_scope(None))
+    }
 }
 
 impl<'a> Transactable for Transaction<'a> {
@@ -355,6 +822,15 @@ impl<'a> Transactable for Transaction<'a> {
         self.do_tx(|tx, doc, hist| tx.put(doc, hist, obj.as_ref(), prop, value))
     }
 
+    fn put_if_absent<O: AsRef<ExId>, P: Into<Prop>, V: Into<ScalarValue>>(
+        &mut self,
+        obj: O,
+        prop: P,
+        value: V,
+    ) -> Result<bool, AutomergeError> {
+        self.do_tx(|tx, doc, hist| tx.put_if_absent(doc, hist, obj.as_ref(), prop, value))
+    }
+
     fn put_object<O: AsRef<ExId>, P: Into<Prop>>(
         &mut self,
         obj: O,
@@ -412,6 +888,15 @@ impl<'a> Transactable for Transaction<'a> {
         Ok(())
     }
 
+    fn splice_objects<O: AsRef<ExId>>(
+        &mut self,
+        obj: O,
+        index: usize,
+        objs: Vec<ObjType>,
+    ) -> Result<Vec<ExId>, AutomergeError> {
+        self.do_tx(|tx, doc, hist| tx.splice_objects(doc, hist, obj.as_ref(), index, objs))
+    }
+
     fn splice_text<O: AsRef<ExId>>(
         &mut self,
         obj: O,
@@ -423,6 +908,59 @@ impl<'a> Transactable for Transaction<'a> {
         Ok(())
     }
 
+    fn splice_text_with_ids<O: AsRef<ExId>>(
+        &mut self,
+        obj: O,
+        pos: usize,
+        text: &str,
+    ) -> Result<Vec<ExId>, AutomergeError> {
+        self.do_tx(|tx, doc, hist| tx.splice_text_with_ids(doc, hist, obj.as_ref(), pos, text))
+    }
+
+    fn splice_text_with_id_range<O: AsRef<ExId>>(
+        &mut self,
+        obj: O,
+        pos: usize,
+        text: &str,
+    ) -> Result<Option<(ExId, ExId)>, AutomergeError> {
+        self.do_tx(|tx, doc, hist| tx.splice_text_with_id_range(doc, hist, obj.as_ref(), pos, text))
+    }
+
+    fn splice_text_utf16<O: AsRef<ExId>>(
+        &mut self,
+        obj: O,
+        pos: usize,
+        del: isize,
+        text: &str,
+    ) -> Result<(), AutomergeError> {
+        self.do_tx(|tx, doc, hist| tx.splice_text_utf16(doc, hist, obj.as_ref(), pos, del, text))
+    }
+
+    fn splice_text_with_encoding<O: AsRef<ExId>>(
+        &mut self,
+        obj: O,
+        pos: usize,
+        del: isize,
+        text: &str,
+        encoding: TextEncoding,
+    ) -> Result<(), AutomergeError> {
+        self.do_tx(|tx, doc, hist| {
+            tx.splice_text_with_encoding(doc, hist, obj.as_ref(), pos, del, text, encoding.into())
+        })
+    }
+
+    fn splice_text_with_marks<O: AsRef<ExId>>(
+        &mut self,
+        obj: O,
+        pos: usize,
+        text: &str,
+        marks: &[(Range<usize>, &str, ScalarValue)],
+    ) -> Result<(), AutomergeError> {
+        self.do_tx(|tx, doc, hist| {
+            tx.splice_text_with_marks(doc, hist, obj.as_ref(), pos, text, marks)
+        })
+    }
+
     fn mark<O: AsRef<ExId>>(
         &mut self,
         obj: O,
@@ -432,6 +970,15 @@ impl<'a> Transactable for Transaction<'a> {
         self.do_tx(|tx, doc, hist| tx.mark(doc, hist, obj.as_ref(), mark, expand))
     }
 
+    fn mark_and_return_range<O: AsRef<ExId>>(
+        &mut self,
+        obj: O,
+        mark: Mark<'_>,
+        expand: ExpandMark,
+    ) -> Result<Range<usize>, AutomergeError> {
+        self.do_tx(|tx, doc, hist| tx.mark_and_return_range(doc, hist, obj.as_ref(), mark, expand))
+    }
+
     fn unmark<O: AsRef<ExId>>(
         &mut self,
         obj: O,
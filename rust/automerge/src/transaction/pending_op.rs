@@ -0,0 +1,47 @@
+use crate::exid::ExId;
+use crate::op_set::{Op, OpSet};
+use crate::types::{Key, OpType};
+
+/// An operation which has been made in a [`crate::transaction::Transaction`] but not yet
+/// committed, see [`crate::transaction::Transaction::observe_ops()`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct PendingOpView<'a> {
+    /// The id of the operation
+    pub id: ExId,
+    /// The object the operation modifies
+    pub obj: ExId,
+    /// The property of `obj` the operation modifies
+    pub key: PendingKey<'a>,
+    /// Whether the operation inserts a new element into a sequence
+    pub insert: bool,
+    /// What the operation does, including the value for a put
+    pub action: &'a OpType,
+}
+
+/// The property modified by a [`PendingOpView`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum PendingKey<'a> {
+    /// A key in a map
+    Map(&'a str),
+    /// An element of a sequence, identified by the id of the operation which inserted it. For an
+    /// insert this is the element the new element is inserted after, with [`None`] meaning the
+    /// start of the sequence.
+    Seq(Option<ExId>),
+}
+
+impl<'a> PendingOpView<'a> {
+    pub(crate) fn new(ops: &'a OpSet, op: Op<'a>) -> Self {
+        let key = match op.key() {
+            Key::Map(n) => PendingKey::Map(&ops.osd.props[*n]),
+            Key::Seq(e) if e.is_head() => PendingKey::Seq(None),
+            Key::Seq(e) => PendingKey::Seq(Some(ops.id_to_exid(e.0))),
+        };
+        PendingOpView {
+            id: op.exid(),
+            obj: ops.id_to_exid(op.obj().0),
+            key,
+            insert: op.insert(),
+            action: op.action(),
+        }
+    }
+}
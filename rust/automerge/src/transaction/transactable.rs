@@ -1,8 +1,12 @@
 use std::borrow::Cow;
+use std::ops::Range;
 
 use crate::exid::ExId;
-use crate::marks::{ExpandMark, Mark};
-use crate::{AutomergeError, ChangeHash, ObjType, Prop, ReadDoc, ScalarValue};
+use crate::marks::{ExpandMark, Mark, RichText};
+use crate::{
+    AutomergeError, ChangeHash, ObjType, Patch, PatchAction, Prop, ReadDoc, ScalarValue, Value,
+    ROOT,
+};
 
 /// A way of mutating a document within a single change.
 pub trait Transactable: ReadDoc {
@@ -24,6 +28,30 @@ pub trait Transactable: ReadDoc {
         value: V,
     ) -> Result<(), AutomergeError>;
 
+    /// Set the value of property `P` to value `V` in object `obj` if there is no visible value for
+    /// `P` yet.
+    ///
+    /// This only checks the state of the document this transaction can see, concurrent changes
+    /// from other actors may still put a value for the same property.
+    ///
+    /// # Returns
+    ///
+    /// Whether the value was put. Every index in a sequence always has a value so for lists and
+    /// text this always returns `false`.
+    ///
+    /// # Errors
+    ///
+    /// This will return an error if
+    /// - The object does not exist
+    /// - The key is the wrong type for the object
+    /// - The index is out of bounds for a sequence
+    fn put_if_absent<O: AsRef<ExId>, P: Into<Prop>, V: Into<ScalarValue>>(
+        &mut self,
+        obj: O,
+        prop: P,
+        value: V,
+    ) -> Result<bool, AutomergeError>;
+
     /// Set the value of property `P` to the new object `V` in object `obj`.
     ///
     /// # Returns
@@ -43,6 +71,42 @@ pub trait Transactable: ReadDoc {
         object: ObjType,
     ) -> Result<ExId, AutomergeError>;
 
+    /// Like [`Self::put_object()`] but then calls `init` with the id of the new object so it can
+    /// be populated
+    ///
+    /// ```
+    /// # use automerge::{transaction::Transactable, AutoCommit, ObjType, ReadDoc, ROOT};
+    /// let mut doc = AutoCommit::new();
+    /// let user = doc
+    ///     .put_object_init(ROOT, "user", ObjType::Map, |doc, user| {
+    ///         doc.put(user, "name", "alice")?;
+    ///         doc.put(user, "age", 42)
+    ///     })
+    ///     .unwrap();
+    /// assert_eq!(doc.keys(&user).collect::<Vec<_>>(), vec!["age", "name"]);
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// This will return an error if creating the object fails or if `init` returns an error.
+    /// Any operations `init` made before failing are not rolled back.
+    fn put_object_init<O, P, F>(
+        &mut self,
+        obj: O,
+        prop: P,
+        object: ObjType,
+        init: F,
+    ) -> Result<ExId, AutomergeError>
+    where
+        O: AsRef<ExId>,
+        P: Into<Prop>,
+        F: FnOnce(&mut Self, &ExId) -> Result<(), AutomergeError>,
+    {
+        let id = self.put_object(obj, prop, object)?;
+        init(self, &id)?;
+        Ok(id)
+    }
+
     /// Insert a value into a list at the given index.
     fn insert<O: AsRef<ExId>, V: Into<ScalarValue>>(
         &mut self,
@@ -85,6 +149,130 @@ pub trait Transactable: ReadDoc {
         vals: V,
     ) -> Result<(), AutomergeError>;
 
+    /// Like [`Self::splice`] but deletes `del` values after `pos` and takes the new values from an
+    /// iterator
+    ///
+    /// The values are turned into operations as they are produced rather than being collected
+    /// first, so this is a good way to insert a large generated sequence. Deleting more values
+    /// than there are after `pos` deletes to the end of the sequence, as with [`Self::splice`].
+    ///
+    /// ```
+    /// # use automerge::{transaction::Transactable, AutoCommit, ObjType, ReadDoc, ROOT};
+    /// let mut doc = AutoCommit::new();
+    /// let list = doc.put_object(ROOT, "squares", ObjType::List).unwrap();
+    /// doc.splice_iter(&list, 0, 0, (0..1000_u64).map(|i| (i * i).into())).unwrap();
+    /// assert_eq!(doc.length(&list), 1000);
+    /// assert_eq!(doc.get(&list, 3).unwrap().unwrap().0.to_u64(), Some(9));
+    /// ```
+    fn splice_iter<O: AsRef<ExId>, I: Iterator<Item = ScalarValue>>(
+        &mut self,
+        obj: O,
+        pos: usize,
+        del: usize,
+        values: I,
+    ) -> Result<(), AutomergeError> {
+        let del = isize::try_from(del).unwrap_or(isize::MAX);
+        self.splice(obj, pos, del, values)
+    }
+
+    /// Delete every visible key of the map `obj`, or every element of the list or text `obj`
+    ///
+    /// For a sequence this is a splice deleting the whole sequence, for a map it deletes each key
+    /// in turn, including all the conflicting values of a key. Clearing an empty object creates no
+    /// operations.
+    ///
+    /// ```
+    /// # use automerge::{transaction::Transactable, AutoCommit, ObjType, ReadDoc, ROOT};
+    /// let mut doc = AutoCommit::new();
+    /// let list = doc.put_object(ROOT, "list", ObjType::List).unwrap();
+    /// doc.splice(&list, 0, 0, [1.into(), 2.into(), 3.into()]).unwrap();
+    /// doc.clear(&list).unwrap();
+    /// assert_eq!(doc.length(&list), 0);
+    /// doc.clear(ROOT).unwrap();
+    /// assert_eq!(doc.keys(ROOT).count(), 0);
+    /// ```
+    fn clear<O: AsRef<ExId>>(&mut self, obj: O) -> Result<(), AutomergeError> {
+        let obj = obj.as_ref();
+        let obj_type = self.object_type(obj)?;
+        if obj_type.is_sequence() {
+            let len = isize::try_from(self.length(obj)).unwrap_or(isize::MAX);
+            if len == 0 {
+                Ok(())
+            } else if obj_type == ObjType::Text {
+                self.splice_text(obj, 0, len, "")
+            } else {
+                self.splice(obj, 0, len, std::iter::empty())
+            }
+        } else {
+            let keys: Vec<_> = self.keys(obj).collect();
+            for key in keys {
+                self.delete(obj, key)?;
+            }
+            Ok(())
+        }
+    }
+
+    /// Like [`Self::splice`] but inserts each byte of `bytes` as a [`ScalarValue::Uint`]
+    ///
+    /// Together with [`ReadDoc::read_bytes()`] this lets a list be used as a byte buffer which
+    /// can be edited incrementally rather than replacing a whole [`ScalarValue::Bytes`] on each
+    /// change. Each byte is a list element, so inserting `n` bytes costs `n` operations and the
+    /// buffer takes as much space in the document as a list of `n` numbers. This suits small
+    /// buffers which are edited often, a large blob is better stored as a
+    /// [`ScalarValue::Bytes`].
+    fn splice_bytes<O: AsRef<ExId>>(
+        &mut self,
+        obj: O,
+        pos: usize,
+        del: isize,
+        bytes: &[u8],
+    ) -> Result<(), AutomergeError> {
+        let vals = bytes.iter().map(|b| ScalarValue::Uint(u64::from(*b)));
+        self.splice(obj, pos, del, vals)
+    }
+
+    /// Delete the elements in `range` from the list `obj`
+    ///
+    /// This is the same as [`Self::splice()`] with no values to insert, except that it is an
+    /// error for the range to extend past the end of the list.
+    ///
+    /// # Errors
+    ///
+    /// This will return an error if
+    /// - The object does not exist or is not a list
+    /// - The range is reversed or extends past the end of the list
+    fn delete_range<O: AsRef<ExId>>(
+        &mut self,
+        obj: O,
+        range: Range<usize>,
+    ) -> Result<(), AutomergeError> {
+        let obj = obj.as_ref();
+        let typ = self.object_type(obj)?;
+        if typ != ObjType::List {
+            return Err(AutomergeError::InvalidOp(typ));
+        }
+        if range.start > range.end {
+            return Err(AutomergeError::InvalidIndex(range.start));
+        }
+        if range.end > self.length(obj) {
+            return Err(AutomergeError::InvalidIndex(range.end));
+        }
+        self.splice(obj, range.start, range.len() as isize, [])
+    }
+
+    /// Insert a new, empty object of each type in `objs` into the list `obj`, one after the
+    /// other starting at `index`
+    ///
+    /// Returns the ids of the new objects in the same order as `objs`. This is the same as
+    /// calling [`Self::insert_object()`] for each object but only looks up the insert position
+    /// once.
+    fn splice_objects<O: AsRef<ExId>>(
+        &mut self,
+        obj: O,
+        index: usize,
+        objs: Vec<ObjType>,
+    ) -> Result<Vec<ExId>, AutomergeError>;
+
     /// Like [`Self::splice`] but for text.
     fn splice_text<O: AsRef<ExId>>(
         &mut self,
@@ -94,6 +282,101 @@ pub trait Transactable: ReadDoc {
         text: &str,
     ) -> Result<(), AutomergeError>;
 
+    /// Like [`Self::splice_text`] but `pos` and `del` are in UTF-16 code units, see
+    /// [`crate::ReadDoc::char_to_utf16`]
+    ///
+    /// Returns [`AutomergeError::InvalidIndex`] if either end of the deleted range is in the
+    /// middle of a surrogate pair or past the end of the text.
+    fn splice_text_utf16<O: AsRef<ExId>>(
+        &mut self,
+        obj: O,
+        pos: usize,
+        del: isize,
+        text: &str,
+    ) -> Result<(), AutomergeError>;
+
+    /// Like [`Self::splice_text`] but `pos` and `del` are measured in `encoding` rather than the
+    /// native encoding of text in this build of automerge
+    ///
+    /// This is useful when edits come from clients which each count offsets differently.
+    ///
+    /// ```
+    /// # use automerge::{transaction::Transactable, AutoCommit, ObjType, ReadDoc, ROOT};
+    /// # use automerge::TextEncoding;
+    /// let mut doc = AutoCommit::new();
+    /// let text = doc.put_object(ROOT, "text", ObjType::Text).unwrap();
+    /// doc.splice_text(&text, 0, 0, "héllo").unwrap();
+    /// // "é" is two bytes in UTF-8, so "llo" starts at byte 3
+    /// doc.splice_text_with_encoding(&text, 3, 3, "p!", TextEncoding::Utf8CodeUnit)
+    ///     .unwrap();
+    /// assert_eq!(doc.text(&text).unwrap(), "hép!");
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AutomergeError::InvalidIndex`] if either end of the deleted range is in the
+    /// middle of a character, e.g. between the bytes of a multi-byte UTF-8 sequence or between
+    /// the two halves of a surrogate pair, or past the end of the text, and
+    /// [`AutomergeError::InvalidOp`] if `obj` is not text.
+    fn splice_text_with_encoding<O: AsRef<ExId>>(
+        &mut self,
+        obj: O,
+        pos: usize,
+        del: isize,
+        text: &str,
+        encoding: crate::TextEncoding,
+    ) -> Result<(), AutomergeError>;
+
+    /// Insert `text` at `pos` and return the IDs of the inserted characters, in order
+    ///
+    /// The IDs are the element IDs of the characters, which can be used to create cursors or
+    /// find the characters with [`ReadDoc::index_of()`] after further edits. For large inserts
+    /// [`Self::splice_text_with_id_range()`] avoids allocating an ID per character.
+    fn splice_text_with_ids<O: AsRef<ExId>>(
+        &mut self,
+        obj: O,
+        pos: usize,
+        text: &str,
+    ) -> Result<Vec<ExId>, AutomergeError>;
+
+    /// Like [`Self::splice_text_with_ids()`] but only returns the IDs of the first and last
+    /// inserted characters, or [`None`] if `text` is empty
+    fn splice_text_with_id_range<O: AsRef<ExId>>(
+        &mut self,
+        obj: O,
+        pos: usize,
+        text: &str,
+    ) -> Result<Option<(ExId, ExId)>, AutomergeError>;
+
+    /// Insert `text` at `pos` along with marks over ranges of the inserted text
+    ///
+    /// Each mark is a range relative to the start of `text`, the mark name and its value. The
+    /// marks are created with [`ExpandMark::default()`] and empty ranges are ignored. This is the same as calling
+    /// [`Self::splice_text()`] followed by [`Self::mark()`] for each mark, but it only looks up the
+    /// insert position once. A range which extends past the end of `text` returns
+    /// [`AutomergeError::InvalidIndex`].
+    fn splice_text_with_marks<O: AsRef<ExId>>(
+        &mut self,
+        obj: O,
+        pos: usize,
+        text: &str,
+        marks: &[(Range<usize>, &str, ScalarValue)],
+    ) -> Result<(), AutomergeError>;
+
+    /// Insert text copied with [`ReadDoc::export_rich_text()`] at `pos` of the text object
+    /// `obj`, along with its marks
+    ///
+    /// This is [`Self::splice_text_with_marks()`] with the text and mark ranges of `rich`, so the
+    /// marks are created with [`ExpandMark::default()`].
+    fn insert_rich_text<O: AsRef<ExId>>(
+        &mut self,
+        obj: O,
+        pos: usize,
+        rich: &RichText,
+    ) -> Result<(), AutomergeError> {
+        self.splice_text_with_marks(obj, pos, &rich.text(), &rich.mark_ranges())
+    }
+
     /// Mark a sequence
     fn mark<O: AsRef<ExId>>(
         &mut self,
@@ -102,6 +385,18 @@ pub trait Transactable: ReadDoc {
         expand: ExpandMark,
     ) -> Result<(), AutomergeError>;
 
+    /// Like [`Self::mark()`] but returns the range the mark covers in the current state of the
+    /// sequence
+    ///
+    /// The range is calculated from the positions of the anchors the mark was attached to, so a
+    /// zero width mark returns an empty range at the index it was inserted at.
+    fn mark_and_return_range<O: AsRef<ExId>>(
+        &mut self,
+        obj: O,
+        mark: Mark<'_>,
+        expand: ExpandMark,
+    ) -> Result<Range<usize>, AutomergeError>;
+
     /// Remove a Mark from a sequence
     fn unmark<O: AsRef<ExId>>(
         &mut self,
@@ -160,6 +455,413 @@ pub trait Transactable: ReadDoc {
         obj: O,
         new_value: &crate::hydrate::Value,
     ) -> Result<(), crate::error::UpdateObjectError>;
+
+    /// Set the value at `path`, starting from the root of the document
+    ///
+    /// # Errors
+    ///
+    /// This will return [`AutomergeError::InvalidPath`] if `path` is empty or any of the props
+    /// before the last one do not refer to an object, as well as any of the errors from
+    /// [`Self::put()`]
+    fn put_path<V: Into<ScalarValue>>(
+        &mut self,
+        path: &[Prop],
+        value: V,
+    ) -> Result<(), AutomergeError> {
+        let (obj, prop) = resolve_path(self, path)?;
+        self.put(obj, prop, value)
+    }
+
+    /// Increment the counter at `path`, starting from the root of the document
+    ///
+    /// # Errors
+    ///
+    /// This will return [`AutomergeError::InvalidPath`] if `path` is empty or any of the props
+    /// before the last one do not refer to an object, and [`AutomergeError::InvalidOp`] if the
+    /// last one does not refer to a counter
+    fn increment_path(&mut self, path: &[Prop], by: i64) -> Result<(), AutomergeError> {
+        let (obj, prop) = resolve_path(self, path)?;
+        match self.get(&obj, prop.clone())? {
+            Some((Value::Scalar(s), _)) if matches!(s.as_ref(), ScalarValue::Counter(_)) => {
+                self.increment(obj, prop, by)
+            }
+            _ => Err(AutomergeError::InvalidOp(self.object_type(&obj)?)),
+        }
+    }
+
+    /// Delete the value at `path`, starting from the root of the document
+    ///
+    /// # Errors
+    ///
+    /// This will return [`AutomergeError::InvalidPath`] if `path` is empty or any of the props
+    /// before the last one do not refer to an object, as well as any of the errors from
+    /// [`Self::delete()`]
+    fn delete_path(&mut self, path: &[Prop]) -> Result<(), AutomergeError> {
+        let (obj, prop) = resolve_path(self, path)?;
+        self.delete(obj, prop)
+    }
+
+    /// Move the value at `from` in the map `obj` to `to`
+    ///
+    /// Object ids are the ids of the operations which created the objects, so an object can't be
+    /// moved to another key without getting a new id. For object values a new object of the same
+    /// type is created at `to` with a copy of the contents of the old one, including the marks on
+    /// any text in it, for scalar values the value is put at `to`. `from` is then deleted.
+    ///
+    /// The copy is a different object from the old one. Edits made to the old object
+    /// concurrently with the rename, by a peer which hadn't seen it yet, are merged into the old
+    /// object, which is deleted, so they don't appear in the copy and are lost. Ids of the old
+    /// object or of anything in it which are held elsewhere, for example cursors, don't refer to
+    /// the copy. If two peers rename the same key concurrently each makes its own copy and the
+    /// copies conflict at `to`.
+    ///
+    /// ```
+    /// # use automerge::{transaction::Transactable, AutoCommit, ObjType, ReadDoc, ROOT};
+    /// let mut doc = AutoCommit::new();
+    /// let user = doc.put_object(ROOT, "usr", ObjType::Map).unwrap();
+    /// doc.put(&user, "name", "alice").unwrap();
+    /// doc.rename_key(ROOT, "usr", "user").unwrap();
+    /// assert!(doc.get(ROOT, "usr").unwrap().is_none());
+    /// let (_, user) = doc.get(ROOT, "user").unwrap().unwrap();
+    /// assert_eq!(doc.get(&user, "name").unwrap().unwrap().0.to_str(), Some("alice"));
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AutomergeError::MissingKey`] if there is no value at `from` and
+    /// [`AutomergeError::KeyExists`] if there already is one at `to`, as well as
+    /// [`AutomergeError::InvalidOp`] if `obj` is not a map
+    fn rename_key<O: AsRef<ExId>>(
+        &mut self,
+        obj: O,
+        from: &str,
+        to: &str,
+    ) -> Result<(), AutomergeError> {
+        let obj = obj.as_ref();
+        let obj_type = self.object_type(obj)?;
+        if obj_type.is_sequence() {
+            return Err(AutomergeError::InvalidOp(obj_type));
+        }
+        let (value, id) = self
+            .get(obj, from)?
+            .ok_or_else(|| AutomergeError::MissingKey(from.to_string()))?;
+        if self.get(obj, to)?.is_some() {
+            return Err(AutomergeError::KeyExists(to.to_string()));
+        }
+        match value {
+            Value::Object(typ) => {
+                let copy = self.put_object(obj, to, typ)?;
+                copy_contents(self, &id, &copy, typ)?;
+            }
+            Value::Scalar(s) => {
+                let s = s.into_owned();
+                self.put(obj, to, s)?;
+            }
+        }
+        self.delete(obj, from)
+    }
+
+    /// Delete the element at index `from` of the sequence `obj` and insert a copy of it so that
+    /// it ends up at index `to`
+    ///
+    /// This is not a move: the document has no operation which moves an element, this is the
+    /// delete and insert an application would otherwise make itself, in one change. As with
+    /// [`Self::rename_key()`] object elements are copied into a new object of the same type, with
+    /// a new id, including the marks on any text in them. Concurrent calls for the same element
+    /// are not resolved to one position, each leaves its own copy, and edits made concurrently to
+    /// the old element are lost.
+    ///
+    /// ```
+    /// # use automerge::{transaction::Transactable, AutoCommit, ObjType, ReadDoc, ROOT};
+    /// let mut doc = AutoCommit::new();
+    /// let list = doc.put_object(ROOT, "list", ObjType::List).unwrap();
+    /// doc.splice(&list, 0, 0, ["a".into(), "b".into(), "c".into()]).unwrap();
+    /// doc.copy_and_delete_element(&list, 0, 2).unwrap();
+    /// let values = doc
+    ///     .list_range(&list, ..)
+    ///     .map(|item| item.value.to_str().unwrap().to_string())
+    ///     .collect::<Vec<_>>();
+    /// assert_eq!(values, vec!["b", "c", "a"]);
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AutomergeError::InvalidIndex`] if `from` or `to` is not less than the length of
+    /// `obj` and [`AutomergeError::InvalidOp`] if `obj` is not a sequence
+    fn copy_and_delete_element<O: AsRef<ExId>>(
+        &mut self,
+        obj: O,
+        from: usize,
+        to: usize,
+    ) -> Result<(), AutomergeError> {
+        let obj = obj.as_ref();
+        let obj_type = self.object_type(obj)?;
+        if !obj_type.is_sequence() {
+            return Err(AutomergeError::InvalidOp(obj_type));
+        }
+        let len = self.length(obj);
+        if let Some(&index) = [from, to].iter().find(|&&i| i >= len) {
+            return Err(AutomergeError::InvalidIndex(index));
+        }
+        if from == to {
+            return Ok(());
+        }
+        let (value, id) = self
+            .get(obj, from)?
+            .ok_or(AutomergeError::InvalidIndex(from))?;
+        match value {
+            Value::Object(typ) => {
+                self.delete(obj, from)?;
+                let copy = self.insert_object(obj, to, typ)?;
+                copy_contents(self, &id, &copy, typ)
+            }
+            Value::Scalar(s) => {
+                let s = s.into_owned();
+                self.delete(obj, from)?;
+                self.insert(obj, to, s)
+            }
+        }
+    }
+
+    /// Add `delta_ms` to the timestamp at `prop` in `obj` and return the new timestamp
+    ///
+    /// ```
+    /// # use automerge::{transaction::Transactable, AutoCommit, ReadDoc, ScalarValue, ROOT};
+    /// let mut doc = AutoCommit::new();
+    /// doc.put(ROOT, "due", ScalarValue::Timestamp(1_000)).unwrap();
+    /// assert_eq!(doc.bump_timestamp(ROOT, "due", 500).unwrap(), 1_500);
+    /// assert_eq!(doc.get_timestamp(ROOT, "due").unwrap(), Some(1_500));
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AutomergeError::InvalidValueType`] if the value at `prop` is missing or is not a
+    /// timestamp, or if adding `delta_ms` to it overflows
+    fn bump_timestamp<O: AsRef<ExId>, P: Into<Prop>>(
+        &mut self,
+        obj: O,
+        prop: P,
+        delta_ms: i64,
+    ) -> Result<i64, AutomergeError> {
+        let obj = obj.as_ref();
+        let prop = prop.into();
+        let current = match self.get(obj, prop.clone())? {
+            Some((Value::Scalar(s), _)) => match s.as_ref() {
+                ScalarValue::Timestamp(t) => *t,
+                other => {
+                    return Err(AutomergeError::InvalidValueType {
+                        expected: "a timestamp".to_string(),
+                        unexpected: other.to_string(),
+                    })
+                }
+            },
+            other => {
+                return Err(AutomergeError::InvalidValueType {
+                    expected: "a timestamp".to_string(),
+                    unexpected: other.map_or("nothing".to_string(), |(v, _)| v.to_string()),
+                })
+            }
+        };
+        let bumped =
+            current
+                .checked_add(delta_ms)
+                .ok_or_else(|| AutomergeError::InvalidValueType {
+                    expected: "a timestamp".to_string(),
+                    unexpected: format!("{} + {}", current, delta_ms),
+                })?;
+        self.put(obj, prop, ScalarValue::Timestamp(bumped))?;
+        Ok(bumped)
+    }
+
+    /// Make the edits described by `patches`, in order, as local operations
+    ///
+    /// This is meant for replaying patches generated from another document (e.g. by
+    /// [`crate::AutoCommit::diff()`]) against a document in the state the patches start from. The
+    /// object each patch targets is found by following the props in [`Patch::path`] from the root
+    /// of this document, so the object IDs in the patches don't need to exist here. Conflict
+    /// patches are ignored, the winning value of a conflict is set like any other value.
+    ///
+    /// ```
+    /// # use automerge::{transaction::Transactable, AutoCommit, ObjType, ReadDoc, ROOT};
+    /// let mut source = AutoCommit::new();
+    /// let mut target = source.fork();
+    /// let before = source.get_heads();
+    /// let list = source.put_object(ROOT, "list", ObjType::List).unwrap();
+    /// source.insert(&list, 0, "a").unwrap();
+    /// let after = source.get_heads();
+    ///
+    /// let patches = source.diff(&before, &after);
+    /// target.apply_patches(&patches).unwrap();
+    /// assert_eq!(target.hydrate(ROOT, None), source.hydrate(ROOT, None));
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`AutomergeError::UnresolvedPatchPath`] if the path of a patch doesn't lead to an
+    /// object in the current state of the document, as well as any error from the operation the
+    /// patch is translated to. Patches before the failing one will already have been applied.
+    fn apply_patches(&mut self, patches: &[Patch]) -> Result<(), AutomergeError> {
+        for patch in patches {
+            let mut obj = ROOT;
+            for (_, prop) in &patch.path {
+                obj = match self.get(&obj, prop.clone())? {
+                    Some((Value::Object(_), id)) => id,
+                    _ => {
+                        let path = patch.path.iter().map(|(_, p)| p.clone()).collect();
+                        return Err(AutomergeError::UnresolvedPatchPath(path));
+                    }
+                };
+            }
+            apply_patch_action(self, &obj, &patch.action)?;
+        }
+        Ok(())
+    }
+}
+
+fn apply_patch_action<T: Transactable + ?Sized>(
+    doc: &mut T,
+    obj: &ExId,
+    action: &PatchAction,
+) -> Result<(), AutomergeError> {
+    match action {
+        PatchAction::PutMap {
+            key, value: (v, _), ..
+        } => put_value(doc, obj, Prop::Map(key.clone()), v),
+        PatchAction::PutSeq {
+            index,
+            value: (v, _),
+            ..
+        } => put_value(doc, obj, Prop::Seq(*index), v),
+        PatchAction::Insert { index, values } => {
+            for (offset, (v, _, _)) in values.iter().enumerate() {
+                match v {
+                    Value::Object(typ) => {
+                        doc.insert_object(obj, index + offset, *typ)?;
+                    }
+                    Value::Scalar(s) => doc.insert(obj, index + offset, s.as_ref().clone())?,
+                }
+            }
+            Ok(())
+        }
+        PatchAction::SpliceText {
+            index,
+            value,
+            marks,
+        } => {
+            let before = doc.length(obj);
+            doc.splice_text(obj, *index, 0, &value.make_string())?;
+            let end = index + (doc.length(obj) - before);
+            // the inserted text only picks up marks by expanding existing ones, set any which the
+            // patch says it should have but it didn't get that way
+            if let Some(marks) = marks {
+                let current = doc.get_marks(obj, *index, None)?;
+                for (name, value) in marks.iter() {
+                    if current.iter().all(|(n, v)| n != name || v != value) {
+                        let mark = Mark::new(name.to_string(), value.clone(), *index, end);
+                        doc.mark(obj, mark, ExpandMark::default())?;
+                    }
+                }
+            }
+            Ok(())
+        }
+        PatchAction::Increment { prop, value } => doc.increment(obj, prop.clone(), *value),
+        PatchAction::Conflict { .. } => Ok(()),
+        PatchAction::DeleteMap { key, .. } => doc.delete(obj, key.as_str()),
+        PatchAction::DeleteSeq { index, length } => {
+            if doc.object_type(obj)? == ObjType::Text {
+                doc.splice_text(obj, *index, *length as isize, "")
+            } else {
+                doc.splice(obj, *index, *length as isize, [])
+            }
+        }
+        PatchAction::Mark { marks } => {
+            for mark in marks {
+                if mark.value().is_null() {
+                    doc.unmark(
+                        obj,
+                        mark.name(),
+                        mark.start,
+                        mark.end,
+                        ExpandMark::default(),
+                    )?;
+                } else {
+                    doc.mark(obj, mark.clone(), ExpandMark::default())?;
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Fill the new, empty object `copy` with a copy of the contents of `obj`, which is of type `typ`
+///
+/// Nested objects are copied into new objects and text keeps its marks.
+fn copy_contents<T: Transactable + ?Sized>(
+    doc: &mut T,
+    obj: &ExId,
+    copy: &ExId,
+    typ: ObjType,
+) -> Result<(), AutomergeError> {
+    let values = match typ {
+        ObjType::Text => {
+            let rich = doc.export_rich_text(obj, 0..doc.length(obj), None)?;
+            return doc.insert_rich_text(copy, 0, &rich);
+        }
+        ObjType::Map | ObjType::Table => doc
+            .map_range(obj, ..)
+            .map(|item| (Prop::from(item.key), item.value.into_owned(), item.id))
+            .collect::<Vec<_>>(),
+        ObjType::List => doc
+            .list_range(obj, ..)
+            .map(|item| (Prop::from(item.index), item.value.into_owned(), item.id))
+            .collect::<Vec<_>>(),
+    };
+    for (prop, value, id) in values {
+        match value {
+            Value::Object(typ) => {
+                let child = match prop {
+                    Prop::Map(key) => doc.put_object(copy, key, typ)?,
+                    Prop::Seq(index) => doc.insert_object(copy, index, typ)?,
+                };
+                copy_contents(doc, &id, &child, typ)?;
+            }
+            Value::Scalar(s) => match prop {
+                Prop::Map(key) => doc.put(copy, key, s.into_owned())?,
+                Prop::Seq(index) => doc.insert(copy, index, s.into_owned())?,
+            },
+        }
+    }
+    Ok(())
+}
+
+fn put_value<T: Transactable + ?Sized>(
+    doc: &mut T,
+    obj: &ExId,
+    prop: Prop,
+    value: &Value<'_>,
+) -> Result<(), AutomergeError> {
+    match value {
+        Value::Object(typ) => doc.put_object(obj, prop, *typ).map(|_| ()),
+        Value::Scalar(s) => doc.put(obj, prop, s.as_ref().clone()),
+    }
+}
+
+/// Find the object containing the last prop in `path` and return it along with that prop
+fn resolve_path<T: ReadDoc + ?Sized>(
+    doc: &T,
+    path: &[Prop],
+) -> Result<(ExId, Prop), AutomergeError> {
+    let (last, parents) = path
+        .split_last()
+        .ok_or(AutomergeError::InvalidPath { index: 0 })?;
+    let mut obj = ROOT;
+    for (index, prop) in parents.iter().enumerate() {
+        obj = match doc.get(&obj, prop.clone())? {
+            Some((Value::Object(_), id)) => id,
+            _ => return Err(AutomergeError::InvalidPath { index }),
+        };
+    }
+    Ok((obj, last.clone()))
 }
 
 #[derive(Debug, PartialEq, Clone)]
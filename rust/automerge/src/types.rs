@@ -1,6 +1,7 @@
 use crate::error;
 use crate::legacy as amp;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::cmp::Eq;
 use std::cmp::Ordering;
 use std::fmt;
@@ -50,6 +51,16 @@ impl ActorId {
         ActorId(TinyVec::from(*uuid::Uuid::new_v4().as_bytes()))
     }
 
+    /// A 16 byte actor ID derived from `seed`, the same seed always gives the same ID
+    ///
+    /// This is intended for tests which need documents to be byte for byte reproducible. Actor
+    /// IDs must be unique to each writer so don't use this with a seed which other writers might
+    /// also use.
+    pub fn from_seed(seed: &[u8]) -> ActorId {
+        let hash = Sha256::digest(seed);
+        ActorId(TinyVec::from(&hash[..16]))
+    }
+
     pub fn to_bytes(&self) -> &[u8] {
         &self.0
     }
@@ -597,6 +608,34 @@ pub(crate) enum ListEncoding {
     #[default]
     List,
     Text,
+    /// Text measured in UTF-16 code units, whatever the native text encoding is
+    Utf16,
+    /// Text measured in UTF-8 code units, whatever the native text encoding is
+    Utf8,
+    /// Text measured in unicode code points, whatever the native text encoding is
+    CodePoints,
+}
+
+/// A unit to measure positions in text with, see
+/// [`crate::transaction::Transactable::splice_text_with_encoding()`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum TextEncoding {
+    /// Bytes of the UTF-8 encoding of the text
+    Utf8CodeUnit,
+    /// Unicode code points, i.e. [`char`]s
+    UnicodeCodePoint,
+    /// 16 bit code units of the UTF-16 encoding of the text, as used by JavaScript strings
+    Utf16CodeUnit,
+}
+
+impl From<TextEncoding> for ListEncoding {
+    fn from(encoding: TextEncoding) -> Self {
+        match encoding {
+            TextEncoding::Utf8CodeUnit => ListEncoding::Utf8,
+            TextEncoding::UnicodeCodePoint => ListEncoding::CodePoints,
+            TextEncoding::Utf16CodeUnit => ListEncoding::Utf16,
+        }
+    }
 }
 
 impl From<Option<ObjType>> for ListEncoding {
@@ -730,6 +769,7 @@ pub(crate) mod gen {
             any::<i64>().prop_map(ScalarValue::Int),
             any::<u64>().prop_map(ScalarValue::Uint),
             any::<f64>().prop_map(ScalarValue::F64),
+            any::<f32>().prop_map(ScalarValue::F32),
             any::<i64>().prop_map(|c| ScalarValue::Counter(Counter::from(c))),
             any::<i64>().prop_map(ScalarValue::Timestamp),
             any::<bool>().prop_map(ScalarValue::Boolean),
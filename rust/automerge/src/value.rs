@@ -1,4 +1,5 @@
 use crate::error;
+use crate::exid::ExId;
 use crate::types::ObjType;
 use serde::{Deserialize, Serialize, Serializer};
 use smol_str::SmolStr;
@@ -57,6 +58,10 @@ impl<'a> Value<'a> {
         Value::Scalar(Cow::Owned(ScalarValue::F64(n)))
     }
 
+    pub fn f32(n: f32) -> Value<'a> {
+        Value::Scalar(Cow::Owned(ScalarValue::F32(n)))
+    }
+
     pub fn bytes(b: Vec<u8>) -> Value<'a> {
         Value::Scalar(Cow::Owned(ScalarValue::Bytes(b)))
     }
@@ -109,6 +114,14 @@ impl<'a> Value<'a> {
         }
     }
 
+    pub fn is_f32(&self) -> bool {
+        if let Self::Scalar(s) = self {
+            s.is_f32()
+        } else {
+            false
+        }
+    }
+
     pub fn is_counter(&self) -> bool {
         if let Self::Scalar(s) = self {
             s.is_counter()
@@ -234,6 +247,14 @@ impl<'a> Value<'a> {
         }
     }
 
+    /// If this value is an f32, return it
+    pub fn to_f32(&self) -> Option<f32> {
+        match self {
+            Value::Scalar(s) => s.to_f32(),
+            _ => None,
+        }
+    }
+
     pub fn to_bool(&self) -> Option<bool> {
         match self {
             Value::Scalar(s) => s.to_bool(),
@@ -242,6 +263,29 @@ impl<'a> Value<'a> {
     }
 }
 
+/// A value yielded by [`crate::ReadDoc::values_ref()`]
+///
+/// Unlike the `(Value, ExId)` pairs yielded by [`crate::ReadDoc::values()`] only objects carry an
+/// id, so scanning a sequence of scalars doesn't build an [`ExId`] for every element.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValueRef<'a> {
+    /// A composite object of type [`ObjType`] and its id
+    Object(ObjType, ExId),
+    /// A non composite value, this is only owned if it had to be computed, for example the value
+    /// of a counter at some point in the past
+    Scalar(Cow<'a, ScalarValue>),
+}
+
+impl<'a> ValueRef<'a> {
+    /// The equivalent [`Value`]
+    pub fn to_value(&self) -> Value<'a> {
+        match self {
+            ValueRef::Object(typ, _) => Value::Object(*typ),
+            ValueRef::Scalar(s) => Value::Scalar(s.clone()),
+        }
+    }
+}
+
 impl<'a> fmt::Display for Value<'a> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -293,6 +337,12 @@ impl<'a> From<f64> for Value<'a> {
     }
 }
 
+impl<'a> From<f32> for Value<'a> {
+    fn from(n: f32) -> Self {
+        Value::Scalar(Cow::Owned(ScalarValue::F32(n)))
+    }
+}
+
 impl<'a> From<i64> for Value<'a> {
     fn from(n: i64) -> Self {
         Value::Scalar(Cow::Owned(ScalarValue::Int(n)))
@@ -361,6 +411,8 @@ pub(crate) enum DataType {
     Int,
     #[serde(rename = "float64")]
     F64,
+    #[serde(rename = "float32")]
+    F32,
     #[serde(rename = "undefined")]
     Undefined,
 }
@@ -443,6 +495,8 @@ pub enum ScalarValue {
     Int(i64),
     Uint(u64),
     F64(f64),
+    /// A 32 bit float, stored with 4 bytes rather than the 8 of [`ScalarValue::F64`]
+    F32(f32),
     Counter(Counter),
     Timestamp(i64),
     Boolean(bool),
@@ -529,6 +583,14 @@ impl ScalarValue {
                     datatype,
                 },
             )?)),
+            (DataType::F32, v) => Ok(ScalarValue::F32(v.to_f64().ok_or(
+                error::InvalidScalarValue {
+                    raw_value: self.clone(),
+                    expected: "an f32".to_string(),
+                    unexpected: v.to_string(),
+                    datatype,
+                },
+            )? as f32)),
             (DataType::Undefined, _) => Ok(self.clone()),
         }
     }
@@ -544,6 +606,7 @@ impl ScalarValue {
             ScalarValue::Int(..) => Some(DataType::Int),
             ScalarValue::Uint(..) => Some(DataType::Uint),
             ScalarValue::F64(..) => Some(DataType::F64),
+            ScalarValue::F32(..) => Some(DataType::F32),
             _ => None,
         }
     }
@@ -568,6 +631,10 @@ impl ScalarValue {
         matches!(self, Self::F64(_))
     }
 
+    pub fn is_f32(&self) -> bool {
+        matches!(self, Self::F32(_))
+    }
+
     pub fn is_counter(&self) -> bool {
         matches!(self, Self::Counter(_))
     }
@@ -618,6 +685,7 @@ impl ScalarValue {
             ScalarValue::Int(n) => Some(*n),
             ScalarValue::Uint(n) => Some(*n as i64),
             ScalarValue::F64(n) => Some(*n as i64),
+            ScalarValue::F32(n) => Some(*n as i64),
             ScalarValue::Counter(n) => Some(n.into()),
             ScalarValue::Timestamp(n) => Some(*n),
             _ => None,
@@ -629,6 +697,7 @@ impl ScalarValue {
             ScalarValue::Int(n) => Some(*n as u64),
             ScalarValue::Uint(n) => Some(*n),
             ScalarValue::F64(n) => Some(*n as u64),
+            ScalarValue::F32(n) => Some(*n as u64),
             ScalarValue::Counter(n) => Some(n.into()),
             ScalarValue::Timestamp(n) => Some(*n as u64),
             _ => None,
@@ -640,12 +709,24 @@ impl ScalarValue {
             ScalarValue::Int(n) => Some(*n as f64),
             ScalarValue::Uint(n) => Some(*n as f64),
             ScalarValue::F64(n) => Some(*n),
+            ScalarValue::F32(n) => Some(f64::from(*n)),
             ScalarValue::Counter(n) => Some(n.into()),
             ScalarValue::Timestamp(n) => Some(*n as f64),
             _ => None,
         }
     }
 
+    /// If this value is an f32, return it
+    ///
+    /// Unlike [`Self::to_f64()`] this doesn't coerce other numeric values, which could lose
+    /// precision.
+    pub fn to_f32(&self) -> Option<f32> {
+        match self {
+            ScalarValue::F32(n) => Some(*n),
+            _ => None,
+        }
+    }
+
     pub fn to_bool(&self) -> Option<bool> {
         match self {
             ScalarValue::Boolean(b) => Some(*b),
@@ -653,6 +734,16 @@ impl ScalarValue {
         }
     }
 
+    /// If this value is a timestamp, return the milliseconds since the epoch
+    ///
+    /// Unlike [`Self::to_i64()`] this doesn't coerce other numeric values.
+    pub fn to_timestamp(&self) -> Option<i64> {
+        match self {
+            ScalarValue::Timestamp(n) => Some(*n),
+            _ => None,
+        }
+    }
+
     pub fn counter(n: i64) -> ScalarValue {
         ScalarValue::Counter(n.into())
     }
@@ -694,6 +785,12 @@ impl From<f64> for ScalarValue {
     }
 }
 
+impl From<f32> for ScalarValue {
+    fn from(n: f32) -> Self {
+        ScalarValue::F32(n)
+    }
+}
+
 impl From<u64> for ScalarValue {
     fn from(n: u64) -> Self {
         ScalarValue::Uint(n)
@@ -738,6 +835,7 @@ impl fmt::Display for ScalarValue {
             ScalarValue::Int(i) => write!(f, "{}", i),
             ScalarValue::Uint(i) => write!(f, "{}", i),
             ScalarValue::F64(n) => write!(f, "{:.324}", n),
+            ScalarValue::F32(n) => write!(f, "{:.324}", n),
             ScalarValue::Counter(c) => write!(f, "Counter: {}", c),
             ScalarValue::Timestamp(i) => write!(f, "Timestamp: {}", i),
             ScalarValue::Boolean(b) => write!(f, "{}", b),
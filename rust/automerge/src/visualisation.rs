@@ -240,6 +240,7 @@ impl OpTableRow {
         let op_description = match &op.action() {
             crate::OpType::Delete => "del".to_string(),
             crate::OpType::Put(crate::ScalarValue::F64(v)) => format!("set {:.2}", v),
+            crate::OpType::Put(crate::ScalarValue::F32(v)) => format!("set {:.2}", v),
             crate::OpType::Put(v) => format!("set {}", v),
             crate::OpType::Make(obj) => format!("make {}", obj),
             crate::OpType::Increment(v) => format!("inc {}", v),
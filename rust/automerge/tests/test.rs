@@ -1611,6 +1611,7 @@ fn regression_insert_opid() {
                 ObjId::Id(1, doc.get_actor().clone(), 0),
             ),
             conflict: false,
+            old: None,
         },
     });
     for i in 0..=N {
@@ -1638,6 +1639,7 @@ fn regression_insert_opid() {
                     ObjId::Id((2 * (i + 1) + 1) as u64, doc.get_actor().clone(), 0),
                 ),
                 conflict: false,
+                old: None,
             },
         });
     }
@@ -1904,6 +1906,132 @@ fn get_marks_at_heads() {
     assert_eq!(mark_value, &ScalarValue::Boolean(true));
 }
 
+#[test]
+fn get_marks_after_splice_uses_cached_marks() {
+    for expand in [
+        ExpandMark::None,
+        ExpandMark::Before,
+        ExpandMark::After,
+        ExpandMark::Both,
+    ] {
+        let mut doc = AutoCommit::new();
+        let text = doc.put_object(&ROOT, "text", ObjType::Text).unwrap();
+        doc.splice_text(&text, 0, 0, "hello world").unwrap();
+        doc.mark(&text, Mark::new("bold".to_string(), true, 2, 5), expand)
+            .unwrap();
+        doc.mark(&text, Mark::new("italic".to_string(), true, 0, 11), expand)
+            .unwrap();
+
+        for (index, insert) in [(5, "a"), (3, "bb"), (2, "c"), (0, "d"), (16, "e")] {
+            doc.splice_text(&text, index, 0, insert).unwrap();
+            let last = index + insert.len() - 1;
+            let cached = doc.get_marks(&text, last, None).unwrap();
+            let reloaded = AutoCommit::load(&doc.save()).unwrap();
+            let expected = reloaded.get_marks(&text, last, None).unwrap();
+            assert_eq!(cached, expected);
+        }
+    }
+}
+
+#[test]
+fn text_at_cursor_returns_marks_at_cursor() {
+    let mut doc = AutoCommit::new();
+    let text = doc.put_object(&ROOT, "text", ObjType::Text).unwrap();
+    doc.splice_text(&text, 0, 0, "hello world").unwrap();
+    doc.mark(
+        &text,
+        Mark::new("bold".to_string(), true, 6, 11),
+        ExpandMark::After,
+    )
+    .unwrap();
+    let heads = doc.get_heads();
+
+    let plain = doc.get_cursor(&text, 2, None).unwrap();
+    let bold = doc.get_cursor(&text, 7, None).unwrap();
+    let last = doc.get_cursor(&text, 10, None).unwrap();
+    assert!(doc.text_at_cursor(&text, &plain, None).unwrap().is_empty());
+    let marks = doc.text_at_cursor(&text, &bold, None).unwrap();
+    assert_eq!(
+        marks.iter().collect::<Vec<_>>(),
+        vec![("bold", &ScalarValue::Boolean(true))]
+    );
+
+    // once the last character is deleted the cursor is past the end so we get the marks a new
+    // character would be inserted with
+    doc.splice_text(&text, 10, 1, "").unwrap();
+    let marks = doc.text_at_cursor(&text, &last, None).unwrap();
+    assert_eq!(
+        marks.iter().collect::<Vec<_>>(),
+        vec![("bold", &ScalarValue::Boolean(true))]
+    );
+    let marks = doc.text_at_cursor(&text, &last, Some(&heads)).unwrap();
+    assert_eq!(
+        marks.iter().collect::<Vec<_>>(),
+        vec![("bold", &ScalarValue::Boolean(true))]
+    );
+
+    doc.splice_text(&text, 0, 10, "").unwrap();
+    assert!(doc.text_at_cursor(&text, &plain, None).unwrap().is_empty());
+}
+
+#[test]
+fn splice_text_with_marks_matches_splice_then_mark() {
+    let mut doc1 = AutoCommit::new();
+    let text = doc1.put_object(&ROOT, "text", ObjType::Text).unwrap();
+    doc1.splice_text(&text, 0, 0, "<>").unwrap();
+    doc1.commit();
+    let mut doc2 = doc1.fork();
+
+    let inserted = "hello world";
+    let marks = [
+        (0..5, "bold", ScalarValue::from(true)),
+        (5..11, "italic", ScalarValue::from(true)),
+        (0..11, "link", ScalarValue::from("x")),
+    ];
+    // empty ranges don't mark anything
+    let mut with_empty = marks.to_vec();
+    with_empty.push((3..3, "empty", ScalarValue::from(true)));
+    doc1.splice_text_with_marks(&text, 1, inserted, &with_empty)
+        .unwrap();
+    doc2.splice_text(&text, 1, 0, inserted).unwrap();
+    for (range, name, value) in &marks {
+        let mark = Mark::new(
+            name.to_string(),
+            value.clone(),
+            range.start + 1,
+            range.end + 1,
+        );
+        doc2.mark(&text, mark, ExpandMark::default()).unwrap();
+    }
+
+    assert_eq!(doc1.text(&text).unwrap(), "<hello world>");
+    assert_eq!(doc1.marks(&text).unwrap(), doc2.marks(&text).unwrap());
+    let loaded = AutoCommit::load(&doc1.save()).unwrap();
+    assert_eq!(loaded.marks(&text).unwrap(), doc2.marks(&text).unwrap());
+    let mut merged = AutoCommit::new();
+    merged.merge(&mut doc1).unwrap();
+    assert_eq!(merged.marks(&text).unwrap(), doc2.marks(&text).unwrap());
+
+    // the inserted characters get the marks of the surrounding text
+    doc1.mark(
+        &text,
+        Mark::new("underline".to_string(), true, 0, 13),
+        ExpandMark::Both,
+    )
+    .unwrap();
+    doc1.splice_text_with_marks(&text, 6, "!", &[]).unwrap();
+    assert_eq!(
+        doc1.get_marks(&text, 6, None).unwrap(),
+        doc1.get_marks(&text, 5, None).unwrap()
+    );
+
+    assert_eq!(
+        doc1.splice_text_with_marks(&text, 0, "ab", &[(1..3, "bold", true.into())]),
+        Err(AutomergeError::InvalidIndex(3))
+    );
+    assert_eq!(doc1.text(&text).unwrap(), "<hello! world>");
+}
+
 /*
 #[test]
 fn conflicting_unicode_text_with_different_widths() -> Result<(), AutomergeError> {
@@ -2014,6 +2142,975 @@ fn save_with_empty_commits() {
     let _ = Automerge::load(&saved).unwrap();
 }
 
+#[test]
+fn save_to_writer_matches_save() {
+    let mut doc = Automerge::new();
+    doc.transact::<_, _, AutomergeError>(|tx| {
+        let text = tx.put_object(ROOT, "text", ObjType::Text)?;
+        tx.splice_text(&text, 0, 0, &"hello ".repeat(100))?;
+        tx.put(ROOT, "a", 1)?;
+        Ok(())
+    })
+    .unwrap();
+    let heads = doc.get_heads();
+    doc.transact::<_, _, AutomergeError>(|tx| tx.put(ROOT, "b", 2).map(|_| ()))
+        .unwrap();
+
+    let mut written = Vec::new();
+    doc.save_to_writer(&mut written).unwrap();
+    assert_eq!(written, doc.save());
+
+    // orphaned changes are written too
+    let mut orphaned = Automerge::new();
+    orphaned.load_incremental(&doc.save_after(&heads)).unwrap();
+    let mut written = Vec::new();
+    orphaned.save_to_writer(&mut written).unwrap();
+    assert_eq!(written, orphaned.save());
+
+    struct FailingWriter;
+    impl std::io::Write for FailingWriter {
+        fn write(&mut self, _buf: &[u8]) -> std::io::Result<usize> {
+            Err(std::io::Error::new(std::io::ErrorKind::Other, "disk full"))
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+    let result = doc.save_to_writer(FailingWriter);
+    assert!(matches!(result, Err(AutomergeError::Io(_))));
+}
+
+#[test]
+fn save_to_writer_matches_save_for_large_documents() {
+    // documents with more than 30000 ops are encoded row by row by `save()`
+    let mut doc = AutoCommit::new();
+    let list = doc.put_object(ROOT, "list", ObjType::List).unwrap();
+    for i in 0..15_000 {
+        doc.insert(&list, i, i as i64).unwrap();
+    }
+    let text = doc.put_object(ROOT, "text", ObjType::Text).unwrap();
+    doc.splice_text(&text, 0, 0, &"some text ".repeat(1_600))
+        .unwrap();
+    doc.mark(
+        &text,
+        Mark::new("bold".to_string(), true, 0, 100),
+        ExpandMark::Both,
+    )
+    .unwrap();
+    doc.put(ROOT, "counter", ScalarValue::counter(1)).unwrap();
+    doc.increment(ROOT, "counter", 2).unwrap();
+    doc.commit_with(CommitOptions::default().with_message("big".to_string()));
+    doc.delete(&list, 10).unwrap();
+
+    let mut written = Vec::new();
+    doc.document().save_to_writer(&mut written).unwrap();
+    assert_eq!(written, doc.save());
+}
+
+#[test]
+fn load_from_reader_matches_load() {
+    let mut doc = Automerge::new();
+    doc.transact::<_, _, AutomergeError>(|tx| {
+        let list = tx.put_object(ROOT, "list", ObjType::List)?;
+        for i in 0..100 {
+            tx.insert(&list, i, i as i64)?;
+        }
+        Ok(())
+    })
+    .unwrap();
+    let heads = doc.get_heads();
+    doc.transact::<_, _, AutomergeError>(|tx| tx.put(ROOT, "b", 2).map(|_| ()))
+        .unwrap();
+
+    // a document chunk followed by change chunks
+    let mut saved = Automerge::load(&doc.save())
+        .unwrap()
+        .fork_at(&heads)
+        .unwrap()
+        .save();
+    saved.extend(doc.save_after(&heads));
+    for data in [doc.save(), saved, doc.save_after(&[]), vec![]] {
+        let loaded = Automerge::load_from_reader(data.as_slice()).unwrap();
+        let expected = Automerge::load(&data).unwrap();
+        assert_eq!(loaded.get_heads(), expected.get_heads());
+        assert_eq!(loaded.save(), expected.save());
+    }
+
+    // a stream which ends part way through a chunk
+    let data = doc.save_after(&[]);
+    for len in [1, 10, data.len() - 1] {
+        let result = Automerge::load_from_reader(&data[..len]);
+        assert!(matches!(result, Err(AutomergeError::Load(_))));
+    }
+
+    // a corrupted chunk
+    let mut data = doc.save();
+    let last = data.len() - 1;
+    data[last] ^= 0xff;
+    assert!(Automerge::load_from_reader(data.as_slice()).is_err());
+
+    // changes without their dependencies
+    let result = Automerge::load_from_reader(doc.save_after(&heads).as_slice());
+    assert!(matches!(result, Err(AutomergeError::MissingDeps)));
+}
+
+#[test]
+fn changes_by_actor_in_seq_order() {
+    let actor1 = ActorId::from("aaaa".as_bytes());
+    let actor2 = ActorId::from("bbbb".as_bytes());
+    let mut doc1 = AutoCommit::new().with_actor(actor1.clone());
+    let mut doc2 = AutoCommit::new().with_actor(actor2.clone());
+    for i in 0..3 {
+        doc1.put(ROOT, "a", i).unwrap();
+        doc1.commit();
+        doc2.put(ROOT, "b", i).unwrap();
+        doc2.commit();
+        doc1.merge(&mut doc2).unwrap();
+    }
+
+    let doc = doc1.document();
+    for actor in [&actor1, &actor2] {
+        let changes = doc.changes_by_actor(actor).collect::<Vec<_>>();
+        assert_eq!(
+            changes.iter().map(|c| c.seq()).collect::<Vec<_>>(),
+            [1, 2, 3]
+        );
+        assert!(changes.iter().all(|c| c.actor_id() == actor));
+    }
+    let unknown = ActorId::from("cccc".as_bytes());
+    assert_eq!(doc.changes_by_actor(&unknown).count(), 0);
+}
+
+#[test]
+fn patch_log_object_filter() {
+    let mut doc1 = AutoCommit::new();
+    let section = doc1.put_object(ROOT, "section", ObjType::Map).unwrap();
+    let other = doc1.put_object(ROOT, "other", ObjType::Map).unwrap();
+    doc1.commit();
+    let mut doc2 = doc1.fork();
+    let list = doc2.put_object(&section, "list", ObjType::List).unwrap();
+    doc2.insert(&list, 0, "a").unwrap();
+    doc2.put(&other, "b", 1).unwrap();
+    doc2.put(ROOT, "c", 2).unwrap();
+    doc2.commit();
+
+    let changes = doc2
+        .get_changes(&doc1.get_heads())
+        .into_iter()
+        .cloned()
+        .collect::<Vec<_>>();
+    let mut doc = doc1.document().clone();
+    let mut patch_log =
+        PatchLog::active(TextRepresentation::String).with_object_filter(vec![section.clone()]);
+    doc.apply_changes_log_patches(changes, &mut patch_log)
+        .unwrap();
+    let patches = doc.make_patches(&mut patch_log);
+
+    assert_eq!(patches.len(), 2);
+    assert_eq!(patches[0].obj, section);
+    assert_eq!(patches[1].obj, list);
+    assert_eq!(patches[1].path[0].0, ROOT);
+    assert_eq!(patches[1].path[1].0, section);
+    // the filtered changes are still applied
+    assert!(doc.get(&other, "b").unwrap().is_some());
+}
+
+#[test]
+fn remote_text_edits_are_coalesced_into_single_patches() {
+    let mut doc1 = AutoCommit::new();
+    let text = doc1.put_object(ROOT, "text", ObjType::Text).unwrap();
+    doc1.splice_text(&text, 0, 0, "hello world").unwrap();
+    doc1.commit();
+    let mut doc2 = doc1.fork();
+
+    // a large paste followed by a run of single character backspaces
+    doc2.splice_text(&text, 5, 0, &"x".repeat(5000)).unwrap();
+    doc2.commit();
+    for i in (0..5).rev() {
+        doc2.delete(&text, i).unwrap();
+        doc2.commit();
+    }
+
+    let changes = doc2
+        .get_changes(&doc1.get_heads())
+        .into_iter()
+        .cloned()
+        .collect::<Vec<_>>();
+    let mut patch_log = PatchLog::active(TextRepresentation::String);
+    let mut doc = doc1.document().clone();
+    doc.apply_changes_log_patches(changes, &mut patch_log)
+        .unwrap();
+    let patches = doc
+        .make_patches(&mut patch_log)
+        .into_iter()
+        .map(|p| p.action)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        patches,
+        vec![
+            PatchAction::SpliceText {
+                index: 5,
+                value: "x".repeat(5000).as_str().into(),
+                marks: None,
+            },
+            PatchAction::DeleteSeq {
+                index: 0,
+                length: 5,
+            },
+        ]
+    );
+}
+
+#[test]
+fn try_diff_between_unrelated_heads() {
+    let mut doc = AutoCommit::new();
+    doc.put(ROOT, "x", 1).unwrap();
+    doc.commit();
+    let mut branch_a = doc.fork();
+    branch_a.put(ROOT, "a", 1).unwrap();
+    branch_a.put(ROOT, "x", 2).unwrap();
+    branch_a.commit();
+    let mut branch_b = doc.fork();
+    branch_b.put(ROOT, "b", 2).unwrap();
+    branch_b.delete(ROOT, "x").unwrap();
+    branch_b.commit();
+    let (heads_a, heads_b) = (branch_a.get_heads(), branch_b.get_heads());
+    doc.merge(&mut branch_a).unwrap();
+    doc.merge(&mut branch_b).unwrap();
+
+    let mut patches = doc
+        .document()
+        .try_diff(&heads_a, &heads_b, TextRepresentation::String)
+        .unwrap()
+        .into_iter()
+        .map(|p| p.action)
+        .collect::<Vec<_>>();
+    patches.sort_by_key(|p| format!("{:?}", p));
+    let b = doc.get_at(ROOT, "b", &heads_b).unwrap().unwrap().1;
+    assert_eq!(
+        patches,
+        vec![
+            PatchAction::DeleteMap {
+                key: "a".into(),
+                old: None
+            },
+            PatchAction::DeleteMap {
+                key: "x".into(),
+                old: None
+            },
+            PatchAction::PutMap {
+                key: "b".into(),
+                value: (2.into(), b),
+                conflict: false,
+                old: None,
+            },
+        ]
+    );
+
+    let missing = automerge::ChangeHash([0; 32]);
+    assert_eq!(
+        doc.document()
+            .try_diff(&heads_a, &[missing], TextRepresentation::String),
+        Err(AutomergeError::MissingHash(missing))
+    );
+}
+
+#[test]
+fn apply_changes_checked_rejects_batches_with_missing_deps() {
+    let mut doc = AutoCommit::new();
+    for i in 0..4 {
+        doc.put(ROOT, "key", i).unwrap();
+        doc.commit();
+    }
+    let changes = doc
+        .get_changes(&[])
+        .into_iter()
+        .cloned()
+        .collect::<Vec<_>>();
+
+    // the batch is missing the second change, which the third change depends on
+    let mut other = Automerge::new();
+    let batch = vec![changes[3].clone(), changes[0].clone(), changes[2].clone()];
+    let (index, err) = other.apply_changes_checked(batch).unwrap_err();
+    assert_eq!(index, 2);
+    assert!(matches!(err, AutomergeError::MissingDeps));
+    assert!(other.get_heads().is_empty());
+    assert!(other.get_missing_deps(&[]).is_empty());
+
+    let (index, err) = other
+        .apply_changes_checked(vec![changes[0].clone(), changes[2].clone()])
+        .unwrap_err();
+    assert_eq!(index, 1);
+    assert!(matches!(err, AutomergeError::MissingDeps));
+    assert!(other.get_heads().is_empty());
+
+    // a complete batch applies in any order
+    let mut reversed = changes.clone();
+    reversed.reverse();
+    other.apply_changes_checked(reversed).unwrap();
+    assert_eq!(other.get_heads(), doc.get_heads());
+    other.apply_changes_checked(changes).unwrap();
+    assert_eq!(other.get_heads(), doc.get_heads());
+}
+
+#[test]
+fn apply_changes_checked_applies_nothing_if_a_change_fails() {
+    let mut doc = AutoCommit::new();
+    let map = doc.put_object(ROOT, "map", ObjType::Map).unwrap();
+    doc.commit();
+    doc.put(&map, "key", "value").unwrap();
+    doc.commit();
+    // a change which writes into `map` without depending on the change which created it
+    let mut expanded = doc.get_last_local_change().unwrap().decode();
+    expanded.actor_id = ActorId::random();
+    expanded.seq = 1;
+    expanded.deps.clear();
+    expanded.hash = None;
+    let detached = Change::from(expanded);
+
+    let mut source = AutoCommit::new();
+    for i in 0..4 {
+        source.put(ROOT, "count", i).unwrap();
+        source.commit();
+    }
+    let mut batch = source
+        .get_changes(&[])
+        .into_iter()
+        .cloned()
+        .collect::<Vec<_>>();
+    batch.insert(2, detached);
+
+    let mut other = Automerge::new();
+    other
+        .transact::<_, _, AutomergeError>(|tx| tx.put(ROOT, "mine", 1))
+        .unwrap();
+    let heads = other.get_heads();
+    let (index, err) = other.apply_changes_checked(batch.clone()).unwrap_err();
+    assert_eq!(index, 2);
+    assert!(matches!(err, AutomergeError::MissingObject { obj, .. } if obj == map));
+    assert_eq!(other.get_heads(), heads);
+    assert_eq!(other.get_changes(&[]).len(), 1);
+    assert_eq!(other.get(ROOT, "count").unwrap(), None);
+
+    batch.remove(2);
+    other.apply_changes_checked(batch).unwrap();
+    assert_eq!(other.get(ROOT, "count").unwrap().unwrap().0, Value::int(3));
+
+    // objects made by an earlier change in the batch exist for the changes after it
+    let mut other = Automerge::new();
+    let batch = doc
+        .get_changes(&[])
+        .into_iter()
+        .rev()
+        .cloned()
+        .collect::<Vec<_>>();
+    other.apply_changes_checked(batch).unwrap();
+    assert_eq!(
+        other.get(&map, "key").unwrap().unwrap().0,
+        Value::str("value")
+    );
+}
+
+#[test]
+fn fork_at_restores_old_version() {
+    let mut doc = AutoCommit::new();
+    doc.put(ROOT, "title", "first").unwrap();
+    let list = doc.put_object(ROOT, "list", ObjType::List).unwrap();
+    doc.insert(&list, 0, 1).unwrap();
+    doc.commit();
+    let mut other = doc.fork();
+    other.insert(&list, 1, 2).unwrap();
+    doc.put(ROOT, "title", "second").unwrap();
+    doc.merge(&mut other).unwrap();
+    let old_heads = doc.get_heads();
+    doc.put(ROOT, "title", "third").unwrap();
+    doc.delete(&list, 0).unwrap();
+    doc.commit();
+
+    let original = doc.document();
+    let mut fork = original.fork_at(&old_heads).unwrap();
+    assert_eq!(fork.get_heads(), old_heads);
+    assert_ne!(fork.get_actor(), original.get_actor());
+    assert_eq!(
+        fork.get(ROOT, "title").unwrap().unwrap().0,
+        Value::from("second")
+    );
+    assert_eq!(fork.length(&list), 2);
+
+    // editing the fork leaves the original alone
+    fork.transact::<_, _, AutomergeError>(|tx| tx.put(ROOT, "title", "restored"))
+        .unwrap();
+    assert_eq!(
+        original.get(ROOT, "title").unwrap().unwrap().0,
+        Value::from("third")
+    );
+
+    let mut unrelated = AutoCommit::new();
+    unrelated.put(ROOT, "key", "value").unwrap();
+    let unknown = unrelated.commit().unwrap();
+    assert!(matches!(
+        original.fork_at(&[unknown]),
+        Err(AutomergeError::InvalidHash(h)) if h == unknown
+    ));
+}
+
+#[test]
+fn get_heads_at_change_tracks_concurrent_heads() {
+    let mut doc = AutoCommit::new();
+    doc.put(ROOT, "a", 1).unwrap();
+    let first = doc.commit().unwrap();
+    let mut other = doc.fork();
+    doc.put(ROOT, "b", 2).unwrap();
+    let second = doc.commit().unwrap();
+    other.put(ROOT, "c", 3).unwrap();
+    let concurrent = other.commit().unwrap();
+    doc.merge(&mut other).unwrap();
+    doc.put(ROOT, "d", 4).unwrap();
+    let last = doc.commit().unwrap();
+
+    let doc = doc.document();
+    assert_eq!(doc.get_heads_at_change(&first).unwrap(), vec![first]);
+    assert_eq!(doc.get_heads_at_change(&second).unwrap(), vec![second]);
+    let mut both = vec![second, concurrent];
+    both.sort();
+    assert_eq!(doc.get_heads_at_change(&concurrent).unwrap(), both);
+    assert_eq!(doc.get_heads_at_change(&last).unwrap(), vec![last]);
+
+    let mut unrelated = AutoCommit::new();
+    unrelated.put(ROOT, "key", "value").unwrap();
+    let unknown = unrelated.commit().unwrap();
+    assert!(matches!(
+        doc.get_heads_at_change(&unknown),
+        Err(AutomergeError::MissingHash(h)) if h == unknown
+    ));
+}
+
+#[test]
+fn merge_and_log_patches_reports_touched_paths() {
+    let mut doc = Automerge::new();
+    let mut tx = doc.transaction();
+    let todos = tx.put_object(ROOT, "todos", ObjType::List).unwrap();
+    let settings = tx.put_object(ROOT, "settings", ObjType::Map).unwrap();
+    tx.put(&settings, "theme", "dark").unwrap();
+    tx.commit();
+
+    let mut other = doc.fork();
+    let mut tx = other.transaction();
+    let todo = tx.insert_object(&todos, 0, ObjType::Map).unwrap();
+    tx.put(&todo, "title", "write tests").unwrap();
+    tx.commit();
+    let missing = other
+        .get_changes(&doc.get_heads())
+        .into_iter()
+        .cloned()
+        .collect::<Vec<_>>();
+
+    let mut applied = doc.clone();
+    let mut apply_log = PatchLog::active(TextRepresentation::default());
+    applied
+        .apply_changes_log_patches(missing, &mut apply_log)
+        .unwrap();
+
+    let mut merge_log = PatchLog::active(TextRepresentation::default());
+    let heads = doc
+        .merge_and_log_patches(&mut other, &mut merge_log)
+        .unwrap();
+    assert_eq!(heads, other.get_heads());
+
+    let patches = doc.make_patches(&mut merge_log);
+    assert_eq!(patches, applied.make_patches(&mut apply_log));
+    assert!(!patches.is_empty());
+    // nothing under "settings" changed
+    assert!(patches
+        .iter()
+        .all(|p| p.obj != settings && p.path.iter().all(|(obj, _)| obj != &settings)));
+    assert!(patches.iter().any(|p| p.obj == todos));
+}
+
+#[test]
+fn actor_ids_from_seed_give_reproducible_documents() {
+    assert_eq!(ActorId::from_seed(b"alice"), ActorId::from_seed(b"alice"));
+    assert_ne!(ActorId::from_seed(b"alice"), ActorId::from_seed(b"bob"));
+    assert_eq!(ActorId::from_seed(b"alice").to_bytes().len(), 16);
+
+    let build = || {
+        let mut doc = AutoCommit::new().with_actor(ActorId::from_seed(b"alice"));
+        doc.put(ROOT, "name", "alice").unwrap();
+        let list = doc.put_object(ROOT, "list", ObjType::List).unwrap();
+        doc.insert(&list, 0, 1).unwrap();
+        doc.commit_with(CommitOptions::default().with_time(0));
+        doc.save()
+    };
+    assert_eq!(build(), build());
+}
+
+#[test]
+fn save_after_brings_a_peer_at_heads_up_to_date() {
+    let mut doc = AutoCommit::new();
+    doc.put(ROOT, "a", 1).unwrap();
+    doc.commit();
+    let mut peer = doc.fork();
+    let heads = doc.get_heads();
+
+    let mut other = doc.fork();
+    other.put(ROOT, "b", 2).unwrap();
+    doc.put(ROOT, "c", 3).unwrap();
+    doc.merge(&mut other).unwrap();
+    doc.put(ROOT, "d", 4).unwrap();
+
+    // the last save doesn't matter
+    doc.save();
+    let changes = doc.save_after(&heads);
+    peer.load_incremental(&changes).unwrap();
+    assert_eq!(peer.get_heads(), doc.get_heads());
+    assert_eq!(peer.get(ROOT, "b").unwrap().unwrap().0, Value::int(2));
+
+    // nothing is missing at the current heads and unknown heads are ignored
+    let current = doc.get_heads();
+    assert!(doc.save_after(&current).is_empty());
+    let unknown = automerge::ChangeHash([7; 32]);
+    assert_eq!(doc.save_after(&[unknown]), doc.save_after(&[]));
+}
+
+#[test]
+fn patch_log_records_previous_values() {
+    let mut doc = Automerge::new();
+    let list = doc
+        .transact::<_, _, AutomergeError>(|tx| {
+            tx.put(ROOT, "a", 1)?;
+            tx.put(ROOT, "b", "gone")?;
+            let list = tx.put_object(ROOT, "list", ObjType::List)?;
+            tx.insert(&list, 0, 10)?;
+            Ok(list)
+        })
+        .unwrap()
+        .result;
+    let mut remote = doc.fork();
+    remote
+        .transact::<_, _, AutomergeError>(|tx| {
+            tx.put(ROOT, "a", 2)?;
+            tx.delete(ROOT, "b")?;
+            tx.put(&list, 0, 11)?;
+            tx.put(ROOT, "c", 3)?;
+            Ok(())
+        })
+        .unwrap();
+
+    let old_values = |doc: &mut Automerge, log: &mut PatchLog| {
+        doc.make_patches(log)
+            .into_iter()
+            .map(|patch| match patch.action {
+                PatchAction::PutMap { old, .. }
+                | PatchAction::PutSeq { old, .. }
+                | PatchAction::DeleteMap { old, .. } => old,
+                other => panic!("unexpected patch {:?}", other),
+            })
+            .collect::<Vec<_>>()
+    };
+    let expected = vec![
+        Some(Value::int(1)),
+        Some(Value::str("gone")),
+        None,
+        Some(Value::int(10)),
+    ];
+
+    // changes from another peer
+    let mut merged = doc.fork();
+    let mut plain = doc.fork();
+    let mut log = PatchLog::active(TextRepresentation::String).with_previous_values(true);
+    merged.merge_and_log_patches(&mut remote, &mut log).unwrap();
+    assert_eq!(old_values(&mut merged, &mut log), expected);
+
+    // local changes
+    let mut log = PatchLog::active(TextRepresentation::String).with_previous_values(true);
+    let mut tx = doc.transaction_log_patches(log);
+    tx.put(ROOT, "a", 2).unwrap();
+    tx.delete(ROOT, "b").unwrap();
+    tx.put(&list, 0, 11).unwrap();
+    tx.put(ROOT, "c", 3).unwrap();
+    (_, log) = tx.commit_with(CommitOptions::default());
+    assert_eq!(old_values(&mut doc, &mut log), expected);
+
+    // previous values are off by default
+    let mut log = PatchLog::active(TextRepresentation::String);
+    plain.merge_and_log_patches(&mut remote, &mut log).unwrap();
+    assert_eq!(old_values(&mut plain, &mut log), vec![None; 4]);
+}
+
+#[test]
+fn inspect_summarises_chunks_without_loading() {
+    let mut doc1 = AutoCommit::new().with_actor(ActorId::from([1]));
+    doc1.put(ROOT, "a", 1).unwrap();
+    doc1.put(ROOT, "b", 2).unwrap();
+    let mut bytes = doc1.save();
+    let doc_heads = doc1.get_heads();
+
+    let mut doc2 = doc1.fork().with_actor(ActorId::from([2]));
+    doc2.put(ROOT, "c", 3).unwrap();
+    bytes.extend(doc2.save_after(&doc_heads));
+
+    let inspection = automerge::storage::inspect(&bytes).unwrap();
+    assert_eq!(
+        inspection,
+        automerge::storage::DocInspection {
+            document_chunks: 1,
+            change_chunks: 1,
+            document_heads: doc_heads,
+            actors: vec![ActorId::from([1]), ActorId::from([2])],
+            num_changes: 2,
+            num_ops: 3,
+            num_objects: 0,
+        }
+    );
+
+    let mut corrupted = bytes.clone();
+    let last = corrupted.len() - 1;
+    corrupted[last] ^= 0xff;
+    assert!(matches!(
+        automerge::storage::inspect(&corrupted),
+        Err(AutomergeError::Load(_))
+    ));
+}
+
+#[test]
+fn change_reader_yields_change_chunks_and_skips_documents() {
+    let mut doc1 = AutoCommit::new().with_actor(ActorId::from([1]));
+    doc1.put(ROOT, "a", 1).unwrap();
+    let mut bytes = doc1.save();
+    let doc_heads = doc1.get_heads();
+
+    let mut doc2 = doc1.fork().with_actor(ActorId::from([2]));
+    doc2.put(ROOT, "b", 2).unwrap();
+    doc2.commit();
+    let text = doc2.put_object(ROOT, "text", ObjType::Text).unwrap();
+    doc2.splice_text(&text, 0, 0, &"long enough to compress ".repeat(20))
+        .unwrap();
+    bytes.extend(doc2.save_after(&doc_heads));
+
+    let mut reader = automerge::storage::ChangeReader::from_bytes(&bytes);
+    let changes = reader.by_ref().collect::<Result<Vec<_>, _>>().unwrap();
+    assert_eq!(reader.skipped_document_chunks(), 1);
+    let expected = doc2
+        .get_changes(&doc_heads)
+        .into_iter()
+        .map(|c| c.hash())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        changes.iter().map(|c| c.hash()).collect::<Vec<_>>(),
+        expected
+    );
+
+    let mut forwarded = doc1.fork();
+    forwarded.apply_changes(changes).unwrap();
+    assert_eq!(forwarded.get_heads(), doc2.get_heads());
+
+    let mut corrupted = bytes.clone();
+    let last = corrupted.len() - 1;
+    corrupted[last] ^= 0xff;
+    let mut reader = automerge::storage::ChangeReader::from_bytes(&corrupted);
+    assert!(matches!(reader.next(), Some(Ok(_))));
+    assert!(matches!(reader.next(), Some(Err(AutomergeError::Load(_)))));
+    assert!(reader.next().is_none());
+}
+
+#[test]
+fn load_with_limits_rejects_oversized_documents() {
+    let mut doc = AutoCommit::new();
+    let list = doc.put_object(ROOT, "list", ObjType::List).unwrap();
+    for i in 0..10 {
+        doc.insert(&list, i, i as i64).unwrap();
+        doc.commit();
+    }
+    let bytes = doc.save();
+    let limits = automerge::Limits {
+        max_ops: 11,
+        max_objects: 1,
+        max_change_count: 11,
+        max_decompressed_size: usize::MAX,
+    };
+    let loaded = Automerge::load_with_limits(&bytes, limits).unwrap();
+    assert_eq!(loaded.length(&list), 10);
+
+    let too_many_ops = automerge::Limits {
+        max_ops: 10,
+        ..limits
+    };
+    assert!(matches!(
+        Automerge::load_with_limits(&bytes, too_many_ops),
+        Err(AutomergeError::LimitExceeded("ops", 10))
+    ));
+    let too_many_changes = automerge::Limits {
+        max_change_count: 5,
+        ..limits
+    };
+    assert!(matches!(
+        Automerge::load_with_limits(&bytes, too_many_changes),
+        Err(AutomergeError::LimitExceeded("changes", 5))
+    ));
+    let too_many_objects = automerge::Limits {
+        max_objects: 0,
+        ..limits
+    };
+    assert!(matches!(
+        Automerge::load_with_limits(&bytes, too_many_objects),
+        Err(AutomergeError::LimitExceeded("objects", 0))
+    ));
+}
+
+#[test]
+fn load_with_limits_bounds_the_decompressed_size() {
+    let mut doc = AutoCommit::new();
+    doc.put(ROOT, "padding", "a".repeat(100_000)).unwrap();
+    doc.commit();
+    let limits = automerge::Limits {
+        max_decompressed_size: 10_000,
+        ..Default::default()
+    };
+
+    // the document chunk deflates the value column
+    let saved = doc.save();
+    assert!(saved.len() < 10_000);
+    assert!(matches!(
+        Automerge::load_with_limits(&saved, limits),
+        Err(AutomergeError::LimitExceeded("decompressed bytes", 10_000))
+    ));
+
+    // a large change is saved as a compressed change chunk
+    let changes = doc
+        .get_last_local_change()
+        .unwrap()
+        .clone()
+        .bytes()
+        .into_owned();
+    assert!(changes.len() < 10_000);
+    assert!(matches!(
+        Automerge::load_with_limits(&changes, limits),
+        Err(AutomergeError::LimitExceeded("decompressed bytes", 10_000))
+    ));
+
+    let enough = automerge::Limits {
+        max_decompressed_size: 200_000,
+        ..Default::default()
+    };
+    for bytes in [&saved, &changes] {
+        let loaded = Automerge::load_with_limits(bytes, enough).unwrap();
+        assert_eq!(loaded.get_heads(), doc.get_heads());
+    }
+}
+
+#[test]
+fn json_with_meta_round_trips_typed_values() {
+    let mut doc = AutoCommit::new();
+    doc.put(ROOT, "str", "hello").unwrap();
+    doc.put(ROOT, "int", -3).unwrap();
+    doc.put(ROOT, "float", 1.5).unwrap();
+    doc.put(ROOT, "uint", ScalarValue::Uint(7)).unwrap();
+    doc.put(ROOT, "counter", ScalarValue::counter(10)).unwrap();
+    doc.increment(ROOT, "counter", 5).unwrap();
+    doc.put(ROOT, "time", ScalarValue::Timestamp(1000)).unwrap();
+    doc.put(ROOT, "bytes", vec![1_u8, 255]).unwrap();
+    doc.put(ROOT, "null", ()).unwrap();
+    let text = doc.put_object(ROOT, "text", ObjType::Text).unwrap();
+    doc.splice_text(&text, 0, 0, "some text").unwrap();
+    let list = doc.put_object(ROOT, "list", ObjType::List).unwrap();
+    doc.insert(&list, 0, true).unwrap();
+    let tricky = doc.insert_object(&list, 1, ObjType::Map).unwrap();
+    doc.put(&tricky, "@type", "not a tag").unwrap();
+
+    let json = serde_json::to_value(doc.document().to_json_with_meta(None)).unwrap();
+    assert_eq!(
+        json,
+        serde_json::json!({
+            "str": "hello",
+            "int": -3,
+            "float": 1.5,
+            "uint": {"@type": "uint", "@value": 7},
+            "counter": {"@type": "counter", "@value": 15},
+            "time": {"@type": "timestamp", "@value": 1000},
+            "bytes": {"@type": "bytes", "@value": "01ff"},
+            "null": null,
+            "text": {"@type": "text", "@value": "some text"},
+            "list": [true, {"@type": "map", "@value": {"@type": "not a tag"}}],
+        })
+    );
+
+    let imported: automerge::JsonWithMeta = serde_json::from_value(json).unwrap();
+    let doc2 = Automerge::from_json_with_meta(imported).unwrap();
+    assert_eq!(doc2.hydrate(None), doc.hydrate(ROOT, None).unwrap());
+    assert_eq!(
+        doc2.get(ROOT, "counter").unwrap().unwrap().0,
+        Value::Scalar(std::borrow::Cow::Owned(ScalarValue::counter(15)))
+    );
+
+    let bad = serde_json::json!({"x": {"@type": "counter", "@value": "one"}});
+    assert!(serde_json::from_value::<automerge::JsonWithMeta>(bad).is_err());
+}
+
+#[test]
+fn json_with_meta_round_trips_f32() {
+    let mut doc = AutoCommit::new();
+    doc.put(ROOT, "small", 0.1_f32).unwrap();
+    doc.put(ROOT, "whole", 2.0_f32).unwrap();
+    doc.put(ROOT, "double", 0.1_f64).unwrap();
+
+    let json = serde_json::to_string(&doc.document().to_json_with_meta(None)).unwrap();
+    assert_eq!(
+        json,
+        r#"{"double":0.1,"small":{"@type":"f32","@value":0.1},"whole":{"@type":"f32","@value":2.0}}"#
+    );
+
+    let imported: automerge::JsonWithMeta = serde_json::from_str(&json).unwrap();
+    let doc2 = Automerge::from_json_with_meta(imported).unwrap();
+    assert_eq!(doc2.hydrate(None), doc.hydrate(ROOT, None).unwrap());
+    assert_eq!(
+        doc2.get(ROOT, "small").unwrap().unwrap().0,
+        Value::Scalar(std::borrow::Cow::Owned(ScalarValue::F32(0.1)))
+    );
+}
+
+#[test]
+fn apply_patches_replays_a_diff_as_local_edits() {
+    let mut source = AutoCommit::new();
+    source.put(ROOT, "gone", "soon").unwrap();
+    source.put(ROOT, "count", ScalarValue::counter(1)).unwrap();
+    let text = source.put_object(ROOT, "text", ObjType::Text).unwrap();
+    source.splice_text(&text, 0, 0, "hello world").unwrap();
+    let list = source.put_object(ROOT, "list", ObjType::List).unwrap();
+    source.insert(&list, 0, 1).unwrap();
+    source.insert(&list, 1, 2).unwrap();
+    source.commit();
+    let mut target = AutoCommit::load(&source.save()).unwrap();
+    let before = source.get_heads();
+
+    source.delete(ROOT, "gone").unwrap();
+    source.increment(ROOT, "count", 5).unwrap();
+    source.splice_text(&text, 5, 6, "!").unwrap();
+    source
+        .mark(
+            &text,
+            Mark::new("bold".into(), true, 0, 2),
+            ExpandMark::None,
+        )
+        .unwrap();
+    source.put(&list, 0, "one").unwrap();
+    source.delete(&list, 1).unwrap();
+    let nested = source.insert_object(&list, 1, ObjType::Map).unwrap();
+    source.put(&nested, "deep", 1.5).unwrap();
+    source.commit();
+    let after = source.get_heads();
+
+    let patches = source.diff(&before, &after);
+    target.apply_patches(&patches).unwrap();
+    assert_eq!(
+        target.hydrate(ROOT, None).unwrap(),
+        source.hydrate(ROOT, None).unwrap()
+    );
+    let target_text = target.get(ROOT, "text").unwrap().unwrap().1;
+    assert_eq!(
+        target.marks(&target_text).unwrap(),
+        source.marks(&text).unwrap()
+    );
+    // the edits were made as a new change of the target's actor
+    assert_ne!(target.get_heads(), after);
+
+    let orphan = Patch {
+        obj: nested.clone(),
+        path: vec![(ROOT, "missing".into())],
+        action: PatchAction::DeleteMap {
+            key: "deep".into(),
+            old: None,
+        },
+    };
+    assert!(matches!(
+        target.apply_patches(&[orphan]),
+        Err(AutomergeError::UnresolvedPatchPath(path)) if path == vec![Prop::from("missing")]
+    ));
+}
+
+#[test]
+fn patches_skip_puts_which_are_deleted_in_the_same_batch() {
+    let mut doc = AutoCommit::new();
+    doc.put(ROOT, "existing", "before").unwrap();
+    doc.commit();
+    let before = doc.get_heads();
+    let mut remote = doc.fork();
+    remote.put(ROOT, "temporary", 1).unwrap();
+    remote.put(ROOT, "temporary", 2).unwrap();
+    remote.commit();
+    remote.delete(ROOT, "temporary").unwrap();
+    remote.put(ROOT, "existing", "changed").unwrap();
+    remote.commit();
+    remote.delete(ROOT, "existing").unwrap();
+    remote.put(ROOT, "kept", true).unwrap();
+    remote.commit();
+
+    let mut doc = doc.document().clone();
+    let mut patch_log = PatchLog::active(TextRepresentation::String).with_previous_values(true);
+    let changes = remote
+        .get_changes(&before)
+        .into_iter()
+        .cloned()
+        .collect::<Vec<_>>();
+    doc.apply_changes_log_patches(changes, &mut patch_log)
+        .unwrap();
+    let patches = doc.make_patches(&mut patch_log);
+    let actions = patches.iter().map(|p| &p.action).collect::<Vec<_>>();
+    assert_eq!(actions.len(), 2);
+    assert!(matches!(
+        actions[0],
+        PatchAction::DeleteMap { key, old: Some(Value::Scalar(old)) }
+            if key == "existing" && old.as_ref() == &ScalarValue::from("before")
+    ));
+    assert!(matches!(actions[1], PatchAction::PutMap { key, .. } if key == "kept"));
+
+    let mut state = doc.hydrate(Some(&before));
+    state.apply_patches(patches).unwrap();
+    assert_eq!(state, doc.hydrate(None));
+}
+
+#[test]
+fn patches_keep_deletes_of_replaced_values_without_previous_values() {
+    let mut doc = Automerge::new();
+    doc.transact::<_, _, AutomergeError>(|tx| {
+        tx.put(ROOT, "existing", "before")?;
+        Ok(())
+    })
+    .unwrap();
+    let before = doc.get_heads();
+
+    // a local transaction
+    let mut tx = doc.transaction_log_patches(PatchLog::active(TextRepresentation::String));
+    tx.put(ROOT, "existing", "changed").unwrap();
+    tx.delete(ROOT, "existing").unwrap();
+    tx.put(ROOT, "temporary", 1).unwrap();
+    tx.delete(ROOT, "temporary").unwrap();
+    let (_, mut patch_log) = tx.commit();
+    let patches = doc.make_patches(&mut patch_log);
+    assert_eq!(patches.len(), 1);
+    assert!(matches!(
+        &patches[0].action,
+        PatchAction::DeleteMap { key, old: None } if key == "existing"
+    ));
+
+    // changes applied from another document
+    let mut other = doc.fork_at(&before).unwrap();
+    let mut patch_log = PatchLog::active(TextRepresentation::String);
+    let changes = doc
+        .get_changes(&before)
+        .into_iter()
+        .cloned()
+        .collect::<Vec<_>>();
+    other
+        .apply_changes_log_patches(changes, &mut patch_log)
+        .unwrap();
+    let patches = other.make_patches(&mut patch_log);
+    assert_eq!(patches.len(), 1);
+    assert!(matches!(
+        &patches[0].action,
+        PatchAction::DeleteMap { key, old: None } if key == "existing"
+    ));
+}
+
 #[test]
 fn large_patches_in_lists_are_correct() {
     // Reproduces a bug caused by an incorrect use of ListEncoding in Automerge::live_obj_paths.
@@ -207,6 +207,7 @@ fn local_patches_created_for_marks() {
                     text.clone(),
                 ),
                 conflict: false,
+                old: None,
             },
         },
         Patch {